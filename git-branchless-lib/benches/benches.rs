@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 use branchless::core::dag::{CommitSet, Dag};
@@ -9,14 +9,29 @@ use branchless::core::repo_ext::RepoExt;
 use branchless::core::rewrite::{
     BuildRebasePlanOptions, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
 };
-use branchless::git::{CherryPickFastOptions, Commit, Diff, Repo};
+use branchless::git::{
+    hydrate_tree, CherryPickFastOptions, Commit, Diff, FileMode, NonZeroOid, Repo,
+};
+use branchless::testing::{make_synthetic_repo, SyntheticRepo, SyntheticRepoOptions};
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use rayon::ThreadPoolBuilder;
 
-fn get_repo() -> Repo {
-    let repo_dir =
-        std::env::var("PATH_TO_REPO").expect("`PATH_TO_REPO` environment variable not set");
-    Repo::from_dir(&PathBuf::from(repo_dir)).unwrap()
+/// Open the repository at `PATH_TO_REPO`. If it's not set, then the
+/// benchmarks which need a real repository are skipped.
+fn get_repo() -> Option<Repo> {
+    match std::env::var("PATH_TO_REPO") {
+        Ok(repo_dir) => Some(Repo::from_dir(&PathBuf::from(repo_dir)).unwrap()),
+        Err(_) => {
+            eprintln!("`PATH_TO_REPO` environment variable not set, skipping benchmark");
+            None
+        }
+    }
+}
+
+/// Generate a large repository, so that the benchmarks can be run without
+/// having to set up a real one. See [`SyntheticRepoOptions`] for its shape.
+fn get_synthetic_repo() -> SyntheticRepo {
+    make_synthetic_repo(&SyntheticRepoOptions::default()).unwrap()
 }
 
 fn nth_parent(commit: Commit, n: usize) -> Commit {
@@ -31,8 +46,11 @@ fn nth_parent(commit: Commit, n: usize) -> Commit {
 }
 
 fn bench_rebase_plan(c: &mut Criterion) {
+    let repo = match get_repo() {
+        Some(repo) => repo,
+        None => return,
+    };
     c.bench_function("RebasePlanBuilder::build", |b| {
-        let repo = get_repo();
         let references_snapshot = repo.get_references_snapshot().unwrap();
         let head_oid = repo.get_head_info().unwrap().oid.unwrap();
         let later_commit = nth_parent(repo.find_commit_or_fail(head_oid).unwrap(), 20);
@@ -58,12 +76,14 @@ fn bench_rebase_plan(c: &mut Criterion) {
 
         let build_options = BuildRebasePlanOptions {
             force_rewrite_public_commits: true,
+            force_rewrite_protected_branches: false,
             dump_rebase_constraints: false,
             dump_rebase_plan: false,
             detect_duplicate_commits_via_patch_id: true,
         };
         let permissions = RebasePlanPermissions::verify_rewrite_set(
             &dag,
+            &repo,
             &build_options,
             &CommitSet::from(later_commit.get_oid()),
         )
@@ -88,10 +108,13 @@ fn bench_rebase_plan(c: &mut Criterion) {
 }
 
 fn bench_cherry_pick_fast(c: &mut Criterion) {
+    let repo = match get_repo() {
+        Some(repo) => repo,
+        None => return,
+    };
     let mut group = c.benchmark_group("cherry-pick");
     group.sample_size(10);
     group.bench_function("Repo::cherry_pick_commit", |b| {
-        let repo = get_repo();
         let head_oid = repo.get_head_info().unwrap().oid.unwrap();
         let head_commit = repo.find_commit_or_fail(head_oid).unwrap();
         let target_commit = nth_parent(head_commit.clone(), 1);
@@ -105,7 +128,6 @@ fn bench_cherry_pick_fast(c: &mut Criterion) {
         })
     });
     group.bench_function("Repo::cherry_pick_fast", |b| {
-        let repo = get_repo();
         let head_oid = repo.get_head_info().unwrap().oid.unwrap();
         let head_commit = repo.find_commit_or_fail(head_oid).unwrap();
         let target_commit = nth_parent(head_commit.clone(), 1);
@@ -125,10 +147,13 @@ fn bench_cherry_pick_fast(c: &mut Criterion) {
 }
 
 fn bench_diff_fast(c: &mut Criterion) {
+    let repo = match get_repo() {
+        Some(repo) => repo,
+        None => return,
+    };
     let mut group = c.benchmark_group("diff");
     group.sample_size(10);
     group.bench_function("git2::Repository::diff_tree_to_tree", |b| {
-        let repo = get_repo();
         let repo = git2::Repository::open(repo.get_path()).unwrap();
         let commit = repo.head().unwrap().peel_to_commit().unwrap();
 
@@ -143,7 +168,6 @@ fn bench_diff_fast(c: &mut Criterion) {
     });
 
     group.bench_function("Repo::get_patch_for_commit", |b| {
-        let repo = get_repo();
         let oid = repo.get_head_info().unwrap().oid.unwrap();
         let commit = repo.find_commit_or_fail(oid).unwrap();
         let effects = Effects::new_suppress_for_test(Glyphs::text());
@@ -153,8 +177,11 @@ fn bench_diff_fast(c: &mut Criterion) {
 }
 
 fn bench_get_paths_touched_by_commits(c: &mut Criterion) {
+    let repo = match get_repo() {
+        Some(repo) => repo,
+        None => return,
+    };
     c.bench_function("Repo::get_paths_touched_by_commit", |b| {
-        let repo = get_repo();
         let oid = repo.get_head_info().unwrap().oid.unwrap();
         let commit = repo.find_commit_or_fail(oid).unwrap();
 
@@ -164,6 +191,60 @@ fn bench_get_paths_touched_by_commits(c: &mut Criterion) {
     });
 }
 
+fn bench_synthetic_tree_operations(c: &mut Criterion) {
+    let mut group = c.benchmark_group("synthetic-tree");
+    let synthetic_repo = get_synthetic_repo();
+    let repo = &synthetic_repo.repo;
+    let draft_oid = *synthetic_repo.draft_commit_oids.last().unwrap();
+    let draft_commit = repo.find_commit_or_fail(draft_oid).unwrap();
+
+    group.bench_function("hydrate_tree", |b| {
+        let tree = draft_commit.get_tree().unwrap();
+        let blob_oid = repo.create_blob_from_contents(b"updated\n").unwrap();
+        let entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = (0..10)
+            .map(|i| {
+                (
+                    PathBuf::from(format!("dir{}/file{}.txt", i * 7, i)),
+                    Some((blob_oid, FileMode::Blob)),
+                )
+            })
+            .collect();
+        b.iter(|| hydrate_tree(repo, Some(&tree), entries.clone()).unwrap());
+    });
+
+    group.bench_function("Repo::get_paths_touched_by_commit", |b| {
+        b.iter(|| -> Option<HashSet<PathBuf>> {
+            repo.get_paths_touched_by_commit(&draft_commit).unwrap()
+        });
+    });
+
+    group.bench_function("Repo::get_changed_paths_between_trees", |b| {
+        // Compare against the start of the main branch, so that most of the
+        // tree has changed.
+        let first_main_oid = *synthetic_repo.main_commit_oids.first().unwrap();
+        let first_main_commit = repo.find_commit_or_fail(first_main_oid).unwrap();
+        let lhs_tree = first_main_commit.get_tree().unwrap();
+        let rhs_tree = draft_commit.get_tree().unwrap();
+        b.iter(|| -> HashSet<PathBuf> {
+            repo.get_changed_paths_between_trees(Some(&lhs_tree), Some(&rhs_tree))
+                .unwrap()
+        });
+    });
+}
+
+fn bench_synthetic_event_replay(c: &mut Criterion) {
+    let mut group = c.benchmark_group("synthetic-event-replay");
+    let synthetic_repo = get_synthetic_repo();
+    let repo = &synthetic_repo.repo;
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let conn = repo.get_db_conn().unwrap();
+    let event_log_db = EventLogDb::new(&conn).unwrap();
+
+    group.bench_function("EventReplayer::from_event_log_db", |b| {
+        b.iter(|| EventReplayer::from_event_log_db(&effects, repo, &event_log_db).unwrap());
+    });
+}
+
 criterion_group!(
     name = benches;
     config = Criterion::default().sample_size(10);
@@ -172,5 +253,7 @@ criterion_group!(
         bench_diff_fast,
         bench_get_paths_touched_by_commits,
         bench_rebase_plan,
+        bench_synthetic_tree_operations,
+        bench_synthetic_event_replay,
 );
 criterion_main!(benches);
@@ -6,7 +6,10 @@ use std::path::PathBuf;
 
 use branchless::core::effects::Effects;
 use branchless::core::formatting::Glyphs;
-use branchless::git::{hydrate_tree, process_diff_for_record, FileMode, Repo};
+use branchless::git::{
+    get_selected_submodule_commit, hydrate_tree, is_submodule_file_state, process_diff_for_record,
+    FileMode, Repo,
+};
 use bstr::ByteSlice;
 use eyre::Context;
 use git_record::{FileState, Section};
@@ -81,6 +84,11 @@ fn main() -> eyre::Result<()> {
         let entries: HashMap<_, _> = entries
             .into_iter()
             .map(|(path, file_state)| {
+                if is_submodule_file_state(&file_state) {
+                    let value = get_selected_submodule_commit(&file_state)?
+                        .map(|oid| (oid, FileMode::Commit));
+                    return Ok((path, value));
+                }
                 let value = {
                     let new_file_mode = file_state
                         .get_file_mode()
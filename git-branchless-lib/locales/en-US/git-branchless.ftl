@@ -0,0 +1,94 @@
+# User-facing messages for git-branchless, in Fluent syntax. See
+# https://projectfluent.org/ for the syntax, and `core::i18n` for how these
+# are loaded.
+#
+# This is the source catalog: every message must be defined here, since it's
+# used as the fallback for messages which are missing from a translation.
+
+## git hide
+
+hide-hid-commit = Hid commit: { $commit }
+hide-already-hidden = (It was already hidden, so this operation had no effect.)
+hide-abandoned-branches =
+    Abandoned { $count ->
+        [one] { $count } branch
+       *[other] { $count } branches
+    }: { $branches }
+hide-deleted-branches =
+    Deleted { $count ->
+        [one] { $count } branch
+       *[other] { $count } branches
+    }: { $branches }
+hide-undo-hint =
+    To unhide { $count ->
+        [one] this { $count } commit
+       *[other] these { $count } commits
+    }, run: git undo
+hide-undo-hint-with-branches =
+    To unhide { $count ->
+        [one] this { $count } commit
+       *[other] these { $count } commits
+    } and restore { $branch-count ->
+        [one] { $branch-count } branch
+       *[other] { $branch-count } branches
+    }, run: git undo
+
+## git unhide
+
+unhide-unhid-commit = Unhid commit: { $commit }
+unhide-not-hidden = (It was not hidden, so this operation had no effect.)
+unhide-undo-hint =
+    To hide { $count ->
+        [one] this { $count } commit
+       *[other] these { $count } commits
+    }, run: git undo
+
+## Checking out commits
+
+check-out-failed = Failed to check out commit
+check-out-failed-target = Failed to check out commit: { $target }
+
+## Rebasing commits, as in git move, git restack and git sync
+
+rebase-attempting-in-memory = Attempting rebase in-memory...
+rebase-in-memory-succeeded = In-memory rebase succeeded.
+rebase-calling-git-on-disk = Calling Git for on-disk rebase...
+rebase-trying-on-disk = Trying again on-disk...
+rebase-aborting-in-memory-requested = Aborting since an in-memory rebase was requested.
+rebase-committed-as = { $progress } Committed as: { $commit }
+rebase-skipped-empty = { $progress } Skipped now-empty commit: { $commit }
+rebase-skipped-upstream = { $progress } Skipped commit (was already applied upstream): { $commit }
+rebase-folded =
+    { $progress } { $kind ->
+        [squash] Squashed
+       *[fixup] Fixed up
+    } { $commit } into: { $target }
+rebase-merge-commit-unsupported = Merge commits currently can't be rebased in-memory.
+rebase-merge-commit-was = The merge commit was: { $commit }
+rebase-merge-conflict-unsupported = There was a merge conflict, which currently can't be resolved when rebasing in-memory.
+rebase-conflicting-commit-was = The conflicting commit was: { $commit }
+rebase-interrupted-in-memory = Interrupted. No commits or branches were changed.
+rebase-interrupted-on-disk = Interrupted. Run git rebase --continue to finish the rebase, or git rebase --abort to undo it.
+rebase-uncommitted-changes =
+    This operation would modify the working copy, but you have uncommitted changes
+    in your working copy which might be overwritten as a result.
+    Commit your changes and then try again.
+rebase-operation-in-progress = A { $operation } operation is already in progress.
+rebase-operation-in-progress-hint = Run git { $operation } --continue or git { $operation } --abort to resolve it and proceed.
+rebase-rolled-back = The rebase could not be completed, so it was rolled back.
+rebase-rolled-back-branches =
+    The rebase could not be completed, so it was rolled back. Moved back { $count ->
+        [one] { $count } branch
+       *[other] { $count } branches
+    }: { $branches }
+
+## Merge conflicts
+
+merge-conflict-header = This operation would cause a merge conflict:
+merge-conflict-commit =
+    { $bullet } ({ $count ->
+        [one] { $count } conflicting file
+       *[other] { $count } conflicting files
+    }) { $commit }
+merge-conflict-retry-hint = To resolve merge conflicts, retry this operation with the --merge option.
+merge-conflict-restack-hint = To resolve merge conflicts, run: git restack --merge
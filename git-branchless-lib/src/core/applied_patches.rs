@@ -0,0 +1,266 @@
+//! Record which commits have been applied to other repositories as patches.
+//!
+//! When a stack of commits is moved onto a checkout of a different repository
+//! (such as a mirror), the commits are recreated there with new OIDs. To detect
+//! which commits were already applied when the stack is moved again later,
+//! the patch ID of each applied commit is recorded, along with the repository
+//! and the commit that it was applied as.
+//!
+//! If applying the patches stops partway through, then the remaining patches
+//! are applied by the user with `git am --continue` instead. In that case, the
+//! commit which the other repository's `HEAD` pointed to when `git am` stopped
+//! is recorded as the pending base, so that the commits applied on top of it
+//! can be found and recorded the next time.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::git::{NonZeroOid, PatchId};
+
+/// A commit which was applied to another repository.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppliedPatch {
+    /// The patch ID of the commit.
+    pub patch_id: PatchId,
+
+    /// The commit in this repository which was applied.
+    pub source_commit_oid: NonZeroOid,
+
+    /// The commit in the other repository which it was applied as.
+    pub target_commit_oid: NonZeroOid,
+}
+
+/// Stores the patches applied to other repositories on disk.
+pub struct AppliedPatchesDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for AppliedPatchesDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<AppliedPatchesDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS applied_patches (
+    target_repo TEXT NOT NULL,
+    patch_id TEXT NOT NULL,
+    source_commit_oid TEXT NOT NULL,
+    target_commit_oid TEXT NOT NULL,
+    timestamp REAL NOT NULL,
+    PRIMARY KEY (target_repo, patch_id)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `applied_patches` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS pending_patches (
+    target_repo TEXT NOT NULL PRIMARY KEY,
+    base_commit_oid TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `pending_patches` table")?;
+    Ok(())
+}
+
+/// Get the key to store patches applied to the repository at `target_repo`
+/// under, so that the same repository is found regardless of which path it
+/// was referred to by.
+fn get_target_repo_key(target_repo: &Path) -> String {
+    let target_repo: PathBuf =
+        std::fs::canonicalize(target_repo).unwrap_or_else(|_| target_repo.to_owned());
+    target_repo.to_string_lossy().into_owned()
+}
+
+impl<'conn> AppliedPatchesDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(AppliedPatchesDb { conn })
+    }
+
+    /// Get the most recent record of the patch with the given ID being applied
+    /// to the repository at `target_repo`, if any.
+    #[instrument]
+    pub fn get(&self, target_repo: &Path, patch_id: PatchId) -> eyre::Result<Option<AppliedPatch>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT source_commit_oid, target_commit_oid
+FROM applied_patches
+WHERE target_repo = :target_repo AND patch_id = :patch_id
+",
+        )?;
+        let mut rows = stmt.query_map(
+            rusqlite::named_params! {
+                ":target_repo": get_target_repo_key(target_repo),
+                ":patch_id": patch_id.to_string(),
+            },
+            |row| {
+                let source_commit_oid: String = row.get("source_commit_oid")?;
+                let target_commit_oid: String = row.get("target_commit_oid")?;
+                Ok((source_commit_oid, target_commit_oid))
+            },
+        )?;
+        match rows.next() {
+            None => Ok(None),
+            Some(row) => {
+                let (source_commit_oid, target_commit_oid) = row?;
+                Ok(Some(AppliedPatch {
+                    patch_id,
+                    source_commit_oid: NonZeroOid::from_str(&source_commit_oid)?,
+                    target_commit_oid: NonZeroOid::from_str(&target_commit_oid)?,
+                }))
+            }
+        }
+    }
+
+    /// Record that the given patches were applied to the repository at
+    /// `target_repo`.
+    #[instrument]
+    pub fn add_many(
+        &self,
+        now: SystemTime,
+        target_repo: &Path,
+        applied_patches: &[AppliedPatch],
+    ) -> eyre::Result<()> {
+        let timestamp = now.duration_since(UNIX_EPOCH)?.as_secs_f64();
+        let target_repo = get_target_repo_key(target_repo);
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .wrap_err("Starting applied patches transaction")?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "
+INSERT OR REPLACE INTO applied_patches
+    (target_repo, patch_id, source_commit_oid, target_commit_oid, timestamp)
+VALUES
+    (:target_repo, :patch_id, :source_commit_oid, :target_commit_oid, :timestamp)
+",
+            )?;
+            for AppliedPatch {
+                patch_id,
+                source_commit_oid,
+                target_commit_oid,
+            } in applied_patches
+            {
+                stmt.execute(rusqlite::named_params! {
+                    ":target_repo": target_repo,
+                    ":patch_id": patch_id.to_string(),
+                    ":source_commit_oid": source_commit_oid.to_string(),
+                    ":target_commit_oid": target_commit_oid.to_string(),
+                    ":timestamp": timestamp,
+                })
+                .wrap_err("Recording applied patch")?;
+            }
+        }
+        tx.commit()
+            .wrap_err("Committing applied patches transaction")?;
+        Ok(())
+    }
+
+    /// Record that applying patches to the repository at `target_repo` stopped
+    /// partway through when its `HEAD` pointed to `base_commit_oid`, so that
+    /// any patches applied on top of it later are not yet recorded.
+    #[instrument]
+    pub fn set_pending_base(
+        &self,
+        target_repo: &Path,
+        base_commit_oid: NonZeroOid,
+    ) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO pending_patches (target_repo, base_commit_oid)
+VALUES (:target_repo, :base_commit_oid)
+",
+                rusqlite::named_params! {
+                    ":target_repo": get_target_repo_key(target_repo),
+                    ":base_commit_oid": base_commit_oid.to_string(),
+                },
+            )
+            .wrap_err("Recording pending patches")?;
+        Ok(())
+    }
+
+    /// Get and forget the pending base for the repository at `target_repo`, as
+    /// set by [`AppliedPatchesDb::set_pending_base`], if any.
+    #[instrument]
+    pub fn take_pending_base(&self, target_repo: &Path) -> eyre::Result<Option<NonZeroOid>> {
+        let target_repo = get_target_repo_key(target_repo);
+        let base_commit_oid: Option<String> = {
+            let mut stmt = self.conn.prepare(
+                "SELECT base_commit_oid FROM pending_patches WHERE target_repo = :target_repo",
+            )?;
+            let mut rows = stmt.query_map(
+                rusqlite::named_params! { ":target_repo": target_repo },
+                |row| row.get("base_commit_oid"),
+            )?;
+            rows.next().transpose()?
+        };
+        self.conn
+            .execute(
+                "DELETE FROM pending_patches WHERE target_repo = :target_repo",
+                rusqlite::named_params! { ":target_repo": target_repo },
+            )
+            .wrap_err("Clearing pending patches")?;
+        match base_commit_oid {
+            None => Ok(None),
+            Some(base_commit_oid) => Ok(Some(NonZeroOid::from_str(&base_commit_oid)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_applied_patches_db() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        let db = AppliedPatchesDb::new(&conn)?;
+        let patch_id = PatchId::from_str("1111111111111111111111111111111111111111")?;
+        let applied_patch = AppliedPatch {
+            patch_id,
+            source_commit_oid: NonZeroOid::from_str("2222222222222222222222222222222222222222")?,
+            target_commit_oid: NonZeroOid::from_str("3333333333333333333333333333333333333333")?,
+        };
+
+        assert_eq!(db.get(Path::new("/nonexistent/mirror"), patch_id)?, None);
+        db.add_many(
+            SystemTime::UNIX_EPOCH,
+            Path::new("/nonexistent/mirror"),
+            std::slice::from_ref(&applied_patch),
+        )?;
+        assert_eq!(
+            db.get(Path::new("/nonexistent/mirror"), patch_id)?,
+            Some(applied_patch)
+        );
+        assert_eq!(db.get(Path::new("/nonexistent/other"), patch_id)?, None);
+
+        let base_commit_oid = NonZeroOid::from_str("4444444444444444444444444444444444444444")?;
+        db.set_pending_base(Path::new("/nonexistent/mirror"), base_commit_oid)?;
+        assert_eq!(
+            db.take_pending_base(Path::new("/nonexistent/mirror"))?,
+            Some(base_commit_oid)
+        );
+        assert_eq!(
+            db.take_pending_base(Path::new("/nonexistent/mirror"))?,
+            None
+        );
+        Ok(())
+    }
+}
@@ -4,7 +4,6 @@ use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use eyre::Context;
 use tracing::instrument;
@@ -103,10 +102,11 @@ pub fn check_out_commit(
                 effects.get_glyphs(),
                 StyledString::styled(
                     match target {
-                        Some(target) => format!("Failed to check out commit: {target}"),
-                        None => "Failed to check out commit".to_string(),
+                        Some(target) =>
+                            effects.tr("check-out-failed-target", &[("target", target.into())]),
+                        None => effects.tr("check-out-failed", &[]),
                     },
-                    BaseColor::Red.light()
+                    effects.get_theme().error
                 )
             )?
         )?;
@@ -130,8 +130,14 @@ pub fn check_out_commit(
     }
 
     if *render_smartlog {
-        let exit_code =
-            git_run_info.run_direct_no_wrapping(Some(event_tx_id), &["branchless", "smartlog"])?;
+        let args = ["branchless", "smartlog"];
+        let exit_code = if effects.is_output_rerouted_to_stderr() {
+            // Running the command directly would write the smartlog to stdout,
+            // which is reserved for machine-readable output.
+            git_run_info.run(effects, Some(event_tx_id), &args)?
+        } else {
+            git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)?
+        };
         Ok(exit_code)
     } else {
         Ok(exit_code)
@@ -0,0 +1,380 @@
+//! Query the CI status of commits from the code forge, so that it can be
+//! displayed in the smartlog.
+//!
+//! The forge is queried by running the user-configured
+//! `branchless.ci.statusCommand`, which lets users integrate with whichever
+//! forge they use (for example, by wrapping `gh pr checks`). Since querying the
+//! forge is slow, the command is run in the background, and its results are
+//! cached in the database for `branchless.ci.cacheTtl` seconds.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::Context;
+use tracing::{instrument, warn};
+
+use crate::core::config::{get_ci_status_cache_ttl, get_ci_status_command};
+use crate::git::{NonZeroOid, Repo};
+use crate::util::shell_command;
+
+/// The CI status of a commit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CiStatus {
+    /// The checks for the commit haven't finished yet.
+    Pending,
+
+    /// The checks for the commit passed.
+    Success,
+
+    /// At least one check for the commit failed.
+    Failure,
+
+    /// The forge doesn't have a status for the commit, such as because it
+    /// hasn't been pushed.
+    Unknown,
+
+    /// Some other status reported by the forge.
+    Other(String),
+}
+
+impl FromStr for CiStatus {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let status = match s.to_lowercase().as_str() {
+            "pending" | "running" | "queued" => CiStatus::Pending,
+            "success" | "passed" | "pass" => CiStatus::Success,
+            "failure" | "failed" | "fail" => CiStatus::Failure,
+            "unknown" | "none" | "" => CiStatus::Unknown,
+            _ => CiStatus::Other(s.to_owned()),
+        };
+        Ok(status)
+    }
+}
+
+impl Display for CiStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CiStatus::Pending => write!(f, "pending"),
+            CiStatus::Success => write!(f, "passed"),
+            CiStatus::Failure => write!(f, "failed"),
+            CiStatus::Unknown => write!(f, "unknown"),
+            CiStatus::Other(status) => write!(f, "{}", status),
+        }
+    }
+}
+
+/// Caches the CI statuses of commits on disk.
+pub struct CiStatusDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for CiStatusDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<CiStatusDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS ci_status (
+    commit_oid TEXT NOT NULL PRIMARY KEY,
+    status TEXT NOT NULL,
+    timestamp REAL NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `ci_status` table")?;
+    Ok(())
+}
+
+impl<'conn> CiStatusDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(CiStatusDb { conn })
+    }
+
+    /// Get the cached CI status for the given commit, along with the time at
+    /// which it was queried.
+    #[instrument]
+    pub fn get(&self, commit_oid: NonZeroOid) -> eyre::Result<Option<(CiStatus, SystemTime)>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT status, timestamp
+FROM ci_status
+WHERE commit_oid = :commit_oid
+",
+        )?;
+        let mut rows = stmt.query_map(
+            rusqlite::named_params! {
+                ":commit_oid": commit_oid.to_string(),
+            },
+            |row| {
+                let status: String = row.get("status")?;
+                let timestamp: f64 = row.get("timestamp")?;
+                Ok((status, timestamp))
+            },
+        )?;
+        match rows.next() {
+            None => Ok(None),
+            Some(row) => {
+                let (status, timestamp) = row?;
+                let status = CiStatus::from_str(&status)?;
+                let timestamp = UNIX_EPOCH + Duration::from_secs_f64(timestamp);
+                Ok(Some((status, timestamp)))
+            }
+        }
+    }
+
+    /// Cache the CI status for the given commit.
+    #[instrument]
+    pub fn set(
+        &self,
+        now: SystemTime,
+        commit_oid: NonZeroOid,
+        status: &CiStatus,
+    ) -> eyre::Result<()> {
+        let timestamp = now.duration_since(UNIX_EPOCH)?.as_secs_f64();
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO ci_status (commit_oid, status, timestamp)
+VALUES (:commit_oid, :status, :timestamp)
+",
+                rusqlite::named_params! {
+                    ":commit_oid": commit_oid.to_string(),
+                    ":status": status.to_string(),
+                    ":timestamp": timestamp,
+                },
+            )
+            .wrap_err("Caching CI status")?;
+        Ok(())
+    }
+}
+
+/// The directory in which the results of background status queries are
+/// stored until they're read into the cache.
+fn get_background_query_dir(repo: &Repo) -> PathBuf {
+    repo.get_path().join("branchless").join("ci-status")
+}
+
+/// The file listing the commits which a background query was started for.
+/// While it exists, no other background query is started, unless it's older
+/// than [`BACKGROUND_QUERY_TIMEOUT`].
+const REQUESTED_FILE_NAME: &str = "requested";
+
+/// The file containing the output of a completed background query. The output
+/// is moved into place only once the status command has succeeded.
+const STATUSES_FILE_NAME: &str = "statuses";
+
+/// If a background query has been running for longer than this, then it's
+/// assumed to have failed, and another one can be started.
+const BACKGROUND_QUERY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Parse the output of the status command.
+fn parse_status_output(output: &str) -> eyre::Result<HashMap<NonZeroOid, CiStatus>> {
+    let mut result = HashMap::new();
+    for line in output.lines() {
+        let (oid, status) = match line.trim().split_once(char::is_whitespace) {
+            Some(parts) => parts,
+            None => continue,
+        };
+        // Abbreviated OIDs would be parsed as if they were padded with zeros.
+        if oid.len() != 40 {
+            continue;
+        }
+        if let Ok(oid) = NonZeroOid::from_str(oid) {
+            result.insert(oid, CiStatus::from_str(status.trim())?);
+        }
+    }
+    Ok(result)
+}
+
+fn read_file_if_exists(path: &Path) -> eyre::Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).wrap_err_with(|| format!("Reading {:?}", path)),
+    }
+}
+
+/// If a background query has completed, cache its results. Commits which were
+/// queried but which the status command didn't report are cached as
+/// [`CiStatus::Unknown`].
+#[instrument]
+fn read_background_query_results(
+    repo: &Repo,
+    ci_status_db: &CiStatusDb,
+    now: SystemTime,
+) -> eyre::Result<()> {
+    let dir = get_background_query_dir(repo);
+    let statuses_path = dir.join(STATUSES_FILE_NAME);
+    let requested_path = dir.join(REQUESTED_FILE_NAME);
+    let output = match read_file_if_exists(&statuses_path)? {
+        Some(output) => output,
+        None => return Ok(()),
+    };
+    let requested = read_file_if_exists(&requested_path)?.unwrap_or_default();
+
+    let mut statuses = parse_status_output(&output)?;
+    for line in requested.lines() {
+        if let Ok(commit_oid) = NonZeroOid::from_str(line) {
+            let status = statuses.remove(&commit_oid).unwrap_or(CiStatus::Unknown);
+            ci_status_db.set(now, commit_oid, &status)?;
+        }
+    }
+    for (commit_oid, status) in statuses {
+        ci_status_db.set(now, commit_oid, &status)?;
+    }
+
+    std::fs::remove_file(&statuses_path)
+        .wrap_err_with(|| format!("Removing {:?}", statuses_path))?;
+    match std::fs::remove_file(&requested_path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err).wrap_err_with(|| format!("Removing {:?}", requested_path)),
+    }
+    Ok(())
+}
+
+/// Start running the configured status command for the given commits in the
+/// background, unless a background query is already running. Its results are
+/// cached the next time that the statuses are requested.
+#[instrument]
+fn start_background_query(
+    repo: &Repo,
+    status_command: &str,
+    now: SystemTime,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<()> {
+    let dir = get_background_query_dir(repo);
+    let requested_path = dir.join(REQUESTED_FILE_NAME);
+    match std::fs::metadata(&requested_path) {
+        Ok(metadata) => {
+            let is_running = match now.duration_since(metadata.modified()?) {
+                Ok(age) => age < BACKGROUND_QUERY_TIMEOUT,
+                Err(_) => false,
+            };
+            if is_running {
+                return Ok(());
+            }
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {:?}", requested_path)),
+    }
+
+    std::fs::create_dir_all(&dir).wrap_err_with(|| format!("Creating {:?}", dir))?;
+    let requested: String = commit_oids
+        .iter()
+        .map(|commit_oid| format!("{}\n", commit_oid))
+        .collect();
+    std::fs::write(&requested_path, requested)
+        .wrap_err_with(|| format!("Writing {:?}", requested_path))?;
+
+    // The output is written to a temporary file and only moved into place if
+    // the command succeeds, so that partial output is never read. If it fails,
+    // the request is removed, so that the query is retried next time.
+    let script = format!(
+        r#"branchless_ci_status() {{ output=$1; requested=$2; shift 2; {{ {} "$@"; }} >"$output.tmp" && mv "$output.tmp" "$output" || rm -f "$output.tmp" "$requested"; }}; branchless_ci_status"#,
+        status_command
+    );
+    let _child = shell_command(&script)?
+        .current_dir(
+            repo.get_working_copy_path()
+                .unwrap_or_else(|| repo.get_path()),
+        )
+        .arg(dir.join(STATUSES_FILE_NAME))
+        .arg(&requested_path)
+        .args(commit_oids.iter().map(|oid| oid.to_string()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .wrap_err("Invoking CI status command")?;
+    Ok(())
+}
+
+/// Get the CI statuses for the given commits.
+///
+/// Querying the forge is slow, so only cached statuses are returned. Commits
+/// whose statuses are missing or older than the configured TTL are queried in
+/// the background by running `branchless.ci.statusCommand`, and the results
+/// are cached the next time that this function is called. If no command is
+/// configured, then no statuses are returned. If the command fails, then any
+/// stale cached statuses continue to be returned, so that a misbehaving forge
+/// doesn't prevent the smartlog from rendering.
+#[instrument]
+pub fn get_ci_statuses(
+    repo: &Repo,
+    ci_status_db: &CiStatusDb,
+    now: SystemTime,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<HashMap<NonZeroOid, CiStatus>> {
+    let status_command = match get_ci_status_command(repo)? {
+        Some(status_command) => status_command,
+        None => return Ok(HashMap::new()),
+    };
+    let ttl = get_ci_status_cache_ttl(repo)?;
+
+    if let Err(err) = read_background_query_results(repo, ci_status_db, now) {
+        warn!(?err, "Failed to read CI statuses queried in the background");
+    }
+
+    let mut result = HashMap::new();
+    let mut stale_oids = Vec::new();
+    for commit_oid in commit_oids {
+        match ci_status_db.get(*commit_oid)? {
+            Some((status, timestamp)) => {
+                let is_fresh = match now.duration_since(timestamp) {
+                    Ok(age) => age < ttl,
+                    // Timestamps are stored as floating-point seconds, so a
+                    // status cached just now may appear to be slightly in the
+                    // future. Otherwise, the clock has probably changed, so
+                    // query it again to be safe.
+                    Err(err) => err.duration() < ttl.min(Duration::from_secs(1)),
+                };
+                if !is_fresh {
+                    stale_oids.push(*commit_oid);
+                }
+                result.insert(*commit_oid, status);
+            }
+            None => stale_oids.push(*commit_oid),
+        }
+    }
+
+    if !stale_oids.is_empty() {
+        if let Err(err) = start_background_query(repo, &status_command, now, &stale_oids) {
+            warn!(?err, "Failed to query CI statuses");
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ci_status() {
+        assert_eq!(CiStatus::from_str("SUCCESS").unwrap(), CiStatus::Success);
+        assert_eq!(CiStatus::from_str("failed").unwrap(), CiStatus::Failure);
+        assert_eq!(CiStatus::from_str("none").unwrap(), CiStatus::Unknown);
+        assert_eq!(
+            CiStatus::from_str("skipped").unwrap(),
+            CiStatus::Other("skipped".to_owned())
+        );
+        assert_eq!(
+            CiStatus::from_str(&CiStatus::Pending.to_string()).unwrap(),
+            CiStatus::Pending
+        );
+    }
+}
@@ -1,16 +1,19 @@
 //! Accesses repo-specific configuration.
 
-use std::ffi::OsString;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use console::style;
 use eyre::Context;
 use tracing::{instrument, warn};
 
 use crate::git::{ConfigRead, GitRunInfo, GitRunOpts, Repo};
 
 use super::effects::Effects;
+use super::formatting::{parse_style, GlyphSet, Glyphs, Theme, GLYPH_SET_NAMES, THEME_NAMES};
 
 /// Get the path where Git hooks are stored on disk.
 #[instrument]
@@ -45,16 +48,197 @@ pub fn get_main_branch_name(repo: &Repo) -> eyre::Result<String> {
     Ok("master".to_string())
 }
 
-/// Get the default comment character.
+/// Get the names of any additional main branches, such as release branches.
+/// Commits reachable from these branches are considered public, just like
+/// commits reachable from the main branch.
+///
+/// This is a multivariable key, so it can be set more than once to configure
+/// several additional main branches.
+#[instrument]
+pub fn get_additional_main_branch_names(repo: &Repo) -> eyre::Result<Vec<String>> {
+    repo.get_readonly_config()?
+        .get_or("branchless.core.additionalMainBranches", Vec::new())
+}
+
+/// Get the glob patterns for remote-tracking branches whose commits are
+/// considered public, such as `origin/*`. The patterns are matched against
+/// branch names without the `refs/remotes/` prefix. Remote-tracking branches
+/// which don't match any pattern, such as those for a personal fork, don't
+/// make their commits public.
+///
+/// This is a multivariable key, so it can be set more than once to configure
+/// several groups of remote-tracking branches.
+#[instrument]
+pub fn get_public_remote_branch_patterns(repo: &Repo) -> eyre::Result<Vec<String>> {
+    repo.get_readonly_config()?
+        .get_or("branchless.core.publicRemoteBranches", Vec::new())
+}
+
+/// Get the glob patterns for branches which shouldn't be moved or deleted by
+/// rewrite operations, such as `release-*`. The patterns are matched against
+/// branch names without the `refs/heads/` or `refs/remotes/` prefix.
+///
+/// This is a multivariable key, so it can be set more than once to protect
+/// several groups of branches.
+#[instrument]
+pub fn get_protected_branch_patterns(repo: &Repo) -> eyre::Result<Vec<String>> {
+    repo.get_readonly_config()?
+        .get_or("branchless.core.protectedBranches", Vec::new())
+}
+
+/// Get the glob patterns for references whose updates should not be recorded in
+/// the event log, such as `refs/notes/*` or `refs/pull/*`.
+///
+/// This is a multivariable key, so it can be set more than once to ignore
+/// several reference namespaces.
 #[instrument]
-pub fn get_comment_char(repo: &Repo) -> eyre::Result<char> {
-    let from_config: Option<String> = repo.get_readonly_config()?.get("core.commentChar")?;
-    let comment_char = match from_config {
-        // Note that git also allows `core.commentChar="auto"`, which we do not currently support.
-        Some(comment_char) => comment_char.chars().next().unwrap(),
-        None => char::from(git2::DEFAULT_COMMENT_CHAR.unwrap()),
+pub fn get_ref_patterns_to_ignore(repo: &Repo) -> eyre::Result<Vec<String>> {
+    repo.get_readonly_config()?
+        .get_or("branchless.core.refPatternsToIgnore", Vec::new())
+}
+
+/// The string which marks comment lines in commit messages being edited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommentString {
+    /// Lines starting with this string are comments.
+    Fixed(String),
+
+    /// Choose a comment character which doesn't start any line of the message
+    /// being edited, as with `core.commentChar=auto`.
+    Auto,
+}
+
+impl CommentString {
+    /// The characters which may be chosen when the comment string is `auto`, in
+    /// order of preference. This is the same list which Git uses.
+    const AUTO_CANDIDATES: &'static str = "#;@!$%^&|:";
+
+    /// Determine the comment string to use when editing `message`, which
+    /// should contain all of the text which will be presented to the user,
+    /// except for the comment lines themselves.
+    pub fn resolve(&self, message: &str) -> eyre::Result<String> {
+        match self {
+            CommentString::Fixed(comment_string) => Ok(comment_string.clone()),
+            CommentString::Auto => {
+                let used_chars: HashSet<char> = message
+                    .lines()
+                    .filter_map(|line| line.trim_start_matches([' ', '\t']).chars().next())
+                    .collect();
+                match Self::AUTO_CANDIDATES
+                    .chars()
+                    .find(|candidate| !used_chars.contains(candidate))
+                {
+                    Some(comment_char) => Ok(comment_char.to_string()),
+                    None => eyre::bail!(
+                        "Unable to select a comment character that is not used in the current commit message"
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Get the comment string used to mark comment lines in commit messages.
+///
+/// `core.commentString` takes precedence over `core.commentChar`. Either may be
+/// set to `auto`, or to a string of more than one character.
+#[instrument]
+pub fn get_comment_string(repo: &Repo) -> eyre::Result<CommentString> {
+    let config = repo.get_readonly_config()?;
+    let from_config: Option<String> = match config.get("core.commentString")? {
+        Some(comment_string) => Some(comment_string),
+        None => config.get("core.commentChar")?,
     };
-    Ok(comment_char)
+    let comment_string = match from_config {
+        Some(comment_string) if comment_string == "auto" => CommentString::Auto,
+        Some(comment_string) if !comment_string.is_empty() => CommentString::Fixed(comment_string),
+        Some(_) | None => {
+            CommentString::Fixed(char::from(git2::DEFAULT_COMMENT_CHAR.unwrap()).to_string())
+        }
+    };
+    Ok(comment_string)
+}
+
+/// Get the prefix of the directive lines, such as `++ reword <oid>`, which
+/// separate the commit messages when rewording several commits at once.
+#[instrument]
+pub fn get_reword_directive_prefix(repo: &Repo) -> eyre::Result<String> {
+    repo.get_readonly_config()?
+        .get_or("branchless.reword.directivePrefix", "++".to_string())
+}
+
+/// Get the kind of context to show under each commit's message when editing
+/// commit messages with `git reword`: `none`, `stat`, or `files`.
+#[instrument]
+pub fn get_reword_context(repo: &Repo) -> eyre::Result<String> {
+    repo.get_readonly_config()?
+        .get_or("branchless.reword.context", "none".to_string())
+}
+
+/// Get the column to wrap commit message bodies at with `git reword --reflow`.
+#[instrument]
+pub fn get_reword_reflow_column(repo: &Repo) -> eyre::Result<usize> {
+    let column: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.reword.reflowColumn", 72)?;
+    Ok(usize::try_from(column).unwrap_or_default())
+}
+
+/// If `true`, check commit messages edited with `git reword` for common style
+/// problems, and ask whether to edit them again if there are any.
+#[instrument]
+pub fn get_reword_lint(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.reword.lint", false)
+}
+
+/// Get the maximum length of a commit message's subject line before `git
+/// reword` warns about it, when `branchless.reword.lint` is enabled.
+#[instrument]
+pub fn get_reword_max_subject_length(repo: &Repo) -> eyre::Result<usize> {
+    let length: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.reword.maxSubjectLength", 72)?;
+    Ok(usize::try_from(length).unwrap_or_default())
+}
+
+/// Get the command used to check commit messages edited with `git reword`,
+/// such as a spell checker. It's passed the path to a file containing the
+/// message, and any output is shown as warnings.
+#[instrument]
+pub fn get_reword_lint_command(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.reword.lintCommand")
+}
+
+/// Get the encoding to store new commit messages in, as set by
+/// `i18n.commitEncoding`, if any.
+#[instrument]
+pub fn get_commit_encoding(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?.get("i18n.commitEncoding")
+}
+
+/// Get the command used to validate new commit messages before they're
+/// committed, if any.
+///
+/// The command is invoked with the path to a file containing the message, as
+/// with Git's `commit-msg` hook, and the message is rejected if it exits with
+/// a non-zero status.
+#[instrument]
+pub fn get_commit_msg_hook(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?.get("branchless.commit.msgHook")
+}
+
+/// Get the template used to pre-fill the message for commits created with
+/// `git record`, if any.
+///
+/// Unlike Git's `commit.template`, this is the template itself rather than a
+/// path to it, and it can contain variables such as `{branch}`, `{ticket}` and
+/// `{files}`, which are substituted before the editor is opened.
+#[instrument]
+pub fn get_commit_message_template(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.commit.template")
 }
 
 /// Get the commit template message, if any.
@@ -96,12 +280,19 @@ pub fn get_default_branch_name(repo: &Repo) -> eyre::Result<Option<String>> {
     Ok(default_branch_name)
 }
 
-/// Get the configured editor, if any.
+/// Get the editor to use for editing commit messages.
 ///
-/// Because this is primarily intended for use w/ dialoguer::Editor, and it already considers
-/// several environment variables, we only need to consider git-specific config options: the
-/// `$GIT_EDITOR` environment var and the `core.editor` config setting. We do so in that order to
-/// match how git resolves the editor to use.
+/// This is resolved with `git var GIT_EDITOR`, which considers the
+/// `$GIT_EDITOR` environment variable, the `core.editor` config setting, and
+/// the `$VISUAL` and `$EDITOR` environment variables, in that order, and
+/// otherwise defaults to `vi`. If `git var` fails, then the same order is
+/// applied here (see [`resolve_editor`]).
+///
+/// Graphical editors which return immediately by default, such as `code`, are
+/// passed the option to wait until the file is closed.
+///
+/// Returns `None` if there is no editor to use, which happens when the terminal
+/// is dumb and no editor has been configured.
 ///
 /// FMI see https://git-scm.com/docs/git-var#Documentation/git-var.txt-GITEDITOR
 #[instrument]
@@ -113,26 +304,213 @@ pub fn get_editor(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<Option
             let editor =
                 std::str::from_utf8(&result.stdout).context("Decoding git var output as UTF-8")?;
             let editor = editor.trim_end();
-            let editor = OsString::from(editor);
-            return Ok(Some(editor));
+            if !editor.is_empty() {
+                return Ok(Some(add_editor_wait_option(editor).into()));
+            }
         } else {
             warn!(?result, "`git var` invocation failed");
         }
     }
 
-    let editor = std::env::var_os("GIT_EDITOR");
-    if editor != None {
-        return Ok(editor);
-    }
-
+    let get_env = |name: &str| git_run_info.env.get(OsStr::new(name)).cloned();
     let config = repo.get_readonly_config()?;
-    let editor: Option<String> = config.get("core.editor")?;
+    let editor = resolve_editor(
+        get_env("GIT_EDITOR"),
+        config.get("core.editor")?,
+        get_env("VISUAL"),
+        get_env("EDITOR"),
+        get_env("TERM").map_or(true, |term| term == "dumb"),
+    );
+    Ok(editor.map(|editor| match editor.to_str() {
+        Some(editor) => add_editor_wait_option(editor).into(),
+        None => editor,
+    }))
+}
+
+/// Get the editor to use for editing a sequence of commits at once, such as
+/// when rewording several commits.
+///
+/// As in Git, this is the `$GIT_SEQUENCE_EDITOR` environment variable or the
+/// `sequence.editor` config setting, in that order, and otherwise the editor
+/// returned by [`get_editor`]. (`git var GIT_SEQUENCE_EDITOR` is only available
+/// in newer versions of Git, so it's resolved here instead.)
+#[instrument]
+pub fn get_sequence_editor(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+) -> eyre::Result<Option<OsString>> {
+    let editor = match git_run_info.env.get(OsStr::new("GIT_SEQUENCE_EDITOR")) {
+        Some(editor) if !editor.is_empty() => Some(editor.clone()),
+        _ => {
+            let editor: Option<String> = repo.get_readonly_config()?.get("sequence.editor")?;
+            editor
+                .filter(|editor| !editor.is_empty())
+                .map(OsString::from)
+        }
+    };
     match editor {
-        Some(editor) => Ok(Some(editor.into())),
-        None => Ok(None),
+        Some(editor) => Ok(Some(match editor.to_str() {
+            Some(editor) => add_editor_wait_option(editor).into(),
+            None => editor,
+        })),
+        None => get_editor(git_run_info, repo),
+    }
+}
+
+/// Resolve the editor in the same order as Git: `$GIT_EDITOR`, `core.editor`,
+/// `$VISUAL`, `$EDITOR`, and then `vi`. `$VISUAL` and `vi` are skipped if the
+/// terminal is dumb, in which case there may not be an editor to use. Empty
+/// values are treated as unset.
+pub fn resolve_editor(
+    git_editor_env: Option<OsString>,
+    core_editor: Option<String>,
+    visual_env: Option<OsString>,
+    editor_env: Option<OsString>,
+    is_terminal_dumb: bool,
+) -> Option<OsString> {
+    let non_empty = |editor: Option<OsString>| editor.filter(|editor| !editor.is_empty());
+    non_empty(git_editor_env)
+        .or_else(|| non_empty(core_editor.map(OsString::from)))
+        .or_else(|| {
+            if is_terminal_dumb {
+                None
+            } else {
+                non_empty(visual_env)
+            }
+        })
+        .or_else(|| non_empty(editor_env))
+        .or_else(|| {
+            if is_terminal_dumb {
+                None
+            } else {
+                Some(OsString::from("vi"))
+            }
+        })
+}
+
+/// Graphical editors whose command returns immediately, rather than when the
+/// file is closed, unless passed one of the given options. The first option is
+/// the one which is added if none are present.
+const GRAPHICAL_EDITOR_WAIT_OPTIONS: &[(&str, &[&str])] = &[
+    ("atom", &["--wait", "-w"]),
+    ("code", &["--wait", "-w"]),
+    ("code-insiders", &["--wait", "-w"]),
+    ("codium", &["--wait", "-w"]),
+    ("cursor", &["--wait", "-w"]),
+    ("gedit", &["--wait", "-w", "-s", "--standalone"]),
+    ("gvim", &["-f", "--nofork"]),
+    ("kate", &["--block", "-b"]),
+    ("mate", &["--wait", "-w"]),
+    ("mvim", &["-f", "--nofork"]),
+    ("subl", &["--wait", "-w"]),
+    ("zed", &["--wait", "-w"]),
+];
+
+/// Split the editor command into the name of its program (without any path or
+/// executable extension, in lowercase) and the rest of its arguments.
+fn split_editor_program(editor: &str) -> (String, &str) {
+    let (program, args) = match editor.strip_prefix('"') {
+        Some(rest) => match rest.split_once('"') {
+            Some((program, args)) => (program, args),
+            None => (rest, ""),
+        },
+        None => editor.split_at(editor.find(char::is_whitespace).unwrap_or(editor.len())),
+    };
+    let program_name = program.rsplit(['/', '\\']).next().unwrap_or(program);
+    let program_name = program_name.to_lowercase();
+    let program_name = [".exe", ".cmd", ".bat"]
+        .iter()
+        .find_map(|extension| program_name.strip_suffix(extension))
+        .map(ToOwned::to_owned)
+        .unwrap_or(program_name);
+    (program_name, args)
+}
+
+fn get_graphical_editor_wait_options(program_name: &str) -> Option<&'static [&'static str]> {
+    GRAPHICAL_EDITOR_WAIT_OPTIONS
+        .iter()
+        .find(|(name, _)| *name == program_name)
+        .map(|(_, wait_options)| *wait_options)
+}
+
+/// Whether the editor is one of the graphical editors which may return before
+/// the file has been closed, unless passed an option to make it wait.
+pub fn is_graphical_editor(editor: &str) -> bool {
+    let (program_name, _args) = split_editor_program(editor.trim());
+    get_graphical_editor_wait_options(&program_name).is_some()
+}
+
+/// If the editor is a graphical editor which doesn't wait for the file to be
+/// closed by default, add the option which makes it wait. Otherwise, the
+/// editor would exit before the message has been edited.
+pub fn add_editor_wait_option(editor: &str) -> String {
+    let editor = editor.trim();
+    let (program_name, args) = split_editor_program(editor);
+    match get_graphical_editor_wait_options(&program_name) {
+        Some(wait_options)
+            if !args
+                .split_whitespace()
+                .any(|arg| wait_options.contains(&arg)) =>
+        {
+            format!("{} {}", editor, wait_options[0])
+        }
+        Some(_) | None => editor.to_string(),
     }
 }
 
+/// Get the configured pager, or `None` if paging is disabled.
+///
+/// Like the editor, this is resolved with `git var GIT_PAGER`, which considers
+/// the `$GIT_PAGER` environment variable, the `core.pager` config setting and
+/// the `$PAGER` environment variable, in that order, and otherwise defaults to
+/// `less`. As in Git, a pager of `cat` or the empty string disables paging.
+///
+/// FMI see https://git-scm.com/docs/git-var#Documentation/git-var.txt-GITPAGER
+#[instrument]
+pub fn get_pager(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<Option<String>> {
+    let pager =
+        match git_run_info.run_silent(repo, None, &["var", "GIT_PAGER"], GitRunOpts::default()) {
+            Ok(result) if result.exit_code == 0 => std::str::from_utf8(&result.stdout)
+                .context("Decoding git var output as UTF-8")?
+                .trim_end()
+                .to_string(),
+            result => {
+                warn!(?result, "`git var` invocation failed");
+                let pager = match std::env::var("GIT_PAGER") {
+                    Ok(pager) => Some(pager),
+                    Err(_) => repo.get_readonly_config()?.get("core.pager")?,
+                };
+                pager
+                    .or_else(|| std::env::var("PAGER").ok())
+                    .unwrap_or_else(|| "less".to_string())
+            }
+        };
+    match pager.trim() {
+        "" | "cat" => Ok(None),
+        _ => Ok(Some(pager)),
+    }
+}
+
+/// If `true`, query Watchman for the files which changed in the working copy
+/// instead of having `git status` check every file.
+#[instrument]
+pub fn get_use_watchman(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.core.useWatchman", false)
+}
+
+/// The number of main branch commits to add to the commit graph at a time when
+/// syncing it. Each chunk is written to disk before the next one is added, so
+/// that an interrupted sync doesn't have to start over. If `0`, all new commits
+/// are added at once.
+#[instrument]
+pub fn get_dag_sync_chunk_size(repo: &Repo) -> eyre::Result<usize> {
+    let chunk_size: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.core.dagSyncChunkSize", 5000)?;
+    Ok(usize::try_from(chunk_size).unwrap_or_default())
+}
+
 /// If `true`, create working copy snapshots automatically after certain
 /// operations.
 #[instrument]
@@ -141,6 +519,14 @@ pub fn get_undo_create_snapshots(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.undo.createSnapshots", true)
 }
 
+/// If `true`, record a trace of each `git-branchless` command, so that it can
+/// be included in the output of `git branchless bug-report`.
+#[instrument]
+pub fn get_bug_report_record_traces(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.bugReport.recordTraces", false)
+}
+
 /// If `true`, when restacking a commit, do not update its timestamp to the
 /// current time.
 #[instrument]
@@ -149,6 +535,45 @@ pub fn get_restack_preserve_timestamps(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.restack.preserveTimestamps", false)
 }
 
+/// What to do about commits abandoned by a rewrite which `git-branchless`
+/// didn't perform itself, such as `git commit --amend`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoRestack {
+    /// Warn about the abandoned commits and list the ways to fix them.
+    Off,
+
+    /// Print a one-line hint with the command to restack the abandoned
+    /// commits.
+    Hint,
+
+    /// Restack the abandoned commits automatically, if it can be done without
+    /// touching the working copy.
+    Restack,
+}
+
+/// Get what to do about commits abandoned by a rewrite, as set by
+/// `branchless.restack.auto`.
+#[instrument]
+pub fn get_restack_auto(repo: &Repo) -> eyre::Result<AutoRestack> {
+    let value: String = repo
+        .get_readonly_config()?
+        .get_or("branchless.restack.auto", "false".to_string())?;
+    let result = match value.to_lowercase().as_str() {
+        "hint" => AutoRestack::Hint,
+        "true" | "yes" | "on" | "1" => AutoRestack::Restack,
+        _ => AutoRestack::Off,
+    };
+    Ok(result)
+}
+
+/// If `true`, `git restack` without arguments only restacks the commits
+/// abandoned by rewriting the current stack, rather than all abandoned commits.
+#[instrument]
+pub fn get_restack_current_stack_only(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.restack.currentStackOnly", false)
+}
+
 /// If `true`, when advancing to a "next" commit, prompt interactively to
 /// if there is ambiguity in which commit to advance to.
 #[instrument]
@@ -157,6 +582,63 @@ pub fn get_next_interactive(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.next.interactive", false)
 }
 
+/// If `true`, print a summary of what changed since the last time the
+/// smartlog was rendered.
+#[instrument]
+pub fn get_smartlog_changes_banner(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.smartlog.changesBanner", true)
+}
+
+/// If `true`, cache the rendered smartlog, and print the cached output instead
+/// of rendering it again if nothing has changed since. Off by default, since
+/// the cached output doesn't reflect changes which aren't recorded in the
+/// repository, such as newly-reported CI statuses.
+#[instrument]
+pub fn get_smartlog_cache(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.smartlog.cache", false)
+}
+
+/// Apply the smartlog glyph configuration to the given glyphs: the glyph set
+/// named by `branchless.smartlog.glyphs`, if set, and then the custom
+/// characters from `branchless.smartlog.headGlyph`,
+/// `branchless.smartlog.hiddenGlyph` and `branchless.smartlog.mainGlyph`.
+/// Whether to write ANSI escape codes is unaffected.
+#[instrument]
+pub fn get_smartlog_glyphs(repo: &Repo, glyphs: Glyphs) -> eyre::Result<Glyphs> {
+    let config = repo.get_readonly_config()?;
+    let glyph_set_name: Option<String> = config.get("branchless.smartlog.glyphs")?;
+    let mut glyphs = match glyph_set_name {
+        None => glyphs,
+        Some(glyph_set_name) => match GlyphSet::from_name(&glyph_set_name) {
+            Some(glyph_set) => glyphs.with_glyph_set(glyph_set),
+            None => eyre::bail!(
+                "Unknown glyph set {:?} for branchless.smartlog.glyphs (expected one of: {})",
+                glyph_set_name,
+                GLYPH_SET_NAMES.join(", ")
+            ),
+        },
+    };
+
+    let get_glyph = |key: &str| -> eyre::Result<Option<Cow<'static, str>>> {
+        let glyph: Option<String> = config.get(key)?;
+        Ok(glyph.filter(|glyph| !glyph.is_empty()).map(Cow::Owned))
+    };
+    if let Some(glyph) = get_glyph("branchless.smartlog.headGlyph")? {
+        glyphs.commit_visible_head = glyph.clone();
+        glyphs.commit_main_head = glyph;
+    }
+    if let Some(glyph) = get_glyph("branchless.smartlog.hiddenGlyph")? {
+        glyphs.commit_obsolete = glyph.clone();
+        glyphs.commit_main_obsolete = glyph;
+    }
+    if let Some(glyph) = get_glyph("branchless.smartlog.mainGlyph")? {
+        glyphs.commit_main = glyph;
+    }
+    Ok(glyphs)
+}
+
 /// If `true`, show branches pointing to each commit in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_branches(repo: &Repo) -> eyre::Result<bool> {
@@ -171,6 +653,30 @@ pub fn get_commit_descriptors_differential_revision(repo: &Repo) -> eyre::Result
         .get_or("branchless.commitDescriptors.differentialRevision", true)
 }
 
+/// If `true`, show the CI status of each commit in the smartlog. Statuses are
+/// only available if `branchless.ci.statusCommand` is set.
+#[instrument]
+pub fn get_commit_descriptors_ci_status(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.ciStatus", true)
+}
+
+/// If `true`, show whether each commit's signature is valid in the smartlog.
+/// This is disabled by default, since verifying signatures can be slow.
+#[instrument]
+pub fn get_commit_descriptors_signature(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.signature", false)
+}
+
+/// If `true`, show the metadata set with `git branchless meta` for each commit
+/// in the smartlog.
+#[instrument]
+pub fn get_commit_descriptors_metadata(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.metadata", true)
+}
+
 /// If `true`, show the age of each commit in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
@@ -178,6 +684,161 @@ pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.commitDescriptors.relativeTime", true)
 }
 
+/// If `true`, regenerate Git's commit-graph file after garbage collection and
+/// after large rewrites.
+#[instrument]
+pub fn get_maintenance_write_commit_graph(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.maintenance.writeCommitGraph", false)
+}
+
+/// The minimum number of commits which have to be rewritten in a single
+/// operation before maintenance tasks are run.
+#[instrument]
+pub fn get_maintenance_rewrite_threshold(repo: &Repo) -> eyre::Result<usize> {
+    let threshold: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.maintenance.rewriteThreshold", 100)?;
+    Ok(usize::try_from(threshold).unwrap_or_default())
+}
+
+/// The number of references keeping individual commits alive at which they're
+/// compacted into a single reference during maintenance. If `0`, references
+/// are never compacted.
+#[instrument]
+pub fn get_maintenance_keep_ref_compaction_threshold(repo: &Repo) -> eyre::Result<usize> {
+    let threshold: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.maintenance.keepRefCompactionThreshold", 1000)?;
+    Ok(usize::try_from(threshold).unwrap_or_default())
+}
+
+/// If `true`, highlight the words which changed within modified lines when
+/// displaying diffs, such as in `git record -i` and `git branchless diff`, and
+/// show the conflicting changes when describing merge conflicts.
+#[instrument]
+pub fn get_word_diff(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.diff.wordDiff", false)
+}
+
+/// Get the theme used to color output. This is the built-in theme named by
+/// `branchless.color.theme`, with the style of each role overridden by
+/// `branchless.color.<role>`, if set (see [`Theme::ROLE_NAMES`]).
+#[instrument]
+pub fn get_color_theme(repo: &Repo) -> eyre::Result<Theme> {
+    let config = repo.get_readonly_config()?;
+    let theme_name: String = config.get_or("branchless.color.theme", "default".to_string())?;
+    let mut theme = match Theme::from_name(&theme_name) {
+        Some(theme) => theme,
+        None => eyre::bail!(
+            "Unknown color theme {:?} for branchless.color.theme (expected one of: {})",
+            theme_name,
+            THEME_NAMES.join(", ")
+        ),
+    };
+    for role in Theme::ROLE_NAMES {
+        let key = format!("branchless.color.{role}");
+        let spec: Option<String> = config.get(&key)?;
+        if let (Some(spec), Some(style)) = (spec, theme.get_role_mut(role)) {
+            *style = parse_style(&spec).wrap_err_with(|| format!("Parsing {key}"))?;
+        }
+    }
+    Ok(theme)
+}
+
+/// Get the user name used as the first component of the branches created by
+/// `git branchless branch --stack`, if configured.
+#[instrument]
+pub fn get_stack_branches_user(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.stackBranches.user")
+}
+
+/// Get the shell command used to query the CI status of commits from the code
+/// forge, if any.
+///
+/// The command is invoked with the full OIDs of the commits to query as
+/// arguments, and should print one line of the form `<oid> <status>` for each
+/// commit which has a CI status.
+#[instrument]
+pub fn get_ci_status_command(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.ci.statusCommand")
+}
+
+/// Get the commands to run after a branchless operation rewrites commits, for
+/// the given hook name, such as `postRewrite` or `postSync`.
+///
+/// This is a multivariable key (`branchless.hooks.<hook-name>`), so it can be
+/// set more than once to register several hooks.
+#[instrument]
+pub fn get_user_hooks(repo: &Repo, hook_name: &str) -> eyre::Result<Vec<String>> {
+    repo.get_readonly_config()?
+        .get_or(format!("branchless.hooks.{}", hook_name), Vec::new())
+}
+
+/// Whether to run Git's `pre-commit` and `commit-msg` hooks for commits which
+/// are created in memory, such as by `git amend` and `git reword`.
+#[instrument]
+pub fn get_run_commit_hooks(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.hooks.runCommitHooks", false)
+}
+
+/// Get the path to the shared hook script which hooks are symlinked to by
+/// `git branchless init --hook-type symlink`, if configured.
+#[instrument]
+pub fn get_shared_hook_script_path(repo: &Repo) -> eyre::Result<Option<PathBuf>> {
+    repo.get_readonly_config()?
+        .get("branchless.hooks.sharedScript")
+}
+
+/// The number of seconds for which the CI status of a commit is cached before
+/// it's queried again.
+#[instrument]
+pub fn get_ci_status_cache_ttl(repo: &Repo) -> eyre::Result<Duration> {
+    let ttl: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.ci.cacheTtl", 300)?;
+    Ok(Duration::from_secs(u64::try_from(ttl).unwrap_or_default()))
+}
+
+/// After warning about the commits abandoned by a rewrite, only a one-line
+/// hint is printed for further abandoned commits until this much time has
+/// passed. Zero means that the full warning is always printed.
+#[instrument]
+pub fn get_restack_warn_abandoned_interval(repo: &Repo) -> eyre::Result<Duration> {
+    let interval: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.restack.warnAbandonedInterval", 600)?;
+    Ok(Duration::from_secs(
+        u64::try_from(interval).unwrap_or_default(),
+    ))
+}
+
+/// The number of commits which an operation such as `git move` or
+/// `git reword` can rewrite before asking for confirmation. If `0`, the
+/// operation never asks for confirmation.
+#[instrument]
+pub fn get_rewrite_confirm_commit_threshold(repo: &Repo) -> eyre::Result<usize> {
+    let threshold: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.rewrite.confirmCommitThreshold", 100)?;
+    Ok(usize::try_from(threshold).unwrap_or_default())
+}
+
+/// The number of branches which an operation such as `git move` or
+/// `git sync` can move before asking for confirmation. If `0`, the operation
+/// never asks for confirmation.
+#[instrument]
+pub fn get_rewrite_confirm_branch_threshold(repo: &Repo) -> eyre::Result<usize> {
+    let threshold: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.rewrite.confirmBranchThreshold", 20)?;
+    Ok(usize::try_from(threshold).unwrap_or_default())
+}
+
 /// Config key for `get_restack_warn_abandoned`.
 pub const RESTACK_WARN_ABANDONED_CONFIG_KEY: &str = "branchless.restack.warnAbandoned";
 
@@ -215,12 +876,454 @@ pub fn print_hint_suppression_notice(effects: &Effects, hint: Hint) -> eyre::Res
     writeln!(
         effects.get_output_stream(),
         "{}: disable this hint by running: git config --global {} false",
-        style("hint").blue().bold(),
+        effects.get_glyphs().render_hint_label()?,
         hint.get_config_key(),
     )?;
     Ok(())
 }
 
+/// The type of value expected for a configuration key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigKeyType {
+    /// A boolean, such as `true` or `false`.
+    Bool,
+
+    /// A non-negative integer.
+    Int,
+
+    /// An arbitrary string.
+    String,
+
+    /// A path on disk. A leading `~/` is expanded to the home directory.
+    Path,
+
+    /// One of a fixed set of strings.
+    Choice(&'static [&'static str]),
+
+    /// A multivariable key, which can be set to several arbitrary strings.
+    MultiString,
+}
+
+impl ConfigKeyType {
+    /// Check that the given value is valid for this type, and normalize it if
+    /// appropriate (e.g. `yes` to `true`). Returns a description of the
+    /// problem if the value is invalid.
+    pub fn validate(&self, value: &str) -> Result<String, String> {
+        match self {
+            ConfigKeyType::Bool => match value.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok("true".to_string()),
+                "false" | "no" | "off" | "0" => Ok("false".to_string()),
+                _ => Err(format!("expected a boolean, but got: {}", value)),
+            },
+            ConfigKeyType::Int => match value.parse::<u32>() {
+                Ok(value) => Ok(value.to_string()),
+                Err(_) => Err(format!(
+                    "expected a non-negative integer, but got: {}",
+                    value
+                )),
+            },
+            ConfigKeyType::Choice(choices) => {
+                if choices.contains(&value) {
+                    Ok(value.to_string())
+                } else {
+                    Err(format!(
+                        "expected one of {}, but got: {}",
+                        choices.join(", "),
+                        value
+                    ))
+                }
+            }
+            ConfigKeyType::String | ConfigKeyType::Path | ConfigKeyType::MultiString => {
+                if value.is_empty() {
+                    Err("expected a non-empty value".to_string())
+                } else {
+                    Ok(value.to_string())
+                }
+            }
+        }
+    }
+
+    /// A short description of the type, for display.
+    pub fn describe(&self) -> String {
+        match self {
+            ConfigKeyType::Bool => "bool".to_string(),
+            ConfigKeyType::Int => "int".to_string(),
+            ConfigKeyType::String => "string".to_string(),
+            ConfigKeyType::Path => "path".to_string(),
+            ConfigKeyType::Choice(choices) => choices.join("|"),
+            ConfigKeyType::MultiString => "string (multiple)".to_string(),
+        }
+    }
+}
+
+/// Information about a configuration key understood by `git-branchless`.
+#[derive(Clone, Debug)]
+pub struct ConfigKeyInfo {
+    /// The name of the key. If it ends with `.*`, then it describes a family
+    /// of keys, such as `branchless.revsets.alias.<name>`.
+    pub key: &'static str,
+
+    /// The type of value expected for the key.
+    pub value_type: ConfigKeyType,
+
+    /// The value used when the key isn't set, if any.
+    pub default: Option<&'static str>,
+
+    /// A short description of what the key does.
+    pub description: &'static str,
+}
+
+impl ConfigKeyInfo {
+    /// Whether this entry describes the given key. Like Git, the comparison
+    /// ignores case.
+    pub fn matches(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        let own_key = self.key.to_lowercase();
+        match own_key.strip_suffix('*') {
+            Some(prefix) => key.len() > prefix.len() && key.starts_with(prefix),
+            None => key == own_key,
+        }
+    }
+}
+
+/// The configuration keys understood by `git-branchless`. Keep this in sync
+/// with the getters above.
+pub const CONFIG_KEYS: &[ConfigKeyInfo] = &[
+    ConfigKeyInfo {
+        key: "branchless.core.mainBranch",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "The name of the main branch. Defaults to init.defaultBranch, or master.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.core.additionalMainBranches",
+        value_type: ConfigKeyType::MultiString,
+        default: None,
+        description: "Other branches, such as release branches, whose commits are public.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.core.publicRemoteBranches",
+        value_type: ConfigKeyType::MultiString,
+        default: None,
+        description: "Glob patterns for remote-tracking branches whose commits are public.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.core.protectedBranches",
+        value_type: ConfigKeyType::MultiString,
+        default: None,
+        description: "Glob patterns for branches which rewrites may not move or delete.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.core.refPatternsToIgnore",
+        value_type: ConfigKeyType::MultiString,
+        default: None,
+        description: "Glob patterns for references whose updates aren't recorded.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.core.useWatchman",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Query Watchman for changed files instead of running git status.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.core.dagSyncChunkSize",
+        value_type: ConfigKeyType::Int,
+        default: Some("5000"),
+        description: "The number of main branch commits to add to the commit graph at a time.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.reword.directivePrefix",
+        value_type: ConfigKeyType::String,
+        default: Some("++"),
+        description: "The prefix of the directive lines when rewording several commits.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.reword.context",
+        value_type: ConfigKeyType::Choice(&["none", "stat", "files"]),
+        default: Some("none"),
+        description: "The context to show under each commit message in git reword.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.reword.reflowColumn",
+        value_type: ConfigKeyType::Int,
+        default: Some("72"),
+        description: "The column to wrap commit message bodies at with git reword --reflow.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.reword.lint",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Whether to warn about style problems in commit messages edited with git reword.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.reword.maxSubjectLength",
+        value_type: ConfigKeyType::Int,
+        default: Some("72"),
+        description: "The subject line length above which git reword warns, if branchless.reword.lint is set.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.reword.lintCommand",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A command, such as a spell checker, whose output is shown as warnings for messages edited with git reword.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.commit.msgHook",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A command which validates new commit messages.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.commit.template",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A message template for git record, with {branch}, {ticket} and {files}.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.undo.createSnapshots",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Create working copy snapshots automatically.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.bugReport.recordTraces",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Record a trace of each command for inclusion in git branchless bug-report.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.restack.auto",
+        value_type: ConfigKeyType::Choice(&["false", "hint", "true"]),
+        default: Some("false"),
+        description: "Restack commits abandoned by git commit --amend and the like.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.restack.currentStackOnly",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Only restack the current stack when git restack is given no commits.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.restack.preserveTimestamps",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Keep the committer timestamps of restacked commits.",
+    },
+    ConfigKeyInfo {
+        key: RESTACK_WARN_ABANDONED_CONFIG_KEY,
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Warn when a rewrite abandons commits.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.restack.warnAbandonedInterval",
+        value_type: ConfigKeyType::Int,
+        default: Some("600"),
+        description: "Seconds after a warning about abandoned commits during which only a one-line hint is shown.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.rewrite.confirmCommitThreshold",
+        value_type: ConfigKeyType::Int,
+        default: Some("100"),
+        description: "The number of rewritten commits above which to ask for confirmation.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.rewrite.confirmBranchThreshold",
+        value_type: ConfigKeyType::Int,
+        default: Some("20"),
+        description: "The number of moved branches above which to ask for confirmation.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.next.interactive",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Prompt when git next has several commits to choose from.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.smartlog.changesBanner",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Summarize what changed since the smartlog was last rendered.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.smartlog.glyphs",
+        value_type: ConfigKeyType::Choice(GLYPH_SET_NAMES),
+        default: None,
+        description: "The characters to draw the smartlog with. Defaults to unicode on a TTY.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.smartlog.headGlyph",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A custom character for the checked-out commit in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.smartlog.hiddenGlyph",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A custom character for hidden commits in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.smartlog.mainGlyph",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A custom character for main branch commits in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.smartlog.cache",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Reuse the rendered smartlog if nothing has changed since.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.commitDescriptors.branches",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Show branches in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.commitDescriptors.differentialRevision",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Show Phabricator revisions in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.commitDescriptors.ciStatus",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Show CI statuses in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.commitDescriptors.signature",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Show commit signature statuses in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.commitDescriptors.metadata",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Show commit metadata in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.commitDescriptors.relativeTime",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Show the age of each commit in the smartlog.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.maintenance.writeCommitGraph",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Regenerate the commit-graph file after gc and large rewrites.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.maintenance.rewriteThreshold",
+        value_type: ConfigKeyType::Int,
+        default: Some("100"),
+        description: "The number of rewritten commits which triggers maintenance.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.maintenance.keepRefCompactionThreshold",
+        value_type: ConfigKeyType::Int,
+        default: Some("1000"),
+        description: "The number of keep references at which they're compacted into one.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.diff.wordDiff",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Highlight changed words in diffs, and preview the changes in merge conflicts.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.stackBranches.user",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "The user name prefixed to stack branches. Defaults to user.email.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.ci.statusCommand",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A command which queries the CI status of commits.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.ci.cacheTtl",
+        value_type: ConfigKeyType::Int,
+        default: Some("300"),
+        description: "The number of seconds for which CI statuses are cached.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.hooks.runCommitHooks",
+        value_type: ConfigKeyType::Bool,
+        default: Some("false"),
+        description: "Run pre-commit and commit-msg hooks for in-memory commits.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.hooks.sharedScript",
+        value_type: ConfigKeyType::Path,
+        default: None,
+        description: "The shared hook script used by init --hook-type symlink.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.hint.restackWarnAbandoned",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Suggest git restack when commits are abandoned.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.hint.smartlogFixAbandoned",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Suggest git restack when the smartlog shows abandoned commits.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.hint.moveImplicitHeadArgument",
+        value_type: ConfigKeyType::Bool,
+        default: Some("true"),
+        description: "Suggest omitting git move arguments which default to HEAD.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.color.theme",
+        value_type: ConfigKeyType::Choice(THEME_NAMES),
+        default: Some("default"),
+        description: "The built-in color theme; high-contrast is readable on light terminals.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.color.*",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A Git color, such as bold red, for one role, like branchless.color.branch.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.hooks.*",
+        value_type: ConfigKeyType::MultiString,
+        default: None,
+        description: "Commands to run after operations, such as branchless.hooks.postRewrite.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.revsets.alias.*",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A revset alias, usable as a function in revset expressions.",
+    },
+    ConfigKeyInfo {
+        key: "branchless.revsets.function.*",
+        value_type: ConfigKeyType::String,
+        default: None,
+        description: "A shell command implementing a revset function.",
+    },
+];
+
+/// Look up the registry entry describing the given key, if any. Exact entries
+/// take precedence over families of keys.
+pub fn find_config_key_info(key: &str) -> Option<&'static ConfigKeyInfo> {
+    CONFIG_KEYS
+        .iter()
+        .find(|info| !info.key.ends_with('*') && info.matches(key))
+        .or_else(|| CONFIG_KEYS.iter().find(|info| info.matches(key)))
+}
+
 /// Environment variables which affect the functioning of `git-branchless`.
 pub mod env_vars {
     use std::path::PathBuf;
@@ -237,6 +1340,12 @@ pub mod env_vars {
     /// See <https://git-scm.com/docs/git#Documentation/git.txt---exec-pathltpathgt>.
     pub const TEST_GIT_EXEC_PATH: &str = "TEST_GIT_EXEC_PATH";
 
+    /// Path to the `git-branchless` executable to use during tests. If not
+    /// set, the executable built by Cargo for the package under test is used.
+    /// Projects which build on git-branchless should set this to run their
+    /// tests against an installed copy.
+    pub const TEST_GIT_BRANCHLESS: &str = "TEST_GIT_BRANCHLESS";
+
     /// Get the path to the Git executable for testing.
     #[instrument]
     pub fn get_path_to_git() -> eyre::Result<PathBuf> {
@@ -268,4 +1377,77 @@ or set `env.{0}` in your `config.toml` \
         let git_exec_path = PathBuf::from(&git_exec_path);
         Ok(git_exec_path)
     }
+
+    /// Get the path to the `git-branchless` executable for testing, if it was
+    /// overridden with [`TEST_GIT_BRANCHLESS`].
+    #[instrument]
+    pub fn get_path_to_git_branchless() -> Option<PathBuf> {
+        std::env::var_os(TEST_GIT_BRANCHLESS).map(PathBuf::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_editor() {
+        let env = |value: &str| Some(OsString::from(value));
+        assert_eq!(
+            resolve_editor(
+                env("git-editor"),
+                Some("core".into()),
+                env("visual"),
+                env("editor"),
+                false
+            ),
+            env("git-editor")
+        );
+        assert_eq!(
+            resolve_editor(
+                None,
+                Some("core".into()),
+                env("visual"),
+                env("editor"),
+                false
+            ),
+            env("core")
+        );
+        assert_eq!(
+            resolve_editor(env(""), None, env("visual"), env("editor"), false),
+            env("visual")
+        );
+        assert_eq!(
+            resolve_editor(None, None, env("visual"), env("editor"), true),
+            env("editor")
+        );
+        assert_eq!(resolve_editor(None, None, None, None, false), env("vi"));
+        assert_eq!(resolve_editor(None, None, env("visual"), None, true), None);
+    }
+
+    #[test]
+    fn test_add_editor_wait_option() {
+        assert_eq!(add_editor_wait_option("vim"), "vim");
+        assert_eq!(add_editor_wait_option("code"), "code --wait");
+        assert_eq!(add_editor_wait_option("code --wait"), "code --wait");
+        assert_eq!(add_editor_wait_option("code -w -n"), "code -w -n");
+        assert_eq!(
+            add_editor_wait_option("/usr/local/bin/subl"),
+            "/usr/local/bin/subl --wait"
+        );
+        assert_eq!(add_editor_wait_option("gvim"), "gvim -f");
+        assert_eq!(
+            add_editor_wait_option(r#""C:\Program Files\Microsoft VS Code\bin\Code.cmd""#),
+            r#""C:\Program Files\Microsoft VS Code\bin\Code.cmd" --wait"#
+        );
+        assert_eq!(add_editor_wait_option("vscode-wrapper"), "vscode-wrapper");
+    }
+
+    #[test]
+    fn test_is_graphical_editor() {
+        assert!(is_graphical_editor("code --wait"));
+        assert!(is_graphical_editor("/usr/local/bin/subl"));
+        assert!(!is_graphical_editor("vim"));
+        assert!(!is_graphical_editor("true"));
+    }
 }
@@ -7,14 +7,16 @@ use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::iter::FromIterator;
 
-use eden_dag::ops::DagPersistent;
-use eden_dag::DagAlgorithm;
+use eden_dag::ops::{DagAddHeads, DagPersistent, IdConvert};
+use eden_dag::{DagAlgorithm, Group};
 use eyre::Context;
 use itertools::Itertools;
 use tracing::{instrument, trace, warn};
 
+use crate::core::config::get_dag_sync_chunk_size;
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::{CommitActivityStatus, EventCursor, EventReplayer};
+use crate::error::Error;
 use crate::git::{Commit, MaybeZeroOid, NonZeroOid, Repo, Time};
 
 use super::repo_ext::RepoReferencesSnapshot;
@@ -101,9 +103,25 @@ pub struct Dag {
     /// A set containing the commit that the main branch currently points to.
     pub main_branch_commit: CommitSet,
 
+    /// A set containing the commits that the additional main branches (such
+    /// as release branches) currently point to. Like the main branch, their
+    /// ancestors are considered public.
+    pub additional_main_branch_commits: CommitSet,
+
+    /// A set containing the commits that the public remote-tracking branches
+    /// currently point to. Their ancestors are considered public, but unlike
+    /// the main branches, they aren't rendered in the smartlog unless they're
+    /// otherwise visible.
+    pub public_remote_branch_commits: CommitSet,
+
     /// A set containing all commits currently pointed to by local branches.
     pub branch_commits: CommitSet,
 
+    /// A set containing the commits checked out in worktrees other than the
+    /// current one. Like `head_commit`, these are always considered active.
+    /// This is empty unless populated by the caller.
+    pub worktree_commits: CommitSet,
+
     /// A set containing all commits that have been observed by the
     /// `EventReplayer`.
     pub observed_commits: CommitSet,
@@ -111,6 +129,62 @@ pub struct Dag {
     /// A set containing all commits that have been determined to be obsolete by
     /// the `EventReplayer`.
     pub obsolete_commits: CommitSet,
+
+    /// A set containing the commits that were observed by the `EventReplayer`
+    /// and not obsolete, but which no longer exist in the repository, such as
+    /// because they were garbage collected. These commits are not included in
+    /// `observed_commits` or `obsolete_commits`.
+    pub evaporated_commits: CommitSet,
+}
+
+/// Get the parents of the commit with the given vertex name, for use when
+/// adding commits to the DAG.
+fn get_parent_vertices(repo: &Repo, v: CommitVertex) -> eden_dag::Result<Vec<CommitVertex>> {
+    use eden_dag::errors::BackendError;
+    trace!(?v, "visiting Git commit");
+
+    let oid = MaybeZeroOid::from_bytes(v.as_ref())
+        .map_err(|_e| anyhow::anyhow!("Could not convert to Git oid: {:?}", &v))
+        .map_err(BackendError::Other)?;
+    let oid = match oid {
+        MaybeZeroOid::NonZero(oid) => oid,
+        MaybeZeroOid::Zero => return Ok(Vec::new()),
+    };
+
+    let commit = repo
+        .find_commit(oid)
+        .map_err(|_e| anyhow::anyhow!("Could not resolve to Git commit: {:?}", &v))
+        .map_err(BackendError::Other)?;
+    let commit = match commit {
+        Some(commit) => commit,
+        None => {
+            // This might be an OID that's been garbage collected, or just a
+            // non-commit object. Ignore it in either case.
+            return Ok(Vec::new());
+        }
+    };
+
+    Ok(commit
+        .get_parent_oids()
+        .into_iter()
+        .map(CommitVertex::from)
+        .collect())
+}
+
+/// Convert a commit set which was produced statically (i.e. not from a DAG
+/// query) into a list of vertices.
+fn commit_set_to_static_vec(commit_set: CommitSet) -> Vec<CommitVertex> {
+    let mut result = Vec::new();
+    for vertex in commit_set
+        .iter()
+        .expect("The commit set was produced statically, so iteration should not fail")
+    {
+        let vertex = vertex.expect(
+            "The commit set was produced statically, so accessing a vertex should not fail",
+        );
+        result.push(vertex);
+    }
+    result
 }
 
 impl Dag {
@@ -148,52 +222,116 @@ impl Dag {
         event_replayer: &EventReplayer,
         event_cursor: EventCursor,
         references_snapshot: &RepoReferencesSnapshot,
+    ) -> eyre::Result<Self> {
+        let dag_dir = repo.get_dag_dir();
+        std::fs::create_dir_all(&dag_dir).wrap_err("Creating .git/branchless/dag dir")?;
+        let dag = eden_dag::Dag::open(&dag_dir)
+            .wrap_err_with(|| format!("Opening DAG directory at: {:?}", &dag_dir))
+            .wrap_err(Error::Corruption {
+                message: "The commit graph could not be loaded.".to_string(),
+                hint: Some(
+                    "Run `git branchless repair --no-dry-run` to try to fix this.".to_string(),
+                ),
+            })?;
+        Self::from_inner(dag, repo, event_replayer, event_cursor, references_snapshot)
+    }
+
+    /// Update a long-lived DAG, such as one kept by a server across requests,
+    /// to the point in time represented by `event_cursor`, and update it with
+    /// any newly-referenced commits. The underlying commit graph is reused,
+    /// so only the commits which it doesn't already contain are added.
+    #[instrument]
+    pub fn refresh(
+        self,
+        effects: &Effects,
+        repo: &Repo,
+        event_replayer: &EventReplayer,
+        event_cursor: EventCursor,
+        references_snapshot: &RepoReferencesSnapshot,
+    ) -> eyre::Result<Self> {
+        let mut dag = Self::from_inner(
+            self.inner,
+            repo,
+            event_replayer,
+            event_cursor,
+            references_snapshot,
+        )?;
+        dag.sync(effects, repo)?;
+        Ok(dag)
+    }
+
+    fn from_inner(
+        dag: eden_dag::Dag,
+        repo: &Repo,
+        event_replayer: &EventReplayer,
+        event_cursor: EventCursor,
+        references_snapshot: &RepoReferencesSnapshot,
     ) -> eyre::Result<Self> {
         let observed_commits = event_replayer.get_cursor_oids(event_cursor);
         let RepoReferencesSnapshot {
             head_oid,
             main_branch_oid,
+            additional_main_branch_oids,
+            public_remote_branch_oids,
             branch_oid_to_names,
         } = references_snapshot;
 
-        let obsolete_commits: CommitSet = observed_commits
-            .iter()
-            .copied()
-            .filter(|commit_oid| {
-                match event_replayer.get_cursor_commit_activity_status(event_cursor, *commit_oid) {
+        let mut available_commits = Vec::new();
+        let mut obsolete_commits = Vec::new();
+        let mut evaporated_commits = Vec::new();
+        for commit_oid in observed_commits {
+            let is_obsolete =
+                match event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid) {
                     CommitActivityStatus::Active | CommitActivityStatus::Inactive => false,
                     CommitActivityStatus::Obsolete => true,
+                };
+            if !repo.contains_object(commit_oid)? {
+                if !is_obsolete {
+                    evaporated_commits.push(commit_oid);
                 }
-            })
-            .collect();
-
-        let dag_dir = repo.get_dag_dir();
-        std::fs::create_dir_all(&dag_dir).wrap_err("Creating .git/branchless/dag dir")?;
-        let dag = eden_dag::Dag::open(&dag_dir)
-            .wrap_err_with(|| format!("Opening DAG directory at: {:?}", &dag_dir))?;
+                continue;
+            }
+            available_commits.push(commit_oid);
+            if is_obsolete {
+                obsolete_commits.push(commit_oid);
+            }
+        }
 
-        let observed_commits: CommitSet = observed_commits.into_iter().collect();
+        let observed_commits: CommitSet = available_commits.into_iter().collect();
+        let obsolete_commits: CommitSet = obsolete_commits.into_iter().collect();
+        let evaporated_commits: CommitSet = evaporated_commits.into_iter().collect();
         let head_commit = match head_oid {
             Some(head_oid) => CommitSet::from(*head_oid),
             None => CommitSet::empty(),
         };
         let main_branch_commit = CommitSet::from(*main_branch_oid);
+        let additional_main_branch_commits: CommitSet =
+            additional_main_branch_oids.iter().copied().collect();
+        let public_remote_branch_commits: CommitSet =
+            public_remote_branch_oids.iter().copied().collect();
         let branch_commits: CommitSet = branch_oid_to_names.keys().copied().collect();
 
         Ok(Self {
             inner: dag,
             head_commit,
             main_branch_commit,
+            additional_main_branch_commits,
+            public_remote_branch_commits,
             branch_commits,
+            worktree_commits: CommitSet::empty(),
             observed_commits,
             obsolete_commits,
+            evaporated_commits,
         })
     }
 
     /// This function's code adapted from `GitDag`, licensed under GPL-2.
     #[instrument]
     fn sync(&mut self, effects: &Effects, repo: &Repo) -> eyre::Result<()> {
-        let master_heads = self.main_branch_commit.clone();
+        let master_heads = self
+            .main_branch_commit
+            .union(&self.additional_main_branch_commits)
+            .union(&self.public_remote_branch_commits);
         let non_master_heads = self
             .observed_commits
             .union(&self.head_commit)
@@ -202,6 +340,13 @@ impl Dag {
     }
 
     /// Update the DAG with the given heads.
+    ///
+    /// If the main branch heads are far ahead of the commits already in the
+    /// DAG, such as after a large fetch, the new commits are added in chunks of
+    /// `branchless.core.dagSyncChunkSize` commits along the first-parent
+    /// history, and each chunk is written to disk before the next one is
+    /// added. That way, progress can be reported, and an interrupted sync
+    /// resumes from the last chunk instead of starting over.
     #[instrument]
     pub fn sync_from_oids(
         &mut self,
@@ -210,62 +355,104 @@ impl Dag {
         master_heads: CommitSet,
         non_master_heads: CommitSet,
     ) -> eyre::Result<()> {
-        let (effects, _progress) = effects.start_operation(OperationType::UpdateCommitGraph);
+        let (effects, progress) = effects.start_operation(OperationType::UpdateCommitGraph);
         let _effects = effects;
 
-        let parent_func = |v: CommitVertex| -> eden_dag::Result<Vec<CommitVertex>> {
-            use eden_dag::errors::BackendError;
-            trace!(?v, "visiting Git commit");
-
-            let oid = MaybeZeroOid::from_bytes(v.as_ref())
-                .map_err(|_e| anyhow::anyhow!("Could not convert to Git oid: {:?}", &v))
-                .map_err(BackendError::Other)?;
-            let oid = match oid {
-                MaybeZeroOid::NonZero(oid) => oid,
-                MaybeZeroOid::Zero => return Ok(Vec::new()),
-            };
-
-            let commit = repo
-                .find_commit(oid)
-                .map_err(|_e| anyhow::anyhow!("Could not resolve to Git commit: {:?}", &v))
-                .map_err(BackendError::Other)?;
-            let commit = match commit {
-                Some(commit) => commit,
-                None => {
-                    // This might be an OID that's been garbage collected, or
-                    // just a non-commit object. Ignore it in either case.
-                    return Ok(Vec::new());
-                }
-            };
-
-            Ok(commit
-                .get_parent_oids()
-                .into_iter()
-                .map(CommitVertex::from)
-                .collect())
-        };
-
-        let commit_set_to_vec = |commit_set: CommitSet| -> Vec<CommitVertex> {
-            let mut result = Vec::new();
-            for vertex in commit_set
-                .iter()
-                .expect("The commit set was produced statically, so iteration should not fail")
-            {
-                let vertex = vertex.expect(
-                    "The commit set was produced statically, so accessing a vertex should not fail",
-                );
-                result.push(vertex);
+        let parent_func = |v: CommitVertex| get_parent_vertices(repo, v);
+        let master_heads = commit_set_to_static_vec(master_heads);
+        let non_master_heads = commit_set_to_static_vec(non_master_heads);
+
+        let chunk_size = get_dag_sync_chunk_size(repo)?;
+        if chunk_size > 0 {
+            let chunk_heads = self.find_sync_chunk_heads(repo, &master_heads, chunk_size)?;
+            progress.notify_progress(0, chunk_heads.len());
+            for chunk_head in chunk_heads {
+                self.inner
+                    .add_heads_and_flush(parent_func, &[chunk_head], &[])?;
+                progress.notify_progress_inc(1);
             }
-            result
-        };
+        }
+
         self.inner.add_heads_and_flush(
             parent_func,
-            commit_set_to_vec(master_heads).as_slice(),
-            commit_set_to_vec(non_master_heads).as_slice(),
+            master_heads.as_slice(),
+            non_master_heads.as_slice(),
         )?;
         Ok(())
     }
 
+    /// Update the DAG in memory with the commits which are referenced, without
+    /// writing them to disk. This is suitable for previewing the result of an
+    /// operation, such as for a dry run, since the on-disk DAG is left
+    /// unchanged.
+    #[instrument]
+    pub fn sync_without_flushing(&mut self, repo: &Repo) -> eyre::Result<()> {
+        let heads = self
+            .main_branch_commit
+            .union(&self.additional_main_branch_commits)
+            .union(&self.public_remote_branch_commits)
+            .union(&self.observed_commits)
+            .union(&self.head_commit)
+            .union(&self.branch_commits);
+        let heads = commit_set_to_static_vec(heads);
+        self.inner
+            .add_heads(|v: CommitVertex| get_parent_vertices(repo, v), &heads)?;
+        Ok(())
+    }
+
+    /// Find the commits to add to the DAG as intermediate main branch heads
+    /// before adding `master_heads` themselves. These are every `chunk_size`th
+    /// commit along the first-parent history of each head which isn't already
+    /// in the main branch group of the DAG, ordered from oldest to newest.
+    #[instrument]
+    fn find_sync_chunk_heads(
+        &self,
+        repo: &Repo,
+        master_heads: &[CommitVertex],
+        chunk_size: usize,
+    ) -> eyre::Result<Vec<CommitVertex>> {
+        let mut result = Vec::new();
+        for head in master_heads {
+            let mut new_oids = Vec::new();
+            let mut current_oid = match MaybeZeroOid::try_from(head.clone())? {
+                MaybeZeroOid::NonZero(oid) => oid,
+                MaybeZeroOid::Zero => continue,
+            };
+            loop {
+                let vertex = CommitVertex::from(current_oid);
+                if self
+                    .inner
+                    .vertex_id_with_max_group(&vertex, Group::MASTER)?
+                    .is_some()
+                {
+                    break;
+                }
+                let commit = match repo.find_commit(current_oid)? {
+                    Some(commit) => commit,
+                    None => break,
+                };
+                new_oids.push(vertex);
+                match commit.get_parent_oids().first() {
+                    Some(parent_oid) => current_oid = *parent_oid,
+                    None => break,
+                }
+            }
+
+            // The head itself is added last by the caller, so don't include
+            // it as a chunk.
+            let num_new_commits = new_oids.len();
+            result.extend(
+                new_oids
+                    .into_iter()
+                    .rev()
+                    .enumerate()
+                    .filter(|(i, _)| (i + 1) % chunk_size == 0 && i + 1 < num_new_commits)
+                    .map(|(_, vertex)| vertex),
+            );
+        }
+        Ok(result)
+    }
+
     /// Create a new version of this DAG at the point in time represented by
     /// `event_cursor`.
     pub fn set_cursor(
@@ -361,9 +548,15 @@ impl Dag {
         self.inner.borrow()
     }
 
-    /// Return the set of commits which are public (checked into the main branch).
+    /// Return the set of commits which are public (checked into the main
+    /// branch, one of the additional main branches, or one of the public
+    /// remote-tracking branches).
     pub fn query_public_commits(&self) -> eyre::Result<CommitSet> {
-        let public_commits = self.query().ancestors(self.main_branch_commit.clone())?;
+        let public_commits = self.query().ancestors(
+            self.main_branch_commit
+                .union(&self.additional_main_branch_commits)
+                .union(&self.public_remote_branch_commits),
+        )?;
         Ok(public_commits)
     }
 
@@ -389,8 +582,10 @@ impl Dag {
         let anomalous_main_branch_commits = self.obsolete_commits.intersection(public_commits);
         let active_heads = active_heads
             .union(&self.head_commit)
+            .union(&self.worktree_commits)
             .union(&self.branch_commits)
             .union(&self.main_branch_commit)
+            .union(&self.additional_main_branch_commits)
             .union(&anomalous_main_branch_commits);
 
         Ok(active_heads)
@@ -545,3 +740,63 @@ pub fn sorted_commit_set<'repo>(
 
     Ok(commits)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::eventlog::EventLogDb;
+    use crate::core::formatting::Glyphs;
+    use crate::core::repo_ext::RepoExt;
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_find_sync_chunk_heads() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let references_snapshot = repo.get_references_snapshot()?;
+        let dag = Dag::open_and_sync(
+            &effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+
+        // The DAG was opened before these commits were made, so none of them
+        // are in its main branch group yet.
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        let test4_oid = git.commit_file("test4", 4)?;
+        let test5_oid = git.commit_file("test5", 5)?;
+        let repo = git.get_repo()?;
+        let heads = [CommitVertex::from(test5_oid)];
+
+        assert_eq!(
+            dag.find_sync_chunk_heads(&repo, &heads, 2)?,
+            vec![CommitVertex::from(test2_oid), CommitVertex::from(test4_oid)]
+        );
+        assert_eq!(
+            dag.find_sync_chunk_heads(&repo, &heads, 1)?,
+            vec![
+                CommitVertex::from(test1_oid),
+                CommitVertex::from(test2_oid),
+                CommitVertex::from(test3_oid),
+                CommitVertex::from(test4_oid),
+            ]
+        );
+        // The head itself is never a chunk head, since the caller adds it
+        // afterwards.
+        assert_eq!(dag.find_sync_chunk_heads(&repo, &heads, 5)?, vec![]);
+
+        Ok(())
+    }
+}
@@ -4,16 +4,23 @@ use std::convert::TryInto;
 use std::fmt::Write;
 use std::io::{stderr, stdout, Stderr, Stdout, Write as WriteIo};
 use std::mem::take;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
 
+use eyre::Context;
 use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use itertools::Itertools;
 use lazy_static::lazy_static;
+use once_cell::sync::OnceCell;
+use signal_hook::SigId;
 use tracing::warn;
 
-use crate::core::formatting::Glyphs;
+use crate::core::formatting::{Glyphs, Theme};
+use crate::core::i18n::{FluentValue, Messages};
+use crate::util::get_sh;
 
 #[allow(missing_docs)]
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -39,6 +46,7 @@ pub enum OperationType {
     RebaseCommits,
     RepairBranches,
     RepairCommits,
+    RepairDag,
     RunGitCommand(Arc<String>),
     SortCommits,
     SyncCommits,
@@ -72,6 +80,7 @@ impl ToString for OperationType {
             OperationType::RebaseCommits => "Rebasing commits",
             OperationType::RepairBranches => "Checking for broken branches",
             OperationType::RepairCommits => "Checking for broken commits",
+            OperationType::RepairDag => "Checking the commit graph",
             OperationType::RunGitCommand(command) => {
                 return format!("Running Git command: {}", &command)
             }
@@ -87,11 +96,76 @@ impl ToString for OperationType {
 #[derive(Clone, Debug)]
 enum OutputDest {
     Stdout,
+    /// Like `Stdout`, but regular output is written to stderr instead, so
+    /// that stdout can be reserved for machine-readable output.
+    Stderr,
     Suppress,
-    BufferForTest {
+    Buffer {
         stdout: Arc<Mutex<Vec<u8>>>,
         stderr: Arc<Mutex<Vec<u8>>>,
     },
+    /// Like `Stdout`, but regular output is piped into a pager. Progress isn't
+    /// displayed, since it would be drawn over the pager.
+    Pager(Arc<Pager>),
+}
+
+/// A running pager process, such as `less`. When dropped, its input is closed
+/// and the pager is waited on, so that the user can finish reading the output
+/// before the process exits.
+struct Pager {
+    child: Mutex<Child>,
+    stdin: Mutex<Option<ChildStdin>>,
+}
+
+impl std::fmt::Debug for Pager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Pager>")
+    }
+}
+
+impl Pager {
+    fn spawn(pager: &str) -> eyre::Result<Self> {
+        let sh = get_sh().ok_or_else(|| eyre::eyre!("Could not find sh to run pager"))?;
+        let mut command = Command::new(sh);
+        command.arg("-c").arg(pager).stdin(Stdio::piped());
+        // Like Git, pass options to `less` and `lv` so that they exit if the
+        // output fits on one screen and render colors, unless the user has
+        // already configured them.
+        for (var, value) in [("LESS", "FRX"), ("LV", "-c")] {
+            if std::env::var_os(var).is_none() {
+                command.env(var, value);
+            }
+        }
+        let mut child = command
+            .spawn()
+            .wrap_err_with(|| format!("Spawning pager: {}", pager))?;
+        let stdin = child.stdin.take();
+        Ok(Self {
+            child: Mutex::new(child),
+            stdin: Mutex::new(stdin),
+        })
+    }
+
+    fn write(&self, s: &str) {
+        let mut stdin = self.stdin.lock().unwrap();
+        if let Some(child_stdin) = stdin.as_mut() {
+            if let Err(err) = child_stdin.write_all(s.as_bytes()) {
+                // The user probably quit the pager before reading all of the
+                // output, so discard the rest of it.
+                warn!(?err, "Could not write to pager");
+                *stdin = None;
+            }
+        }
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        drop(self.stdin.lock().unwrap().take());
+        if let Err(err) = self.child.lock().unwrap().wait() {
+            warn!(?err, "Could not wait for pager");
+        }
+    }
 }
 
 /// An index into the recursive hierarchy of progress bars. For example, the key
@@ -345,23 +419,61 @@ impl OperationState {
         // Both `set_message` and `set_prefix` implicitly call
         // `ProgressBar::tick` and force a redraw.
         self.progress_bar.set_prefix("  ".repeat(nesting_level));
-        self.progress_bar.set_message(format!(
-            "{} ({:.1}s)",
-            self.operation_type.to_string(),
-            elapsed_duration.as_secs_f64(),
-        ));
+        let remaining_duration = match (self.start_times.as_slice(), self.has_meter) {
+            ([], _) | (_, false) => None,
+            ([..], true) => estimate_remaining_duration(
+                elapsed_duration,
+                self.progress_bar.position(),
+                self.progress_bar.length().unwrap_or_default(),
+            ),
+        };
+        self.progress_bar.set_message(match remaining_duration {
+            None => format!(
+                "{} ({:.1}s)",
+                self.operation_type.to_string(),
+                elapsed_duration.as_secs_f64(),
+            ),
+            Some(remaining_duration) => format!(
+                "{} ({:.1}s, about {:.0}s left)",
+                self.operation_type.to_string(),
+                elapsed_duration.as_secs_f64(),
+                remaining_duration.as_secs_f64().ceil(),
+            ),
+        });
     }
 }
 
+/// Estimate how much longer an operation will take, given that it took
+/// `elapsed_duration` to complete `current` out of `total` units of work.
+/// Assumes that the remaining units of work take as long on average as the
+/// completed ones.
+///
+/// Returns `None` if no work has been completed yet, since there's no basis
+/// for an estimate, or if all of the work has been completed.
+fn estimate_remaining_duration(
+    elapsed_duration: Duration,
+    current: u64,
+    total: u64,
+) -> Option<Duration> {
+    if current == 0 || current >= total {
+        return None;
+    }
+    let current = u32::try_from(current).ok()?;
+    let remaining = u32::try_from(total).ok()? - current;
+    Some(elapsed_duration / current * remaining)
+}
+
 /// Wrapper around side-effectful operations, such as output and progress
 /// indicators.
 #[derive(Clone)]
 pub struct Effects {
     glyphs: Glyphs,
+    messages: Messages,
     dest: OutputDest,
     updater_thread_handle: Arc<RwLock<UpdaterThreadHandle>>,
     operation_key: Vec<OperationType>,
     root_operation: Arc<Mutex<RootOperation>>,
+    is_interrupted: Arc<AtomicBool>,
 }
 
 impl std::fmt::Debug for Effects {
@@ -433,10 +545,12 @@ impl Effects {
         let updater_thread_handle = spawn_progress_updater_thread(&root_operation);
         Effects {
             glyphs,
+            messages: Messages::detect(),
             dest: OutputDest::Stdout,
             updater_thread_handle,
             operation_key: Default::default(),
             root_operation,
+            is_interrupted: Default::default(),
         }
     }
 
@@ -444,10 +558,12 @@ impl Effects {
     pub fn new_suppress_for_test(glyphs: Glyphs) -> Self {
         Effects {
             glyphs,
+            messages: Default::default(),
             dest: OutputDest::Suppress,
             updater_thread_handle: Default::default(),
             operation_key: Default::default(),
             root_operation: Default::default(),
+            is_interrupted: Default::default(),
         }
     }
 
@@ -456,16 +572,31 @@ impl Effects {
         glyphs: Glyphs,
         stdout: &Arc<Mutex<Vec<u8>>>,
         stderr: &Arc<Mutex<Vec<u8>>>,
+    ) -> Self {
+        Self {
+            messages: Default::default(),
+            ..Self::new_from_buffer(glyphs, stdout, stderr)
+        }
+    }
+
+    /// Constructor. Writes to the provided buffers instead of stdout and
+    /// stderr, and doesn't display progress.
+    pub fn new_from_buffer(
+        glyphs: Glyphs,
+        stdout: &Arc<Mutex<Vec<u8>>>,
+        stderr: &Arc<Mutex<Vec<u8>>>,
     ) -> Self {
         Effects {
             glyphs,
-            dest: OutputDest::BufferForTest {
+            messages: Messages::detect(),
+            dest: OutputDest::Buffer {
                 stdout: Arc::clone(stdout),
                 stderr: Arc::clone(stderr),
             },
             updater_thread_handle: Default::default(),
             operation_key: Default::default(),
             root_operation: Default::default(),
+            is_interrupted: Default::default(),
         }
     }
 
@@ -480,6 +611,93 @@ impl Effects {
         }
     }
 
+    /// Write regular output to stderr instead of stdout, such as when stdout is
+    /// reserved for machine-readable output. Output written to the returned
+    /// `Effects` can't be captured by writing to the original `Effects`.
+    pub fn reroute_output_to_stderr(&self) -> Self {
+        let dest = match &self.dest {
+            OutputDest::Stdout | OutputDest::Stderr | OutputDest::Pager(_) => OutputDest::Stderr,
+            OutputDest::Suppress => OutputDest::Suppress,
+            OutputDest::Buffer { stdout: _, stderr } => OutputDest::Buffer {
+                stdout: Arc::clone(stderr),
+                stderr: Arc::clone(stderr),
+            },
+        };
+        Self {
+            dest,
+            ..self.clone()
+        }
+    }
+
+    /// Whether regular output has been rerouted to stderr with
+    /// [`Effects::reroute_output_to_stderr`].
+    pub fn is_output_rerouted_to_stderr(&self) -> bool {
+        match &self.dest {
+            OutputDest::Stderr => true,
+            OutputDest::Buffer { stdout, stderr } => Arc::ptr_eq(stdout, stderr),
+            OutputDest::Stdout | OutputDest::Suppress | OutputDest::Pager(_) => false,
+        }
+    }
+
+    /// Pipe regular output through the given pager command, which is run with
+    /// the shell. Has no effect unless output is being written to stdout.
+    ///
+    /// The pager is waited on once the returned `Effects` and all of its
+    /// clones have been dropped.
+    pub fn enable_pager(&self, pager: &str) -> eyre::Result<Self> {
+        match self.dest {
+            OutputDest::Stdout => {}
+            OutputDest::Stderr
+            | OutputDest::Suppress
+            | OutputDest::Buffer { .. }
+            | OutputDest::Pager(_) => return Ok(self.clone()),
+        }
+
+        let pager = Pager::spawn(pager)?;
+        Ok(Self {
+            dest: OutputDest::Pager(Arc::new(pager)),
+            ..self.clone()
+        })
+    }
+
+    /// Defer interrupts (such as from pressing Ctrl-C) until the returned
+    /// guard is dropped, so that the current operation can stop at a point
+    /// where the repository is in a consistent state. Check
+    /// [`Effects::is_interrupted`] to find out whether an interrupt was
+    /// received.
+    ///
+    /// If a second interrupt is received, then the process exits immediately,
+    /// in case the operation has hung.
+    pub fn catch_interrupts(&self) -> eyre::Result<InterruptGuard> {
+        install_default_interrupt_handlers()?;
+
+        let mut sig_ids = Vec::new();
+        for signal in INTERRUPT_SIGNALS {
+            // The conditional shutdown must be registered first, so that it
+            // only sees the flag as set if an earlier signal set it.
+            sig_ids.push(signal_hook::flag::register_conditional_shutdown(
+                *signal,
+                INTERRUPTED_EXIT_CODE,
+                Arc::clone(&self.is_interrupted),
+            )?);
+            sig_ids.push(signal_hook::flag::register(
+                *signal,
+                Arc::clone(&self.is_interrupted),
+            )?);
+        }
+
+        let mut num_interrupt_guards = NUM_INTERRUPT_GUARDS.lock().unwrap();
+        *num_interrupt_guards += 1;
+        USE_DEFAULT_INTERRUPT_HANDLING.store(false, Ordering::SeqCst);
+        Ok(InterruptGuard { sig_ids })
+    }
+
+    /// Whether an interrupt was received while interrupts were being caught
+    /// with [`Effects::catch_interrupts`].
+    pub fn is_interrupted(&self) -> bool {
+        self.is_interrupted.load(Ordering::SeqCst)
+    }
+
     /// Start reporting progress for the specified operation type.
     ///
     /// A progress spinner is shown until the returned `ProgressHandle` is
@@ -510,8 +728,8 @@ impl Effects {
             operation_key: operation_key.clone(),
         };
         match self.dest {
-            OutputDest::Stdout => {}
-            OutputDest::Suppress | OutputDest::BufferForTest { .. } => {
+            OutputDest::Stdout | OutputDest::Stderr => {}
+            OutputDest::Suppress | OutputDest::Buffer { .. } | OutputDest::Pager(_) => {
                 return (self.clone(), progress)
             }
         }
@@ -531,8 +749,8 @@ impl Effects {
 
     fn on_notify_progress(&self, operation_key: &OperationKey, current: usize, total: usize) {
         match self.dest {
-            OutputDest::Stdout => {}
-            OutputDest::Suppress | OutputDest::BufferForTest { .. } => return,
+            OutputDest::Stdout | OutputDest::Stderr => {}
+            OutputDest::Suppress | OutputDest::Buffer { .. } | OutputDest::Pager(_) => return,
         }
 
         let mut root_operation = self.root_operation.lock().unwrap();
@@ -542,8 +760,8 @@ impl Effects {
 
     fn on_notify_progress_inc(&self, operation_key: &OperationKey, increment: usize) {
         match self.dest {
-            OutputDest::Stdout => {}
-            OutputDest::Suppress | OutputDest::BufferForTest { .. } => return,
+            OutputDest::Stdout | OutputDest::Stderr => {}
+            OutputDest::Suppress | OutputDest::Buffer { .. } | OutputDest::Pager(_) => return,
         }
 
         let mut root_operation = self.root_operation.lock().unwrap();
@@ -557,8 +775,8 @@ impl Effects {
 
     fn on_set_message(&self, operation_key: &OperationKey, message: String) {
         match self.dest {
-            OutputDest::Stdout => {}
-            OutputDest::Suppress | OutputDest::BufferForTest { .. } => return,
+            OutputDest::Stdout | OutputDest::Stderr => {}
+            OutputDest::Suppress | OutputDest::Buffer { .. } | OutputDest::Pager(_) => return,
         }
 
         let mut root_operation = self.root_operation.lock().unwrap();
@@ -572,8 +790,8 @@ impl Effects {
 
     fn on_drop_progress_handle(&self, operation_key: &OperationKey) {
         match self.dest {
-            OutputDest::Stdout => {}
-            OutputDest::Suppress | OutputDest::BufferForTest { .. } => return,
+            OutputDest::Stdout | OutputDest::Stderr => {}
+            OutputDest::Suppress | OutputDest::Buffer { .. } | OutputDest::Pager(_) => return,
         }
 
         let now = Instant::now();
@@ -619,6 +837,31 @@ impl Effects {
         &self.glyphs
     }
 
+    /// Get the styles to render output with. See [`Glyphs::theme`].
+    pub fn get_theme(&self) -> &Theme {
+        &self.glyphs.theme
+    }
+
+    /// Get the message catalogs to render user-facing output with.
+    pub fn get_messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    /// Use the given message catalogs to render user-facing output, such as
+    /// to test a translation.
+    pub fn with_messages(&self, messages: Messages) -> Self {
+        Self {
+            messages,
+            ..self.clone()
+        }
+    }
+
+    /// Render the message with the given ID and arguments. See
+    /// [`Messages::format`].
+    pub fn tr(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        self.messages.format(id, args)
+    }
+
     /// Create a stream that can be written to. The output might go to stdout or
     /// be rendered specially in the terminal.
     pub fn get_output_stream(&self) -> OutputStream {
@@ -782,14 +1025,28 @@ impl Write for OutputStream {
                 self.flush();
             }
 
+            OutputDest::Stderr => {
+                let mut error_stream = ErrorStream {
+                    dest: OutputDest::Stderr,
+                    buffer: take(&mut self.buffer),
+                    updater_thread_handle: Arc::clone(&self.updater_thread_handle),
+                    root_operation: Arc::clone(&self.root_operation),
+                };
+                error_stream.buffer.push_str(s);
+                error_stream.flush();
+                self.buffer = take(&mut error_stream.buffer);
+            }
+
             OutputDest::Suppress => {
                 // Do nothing.
             }
 
-            OutputDest::BufferForTest { stdout, stderr: _ } => {
+            OutputDest::Buffer { stdout, stderr: _ } => {
                 let mut buffer = stdout.lock().unwrap();
                 write!(buffer, "{}", s).unwrap();
             }
+
+            OutputDest::Pager(pager) => pager.write(s),
         }
         Ok(())
     }
@@ -797,7 +1054,21 @@ impl Write for OutputStream {
 
 impl Drop for OutputStream {
     fn drop(&mut self) {
-        WriteProgress::drop(self)
+        match self.dest {
+            OutputDest::Stderr => {
+                // Let the `ErrorStream` handle any incompletely-written lines.
+                drop(ErrorStream {
+                    dest: OutputDest::Stderr,
+                    buffer: take(&mut self.buffer),
+                    updater_thread_handle: Arc::clone(&self.updater_thread_handle),
+                    root_operation: Arc::clone(&self.root_operation),
+                });
+            }
+            OutputDest::Stdout
+            | OutputDest::Suppress
+            | OutputDest::Buffer { .. }
+            | OutputDest::Pager(_) => WriteProgress::drop(self),
+        }
     }
 }
 
@@ -837,7 +1108,7 @@ impl WriteProgress for ErrorStream {
 impl Write for ErrorStream {
     fn write_str(&mut self, s: &str) -> std::fmt::Result {
         match &self.dest {
-            OutputDest::Stdout => {
+            OutputDest::Stdout | OutputDest::Stderr | OutputDest::Pager(_) => {
                 self.buffer.push_str(s);
                 self.flush();
             }
@@ -846,7 +1117,7 @@ impl Write for ErrorStream {
                 // Do nothing.
             }
 
-            OutputDest::BufferForTest { stdout: _, stderr } => {
+            OutputDest::Buffer { stdout: _, stderr } => {
                 let mut buffer = stderr.lock().unwrap();
                 write!(buffer, "{}", s).unwrap();
             }
@@ -861,6 +1132,60 @@ impl Drop for ErrorStream {
     }
 }
 
+/// The exit code for a process which was terminated by an interrupt, by the
+/// usual convention of 128 plus the signal number of `SIGINT`.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// The signals which are deferred by [`Effects::catch_interrupts`].
+const INTERRUPT_SIGNALS: &[i32] = &[signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM];
+
+lazy_static! {
+    /// The number of live [`InterruptGuard`]s.
+    static ref NUM_INTERRUPT_GUARDS: Mutex<usize> = Default::default();
+
+    /// Set when there are no live [`InterruptGuard`]s, in which case
+    /// interrupts should terminate the process as usual.
+    static ref USE_DEFAULT_INTERRUPT_HANDLING: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
+}
+
+/// Once a signal handler has been installed, it can't be uninstalled, so
+/// install a handler which emulates the default behavior of the interrupt
+/// signals when there are no live [`InterruptGuard`]s.
+fn install_default_interrupt_handlers() -> eyre::Result<()> {
+    static INSTALLED: OnceCell<()> = OnceCell::new();
+    INSTALLED.get_or_try_init(|| -> eyre::Result<()> {
+        for signal in INTERRUPT_SIGNALS {
+            signal_hook::flag::register_conditional_default(
+                *signal,
+                Arc::clone(&USE_DEFAULT_INTERRUPT_HANDLING),
+            )?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Restores the default handling of interrupts when dropped. Returned by
+/// [`Effects::catch_interrupts`].
+#[derive(Debug)]
+pub struct InterruptGuard {
+    sig_ids: Vec<SigId>,
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        for sig_id in self.sig_ids.drain(..) {
+            signal_hook::low_level::unregister(sig_id);
+        }
+
+        let mut num_interrupt_guards = NUM_INTERRUPT_GUARDS.lock().unwrap();
+        *num_interrupt_guards -= 1;
+        if *num_interrupt_guards == 0 {
+            USE_DEFAULT_INTERRUPT_HANDLING.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
 /// A handle to an operation in progress. This object should be kept live while
 /// the operation is underway, and a timing entry for it will be displayed in
 /// the interactive progress display.
@@ -966,6 +1291,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_estimate_remaining_duration() {
+        let elapsed_duration = Duration::from_secs(10);
+        assert_eq!(estimate_remaining_duration(elapsed_duration, 0, 10), None);
+        assert_eq!(
+            estimate_remaining_duration(elapsed_duration, 2, 10),
+            Some(Duration::from_secs(40))
+        );
+        assert_eq!(
+            estimate_remaining_duration(elapsed_duration, 5, 10),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(estimate_remaining_duration(elapsed_duration, 10, 10), None);
+        assert_eq!(estimate_remaining_duration(elapsed_duration, 12, 10), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_effects_catch_interrupts() -> eyre::Result<()> {
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let (effects, _progress) = effects.start_operation(OperationType::RebaseCommits);
+        assert!(!effects.is_interrupted());
+        {
+            let _guard = effects.catch_interrupts()?;
+            signal_hook::low_level::raise(signal_hook::consts::SIGINT)?;
+        }
+        assert!(effects.is_interrupted());
+        assert!(USE_DEFAULT_INTERRUPT_HANDLING.load(Ordering::SeqCst));
+        Ok(())
+    }
+
     /// Test for the issue fixed by <https://github.com/console-rs/indicatif/pull/403>.
     #[test]
     fn test_effects_progress_rewind_panic() -> eyre::Result<()> {
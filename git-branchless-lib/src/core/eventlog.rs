@@ -13,10 +13,13 @@ use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
 use eyre::Context;
-use tracing::{error, instrument};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tracing::{error, instrument, warn};
 
+use crate::core::config::get_ref_patterns_to_ignore;
 use crate::core::effects::{Effects, OperationType};
-use crate::core::repo_ext::RepoExt;
+use crate::core::repo_ext::{get_public_remote_branch_globs, is_public_remote_branch, RepoExt};
 use crate::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
 
 use super::repo_ext::RepoReferencesSnapshot;
@@ -25,6 +28,34 @@ use super::repo_ext::RepoReferencesSnapshot;
 /// which the caller has already started.
 pub const BRANCHLESS_TRANSACTION_ID_ENV_VAR: &str = "BRANCHLESS_TRANSACTION_ID";
 
+/// When this environment variable is set, new transactions record it as the
+/// program which caused them, such as `git branchless move`.
+pub const BRANCHLESS_PROVENANCE_ENV_VAR: &str = "BRANCHLESS_PROVENANCE";
+
+/// Describe the program which caused the current process to start a
+/// transaction, if known.
+///
+/// Git sets `GIT_REFLOG_ACTION` for some commands, like `git pull` and `git
+/// rebase`, including for the hooks which they run. Other Git commands (such
+/// as `git commit`) don't tell their hooks who they are, so their transactions
+/// have no provenance unless they were run via `git branchless wrap`.
+fn get_provenance_from_env() -> Option<String> {
+    if let Ok(provenance) = std::env::var(BRANCHLESS_PROVENANCE_ENV_VAR) {
+        if !provenance.is_empty() {
+            return Some(provenance);
+        }
+    }
+    if let Ok(reflog_action) = std::env::var("GIT_REFLOG_ACTION") {
+        if let Some(command) = reflog_action.split_whitespace().next() {
+            // Builtins implemented by shell scripts (e.g. `git bisect`) run
+            // their `--helper` counterpart, which is not what the user typed.
+            let command = command.trim_end_matches("--helper");
+            return Some(format!("git {}", command));
+        }
+    }
+    None
+}
+
 // Wrapper around the row stored directly in the database.
 #[derive(Clone, Debug)]
 struct Row {
@@ -53,9 +84,17 @@ struct Row {
 ///
 /// Unlike in a database, there is no specific guarantee that an event
 /// transaction is an atomic unit of work.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct EventTransactionId(isize);
 
+impl EventTransactionId {
+    /// The transaction ID for events which are only replayed in memory to
+    /// preview the result of an operation, such as for a dry run, and which
+    /// are never added to the event log. No transaction in the event log has
+    /// this ID.
+    pub const DRY_RUN: EventTransactionId = EventTransactionId(0);
+}
+
 impl ToString for EventTransactionId {
     fn to_string(&self) -> String {
         let EventTransactionId(event_id) = self;
@@ -73,7 +112,7 @@ impl FromStr for EventTransactionId {
 }
 
 /// An event that occurred to one of the commits in the repository.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Event {
     /// Indicates that the commit was rewritten.
     ///
@@ -464,16 +503,121 @@ CREATE TABLE IF NOT EXISTS event_transactions (
     -- later?)
     event_tx_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
 
-    message TEXT
+    message TEXT,
+
+    -- The program which started the transaction, such as `git pull`, if known.
+    provenance TEXT
 )
 ",
         rusqlite::params![],
     )
     .wrap_err("Creating `event_transactions` table")?;
 
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_replayer_snapshots (
+    -- The `rowid` of the last row of `event_log` which was processed to
+    -- produce this snapshot.
+    last_event_rowid INTEGER NOT NULL,
+
+    -- The number of rows of `event_log` which were processed, used to detect
+    -- if rows have been deleted since the snapshot was taken.
+    num_event_rows INTEGER NOT NULL,
+
+    -- Identifies the replayer format and settings used to produce this
+    -- snapshot. Snapshots with a different fingerprint are ignored.
+    fingerprint TEXT NOT NULL,
+
+    state BLOB NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_replayer_snapshots` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS merge_conflicts (
+    timestamp REAL NOT NULL,
+    event_tx_id INTEGER NOT NULL,
+
+    -- The commit which couldn't be applied without conflicts.
+    commit_oid TEXT NOT NULL,
+
+    num_conflicting_paths INTEGER NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `merge_conflicts` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS suspended_operation (
+    -- The name of the command which was suspended, such as `sync`.
+    command_name TEXT NOT NULL,
+
+    -- The first transaction of the suspended operation. Aborting the
+    -- operation undoes all events since the beginning of this transaction.
+    event_tx_id INTEGER NOT NULL,
+
+    -- A JSON array of the arguments to `git branchless` which will finish the
+    -- operation, or an empty array if no further work is needed.
+    resume_args TEXT NOT NULL,
+
+    -- Whether an on-disk rebase was in progress when the operation was
+    -- suspended.
+    is_rebase_in_progress INTEGER NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `suspended_operation` table")?;
+
     Ok(())
 }
 
+/// A merge conflict which was encountered while rewriting commits, as recorded
+/// by [`EventLogDb::add_merge_conflict`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeConflictRecord {
+    /// The time at which the merge conflict was encountered.
+    pub timestamp: SystemTime,
+
+    /// The transaction of the rewrite operation which encountered the merge
+    /// conflict.
+    pub event_tx_id: EventTransactionId,
+
+    /// The commit which couldn't be applied without conflicts.
+    pub commit_oid: NonZeroOid,
+
+    /// The number of paths which had conflicts.
+    pub num_conflicting_paths: usize,
+}
+
+/// An operation which stopped partway through, such as due to a merge conflict
+/// or an interruption, and which can be resumed with `git branchless continue`
+/// or rolled back with `git branchless abort`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SuspendedOperation {
+    /// The name of the command which was suspended, such as `sync`.
+    pub command_name: String,
+
+    /// The first transaction of the suspended operation.
+    pub event_tx_id: EventTransactionId,
+
+    /// The arguments to `git branchless` which will finish the operation, after
+    /// any in-progress rebase has been continued. Empty if there is no further
+    /// work to do.
+    pub resume_args: Vec<String>,
+
+    /// Whether an on-disk rebase was in progress when the operation was
+    /// suspended. If so, and that rebase is no longer in progress, then the
+    /// rebase was finished or aborted outside of `git branchless continue` or
+    /// `git branchless abort`, and the suspended operation is stale.
+    pub is_rebase_in_progress: bool,
+}
+
 impl<'conn> EventLogDb<'conn> {
     /// Constructor.
     #[instrument]
@@ -536,17 +680,54 @@ INSERT INTO event_log VALUES (
     ///
     /// Returns: All the events in the database, ordered from oldest to newest.
     #[instrument]
-
     pub fn get_events(&self) -> eyre::Result<Vec<Event>> {
+        let events = self
+            .get_events_after_rowid(0)?
+            .into_iter()
+            .map(|(_rowid, event)| event)
+            .collect();
+        Ok(events)
+    }
+
+    /// Get the number of events in the database.
+    #[instrument]
+    pub fn count_events(&self) -> eyre::Result<usize> {
+        let num_events: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM event_log",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        Ok(num_events.try_into()?)
+    }
+
+    /// Get the `rowid` of the most recent event in the database, or `0` if
+    /// there are no events.
+    #[instrument]
+    pub fn get_last_event_rowid(&self) -> eyre::Result<i64> {
+        let rowid: Option<i64> = self.conn.query_row(
+            "SELECT MAX(rowid) FROM event_log",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        Ok(rowid.unwrap_or(0))
+    }
+
+    /// Get the events in the database which were added after the row with the
+    /// given `rowid`, along with their own `rowid`s, ordered from oldest to
+    /// newest.
+    #[instrument]
+    pub fn get_events_after_rowid(&self, rowid: i64) -> eyre::Result<Vec<(i64, Event)>> {
         let mut stmt = self.conn.prepare(
             "
-SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+SELECT rowid, timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
 FROM event_log
+WHERE rowid > :rowid
 ORDER BY rowid ASC
 ",
         )?;
-        let rows: rusqlite::Result<Vec<Row>> = stmt
-            .query_map(rusqlite::params![], |row| {
+        let rows: rusqlite::Result<Vec<(i64, Row)>> = stmt
+            .query_map(rusqlite::named_params! {":rowid": rowid}, |row| {
+                let rowid: i64 = row.get("rowid")?;
                 let timestamp: f64 = row.get("timestamp")?;
                 let event_tx_id: isize = row.get("event_tx_id")?;
                 let type_: String = row.get("type")?;
@@ -555,19 +736,256 @@ ORDER BY rowid ASC
                 let new_ref: Option<String> = row.get("new_ref")?;
                 let message: Option<String> = row.get("message")?;
 
-                Ok(Row {
-                    timestamp,
-                    event_tx_id,
-                    type_,
-                    ref_name: ref_name.map(ReferenceName::from),
-                    ref1: old_ref.map(ReferenceName::from),
-                    ref2: new_ref.map(ReferenceName::from),
-                    message: message.map(ReferenceName::from),
-                })
+                Ok((
+                    rowid,
+                    Row {
+                        timestamp,
+                        event_tx_id,
+                        type_,
+                        ref_name: ref_name.map(ReferenceName::from),
+                        ref1: old_ref.map(ReferenceName::from),
+                        ref2: new_ref.map(ReferenceName::from),
+                        message: message.map(ReferenceName::from),
+                    },
+                ))
             })?
             .collect();
         let rows = rows?;
-        rows.into_iter().map(Event::try_from).collect()
+        rows.into_iter()
+            .map(|(rowid, row)| Ok((rowid, Event::try_from(row)?)))
+            .collect()
+    }
+
+    /// Record that a merge conflict was encountered while rewriting commits.
+    /// These records are only used to report statistics, and don't affect the
+    /// state of the repository as replayed from the event log.
+    #[instrument]
+    pub fn add_merge_conflict(
+        &self,
+        now: SystemTime,
+        event_tx_id: EventTransactionId,
+        commit_oid: NonZeroOid,
+        num_conflicting_paths: usize,
+    ) -> eyre::Result<()> {
+        let timestamp = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .wrap_err("Calculating merge conflict timestamp")?
+            .as_secs_f64();
+        let EventTransactionId(event_tx_id) = event_tx_id;
+        let num_conflicting_paths: i64 = num_conflicting_paths.try_into()?;
+        self.conn
+            .execute(
+                "
+INSERT INTO merge_conflicts
+(timestamp, event_tx_id, commit_oid, num_conflicting_paths)
+VALUES
+(:timestamp, :event_tx_id, :commit_oid, :num_conflicting_paths)
+",
+                rusqlite::named_params! {
+                    ":timestamp": timestamp,
+                    ":event_tx_id": event_tx_id,
+                    ":commit_oid": commit_oid.to_string(),
+                    ":num_conflicting_paths": num_conflicting_paths,
+                },
+            )
+            .wrap_err("Recording merge conflict")?;
+        Ok(())
+    }
+
+    /// Get all recorded merge conflicts, in the order in which they were
+    /// encountered.
+    #[instrument]
+    pub fn get_merge_conflicts(&self) -> eyre::Result<Vec<MergeConflictRecord>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT timestamp, event_tx_id, commit_oid, num_conflicting_paths
+FROM merge_conflicts
+ORDER BY rowid
+",
+        )?;
+        let rows = stmt.query_map(rusqlite::params![], |row| {
+            let timestamp: f64 = row.get("timestamp")?;
+            let event_tx_id: isize = row.get("event_tx_id")?;
+            let commit_oid: String = row.get("commit_oid")?;
+            let num_conflicting_paths: i64 = row.get("num_conflicting_paths")?;
+            Ok((timestamp, event_tx_id, commit_oid, num_conflicting_paths))
+        })?;
+        let mut result = Vec::new();
+        for row in rows {
+            let (timestamp, event_tx_id, commit_oid, num_conflicting_paths) = row?;
+            result.push(MergeConflictRecord {
+                timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs_f64(timestamp),
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid: commit_oid.parse()?,
+                num_conflicting_paths: num_conflicting_paths.try_into()?,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Delete the records of merge conflicts for commits which are no longer
+    /// in the repository, such as after Git has garbage-collected them.
+    /// Returns the number of records deleted.
+    #[instrument]
+    pub fn prune_merge_conflicts(&self, repo: &Repo) -> eyre::Result<usize> {
+        let mut num_deleted = 0;
+        for commit_oid in self
+            .get_merge_conflicts()?
+            .into_iter()
+            .map(|record| record.commit_oid)
+            .unique()
+        {
+            if !repo.contains_object(commit_oid)? {
+                num_deleted += self
+                    .conn
+                    .execute(
+                        "DELETE FROM merge_conflicts WHERE commit_oid = :commit_oid",
+                        rusqlite::named_params! {
+                            ":commit_oid": commit_oid.to_string(),
+                        },
+                    )
+                    .wrap_err("Pruning merge conflicts")?;
+            }
+        }
+        Ok(num_deleted)
+    }
+
+    /// Get the operation which was most recently suspended, if any.
+    #[instrument]
+    pub fn get_suspended_operation(&self) -> eyre::Result<Option<SuspendedOperation>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT command_name, event_tx_id, resume_args, is_rebase_in_progress
+            FROM suspended_operation
+            LIMIT 1",
+        )?;
+        let mut rows = stmt.query(rusqlite::params![])?;
+        match rows.next()? {
+            Some(row) => {
+                let command_name: String = row.get("command_name")?;
+                let event_tx_id: isize = row.get("event_tx_id")?;
+                let resume_args: String = row.get("resume_args")?;
+                let is_rebase_in_progress: bool = row.get("is_rebase_in_progress")?;
+                Ok(Some(SuspendedOperation {
+                    command_name,
+                    event_tx_id: EventTransactionId(event_tx_id),
+                    resume_args: serde_json::from_str(&resume_args)
+                        .wrap_err("Deserializing suspended operation arguments")?,
+                    is_rebase_in_progress,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Record that the given operation was suspended, replacing any previously
+    /// suspended operation.
+    #[instrument]
+    pub fn set_suspended_operation(&self, operation: &SuspendedOperation) -> eyre::Result<()> {
+        let SuspendedOperation {
+            command_name,
+            event_tx_id: EventTransactionId(event_tx_id),
+            resume_args,
+            is_rebase_in_progress,
+        } = operation;
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM suspended_operation", rusqlite::params![])?;
+        tx.execute(
+            "
+INSERT INTO suspended_operation
+(command_name, event_tx_id, resume_args, is_rebase_in_progress)
+VALUES
+(:command_name, :event_tx_id, :resume_args, :is_rebase_in_progress)
+",
+            rusqlite::named_params! {
+                ":command_name": command_name,
+                ":event_tx_id": event_tx_id,
+                ":resume_args": serde_json::to_string(resume_args)?,
+                ":is_rebase_in_progress": is_rebase_in_progress,
+            },
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Forget about the suspended operation, if any.
+    #[instrument]
+    pub fn clear_suspended_operation(&self) -> eyre::Result<()> {
+        self.conn
+            .execute("DELETE FROM suspended_operation", rusqlite::params![])
+            .wrap_err("Clearing suspended operation")?;
+        Ok(())
+    }
+
+    /// Load the most recent replayer snapshot with the given fingerprint.
+    ///
+    /// Returns `None` if there is no such snapshot, or if rows have been
+    /// removed from the event log since the snapshot was taken. Otherwise,
+    /// returns the `rowid` of the last event included in the snapshot and the
+    /// serialized replayer state.
+    #[instrument]
+    fn load_replayer_snapshot(&self, fingerprint: &str) -> eyre::Result<Option<(i64, Vec<u8>)>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT last_event_rowid, num_event_rows, state
+FROM event_replayer_snapshots
+WHERE fingerprint = :fingerprint
+ORDER BY last_event_rowid DESC
+LIMIT 1
+",
+        )?;
+        let mut rows = stmt.query(rusqlite::named_params! {":fingerprint": fingerprint})?;
+        let (last_event_rowid, num_event_rows, state) = match rows.next()? {
+            Some(row) => {
+                let last_event_rowid: i64 = row.get("last_event_rowid")?;
+                let num_event_rows: i64 = row.get("num_event_rows")?;
+                let state: Vec<u8> = row.get("state")?;
+                (last_event_rowid, num_event_rows, state)
+            }
+            None => return Ok(None),
+        };
+
+        let actual_num_event_rows: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM event_log WHERE rowid <= :rowid",
+            rusqlite::named_params! {":rowid": last_event_rowid},
+            |row| row.get(0),
+        )?;
+        if actual_num_event_rows != num_event_rows {
+            return Ok(None);
+        }
+        Ok(Some((last_event_rowid, state)))
+    }
+
+    /// Save a replayer snapshot, replacing any existing snapshots.
+    #[instrument(skip(state))]
+    fn save_replayer_snapshot(
+        &self,
+        last_event_rowid: i64,
+        fingerprint: &str,
+        state: &[u8],
+    ) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let num_event_rows: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM event_log WHERE rowid <= :rowid",
+            rusqlite::named_params! {":rowid": last_event_rowid},
+            |row| row.get(0),
+        )?;
+        tx.execute("DELETE FROM event_replayer_snapshots", rusqlite::params![])?;
+        tx.execute(
+            "
+INSERT INTO event_replayer_snapshots
+(last_event_rowid, num_event_rows, fingerprint, state)
+VALUES
+(:last_event_rowid, :num_event_rows, :fingerprint, :state)
+",
+            rusqlite::named_params! {
+                ":last_event_rowid": last_event_rowid,
+                ":num_event_rows": num_event_rows,
+                ":fingerprint": fingerprint,
+                ":state": state,
+            },
+        )?;
+        tx.commit()?;
+        Ok(())
     }
 
     #[instrument]
@@ -592,13 +1010,14 @@ ORDER BY rowid ASC
             .execute(
                 "
             INSERT INTO event_transactions
-            (timestamp, message)
+            (timestamp, message, provenance)
             VALUES
-            (:timestamp, :message)
+            (:timestamp, :message, :provenance)
         ",
                 rusqlite::named_params! {
                     ":timestamp": timestamp,
                     ":message": message,
+                    ":provenance": get_provenance_from_env(),
                 },
             )
             .wrap_err("Creating event transaction")?;
@@ -640,6 +1059,48 @@ WHERE event_tx_id = :event_tx_id
         )?;
         Ok(result)
     }
+
+    /// Get the program which started the given transaction, such as
+    /// `git pull` or `git branchless move`, if it was recorded.
+    pub fn get_transaction_provenance(
+        &self,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<Option<String>> {
+        let EventTransactionId(event_tx_id) = event_tx_id;
+        let mut stmt = self.conn.prepare(
+            "
+SELECT provenance
+FROM event_transactions
+WHERE event_tx_id = :event_tx_id
+",
+        )?;
+        let result: Option<String> = stmt.query_row(
+            rusqlite::named_params![":event_tx_id": event_tx_id,],
+            |row| row.get("provenance"),
+        )?;
+        Ok(result)
+    }
+
+    /// Get a human-readable description of what started the given
+    /// transaction, such as `git branchless sync`. The provenance is used if
+    /// it was recorded. Otherwise, the description is derived from the
+    /// transaction's message.
+    pub fn get_transaction_description(
+        &self,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<String> {
+        if let Some(provenance) = self.get_transaction_provenance(event_tx_id)? {
+            return Ok(provenance);
+        }
+        let message = self.get_transaction_message(event_tx_id)?;
+        let description = match message.as_str() {
+            "hook-post-rewrite" => "git commit --amend or git rebase".to_string(),
+            "hook-post-checkout" => "git checkout".to_string(),
+            "reference-transaction" => "a reference update".to_string(),
+            message => format!("git branchless {}", message),
+        };
+        Ok(description)
+    }
 }
 
 /// Determine whether a given reference is used to keep a commit alive.
@@ -665,7 +1126,70 @@ pub fn should_ignore_ref_updates(reference_name: &ReferenceName) -> bool {
     )
 }
 
-#[derive(Debug)]
+/// Decides which reference updates are recorded in and replayed from the event
+/// log.
+///
+/// In addition to the references which are always ignored (see
+/// `should_ignore_ref_updates`), the user can configure glob patterns via
+/// `branchless.core.refPatternsToIgnore` to drop updates from noisy reference
+/// namespaces, such as `refs/notes/*` or `refs/pull/*`. A `*` in the pattern
+/// may match across `/` characters.
+#[derive(Clone, Debug, Default)]
+pub struct RefUpdateFilter {
+    patterns_to_ignore: Vec<glob::Pattern>,
+}
+
+impl RefUpdateFilter {
+    /// Constructor. Patterns which fail to parse are skipped with a warning,
+    /// since a typo in the configuration shouldn't break the hooks.
+    pub fn new(patterns_to_ignore: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        let patterns_to_ignore = patterns_to_ignore
+            .into_iter()
+            .filter_map(|pattern| {
+                let pattern = pattern.as_ref();
+                match glob::Pattern::new(pattern) {
+                    Ok(pattern) => Some(pattern),
+                    Err(err) => {
+                        warn!(
+                            ?pattern,
+                            ?err,
+                            "Could not parse reference pattern to ignore"
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self { patterns_to_ignore }
+    }
+
+    /// Construct the filter from the repository's configuration.
+    #[instrument]
+    pub fn from_repo(repo: &Repo) -> eyre::Result<Self> {
+        let patterns_to_ignore = get_ref_patterns_to_ignore(repo)?;
+        Ok(Self::new(patterns_to_ignore))
+    }
+
+    /// A string which changes whenever the set of ignored references changes.
+    fn fingerprint(&self) -> String {
+        self.patterns_to_ignore
+            .iter()
+            .map(|pattern| pattern.as_str())
+            .join("\n")
+    }
+
+    /// Determines whether or not updates to the given reference should be
+    /// ignored.
+    pub fn should_ignore(&self, reference_name: &ReferenceName) -> bool {
+        should_ignore_ref_updates(reference_name)
+            || self
+                .patterns_to_ignore
+                .iter()
+                .any(|pattern| pattern.matches(reference_name.as_str()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 enum EventClassification {
     Show,
     Hide,
@@ -713,6 +1237,25 @@ pub struct EventCursor {
     event_id: isize,
 }
 
+/// The number of events which have to be replayed on top of the most recent
+/// replayer snapshot (or from scratch) before a new snapshot is saved.
+const EVENT_REPLAYER_SNAPSHOT_INTERVAL: usize = 1000;
+
+/// Incremented whenever the serialized format of `EventReplayerSnapshot`
+/// changes, so that old snapshots are discarded.
+const EVENT_REPLAYER_SNAPSHOT_VERSION: usize = 1;
+
+/// The state of an `EventReplayer` after processing a prefix of the event log.
+/// This is persisted in the database so that subsequent invocations only have
+/// to replay the events which were added since the snapshot was taken.
+#[derive(Deserialize, Serialize)]
+struct EventReplayerSnapshot {
+    id_counter: isize,
+    events: Vec<Event>,
+    commit_history: HashMap<NonZeroOid, Vec<(isize, EventClassification)>>,
+    ref_locations: HashMap<ReferenceName, NonZeroOid>,
+}
+
 /// Processes events in order and determine the repo's visible commits.
 pub struct EventReplayer {
     /// Events are numbered starting from zero.
@@ -724,6 +1267,9 @@ pub struct EventReplayer {
     /// The name of the reference representing the main branch.
     main_branch_reference_name: ReferenceName,
 
+    /// Determines which reference updates should be dropped during replay.
+    ref_update_filter: RefUpdateFilter,
+
     /// The events that have affected each commit.
     commit_history: HashMap<NonZeroOid, Vec<EventInfo>>,
 
@@ -747,11 +1293,12 @@ impl std::fmt::Debug for EventReplayer {
 }
 
 impl EventReplayer {
-    fn new(main_branch_reference_name: ReferenceName) -> Self {
+    fn new(main_branch_reference_name: ReferenceName, ref_update_filter: RefUpdateFilter) -> Self {
         EventReplayer {
             id_counter: 0,
             events: vec![],
             main_branch_reference_name,
+            ref_update_filter,
             commit_history: HashMap::new(),
             ref_locations: HashMap::new(),
         }
@@ -759,6 +1306,10 @@ impl EventReplayer {
 
     /// Construct the replayer from all the events in the database.
     ///
+    /// If a replayer snapshot has been saved in the database, then only the
+    /// events which were added after the snapshot are replayed. If many events
+    /// had to be replayed, a new snapshot is saved for next time.
+    ///
     /// Args:
     /// * `event_log_db`: The database to query events from.
     ///
@@ -767,17 +1318,146 @@ impl EventReplayer {
         effects: &Effects,
         repo: &Repo,
         event_log_db: &EventLogDb,
+    ) -> eyre::Result<Self> {
+        Self::from_event_log_db_with_snapshot_interval(
+            effects,
+            repo,
+            event_log_db,
+            EVENT_REPLAYER_SNAPSHOT_INTERVAL,
+        )
+    }
+
+    fn from_event_log_db_with_snapshot_interval(
+        effects: &Effects,
+        repo: &Repo,
+        event_log_db: &EventLogDb,
+        snapshot_interval: usize,
     ) -> eyre::Result<Self> {
         let (_effects, _progress) = effects.start_operation(OperationType::ProcessEvents);
 
         let main_branch_reference_name = repo.get_main_branch_reference()?.get_name()?;
-        let mut result = EventReplayer::new(main_branch_reference_name);
-        for event in event_log_db.get_events()? {
+        let ref_update_filter = RefUpdateFilter::from_repo(repo)?;
+        let fingerprint = format!(
+            "v{}\n{}",
+            EVENT_REPLAYER_SNAPSHOT_VERSION,
+            ref_update_filter.fingerprint()
+        );
+
+        let snapshot = match event_log_db.load_replayer_snapshot(&fingerprint)? {
+            None => None,
+            Some((last_event_rowid, state)) => match bincode::deserialize(&state) {
+                Ok(snapshot) => Some((last_event_rowid, snapshot)),
+                Err(err) => {
+                    warn!(?err, "Could not deserialize event replayer snapshot");
+                    None
+                }
+            },
+        };
+        let snapshot = snapshot.and_then(|(last_event_rowid, snapshot)| {
+            match EventReplayer::from_snapshot(
+                main_branch_reference_name.clone(),
+                ref_update_filter.clone(),
+                snapshot,
+            ) {
+                Some(replayer) => Some((replayer, last_event_rowid)),
+                None => {
+                    warn!("Event replayer snapshot is inconsistent, replaying all events");
+                    None
+                }
+            }
+        });
+        let (mut result, mut last_event_rowid) = match snapshot {
+            Some(snapshot) => snapshot,
+            None => (
+                EventReplayer::new(main_branch_reference_name, ref_update_filter),
+                0,
+            ),
+        };
+
+        let events = event_log_db.get_events_after_rowid(last_event_rowid)?;
+        let num_replayed_events = events.len();
+        for (rowid, event) in events {
             result.process_event(&event);
+            last_event_rowid = rowid;
         }
+
+        if num_replayed_events >= snapshot_interval {
+            let state = bincode::serialize(&result.make_snapshot())
+                .wrap_err("Serializing event replayer snapshot")?;
+            // Failing to save the snapshot only makes subsequent invocations
+            // slower, so don't abort the current operation.
+            if let Err(err) =
+                event_log_db.save_replayer_snapshot(last_event_rowid, &fingerprint, &state)
+            {
+                warn!(?err, "Could not save event replayer snapshot");
+            }
+        }
+
         Ok(result)
     }
 
+    /// Restore the replayer from a snapshot. Returns `None` if the snapshot
+    /// refers to events which it doesn't contain, such as if it was truncated.
+    fn from_snapshot(
+        main_branch_reference_name: ReferenceName,
+        ref_update_filter: RefUpdateFilter,
+        snapshot: EventReplayerSnapshot,
+    ) -> Option<Self> {
+        let EventReplayerSnapshot {
+            id_counter,
+            events,
+            commit_history,
+            ref_locations,
+        } = snapshot;
+        if usize::try_from(id_counter).ok()? != events.len() {
+            return None;
+        }
+        let commit_history = commit_history
+            .into_iter()
+            .map(|(oid, history)| {
+                let history = history
+                    .into_iter()
+                    .map(|(id, event_classification)| {
+                        let event = events.get(usize::try_from(id).ok()?)?;
+                        Some(EventInfo {
+                            id,
+                            event: event.clone(),
+                            event_classification,
+                        })
+                    })
+                    .collect::<Option<_>>()?;
+                Some((oid, history))
+            })
+            .collect::<Option<_>>()?;
+        Some(EventReplayer {
+            id_counter,
+            events,
+            main_branch_reference_name,
+            ref_update_filter,
+            commit_history,
+            ref_locations,
+        })
+    }
+
+    fn make_snapshot(&self) -> EventReplayerSnapshot {
+        EventReplayerSnapshot {
+            id_counter: self.id_counter,
+            events: self.events.clone(),
+            commit_history: self
+                .commit_history
+                .iter()
+                .map(|(oid, history)| {
+                    let history = history
+                        .iter()
+                        .map(|event_info| (event_info.id, event_info.event_classification))
+                        .collect();
+                    (*oid, history)
+                })
+                .collect(),
+            ref_locations: self.ref_locations.clone(),
+        }
+    }
+
     /// Process the given event.
     ///
     /// This also sets the event cursor to point to immediately after the event
@@ -789,7 +1469,7 @@ impl EventReplayer {
     pub fn process_event(&mut self, event: &Event) {
         // Drop non-meaningful ref-update events.
         if let Event::RefUpdateEvent { ref_name, .. } = event {
-            if should_ignore_ref_updates(ref_name) {
+            if self.ref_update_filter.should_ignore(ref_name) {
                 return;
             }
         }
@@ -1045,6 +1725,18 @@ impl EventReplayer {
         EventCursor { event_id }
     }
 
+    /// Create an event cursor pointing to immediately after the last event
+    /// which happened at or before the given time. If no events happened
+    /// before then, the cursor points to before the first event.
+    pub fn make_cursor_at_time(&self, time: SystemTime) -> EventCursor {
+        let event_id = self
+            .events
+            .iter()
+            .take_while(|event| event.get_timestamp() <= time)
+            .count();
+        self.make_cursor(event_id.try_into().unwrap())
+    }
+
     /// Advance the event cursor by the specified number of events.
     ///
     /// Args:
@@ -1133,6 +1825,22 @@ impl EventReplayer {
         }
     }
 
+    /// Create an event cursor pointing to immediately before the first event
+    /// in the given transaction or any later transaction. If there are no such
+    /// events, the cursor points to immediately after the last event.
+    pub fn make_cursor_before_transaction(&self, event_tx_id: EventTransactionId) -> EventCursor {
+        let EventTransactionId(event_tx_id) = event_tx_id;
+        let event_id = self
+            .events
+            .iter()
+            .position(|event| {
+                let EventTransactionId(other_event_tx_id) = event.get_event_tx_id();
+                other_event_tx_id >= event_tx_id
+            })
+            .unwrap_or(self.events.len());
+        self.make_cursor(event_id.try_into().unwrap())
+    }
+
     /// Get the OID of `HEAD` at the cursor's point in time.
     ///
     /// Returns: The OID pointed to by `HEAD` at that time, or `None` if `HEAD`
@@ -1221,6 +1929,89 @@ impl EventReplayer {
         }
     }
 
+    /// Get the OIDs of the additional main branches at the cursor's point in
+    /// time. Like `get_cursor_main_branch_oid`, branches which haven't been
+    /// observed moving yet are assumed to be at their current location.
+    fn get_cursor_additional_main_branch_oids(
+        &self,
+        cursor: EventCursor,
+        repo: &Repo,
+    ) -> eyre::Result<HashSet<NonZeroOid>> {
+        let mut result = HashSet::new();
+        for reference in repo.get_additional_main_branch_references()? {
+            let reference_name = reference.get_name()?;
+            match self.get_cursor_branch_oid(cursor, &reference_name)? {
+                Some(oid) => {
+                    result.insert(oid);
+                }
+                None => {
+                    if let Some(commit) = reference.peel_to_commit()? {
+                        result.insert(commit.get_oid());
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Get the OIDs of the public remote-tracking branches at the cursor's
+    /// point in time.
+    ///
+    /// Each branch is at the location it was last updated to before the
+    /// cursor. If it was only updated after the cursor, then it's at the
+    /// location it was first updated from. Branches which haven't been
+    /// observed moving at all are assumed to be at their current locations.
+    fn get_cursor_public_remote_branch_oids(
+        &self,
+        cursor: EventCursor,
+        repo: &Repo,
+    ) -> eyre::Result<HashSet<NonZeroOid>> {
+        let patterns = get_public_remote_branch_globs(repo)?;
+        if patterns.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let cursor_event_id: usize = cursor.event_id.try_into().unwrap();
+        let mut ref_name_to_oid: HashMap<&ReferenceName, MaybeZeroOid> = HashMap::new();
+        for event in self.events[..cursor_event_id].iter() {
+            if let Event::RefUpdateEvent {
+                ref_name, new_oid, ..
+            } = event
+            {
+                if is_public_remote_branch(&patterns, ref_name) {
+                    ref_name_to_oid.insert(ref_name, *new_oid);
+                }
+            }
+        }
+        for event in self.events[cursor_event_id..].iter() {
+            if let Event::RefUpdateEvent {
+                ref_name, old_oid, ..
+            } = event
+            {
+                if is_public_remote_branch(&patterns, ref_name) {
+                    ref_name_to_oid.entry(ref_name).or_insert(*old_oid);
+                }
+            }
+        }
+
+        let mut result: HashSet<NonZeroOid> = ref_name_to_oid
+            .values()
+            .filter_map(|oid| match oid {
+                MaybeZeroOid::NonZero(oid) => Some(*oid),
+                MaybeZeroOid::Zero => None,
+            })
+            .collect();
+        for reference in repo.get_public_remote_branch_references()? {
+            if ref_name_to_oid.contains_key(&reference.get_name()?) {
+                continue;
+            }
+            if let Some(commit) = reference.peel_to_commit()? {
+                result.insert(commit.get_oid());
+            }
+        }
+        Ok(result)
+    }
+
     /// Get the mapping of branch OIDs to names at the cursor's point in
     /// time.
     ///
@@ -1286,10 +2077,15 @@ impl EventReplayer {
     ) -> eyre::Result<RepoReferencesSnapshot> {
         let head_oid = self.get_cursor_head_oid(cursor);
         let main_branch_oid = self.get_cursor_main_branch_oid(cursor, repo)?;
+        let additional_main_branch_oids =
+            self.get_cursor_additional_main_branch_oids(cursor, repo)?;
+        let public_remote_branch_oids = self.get_cursor_public_remote_branch_oids(cursor, repo)?;
         let branch_oid_to_names = self.get_cursor_branch_oid_to_names(cursor, repo)?;
         Ok(RepoReferencesSnapshot {
             head_oid,
             main_branch_oid,
+            additional_main_branch_oids,
+            public_remote_branch_oids,
             branch_oid_to_names,
         })
     }
@@ -1329,6 +2125,26 @@ impl EventReplayer {
         }
     }
 
+    /// Get all the events that happened before the event cursor.
+    ///
+    /// Returns: An ordered list of events that happened before the event
+    /// cursor, from least recent to most recent.
+    pub fn get_events_before_cursor(&self, cursor: EventCursor) -> &[Event] {
+        let cursor_event_id: usize = cursor.event_id.try_into().unwrap();
+        &self.events[..cursor_event_id]
+    }
+
+    /// Get all the events that happened after the `start` cursor and before
+    /// the `end` cursor.
+    ///
+    /// Returns: An ordered list of events, from least recent to most recent.
+    /// If `end` is before `start`, returns an empty list.
+    pub fn get_events_between_cursors(&self, start: EventCursor, end: EventCursor) -> &[Event] {
+        let start_event_id: usize = start.event_id.try_into().unwrap();
+        let end_event_id: usize = end.event_id.try_into().unwrap();
+        &self.events[start_event_id..end_event_id.max(start_event_id)]
+    }
+
     /// Get all the events that have happened since the event cursor.
     ///
     /// Returns: An ordered list of events that have happened since the event
@@ -1383,6 +2199,7 @@ pub mod testing {
 mod tests {
     use super::*;
 
+    use crate::core::formatting::Glyphs;
     use crate::testing::make_git;
     use testing::make_dummy_transaction_id;
 
@@ -1394,7 +2211,8 @@ mod tests {
             event_tx_id,
             commit_oid: NonZeroOid::from_str("abc")?,
         };
-        let mut replayer = EventReplayer::new("refs/heads/master".into());
+        let mut replayer =
+            EventReplayer::new("refs/heads/master".into(), RefUpdateFilter::default());
         replayer.process_event(&meaningful_event);
         replayer.process_event(&Event::RefUpdateEvent {
             timestamp: 0.0,
@@ -1469,7 +2287,8 @@ mod tests {
 
     #[test]
     fn test_advance_cursor_by_transaction() -> eyre::Result<()> {
-        let mut event_replayer = EventReplayer::new("refs/heads/master".into());
+        let mut event_replayer =
+            EventReplayer::new("refs/heads/master".into(), RefUpdateFilter::default());
         for (timestamp, event_tx_id) in (0..).zip(&[1, 1, 2, 2, 3, 4]) {
             let timestamp: f64 = timestamp.try_into()?;
             event_replayer.process_event(&Event::UnobsoleteEvent {
@@ -1539,4 +2358,166 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_make_cursor_at_time() -> eyre::Result<()> {
+        let mut event_replayer =
+            EventReplayer::new("refs/heads/master".into(), RefUpdateFilter::default());
+        for (timestamp, event_tx_id) in [(10.0, 1), (10.0, 1), (20.0, 2), (30.0, 3)] {
+            event_replayer.process_event(&Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid: NonZeroOid::from_str("abc")?,
+            });
+        }
+
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(
+            event_replayer.make_cursor_at_time(at(5)),
+            EventCursor { event_id: 0 },
+        );
+        assert_eq!(
+            event_replayer.make_cursor_at_time(at(10)),
+            EventCursor { event_id: 2 },
+        );
+        assert_eq!(
+            event_replayer.make_cursor_at_time(at(25)),
+            EventCursor { event_id: 3 },
+        );
+        assert_eq!(
+            event_replayer.make_cursor_at_time(at(100)),
+            EventCursor { event_id: 4 },
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_replayer_snapshot() -> eyre::Result<()> {
+        let git = make_git()?;
+
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["hide", "HEAD"])?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let fingerprint = format!("v{}\n", EVENT_REPLAYER_SNAPSHOT_VERSION);
+        assert!(event_log_db.load_replayer_snapshot(&fingerprint)?.is_none());
+
+        // Replaying at least one event with an interval of 1 saves a snapshot.
+        EventReplayer::from_event_log_db_with_snapshot_interval(&effects, &repo, &event_log_db, 1)?;
+        assert!(event_log_db.load_replayer_snapshot(&fingerprint)?.is_some());
+
+        git.run(&["checkout", "HEAD^"])?;
+        git.commit_file("test3", 3)?;
+
+        let incremental_replayer = EventReplayer::from_event_log_db_with_snapshot_interval(
+            &effects,
+            &repo,
+            &event_log_db,
+            usize::MAX,
+        )?;
+        let mut full_replayer =
+            EventReplayer::new("refs/heads/master".into(), RefUpdateFilter::default());
+        for event in event_log_db.get_events()? {
+            full_replayer.process_event(&event);
+        }
+
+        assert_eq!(incremental_replayer.events, full_replayer.events);
+        assert_eq!(
+            incremental_replayer.ref_locations,
+            full_replayer.ref_locations
+        );
+        let cursor = full_replayer.make_default_cursor();
+        assert_eq!(incremental_replayer.make_default_cursor(), cursor);
+        assert_eq!(
+            incremental_replayer.get_cursor_oids(cursor),
+            full_replayer.get_cursor_oids(cursor)
+        );
+        for oid in full_replayer.get_cursor_oids(cursor) {
+            assert_eq!(
+                format!(
+                    "{:?}",
+                    incremental_replayer.get_cursor_commit_activity_status(cursor, oid)
+                ),
+                format!(
+                    "{:?}",
+                    full_replayer.get_cursor_commit_activity_status(cursor, oid)
+                ),
+            );
+        }
+
+        // Deleting rows from the event log invalidates the snapshot.
+        conn.execute("DELETE FROM event_log WHERE rowid = 1", rusqlite::params![])?;
+        assert!(event_log_db.load_replayer_snapshot(&fingerprint)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_replayer_inconsistent_snapshot() -> eyre::Result<()> {
+        let git = make_git()?;
+
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let fingerprint = format!("v{}\n", EVENT_REPLAYER_SNAPSHOT_VERSION);
+        EventReplayer::from_event_log_db_with_snapshot_interval(&effects, &repo, &event_log_db, 1)?;
+
+        // Truncate the events in the snapshot, so that the commit history
+        // refers to events which it no longer contains.
+        let (last_event_rowid, state) = event_log_db.load_replayer_snapshot(&fingerprint)?.unwrap();
+        let mut snapshot: EventReplayerSnapshot = bincode::deserialize(&state)?;
+        snapshot.events.truncate(1);
+        let state = bincode::serialize(&snapshot)?;
+        event_log_db.save_replayer_snapshot(last_event_rowid, &fingerprint, &state)?;
+
+        let replayer = EventReplayer::from_event_log_db_with_snapshot_interval(
+            &effects,
+            &repo,
+            &event_log_db,
+            usize::MAX,
+        )?;
+        assert_eq!(replayer.events.len(), event_log_db.get_events()?.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_merge_conflicts() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let missing_oid = NonZeroOid::from_str("0123456789012345678901234567890123456789")?;
+
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let now = SystemTime::UNIX_EPOCH;
+        let event_tx_id = make_dummy_transaction_id(123);
+        event_log_db.add_merge_conflict(now, event_tx_id, test1_oid, 1)?;
+        event_log_db.add_merge_conflict(now, event_tx_id, missing_oid, 2)?;
+        event_log_db.add_merge_conflict(now, event_tx_id, missing_oid, 3)?;
+
+        assert_eq!(event_log_db.prune_merge_conflicts(&repo)?, 2);
+        let commit_oids = event_log_db
+            .get_merge_conflicts()?
+            .into_iter()
+            .map(|record| record.commit_oid)
+            .collect_vec();
+        assert_eq!(commit_oids, vec![test1_oid]);
+
+        Ok(())
+    }
 }
@@ -4,12 +4,95 @@
 //! "TTY"). In the case of interactive output, we render with prettier non-ASCII
 //! characters and with colors, using shell-specific escape codes.
 
+use std::borrow::Cow;
 use std::fmt::Display;
 
-use cursive::theme::{Effect, Style};
+use cursive::theme::{BaseColor, Color, ColorStyle, ColorType, Effect, Style};
 use cursive::utils::markup::StyledString;
 use cursive::utils::span::Span;
 
+/// Word-level diff highlighting, shared between the interactive change
+/// selector (see [`git_record::Recorder::with_word_diff`]) and textual diff
+/// output. [`highlight_word_diff`] renders a removed line and the added line
+/// which replaced it, emphasizing the words which changed.
+pub use git_record::{diff_words, highlight_word_diff, WordDiffSpan};
+
+fn style_patch_line(line: &str) -> StyledString {
+    if line.starts_with("diff --git")
+        || line.starts_with("index ")
+        || line.starts_with("--- ")
+        || line.starts_with("+++ ")
+    {
+        StyledString::styled(line, Effect::Bold)
+    } else if line.starts_with("@@") {
+        StyledString::styled(line, BaseColor::Cyan.dark())
+    } else if line.starts_with('+') {
+        StyledString::styled(line, BaseColor::Green.dark())
+    } else if line.starts_with('-') {
+        StyledString::styled(line, BaseColor::Red.dark())
+    } else {
+        StyledString::plain(line)
+    }
+}
+
+/// Render the lines of a patch, as produced by `git diff`, highlighting changed
+/// words within modified lines if `word_diff` is set.
+///
+/// A run of removed lines which is immediately followed by a run of added lines
+/// of the same length is treated as a modification, and each removed line is
+/// paired with the corresponding added line.
+pub fn render_patch(patch: &str, word_diff: bool) -> Vec<StyledString> {
+    fn flush(
+        result: &mut Vec<StyledString>,
+        removed: &mut Vec<&str>,
+        added: &mut Vec<&str>,
+        word_diff: bool,
+    ) {
+        if word_diff && !removed.is_empty() && removed.len() == added.len() {
+            let (removed_lines, added_lines): (Vec<_>, Vec<_>) = removed
+                .iter()
+                .zip(added.iter())
+                .map(|(removed_line, added_line)| {
+                    highlight_word_diff("-", &removed_line[1..], "+", &added_line[1..])
+                })
+                .unzip();
+            result.extend(removed_lines);
+            result.extend(added_lines);
+        } else {
+            result.extend(removed.iter().map(|line| style_patch_line(line)));
+            result.extend(added.iter().map(|line| style_patch_line(line)));
+        }
+        removed.clear();
+        added.clear();
+    }
+
+    let mut result = Vec::new();
+    let mut is_in_hunk = false;
+    let mut removed: Vec<&str> = Vec::new();
+    let mut added: Vec<&str> = Vec::new();
+    for line in patch.lines() {
+        if is_in_hunk && line.starts_with('-') && added.is_empty() {
+            removed.push(line);
+        } else if is_in_hunk && line.starts_with('+') {
+            added.push(line);
+        } else {
+            flush(&mut result, &mut removed, &mut added, word_diff);
+            if is_in_hunk && line.starts_with('-') {
+                removed.push(line);
+                continue;
+            }
+            if line.starts_with("@@") {
+                is_in_hunk = true;
+            } else if line.starts_with("diff --git") {
+                is_in_hunk = false;
+            }
+            result.push(style_patch_line(line));
+        }
+    }
+    flush(&mut result, &mut removed, &mut added, word_diff);
+    result
+}
+
 /// Pluralize a quantity, as appropriate. Example:
 ///
 /// ```
@@ -69,13 +152,46 @@ impl Display for Pluralize<'_> {
     }
 }
 
+/// A set of characters to render the smartlog with, as configured by
+/// `branchless.smartlog.glyphs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlyphSet {
+    /// Only ASCII characters. See [`Glyphs::text`].
+    Ascii,
+
+    /// Unicode box-drawing characters and shapes. See [`Glyphs::pretty`].
+    Unicode,
+
+    /// Icons from a [Nerd Font](https://www.nerdfonts.com/). See
+    /// [`Glyphs::nerd_font`].
+    NerdFont,
+}
+
+/// The names of the glyph sets, as accepted by [`GlyphSet::from_name`].
+pub const GLYPH_SET_NAMES: &[&str] = &["ascii", "unicode", "nerdfont"];
+
+impl GlyphSet {
+    /// Get the glyph set with the given name. See [`GLYPH_SET_NAMES`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "ascii" => Some(Self::Ascii),
+            "unicode" => Some(Self::Unicode),
+            "nerdfont" => Some(Self::NerdFont),
+            _ => None,
+        }
+    }
+}
+
 /// Glyphs to use for rendering the smartlog.
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Glyphs {
     /// Whether or not ANSI escape codes should be emitted (e.g. to render
     /// color).
     pub should_write_ansi_escape_codes: bool,
 
+    /// The styles to render output with, if ANSI escape codes are written.
+    pub theme: Theme,
+
     /// Line connecting a parent commit to its single child commit.
     pub line: &'static str,
 
@@ -89,32 +205,36 @@ pub struct Glyphs {
     pub slash: &'static str,
 
     /// Cursor for a normal visible commit which is not currently checked out.
-    pub commit_visible: &'static str,
+    ///
+    /// The cursors can be customized via configuration (see
+    /// `get_smartlog_glyphs`), so unlike the other glyphs, they may be owned
+    /// strings.
+    pub commit_visible: Cow<'static, str>,
 
     /// Cursor for the visible commit which is currently checked out.
-    pub commit_visible_head: &'static str,
+    pub commit_visible_head: Cow<'static, str>,
 
     /// Cursor for an obsolete commit.
-    pub commit_obsolete: &'static str,
+    pub commit_obsolete: Cow<'static, str>,
 
     /// Cursor for the obsolete commit which is currently checked out.
-    pub commit_obsolete_head: &'static str,
+    pub commit_obsolete_head: Cow<'static, str>,
 
     /// Cursor for a commit belonging to the main branch, which is not currently
     /// checked out.
-    pub commit_main: &'static str,
+    pub commit_main: Cow<'static, str>,
 
     /// Cursor for a commit belonging to the main branch, which is currently
     /// checked out.
-    pub commit_main_head: &'static str,
+    pub commit_main_head: Cow<'static, str>,
 
     /// Cursor for an obsolete commit belonging to the main branch. (This is an
     /// unusual situation.)
-    pub commit_main_obsolete: &'static str,
+    pub commit_main_obsolete: Cow<'static, str>,
 
     /// Cursor for an obsolete commit belonging to the main branch, which is
     /// currently checked out. (This is an unusual situation.)
-    pub commit_main_obsolete_head: &'static str,
+    pub commit_main_obsolete_head: Cow<'static, str>,
 
     /// Character used to point to the currently-checked-out branch.
     pub branch_arrow: &'static str,
@@ -139,10 +259,10 @@ pub struct Glyphs {
 }
 
 impl Glyphs {
-    /// Make the `Glyphs` object appropriate for `stdout`.
+    /// Make the `Glyphs` object appropriate for `stdout`. See
+    /// [`should_use_color`] for how the environment is consulted.
     pub fn detect() -> Self {
-        let color_support = concolor::get(concolor::Stream::Stdout);
-        if color_support.color() {
+        if should_use_color(console::user_attended(), |var| std::env::var(var).ok()) {
             Glyphs::pretty()
         } else {
             Glyphs::text()
@@ -153,18 +273,19 @@ impl Glyphs {
     pub fn text() -> Self {
         Glyphs {
             should_write_ansi_escape_codes: false,
+            theme: Theme::default(),
             line: "|",
             line_with_offshoot: "|",
             vertical_ellipsis: ":",
             slash: "\\",
-            commit_visible: "o",
-            commit_visible_head: "@",
-            commit_obsolete: "x",
-            commit_obsolete_head: "%",
-            commit_main: "O",
-            commit_main_head: "@",
-            commit_main_obsolete: "X",
-            commit_main_obsolete_head: "%",
+            commit_visible: Cow::Borrowed("o"),
+            commit_visible_head: Cow::Borrowed("@"),
+            commit_obsolete: Cow::Borrowed("x"),
+            commit_obsolete_head: Cow::Borrowed("%"),
+            commit_main: Cow::Borrowed("O"),
+            commit_main_head: Cow::Borrowed("@"),
+            commit_main_obsolete: Cow::Borrowed("X"),
+            commit_main_obsolete_head: Cow::Borrowed("%"),
             branch_arrow: ">",
             bullet_point: "-",
             cycle_arrow: ">",
@@ -179,18 +300,19 @@ impl Glyphs {
     pub fn pretty() -> Self {
         Glyphs {
             should_write_ansi_escape_codes: true,
+            theme: Theme::default(),
             line: "┃",
             line_with_offshoot: "┣",
             vertical_ellipsis: "⋮",
             slash: "━┓",
-            commit_visible: "◯",
-            commit_visible_head: "●",
-            commit_obsolete: "✕",
-            commit_obsolete_head: "⦻",
-            commit_main: "◇",
-            commit_main_head: "◆",
-            commit_main_obsolete: "✕",
-            commit_main_obsolete_head: "❖",
+            commit_visible: Cow::Borrowed("◯"),
+            commit_visible_head: Cow::Borrowed("●"),
+            commit_obsolete: Cow::Borrowed("✕"),
+            commit_obsolete_head: Cow::Borrowed("⦻"),
+            commit_main: Cow::Borrowed("◇"),
+            commit_main_head: Cow::Borrowed("◆"),
+            commit_main_obsolete: Cow::Borrowed("✕"),
+            commit_main_obsolete_head: Cow::Borrowed("❖"),
             branch_arrow: "ᐅ",
             bullet_point: "•",
             cycle_arrow: "ᐅ",
@@ -200,6 +322,67 @@ impl Glyphs {
             cycle_lower_left_corner: "└",
         }
     }
+
+    /// Glyphs used for output to a TTY whose font includes the icons from
+    /// [Nerd Fonts](https://www.nerdfonts.com/).
+    pub fn nerd_font() -> Self {
+        Glyphs {
+            // nf-oct-git_commit
+            commit_visible: Cow::Borrowed("\u{f417}"),
+            // nf-fa-dot_circle_o
+            commit_visible_head: Cow::Borrowed("\u{f192}"),
+            // nf-fa-times
+            commit_obsolete: Cow::Borrowed("\u{f00d}"),
+            // nf-fa-times_circle
+            commit_obsolete_head: Cow::Borrowed("\u{f057}"),
+            // nf-fa-diamond
+            commit_main: Cow::Borrowed("\u{f219}"),
+            // nf-fa-dot_circle_o
+            commit_main_head: Cow::Borrowed("\u{f192}"),
+            // nf-fa-times
+            commit_main_obsolete: Cow::Borrowed("\u{f00d}"),
+            // nf-fa-times_circle
+            commit_main_obsolete_head: Cow::Borrowed("\u{f057}"),
+            // nf-pl-branch
+            branch_arrow: "\u{e0a0}",
+            ..Glyphs::pretty()
+        }
+    }
+
+    /// Use the characters from the given glyph set, but keep the setting for
+    /// whether to write ANSI escape codes and the theme.
+    pub fn with_glyph_set(self, glyph_set: GlyphSet) -> Self {
+        let glyphs = match glyph_set {
+            GlyphSet::Ascii => Glyphs::text(),
+            GlyphSet::Unicode => Glyphs::pretty(),
+            GlyphSet::NerdFont => Glyphs::nerd_font(),
+        };
+        Glyphs {
+            should_write_ansi_escape_codes: self.should_write_ansi_escape_codes,
+            theme: self.theme,
+            ..glyphs
+        }
+    }
+
+    /// Render output with the styles from the given theme.
+    pub fn with_theme(self, theme: Theme) -> Self {
+        Glyphs { theme, ..self }
+    }
+
+    /// Render the `Warning` label which prefixes warning messages, for use in
+    /// output which isn't built from `StyledString`s.
+    pub fn render_warning_label(&self) -> eyre::Result<String> {
+        printable_styled_string(
+            self,
+            StyledString::styled("Warning", self.theme.warning_label),
+        )
+    }
+
+    /// Render the `hint` label which prefixes hints, for use in output which
+    /// isn't built from `StyledString`s.
+    pub fn render_hint_label(&self) -> eyre::Result<String> {
+        printable_styled_string(self, StyledString::styled("hint", self.theme.hint_label))
+    }
 }
 
 impl std::fmt::Debug for Glyphs {
@@ -212,6 +395,250 @@ impl std::fmt::Debug for Glyphs {
     }
 }
 
+/// Determine whether output should be colored, given whether it's being written
+/// to a terminal and a function to look up environment variables. The variables
+/// are consulted in this order:
+///
+/// - `NO_COLOR`: if set to a non-empty value, never use color (see
+///   <https://no-color.org>).
+/// - `CLICOLOR_FORCE`: if set to a non-empty value other than `0`, always use
+///   color, even when not writing to a terminal.
+/// - `CLICOLOR`: if set to `0`, don't use color.
+/// - `TERM`: if set to `dumb`, don't use color.
+///
+/// Otherwise, color is used only when writing to a terminal. An explicit
+/// `--color` option takes precedence over all of these.
+pub fn should_use_color(is_terminal: bool, get_var: impl Fn(&str) -> Option<String>) -> bool {
+    let get_var = |var: &str| get_var(var).filter(|value| !value.is_empty());
+    if get_var("NO_COLOR").is_some() {
+        false
+    } else if get_var("CLICOLOR_FORCE").map_or(false, |value| value != "0") {
+        true
+    } else if get_var("CLICOLOR").as_deref() == Some("0")
+        || get_var("TERM").as_deref() == Some("dumb")
+    {
+        false
+    } else {
+        is_terminal
+    }
+}
+
+/// The styles used to render each kind of user-facing output. Styles only take
+/// effect when color is enabled (see [`Glyphs::should_write_ansi_escape_codes`]).
+///
+/// The theme is carried on [`Glyphs`] (see [`Glyphs::with_theme`]), and so is
+/// available from [`crate::core::effects::Effects::get_theme`]. It starts out
+/// as [`Theme::default`], and is replaced at startup according to the
+/// `branchless.color.*` configuration.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Theme {
+    /// Abbreviated commit hashes.
+    pub commit_oid: Style,
+
+    /// Branch names.
+    pub branch: Style,
+
+    /// De-emphasized information, such as the number of hidden commits.
+    pub muted: Style,
+
+    /// Supplementary labels, such as worktrees, commit metadata and authors.
+    pub annotation: Style,
+
+    /// Topic names.
+    pub topic: Style,
+
+    /// Secondary commit details, such as commit times and code review numbers.
+    pub detail: Style,
+
+    /// Successful statuses, such as passing CI.
+    pub success: Style,
+
+    /// Notices which need attention, such as pending CI or rewritten commits.
+    pub warning: Style,
+
+    /// Failures, such as failing CI or a failed checkout.
+    pub error: Style,
+
+    /// Reports of merge conflicts.
+    pub conflict: Style,
+
+    /// Prominent warnings, such as the `Warning` label which prefixes warning
+    /// messages.
+    pub warning_label: Style,
+
+    /// The `hint` label which prefixes hints.
+    pub hint_label: Style,
+
+    /// The line for the currently-checked-out commit in the smartlog.
+    pub head: Style,
+
+    /// The commit glyphs in the smartlog graph.
+    pub graph: Style,
+}
+
+/// The names of the built-in themes, as accepted by [`Theme::from_name`].
+pub const THEME_NAMES: &[&str] = &["default", "high-contrast"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            commit_oid: BaseColor::Yellow.dark().into(),
+            branch: BaseColor::Green.light().into(),
+            muted: BaseColor::Black.light().into(),
+            annotation: BaseColor::Magenta.light().into(),
+            topic: BaseColor::Cyan.light().into(),
+            detail: BaseColor::Green.dark().into(),
+            success: BaseColor::Green.light().into(),
+            warning: BaseColor::Yellow.light().into(),
+            error: BaseColor::Red.light().into(),
+            conflict: BaseColor::Red.light().into(),
+            warning_label: Style::from(BaseColor::Yellow.dark()).combine(Effect::Bold),
+            hint_label: Style::from(BaseColor::Blue.dark()).combine(Effect::Bold),
+            head: Effect::Bold.into(),
+            graph: Style::none(),
+        }
+    }
+}
+
+impl Theme {
+    /// A theme which only uses the non-bright colors and the terminal's default
+    /// foreground color, so that it's readable on both light and dark
+    /// backgrounds.
+    pub fn high_contrast() -> Self {
+        let bold = |color: BaseColor| Style::from(color.dark()).combine(Effect::Bold);
+        Self {
+            commit_oid: bold(BaseColor::Blue),
+            branch: bold(BaseColor::Green),
+            muted: Color::TerminalDefault.into(),
+            annotation: BaseColor::Magenta.dark().into(),
+            topic: BaseColor::Cyan.dark().into(),
+            detail: BaseColor::Green.dark().into(),
+            success: bold(BaseColor::Green),
+            warning: bold(BaseColor::Magenta),
+            error: bold(BaseColor::Red),
+            conflict: bold(BaseColor::Red),
+            warning_label: bold(BaseColor::Magenta),
+            hint_label: bold(BaseColor::Blue),
+            head: Effect::Bold.into(),
+            graph: Style::none(),
+        }
+    }
+
+    /// Get the built-in theme with the given name. See [`THEME_NAMES`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "default" => Some(Self::default()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// The names of the roles in the theme, as used in the
+    /// `branchless.color.<role>` configuration keys.
+    pub const ROLE_NAMES: &'static [&'static str] = &[
+        "commitOid",
+        "branch",
+        "muted",
+        "annotation",
+        "topic",
+        "detail",
+        "success",
+        "warning",
+        "error",
+        "conflict",
+        "warningLabel",
+        "hintLabel",
+        "head",
+        "graph",
+    ];
+
+    /// Get a mutable reference to the style for the role with the given name
+    /// (see [`Theme::ROLE_NAMES`]). Like Git configuration keys, the name is
+    /// case-insensitive.
+    pub fn get_role_mut(&mut self, name: &str) -> Option<&mut Style> {
+        let style = match name.to_lowercase().as_str() {
+            "commitoid" => &mut self.commit_oid,
+            "branch" => &mut self.branch,
+            "muted" => &mut self.muted,
+            "annotation" => &mut self.annotation,
+            "topic" => &mut self.topic,
+            "detail" => &mut self.detail,
+            "success" => &mut self.success,
+            "warning" => &mut self.warning,
+            "error" => &mut self.error,
+            "conflict" => &mut self.conflict,
+            "warninglabel" => &mut self.warning_label,
+            "hintlabel" => &mut self.hint_label,
+            "head" => &mut self.head,
+            "graph" => &mut self.graph,
+            _ => return None,
+        };
+        Some(style)
+    }
+}
+
+/// Parse a color specification in the format used by Git's `color.*`
+/// configuration, such as `bold red` or `brightblack`: at most one foreground
+/// color, followed by any number of attributes. `normal` and `default` use the
+/// terminal's default color, and an empty specification applies no style.
+///
+/// Background colors, numbered colors and RGB colors aren't supported.
+pub fn parse_style(spec: &str) -> eyre::Result<Style> {
+    let mut color: Option<Color> = None;
+    let mut style = Style::none();
+    for word in spec.split_whitespace() {
+        let word = word.to_lowercase();
+        let effect = match word.as_str() {
+            "bold" => Some(Effect::Bold),
+            "dim" => Some(Effect::Dim),
+            "italic" => Some(Effect::Italic),
+            "ul" | "underline" => Some(Effect::Underline),
+            "blink" => Some(Effect::Blink),
+            "reverse" => Some(Effect::Reverse),
+            _ => None,
+        };
+        if let Some(effect) = effect {
+            style.effects.insert(effect);
+            continue;
+        }
+
+        let word_color = match word.as_str() {
+            "normal" | "default" => Color::TerminalDefault,
+            word => {
+                let (is_bright, base_color_name) = match word.strip_prefix("bright") {
+                    Some(base_color_name) => (true, base_color_name),
+                    None => (false, word),
+                };
+                let base_color = match base_color_name {
+                    "black" => BaseColor::Black,
+                    "red" => BaseColor::Red,
+                    "green" => BaseColor::Green,
+                    "yellow" => BaseColor::Yellow,
+                    "blue" => BaseColor::Blue,
+                    "magenta" => BaseColor::Magenta,
+                    "cyan" => BaseColor::Cyan,
+                    "white" => BaseColor::White,
+                    _ => eyre::bail!("Unsupported color or attribute {:?} in: {:?}", word, spec),
+                };
+                if is_bright {
+                    base_color.light()
+                } else {
+                    base_color.dark()
+                }
+            }
+        };
+        if color.is_some() {
+            eyre::bail!("Background colors are not supported: {:?}", spec);
+        }
+        color = Some(word_color);
+    }
+
+    if let Some(color) = color {
+        style.color = ColorStyle::new(ColorType::Color(color), ColorType::InheritParent);
+    }
+    Ok(style)
+}
+
 /// Helper to build `StyledString`s by combining multiple strings (both regular
 /// `String`s and `StyledString`s).
 pub struct StyledStringBuilder {
@@ -310,6 +737,16 @@ pub fn set_effect(mut string: StyledString, effect: Effect) -> StyledString {
     string
 }
 
+/// Apply the provided style on top of the styles of all the internal spans of
+/// the styled string. The spans keep their own colors unless the style sets a
+/// color.
+pub fn set_style(mut string: StyledString, style: Style) -> StyledString {
+    string.spans_raw_attr_mut().for_each(|span| {
+        *span.attr = span.attr.combine(style);
+    });
+    string
+}
+
 impl From<StyledStringBuilder> for StyledString {
     fn from(builder: StyledStringBuilder) -> Self {
         builder.build()
@@ -320,7 +757,6 @@ fn render_style_as_ansi(content: &str, style: Style) -> eyre::Result<String> {
     let Style { effects, color } = style;
     let output = {
         use console::style;
-        use cursive::theme::{BaseColor, Color, ColorType};
         let output = content.to_string();
         match color.front {
             ColorType::Palette(_) => {
@@ -402,3 +838,135 @@ pub fn printable_styled_string(glyphs: &Glyphs, string: StyledString) -> eyre::R
         .collect::<eyre::Result<String>>()?;
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_use_color() {
+        let should_use_color = |is_terminal: bool, env: &[(&str, &str)]| {
+            should_use_color(is_terminal, |var| {
+                env.iter()
+                    .find(|(key, _value)| *key == var)
+                    .map(|(_key, value)| value.to_string())
+            })
+        };
+        assert!(should_use_color(true, &[]));
+        assert!(!should_use_color(false, &[]));
+        assert!(!should_use_color(true, &[("NO_COLOR", "1")]));
+        assert!(should_use_color(true, &[("NO_COLOR", "")]));
+        assert!(should_use_color(false, &[("CLICOLOR_FORCE", "1")]));
+        assert!(!should_use_color(false, &[("CLICOLOR_FORCE", "0")]));
+        assert!(!should_use_color(
+            true,
+            &[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")]
+        ));
+        assert!(!should_use_color(true, &[("CLICOLOR", "0")]));
+        assert!(should_use_color(true, &[("CLICOLOR", "1")]));
+        assert!(!should_use_color(true, &[("TERM", "dumb")]));
+        assert!(should_use_color(
+            false,
+            &[("TERM", "dumb"), ("CLICOLOR_FORCE", "1")]
+        ));
+    }
+
+    #[test]
+    fn test_with_glyph_set() {
+        for name in GLYPH_SET_NAMES {
+            assert!(
+                GlyphSet::from_name(name).is_some(),
+                "missing glyph set {}",
+                name
+            );
+        }
+
+        let glyphs = Glyphs::text().with_glyph_set(GlyphSet::NerdFont);
+        assert!(!glyphs.should_write_ansi_escape_codes);
+        assert_eq!(glyphs.commit_visible, Glyphs::nerd_font().commit_visible);
+        assert_eq!(glyphs.line, Glyphs::pretty().line);
+
+        let glyphs = Glyphs::pretty().with_glyph_set(GlyphSet::Ascii);
+        assert!(glyphs.should_write_ansi_escape_codes);
+        assert_eq!(glyphs.commit_visible, "o");
+    }
+
+    #[test]
+    fn test_parse_style() -> eyre::Result<()> {
+        assert_eq!(parse_style("")?, Style::none());
+        assert_eq!(parse_style("red")?, Style::from(BaseColor::Red.dark()));
+        assert_eq!(
+            parse_style("brightBlack")?,
+            Style::from(BaseColor::Black.light())
+        );
+        assert_eq!(
+            parse_style("bold  ul yellow")?,
+            Style::from(BaseColor::Yellow.dark())
+                .combine(Effect::Bold)
+                .combine(Effect::Underline)
+        );
+        assert_eq!(parse_style("normal")?, Style::from(Color::TerminalDefault));
+        assert!(parse_style("red blue").is_err());
+        assert!(parse_style("octarine").is_err());
+        assert!(parse_style("#ff0000").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_theme_roles() {
+        let mut theme = Theme::default();
+        for role in Theme::ROLE_NAMES {
+            assert!(theme.get_role_mut(role).is_some(), "missing role {}", role);
+        }
+        assert!(theme.get_role_mut("nonexistent").is_none());
+
+        *theme.get_role_mut("COMMITOID").unwrap() = BaseColor::Red.dark().into();
+        assert_eq!(theme.commit_oid, Style::from(BaseColor::Red.dark()));
+
+        for name in THEME_NAMES {
+            assert!(Theme::from_name(name).is_some(), "missing theme {}", name);
+        }
+        assert_eq!(
+            Theme::from_name("High-Contrast"),
+            Some(Theme::high_contrast())
+        );
+    }
+
+    #[test]
+    fn test_render_patch_word_diff() {
+        let patch = "\
+diff --git a/foo b/foo
+--- a/foo
++++ b/foo
+@@ -1,2 +1,2 @@
+-hello world
++hello there
+ unchanged
+";
+        let render = |word_diff| {
+            render_patch(patch, word_diff)
+                .into_iter()
+                .map(|line| (line.source().to_string(), line.spans_raw().len()))
+                .collect::<Vec<_>>()
+        };
+        let plain = render(false);
+        let highlighted = render(true);
+        assert_eq!(
+            plain.iter().map(|(text, _)| text).collect::<Vec<_>>(),
+            highlighted.iter().map(|(text, _)| text).collect::<Vec<_>>(),
+        );
+        assert_eq!(plain[4], ("-hello world".to_string(), 1));
+        assert!(highlighted[4].1 > 1);
+        assert!(highlighted[5].1 > 1);
+        assert_eq!(highlighted[6], (" unchanged".to_string(), 1));
+    }
+
+    #[test]
+    fn test_glyphs_keep_theme() {
+        let glyphs = Glyphs::pretty().with_theme(Theme::high_contrast());
+        let glyphs = glyphs.with_glyph_set(GlyphSet::Ascii);
+        assert_eq!(glyphs.theme, Theme::high_contrast());
+        assert!(glyphs.should_write_ansi_escape_codes);
+        assert_eq!(Glyphs::pretty().theme, Theme::default());
+    }
+}
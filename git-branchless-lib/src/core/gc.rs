@@ -8,12 +8,85 @@
 //! garbage collection doesn't collect commits which branchless thinks are still
 //! active.
 
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+
 use eyre::Context;
 use tracing::instrument;
 
-use crate::git::{NonZeroOid, Reference, Repo};
+use crate::git::{
+    make_empty_tree, GitRunInfo, GitRunOpts, GitRunResult, NonZeroOid, Reference, Repo, Signature,
+};
 
+use super::config::{
+    get_maintenance_keep_ref_compaction_threshold, get_maintenance_rewrite_threshold,
+    get_maintenance_write_commit_graph,
+};
+use super::effects::Effects;
 use super::eventlog::{is_gc_ref, CommitActivityStatus, EventCursor, EventReplayer};
+use super::formatting::Pluralize;
+
+/// The reference pointing to the commit which keeps compacted commits alive.
+/// Its parents are the commits which used to have their own reference under
+/// `refs/branchless/`.
+pub const KEEP_REF_NAME: &str = "refs/branchless/keep";
+
+/// The message of the commit which keeps compacted commits alive.
+const KEEP_COMMIT_MESSAGE: &str = "branchless: keep hidden commits reachable";
+
+/// Determine whether the given commit was explicitly hidden, so that it no
+/// longer needs to be kept alive.
+fn is_dangling_commit(
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    commit_oid: NonZeroOid,
+) -> bool {
+    match event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid) {
+        CommitActivityStatus::Active => false,
+        CommitActivityStatus::Inactive => {
+            // This commit hasn't been observed, but it's possible that the user expected it
+            // to remain. Do nothing. See https://github.com/arxanas/git-branchless/issues/412.
+            false
+        }
+        CommitActivityStatus::Obsolete => {
+            // This commit was explicitly hidden by some operation.
+            true
+        }
+    }
+}
+
+/// Determine whether the given commit was last affected by an event at least
+/// `window` before `now`.
+fn was_hidden_before(
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    commit_oid: NonZeroOid,
+    now: SystemTime,
+    window: Duration,
+) -> bool {
+    match event_replayer.get_cursor_commit_latest_event(event_cursor, commit_oid) {
+        Some(event) => {
+            now.duration_since(event.get_timestamp())
+                .unwrap_or_default()
+                >= window
+        }
+        None => false,
+    }
+}
+
+/// Get the references under `refs/branchless/` which each keep a single commit
+/// alive, i.e. excluding the compacted keep reference.
+fn get_keep_references(repo: &Repo) -> eyre::Result<Vec<Reference<'_>>> {
+    let mut result = Vec::new();
+    for reference in repo.get_all_references()? {
+        let reference_name = reference.get_name()?;
+        if is_gc_ref(&reference_name) && reference_name.as_str() != KEEP_REF_NAME {
+            result.push(reference);
+        }
+    }
+    Ok(result)
+}
 
 /// Find references under `refs/branchless/` which point to commits which are no
 /// longer active. These are safe to remove.
@@ -23,12 +96,7 @@ pub fn find_dangling_references<'repo>(
     event_cursor: EventCursor,
 ) -> eyre::Result<Vec<Reference<'repo>>> {
     let mut result = Vec::new();
-    for reference in repo.get_all_references()? {
-        let reference_name = reference.get_name()?;
-        if !is_gc_ref(&reference_name) {
-            continue;
-        }
-
+    for reference in get_keep_references(repo)? {
         // The graph only contains commits, so we don't need to handle the
         // case of the reference not peeling to a valid commit. (It might be
         // a reference to a different kind of object.)
@@ -37,23 +105,149 @@ pub fn find_dangling_references<'repo>(
             None => continue,
         };
 
-        match event_replayer.get_cursor_commit_activity_status(event_cursor, commit.get_oid()) {
-            CommitActivityStatus::Active => {
-                // Do nothing.
-            }
-            CommitActivityStatus::Inactive => {
-                // This commit hasn't been observed, but it's possible that the user expected it
-                // to remain. Do nothing. See https://github.com/arxanas/git-branchless/issues/412.
-            }
-            CommitActivityStatus::Obsolete => {
-                // This commit was explicitly hidden by some operation.
-                result.push(reference)
-            }
+        if is_dangling_commit(event_replayer, event_cursor, commit.get_oid()) {
+            result.push(reference);
+        }
+    }
+    Ok(result)
+}
+
+/// Like `find_dangling_references`, but only return references to commits
+/// which were hidden at least `window` before `now`, so that recently-hidden
+/// commits can still be restored.
+pub fn find_prunable_references<'repo>(
+    repo: &'repo Repo,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    now: SystemTime,
+    window: Duration,
+) -> eyre::Result<Vec<Reference<'repo>>> {
+    let mut result = Vec::new();
+    for reference in find_dangling_references(repo, event_replayer, event_cursor)? {
+        let commit = match reference.peel_to_commit()? {
+            Some(commit) => commit,
+            None => continue,
+        };
+        if was_hidden_before(event_replayer, event_cursor, commit.get_oid(), now, window) {
+            result.push(reference);
         }
     }
     Ok(result)
 }
 
+/// Get the commits which are kept alive by the compacted keep reference.
+#[instrument]
+pub fn get_compacted_commit_oids(repo: &Repo) -> eyre::Result<Vec<NonZeroOid>> {
+    let reference = match repo.find_reference(&KEEP_REF_NAME.into())? {
+        Some(reference) => reference,
+        None => return Ok(Vec::new()),
+    };
+    match reference.peel_to_commit()? {
+        Some(commit) => Ok(commit.get_parent_oids()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Point the compacted keep reference to a new commit whose parents are the
+/// given commits, or delete it if there are none. Commits which no longer
+/// exist are skipped.
+fn write_compacted_commit_oids(
+    repo: &Repo,
+    commit_oids: impl IntoIterator<Item = NonZeroOid>,
+) -> eyre::Result<()> {
+    let commit_oids: BTreeSet<NonZeroOid> = commit_oids.into_iter().collect();
+    let mut commits = Vec::new();
+    for commit_oid in commit_oids {
+        if let Some(commit) = repo.find_commit(commit_oid)? {
+            commits.push(commit);
+        }
+    }
+
+    if commits.is_empty() {
+        if let Some(mut reference) = repo.find_reference(&KEEP_REF_NAME.into())? {
+            reference.delete()?;
+        }
+        return Ok(());
+    }
+
+    let signature = Signature::automated()?;
+    let tree = make_empty_tree(repo)?;
+    let keep_commit_oid = repo.create_commit(
+        None,
+        &signature,
+        &signature,
+        KEEP_COMMIT_MESSAGE,
+        &tree,
+        commits.iter().collect(),
+    )?;
+    repo.create_reference(
+        &KEEP_REF_NAME.into(),
+        keep_commit_oid,
+        true,
+        "branchless: compacting references",
+    )
+    .wrap_err("Updating keep reference")?;
+    Ok(())
+}
+
+/// Fold the references which each keep a single commit alive into the
+/// compacted keep reference, which points to a single commit whose parents
+/// are all of the kept commits. This keeps the number of references (and the
+/// size of `packed-refs`) small, which would otherwise slow down every Git
+/// command.
+///
+/// Returns the number of references which were compacted.
+#[instrument]
+pub fn compact_references(repo: &Repo) -> eyre::Result<usize> {
+    let mut references = get_keep_references(repo)?;
+    if references.is_empty() {
+        return Ok(0);
+    }
+
+    let mut commit_oids = get_compacted_commit_oids(repo)?;
+    for reference in references.iter() {
+        if let Some(commit) = reference.peel_to_commit()? {
+            commit_oids.push(commit.get_oid());
+        }
+    }
+    write_compacted_commit_oids(repo, commit_oids)?;
+
+    let num_references = references.len();
+    for reference in references.iter_mut() {
+        reference.delete()?;
+    }
+    Ok(num_references)
+}
+
+/// Stop keeping alive the compacted commits which were explicitly hidden. If
+/// `prune_window` is set, only commits which were hidden at least that long
+/// before the given time are freed.
+///
+/// Returns the commits which were freed.
+#[instrument]
+pub fn free_compacted_commits(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    prune_window: Option<(SystemTime, Duration)>,
+) -> eyre::Result<Vec<NonZeroOid>> {
+    let commit_oids = get_compacted_commit_oids(repo)?;
+    let (freed_oids, kept_oids): (Vec<NonZeroOid>, Vec<NonZeroOid>) =
+        commit_oids.into_iter().partition(|commit_oid| {
+            is_dangling_commit(event_replayer, event_cursor, *commit_oid)
+                && match prune_window {
+                    None => true,
+                    Some((now, window)) => {
+                        was_hidden_before(event_replayer, event_cursor, *commit_oid, now, window)
+                    }
+                }
+        });
+    if !freed_oids.is_empty() {
+        write_compacted_commit_oids(repo, kept_oids)?;
+    }
+    Ok(freed_oids)
+}
+
 /// Mark a commit as reachable.
 ///
 /// Once marked as reachable, the commit won't be collected by Git's garbage
@@ -88,3 +282,132 @@ pub fn mark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Resul
 
     Ok(())
 }
+
+/// Determine whether the repository has reachability bitmaps which are older
+/// than some of its packfiles. Git can't use bitmaps to answer reachability
+/// queries for objects which were packed after the bitmap was written.
+///
+/// Returns `false` if the repository has no bitmaps at all.
+#[instrument]
+pub fn has_stale_bitmaps(repo: &Repo) -> eyre::Result<bool> {
+    let pack_dir = repo.get_common_path()?.join("objects").join("pack");
+    let entries = match std::fs::read_dir(&pack_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {:?}", &pack_dir)),
+    };
+
+    let mut bitmap_times: Vec<(String, SystemTime)> = Vec::new();
+    let mut pack_times: Vec<(String, SystemTime)> = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let (stem, extension) = match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(extension)) => (stem.to_string_lossy(), extension),
+            _ => continue,
+        };
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .wrap_err_with(|| format!("Reading modification time of {:?}", &path))?;
+        if extension == "bitmap" {
+            bitmap_times.push((stem.into_owned(), modified));
+        } else if extension == "pack" {
+            pack_times.push((stem.into_owned(), modified));
+        }
+    }
+
+    let newest_bitmap_time = match bitmap_times.iter().map(|(_stem, time)| time).max() {
+        Some(time) => *time,
+        None => return Ok(false),
+    };
+    let is_stale = pack_times.iter().any(|(pack_stem, pack_time)| {
+        let is_bitmapped = bitmap_times
+            .iter()
+            .any(|(bitmap_stem, _time)| bitmap_stem == pack_stem);
+        !is_bitmapped && *pack_time > newest_bitmap_time
+    });
+    Ok(is_stale)
+}
+
+/// Perform maintenance on Git's own data structures after branchless has
+/// created or abandoned many objects, such as after garbage collection or a
+/// large rewrite.
+///
+/// If `branchless.maintenance.writeCommitGraph` is set, regenerate Git's
+/// commit-graph file, so that plain Git operations like `git log` and `git
+/// merge-base` stay fast. If there are at least
+/// `branchless.maintenance.keepRefCompactionThreshold` references keeping
+/// individual commits alive, compact them into a single reference.
+/// Additionally, warn if the repository's reachability bitmaps have gone
+/// stale.
+#[instrument]
+pub fn run_maintenance(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+) -> eyre::Result<()> {
+    if get_maintenance_write_commit_graph(repo)? {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: writing commit-graph"
+        )?;
+        let GitRunResult {
+            exit_code,
+            stdout: _,
+            stderr,
+        } = git_run_info.run_silent(
+            repo,
+            None,
+            &["commit-graph", "write", "--reachable", "--split"],
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                stdin: None,
+            },
+        )?;
+        if exit_code != 0 {
+            writeln!(
+                effects.get_error_stream(),
+                "branchless: failed to write commit-graph: {}",
+                String::from_utf8_lossy(&stderr).trim_end()
+            )?;
+        }
+    }
+
+    let compaction_threshold = get_maintenance_keep_ref_compaction_threshold(repo)?;
+    if compaction_threshold > 0 && get_keep_references(repo)?.len() >= compaction_threshold {
+        let num_references = compact_references(repo)?;
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: compacted {} into {}",
+            Pluralize {
+                determiner: None,
+                amount: num_references,
+                unit: ("reference", "references"),
+            },
+            KEEP_REF_NAME,
+        )?;
+    }
+
+    if has_stale_bitmaps(repo)? {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: reachability bitmaps are out of date; run `git repack -a -d -b` to regenerate them"
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Run `run_maintenance` if the number of rewritten commits is at least
+/// `branchless.maintenance.rewriteThreshold`.
+#[instrument]
+pub fn run_post_rewrite_maintenance(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    num_rewritten_commits: usize,
+) -> eyre::Result<()> {
+    if num_rewritten_commits >= get_maintenance_rewrite_threshold(repo)? {
+        run_maintenance(effects, git_run_info, repo)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,321 @@
+//! Localized messages for user-facing output.
+//!
+//! Messages are written in [Fluent](https://projectfluent.org/) syntax and
+//! stored in `locales/<locale>/git-branchless.ftl`. The `en-US` catalog is the
+//! source of truth, and is used as a fallback for any message which is missing
+//! from the user's locale. To add a translation, add a catalog for the locale
+//! and register it in [`BUNDLED_LOCALES`].
+//!
+//! Commands render messages through the [`Messages`] attached to their
+//! [`Effects`](super::effects::Effects), rather than hard-coding English
+//! strings, so that the wording of messages can be tested in one place.
+//!
+//! Messages are moved into the catalog one area at a time. It currently covers
+//! `git hide` and `git unhide`, checking out commits, and the progress, merge
+//! conflict and failure output of rebases, which is shared by the commands
+//! that rewrite commits. Other output is still written in English directly.
+
+use std::sync::Arc;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use fluent_langneg::{negotiate_languages, NegotiationStrategy};
+use itertools::Itertools;
+use once_cell::sync::OnceCell;
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+pub use fluent_bundle::FluentValue;
+
+/// The locale which messages are originally written in, which is used as a
+/// fallback for messages which haven't been translated.
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// The message catalogs compiled into the binary, as pairs of locale and
+/// Fluent source.
+pub const BUNDLED_LOCALES: &[(&str, &str)] = &[(
+    DEFAULT_LOCALE,
+    include_str!("../../locales/en-US/git-branchless.ftl"),
+)];
+
+/// Message catalogs to render user-facing output with, in order of
+/// preference.
+#[derive(Clone)]
+pub struct Messages {
+    bundles: Arc<Vec<FluentBundle<FluentResource>>>,
+}
+
+impl std::fmt::Debug for Messages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "<Messages locales={:?}>",
+            self.get_locales()
+                .iter()
+                .map(|locale| locale.to_string())
+                .collect_vec()
+        )
+    }
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self::new(&[]).expect("The bundled message catalogs should be valid")
+    }
+}
+
+impl Messages {
+    /// Load the bundled catalogs which best match the given preferred locales,
+    /// followed by the catalog for [`DEFAULT_LOCALE`].
+    pub fn new(preferred_locales: &[LanguageIdentifier]) -> eyre::Result<Self> {
+        Self::from_sources(preferred_locales, BUNDLED_LOCALES)
+    }
+
+    /// Like [`Messages::new`], but load the catalogs from the given pairs of
+    /// locale and Fluent source instead of the bundled catalogs.
+    pub fn from_sources(
+        preferred_locales: &[LanguageIdentifier],
+        sources: &[(&str, &str)],
+    ) -> eyre::Result<Self> {
+        let available_locales: Vec<LanguageIdentifier> = sources
+            .iter()
+            .map(|(locale, _source)| locale.parse())
+            .try_collect()?;
+        let default_locale: LanguageIdentifier = DEFAULT_LOCALE.parse()?;
+        let negotiated_locales = negotiate_languages(
+            preferred_locales,
+            &available_locales,
+            Some(&default_locale),
+            NegotiationStrategy::Filtering,
+        );
+
+        let mut bundles = Vec::new();
+        for locale in negotiated_locales {
+            let source = match available_locales
+                .iter()
+                .position(|available_locale| available_locale == locale)
+            {
+                Some(index) => sources[index].1,
+                None => eyre::bail!("No message catalog for locale: {}", locale),
+            };
+            let resource =
+                FluentResource::try_new(source.to_string()).map_err(|(_resource, errors)| {
+                    eyre::eyre!(
+                        "Could not parse message catalog for locale {}: {:?}",
+                        locale,
+                        errors
+                    )
+                })?;
+
+            let mut bundle = FluentBundle::new_concurrent(vec![locale.clone()]);
+            // Unicode isolation marks around arguments would end up in the
+            // terminal output, so don't insert them.
+            bundle.set_use_isolating(false);
+            bundle.add_resource(resource).map_err(|errors| {
+                eyre::eyre!(
+                    "Could not load message catalog for locale {}: {:?}",
+                    locale,
+                    errors
+                )
+            })?;
+            bundles.push(bundle);
+        }
+
+        Ok(Self {
+            bundles: Arc::new(bundles),
+        })
+    }
+
+    /// Load the bundled catalogs for the locale configured in the
+    /// environment. See [`get_locale_from_env`].
+    pub fn detect() -> Self {
+        static MESSAGES: OnceCell<Messages> = OnceCell::new();
+        MESSAGES
+            .get_or_init(|| {
+                let preferred_locales = get_locale_from_env().into_iter().collect_vec();
+                Self::new(&preferred_locales).unwrap_or_else(|err| {
+                    warn!(?err, "Could not load message catalogs");
+                    Self::default()
+                })
+            })
+            .clone()
+    }
+
+    /// The locales of the loaded catalogs, in order of preference.
+    pub fn get_locales(&self) -> Vec<LanguageIdentifier> {
+        self.bundles
+            .iter()
+            .flat_map(|bundle| bundle.locales.first().cloned())
+            .collect()
+    }
+
+    /// Whether a message with the given ID is defined in any of the loaded
+    /// catalogs.
+    pub fn has_message(&self, id: &str) -> bool {
+        self.bundles.iter().any(|bundle| bundle.has_message(id))
+    }
+
+    /// Render the message with the given ID and arguments, using the most
+    /// preferred catalog which defines it. If no catalog defines the message,
+    /// then the ID itself is returned, so that output is never lost.
+    pub fn format(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let fluent_args: FluentArgs = args.iter().cloned().collect();
+        for bundle in self.bundles.iter() {
+            let pattern = match bundle.get_message(id).and_then(|message| message.value()) {
+                Some(pattern) => pattern,
+                None => continue,
+            };
+            let mut errors = Vec::new();
+            let message = bundle.format_pattern(pattern, Some(&fluent_args), &mut errors);
+            if !errors.is_empty() {
+                warn!(?id, ?errors, "Errors while formatting message");
+            }
+            return message.into_owned();
+        }
+
+        warn!(?id, "Message not found in any catalog");
+        id.to_string()
+    }
+}
+
+/// Determine the user's preferred locale from the first non-empty value of
+/// the `LC_ALL`, `LC_MESSAGES` and `LANG` environment variables, as `gettext`
+/// does.
+pub fn get_locale_from_env() -> Option<LanguageIdentifier> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| parse_posix_locale(&value))
+}
+
+/// Convert a POSIX locale name such as `pt_BR.UTF-8` into a language
+/// identifier. Returns `None` for the `C` and `POSIX` locales, which don't
+/// specify a language.
+fn parse_posix_locale(locale: &str) -> Option<LanguageIdentifier> {
+    let locale = locale.split(['.', '@']).next()?;
+    match locale {
+        "" | "C" | "POSIX" => None,
+        locale => locale.replace('_', "-").parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_catalogs_are_consistent() -> eyre::Result<()> {
+        let default_resource = FluentResource::try_new(BUNDLED_LOCALES[0].1.to_string())
+            .map_err(|(_resource, errors)| eyre::eyre!("{:?}", errors))?;
+        let default_ids = default_resource
+            .entries()
+            .filter_map(|entry| match entry {
+                fluent_syntax::ast::Entry::Message(message) => Some(message.id.name),
+                _ => None,
+            })
+            .collect_vec();
+        assert!(!default_ids.is_empty());
+
+        for (locale, _source) in BUNDLED_LOCALES {
+            let messages = Messages::new(&[locale.parse()?])?;
+            assert_eq!(messages.get_locales()[0].to_string(), *locale);
+            for id in &default_ids {
+                assert!(messages.has_message(id), "{} is missing {}", locale, id);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_messages() -> eyre::Result<()> {
+        let messages = Messages::default();
+        insta::assert_snapshot!(
+            messages.format("hide-undo-hint", &[("count", 1.into())]),
+            @"To unhide this 1 commit, run: git undo"
+        );
+        insta::assert_snapshot!(
+            messages.format(
+                "hide-undo-hint-with-branches",
+                &[("count", 2.into()), ("branch-count", 1.into())]
+            ),
+            @"To unhide these 2 commits and restore 1 branch, run: git undo"
+        );
+        insta::assert_snapshot!(
+            messages.format("hide-hid-commit", &[("commit", "abc123 foo".into())]),
+            @"Hid commit: abc123 foo"
+        );
+        insta::assert_snapshot!(
+            messages.format(
+                "rebase-folded",
+                &[
+                    ("progress", "[2/3]".into()),
+                    ("kind", "squash".into()),
+                    ("commit", "abc123 foo".into()),
+                    ("target", "def456 bar".into()),
+                ]
+            ),
+            @"[2/3] Squashed abc123 foo into: def456 bar"
+        );
+        insta::assert_snapshot!(
+            messages.format(
+                "merge-conflict-commit",
+                &[
+                    ("bullet", "-".into()),
+                    ("count", 1.into()),
+                    ("commit", "abc123 foo".into()),
+                ]
+            ),
+            @"- (1 conflicting file) abc123 foo"
+        );
+        insta::assert_snapshot!(messages.format("rebase-uncommitted-changes", &[]), @r###"
+        This operation would modify the working copy, but you have uncommitted changes
+        in your working copy which might be overwritten as a result.
+        Commit your changes and then try again.
+        "###);
+        insta::assert_snapshot!(messages.format("no-such-message", &[]), @"no-such-message");
+        Ok(())
+    }
+
+    #[test]
+    fn test_fallback_to_default_locale() -> eyre::Result<()> {
+        let messages = Messages::from_sources(
+            &["fr-FR".parse()?],
+            &[
+                (DEFAULT_LOCALE, "greeting = Hello\nfarewell = Goodbye\n"),
+                ("fr", "greeting = Bonjour\n"),
+            ],
+        )?;
+        assert_eq!(
+            messages
+                .get_locales()
+                .iter()
+                .map(|l| l.to_string())
+                .collect_vec(),
+            vec!["fr", "en-US"]
+        );
+        assert_eq!(messages.format("greeting", &[]), "Bonjour");
+        assert_eq!(messages.format("farewell", &[]), "Goodbye");
+
+        let messages = Messages::from_sources(
+            &["de-DE".parse()?],
+            &[
+                (DEFAULT_LOCALE, "greeting = Hello\n"),
+                ("fr", "greeting = Bonjour\n"),
+            ],
+        )?;
+        assert_eq!(messages.format("greeting", &[]), "Hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_posix_locale() {
+        let parse = |locale| parse_posix_locale(locale).map(|locale| locale.to_string());
+        assert_eq!(parse("pt_BR.UTF-8"), Some("pt-BR".to_string()));
+        assert_eq!(parse("de_DE@euro"), Some("de-DE".to_string()));
+        assert_eq!(parse("fr"), Some("fr".to_string()));
+        assert_eq!(parse("C.UTF-8"), None);
+        assert_eq!(parse("POSIX"), None);
+        assert_eq!(parse(""), None);
+    }
+}
@@ -0,0 +1,255 @@
+//! Arbitrary key-value metadata attached to commits, such as the associated
+//! code review or the status of a test run.
+//!
+//! Metadata is stored for the commit it was set on. When it's read, entries set
+//! on earlier versions of a commit are carried forward to the newest versions
+//! of that commit, using the rewrite mapping recorded in the event log. This
+//! means that metadata follows a commit when it's amended or rebased. If the
+//! same key was set on several versions of a commit, then the most recently-set
+//! value takes precedence.
+
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::rewrite::RewriteMap;
+use crate::git::{MaybeZeroOid, NonZeroOid};
+
+/// The metadata for a single commit, ordered by key.
+pub type CommitMetadata = BTreeMap<String, String>;
+
+/// Stores commit metadata on disk.
+pub struct CommitMetadataDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for CommitMetadataDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<CommitMetadataDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS commit_metadata (
+    commit_oid TEXT NOT NULL,
+    key TEXT NOT NULL,
+
+    -- `NULL` if the key was unset for this commit, which hides any value set
+    -- for an earlier version of the commit.
+    value TEXT,
+
+    timestamp REAL NOT NULL,
+    PRIMARY KEY (commit_oid, key)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `commit_metadata` table")?;
+    Ok(())
+}
+
+struct Row {
+    commit_oid: NonZeroOid,
+    key: String,
+    value: Option<String>,
+}
+
+impl<'conn> CommitMetadataDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(CommitMetadataDb { conn })
+    }
+
+    /// Set the value for the given key on the given commit. If `value` is
+    /// `None`, then the key is unset, including for any earlier versions of the
+    /// commit.
+    #[instrument]
+    pub fn set(
+        &self,
+        now: SystemTime,
+        commit_oid: NonZeroOid,
+        key: &str,
+        value: Option<&str>,
+    ) -> eyre::Result<()> {
+        let timestamp = now.duration_since(UNIX_EPOCH)?.as_secs_f64();
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO commit_metadata (commit_oid, key, value, timestamp)
+VALUES (:commit_oid, :key, :value, :timestamp)
+",
+                rusqlite::named_params! {
+                    ":commit_oid": commit_oid.to_string(),
+                    ":key": key,
+                    ":value": value,
+                    ":timestamp": timestamp,
+                },
+            )
+            .wrap_err("Setting commit metadata")?;
+        Ok(())
+    }
+
+    fn get_rows(&self) -> eyre::Result<Vec<Row>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT commit_oid, key, value
+FROM commit_metadata
+ORDER BY timestamp ASC, rowid ASC
+",
+        )?;
+        let rows: rusqlite::Result<Vec<(String, String, Option<String>)>> = stmt
+            .query_map(rusqlite::params![], |row| {
+                Ok((row.get("commit_oid")?, row.get("key")?, row.get("value")?))
+            })?
+            .collect();
+        rows?
+            .into_iter()
+            .map(|(commit_oid, key, value)| {
+                Ok(Row {
+                    commit_oid: NonZeroOid::from_str(&commit_oid)?,
+                    key,
+                    value,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the metadata for every commit which has any, after carrying
+    /// metadata forward to the newest versions of rewritten commits.
+    #[instrument]
+    pub fn get_all(
+        &self,
+        rewrite_map: &RewriteMap,
+    ) -> eyre::Result<HashMap<NonZeroOid, CommitMetadata>> {
+        let mut result: HashMap<NonZeroOid, BTreeMap<String, Option<String>>> = HashMap::new();
+        for Row {
+            commit_oid,
+            key,
+            value,
+        } in self.get_rows()?
+        {
+            let successors = rewrite_map.find_latest_successors(commit_oid);
+            let target_oids = if successors.is_empty() {
+                vec![commit_oid]
+            } else {
+                successors
+                    .into_iter()
+                    .filter_map(|successor| match successor {
+                        MaybeZeroOid::NonZero(successor_oid) => Some(successor_oid),
+                        MaybeZeroOid::Zero => None,
+                    })
+                    .collect()
+            };
+
+            // Rows are ordered from least to most recently set, so later rows
+            // overwrite earlier ones.
+            for target_oid in target_oids {
+                result
+                    .entry(target_oid)
+                    .or_default()
+                    .insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(result
+            .into_iter()
+            .map(|(oid, metadata)| {
+                let metadata: CommitMetadata = metadata
+                    .into_iter()
+                    .filter_map(|(key, value)| value.map(|value| (key, value)))
+                    .collect();
+                (oid, metadata)
+            })
+            .filter(|(_oid, metadata)| !metadata.is_empty())
+            .collect())
+    }
+
+    /// Get the metadata for the given commit, including metadata carried
+    /// forward from earlier versions of the commit.
+    #[instrument]
+    pub fn get(&self, rewrite_map: &RewriteMap, oid: NonZeroOid) -> eyre::Result<CommitMetadata> {
+        let mut all_metadata = self.get_all(rewrite_map)?;
+        Ok(all_metadata.remove(&oid).unwrap_or_default())
+    }
+
+    /// Get the time at which metadata was last set or unset for any commit,
+    /// or `None` if no metadata has been set.
+    #[instrument]
+    pub fn get_last_modified(&self) -> eyre::Result<Option<SystemTime>> {
+        let timestamp: Option<f64> = self.conn.query_row(
+            "SELECT MAX(timestamp) FROM commit_metadata",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        Ok(timestamp.map(|timestamp| UNIX_EPOCH + Duration::from_secs_f64(timestamp.max(0.0))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::core::effects::Effects;
+    use crate::core::eventlog::{EventLogDb, EventReplayer};
+    use crate::core::formatting::Glyphs;
+    use crate::testing::make_git;
+
+    use super::*;
+
+    #[test]
+    fn test_metadata_follows_rewrites() -> eyre::Result<()> {
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let git = make_git()?;
+        git.init_repo()?;
+        let old_oid = git.commit_file("test1", 1)?;
+
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let metadata_db = CommitMetadataDb::new(&conn)?;
+        let now = SystemTime::UNIX_EPOCH;
+        metadata_db.set(now, old_oid, "review", Some("PR #123"))?;
+        metadata_db.set(now, old_oid, "status", Some("passed"))?;
+
+        git.run(&["commit", "--amend", "-m", "amended"])?;
+        let new_oid = repo.get_head_info()?.oid.unwrap();
+        metadata_db.set(
+            now + Duration::from_secs(1),
+            new_oid,
+            "status",
+            Some("pending"),
+        )?;
+
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let rewrite_map = RewriteMap::from_event_replayer(&event_replayer, event_cursor);
+
+        assert_eq!(
+            metadata_db.get(&rewrite_map, new_oid)?,
+            CommitMetadata::from([
+                ("review".to_string(), "PR #123".to_string()),
+                ("status".to_string(), "pending".to_string()),
+            ])
+        );
+        assert_eq!(
+            metadata_db.get(&rewrite_map, old_oid)?,
+            CommitMetadata::new()
+        );
+
+        metadata_db.set(now + Duration::from_secs(2), new_oid, "review", None)?;
+        assert_eq!(
+            metadata_db.get(&rewrite_map, new_oid)?,
+            CommitMetadata::from([("status".to_string(), "pending".to_string())])
+        );
+
+        Ok(())
+    }
+}
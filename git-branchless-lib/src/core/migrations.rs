@@ -0,0 +1,346 @@
+//! Versioned schema migrations for the database stored in `.git/branchless`.
+//!
+//! Each table is created by the module which uses it, with the latest version
+//! of its schema. Migrations upgrade tables which were created by older
+//! versions of git-branchless. A migration has to be a no-op if the tables it
+//! affects don't exist yet, since they'll be created with the latest schema
+//! when they're first used.
+//!
+//! The version of the database is the highest version of all the migrations
+//! recorded in the `schema_migrations` table. If it's higher than the latest
+//! migration known to this version of git-branchless, then the database was
+//! written by a newer version, and it's not safe to use it.
+
+use std::time::SystemTime;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::error::Error;
+
+/// A change to the database schema.
+pub struct Migration {
+    /// The schema version after applying this migration. Versions start at 1
+    /// and increase by 1 for each migration.
+    pub version: i64,
+
+    /// A short description of the change.
+    pub description: &'static str,
+
+    apply: fn(&rusqlite::Connection) -> eyre::Result<()>,
+}
+
+impl std::fmt::Debug for Migration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<Migration {}: {}>", self.version, self.description)
+    }
+}
+
+/// All migrations, in the order that they have to be applied.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Add `provenance` column to `event_transactions`",
+        apply: add_event_transactions_provenance,
+    },
+    Migration {
+        version: 2,
+        description: "Add `verification_key` column to `signature_status`",
+        apply: add_signature_status_verification_key,
+    },
+];
+
+/// The schema version of a database with all migrations applied.
+pub fn get_latest_schema_version() -> i64 {
+    MIGRATIONS
+        .last()
+        .map(|migration| migration.version)
+        .unwrap_or_default()
+}
+
+fn has_table(conn: &rusqlite::Connection, table: &str) -> eyre::Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = :table",
+        rusqlite::named_params! {":table": table},
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn has_column(conn: &rusqlite::Connection, table: &str, column: &str) -> eyre::Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(:table) WHERE name = :column",
+        rusqlite::named_params! {":table": table, ":column": column},
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn add_event_transactions_provenance(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    // Some databases may have had the column added before migrations were
+    // versioned.
+    if has_table(conn, "event_transactions")?
+        && !has_column(conn, "event_transactions", "provenance")?
+    {
+        conn.execute(
+            "ALTER TABLE event_transactions ADD COLUMN provenance TEXT",
+            rusqlite::params![],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_signature_status_verification_key(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    // Existing rows get an empty key, which never matches the current one, so
+    // those commits are verified again.
+    if has_table(conn, "signature_status")?
+        && !has_column(conn, "signature_status", "verification_key")?
+    {
+        conn.execute(
+            "ALTER TABLE signature_status ADD COLUMN verification_key TEXT NOT NULL DEFAULT ''",
+            rusqlite::params![],
+        )?;
+    }
+    Ok(())
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INTEGER NOT NULL PRIMARY KEY,
+    description TEXT NOT NULL,
+    applied_at REAL NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `schema_migrations` table")?;
+    Ok(())
+}
+
+/// Get the current schema version of the database, which is 0 if no
+/// migrations have been applied.
+#[instrument]
+pub fn get_schema_version(conn: &rusqlite::Connection) -> eyre::Result<i64> {
+    if !has_table(conn, "schema_migrations")? {
+        return Ok(0);
+    }
+    let version: Option<i64> = conn
+        .query_row(
+            "SELECT MAX(version) FROM schema_migrations",
+            rusqlite::params![],
+            |row| row.get(0),
+        )
+        .wrap_err("Querying schema version")?;
+    Ok(version.unwrap_or_default())
+}
+
+/// The state of the database schema relative to this version of
+/// git-branchless.
+#[derive(Debug)]
+pub enum SchemaStatus {
+    /// All known migrations have been applied.
+    UpToDate {
+        /// The current schema version.
+        version: i64,
+    },
+
+    /// Some migrations haven't been applied yet.
+    Outdated {
+        /// The current schema version.
+        version: i64,
+
+        /// The migrations which still have to be applied.
+        pending_migrations: Vec<&'static Migration>,
+    },
+
+    /// The database was written by a newer version of git-branchless.
+    TooNew {
+        /// The current schema version.
+        version: i64,
+
+        /// The latest schema version supported by this version of
+        /// git-branchless.
+        latest_version: i64,
+    },
+}
+
+/// Determine which migrations need to be applied to the database, without
+/// applying them.
+///
+/// An empty database is considered to be up to date, since its tables will be
+/// created with the latest schema.
+#[instrument]
+pub fn check_schema(conn: &rusqlite::Connection) -> eyre::Result<SchemaStatus> {
+    let latest_version = get_latest_schema_version();
+    let num_tables: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+        rusqlite::params![],
+        |row| row.get(0),
+    )?;
+    if num_tables == 0 {
+        return Ok(SchemaStatus::UpToDate {
+            version: latest_version,
+        });
+    }
+
+    let version = get_schema_version(conn)?;
+    let status = if version > latest_version {
+        SchemaStatus::TooNew {
+            version,
+            latest_version,
+        }
+    } else {
+        let pending_migrations: Vec<_> = MIGRATIONS
+            .iter()
+            .filter(|migration| migration.version > version)
+            .collect();
+        if pending_migrations.is_empty() {
+            SchemaStatus::UpToDate { version }
+        } else {
+            SchemaStatus::Outdated {
+                version,
+                pending_migrations,
+            }
+        }
+    };
+    Ok(status)
+}
+
+/// Apply any pending migrations to the database.
+///
+/// Returns an error if the database was written by a newer version of
+/// git-branchless, since this version may not be able to use it correctly.
+#[instrument]
+pub fn run_migrations(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    let latest_version = get_latest_schema_version();
+    if get_schema_version(conn)? == latest_version {
+        return Ok(());
+    }
+
+    // Take the write lock before checking the version again, in case another
+    // process is migrating the database at the same time.
+    let tx = rusqlite::Transaction::new_unchecked(conn, rusqlite::TransactionBehavior::Immediate)?;
+    init_tables(&tx)?;
+    let version = get_schema_version(&tx)?;
+    if version > latest_version {
+        return Err(Error::User {
+            message: format!(
+                "The git-branchless database has schema version {}, but this version of git-branchless only supports up to version {}. It was probably written by a newer version of git-branchless, which should be used instead.",
+                version,
+                latest_version
+            ),
+        }
+        .into());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("Calculating migration timestamp")?
+        .as_secs_f64();
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version)
+    {
+        (migration.apply)(&tx).wrap_err_with(|| {
+            format!(
+                "Applying database migration {}: {}",
+                migration.version, migration.description
+            )
+        })?;
+        tx.execute(
+            "
+INSERT INTO schema_migrations
+(version, description, applied_at)
+VALUES
+(:version, :description, :applied_at)
+",
+            rusqlite::named_params! {
+                ":version": migration.version,
+                ":description": migration.description,
+                ":applied_at": now,
+            },
+        )
+        .wrap_err("Recording database migration")?;
+    }
+    tx.commit().wrap_err("Committing database migrations")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migration_versions_are_sequential() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i64::try_from(i).unwrap() + 1);
+        }
+    }
+
+    #[test]
+    fn test_run_migrations() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        assert!(matches!(
+            check_schema(&conn)?,
+            SchemaStatus::UpToDate { version } if version == get_latest_schema_version()
+        ));
+
+        conn.execute(
+            "
+CREATE TABLE event_transactions (
+    timestamp REAL NOT NULL,
+    event_tx_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    message TEXT
+)
+",
+            rusqlite::params![],
+        )?;
+        conn.execute(
+            "
+CREATE TABLE signature_status (
+    commit_oid TEXT NOT NULL PRIMARY KEY,
+    status TEXT NOT NULL
+)
+",
+            rusqlite::params![],
+        )?;
+        assert!(matches!(
+            check_schema(&conn)?,
+            SchemaStatus::Outdated { version: 0, .. }
+        ));
+
+        run_migrations(&conn)?;
+        assert!(has_column(&conn, "event_transactions", "provenance")?);
+        assert!(has_column(&conn, "signature_status", "verification_key")?);
+        assert!(matches!(
+            check_schema(&conn)?,
+            SchemaStatus::UpToDate { version } if version == get_latest_schema_version()
+        ));
+
+        // Running the migrations again shouldn't do anything.
+        run_migrations(&conn)?;
+        assert_eq!(get_schema_version(&conn)?, get_latest_schema_version());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_migrations_newer_database() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        run_migrations(&conn)?;
+        conn.execute(
+            "INSERT INTO schema_migrations (version, description, applied_at) VALUES (1000, 'from the future', 0.0)",
+            rusqlite::params![],
+        )?;
+
+        assert!(matches!(
+            check_schema(&conn)?,
+            SchemaStatus::TooNew { version: 1000, .. }
+        ));
+        assert!(run_migrations(&conn).is_err());
+        Ok(())
+    }
+}
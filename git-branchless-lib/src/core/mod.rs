@@ -1,13 +1,24 @@
 //! Core algorithms and data structures.
 
+pub mod applied_patches;
 pub mod check_out;
+pub mod ci_status;
 pub mod config;
 pub mod dag;
 pub mod effects;
 pub mod eventlog;
 pub mod formatting;
 pub mod gc;
+pub mod i18n;
+pub mod metadata;
+pub mod migrations;
 pub mod node_descriptors;
+pub mod patch_ids;
 pub mod repo_ext;
 pub mod rewrite;
+pub mod signature_status;
+pub mod smartlog_cache;
+pub mod stack_branches;
 pub mod task;
+pub mod topics;
+pub mod user_hooks;
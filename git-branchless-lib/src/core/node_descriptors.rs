@@ -9,24 +9,29 @@ use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
 use bstr::{ByteSlice, ByteVec};
-use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use lazy_static::lazy_static;
 use regex::Regex;
 use tracing::instrument;
 
+use crate::core::ci_status::CiStatus;
 use crate::core::config::{
-    get_commit_descriptors_branches, get_commit_descriptors_differential_revision,
-    get_commit_descriptors_relative_time,
+    get_commit_descriptors_branches, get_commit_descriptors_ci_status,
+    get_commit_descriptors_differential_revision, get_commit_descriptors_metadata,
+    get_commit_descriptors_relative_time, get_commit_descriptors_signature,
 };
+use crate::core::metadata::CommitMetadata;
+use crate::core::signature_status::SignatureStatus;
 use crate::git::{
-    CategorizedReferenceName, Commit, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo,
+    CategorizedReferenceName, Commit, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
+    ResolvedReferenceInfo, Worktree,
 };
 
 use super::eventlog::{Event, EventCursor, EventReplayer};
-use super::formatting::{Glyphs, StyledStringBuilder};
+use super::formatting::{Glyphs, Pluralize, StyledStringBuilder};
 use super::repo_ext::RepoReferencesSnapshot;
 use super::rewrite::find_rewrite_target;
+use super::topics::{StackTopic, TopicSource, STACK_NAME_METADATA_KEY};
 
 /// An object which can be rendered in the smartlog.
 #[derive(Clone, Debug)]
@@ -189,12 +194,12 @@ impl NodeDescriptor for CommitOidDescriptor {
     #[instrument]
     fn describe_node(
         &mut self,
-        _glyphs: &Glyphs,
+        glyphs: &Glyphs,
         object: &NodeObject,
     ) -> eyre::Result<Option<StyledString>> {
         let oid = object.get_short_oid()?;
         let oid = if self.use_color {
-            StyledString::styled(oid, BaseColor::Yellow.dark())
+            StyledString::styled(oid, glyphs.theme.commit_oid)
         } else {
             StyledString::plain(oid)
         };
@@ -253,7 +258,7 @@ impl<'a> ObsolescenceExplanationDescriptor<'a> {
 impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
     fn describe_node(
         &mut self,
-        _glyphs: &Glyphs,
+        glyphs: &Glyphs,
         object: &NodeObject,
     ) -> eyre::Result<Option<StyledString>> {
         let event = self
@@ -272,14 +277,14 @@ impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
                 rewrite_target.map(|rewritten_oid| {
                     StyledString::styled(
                         format!("(rewritten as {})", &rewritten_oid.to_string()[..8]),
-                        BaseColor::Black.light(),
+                        glyphs.theme.muted,
                     )
                 })
             }
 
             Event::ObsoleteEvent { .. } => Some(StyledString::styled(
                 "(manually hidden)",
-                BaseColor::Black.light(),
+                glyphs.theme.muted,
             )),
 
             Event::RefUpdateEvent { .. }
@@ -371,13 +376,336 @@ impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
             branch_names.sort_unstable();
             let result = StyledString::styled(
                 format!("({})", branch_names.join(", ")),
-                BaseColor::Green.light(),
+                glyphs.theme.branch,
             );
             Ok(Some(result))
         }
     }
 }
 
+/// Display the names of other worktrees which have a given commit checked out.
+///
+/// The current worktree is not included, since its checked-out commit is
+/// already marked in the smartlog.
+#[derive(Debug)]
+pub struct WorktreesDescriptor {
+    oid_to_worktree_names: HashMap<NonZeroOid, Vec<String>>,
+}
+
+impl WorktreesDescriptor {
+    /// Constructor.
+    pub fn new(worktrees: &[Worktree]) -> eyre::Result<Self> {
+        let mut oid_to_worktree_names: HashMap<NonZeroOid, Vec<String>> = HashMap::new();
+        for worktree in worktrees {
+            if worktree.is_current {
+                continue;
+            }
+            if let Some(head_oid) = worktree.head_oid {
+                oid_to_worktree_names
+                    .entry(head_oid)
+                    .or_default()
+                    .push(worktree.name.clone());
+            }
+        }
+        for worktree_names in oid_to_worktree_names.values_mut() {
+            worktree_names.sort_unstable();
+        }
+        Ok(WorktreesDescriptor {
+            oid_to_worktree_names,
+        })
+    }
+}
+
+impl NodeDescriptor for WorktreesDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let worktree_names = match self.oid_to_worktree_names.get(&object.get_oid()) {
+            Some(worktree_names) => worktree_names,
+            None => return Ok(None),
+        };
+        let result = StyledString::styled(
+            format!("(worktree: {})", worktree_names.join(", ")),
+            glyphs.theme.annotation,
+        );
+        Ok(Some(result))
+    }
+}
+
+/// Display the inferred topic for a stack next to the stack's root commit.
+/// Topics named after branches aren't displayed, since the branch names are
+/// already rendered.
+#[derive(Debug)]
+pub struct StackTopicDescriptor {
+    root_oid_to_topic_name: HashMap<NonZeroOid, String>,
+}
+
+impl StackTopicDescriptor {
+    /// Constructor.
+    pub fn new(topics: &[StackTopic]) -> eyre::Result<Self> {
+        let root_oid_to_topic_name = topics
+            .iter()
+            .filter(|topic| match topic.source {
+                TopicSource::Branch => false,
+                TopicSource::Name | TopicSource::TicketId | TopicSource::PathPrefix => true,
+            })
+            .map(|topic| (topic.root_oid, topic.name.clone()))
+            .collect();
+        Ok(StackTopicDescriptor {
+            root_oid_to_topic_name,
+        })
+    }
+}
+
+impl NodeDescriptor for StackTopicDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let topic_name = match self.root_oid_to_topic_name.get(&object.get_oid()) {
+            Some(topic_name) => topic_name,
+            None => return Ok(None),
+        };
+        let result = StyledString::styled(format!("[topic: {}]", topic_name), glyphs.theme.topic);
+        Ok(Some(result))
+    }
+}
+
+/// Display the number of commits hidden above the root commit of a stack which
+/// was collapsed in the smartlog.
+#[derive(Debug)]
+pub struct CollapsedStackDescriptor {
+    num_hidden_commits: HashMap<NonZeroOid, usize>,
+}
+
+impl CollapsedStackDescriptor {
+    /// Constructor. `num_hidden_commits` maps the root commit of each collapsed
+    /// stack to the number of commits which were hidden from that stack.
+    pub fn new(num_hidden_commits: HashMap<NonZeroOid, usize>) -> eyre::Result<Self> {
+        Ok(CollapsedStackDescriptor { num_hidden_commits })
+    }
+}
+
+impl NodeDescriptor for CollapsedStackDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let num_hidden_commits = match self.num_hidden_commits.get(&object.get_oid()) {
+            Some(num_hidden_commits) => *num_hidden_commits,
+            None => return Ok(None),
+        };
+        let result = StyledString::styled(
+            format!(
+                "(+{})",
+                Pluralize {
+                    determiner: None,
+                    amount: num_hidden_commits,
+                    unit: ("commit", "commits"),
+                }
+            ),
+            glyphs.theme.muted,
+        );
+        Ok(Some(result))
+    }
+}
+
+/// Display how a commit would be affected by an operation run with
+/// `--dry-run`.
+#[derive(Debug)]
+pub struct DryRunDescriptor {
+    original_oids: HashMap<NonZeroOid, NonZeroOid>,
+    hidden_oids: HashSet<NonZeroOid>,
+}
+
+impl DryRunDescriptor {
+    /// Constructor. `rewritten_oids` maps each commit which would be rewritten
+    /// to the commit it would be rewritten as, and `hidden_oids` are the
+    /// commits which would be hidden.
+    pub fn new(
+        rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
+        hidden_oids: HashSet<NonZeroOid>,
+    ) -> eyre::Result<Self> {
+        let original_oids = rewritten_oids
+            .iter()
+            .filter_map(|(old_oid, new_oid)| match new_oid {
+                MaybeZeroOid::NonZero(new_oid) if new_oid != old_oid => Some((*new_oid, *old_oid)),
+                MaybeZeroOid::NonZero(_) | MaybeZeroOid::Zero => None,
+            })
+            .collect();
+        Ok(DryRunDescriptor {
+            original_oids,
+            hidden_oids,
+        })
+    }
+}
+
+impl NodeDescriptor for DryRunDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let oid = object.get_oid();
+        let result = if let Some(original_oid) = self.original_oids.get(&oid) {
+            Some(StyledString::styled(
+                format!("(rewritten from {})", &original_oid.to_string()[..7]),
+                glyphs.theme.warning,
+            ))
+        } else if self.hidden_oids.contains(&oid) {
+            Some(StyledString::styled(
+                "(would be hidden)",
+                glyphs.theme.warning,
+            ))
+        } else {
+            None
+        };
+        Ok(result)
+    }
+}
+
+/// Display the CI status of a given commit, as reported by the code forge.
+#[derive(Debug)]
+pub struct CiStatusDescriptor {
+    is_enabled: bool,
+    statuses: HashMap<NonZeroOid, CiStatus>,
+}
+
+impl CiStatusDescriptor {
+    /// Constructor.
+    pub fn new(repo: &Repo, statuses: HashMap<NonZeroOid, CiStatus>) -> eyre::Result<Self> {
+        let is_enabled = get_commit_descriptors_ci_status(repo)?;
+        Ok(CiStatusDescriptor {
+            is_enabled,
+            statuses,
+        })
+    }
+}
+
+impl NodeDescriptor for CiStatusDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+        let status = match self.statuses.get(&object.get_oid()) {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+        let description = format!("[ci: {}]", status);
+        let result = match status {
+            CiStatus::Unknown => return Ok(None),
+            CiStatus::Pending => StyledString::styled(description, glyphs.theme.warning),
+            CiStatus::Success => StyledString::styled(description, glyphs.theme.success),
+            CiStatus::Failure => StyledString::styled(description, glyphs.theme.error),
+            CiStatus::Other(_) => StyledString::plain(description),
+        };
+        Ok(Some(result))
+    }
+}
+
+/// Display whether the signature of a given commit is valid.
+#[derive(Debug)]
+pub struct SignatureStatusDescriptor {
+    is_enabled: bool,
+    statuses: HashMap<NonZeroOid, SignatureStatus>,
+}
+
+impl SignatureStatusDescriptor {
+    /// Constructor.
+    pub fn new(repo: &Repo, statuses: HashMap<NonZeroOid, SignatureStatus>) -> eyre::Result<Self> {
+        let is_enabled = get_commit_descriptors_signature(repo)?;
+        Ok(SignatureStatusDescriptor {
+            is_enabled,
+            statuses,
+        })
+    }
+}
+
+impl NodeDescriptor for SignatureStatusDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+        let status = match self.statuses.get(&object.get_oid()) {
+            Some(status) => status,
+            None => return Ok(None),
+        };
+        let description = format!("[sig: {}]", status);
+        let result = match status {
+            SignatureStatus::Unsigned => return Ok(None),
+            SignatureStatus::Good => StyledString::styled(description, glyphs.theme.success),
+            SignatureStatus::Bad => StyledString::styled(description, glyphs.theme.error),
+            SignatureStatus::Unknown => StyledString::styled(description, glyphs.theme.warning),
+        };
+        Ok(Some(result))
+    }
+}
+
+/// Display the metadata set with `git branchless meta` for a given commit.
+#[derive(Debug)]
+pub struct CommitMetadataDescriptor {
+    is_enabled: bool,
+    metadata: HashMap<NonZeroOid, CommitMetadata>,
+}
+
+impl CommitMetadataDescriptor {
+    /// Constructor.
+    pub fn new(repo: &Repo, metadata: HashMap<NonZeroOid, CommitMetadata>) -> eyre::Result<Self> {
+        let is_enabled = get_commit_descriptors_metadata(repo)?;
+        Ok(CommitMetadataDescriptor {
+            is_enabled,
+            metadata,
+        })
+    }
+}
+
+impl NodeDescriptor for CommitMetadataDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+        let metadata = match self.metadata.get(&object.get_oid()) {
+            Some(metadata) => metadata,
+            None => return Ok(None),
+        };
+        // The stack name is already rendered as the stack's topic.
+        let description = metadata
+            .iter()
+            .filter(|(key, _value)| key.as_str() != STACK_NAME_METADATA_KEY)
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if description.is_empty() {
+            return Ok(None);
+        }
+        let result = StyledString::styled(format!("[{}]", description), glyphs.theme.annotation);
+        Ok(Some(result))
+    }
+}
+
 /// Display the associated Phabricator revision for a given commit.
 #[derive(Debug)]
 pub struct DifferentialRevisionDescriptor<'a> {
@@ -417,7 +745,7 @@ impl<'a> NodeDescriptor for DifferentialRevisionDescriptor<'a> {
     #[instrument]
     fn describe_node(
         &mut self,
-        _glyphs: &Glyphs,
+        glyphs: &Glyphs,
         object: &NodeObject,
     ) -> eyre::Result<Option<StyledString>> {
         match self.redactor {
@@ -436,7 +764,7 @@ impl<'a> NodeDescriptor for DifferentialRevisionDescriptor<'a> {
             Some(diff_number) => diff_number,
             None => return Ok(None),
         };
-        let result = StyledString::styled(diff_number, BaseColor::Green.dark());
+        let result = StyledString::styled(diff_number, glyphs.theme.detail);
         Ok(Some(result))
     }
 }
@@ -500,7 +828,7 @@ impl NodeDescriptor for RelativeTimeDescriptor {
     #[instrument]
     fn describe_node(
         &mut self,
-        _glyphs: &Glyphs,
+        glyphs: &Glyphs,
         object: &NodeObject,
     ) -> eyre::Result<Option<StyledString>> {
         if !self.is_enabled {
@@ -512,7 +840,7 @@ impl NodeDescriptor for RelativeTimeDescriptor {
         };
 
         let description = Self::describe_time_delta(self.now, commit.get_time().to_system_time()?)?;
-        let result = StyledString::styled(description, BaseColor::Green.dark());
+        let result = StyledString::styled(description, glyphs.theme.detail);
         Ok(Some(result))
     }
 }
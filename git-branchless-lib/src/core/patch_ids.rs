@@ -0,0 +1,328 @@
+//! Cache the patch IDs of commits, which are used for duplicate commit
+//! detection.
+//!
+//! Computing a patch ID requires diffing the commit against its parent, which
+//! is slow when there are thousands of upstream commits to compare against.
+//! Since a commit can't change without changing its OID, a patch ID never
+//! goes stale for a given commit. The only invalidation necessary is when the
+//! way that patch IDs are computed changes, in which case
+//! [`PATCH_ID_CACHE_VERSION`] should be bumped.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use eyre::Context;
+use rayon::{prelude::*, ThreadPool};
+use tracing::{instrument, warn};
+
+use crate::core::effects::{Effects, OperationType};
+use crate::core::rewrite::RepoPool;
+use crate::git::{NonZeroOid, PatchId, Repo};
+
+/// The version of the patch ID computation. Cached entries with a different
+/// version are ignored and recomputed.
+pub const PATCH_ID_CACHE_VERSION: i64 = 1;
+
+/// Caches the patch IDs of commits on disk.
+pub struct PatchIdDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for PatchIdDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<PatchIdDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS patch_ids (
+    commit_oid TEXT NOT NULL PRIMARY KEY,
+    patch_id TEXT,
+    version INTEGER NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `patch_ids` table")?;
+    Ok(())
+}
+
+impl<'conn> PatchIdDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(PatchIdDb { conn })
+    }
+
+    /// Get the cached patch ID for the given commit. Returns `None` if there
+    /// is no up-to-date cache entry, and `Some(None)` if the commit is known
+    /// to not have a patch ID (such as for merge commits).
+    #[instrument]
+    pub fn get(&self, commit_oid: NonZeroOid) -> eyre::Result<Option<Option<PatchId>>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+SELECT patch_id
+FROM patch_ids
+WHERE commit_oid = :commit_oid AND version = :version
+",
+        )?;
+        let mut rows = stmt.query_map(
+            rusqlite::named_params! {
+                ":commit_oid": commit_oid.to_string(),
+                ":version": PATCH_ID_CACHE_VERSION,
+            },
+            |row| {
+                let patch_id: Option<String> = row.get("patch_id")?;
+                Ok(patch_id)
+            },
+        )?;
+        match rows.next() {
+            None => Ok(None),
+            Some(row) => {
+                let patch_id = match row? {
+                    None => None,
+                    Some(patch_id) => Some(PatchId::from_str(&patch_id)?),
+                };
+                Ok(Some(patch_id))
+            }
+        }
+    }
+
+    /// Cache the patch IDs for the given commits in a single transaction.
+    #[instrument]
+    pub fn set_many(&self, entries: &[(NonZeroOid, Option<PatchId>)]) -> eyre::Result<()> {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .wrap_err("Starting patch ID cache transaction")?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "
+INSERT OR REPLACE INTO patch_ids (commit_oid, patch_id, version)
+VALUES (:commit_oid, :patch_id, :version)
+",
+            )?;
+            for (commit_oid, patch_id) in entries {
+                stmt.execute(rusqlite::named_params! {
+                    ":commit_oid": commit_oid.to_string(),
+                    ":patch_id": patch_id.map(|patch_id| patch_id.to_string()),
+                    ":version": PATCH_ID_CACHE_VERSION,
+                })
+                .wrap_err("Caching patch ID")?;
+            }
+        }
+        tx.commit()
+            .wrap_err("Committing patch ID cache transaction")?;
+        Ok(())
+    }
+
+    /// Delete cache entries which were computed with a different version of
+    /// the patch ID computation.
+    #[instrument]
+    pub fn prune_stale(&self) -> eyre::Result<usize> {
+        let num_deleted = self
+            .conn
+            .execute(
+                "
+DELETE FROM patch_ids
+WHERE version <> :version
+",
+                rusqlite::named_params! {
+                    ":version": PATCH_ID_CACHE_VERSION,
+                },
+            )
+            .wrap_err("Pruning stale patch IDs")?;
+        Ok(num_deleted)
+    }
+
+    /// Delete cache entries for commits which are no longer reachable: the
+    /// given commits, whose references are being freed, and any commits which
+    /// are no longer in the repository at all.
+    #[instrument(skip(unreachable_commit_oids))]
+    pub fn prune_unreachable(
+        &self,
+        repo: &Repo,
+        unreachable_commit_oids: &[NonZeroOid],
+    ) -> eyre::Result<usize> {
+        let commit_oids: Vec<String> = {
+            let mut stmt = self.conn.prepare("SELECT commit_oid FROM patch_ids")?;
+            let rows = stmt.query_map(rusqlite::params![], |row| row.get("commit_oid"))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .wrap_err("Starting patch ID cache transaction")?;
+        let mut num_deleted = 0;
+        {
+            let mut stmt =
+                tx.prepare_cached("DELETE FROM patch_ids WHERE commit_oid = :commit_oid")?;
+            for commit_oid in commit_oids {
+                let is_reachable = match NonZeroOid::from_str(&commit_oid) {
+                    Ok(oid) => {
+                        !unreachable_commit_oids.contains(&oid) && repo.contains_object(oid)?
+                    }
+                    Err(_) => false,
+                };
+                if !is_reachable {
+                    num_deleted += stmt
+                        .execute(rusqlite::named_params! {
+                            ":commit_oid": commit_oid,
+                        })
+                        .wrap_err("Pruning unreachable patch ID")?;
+                }
+            }
+        }
+        tx.commit()
+            .wrap_err("Committing patch ID cache transaction")?;
+        Ok(num_deleted)
+    }
+}
+
+/// Get the patch IDs for the given commits.
+///
+/// Cached patch IDs are used where available, and the rest are computed in
+/// parallel and then written back to the cache. Commits which don't exist or
+/// don't have a patch ID are omitted from the result. If the cache can't be
+/// read or written, then a warning is logged and the patch IDs are computed
+/// directly, since the cache is only an optimization.
+#[instrument(skip(commit_oids))]
+pub fn get_patch_ids(
+    effects: &Effects,
+    pool: &ThreadPool,
+    repo_pool: &RepoPool,
+    repo: &Repo,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<HashMap<NonZeroOid, PatchId>> {
+    let conn = match repo.get_db_conn() {
+        Ok(conn) => Some(conn),
+        Err(err) => {
+            warn!(?err, "Could not open database for patch ID cache");
+            None
+        }
+    };
+    let patch_id_db = match conn.as_ref().map(PatchIdDb::new).transpose() {
+        Ok(patch_id_db) => patch_id_db,
+        Err(err) => {
+            warn!(?err, "Could not initialize patch ID cache");
+            None
+        }
+    };
+
+    let mut result = HashMap::new();
+    let mut missing_oids = Vec::new();
+    for commit_oid in commit_oids.iter().copied() {
+        let cached = match &patch_id_db {
+            None => None,
+            Some(patch_id_db) => match patch_id_db.get(commit_oid) {
+                Ok(cached) => cached,
+                Err(err) => {
+                    warn!(?err, ?commit_oid, "Could not read cached patch ID");
+                    None
+                }
+            },
+        };
+        match cached {
+            Some(Some(patch_id)) => {
+                result.insert(commit_oid, patch_id);
+            }
+            Some(None) => {}
+            None => missing_oids.push(commit_oid),
+        }
+    }
+
+    let (effects, progress) = effects.start_operation(OperationType::GetUpstreamPatchIds);
+    progress.notify_progress(0, missing_oids.len());
+    let computed: Vec<(NonZeroOid, Option<PatchId>)> = pool.install(|| {
+        missing_oids
+            .into_par_iter()
+            .map(
+                |commit_oid| -> eyre::Result<Option<(NonZeroOid, Option<PatchId>)>> {
+                    let repo = repo_pool.try_create()?;
+                    let commit = match repo.find_commit(commit_oid)? {
+                        Some(commit) => commit,
+                        None => return Ok(None),
+                    };
+                    let patch_id = repo.get_patch_id(&effects, &commit)?;
+                    Ok(Some((commit_oid, patch_id)))
+                },
+            )
+            .inspect(|_| progress.notify_progress_inc(1))
+            .filter_map(|result| result.transpose())
+            .collect::<eyre::Result<_>>()
+    })?;
+
+    if let Some(patch_id_db) = &patch_id_db {
+        if !computed.is_empty() {
+            if let Err(err) = patch_id_db.set_many(&computed) {
+                warn!(?err, "Could not cache patch IDs");
+            }
+        }
+    }
+
+    result.extend(
+        computed
+            .into_iter()
+            .filter_map(|(commit_oid, patch_id)| patch_id.map(|patch_id| (commit_oid, patch_id))),
+    );
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_patch_id_db_round_trip() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        let patch_id_db = PatchIdDb::new(&conn)?;
+
+        let commit_oid = NonZeroOid::from_str("62fc20d2a290daea0d52bdc2ed2ad4be6491010e")?;
+        let merge_oid = NonZeroOid::from_str("96d1c37a3d4363611c49f7e52186e189a04c531f")?;
+        let patch_id = PatchId::from_str("b2fd1ab7a5a0d8e1a4bdc7c8c4b6b0b0f1e7d2a9")?;
+        assert_eq!(patch_id_db.get(commit_oid)?, None);
+
+        patch_id_db.set_many(&[(commit_oid, Some(patch_id)), (merge_oid, None)])?;
+        assert_eq!(patch_id_db.get(commit_oid)?, Some(Some(patch_id)));
+        assert_eq!(patch_id_db.get(merge_oid)?, Some(None));
+
+        conn.execute(
+            "UPDATE patch_ids SET version = version + 1 WHERE commit_oid = :commit_oid",
+            rusqlite::named_params! {
+                ":commit_oid": commit_oid.to_string(),
+            },
+        )?;
+        assert_eq!(patch_id_db.get(commit_oid)?, None);
+        assert_eq!(patch_id_db.prune_stale()?, 1);
+        assert_eq!(patch_id_db.get(merge_oid)?, Some(None));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_id_db_prune_unreachable() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let missing_oid = NonZeroOid::from_str("0123456789012345678901234567890123456789")?;
+
+        let repo = git.get_repo()?;
+        let conn = rusqlite::Connection::open_in_memory()?;
+        let patch_id_db = PatchIdDb::new(&conn)?;
+        patch_id_db.set_many(&[(test1_oid, None), (test2_oid, None), (missing_oid, None)])?;
+
+        assert_eq!(patch_id_db.prune_unreachable(&repo, &[test2_oid])?, 2);
+        assert_eq!(patch_id_db.get(test1_oid)?, Some(None));
+        assert_eq!(patch_id_db.get(test2_oid)?, None);
+        assert_eq!(patch_id_db.get(missing_oid)?, None);
+
+        Ok(())
+    }
+}
@@ -3,14 +3,18 @@
 use std::collections::{HashMap, HashSet};
 
 use color_eyre::Help;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 use crate::git::{NonZeroOid, Reference, ReferenceName, Repo};
 
-use super::config::get_main_branch_name;
+use itertools::Itertools;
+
+use super::config::{
+    get_additional_main_branch_names, get_main_branch_name, get_public_remote_branch_patterns,
+};
 
 /// A snapshot of all the positions of references we care about in the repository.
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct RepoReferencesSnapshot {
     /// The location of the `HEAD` reference. This may be `None` if `HEAD` is unborn.
     pub head_oid: Option<NonZeroOid>,
@@ -18,6 +22,14 @@ pub struct RepoReferencesSnapshot {
     /// The location of the main branch.
     pub main_branch_oid: NonZeroOid,
 
+    /// The locations of any additional main branches, such as release
+    /// branches. See `get_additional_main_branch_names`.
+    pub additional_main_branch_oids: HashSet<NonZeroOid>,
+
+    /// The locations of the remote-tracking branches whose commits are
+    /// considered public. See `get_public_remote_branch_patterns`.
+    pub public_remote_branch_oids: HashSet<NonZeroOid>,
+
     /// A mapping from commit OID to the branches which point to that commit.
     pub branch_oid_to_names: HashMap<NonZeroOid, HashSet<ReferenceName>>,
 }
@@ -30,6 +42,20 @@ pub trait RepoExt {
     /// Get the OID corresponding to the main branch.
     fn get_main_branch_oid(&self) -> eyre::Result<NonZeroOid>;
 
+    /// Get the `Reference`s for the additional main branches for the
+    /// repository. Branches which can't be found are skipped.
+    fn get_additional_main_branch_references(&self) -> eyre::Result<Vec<Reference<'_>>>;
+
+    /// Get the OIDs corresponding to the additional main branches.
+    fn get_additional_main_branch_oids(&self) -> eyre::Result<HashSet<NonZeroOid>>;
+
+    /// Get the remote-tracking branches whose commits are considered public.
+    /// See `get_public_remote_branch_patterns`.
+    fn get_public_remote_branch_references(&self) -> eyre::Result<Vec<Reference<'_>>>;
+
+    /// Get the OIDs corresponding to the public remote-tracking branches.
+    fn get_public_remote_branch_oids(&self) -> eyre::Result<HashSet<NonZeroOid>>;
+
     /// Get a mapping from OID to the names of branches which point to that OID.
     ///
     /// The returned branch names include the `refs/heads/` prefix, so it must
@@ -40,19 +66,61 @@ pub trait RepoExt {
     fn get_references_snapshot(&self) -> eyre::Result<RepoReferencesSnapshot>;
 }
 
+/// Find the reference for a main branch with the given name. If it's a local
+/// branch with an upstream, the upstream branch is used instead.
+fn find_main_branch_reference<'repo>(
+    repo: &'repo Repo,
+    branch_name: &str,
+) -> eyre::Result<Option<Reference<'repo>>> {
+    match repo.find_branch(branch_name, git2::BranchType::Local)? {
+        Some(branch) => match branch.get_upstream_branch()? {
+            Some(upstream_branch) => Ok(Some(upstream_branch.into_reference())),
+            None => Ok(Some(branch.into_reference())),
+        },
+        None => match repo.find_branch(branch_name, git2::BranchType::Remote)? {
+            Some(branch) => Ok(Some(branch.into_reference())),
+            None => Ok(None),
+        },
+    }
+}
+
+/// Parse the patterns from `get_public_remote_branch_patterns`. Patterns which
+/// fail to parse are skipped with a warning.
+pub(crate) fn get_public_remote_branch_globs(repo: &Repo) -> eyre::Result<Vec<glob::Pattern>> {
+    let patterns = get_public_remote_branch_patterns(repo)?
+        .into_iter()
+        .filter_map(|pattern| match glob::Pattern::new(&pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(err) => {
+                warn!(
+                    ?pattern,
+                    ?err,
+                    "Could not parse public remote branch pattern"
+                );
+                None
+            }
+        })
+        .collect_vec();
+    Ok(patterns)
+}
+
+/// Determine whether the given reference is a remote-tracking branch which
+/// matches one of the patterns from `get_public_remote_branch_globs`.
+pub(crate) fn is_public_remote_branch(patterns: &[glob::Pattern], name: &ReferenceName) -> bool {
+    match name.as_str().strip_prefix("refs/remotes/") {
+        Some(name) => patterns.iter().any(|pattern| pattern.matches(name)),
+        None => false,
+    }
+}
+
 impl RepoExt for Repo {
     fn get_main_branch_reference(&self) -> eyre::Result<Reference> {
         let main_branch_name = get_main_branch_name(self)?;
-        match self.find_branch(&main_branch_name, git2::BranchType::Local)? {
-            Some(branch) => match branch.get_upstream_branch()? {
-                Some(upstream_branch) => Ok(upstream_branch.into_reference()),
-                None => Ok(branch.into_reference()),
-            },
-            None => match self.find_branch(&main_branch_name, git2::BranchType::Remote)? {
-                Some(branch) => Ok(branch.into_reference()),
-                None => {
-                    let suggestion = format!(
-                        r"
+        match find_main_branch_reference(self, &main_branch_name)? {
+            Some(reference) => Ok(reference),
+            None => {
+                let suggestion = format!(
+                    r"
 The main branch {:?} could not be found in your repository
 at path: {:?}.
 These branches exist: {:?}
@@ -60,22 +128,21 @@ Either create it, or update the main branch setting by running:
 
     git config branchless.core.mainBranch <branch>
 ",
-                        get_main_branch_name(self)?,
-                        self.get_path(),
-                        self.get_all_local_branches()?
-                            .into_iter()
-                            .map(|branch| {
-                                branch
-                                    .into_reference()
-                                    .get_name()
-                                    .map(|s| format!("{:?}", s))
-                            })
-                            .collect::<eyre::Result<Vec<String>>>()?,
-                    );
-                    Err(eyre::eyre!("Could not find repository main branch")
-                        .with_suggestion(|| suggestion))
-                }
-            },
+                    get_main_branch_name(self)?,
+                    self.get_path(),
+                    self.get_all_local_branches()?
+                        .into_iter()
+                        .map(|branch| {
+                            branch
+                                .into_reference()
+                                .get_name()
+                                .map(|s| format!("{:?}", s))
+                        })
+                        .collect::<eyre::Result<Vec<String>>>()?,
+                );
+                Err(eyre::eyre!("Could not find repository main branch")
+                    .with_suggestion(|| suggestion))
+            }
         }
     }
 
@@ -92,6 +159,60 @@ Either create it, or update the main branch setting by running:
         }
     }
 
+    #[instrument]
+    fn get_additional_main_branch_references(&self) -> eyre::Result<Vec<Reference<'_>>> {
+        let mut result = Vec::new();
+        for branch_name in get_additional_main_branch_names(self)? {
+            match find_main_branch_reference(self, &branch_name)? {
+                Some(reference) => result.push(reference),
+                None => {
+                    warn!(?branch_name, "Additional main branch not found");
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    #[instrument]
+    fn get_additional_main_branch_oids(&self) -> eyre::Result<HashSet<NonZeroOid>> {
+        let mut result = HashSet::new();
+        for reference in self.get_additional_main_branch_references()? {
+            if let Some(commit) = reference.peel_to_commit()? {
+                result.insert(commit.get_oid());
+            }
+        }
+        Ok(result)
+    }
+
+    #[instrument]
+    fn get_public_remote_branch_references(&self) -> eyre::Result<Vec<Reference<'_>>> {
+        let patterns = get_public_remote_branch_globs(self)?;
+        if patterns.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut result = Vec::new();
+        for branch in self.get_all_remote_branches()? {
+            let reference = branch.into_reference();
+            let name = reference.get_name()?;
+            if is_public_remote_branch(&patterns, &name) {
+                result.push(reference);
+            }
+        }
+        Ok(result)
+    }
+
+    #[instrument]
+    fn get_public_remote_branch_oids(&self) -> eyre::Result<HashSet<NonZeroOid>> {
+        let mut result = HashSet::new();
+        for reference in self.get_public_remote_branch_references()? {
+            if let Some(commit) = reference.peel_to_commit()? {
+                result.insert(commit.get_oid());
+            }
+        }
+        Ok(result)
+    }
+
     #[instrument]
     fn get_branch_oid_to_names(&self) -> eyre::Result<HashMap<NonZeroOid, HashSet<ReferenceName>>> {
         let mut result: HashMap<NonZeroOid, HashSet<ReferenceName>> = HashMap::new();
@@ -116,17 +237,31 @@ Either create it, or update the main branch setting by running:
             .or_insert_with(HashSet::new)
             .insert(main_branch_name);
 
+        // Likewise for the additional main branches.
+        for reference in self.get_additional_main_branch_references()? {
+            if let Some(commit) = reference.peel_to_commit()? {
+                result
+                    .entry(commit.get_oid())
+                    .or_default()
+                    .insert(reference.get_name()?);
+            }
+        }
+
         Ok(result)
     }
 
     fn get_references_snapshot(&self) -> eyre::Result<RepoReferencesSnapshot> {
         let head_oid = self.get_head_info()?.oid;
         let main_branch_oid = self.get_main_branch_oid()?;
+        let additional_main_branch_oids = self.get_additional_main_branch_oids()?;
+        let public_remote_branch_oids = self.get_public_remote_branch_oids()?;
         let branch_oid_to_names = self.get_branch_oid_to_names()?;
 
         Ok(RepoReferencesSnapshot {
             head_oid,
             main_branch_oid,
+            additional_main_branch_oids,
+            public_remote_branch_oids,
             branch_oid_to_names,
         })
     }
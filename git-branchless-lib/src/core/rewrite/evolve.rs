@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 
 use eden_dag::DagAlgorithm;
@@ -65,6 +66,101 @@ pub fn find_rewrite_target(
     }
 }
 
+/// The cumulative mapping from each rewritten commit to the commits it was
+/// rewritten into, as recorded in the event log.
+///
+/// Unlike [`find_rewrite_target`], this keeps every rewrite of a commit, so a
+/// commit which was rewritten more than once (for example, after being
+/// unhidden) can have several successors.
+#[derive(Clone, Debug, Default)]
+pub struct RewriteMap {
+    successors: HashMap<NonZeroOid, Vec<MaybeZeroOid>>,
+}
+
+impl RewriteMap {
+    /// Build the mapping from the rewrite events which happened before the
+    /// given cursor.
+    #[instrument]
+    pub fn from_event_replayer(event_replayer: &EventReplayer, event_cursor: EventCursor) -> Self {
+        let mut successors: HashMap<NonZeroOid, Vec<MaybeZeroOid>> = HashMap::new();
+        for event in event_replayer.get_events_before_cursor(event_cursor) {
+            if let Event::RewriteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid,
+            } = event
+            {
+                let new_commit_oids = successors.entry(*old_commit_oid).or_default();
+                if *new_commit_oid != MaybeZeroOid::NonZero(*old_commit_oid)
+                    && !new_commit_oids.contains(new_commit_oid)
+                {
+                    new_commit_oids.push(*new_commit_oid);
+                }
+            }
+        }
+        Self { successors }
+    }
+
+    /// Iterate over each rewritten commit and the commits it was directly
+    /// rewritten into, in no particular order. A successor of
+    /// [`MaybeZeroOid::Zero`] means that the commit was deleted.
+    pub fn iter(&self) -> impl Iterator<Item = (NonZeroOid, &[MaybeZeroOid])> + '_ {
+        self.successors
+            .iter()
+            .filter(|(_oid, successors)| !successors.is_empty())
+            .map(|(oid, successors)| (*oid, successors.as_slice()))
+    }
+
+    /// Find the commits with the given OID as a prefix which were rewritten.
+    pub fn find_by_prefix(&self, prefix: &str) -> Vec<NonZeroOid> {
+        self.iter()
+            .map(|(oid, _successors)| oid)
+            .filter(|oid| oid.to_string().starts_with(prefix))
+            .sorted()
+            .collect()
+    }
+
+    /// Find the newest versions of the given commit, by following its rewrites
+    /// transitively until reaching commits which haven't been rewritten.
+    ///
+    /// Returns an empty list if the commit was never rewritten, or if it was
+    /// rewritten back into itself. If a commit along the way was deleted, then
+    /// [`MaybeZeroOid::Zero`] is included in the result.
+    pub fn find_latest_successors(&self, oid: NonZeroOid) -> Vec<MaybeZeroOid> {
+        let mut result = Vec::new();
+        let mut seen_oids = HashSet::from([oid]);
+        let mut oids_to_visit = vec![oid];
+        while let Some(oid) = oids_to_visit.pop() {
+            let successors = match self.successors.get(&oid) {
+                Some(successors) => successors,
+                None => continue,
+            };
+            for successor in successors {
+                match successor {
+                    MaybeZeroOid::Zero => {
+                        if !result.contains(successor) {
+                            result.push(*successor);
+                        }
+                    }
+                    MaybeZeroOid::NonZero(successor_oid) => {
+                        if !seen_oids.insert(*successor_oid) {
+                            continue;
+                        }
+                        match self.successors.get(successor_oid) {
+                            Some(next_successors) if !next_successors.is_empty() => {
+                                oids_to_visit.push(*successor_oid);
+                            }
+                            _ => result.push(*successor),
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
 /// Find commits which have been "abandoned" in the commit graph.
 ///
 /// A commit is considered "abandoned" if it's not obsolete, but one of its
@@ -161,4 +257,41 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_rewrite_map_find_latest_successors() -> eyre::Result<()> {
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let git = make_git()?;
+
+        git.init_repo()?;
+        let old_oid = git.commit_file("test1", 1)?;
+        git.run(&["commit", "--amend", "-m", "test1 amended once"])?;
+        git.run(&["commit", "--amend", "-m", "test1 amended twice"])?;
+        let new_oid: MaybeZeroOid = {
+            let (stdout, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+            stdout.trim().parse()?
+        };
+
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let rewrite_map = RewriteMap::from_event_replayer(&event_replayer, event_cursor);
+
+        assert_eq!(rewrite_map.iter().count(), 2);
+        assert_eq!(rewrite_map.find_latest_successors(old_oid), vec![new_oid]);
+        assert_eq!(
+            rewrite_map.find_by_prefix(&old_oid.to_string()[..7]),
+            vec![old_oid]
+        );
+        match new_oid {
+            MaybeZeroOid::NonZero(new_oid) => {
+                assert_eq!(rewrite_map.find_latest_successors(new_oid), vec![]);
+            }
+            MaybeZeroOid::Zero => panic!("HEAD should not be the zero OID"),
+        }
+
+        Ok(())
+    }
 }
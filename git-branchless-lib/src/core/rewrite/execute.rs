@@ -1,28 +1,33 @@
 use std::collections::{HashMap, HashSet};
 
 use std::fmt::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-use bstr::BString;
+use cursive::utils::markup::StyledString;
 use eyre::Context;
+use itertools::Itertools;
 use tracing::warn;
 
 use crate::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use crate::core::config::get_word_diff;
 use crate::core::effects::Effects;
-use crate::core::eventlog::{EventLogDb, EventTransactionId};
-use crate::core::formatting::{printable_styled_string, Pluralize};
+use crate::core::eventlog::{Event, EventLogDb, EventTransactionId};
+use crate::core::formatting::{printable_styled_string, render_patch};
 use crate::core::repo_ext::RepoExt;
+use crate::error::ErrorCategory;
 use crate::git::{
-    GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo,
+    GitRunInfo, GitRunOpts, GitRunResult, MaybeZeroOid, NonZeroOid, ReferenceName, ReferenceUpdate,
+    Repo, ResolvedReferenceInfo,
 };
 use crate::util::ExitCode;
 
 use super::plan::RebasePlan;
 
 /// Given a list of rewritten OIDs, move the branches attached to those OIDs
-/// from their old commits to their new commits. Invoke the
-/// `reference-transaction` hook when done.
+/// from their old commits to their new commits. All of the branches are moved
+/// in a single reference transaction, so if any of them can't be moved, then
+/// none of them are. Invoke the `reference-transaction` hook when done.
 pub fn move_branches<'a>(
     effects: &Effects,
     git_run_info: &GitRunInfo,
@@ -30,16 +35,21 @@ pub fn move_branches<'a>(
     event_tx_id: EventTransactionId,
     rewritten_oids_map: &'a HashMap<NonZeroOid, MaybeZeroOid>,
 ) -> eyre::Result<()> {
+    let branch_moves = plan_branch_moves(repo, rewritten_oids_map)?;
+    apply_branch_moves(effects, git_run_info, repo, event_tx_id, &branch_moves)
+}
+
+/// Determine how the branches attached to the given rewritten OIDs should be
+/// moved, without moving them. Fails if any of the rewritten commits can't be
+/// found.
+fn plan_branch_moves(
+    repo: &Repo,
+    rewritten_oids_map: &HashMap<NonZeroOid, MaybeZeroOid>,
+) -> eyre::Result<Vec<ReferenceUpdate>> {
     let branch_oid_to_names = repo.get_branch_oid_to_names()?;
 
-    // We may experience an error in the case of a branch move. Ideally, we
-    // would use `git2::Transaction::commit`, which stops the transaction at the
-    // first error, but we don't know which references we successfully committed
-    // in that case. Instead, we just do things non-atomically and record which
-    // ones succeeded. See https://github.com/libgit2/libgit2/issues/5918
-    let mut branch_moves: Vec<(NonZeroOid, MaybeZeroOid, &ReferenceName)> = Vec::new();
-    let mut branch_move_err: Option<eyre::Error> = None;
-    'outer: for (old_oid, names) in branch_oid_to_names.iter() {
+    let mut branch_moves: Vec<ReferenceUpdate> = Vec::new();
+    for (old_oid, names) in branch_oid_to_names.iter() {
         let new_oid = match rewritten_oids_map.get(old_oid) {
             Some(new_oid) => new_oid,
             None => continue,
@@ -49,96 +59,154 @@ pub fn move_branches<'a>(
         names.sort_unstable();
         match new_oid {
             MaybeZeroOid::NonZero(new_oid) => {
-                let new_commit = match repo.find_commit_or_fail(*new_oid).wrap_err_with(|| {
+                repo.find_commit_or_fail(*new_oid).wrap_err_with(|| {
                     format!(
                         "Could not find newly-rewritten commit with old OID: {:?}, new OID: {:?}",
                         old_oid, new_oid,
                     )
-                }) {
-                    Ok(commit) => commit,
-                    Err(err) => {
-                        branch_move_err = Some(err);
-                        break 'outer;
-                    }
-                };
-
-                for reference_name in names {
-                    if let Err(err) = repo.create_reference(
-                        reference_name,
-                        new_commit.get_oid(),
-                        true,
-                        "move branches",
-                    ) {
-                        branch_move_err = Some(err);
-                        break 'outer;
-                    }
-                    branch_moves.push((*old_oid, MaybeZeroOid::NonZero(*new_oid), reference_name));
-                }
+                })?;
             }
+            MaybeZeroOid::Zero => {}
+        }
 
-            MaybeZeroOid::Zero => {
-                for name in names {
-                    match repo.find_reference(name) {
-                        Ok(Some(mut reference)) => {
-                            if let Err(err) = reference.delete() {
-                                branch_move_err = Some(err);
-                                break 'outer;
-                            }
-                        }
-                        Ok(None) => {
-                            warn!(?name, "Reference not found, not deleting")
-                        }
-                        Err(err) => {
-                            branch_move_err = Some(err);
-                            break 'outer;
-                        }
-                    };
-                    branch_moves.push((*old_oid, MaybeZeroOid::Zero, name));
-                }
+        for name in names {
+            if *new_oid == MaybeZeroOid::Zero && repo.find_reference(name)?.is_none() {
+                warn!(?name, "Reference not found, not deleting");
+                continue;
             }
+            branch_moves.push(ReferenceUpdate {
+                ref_name: name.clone(),
+                old_oid: MaybeZeroOid::NonZero(*old_oid),
+                new_oid: *new_oid,
+            });
         }
     }
+    Ok(branch_moves)
+}
 
-    let branch_moves_stdin: String = branch_moves
-        .into_iter()
-        .map(|(old_oid, new_oid, name)| {
-            format!("{old_oid} {new_oid} {name}\n", name = name.as_str())
-        })
-        .collect();
-    let branch_moves_stdin = BString::from(branch_moves_stdin);
-    git_run_info.run_hook(
-        effects,
-        repo,
-        "reference-transaction",
-        event_tx_id,
-        &["committed"],
-        Some(branch_moves_stdin),
-    )?;
-    match branch_move_err {
-        Some(err) => Err(err),
-        None => Ok(()),
+/// Move branches as determined by [`plan_branch_moves`].
+fn apply_branch_moves(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    branch_moves: &[ReferenceUpdate],
+) -> eyre::Result<()> {
+    match git_run_info.update_references(effects, repo, event_tx_id, branch_moves)? {
+        Ok(()) => Ok(()),
+        Err(err) => Err(eyre::Error::new(err).wrap_err("Moving branches")),
     }
 }
 
-/// After a rebase, check out the appropriate new `HEAD`. This can be difficult
-/// because the commit might have been rewritten, dropped, or have a branch
-/// pointing to it which also needs to be checked out.
+/// Undo the changes recorded in the event log for the given event transaction
+/// after the event with the given row ID: move the branches which were updated
+/// back to where they were, restore the visibility of the rewritten commits,
+/// and point `HEAD` back to `previous_head_info`. The working copy isn't
+/// touched.
 ///
-/// `skipped_head_updated_oid` is the caller's belief of what the new OID of
-/// `HEAD` should be in the event that the original commit was skipped. If the
-/// caller doesn't think that the previous `HEAD` commit was skipped, then they
-/// should pass in `None`.
-pub fn check_out_updated_head(
+/// Returns the names of the branches which were moved back.
+fn roll_back_transaction(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     repo: &Repo,
     event_log_db: &EventLogDb,
     event_tx_id: EventTransactionId,
+    last_event_rowid: i64,
+    previous_head_info: &ResolvedReferenceInfo,
+) -> eyre::Result<Vec<ReferenceName>> {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs_f64();
+
+    // For each branch, the OID it had before the transaction and the OID it
+    // has now.
+    let mut branch_oids: Vec<(ReferenceName, MaybeZeroOid, MaybeZeroOid)> = Vec::new();
+    let mut inverse_events = Vec::new();
+    for (_rowid, event) in event_log_db.get_events_after_rowid(last_event_rowid)? {
+        if event.get_event_tx_id() != event_tx_id {
+            continue;
+        }
+        match event {
+            Event::RefUpdateEvent {
+                ref_name,
+                old_oid,
+                new_oid,
+                ..
+            } if ref_name.as_str().starts_with("refs/heads/") => {
+                match branch_oids
+                    .iter_mut()
+                    .find(|(name, _, _)| *name == ref_name)
+                {
+                    Some((_name, _original_oid, current_oid)) => *current_oid = new_oid,
+                    None => branch_oids.push((ref_name, old_oid, new_oid)),
+                }
+            }
+            Event::RewriteEvent {
+                old_commit_oid,
+                new_commit_oid,
+                ..
+            } => {
+                if let MaybeZeroOid::NonZero(new_commit_oid) = new_commit_oid {
+                    inverse_events.push(Event::ObsoleteEvent {
+                        timestamp,
+                        event_tx_id,
+                        commit_oid: new_commit_oid,
+                    });
+                }
+                if let MaybeZeroOid::NonZero(old_commit_oid) = old_commit_oid {
+                    inverse_events.push(Event::UnobsoleteEvent {
+                        timestamp,
+                        event_tx_id,
+                        commit_oid: old_commit_oid,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let branch_moves = branch_oids
+        .iter()
+        .filter(|(_name, original_oid, current_oid)| original_oid != current_oid)
+        .map(|(name, original_oid, current_oid)| ReferenceUpdate {
+            ref_name: name.clone(),
+            old_oid: *current_oid,
+            new_oid: *original_oid,
+        })
+        .collect::<Vec<_>>();
+    apply_branch_moves(effects, git_run_info, repo, event_tx_id, &branch_moves)
+        .wrap_err("Moving branches back")?;
+    event_log_db.add_events(inverse_events)?;
+
+    match previous_head_info {
+        ResolvedReferenceInfo {
+            oid: _,
+            reference_name: Some(reference_name),
+        } => repo.set_head_to_reference(reference_name)?,
+        ResolvedReferenceInfo {
+            oid: Some(oid),
+            reference_name: None,
+        } => repo.set_head(*oid)?,
+        ResolvedReferenceInfo {
+            oid: None,
+            reference_name: None,
+        } => {}
+    }
+
+    Ok(branch_moves
+        .into_iter()
+        .map(|branch_move| branch_move.ref_name)
+        .collect())
+}
+
+/// Determine what `HEAD` should point to after a rebase. See
+/// `check_out_updated_head` for details.
+fn get_updated_head_target(
+    repo: &Repo,
     rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
     previous_head_info: &ResolvedReferenceInfo,
     skipped_head_updated_oid: Option<NonZeroOid>,
-    check_out_commit_options: &CheckOutCommitOptions,
-) -> eyre::Result<ExitCode> {
+) -> eyre::Result<ResolvedReferenceInfo> {
     let checkout_target: ResolvedReferenceInfo = match previous_head_info {
         ResolvedReferenceInfo {
             oid: None,
@@ -227,7 +295,101 @@ pub fn check_out_updated_head(
             }
         }
     };
+    Ok(checkout_target)
+}
 
+/// Check whether `check_out_updated_head` is expected to succeed, without
+/// touching the index or the working copy. This is used to find out about a
+/// failed checkout (for example, because of untracked files which would be
+/// overwritten) before any hooks have been told about the rewritten commits.
+///
+/// If the checkout would fail, the reason is written to the error stream and
+/// `false` is returned.
+#[allow(clippy::too_many_arguments)]
+fn can_check_out_updated_head(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
+    previous_head_info: &ResolvedReferenceInfo,
+    skipped_head_updated_oid: Option<NonZeroOid>,
+    check_out_commit_options: &CheckOutCommitOptions,
+) -> eyre::Result<bool> {
+    if !check_out_commit_options.additional_args.is_empty() {
+        // The additional arguments (such as `--force` or `--merge`) may
+        // change whether the checkout succeeds, so don't try to predict it.
+        return Ok(true);
+    }
+
+    let checkout_target = get_updated_head_target(
+        repo,
+        rewritten_oids,
+        previous_head_info,
+        skipped_head_updated_oid,
+    )?;
+    let (head_oid, target_oid) = match (repo.get_head_info()?.oid, checkout_target.oid) {
+        (Some(head_oid), Some(target_oid)) => (head_oid, target_oid),
+        _ => return Ok(true),
+    };
+    if head_oid == target_oid {
+        return Ok(true);
+    }
+
+    let head_oid = head_oid.to_string();
+    let target_oid = target_oid.to_string();
+    let GitRunResult {
+        exit_code,
+        stdout: _,
+        stderr,
+    } = git_run_info
+        .run_silent(
+            repo,
+            Some(event_tx_id),
+            &["read-tree", "-n", "-m", "-u", &head_oid, &target_oid],
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                stdin: None,
+            },
+        )
+        .wrap_err("Checking whether the updated HEAD can be checked out")?;
+    if exit_code == 0 {
+        Ok(true)
+    } else {
+        write!(
+            effects.get_error_stream(),
+            "{}",
+            String::from_utf8_lossy(&stderr)
+        )?;
+        Ok(false)
+    }
+}
+
+/// After a rebase, check out the appropriate new `HEAD`. This can be difficult
+/// because the commit might have been rewritten, dropped, or have a branch
+/// pointing to it which also needs to be checked out.
+///
+/// `skipped_head_updated_oid` is the caller's belief of what the new OID of
+/// `HEAD` should be in the event that the original commit was skipped. If the
+/// caller doesn't think that the previous `HEAD` commit was skipped, then they
+/// should pass in `None`.
+pub fn check_out_updated_head(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
+    previous_head_info: &ResolvedReferenceInfo,
+    skipped_head_updated_oid: Option<NonZeroOid>,
+    check_out_commit_options: &CheckOutCommitOptions,
+) -> eyre::Result<ExitCode> {
+    let checkout_target = get_updated_head_target(
+        repo,
+        rewritten_oids,
+        previous_head_info,
+        skipped_head_updated_oid,
+    )?;
     let head_info = repo.get_head_info()?;
     if head_info == checkout_target {
         return Ok(ExitCode(0));
@@ -293,7 +455,9 @@ pub struct MergeConflictInfo {
 
 impl MergeConflictInfo {
     /// Describe the merge conflict in a user-friendly way and advise to rerun
-    /// with `--merge`.
+    /// with `--merge`. If `branchless.diff.wordDiff` is set, the commit's
+    /// changes to the conflicting files are also shown, with the changed words
+    /// highlighted.
     pub fn describe(
         &self,
         effects: &Effects,
@@ -302,38 +466,71 @@ impl MergeConflictInfo {
     ) -> eyre::Result<()> {
         writeln!(
             effects.get_output_stream(),
-            "This operation would cause a merge conflict:"
-        )?;
-        writeln!(
-            effects.get_output_stream(),
-            "{} ({}) {}",
-            effects.get_glyphs().bullet_point,
-            Pluralize {
-                determiner: None,
-                amount: self.conflicting_paths.len(),
-                unit: ("conflicting file", "conflicting files"),
-            },
+            "{}",
             printable_styled_string(
                 effects.get_glyphs(),
-                repo.friendly_describe_commit_from_oid(effects.get_glyphs(), self.commit_oid)?
+                StyledString::styled(
+                    effects.tr("merge-conflict-header", &[]),
+                    effects.get_theme().conflict
+                )
             )?
         )?;
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            effects.tr(
+                "merge-conflict-commit",
+                &[
+                    ("bullet", effects.get_glyphs().bullet_point.into()),
+                    ("count", self.conflicting_paths.len().into()),
+                    (
+                        "commit",
+                        printable_styled_string(
+                            effects.get_glyphs(),
+                            repo.friendly_describe_commit_from_oid(
+                                effects.get_glyphs(),
+                                self.commit_oid
+                            )?
+                        )?
+                        .into()
+                    ),
+                ]
+            )
+        )?;
 
-        match remediation {
-            MergeConflictRemediation::Retry => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "To resolve merge conflicts, retry this operation with the --merge option."
-                )?;
-            }
-            MergeConflictRemediation::Restack => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "To resolve merge conflicts, run: git restack --merge"
-                )?;
-            }
+        if get_word_diff(repo)? {
+            self.describe_conflicting_changes(effects, repo)?;
         }
 
+        let hint_id = match remediation {
+            MergeConflictRemediation::Retry => "merge-conflict-retry-hint",
+            MergeConflictRemediation::Restack => "merge-conflict-restack-hint",
+        };
+        writeln!(effects.get_output_stream(), "{}", effects.tr(hint_id, &[]))?;
+
+        Ok(())
+    }
+
+    fn describe_conflicting_changes(&self, effects: &Effects, repo: &Repo) -> eyre::Result<()> {
+        let commit = repo.find_commit_or_fail(self.commit_oid)?;
+        let conflicting_paths = self
+            .conflicting_paths
+            .iter()
+            .map(|path| -> &Path { path })
+            .sorted()
+            .collect_vec();
+        let diff = match repo.get_patch_for_commit_paths(effects, &commit, &conflicting_paths)? {
+            Some(diff) => diff,
+            None => return Ok(()),
+        };
+        let patch = diff.get_patch()?;
+        for line in render_patch(&String::from_utf8_lossy(&patch), true) {
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                printable_styled_string(effects.get_glyphs(), line)?
+            )?;
+        }
         Ok(())
     }
 }
@@ -342,23 +539,28 @@ mod in_memory {
     use std::collections::HashMap;
     use std::fmt::Write;
 
-    use bstr::{BString, ByteSlice};
+    use bstr::{BString, ByteSlice, ByteVec};
     use eyre::Context;
+    use itertools::Itertools;
     use tracing::{instrument, warn};
 
     use crate::core::effects::{Effects, OperationType};
     use crate::core::eventlog::EventLogDb;
     use crate::core::formatting::printable_styled_string;
-    use crate::core::gc::mark_commit_reachable;
+    use crate::core::gc::{mark_commit_reachable, run_post_rewrite_maintenance};
     use crate::core::rewrite::execute::check_out_updated_head;
-    use crate::core::rewrite::move_branches;
-    use crate::core::rewrite::plan::{OidOrLabel, RebaseCommand, RebasePlan};
+    use crate::core::rewrite::plan::{FixupKind, OidOrLabel, RebaseCommand, RebasePlan};
+    use crate::core::stack_branches::sync_stack_branches;
     use crate::git::{
-        CherryPickFastError, CherryPickFastOptions, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo,
+        CategorizedReferenceName, CherryPickFastError, CherryPickFastOptions, GitRunInfo,
+        MaybeZeroOid, NonZeroOid, ReferenceUpdate, Repo, ResolvedReferenceInfo,
     };
     use crate::util::ExitCode;
 
-    use super::{ExecuteRebasePlanOptions, MergeConflictInfo};
+    use super::{
+        apply_branch_moves, can_check_out_updated_head, plan_branch_moves, roll_back_transaction,
+        ExecuteRebasePlanOptions, MergeConflictInfo,
+    };
 
     pub enum RebaseInMemoryResult {
         Succeeded {
@@ -375,6 +577,10 @@ mod in_memory {
             commit_oid: NonZeroOid,
         },
         MergeConflict(MergeConflictInfo),
+
+        /// The user interrupted the rebase before it finished. No commits
+        /// have been rewritten yet, so there's nothing to roll back.
+        Interrupted,
     }
 
     #[instrument]
@@ -398,7 +604,9 @@ mod in_memory {
                     | RebaseCommand::Pick { .. }
                     | RebaseCommand::RegisterExtraPostRewriteHook
                     | RebaseCommand::DetectEmptyCommit { .. }
-                    | RebaseCommand::SkipUpstreamAppliedCommit { .. } => None,
+                    | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+                    | RebaseCommand::Fixup { .. }
+                    | RebaseCommand::SkipFoldedCommit { .. } => None,
                 })
         {
             return Ok(RebaseInMemoryResult::CannotRebaseMergeCommit {
@@ -443,15 +651,21 @@ mod in_memory {
                 RebaseCommand::CreateLabel { .. }
                 | RebaseCommand::Reset { .. }
                 | RebaseCommand::RegisterExtraPostRewriteHook
-                | RebaseCommand::DetectEmptyCommit { .. } => false,
+                | RebaseCommand::DetectEmptyCommit { .. }
+                | RebaseCommand::SkipFoldedCommit { .. } => false,
                 RebaseCommand::Pick { .. }
                 | RebaseCommand::Merge { .. }
-                | RebaseCommand::SkipUpstreamAppliedCommit { .. } => true,
+                | RebaseCommand::SkipUpstreamAppliedCommit { .. }
+                | RebaseCommand::Fixup { .. } => true,
             })
             .count();
         let (effects, progress) = effects.start_operation(OperationType::RebaseCommits);
 
         for command in rebase_plan.commands.iter() {
+            if effects.is_interrupted() {
+                return Ok(RebaseInMemoryResult::Interrupted);
+            }
+
             match command {
                 RebaseCommand::CreateLabel { label_name } => {
                     labels.insert(label_name.clone(), current_oid);
@@ -522,12 +736,6 @@ mod in_memory {
                     };
 
                     let commit_message = commit_to_apply.get_message_raw()?;
-                    let commit_message = commit_message.to_str().with_context(|| {
-                        eyre::eyre!(
-                            "Could not decode commit message for commit: {:?}",
-                            commit_to_apply_oid
-                        )
-                    })?;
 
                     progress
                         .notify_status(format!("Committing to repository: {}", commit_description));
@@ -536,16 +744,29 @@ mod in_memory {
                     } else {
                         commit_to_apply.get_committer().update_timestamp(*now)?
                     };
-                    let rebased_commit_oid = repo
-                        .create_commit(
+                    let rebased_commit_oid = match (
+                        commit_to_apply.get_message_encoding(),
+                        commit_message.to_str(),
+                    ) {
+                        (None, Ok(commit_message)) => repo.create_commit(
                             None,
                             &commit_to_apply.get_author(),
                             &committer_signature,
                             commit_message,
                             &commit_tree,
                             vec![&current_commit],
-                        )
-                        .wrap_err("Applying rebased commit")?;
+                        ),
+                        // Keep messages which aren't stored as UTF-8 byte-for-byte.
+                        (encoding, _) => repo.create_commit_with_encoding(
+                            &commit_to_apply.get_author(),
+                            &committer_signature,
+                            commit_message.as_slice(),
+                            encoding,
+                            &commit_tree,
+                            vec![&current_commit],
+                        ),
+                    }
+                    .wrap_err("Applying rebased commit")?;
 
                     let rebased_commit = repo
                         .find_commit_or_fail(rebased_commit_oid)
@@ -563,10 +784,14 @@ mod in_memory {
 
                         writeln!(
                             effects.get_output_stream(),
-                            "[{}/{}] Skipped now-empty commit: {}",
-                            i,
-                            num_picks,
-                            commit_description
+                            "{}",
+                            effects.tr(
+                                "rebase-skipped-empty",
+                                &[
+                                    ("progress", format!("[{}/{}]", i, num_picks).into()),
+                                    ("commit", commit_description.into()),
+                                ]
+                            )
                         )?;
                     } else {
                         rewritten_oids.push((
@@ -577,9 +802,14 @@ mod in_memory {
 
                         writeln!(
                             effects.get_output_stream(),
-                            "{} Committed as: {}",
-                            commit_num,
-                            commit_description
+                            "{}",
+                            effects.tr(
+                                "rebase-committed-as",
+                                &[
+                                    ("progress", commit_num.into()),
+                                    ("commit", commit_description.into()),
+                                ]
+                            )
                         )?;
                     }
                 }
@@ -610,10 +840,196 @@ mod in_memory {
                         printable_styled_string(effects.get_glyphs(), commit_description)?;
                     writeln!(
                         effects.get_output_stream(),
-                        "{} Skipped commit (was already applied upstream): {}",
-                        commit_num,
+                        "{}",
+                        effects.tr(
+                            "rebase-skipped-upstream",
+                            &[
+                                ("progress", commit_num.into()),
+                                ("commit", commit_description.into()),
+                            ]
+                        )
+                    )?;
+                }
+
+                RebaseCommand::Fixup { commit_oid, kind } => {
+                    let current_commit = repo
+                        .find_commit_or_fail(current_oid)
+                        .wrap_err("Finding current commit")?;
+                    let commit_to_apply = repo
+                        .find_commit_or_fail(*commit_oid)
+                        .wrap_err("Finding commit to fold")?;
+                    i += 1;
+
+                    let commit_description = printable_styled_string(
+                        effects.get_glyphs(),
+                        commit_to_apply.friendly_describe(effects.get_glyphs())?,
+                    )?;
+                    let commit_num = format!("[{}/{}]", i, num_picks);
+                    progress.notify_progress(i, num_picks);
+
+                    // The commit being folded into must have been rewritten as
+                    // part of this rebase. If it was skipped (for example,
+                    // because it became empty), then there's nothing to fold
+                    // into, so apply the commit as-is instead.
+                    let is_foldable = rewritten_oids
+                        .iter()
+                        .any(|(_, new_oid)| *new_oid == MaybeZeroOid::NonZero(current_oid));
+
+                    progress.notify_status(format!(
+                        "Applying patch for commit: {}",
                         commit_description
+                    ));
+                    let commit_tree = match repo.cherry_pick_fast(
+                        &commit_to_apply,
+                        &current_commit,
+                        &CherryPickFastOptions {
+                            reuse_parent_tree_if_possible: true,
+                        },
+                    )? {
+                        Ok(rebased_commit) => rebased_commit,
+                        Err(CherryPickFastError::MergeConflict { conflicting_paths }) => {
+                            return Ok(RebaseInMemoryResult::MergeConflict(MergeConflictInfo {
+                                commit_oid: *commit_oid,
+                                conflicting_paths,
+                            }))
+                        }
+                    };
+
+                    progress
+                        .notify_status(format!("Committing to repository: {}", commit_description));
+                    let (base_commit, parents) = if is_foldable {
+                        (&current_commit, current_commit.get_parents())
+                    } else {
+                        (&commit_to_apply, vec![current_commit.clone()])
+                    };
+                    let commit_message = match kind {
+                        FixupKind::Squash if is_foldable => {
+                            let message = base_commit.get_message_raw()?;
+                            let squash_message = commit_to_apply.get_message_raw()?;
+                            let squash_body = squash_message
+                                .split_once_str("\n")
+                                .map(|(_subject, body)| body.trim())
+                                .unwrap_or_default();
+                            if squash_body.is_empty() {
+                                message
+                            } else {
+                                let mut message = BString::from(message.trim_end());
+                                message.push_str("\n\n");
+                                message.push_str(squash_body);
+                                message.push_str("\n");
+                                message
+                            }
+                        }
+                        FixupKind::Fixup | FixupKind::Squash => base_commit.get_message_raw()?,
+                    };
+                    let committer_signature = if *preserve_timestamps {
+                        base_commit.get_committer()
+                    } else {
+                        base_commit.get_committer().update_timestamp(*now)?
+                    };
+                    let fixed_up_commit_oid = repo
+                        .create_commit_with_encoding(
+                            &base_commit.get_author(),
+                            &committer_signature,
+                            commit_message.as_slice(),
+                            base_commit.get_message_encoding(),
+                            &commit_tree,
+                            parents.iter().collect(),
+                        )
+                        .wrap_err("Applying folded commit")?;
+
+                    let fixed_up_commit = repo
+                        .find_commit_or_fail(fixed_up_commit_oid)
+                        .wrap_err("Looking up just-folded commit")?;
+                    let fixed_up_commit_description = printable_styled_string(
+                        effects.get_glyphs(),
+                        repo.friendly_describe_commit_from_oid(
+                            effects.get_glyphs(),
+                            fixed_up_commit_oid,
+                        )?,
                     )?;
+                    if !is_foldable {
+                        rewritten_oids
+                            .push((*commit_oid, MaybeZeroOid::NonZero(fixed_up_commit_oid)));
+                        current_oid = fixed_up_commit_oid;
+                        writeln!(
+                            effects.get_output_stream(),
+                            "{}",
+                            effects.tr(
+                                "rebase-committed-as",
+                                &[
+                                    ("progress", commit_num.into()),
+                                    ("commit", fixed_up_commit_description.into()),
+                                ]
+                            )
+                        )?;
+                    } else if fixed_up_commit.is_empty() {
+                        // The folded commit undid all the changes of the
+                        // commit that it was folded into, so skip both.
+                        let new_current_oid = match parents.as_slice() {
+                            [parent] => parent.get_oid(),
+                            _ => eyre::bail!(
+                                "BUG: Commit {} being folded into should have exactly 1 parent",
+                                current_oid
+                            ),
+                        };
+                        for (original_oid, new_oid) in rewritten_oids.iter_mut() {
+                            if *new_oid == MaybeZeroOid::NonZero(current_oid) {
+                                *new_oid = MaybeZeroOid::Zero;
+                                maybe_set_skipped_head_new_oid(*original_oid, new_current_oid);
+                            }
+                        }
+                        rewritten_oids.push((*commit_oid, MaybeZeroOid::Zero));
+                        maybe_set_skipped_head_new_oid(*commit_oid, new_current_oid);
+                        current_oid = new_current_oid;
+
+                        writeln!(
+                            effects.get_output_stream(),
+                            "{}",
+                            effects.tr(
+                                "rebase-skipped-empty",
+                                &[
+                                    ("progress", commit_num.into()),
+                                    ("commit", fixed_up_commit_description.into()),
+                                ]
+                            )
+                        )?;
+                    } else {
+                        for (_original_oid, new_oid) in rewritten_oids.iter_mut() {
+                            if *new_oid == MaybeZeroOid::NonZero(current_oid) {
+                                *new_oid = MaybeZeroOid::NonZero(fixed_up_commit_oid);
+                            }
+                        }
+                        current_oid = fixed_up_commit_oid;
+
+                        writeln!(
+                            effects.get_output_stream(),
+                            "{}",
+                            effects.tr(
+                                "rebase-folded",
+                                &[
+                                    ("progress", commit_num.into()),
+                                    (
+                                        "kind",
+                                        match kind {
+                                            FixupKind::Fixup => "fixup",
+                                            FixupKind::Squash => "squash",
+                                        }
+                                        .into()
+                                    ),
+                                    ("commit", commit_description.into()),
+                                    ("target", fixed_up_commit_description.into()),
+                                ]
+                            )
+                        )?;
+                    }
+                }
+
+                RebaseCommand::SkipFoldedCommit { commit_oid } => {
+                    // The commit was already folded into an ancestor, so
+                    // anything which pointed to it should now point to the
+                    // current commit instead.
+                    rewritten_oids.push((*commit_oid, MaybeZeroOid::NonZero(current_oid)));
                 }
 
                 RebaseCommand::RegisterExtraPostRewriteHook
@@ -700,20 +1116,66 @@ mod in_memory {
             }
         }
 
-        let head_info = repo.get_head_info()?;
-        if head_info.oid.is_some() {
-            // Avoid moving the branch which HEAD points to, or else the index will show
-            // a lot of changes in the working copy.
-            repo.detach_head(&head_info)?;
-        }
+        // Determine all of the branch moves before changing anything, so that
+        // an invalid rewrite doesn't leave the repository partially updated.
+        let branch_moves = plan_branch_moves(repo, &rewritten_oids_map)?;
 
-        move_branches(
+        // If the branches can't be moved, or if `HEAD` can't be checked out
+        // afterwards, roll back the references which were already updated as
+        // part of this event transaction, rather than leaving the stack
+        // half-updated. This has to be decided before the `post-rewrite` hook
+        // is called, since the hook's effects can't be undone.
+        let head_info = repo.get_head_info()?;
+        let last_event_rowid = event_log_db.get_last_event_rowid()?;
+        let result = move_branches_for_rebase(
             effects,
             git_run_info,
             repo,
-            *event_tx_id,
             &rewritten_oids_map,
-        )?;
+            &branch_moves,
+            &head_info,
+            skipped_head_updated_oid,
+            options,
+        );
+        match result {
+            Ok(exit_code) if exit_code.is_success() => {}
+            result => {
+                let rolled_back_branches = roll_back_transaction(
+                    effects,
+                    git_run_info,
+                    repo,
+                    event_log_db,
+                    *event_tx_id,
+                    last_event_rowid,
+                    &head_info,
+                )?;
+                let message = if rolled_back_branches.is_empty() {
+                    effects.tr("rebase-rolled-back", &[])
+                } else {
+                    effects.tr(
+                        "rebase-rolled-back-branches",
+                        &[
+                            ("count", rolled_back_branches.len().into()),
+                            (
+                                "branches",
+                                rolled_back_branches
+                                    .iter()
+                                    .map(|name| CategorizedReferenceName::new(name).render_suffix())
+                                    .join(", ")
+                                    .into(),
+                            ),
+                        ],
+                    )
+                };
+                return match result {
+                    Ok(exit_code) => {
+                        writeln!(effects.get_output_stream(), "{}", message)?;
+                        Ok(exit_code)
+                    }
+                    Err(err) => Err(err.wrap_err(message)),
+                };
+            }
+        }
 
         // Call the `post-rewrite` hook only after moving branches so that we don't
         // produce a spurious abandoned-branch warning.
@@ -730,6 +1192,8 @@ mod in_memory {
             &["rebase"],
             Some(post_rewrite_stdin),
         )?;
+        sync_stack_branches(effects, git_run_info, repo, *event_tx_id)?;
+        run_post_rewrite_maintenance(effects, git_run_info, repo, rewritten_oids_map.len())?;
 
         let exit_code = check_out_updated_head(
             effects,
@@ -744,6 +1208,53 @@ mod in_memory {
         )?;
         Ok(exit_code)
     }
+
+    /// Move the branches after an in-memory rebase, and check that the new
+    /// `HEAD` can be checked out afterwards.
+    #[allow(clippy::too_many_arguments)]
+    fn move_branches_for_rebase(
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        repo: &Repo,
+        rewritten_oids_map: &HashMap<NonZeroOid, MaybeZeroOid>,
+        branch_moves: &[ReferenceUpdate],
+        head_info: &ResolvedReferenceInfo,
+        skipped_head_updated_oid: Option<NonZeroOid>,
+        options: &ExecuteRebasePlanOptions,
+    ) -> eyre::Result<ExitCode> {
+        let ExecuteRebasePlanOptions {
+            now: _,
+            event_tx_id,
+            preserve_timestamps: _,
+            force_in_memory: _,
+            force_on_disk: _,
+            resolve_merge_conflicts: _,
+            check_out_commit_options,
+        } = options;
+
+        if head_info.oid.is_some() {
+            // Avoid moving the branch which HEAD points to, or else the index will show
+            // a lot of changes in the working copy.
+            repo.detach_head(head_info)?;
+        }
+
+        apply_branch_moves(effects, git_run_info, repo, *event_tx_id, branch_moves)?;
+
+        if can_check_out_updated_head(
+            effects,
+            git_run_info,
+            repo,
+            *event_tx_id,
+            rewritten_oids_map,
+            head_info,
+            skipped_head_updated_oid,
+            check_out_commit_options,
+        )? {
+            Ok(ExitCode(0))
+        } else {
+            Ok(ExitCode(1))
+        }
+    }
 }
 
 mod on_disk {
@@ -963,7 +1474,8 @@ mod on_disk {
 
         writeln!(
             effects.get_output_stream(),
-            "Calling Git for on-disk rebase..."
+            "{}",
+            effects.tr("rebase-calling-git-on-disk", &[])
         )?;
         let exit_code = git_run_info.run(effects, Some(*event_tx_id), &["rebase", "--continue"])?;
         Ok(Ok(exit_code))
@@ -1023,6 +1535,66 @@ pub enum ExecuteRebasePlanResult {
     },
 }
 
+/// The result of simulating a rebase plan with [`simulate_rebase_plan`].
+#[must_use]
+#[derive(Debug)]
+pub enum SimulateRebasePlanResult {
+    /// The rebase plan could be carried out in-memory. The rewritten commits
+    /// have been written to the object database, but no references were
+    /// updated and no events were recorded.
+    Succeeded {
+        /// Mapping from old OID to new/rewritten OID.
+        rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid>,
+    },
+
+    /// The rebase plan would encounter a merge conflict.
+    MergeConflict {
+        /// Information about the merge conflict that would occur.
+        merge_conflict: MergeConflictInfo,
+    },
+
+    /// The rebase plan can't be carried out in-memory, so its result can't be
+    /// determined without rebasing on-disk.
+    CannotSimulate {
+        /// A description of why the rebase plan couldn't be simulated.
+        reason: String,
+    },
+}
+
+/// Carry out the provided rebase plan in-memory without updating any
+/// references, such as to show what a command would do with `--dry-run`.
+pub fn simulate_rebase_plan(
+    effects: &Effects,
+    repo: &Repo,
+    rebase_plan: &RebasePlan,
+    options: &ExecuteRebasePlanOptions,
+) -> eyre::Result<SimulateRebasePlanResult> {
+    use in_memory::*;
+    let result = match rebase_in_memory(effects, repo, rebase_plan, options)? {
+        RebaseInMemoryResult::Succeeded {
+            rewritten_oids,
+            new_head_oid: _,
+        } => SimulateRebasePlanResult::Succeeded {
+            rewritten_oids: rewritten_oids.into_iter().collect(),
+        },
+        RebaseInMemoryResult::MergeConflict(merge_conflict) => {
+            SimulateRebasePlanResult::MergeConflict { merge_conflict }
+        }
+        RebaseInMemoryResult::CannotRebaseMergeCommit { commit_oid } => {
+            SimulateRebasePlanResult::CannotSimulate {
+                reason: format!(
+                    "Merge commits can't be rebased in-memory, and the merge commit {} would be rebased.",
+                    commit_oid
+                ),
+            }
+        }
+        RebaseInMemoryResult::Interrupted => SimulateRebasePlanResult::CannotSimulate {
+            reason: "The simulated rebase was interrupted.".to_string(),
+        },
+    };
+    Ok(result)
+}
+
 /// Execute the provided rebase plan. Returns the exit status (zero indicates
 /// success).
 pub fn execute_rebase_plan(
@@ -1034,8 +1606,8 @@ pub fn execute_rebase_plan(
     options: &ExecuteRebasePlanOptions,
 ) -> eyre::Result<ExecuteRebasePlanResult> {
     let ExecuteRebasePlanOptions {
-        now: _,
-        event_tx_id: _,
+        now,
+        event_tx_id,
         preserve_timestamps: _,
         force_in_memory,
         force_on_disk,
@@ -1043,11 +1615,16 @@ pub fn execute_rebase_plan(
         check_out_commit_options: _,
     } = options;
 
+    // Defer interrupts until the in-memory rebase reaches a point where it can
+    // stop cleanly, and so that branches aren't left half-moved.
+    let _interrupt_guard = effects.catch_interrupts()?;
+
     if !force_on_disk {
         use in_memory::*;
         writeln!(
             effects.get_output_stream(),
-            "Attempting rebase in-memory..."
+            "{}",
+            effects.tr("rebase-attempting-in-memory", &[])
         )?;
 
         match rebase_in_memory(effects, repo, rebase_plan, options)? {
@@ -1055,14 +1632,11 @@ pub fn execute_rebase_plan(
                 rewritten_oids,
                 new_head_oid,
             } => {
-                // Ignore the return code, as it probably indicates that the
-                // checkout failed (which might happen if the user has changes
-                // which don't merge cleanly). The user can resolve that
-                // themselves.
-                //
-                // FIXME: we may still want to propagate the exit code to the
-                // caller.
-                let ExitCode(_exit_code) = post_rebase_in_memory(
+                // If the branches couldn't be updated or the new `HEAD`
+                // couldn't be checked out (such as because the user has
+                // changes which would be overwritten), then the rebase was
+                // rolled back.
+                let exit_code = post_rebase_in_memory(
                     effects,
                     git_run_info,
                     repo,
@@ -1071,10 +1645,17 @@ pub fn execute_rebase_plan(
                     new_head_oid,
                     options,
                 )?;
+                if !exit_code.is_success() {
+                    return Ok(ExecuteRebasePlanResult::Failed { exit_code });
+                }
 
                 let rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid> =
                     rewritten_oids.into_iter().collect();
-                writeln!(effects.get_output_stream(), "In-memory rebase succeeded.")?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "{}",
+                    effects.tr("rebase-in-memory-succeeded", &[])
+                )?;
                 return Ok(ExecuteRebasePlanResult::Succeeded {
                     rewritten_oids: Some(rewritten_oids),
                 });
@@ -1083,19 +1664,37 @@ pub fn execute_rebase_plan(
             RebaseInMemoryResult::CannotRebaseMergeCommit { commit_oid } => {
                 writeln!(
                     effects.get_output_stream(),
-                    "Merge commits currently can't be rebased in-memory."
+                    "{}",
+                    effects.tr("rebase-merge-commit-unsupported", &[])
                 )?;
                 writeln!(
                     effects.get_output_stream(),
-                    "The merge commit was: {}",
-                    printable_styled_string(
-                        effects.get_glyphs(),
-                        repo.friendly_describe_commit_from_oid(effects.get_glyphs(), commit_oid)?
-                    )?,
+                    "{}",
+                    effects.tr(
+                        "rebase-merge-commit-was",
+                        &[(
+                            "commit",
+                            printable_styled_string(
+                                effects.get_glyphs(),
+                                repo.friendly_describe_commit_from_oid(
+                                    effects.get_glyphs(),
+                                    commit_oid
+                                )?
+                            )?
+                            .into()
+                        )]
+                    )
                 )?;
             }
 
             RebaseInMemoryResult::MergeConflict(merge_conflict) => {
+                event_log_db.add_merge_conflict(
+                    *now,
+                    *event_tx_id,
+                    merge_conflict.commit_oid,
+                    merge_conflict.conflicting_paths.len(),
+                )?;
+
                 if !resolve_merge_conflicts
                     // If an in-memory rebase was forced, don't suggest to the user
                     // that they can re-run with `--merge`, since that still won't
@@ -1111,17 +1710,39 @@ pub fn execute_rebase_plan(
                 } = merge_conflict;
                 writeln!(
                     effects.get_output_stream(),
-                    "There was a merge conflict, which currently can't be resolved when rebasing in-memory."
+                    "{}",
+                    effects.tr("rebase-merge-conflict-unsupported", &[])
                 )?;
                 writeln!(
                     effects.get_output_stream(),
-                    "The conflicting commit was: {}",
-                    printable_styled_string(
-                        effects.get_glyphs(),
-                        repo.friendly_describe_commit_from_oid(effects.get_glyphs(), commit_oid)?
-                    )?,
+                    "{}",
+                    effects.tr(
+                        "rebase-conflicting-commit-was",
+                        &[(
+                            "commit",
+                            printable_styled_string(
+                                effects.get_glyphs(),
+                                repo.friendly_describe_commit_from_oid(
+                                    effects.get_glyphs(),
+                                    commit_oid
+                                )?
+                            )?
+                            .into()
+                        )]
+                    )
                 )?;
             }
+
+            RebaseInMemoryResult::Interrupted => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "{}",
+                    effects.tr("rebase-interrupted-in-memory", &[])
+                )?;
+                return Ok(ExecuteRebasePlanResult::Failed {
+                    exit_code: ErrorCategory::Interrupted.exit_code(),
+                });
+            }
         }
 
         // The rebase has failed at this point, decide whether or not to try
@@ -1129,13 +1750,18 @@ pub fn execute_rebase_plan(
         if *force_in_memory {
             writeln!(
                 effects.get_output_stream(),
-                "Aborting since an in-memory rebase was requested."
+                "{}",
+                effects.tr("rebase-aborting-in-memory-requested", &[])
             )?;
             return Ok(ExecuteRebasePlanResult::Failed {
                 exit_code: ExitCode(1),
             });
         } else {
-            writeln!(effects.get_output_stream(), "Trying again on-disk...")?;
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                effects.tr("rebase-trying-on-disk", &[])
+            )?;
         }
     }
 
@@ -1147,30 +1773,40 @@ pub fn execute_rebase_plan(
                     rewritten_oids: None,
                 });
             }
+            Ok(_exit_code) if effects.is_interrupted() => {
+                // Git stops the rebase partway through, but leaves its state
+                // on disk so that it can be resumed.
+                writeln!(
+                    effects.get_output_stream(),
+                    "{}",
+                    effects.tr("rebase-interrupted-on-disk", &[])
+                )?;
+                return Ok(ExecuteRebasePlanResult::Failed {
+                    exit_code: ErrorCategory::Interrupted.exit_code(),
+                });
+            }
             Ok(exit_code) => return Ok(ExecuteRebasePlanResult::Failed { exit_code }),
             Err(Error::ChangedFilesInRepository) => {
-                write!(
+                writeln!(
                     effects.get_output_stream(),
-                    "\
-This operation would modify the working copy, but you have uncommitted changes
-in your working copy which might be overwritten as a result.
-Commit your changes and then try again.
-"
+                    "{}",
+                    effects.tr("rebase-uncommitted-changes", &[])
                 )?;
                 return Ok(ExecuteRebasePlanResult::Failed {
                     exit_code: ExitCode(1),
                 });
             }
             Err(Error::OperationAlreadyInProgress { operation_type }) => {
+                let args = [("operation", operation_type.to_string().into())];
                 writeln!(
                     effects.get_output_stream(),
-                    "A {} operation is already in progress.",
-                    operation_type
+                    "{}",
+                    effects.tr("rebase-operation-in-progress", &args)
                 )?;
                 writeln!(
                     effects.get_output_stream(),
-                    "Run git {0} --continue or git {0} --abort to resolve it and proceed.",
-                    operation_type
+                    "{}",
+                    effects.tr("rebase-operation-in-progress-hint", &args)
                 )?;
                 return Ok(ExecuteRebasePlanResult::Failed {
                     exit_code: ExitCode(1),
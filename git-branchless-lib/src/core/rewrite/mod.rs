@@ -4,18 +4,20 @@ mod evolve;
 mod execute;
 mod plan;
 pub mod rewrite_hooks;
+mod summary;
 
 use std::sync::Mutex;
 
-pub use evolve::{find_abandoned_children, find_rewrite_target};
+pub use evolve::{find_abandoned_children, find_rewrite_target, RewriteMap};
 pub use execute::{
-    execute_rebase_plan, move_branches, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    MergeConflictInfo, MergeConflictRemediation,
+    execute_rebase_plan, move_branches, simulate_rebase_plan, ExecuteRebasePlanOptions,
+    ExecuteRebasePlanResult, MergeConflictInfo, MergeConflictRemediation, SimulateRebasePlanResult,
 };
 pub use plan::{
-    BuildRebasePlanError, BuildRebasePlanOptions, RebasePlan, RebasePlanBuilder,
+    BuildRebasePlanError, BuildRebasePlanOptions, FixupKind, RebasePlan, RebasePlanBuilder,
     RebasePlanPermissions,
 };
+pub use summary::{ConflictSummary, MovedBranch, RewriteSummary, RewrittenCommit};
 use tracing::instrument;
 
 use crate::core::task::{Resource, ResourcePool};
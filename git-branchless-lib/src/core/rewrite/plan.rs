@@ -10,12 +10,15 @@ use itertools::Itertools;
 use rayon::{prelude::*, ThreadPool};
 use tracing::{instrument, warn};
 
-use crate::core::dag::{commit_set_to_vec_unsorted, CommitSet, Dag};
-use crate::core::effects::{Effects, OperationType};
+use crate::core::config::get_protected_branch_patterns;
+use crate::core::dag::{commit_set_to_vec_unsorted, sorted_commit_set, CommitSet, Dag};
+use crate::core::effects::{Effects, OperationType, ProgressHandle};
 use crate::core::formatting::{printable_styled_string, Pluralize};
+use crate::core::patch_ids::get_patch_ids;
+use crate::core::repo_ext::RepoExt;
 use crate::core::rewrite::{RepoPool, RepoResource};
 use crate::core::task::ResourcePool;
-use crate::git::{Commit, NonZeroOid, PatchId, Repo};
+use crate::git::{CategorizedReferenceName, Commit, NonZeroOid, PatchId, Repo};
 
 #[derive(Debug)]
 pub enum OidOrLabel {
@@ -71,6 +74,61 @@ pub enum RebaseCommand {
     /// The commit that would have been applied to the rebase head was already
     /// applied upstream. Skip it and record it in the `rewritten-list`.
     SkipUpstreamAppliedCommit { commit_oid: NonZeroOid },
+
+    /// Apply the provided commit on top of the rebase head, and fold it into
+    /// the rebase head, rather than creating a new commit.
+    Fixup {
+        commit_oid: NonZeroOid,
+        kind: FixupKind,
+    },
+
+    /// The commit was folded into another commit with [`RebaseCommand::Fixup`].
+    /// Skip it and record in the `rewritten-list` that it was rewritten into
+    /// the rebase head, so that any branches pointing to it stay in place.
+    SkipFoldedCommit { commit_oid: NonZeroOid },
+}
+
+/// How a commit is folded into another commit. See
+/// [`RebasePlanBuilder::fixup_commit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FixupKind {
+    /// Keep the message of the commit being folded into, as with
+    /// `git commit --fixup`.
+    Fixup,
+
+    /// Combine the messages of both commits, as with `git commit --squash`.
+    Squash,
+}
+
+impl FixupKind {
+    /// Determine the kind of fixup commit from its subject line, and the subject
+    /// or hash of the commit that it refers to, as written by `git commit
+    /// --fixup` or `git commit --squash`. Returns `None` if the commit isn't a
+    /// fixup commit.
+    pub fn parse_subject(subject: &str) -> Option<(Self, &str)> {
+        let (kind, mut target) = if let Some(target) = subject.strip_prefix("fixup! ") {
+            (Self::Fixup, target)
+        } else if let Some(target) = subject.strip_prefix("squash! ") {
+            (Self::Squash, target)
+        } else {
+            return None;
+        };
+
+        // A fixup of a fixup commit refers to the same commit, since they'll
+        // all be folded into it.
+        while let Some(rest) = target
+            .strip_prefix("fixup! ")
+            .or_else(|| target.strip_prefix("squash! "))
+        {
+            target = rest;
+        }
+        let target = target.trim();
+        if target.is_empty() {
+            None
+        } else {
+            Some((kind, target))
+        }
+    }
 }
 
 /// Represents a sequence of commands that can be executed to carry out a rebase
@@ -81,6 +139,37 @@ pub struct RebasePlan {
     pub(super) commands: Vec<RebaseCommand>,
 }
 
+impl RebasePlan {
+    /// Get the original commits which will be rewritten by this plan,
+    /// including those which will be skipped because they're empty or have
+    /// already been applied upstream.
+    pub fn get_rewritten_commit_oids(&self) -> HashSet<NonZeroOid> {
+        self.commands
+            .iter()
+            .filter_map(|command| match command {
+                RebaseCommand::Pick {
+                    original_commit_oid,
+                    commit_to_apply_oid: _,
+                } => Some(*original_commit_oid),
+                RebaseCommand::Merge {
+                    commit_oid,
+                    commits_to_merge: _,
+                }
+                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid }
+                | RebaseCommand::Fixup {
+                    commit_oid,
+                    kind: _,
+                }
+                | RebaseCommand::SkipFoldedCommit { commit_oid } => Some(*commit_oid),
+                RebaseCommand::CreateLabel { .. }
+                | RebaseCommand::Reset { .. }
+                | RebaseCommand::RegisterExtraPostRewriteHook
+                | RebaseCommand::DetectEmptyCommit { .. } => None,
+            })
+            .collect()
+    }
+}
+
 impl ToString for RebaseCommand {
     fn to_string(&self) -> String {
         match self {
@@ -116,10 +205,56 @@ impl ToString for RebaseCommand {
                     commit_oid
                 )
             }
+            RebaseCommand::Fixup {
+                commit_oid,
+                kind: FixupKind::Fixup,
+            } => format!("fixup {}", commit_oid),
+            RebaseCommand::Fixup {
+                commit_oid,
+                kind: FixupKind::Squash,
+            } => format!("squash {}", commit_oid),
+            RebaseCommand::SkipFoldedCommit { commit_oid } => {
+                format!("exec git branchless hook-skip-folded-commit {}", commit_oid)
+            }
         }
     }
 }
 
+/// Find the names of the branches matching `branchless.core.protectedBranches`
+/// which point to any of the given commits, and so would be moved or deleted
+/// if those commits were rewritten.
+#[instrument]
+fn find_protected_branches(repo: &Repo, commits: &CommitSet) -> eyre::Result<Vec<String>> {
+    let patterns = get_protected_branch_patterns(repo)?
+        .into_iter()
+        .filter_map(|pattern| match glob::Pattern::new(&pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(err) => {
+                warn!(?pattern, ?err, "Could not parse protected branch pattern");
+                None
+            }
+        })
+        .collect_vec();
+    if patterns.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut result = Vec::new();
+    for (oid, names) in repo.get_branch_oid_to_names()? {
+        if !commits.contains(&oid.into())? {
+            continue;
+        }
+        for name in names {
+            let name = CategorizedReferenceName::new(&name).remove_prefix()?;
+            if patterns.iter().any(|pattern| pattern.matches(&name)) {
+                result.push(name);
+            }
+        }
+    }
+    result.sort_unstable();
+    Ok(result)
+}
+
 /// A token representing that the rebase plan has been checked for validity.
 #[derive(Clone, Debug)]
 pub struct RebasePlanPermissions<'a> {
@@ -131,9 +266,11 @@ impl<'a> RebasePlanPermissions<'a> {
     /// Construct a new `RebasePlanPermissions`.
     pub fn verify_rewrite_set(
         dag: &Dag,
+        repo: &Repo,
         build_options: &'a BuildRebasePlanOptions,
         commits: &CommitSet,
     ) -> eyre::Result<Result<Self, BuildRebasePlanError>> {
+        let requested_commits = commits;
         // This isn't necessary for correctness, but helps to produce a better
         // error message which indicates the magnitude of the issue.
         let commits = dag.query().descendants(commits.clone())?;
@@ -142,8 +279,23 @@ impl<'a> RebasePlanPermissions<'a> {
         if !build_options.force_rewrite_public_commits {
             let public_commits_to_move = public_commits.intersection(&commits);
             if !public_commits_to_move.is_empty()? {
+                let public_commit_infos =
+                    get_public_commit_infos(repo, dag, &public_commits_to_move)?;
+                let draft_revset =
+                    make_draft_revset(repo, dag, requested_commits, &public_commits)?;
                 return Ok(Err(BuildRebasePlanError::MovePublicCommits {
                     public_commits_to_move,
+                    public_commit_infos,
+                    draft_revset,
+                }));
+            }
+        }
+
+        if !build_options.force_rewrite_protected_branches {
+            let protected_branch_names = find_protected_branches(repo, &commits)?;
+            if !protected_branch_names.is_empty() {
+                return Ok(Err(BuildRebasePlanError::MoveProtectedBranches {
+                    protected_branch_names,
                 }));
             }
         }
@@ -477,6 +629,14 @@ pub struct RebasePlanBuilder<'a> {
     /// with.
     replacement_commits: HashMap<NonZeroOid, NonZeroOid>,
 
+    /// Mapping of commits to the commits which should be folded into them, in
+    /// the order that they should be applied.
+    fixup_commits: HashMap<NonZeroOid, Vec<(NonZeroOid, FixupKind)>>,
+
+    /// The commits which are folded into other commits, and so shouldn't be
+    /// applied in their original location.
+    folded_commits: HashSet<NonZeroOid>,
+
     /// Cache mapping from commit OID to the paths changed in the diff for that
     /// commit. The value is `None` if the commit doesn't have an associated
     /// diff (i.e. is a merge commit).
@@ -507,6 +667,10 @@ pub struct BuildRebasePlanOptions {
     /// to those commits.
     pub force_rewrite_public_commits: bool,
 
+    /// Force moving or deleting branches which match
+    /// `branchless.core.protectedBranches`.
+    pub force_rewrite_protected_branches: bool,
+
     /// Print the rebase constraints for debugging.
     pub dump_rebase_constraints: bool,
 
@@ -519,6 +683,123 @@ pub struct BuildRebasePlanOptions {
     pub detect_duplicate_commits_via_patch_id: bool,
 }
 
+/// The reasons that a commit is public, for explaining why it can't be
+/// rewritten.
+#[derive(Debug)]
+pub struct PublicCommitInfo {
+    /// The public commit.
+    pub commit_oid: NonZeroOid,
+
+    /// The names of the main branches which the commit is reachable from.
+    pub main_branch_names: Vec<String>,
+
+    /// The name of the newest tag which contains the commit, if any.
+    pub newest_tag_name: Option<String>,
+}
+
+/// The maximum number of public commits to explain in a
+/// [`BuildRebasePlanError::MovePublicCommits`] error.
+const MAX_PUBLIC_COMMIT_INFOS: usize = 5;
+
+/// Explain why some of the given public commits are public.
+#[instrument]
+fn get_public_commit_infos(
+    repo: &Repo,
+    dag: &Dag,
+    public_commits: &CommitSet,
+) -> eyre::Result<Vec<PublicCommitInfo>> {
+    let mut main_branches = vec![repo.get_main_branch_reference()?];
+    main_branches.extend(repo.get_additional_main_branch_references()?);
+    main_branches.extend(repo.get_public_remote_branch_references()?);
+    let mut main_branch_ancestors = Vec::new();
+    for reference in main_branches {
+        if let Some(commit) = reference.peel_to_commit()? {
+            let name = reference.get_name()?;
+            let name = CategorizedReferenceName::new(&name).render_suffix();
+            let ancestors = dag.query().ancestors(CommitSet::from(commit.get_oid()))?;
+            main_branch_ancestors.push((name, ancestors));
+        }
+    }
+
+    let mut tags = Vec::new();
+    for reference in repo.get_all_references()? {
+        let name = reference.get_name()?;
+        let name = match name.as_str().strip_prefix("refs/tags/") {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        if let Some(commit) = reference.peel_to_commit()? {
+            tags.push((commit.get_time(), name, commit.get_oid()));
+        }
+    }
+    // Newest first, and by name for determinism.
+    tags.sort_by(|(lhs_time, lhs_name, _), (rhs_time, rhs_name, _)| {
+        rhs_time.cmp(lhs_time).then_with(|| lhs_name.cmp(rhs_name))
+    });
+    let tag_commits: CommitSet = tags.iter().map(|(_time, _name, oid)| *oid).collect();
+
+    // There may be a great many public commits, so only sort the ones which
+    // will be explained.
+    let mut sample_oids = Vec::new();
+    for vertex in public_commits.iter()?.take(MAX_PUBLIC_COMMIT_INFOS) {
+        sample_oids.push(NonZeroOid::try_from(vertex?)?);
+    }
+    let sample_commits: CommitSet = sample_oids.into_iter().collect();
+
+    let mut result = Vec::new();
+    for commit in sorted_commit_set(repo, dag, &sample_commits)? {
+        let commit_oid = commit.get_oid();
+        let mut main_branch_names = Vec::new();
+        for (name, ancestors) in main_branch_ancestors.iter() {
+            if ancestors.contains(&commit_oid.into())? {
+                main_branch_names.push(name.clone());
+            }
+        }
+        // Only tagged commits which are in the DAG are considered, which
+        // includes any tags on the main branch.
+        let tagged_descendants = dag
+            .query()
+            .descendants(CommitSet::from(commit_oid))?
+            .intersection(&tag_commits);
+        let mut newest_tag_name = None;
+        for (_time, name, tag_oid) in tags.iter() {
+            if tagged_descendants.contains(&(*tag_oid).into())? {
+                newest_tag_name = Some(name.clone());
+                break;
+            }
+        }
+        result.push(PublicCommitInfo {
+            commit_oid,
+            main_branch_names,
+            newest_tag_name,
+        });
+    }
+    Ok(result)
+}
+
+/// Make a revset which selects only the draft commits among the descendants of
+/// the given commits, or `None` if there aren't any.
+fn make_draft_revset(
+    repo: &Repo,
+    dag: &Dag,
+    commits: &CommitSet,
+    public_commits: &CommitSet,
+) -> eyre::Result<Option<String>> {
+    let descendants = dag.query().descendants(commits.clone())?;
+    if descendants.difference(public_commits).is_empty()? {
+        return Ok(None);
+    }
+    let roots = dag.query().roots(commits.clone())?;
+    let roots = sorted_commit_set(repo, dag, &roots)?
+        .into_iter()
+        .map(|commit| commit.get_short_oid())
+        .collect::<eyre::Result<Vec<_>>>()?;
+    Ok(Some(format!(
+        "draft() & descendants({})",
+        roots.join(" + ")
+    )))
+}
+
 /// An error caused when attempting to build a rebase plan.
 #[derive(Debug)]
 pub enum BuildRebasePlanError {
@@ -532,6 +813,21 @@ pub enum BuildRebasePlanError {
     MovePublicCommits {
         /// The public commits which the user was trying to move.
         public_commits_to_move: CommitSet,
+
+        /// Explanations of why some of the public commits are public, in
+        /// topological order.
+        public_commit_infos: Vec<PublicCommitInfo>,
+
+        /// A revset which selects only the draft commits out of the ones which
+        /// the user was trying to move, if there are any.
+        draft_revset: Option<String>,
+    },
+
+    /// The user was trying to move or delete protected branches.
+    MoveProtectedBranches {
+        /// The names of the protected branches which would be moved or
+        /// deleted.
+        protected_branch_names: Vec<String>,
     },
 
     /// The user was trying to move commits that weren't verified before the
@@ -586,6 +882,8 @@ impl BuildRebasePlanError {
 
             BuildRebasePlanError::MovePublicCommits {
                 public_commits_to_move,
+                public_commit_infos,
+                draft_revset,
             } => {
                 let example_bad_commit_oid = public_commits_to_move.first()?.ok_or_else(|| {
                     eyre::eyre!("BUG: could not get OID of a public commit to move")
@@ -597,8 +895,7 @@ impl BuildRebasePlanError {
                     "\
 You are trying to rewrite {}, such as: {}
 It is generally not advised to rewrite public commits, because your
-collaborators will have difficulty merging your changes.
-Retry with -f/--force-rewrite to proceed anyways.",
+collaborators will have difficulty merging your changes.",
                     Pluralize {
                         determiner: None,
                         amount: public_commits_to_move.count()?,
@@ -609,6 +906,77 @@ Retry with -f/--force-rewrite to proceed anyways.",
                         example_bad_commit.friendly_describe(effects.get_glyphs())?
                     )?,
                 )?;
+
+                if !public_commit_infos.is_empty() {
+                    writeln!(effects.get_output_stream(), "These commits are public:")?;
+                    for PublicCommitInfo {
+                        commit_oid,
+                        main_branch_names,
+                        newest_tag_name,
+                    } in public_commit_infos
+                    {
+                        let mut reasons = Vec::new();
+                        if !main_branch_names.is_empty() {
+                            reasons
+                                .push(format!("reachable from {}", main_branch_names.join(", ")));
+                        }
+                        if let Some(newest_tag_name) = newest_tag_name {
+                            reasons.push(format!("contained in tag {}", newest_tag_name));
+                        }
+                        let commit = repo.find_commit_or_fail(*commit_oid)?;
+                        writeln!(
+                            effects.get_output_stream(),
+                            "  {}{}",
+                            printable_styled_string(
+                                effects.get_glyphs(),
+                                commit.friendly_describe(effects.get_glyphs())?
+                            )?,
+                            if reasons.is_empty() {
+                                String::new()
+                            } else {
+                                format!(" ({})", reasons.join("; "))
+                            },
+                        )?;
+                    }
+                    let num_remaining = public_commits_to_move.count()? - public_commit_infos.len();
+                    if num_remaining > 0 {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "  ...and {} more",
+                            num_remaining
+                        )?;
+                    }
+                }
+
+                if let Some(draft_revset) = draft_revset {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "To rewrite only the draft commits, use the revset: '{}'",
+                        draft_revset
+                    )?;
+                }
+                writeln!(
+                    effects.get_output_stream(),
+                    "Retry with -f/--force-rewrite to proceed anyways."
+                )?;
+            }
+
+            BuildRebasePlanError::MoveProtectedBranches {
+                protected_branch_names,
+            } => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "\
+You are trying to move or delete {}: {}
+These branches match the patterns in branchless.core.protectedBranches.
+Retry with --force-rewrite-protected-branches to proceed anyways.",
+                    Pluralize {
+                        determiner: None,
+                        amount: protected_branch_names.len(),
+                        unit: ("protected branch", "protected branches")
+                    },
+                    protected_branch_names.join(", "),
+                )?;
             }
 
             BuildRebasePlanError::MoveIllegalCommits {
@@ -636,6 +1004,8 @@ impl<'a> RebasePlanBuilder<'a> {
             permissions,
             initial_constraints: Default::default(),
             replacement_commits: Default::default(),
+            fixup_commits: Default::default(),
+            folded_commits: Default::default(),
             touched_paths_cache: Default::default(),
         }
     }
@@ -658,6 +1028,7 @@ impl<'a> RebasePlanBuilder<'a> {
     fn make_rebase_plan_for_current_commit(
         &self,
         effects: &Effects,
+        progress: &ProgressHandle,
         repo: &Repo,
         state: &mut BuildState,
         previous_head_oid: NonZeroOid,
@@ -665,8 +1036,14 @@ impl<'a> RebasePlanBuilder<'a> {
         upstream_patch_ids: &HashSet<PatchId>,
         mut acc: Vec<RebaseCommand>,
     ) -> eyre::Result<Vec<RebaseCommand>> {
+        progress.notify_progress_inc(1);
+
         let patch_already_applied_upstream = {
-            if upstream_patch_ids.is_empty() {
+            if self.folded_commits.contains(&current_commit.get_oid()) {
+                // The commit will be skipped regardless, since it was folded
+                // into another commit.
+                false
+            } else if upstream_patch_ids.is_empty() {
                 // Save time in the common case that there are no
                 // similar-looking upstream commits, so that we don't have
                 // to calculate the diff for the patch ID.
@@ -680,7 +1057,13 @@ impl<'a> RebasePlanBuilder<'a> {
         };
 
         let acc = {
-            if patch_already_applied_upstream {
+            if self.folded_commits.contains(&current_commit.get_oid()) {
+                // The commit's changes were already applied along with the
+                // commit that it was folded into.
+                acc.push(RebaseCommand::SkipFoldedCommit {
+                    commit_oid: current_commit.get_oid(),
+                });
+            } else if patch_already_applied_upstream {
                 acc.push(RebaseCommand::SkipUpstreamAppliedCommit {
                     commit_oid: current_commit.get_oid(),
                 });
@@ -750,6 +1133,14 @@ impl<'a> RebasePlanBuilder<'a> {
                     original_commit_oid,
                     commit_to_apply_oid: commit_oid,
                 });
+                if let Some(fixup_commits) = self.fixup_commits.get(&original_commit_oid) {
+                    for (fixup_commit_oid, kind) in fixup_commits {
+                        acc.push(RebaseCommand::Fixup {
+                            commit_oid: *fixup_commit_oid,
+                            kind: *kind,
+                        });
+                    }
+                }
                 acc.push(RebaseCommand::DetectEmptyCommit {
                     commit_oid: current_commit.get_oid(),
                 });
@@ -797,6 +1188,7 @@ impl<'a> RebasePlanBuilder<'a> {
             let only_child_commit = child_commits.pop().unwrap();
             let acc = self.make_rebase_plan_for_current_commit(
                 effects,
+                progress,
                 repo,
                 state,
                 current_commit.get_oid(),
@@ -815,6 +1207,7 @@ impl<'a> RebasePlanBuilder<'a> {
             for child_commit in child_commits {
                 acc = self.make_rebase_plan_for_current_commit(
                     effects,
+                    progress,
                     repo,
                     state,
                     current_commit.get_oid(),
@@ -894,6 +1287,35 @@ impl<'a> RebasePlanBuilder<'a> {
         Ok(())
     }
 
+    /// Instruct the rebase planner to fold the commit at `fixup_oid` into the
+    /// commit at `target_oid`, which must be one of its ancestors, as with `git
+    /// rebase --autosquash`. The target commit and its descendants are
+    /// rebased in place, and the fixup commit is removed from its original
+    /// location.
+    pub fn fixup_commit(
+        &mut self,
+        fixup_oid: NonZeroOid,
+        target_oid: NonZeroOid,
+        kind: FixupKind,
+    ) -> eyre::Result<()> {
+        if !self.folded_commits.insert(fixup_oid) {
+            eyre::bail!(
+                "Attempting to fold commit {}. Refusing to fold a commit twice.",
+                fixup_oid
+            );
+        }
+        let target_parent_oid = self.dag.get_only_parent_oid(target_oid)?;
+        self.initial_constraints.push(Constraint::MoveSubtree {
+            parent_oid: target_parent_oid,
+            child_oid: target_oid,
+        });
+        self.fixup_commits
+            .entry(target_oid)
+            .or_default()
+            .push((fixup_oid, kind));
+        Ok(())
+    }
+
     /// Create the rebase plan. Returns `None` if there were no commands in the rebase plan.
     pub fn build(
         &self,
@@ -909,10 +1331,11 @@ impl<'a> RebasePlanBuilder<'a> {
             merge_commit_parent_labels: Default::default(),
         };
 
-        let (effects, _progress) = effects.start_operation(OperationType::BuildRebasePlan);
+        let (effects, progress) = effects.start_operation(OperationType::BuildRebasePlan);
 
         let BuildRebasePlanOptions {
             force_rewrite_public_commits: _,
+            force_rewrite_protected_branches: _,
             dump_rebase_constraints,
             dump_rebase_plan,
             detect_duplicate_commits_via_patch_id,
@@ -944,6 +1367,7 @@ impl<'a> RebasePlanBuilder<'a> {
 
         let repo = repo_pool.try_create()?;
         let roots = state.constraints.find_roots();
+        progress.notify_progress(0, state.constraints.commits_to_move().len());
         let mut acc = Vec::new();
         let mut first_dest_oid = None;
         for constraint in roots {
@@ -973,6 +1397,7 @@ impl<'a> RebasePlanBuilder<'a> {
             };
             acc = self.make_rebase_plan_for_current_commit(
                 &effects,
+                &progress,
                 &repo,
                 &mut state,
                 parent_oid,
@@ -1017,7 +1442,12 @@ impl<'a> RebasePlanBuilder<'a> {
                     commit_oid,
                     commits_to_merge: _,
                 }
-                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid } => Some(*commit_oid),
+                | RebaseCommand::SkipUpstreamAppliedCommit { commit_oid }
+                | RebaseCommand::SkipFoldedCommit { commit_oid } => Some(*commit_oid),
+                RebaseCommand::Fixup {
+                    commit_oid: _,
+                    kind: _,
+                } => None,
             })
             .collect();
         let missing_commit_oids = state
@@ -1074,33 +1504,12 @@ impl<'a> RebasePlanBuilder<'a> {
             )?
         };
 
-        // FIXME: we may recalculate common patch IDs many times, should be
-        // cached.
-        let (effects, progress) = effects.start_operation(OperationType::GetUpstreamPatchIds);
-        progress.notify_progress(0, path.len());
-        let result: HashSet<PatchId> = {
-            let path_oids = path
-                .into_iter()
-                .map(|commit| commit.get_oid())
-                .collect_vec();
-
-            pool.install(|| {
-                path_oids
-                    .into_par_iter()
-                    .map(|commit_oid| -> eyre::Result<Option<PatchId>> {
-                        let repo = repo_pool.try_create()?;
-                        let commit = match repo.find_commit(commit_oid)? {
-                            Some(commit) => commit,
-                            None => return Ok(None),
-                        };
-                        let result = repo.get_patch_id(&effects, &commit)?;
-                        Ok(result)
-                    })
-                    .inspect(|_| progress.notify_progress_inc(1))
-                    .filter_map(|result| result.transpose())
-                    .collect::<eyre::Result<HashSet<PatchId>>>()
-            })?
-        };
+        let path_oids = path
+            .into_iter()
+            .map(|commit| commit.get_oid())
+            .collect_vec();
+        let patch_ids = get_patch_ids(effects, pool, repo_pool, repo, &path_oids)?;
+        let result: HashSet<PatchId> = patch_ids.into_values().collect();
         Ok(result)
     }
 
@@ -1237,6 +1646,20 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_fixup_kind_parse_subject() {
+        assert_eq!(
+            FixupKind::parse_subject("fixup! create test1.txt"),
+            Some((FixupKind::Fixup, "create test1.txt"))
+        );
+        assert_eq!(
+            FixupKind::parse_subject("squash! fixup! create test1.txt"),
+            Some((FixupKind::Squash, "create test1.txt"))
+        );
+        assert_eq!(FixupKind::parse_subject("fixup! "), None);
+        assert_eq!(FixupKind::parse_subject("create test1.txt"), None);
+    }
+
     #[test]
     fn test_cache_shared_between_builders() -> eyre::Result<()> {
         let git = make_git()?;
@@ -1263,6 +1686,7 @@ mod tests {
 
         let build_options = BuildRebasePlanOptions {
             force_rewrite_public_commits: true,
+            force_rewrite_protected_branches: false,
             dump_rebase_constraints: false,
             dump_rebase_plan: false,
             detect_duplicate_commits_via_patch_id: true,
@@ -1914,6 +2338,7 @@ mod tests {
 
         let build_options = BuildRebasePlanOptions {
             force_rewrite_public_commits: false,
+            force_rewrite_protected_branches: false,
             dump_rebase_constraints: false,
             dump_rebase_plan: false,
             detect_duplicate_commits_via_patch_id: true,
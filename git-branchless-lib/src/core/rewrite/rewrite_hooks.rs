@@ -9,6 +9,7 @@ use std::path::Path;
 use std::time::SystemTime;
 
 use console::style;
+use cursive::utils::markup::StyledString;
 use eden_dag::DagAlgorithm;
 use eyre::Context;
 use itertools::Itertools;
@@ -16,12 +17,17 @@ use tempfile::NamedTempFile;
 use tracing::instrument;
 
 use crate::core::check_out::CheckOutCommitOptions;
-use crate::core::config::{get_hint_enabled, print_hint_suppression_notice, Hint};
+use crate::core::config::{
+    get_hint_enabled, get_restack_auto, get_restack_warn_abandoned_interval,
+    print_hint_suppression_notice, AutoRestack, Hint,
+};
 use crate::core::dag::Dag;
 use crate::core::effects::Effects;
 use crate::core::eventlog::{Event, EventLogDb, EventReplayer};
 use crate::core::formatting::{printable_styled_string, Pluralize};
+use crate::core::gc::run_post_rewrite_maintenance;
 use crate::core::repo_ext::RepoExt;
+use crate::core::stack_branches::sync_stack_branches;
 use crate::git::{
     CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
     ResolvedReferenceInfo,
@@ -101,12 +107,18 @@ fn add_rewritten_list_entries(
 /// Handle Git's `post-rewrite` hook.
 ///
 /// See the man-page for `githooks(5)`.
+///
+/// Returns the rewritten commits whose abandoned descendants should be
+/// restacked automatically, as configured by `branchless.restack.auto`.
 #[instrument]
 pub fn hook_post_rewrite(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     rewrite_type: &str,
-) -> eyre::Result<()> {
+) -> eyre::Result<Vec<NonZeroOid>> {
+    // Finish recording the rewritten commits and moving their branches even if
+    // the user presses Ctrl-C, since Git has already rewritten the commits.
+    let _interrupt_guard = effects.catch_interrupts()?;
     let now = SystemTime::now();
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
 
@@ -148,16 +160,23 @@ pub fn hook_post_rewrite(
     }
 
     event_log_db.add_events(events)?;
+    if rewrite_type == "rebase" {
+        // The rebase finished, so any operation which was suspended partway
+        // through it can no longer be continued or aborted.
+        event_log_db.clear_suspended_operation()?;
+    }
 
-    if repo
+    let is_branchless_rewrite = repo
         .get_rebase_state_dir_path()
         .join(EXTRA_POST_REWRITE_FILE_NAME)
-        .exists()
-    {
+        .exists();
+    if is_branchless_rewrite {
         // Make sure to resolve `ORIG_HEAD` before we potentially delete the
         // branch it points to, so that we can get the original OID of `HEAD`.
         let previous_head_info = load_original_head_info(&repo)?;
         move_branches(effects, git_run_info, &repo, event_tx_id, &rewritten_oids)?;
+        sync_stack_branches(effects, git_run_info, &repo, event_tx_id)?;
+        run_post_rewrite_maintenance(effects, git_run_info, &repo, rewritten_oids.len())?;
 
         let skipped_head_updated_oid = load_updated_head_oid(&repo)?;
         let exit_code = check_out_updated_head(
@@ -176,31 +195,117 @@ pub fn hook_post_rewrite(
         }
     }
 
-    let should_check_abandoned_commits = get_hint_enabled(&repo, Hint::RestackWarnAbandoned)?;
-    if should_check_abandoned_commits && !is_spurious_event {
-        let printed_hint = warn_abandoned(
-            effects,
-            &repo,
-            &conn,
-            &event_log_db,
-            rewritten_oids.keys().copied(),
-        )?;
-        if printed_hint {
-            print_hint_suppression_notice(effects, Hint::RestackWarnAbandoned)?;
+    if is_spurious_event {
+        return Ok(Vec::new());
+    }
+    match get_restack_auto(&repo)? {
+        AutoRestack::Off => {
+            let should_check_abandoned_commits =
+                get_hint_enabled(&repo, Hint::RestackWarnAbandoned)?;
+            if should_check_abandoned_commits {
+                let abandoned = find_abandoned(
+                    effects,
+                    &repo,
+                    &event_log_db,
+                    rewritten_oids.keys().copied(),
+                )?;
+                if abandoned.is_empty() {
+                    // Nothing to warn about.
+                } else if was_abandoned_warning_shown_recently(&repo, now)? {
+                    print_restack_hint(effects, &repo, &abandoned)?;
+                } else {
+                    warn_abandoned(effects, &repo, &abandoned)?;
+                    print_hint_suppression_notice(effects, Hint::RestackWarnAbandoned)?;
+                    record_abandoned_warning_shown(&repo, now)?;
+                }
+            }
+            Ok(Vec::new())
+        }
+
+        AutoRestack::Hint => {
+            let abandoned = find_abandoned(
+                effects,
+                &repo,
+                &event_log_db,
+                rewritten_oids.keys().copied(),
+            )?;
+            if !abandoned.is_empty() {
+                print_restack_hint(effects, &repo, &abandoned)?;
+            }
+            Ok(Vec::new())
+        }
+
+        // Commits abandoned by a rewrite which `git-branchless` performed will
+        // have been reported as part of that operation.
+        AutoRestack::Restack if is_branchless_rewrite => Ok(Vec::new()),
+        AutoRestack::Restack => {
+            let abandoned = find_abandoned(
+                effects,
+                &repo,
+                &event_log_db,
+                rewritten_oids.keys().copied(),
+            )?;
+            Ok(abandoned.rewritten_commit_oids)
         }
     }
+}
 
-    Ok(())
+/// The commits and branches left behind by a rewrite.
+#[derive(Debug)]
+struct AbandonedCommits {
+    /// The rewritten commits which had descendants or branches abandoned.
+    rewritten_commit_oids: Vec<NonZeroOid>,
+
+    /// The abandoned descendant commits.
+    children: HashSet<NonZeroOid>,
+
+    /// The names of the abandoned branches, sorted.
+    branch_names: Vec<String>,
+}
+
+impl AbandonedCommits {
+    fn is_empty(&self) -> bool {
+        self.children.is_empty() && self.branch_names.is_empty()
+    }
+
+    /// Describe the abandoned commits and branches, such as `2 commits and 1
+    /// branch (foo)`.
+    fn describe(&self) -> String {
+        let mut items = Vec::new();
+        if !self.children.is_empty() {
+            items.push(
+                Pluralize {
+                    determiner: None,
+                    amount: self.children.len(),
+                    unit: ("commit", "commits"),
+                }
+                .to_string(),
+            );
+        }
+        if !self.branch_names.is_empty() {
+            let abandoned_branch_count = Pluralize {
+                determiner: None,
+                amount: self.branch_names.len(),
+                unit: ("branch", "branches"),
+            }
+            .to_string();
+            items.push(format!(
+                "{} ({})",
+                abandoned_branch_count,
+                self.branch_names.join(", ")
+            ));
+        }
+        items.join(" and ")
+    }
 }
 
 #[instrument(skip(old_commit_oids))]
-fn warn_abandoned(
+fn find_abandoned(
     effects: &Effects,
     repo: &Repo,
-    conn: &rusqlite::Connection,
     event_log_db: &EventLogDb,
     old_commit_oids: impl IntoIterator<Item = NonZeroOid>,
-) -> eyre::Result<bool> {
+) -> eyre::Result<AbandonedCommits> {
     // The caller will have added events to the event log database, so make sure
     // to construct a fresh `EventReplayer` here.
     let references_snapshot = repo.get_references_snapshot()?;
@@ -221,78 +326,61 @@ fn warn_abandoned(
     )?;
     let draft_commits = dag.query().range(public_commits, active_heads)?;
 
-    let (all_abandoned_children, all_abandoned_branches) = {
-        let mut all_abandoned_children: HashSet<NonZeroOid> = HashSet::new();
-        let mut all_abandoned_branches: HashSet<&str> = HashSet::new();
-        for old_commit_oid in old_commit_oids {
-            let abandoned_result = find_abandoned_children(
-                &dag,
-                &draft_commits,
-                &event_replayer,
-                event_cursor,
-                old_commit_oid,
-            )?;
-            let (_rewritten_oid, abandoned_children) = match abandoned_result {
-                Some(abandoned_result) => abandoned_result,
-                None => continue,
-            };
-            all_abandoned_children.extend(abandoned_children.iter());
-            if let Some(branch_names) = references_snapshot.branch_oid_to_names.get(&old_commit_oid)
-            {
-                all_abandoned_branches
-                    .extend(branch_names.iter().map(|branch_name| branch_name.as_str()));
-            }
-        }
-        (all_abandoned_children, all_abandoned_branches)
-    };
-    let num_abandoned_children = all_abandoned_children.len();
-    let num_abandoned_branches = all_abandoned_branches.len();
-
-    if num_abandoned_children > 0 || num_abandoned_branches > 0 {
-        let warning_items = {
-            let mut warning_items = Vec::new();
-            if num_abandoned_children > 0 {
-                warning_items.push(
-                    Pluralize {
-                        determiner: None,
-                        amount: num_abandoned_children,
-                        unit: ("commit", "commits"),
-                    }
-                    .to_string(),
-                );
-            }
-            if num_abandoned_branches > 0 {
-                let abandoned_branch_count = Pluralize {
-                    determiner: None,
-                    amount: num_abandoned_branches,
-                    unit: ("branch", "branches"),
-                }
-                .to_string();
-
-                let mut all_abandoned_branches: Vec<String> = all_abandoned_branches
-                    .into_iter()
-                    .map(|branch_name| {
-                        CategorizedReferenceName::new(&branch_name.into()).render_suffix()
-                    })
-                    .collect();
-                all_abandoned_branches.sort_unstable();
-                let abandoned_branches_list = all_abandoned_branches.join(", ");
-                warning_items.push(format!(
-                    "{} ({})",
-                    abandoned_branch_count, abandoned_branches_list
-                ));
-            }
-
-            warning_items
+    let mut rewritten_commit_oids = Vec::new();
+    let mut children: HashSet<NonZeroOid> = HashSet::new();
+    let mut branch_names: HashSet<String> = HashSet::new();
+    for old_commit_oid in old_commit_oids {
+        let abandoned_result = find_abandoned_children(
+            &dag,
+            &draft_commits,
+            &event_replayer,
+            event_cursor,
+            old_commit_oid,
+        )?;
+        let (_rewritten_oid, abandoned_children) = match abandoned_result {
+            Some(abandoned_result) => abandoned_result,
+            None => continue,
         };
+        let abandoned_branch_names = references_snapshot
+            .branch_oid_to_names
+            .get(&old_commit_oid)
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|name| CategorizedReferenceName::new(name).render_suffix())
+                    .collect_vec()
+            })
+            .unwrap_or_default();
+        if abandoned_children.is_empty() && abandoned_branch_names.is_empty() {
+            continue;
+        }
+        rewritten_commit_oids.push(old_commit_oid);
+        children.extend(abandoned_children.iter());
+        branch_names.extend(abandoned_branch_names);
+    }
+    rewritten_commit_oids.sort_unstable();
+    Ok(AbandonedCommits {
+        rewritten_commit_oids,
+        children,
+        branch_names: branch_names.into_iter().sorted().collect(),
+    })
+}
 
-        let warning_message = warning_items.join(" and ");
-        let warning_message = style(format!("This operation abandoned {}!", warning_message))
-            .bold()
-            .yellow();
+fn warn_abandoned(
+    effects: &Effects,
+    repo: &Repo,
+    abandoned: &AbandonedCommits,
+) -> eyre::Result<()> {
+    let warning_message = printable_styled_string(
+        effects.get_glyphs(),
+        StyledString::styled(
+            format!("This operation abandoned {}!", abandoned.describe()),
+            effects.get_theme().warning_label,
+        ),
+    )?;
 
-        print!(
-            "\
+    print!(
+        "\
 branchless: {warning_message}
 branchless: Consider running one of the following:
 branchless:   - {git_restack}: re-apply the abandoned commits/branches
@@ -301,16 +389,80 @@ branchless:   - {git_smartlog}: assess the situation
 branchless:   - {git_hide} [<commit>...]: hide the commits from the smartlog
 branchless:   - {git_undo}: undo the operation
 ",
-            warning_message = warning_message,
-            git_smartlog = style("git smartlog").bold(),
-            git_restack = style("git restack").bold(),
-            git_hide = style("git hide").bold(),
-            git_undo = style("git undo").bold(),
-        );
-        Ok(true)
-    } else {
-        Ok(false)
+        warning_message = warning_message,
+        git_smartlog = style("git smartlog").bold(),
+        git_restack = style(make_restack_command(
+            repo,
+            &abandoned.rewritten_commit_oids
+        )?)
+        .bold(),
+        git_hide = style("git hide").bold(),
+        git_undo = style("git undo").bold(),
+    );
+    Ok(())
+}
+
+/// Print a one-line hint with the command to restack the abandoned commits.
+fn print_restack_hint(
+    effects: &Effects,
+    repo: &Repo,
+    abandoned: &AbandonedCommits,
+) -> eyre::Result<()> {
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: This operation abandoned {}. To restack, run: {}",
+        abandoned.describe(),
+        make_restack_command(repo, &abandoned.rewritten_commit_oids)?,
+    )?;
+    Ok(())
+}
+
+/// Whether the full warning about abandoned commits was printed within the
+/// last `branchless.restack.warnAbandonedInterval`, in which case only a
+/// one-line hint should be printed.
+fn was_abandoned_warning_shown_recently(repo: &Repo, now: SystemTime) -> eyre::Result<bool> {
+    let interval = get_restack_warn_abandoned_interval(repo)?;
+    if interval.is_zero() {
+        return Ok(false);
     }
+    let path = repo.get_abandoned_warning_timestamp_path();
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => {
+            return Err(err).wrap_err_with(|| format!("Reading timestamp from: {:?}", path))
+        }
+    };
+    let last_shown: f64 = match contents.trim().parse() {
+        Ok(last_shown) => last_shown,
+        Err(_) => return Ok(false),
+    };
+    let now = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    Ok(last_shown <= now && now - last_shown < interval.as_secs_f64())
+}
+
+/// Record that the full warning about abandoned commits was printed.
+fn record_abandoned_warning_shown(repo: &Repo, now: SystemTime) -> eyre::Result<()> {
+    let path = repo.get_abandoned_warning_timestamp_path();
+    let now = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    std::fs::write(&path, now.to_string())
+        .wrap_err_with(|| format!("Writing timestamp to: {:?}", path))?;
+    Ok(())
+}
+
+/// Make the command to restack the descendants of the given rewritten
+/// commits.
+pub fn make_restack_command(
+    repo: &Repo,
+    rewritten_commit_oids: &[NonZeroOid],
+) -> eyre::Result<String> {
+    let mut command = "git restack".to_string();
+    for oid in rewritten_commit_oids {
+        let commit = repo.find_commit_or_fail(*oid)?;
+        command.push(' ');
+        command.push_str(&commit.get_short_oid()?);
+    }
+    Ok(command)
 }
 
 const ORIGINAL_HEAD_OID_FILE_NAME: &str = "branchless_original_head_oid";
@@ -523,3 +675,37 @@ pub fn hook_skip_upstream_applied_commit(
 
     Ok(())
 }
+
+/// For rebases, if a commit was folded into one of its ancestors, skip it and
+/// record that it was rewritten into the current `HEAD`, so that any branches
+/// pointing to it are moved there.
+pub fn hook_skip_folded_commit(effects: &Effects, commit_oid: NonZeroOid) -> eyre::Result<()> {
+    let repo = Repo::from_current_dir()?;
+    let commit = repo.find_commit_or_fail(commit_oid)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Skipping commit (was folded into an earlier commit): {}",
+        printable_styled_string(
+            effects.get_glyphs(),
+            commit.friendly_describe(effects.get_glyphs())?
+        )?
+    )?;
+
+    let current_head_oid = match repo.get_head_info()?.oid {
+        Some(current_head_oid) => current_head_oid,
+        None => eyre::bail!("No `HEAD` to record folded commit {} as", commit_oid),
+    };
+    if let Some(orig_head_reference) = repo.find_reference(&"ORIG_HEAD".into())? {
+        let resolved_orig_head = repo.resolve_reference(&orig_head_reference)?;
+        if resolved_orig_head.oid == Some(commit_oid) {
+            save_updated_head_oid(&repo, current_head_oid)?;
+        }
+    }
+    add_rewritten_list_entries(
+        &repo.get_tempfile_dir(),
+        &repo.get_rebase_state_dir_path().join("rewritten-list"),
+        &[(commit_oid, MaybeZeroOid::NonZero(current_head_oid))],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! A machine-readable summary of a rewrite operation, for use by scripts and
+//! editor integrations which need to react to rewritten commits.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use crate::core::eventlog::Event;
+use crate::git::{CategorizedReferenceName, MaybeZeroOid};
+
+use super::MergeConflictInfo;
+
+/// A commit which was rewritten by the operation.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct RewrittenCommit {
+    /// The OID of the commit before it was rewritten.
+    pub old_oid: String,
+
+    /// The OID of the commit after it was rewritten, or `None` if the commit
+    /// was dropped (for example, because it became empty).
+    pub new_oid: Option<String>,
+}
+
+/// A branch which was moved by the operation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MovedBranch {
+    /// The name of the branch, without the `refs/heads/` prefix.
+    pub name: String,
+
+    /// The OID the branch pointed to before the operation, or `None` if it was
+    /// created by the operation.
+    pub old_oid: Option<String>,
+
+    /// The OID the branch points to after the operation, or `None` if it was
+    /// deleted by the operation.
+    pub new_oid: Option<String>,
+}
+
+/// A merge conflict which stopped the operation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ConflictSummary {
+    /// The OID of the commit which could not be applied.
+    pub commit_oid: String,
+
+    /// The paths which were in conflict, sorted.
+    pub paths: Vec<String>,
+}
+
+/// A summary of the commits rewritten, branches moved, and conflicts
+/// encountered by a rewrite operation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RewriteSummary {
+    /// The commits which were rewritten, in the order that they were rewritten.
+    pub rewritten_commits: Vec<RewrittenCommit>,
+
+    /// The branches which were moved, sorted by name.
+    pub moved_branches: Vec<MovedBranch>,
+
+    /// The merge conflicts which were encountered.
+    pub conflicts: Vec<ConflictSummary>,
+}
+
+fn oid_to_string(oid: MaybeZeroOid) -> Option<String> {
+    match oid {
+        MaybeZeroOid::NonZero(oid) => Some(oid.to_string()),
+        MaybeZeroOid::Zero => None,
+    }
+}
+
+impl RewriteSummary {
+    /// Record the rewritten commits and moved branches from the events which
+    /// were added to the event log during the operation.
+    pub fn add_events<'a>(&mut self, events: impl IntoIterator<Item = &'a Event>) {
+        let mut seen_rewrites: HashSet<RewrittenCommit> =
+            self.rewritten_commits.iter().cloned().collect();
+        let mut branches: BTreeMap<String, (MaybeZeroOid, MaybeZeroOid)> = BTreeMap::new();
+        for event in events {
+            match event {
+                Event::RewriteEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    old_commit_oid,
+                    new_commit_oid,
+                } => {
+                    if let Some(old_oid) = oid_to_string(*old_commit_oid) {
+                        let rewritten_commit = RewrittenCommit {
+                            old_oid,
+                            new_oid: oid_to_string(*new_commit_oid),
+                        };
+                        if seen_rewrites.insert(rewritten_commit.clone()) {
+                            self.rewritten_commits.push(rewritten_commit);
+                        }
+                    }
+                }
+
+                Event::RefUpdateEvent {
+                    timestamp: _,
+                    event_tx_id: _,
+                    ref_name,
+                    old_oid,
+                    new_oid,
+                    message: _,
+                } => {
+                    if !ref_name.as_str().starts_with("refs/heads/") {
+                        continue;
+                    }
+                    let name = CategorizedReferenceName::new(ref_name).render_suffix();
+                    branches
+                        .entry(name)
+                        .and_modify(|(_first_old_oid, last_new_oid)| *last_new_oid = *new_oid)
+                        .or_insert((*old_oid, *new_oid));
+                }
+
+                Event::CommitEvent { .. }
+                | Event::ObsoleteEvent { .. }
+                | Event::UnobsoleteEvent { .. }
+                | Event::WorkingCopySnapshot { .. } => {}
+            }
+        }
+
+        self.moved_branches.extend(
+            branches
+                .into_iter()
+                .filter(|(_name, (old_oid, new_oid))| old_oid != new_oid)
+                .map(|(name, (old_oid, new_oid))| MovedBranch {
+                    name,
+                    old_oid: oid_to_string(old_oid),
+                    new_oid: oid_to_string(new_oid),
+                }),
+        );
+    }
+
+    /// Record a merge conflict which stopped the operation.
+    pub fn add_conflict(&mut self, merge_conflict: &MergeConflictInfo) {
+        let MergeConflictInfo {
+            commit_oid,
+            conflicting_paths,
+        } = merge_conflict;
+        let mut paths: Vec<String> = conflicting_paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        paths.sort_unstable();
+        self.conflicts.push(ConflictSummary {
+            commit_oid: commit_oid.to_string(),
+            paths,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::core::eventlog::testing::make_dummy_transaction_id;
+    use crate::git::ReferenceName;
+
+    use super::*;
+
+    #[test]
+    fn test_add_events() -> eyre::Result<()> {
+        let event_tx_id = make_dummy_transaction_id(1);
+        let oid1 = MaybeZeroOid::from_str("1111111111111111111111111111111111111111")?;
+        let oid2 = MaybeZeroOid::from_str("2222222222222222222222222222222222222222")?;
+        let oid3 = MaybeZeroOid::from_str("3333333333333333333333333333333333333333")?;
+        let events = vec![
+            Event::RewriteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                old_commit_oid: oid1,
+                new_commit_oid: oid2,
+            },
+            Event::RewriteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                old_commit_oid: oid1,
+                new_commit_oid: oid2,
+            },
+            Event::RefUpdateEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                ref_name: ReferenceName::from("refs/heads/foo"),
+                old_oid: oid1,
+                new_oid: oid2,
+                message: None,
+            },
+            Event::RefUpdateEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                ref_name: ReferenceName::from("refs/heads/foo"),
+                old_oid: oid2,
+                new_oid: oid3,
+                message: None,
+            },
+            Event::RefUpdateEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                ref_name: ReferenceName::from("HEAD"),
+                old_oid: oid1,
+                new_oid: oid2,
+                message: None,
+            },
+        ];
+
+        let mut summary = RewriteSummary::default();
+        summary.add_events(&events);
+        assert_eq!(
+            summary,
+            RewriteSummary {
+                rewritten_commits: vec![RewrittenCommit {
+                    old_oid: oid1.to_string(),
+                    new_oid: Some(oid2.to_string()),
+                }],
+                moved_branches: vec![MovedBranch {
+                    name: "foo".to_string(),
+                    old_oid: Some(oid1.to_string()),
+                    new_oid: Some(oid3.to_string()),
+                }],
+                conflicts: vec![],
+            }
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+//! Verify the signatures of commits, so that their status can be displayed in
+//! the smartlog and used in revsets.
+//!
+//! Signatures are verified by Git itself (with `git log --format=%G?`), so
+//! that the user's `gpg.*` configuration is respected. Since verifying a
+//! signature is slow, results are cached in the database. Each result is
+//! cached along with a key summarizing the configuration and keyrings that it
+//! was verified with, so that it's verified again if they change.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use eyre::Context;
+use tracing::{instrument, warn};
+
+use crate::git::{ConfigRead, GitRunInfo, GitRunOpts, NonZeroOid, Repo};
+
+/// The status of a commit's signature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// The commit has a valid signature from a trusted key.
+    Good,
+
+    /// The commit has a signature, but it's not valid, or it was made with a
+    /// revoked key.
+    Bad,
+
+    /// The commit has a signature, but it couldn't be fully verified, such as
+    /// because the key is missing, untrusted or expired.
+    Unknown,
+
+    /// The commit doesn't have a signature.
+    Unsigned,
+}
+
+impl SignatureStatus {
+    /// Parse the status as reported by Git's `%G?` format placeholder. See
+    /// `git-log(1)`.
+    pub fn from_format_code(code: &str) -> Option<Self> {
+        let status = match code {
+            "G" => SignatureStatus::Good,
+            "B" | "R" => SignatureStatus::Bad,
+            "U" | "X" | "Y" | "E" => SignatureStatus::Unknown,
+            "N" => SignatureStatus::Unsigned,
+            _ => return None,
+        };
+        Some(status)
+    }
+}
+
+impl FromStr for SignatureStatus {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "good" => Ok(SignatureStatus::Good),
+            "bad" => Ok(SignatureStatus::Bad),
+            "unknown" => Ok(SignatureStatus::Unknown),
+            "unsigned" => Ok(SignatureStatus::Unsigned),
+            other => eyre::bail!("Unknown signature status: {:?}", other),
+        }
+    }
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureStatus::Good => write!(f, "good"),
+            SignatureStatus::Bad => write!(f, "bad"),
+            SignatureStatus::Unknown => write!(f, "unknown"),
+            SignatureStatus::Unsigned => write!(f, "unsigned"),
+        }
+    }
+}
+
+/// Caches the signature statuses of commits on disk.
+pub struct SignatureStatusDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for SignatureStatusDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<SignatureStatusDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS signature_status (
+    commit_oid TEXT NOT NULL PRIMARY KEY,
+    status TEXT NOT NULL,
+
+    -- The result of `get_verification_key` when the signature was verified.
+    verification_key TEXT NOT NULL DEFAULT ''
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `signature_status` table")?;
+    Ok(())
+}
+
+impl<'conn> SignatureStatusDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(SignatureStatusDb { conn })
+    }
+
+    /// Get the cached signature status for the given commit, if it was
+    /// verified with the given verification key.
+    #[instrument]
+    pub fn get(
+        &self,
+        commit_oid: NonZeroOid,
+        verification_key: &str,
+    ) -> eyre::Result<Option<SignatureStatus>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT status
+FROM signature_status
+WHERE commit_oid = :commit_oid AND verification_key = :verification_key
+",
+        )?;
+        let mut rows = stmt.query_map(
+            rusqlite::named_params! {
+                ":commit_oid": commit_oid.to_string(),
+                ":verification_key": verification_key,
+            },
+            |row| {
+                let status: String = row.get("status")?;
+                Ok(status)
+            },
+        )?;
+        match rows.next() {
+            None => Ok(None),
+            Some(row) => {
+                let status = row?;
+                Ok(Some(SignatureStatus::from_str(&status)?))
+            }
+        }
+    }
+
+    /// Cache the signature status for the given commit, as verified with the
+    /// given verification key.
+    #[instrument]
+    pub fn set(
+        &self,
+        commit_oid: NonZeroOid,
+        verification_key: &str,
+        status: SignatureStatus,
+    ) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO signature_status (commit_oid, status, verification_key)
+VALUES (:commit_oid, :status, :verification_key)
+",
+                rusqlite::named_params! {
+                    ":commit_oid": commit_oid.to_string(),
+                    ":status": status.to_string(),
+                    ":verification_key": verification_key,
+                },
+            )
+            .wrap_err("Caching signature status")?;
+        Ok(())
+    }
+}
+
+/// Summarize the state which affects the result of verifying a signature: the
+/// `gpg.*` configuration, the SSH allowed signers and revocation files, and
+/// the GnuPG keyring and trust database. The files are summarized by their
+/// modification times and sizes, so that adding, removing or trusting a key
+/// invalidates the cached statuses.
+#[instrument]
+pub fn get_verification_key(repo: &Repo) -> eyre::Result<String> {
+    let config = repo.get_readonly_config()?;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for key in ["gpg.ssh.allowedSignersFile", "gpg.ssh.revocationFile"] {
+        if let Some(path) = config.get::<PathBuf, _>(key)? {
+            paths.push(path);
+        }
+    }
+    let gnupg_home = std::env::var_os("GNUPGHOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".gnupg")));
+    if let Some(gnupg_home) = gnupg_home {
+        for file_name in ["pubring.kbx", "pubring.gpg", "trustdb.gpg"] {
+            paths.push(gnupg_home.join(file_name));
+        }
+    }
+
+    let mut hasher = DefaultHasher::new();
+    config
+        .into_config()
+        .get_entries("^gpg\\.")?
+        .hash(&mut hasher);
+    for path in paths {
+        let metadata = std::fs::metadata(&path).ok();
+        let modified = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.modified().ok());
+        let len = metadata.as_ref().map(|metadata| metadata.len());
+        (path, modified, len).hash(&mut hasher);
+    }
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Ask Git to verify the signatures of the given commits. The commits are
+/// passed on stdin, since there may be too many to fit on the command line.
+fn run_verify_command(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<HashMap<NonZeroOid, SignatureStatus>> {
+    let stdin: String = commit_oids.iter().map(|oid| format!("{}\n", oid)).collect();
+    let result = git_run_info.run_silent(
+        repo,
+        None,
+        &["log", "--no-walk=unsorted", "--stdin", "--format=%H %G?"],
+        GitRunOpts {
+            treat_git_failure_as_error: true,
+            stdin: Some(stdin.into_bytes()),
+        },
+    )?;
+
+    let mut statuses = HashMap::new();
+    for line in String::from_utf8_lossy(&result.stdout).lines() {
+        let (oid, code) = match line.trim().split_once(' ') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        if let (Ok(oid), Some(status)) = (
+            NonZeroOid::from_str(oid),
+            SignatureStatus::from_format_code(code),
+        ) {
+            statuses.insert(oid, status);
+        }
+    }
+    Ok(statuses)
+}
+
+/// Get the signature statuses for the given commits.
+///
+/// Commits without a signature are reported as `Unsigned` without consulting
+/// Git. Cached statuses are used for the rest, and the remaining commits are
+/// verified with Git. Cached statuses are only used if the configuration and
+/// keyrings haven't changed since they were verified (see
+/// [`get_verification_key`]). `Unknown` statuses aren't cached, since they may
+/// also change when the signing key becomes available some other way, such as
+/// by being fetched from a keyserver. If Git fails, then a
+/// warning is logged and the statuses of those commits are omitted, so that a
+/// misconfigured signing program doesn't prevent the smartlog from rendering.
+#[instrument]
+pub fn get_signature_statuses(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    signature_status_db: &SignatureStatusDb,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<HashMap<NonZeroOid, SignatureStatus>> {
+    let verification_key = get_verification_key(repo)?;
+    let mut result = HashMap::new();
+    let mut uncached_oids = Vec::new();
+    for commit_oid in commit_oids {
+        let commit = repo.find_commit_or_fail(*commit_oid)?;
+        if !commit.is_signed() {
+            result.insert(*commit_oid, SignatureStatus::Unsigned);
+            continue;
+        }
+        match signature_status_db.get(*commit_oid, &verification_key)? {
+            Some(status) => {
+                result.insert(*commit_oid, status);
+            }
+            None => uncached_oids.push(*commit_oid),
+        }
+    }
+    if uncached_oids.is_empty() {
+        return Ok(result);
+    }
+
+    match run_verify_command(git_run_info, repo, &uncached_oids) {
+        Ok(statuses) => {
+            for (commit_oid, status) in statuses {
+                if status != SignatureStatus::Unknown {
+                    signature_status_db.set(commit_oid, &verification_key, status)?;
+                }
+                result.insert(commit_oid, status);
+            }
+        }
+        Err(err) => {
+            warn!(?err, "Failed to verify commit signatures");
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signature_status() {
+        assert_eq!(
+            SignatureStatus::from_format_code("G"),
+            Some(SignatureStatus::Good)
+        );
+        assert_eq!(
+            SignatureStatus::from_format_code("R"),
+            Some(SignatureStatus::Bad)
+        );
+        assert_eq!(
+            SignatureStatus::from_format_code("E"),
+            Some(SignatureStatus::Unknown)
+        );
+        assert_eq!(
+            SignatureStatus::from_format_code("N"),
+            Some(SignatureStatus::Unsigned)
+        );
+        assert_eq!(SignatureStatus::from_format_code("?"), None);
+        assert_eq!(
+            SignatureStatus::from_str(&SignatureStatus::Bad.to_string()).unwrap(),
+            SignatureStatus::Bad
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Cache the rendered smartlog, so that it can be printed without replaying
+//! the event log or syncing the commit graph when nothing has changed since it
+//! was last rendered.
+//!
+//! Each entry is stored along with a fingerprint of the repository state it was
+//! rendered from. Computing the fingerprint is up to the caller; an entry
+//! should only be reused if the fingerprint matches exactly.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::Context;
+use tracing::instrument;
+
+/// A rendered smartlog, along with the state it was rendered from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedSmartlog {
+    /// The fingerprint of the repository state at the time of rendering.
+    pub fingerprint: String,
+
+    /// When the smartlog was rendered.
+    pub rendered_at: SystemTime,
+
+    /// The output which was written to `stdout`.
+    pub stdout: Vec<u8>,
+
+    /// The output which was written to `stderr`, such as hints.
+    pub stderr: Vec<u8>,
+}
+
+/// Caches the rendered smartlog on disk.
+pub struct SmartlogCacheDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for SmartlogCacheDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<SmartlogCacheDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS smartlog_cache (
+    glyphs TEXT NOT NULL PRIMARY KEY,
+    fingerprint TEXT NOT NULL,
+    rendered_at REAL NOT NULL,
+    stdout BLOB NOT NULL,
+    stderr BLOB NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `smartlog_cache` table")?;
+    Ok(())
+}
+
+impl<'conn> SmartlogCacheDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(SmartlogCacheDb { conn })
+    }
+
+    /// Get the cached smartlog which was rendered with the given kind of
+    /// glyphs, if any.
+    #[instrument]
+    pub fn get(&self, glyphs: &str) -> eyre::Result<Option<CachedSmartlog>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT fingerprint, rendered_at, stdout, stderr
+FROM smartlog_cache
+WHERE glyphs = :glyphs
+",
+        )?;
+        let mut rows = stmt.query_map(
+            rusqlite::named_params! {
+                ":glyphs": glyphs,
+            },
+            |row| {
+                let fingerprint: String = row.get("fingerprint")?;
+                let rendered_at: f64 = row.get("rendered_at")?;
+                let stdout: Vec<u8> = row.get("stdout")?;
+                let stderr: Vec<u8> = row.get("stderr")?;
+                Ok((fingerprint, rendered_at, stdout, stderr))
+            },
+        )?;
+        match rows.next() {
+            None => Ok(None),
+            Some(row) => {
+                let (fingerprint, rendered_at, stdout, stderr) = row?;
+                Ok(Some(CachedSmartlog {
+                    fingerprint,
+                    rendered_at: UNIX_EPOCH + Duration::from_secs_f64(rendered_at.max(0.0)),
+                    stdout,
+                    stderr,
+                }))
+            }
+        }
+    }
+
+    /// Cache the smartlog rendered with the given kind of glyphs, replacing
+    /// any previous entry.
+    #[instrument]
+    pub fn set(&self, glyphs: &str, cached_smartlog: &CachedSmartlog) -> eyre::Result<()> {
+        let CachedSmartlog {
+            fingerprint,
+            rendered_at,
+            stdout,
+            stderr,
+        } = cached_smartlog;
+        let rendered_at = rendered_at.duration_since(UNIX_EPOCH)?.as_secs_f64();
+        self.conn
+            .execute(
+                "
+INSERT OR REPLACE INTO smartlog_cache (glyphs, fingerprint, rendered_at, stdout, stderr)
+VALUES (:glyphs, :fingerprint, :rendered_at, :stdout, :stderr)
+",
+                rusqlite::named_params! {
+                    ":glyphs": glyphs,
+                    ":fingerprint": fingerprint,
+                    ":rendered_at": rendered_at,
+                    ":stdout": stdout,
+                    ":stderr": stderr,
+                },
+            )
+            .wrap_err("Caching smartlog")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,439 @@
+//! Maintain a branch for each commit in a stack, for code forges which review
+//! one branch per commit.
+//!
+//! Stack branches have names of the form `<user>/<stack-name>/<n>`, where `n`
+//! is the 1-based position of the commit in the stack. Once created with `git
+//! branchless branch --stack`, they're kept in sync after commits are
+//! rewritten, so that inserting, removing or reordering commits in the stack
+//! renumbers the branches accordingly. Only the stacks which were created by
+//! `git branchless branch --stack` are maintained, as recorded in the
+//! [`StackBranchesDb`], so branches which merely happen to have names of this
+//! form are left alone.
+
+use std::collections::{BTreeMap, HashSet};
+
+use eden_dag::DagAlgorithm;
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::config::get_stack_branches_user;
+use crate::core::dag::{sorted_commit_set, CommitSet, Dag};
+use crate::core::effects::Effects;
+use crate::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
+use crate::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
+use crate::git::{
+    CategorizedReferenceName, ConfigRead, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName,
+    ReferenceUpdate, Repo,
+};
+
+/// Records the stacks whose branches were created by `git branchless branch
+/// --stack`.
+pub struct StackBranchesDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for StackBranchesDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<StackBranchesDb>")
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS stack_branches (
+    user TEXT NOT NULL,
+    stack_name TEXT NOT NULL,
+    PRIMARY KEY (user, stack_name)
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `stack_branches` table")?;
+    Ok(())
+}
+
+impl<'conn> StackBranchesDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(StackBranchesDb { conn })
+    }
+
+    /// Get the names of the stacks created for the given user.
+    #[instrument]
+    pub fn get_stack_names(&self, user: &str) -> eyre::Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT stack_name FROM stack_branches WHERE user = :user")?;
+        let stack_names = stmt
+            .query_map(rusqlite::named_params! { ":user": user }, |row| {
+                row.get("stack_name")
+            })?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(stack_names)
+    }
+
+    /// Record that the given stack was created for the given user.
+    #[instrument]
+    pub fn add_stack(&self, user: &str, stack_name: &str) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                "
+INSERT OR IGNORE INTO stack_branches (user, stack_name)
+VALUES (:user, :stack_name)
+",
+                rusqlite::named_params! {
+                    ":user": user,
+                    ":stack_name": stack_name,
+                },
+            )
+            .wrap_err("Recording stack")?;
+        Ok(())
+    }
+
+    /// Forget about the given stack, such as after all of its branches were
+    /// deleted.
+    #[instrument]
+    pub fn remove_stack(&self, user: &str, stack_name: &str) -> eyre::Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM stack_branches WHERE user = :user AND stack_name = :stack_name",
+                rusqlite::named_params! {
+                    ":user": user,
+                    ":stack_name": stack_name,
+                },
+            )
+            .wrap_err("Removing stack")?;
+        Ok(())
+    }
+}
+
+/// Convert the given text into a string which is safe to use as a component of
+/// a branch name.
+fn sanitize_name_component(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            result.push(c.to_ascii_lowercase());
+        } else if !result.is_empty() && !result.ends_with('-') {
+            result.push('-');
+        }
+    }
+    result
+        .trim_matches(['-', '.'])
+        .chars()
+        .take(40)
+        .collect::<String>()
+        .trim_end_matches(['-', '.'])
+        .to_owned()
+}
+
+/// Get the user name used as the first component of stack branch names. This
+/// is `branchless.stackBranches.user` if set, and otherwise the local part of
+/// `user.email`.
+#[instrument]
+pub fn get_stack_branch_user(repo: &Repo) -> eyre::Result<Option<String>> {
+    if let Some(user) = get_stack_branches_user(repo)? {
+        return Ok(Some(user));
+    }
+    let email: Option<String> = repo.get_readonly_config()?.get("user.email")?;
+    let user = email
+        .as_deref()
+        .and_then(|email| email.split('@').next())
+        .map(sanitize_name_component)
+        .filter(|user| !user.is_empty());
+    Ok(user)
+}
+
+/// Derive a stack name from the summary of the stack's first commit.
+pub fn make_stack_name_from_summary(summary: &str) -> String {
+    sanitize_name_component(summary)
+}
+
+/// Make the name of the branch for the `index`th commit in the stack, starting
+/// from 1.
+pub fn make_stack_branch_name(user: &str, stack_name: &str, index: usize) -> String {
+    format!("{}/{}/{}", user, stack_name, index)
+}
+
+/// Parse a branch name created by [`make_stack_branch_name`] for the given
+/// user into the stack name and the index of the commit in the stack.
+pub fn parse_stack_branch_name(user: &str, branch_name: &str) -> Option<(String, usize)> {
+    let rest = branch_name.strip_prefix(user)?.strip_prefix('/')?;
+    let (stack_name, index) = rest.rsplit_once('/')?;
+    if stack_name.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match index.parse::<usize>() {
+        Ok(index) if index >= 1 => Some((stack_name.to_owned(), index)),
+        _ => None,
+    }
+}
+
+/// Get the stack branches for each of the given stacks which the user has, as a
+/// mapping from the index of the branch to the commit it points to.
+fn get_stack_branch_families(
+    references_snapshot: &RepoReferencesSnapshot,
+    user: &str,
+    stack_names: &HashSet<String>,
+) -> BTreeMap<String, BTreeMap<usize, NonZeroOid>> {
+    let mut result: BTreeMap<String, BTreeMap<usize, NonZeroOid>> = BTreeMap::new();
+    for (oid, reference_names) in references_snapshot.branch_oid_to_names.iter() {
+        for reference_name in reference_names {
+            let branch_name = CategorizedReferenceName::new(reference_name).render_suffix();
+            if let Some((stack_name, index)) = parse_stack_branch_name(user, &branch_name) {
+                if stack_names.contains(&stack_name) {
+                    result.entry(stack_name).or_default().insert(index, *oid);
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Get the names of those of the given stacks which have stack branches
+/// pointing to any of the given commits.
+pub fn get_stack_names_for_commits(
+    references_snapshot: &RepoReferencesSnapshot,
+    user: &str,
+    stack_names: &HashSet<String>,
+    commit_oids: &[NonZeroOid],
+) -> Vec<String> {
+    get_stack_branch_families(references_snapshot, user, stack_names)
+        .into_iter()
+        .filter(|(_stack_name, branches)| branches.values().any(|oid| commit_oids.contains(oid)))
+        .map(|(stack_name, _branches)| stack_name)
+        .collect()
+}
+
+/// Get the draft commits in the stacks containing the given commits, in
+/// topological order. This is the same set of commits as the `stack()` revset.
+#[instrument]
+pub fn query_stack_commits(
+    repo: &Repo,
+    dag: &Dag,
+    commits: CommitSet,
+) -> eyre::Result<Vec<NonZeroOid>> {
+    let public_commits = dag.query_public_commits()?;
+    let active_heads = dag.query_active_heads(
+        &public_commits,
+        &dag.observed_commits.difference(&dag.obsolete_commits),
+    )?;
+    let draft_commits = dag.query().only(active_heads, public_commits)?;
+    let commits = commits.intersection(&draft_commits);
+    let stack_roots = dag.query().roots(draft_commits.clone())?;
+    let stack_ancestors = dag.query().range(stack_roots, commits)?;
+    let stack = dag.query().range(stack_ancestors, draft_commits)?;
+    Ok(sorted_commit_set(repo, dag, &stack)?
+        .into_iter()
+        .map(|commit| commit.get_oid())
+        .collect())
+}
+
+/// Determine the reference updates needed so that the stack branches for the
+/// given stack point to the given commits, in order. Branches beyond the end
+/// of the stack are deleted.
+///
+/// Existing branches are only considered if the stack is one of the given
+/// stacks. Otherwise, creating a branch which already exists will fail, rather
+/// than overwriting it.
+pub fn plan_stack_branch_updates(
+    references_snapshot: &RepoReferencesSnapshot,
+    user: &str,
+    stack_names: &HashSet<String>,
+    stack_name: &str,
+    stack_oids: &[NonZeroOid],
+) -> Vec<ReferenceUpdate> {
+    let existing_branches = get_stack_branch_families(references_snapshot, user, stack_names)
+        .remove(stack_name)
+        .unwrap_or_default();
+    let mut result = Vec::new();
+    for (i, oid) in stack_oids.iter().enumerate() {
+        let index = i + 1;
+        let old_oid = match existing_branches.get(&index) {
+            Some(old_oid) if old_oid == oid => continue,
+            Some(old_oid) => MaybeZeroOid::NonZero(*old_oid),
+            None => MaybeZeroOid::Zero,
+        };
+        result.push(ReferenceUpdate {
+            ref_name: ReferenceName::from(format!(
+                "refs/heads/{}",
+                make_stack_branch_name(user, stack_name, index)
+            )),
+            old_oid,
+            new_oid: MaybeZeroOid::NonZero(*oid),
+        });
+    }
+    for (index, old_oid) in existing_branches.range(stack_oids.len() + 1..) {
+        result.push(ReferenceUpdate {
+            ref_name: ReferenceName::from(format!(
+                "refs/heads/{}",
+                make_stack_branch_name(user, stack_name, *index)
+            )),
+            old_oid: MaybeZeroOid::NonZero(*old_oid),
+            new_oid: MaybeZeroOid::Zero,
+        });
+    }
+    result
+}
+
+/// Apply the given updates to stack branches. If `HEAD` is attached to a
+/// branch which is about to be deleted, then it's detached first.
+pub fn apply_stack_branch_updates(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    updates: &[ReferenceUpdate],
+) -> eyre::Result<()> {
+    if updates.is_empty() {
+        return Ok(());
+    }
+    let head_info = repo.get_head_info()?;
+    if let Some(head_reference_name) = &head_info.reference_name {
+        if updates.iter().any(|update| {
+            &update.ref_name == head_reference_name && update.new_oid == MaybeZeroOid::Zero
+        }) {
+            repo.detach_head(&head_info)?;
+        }
+    }
+    match git_run_info.update_references(effects, repo, event_tx_id, updates)? {
+        Ok(()) => Ok(()),
+        Err(err) => Err(eyre::Error::new(err).wrap_err("Updating stack branches")),
+    }
+}
+
+/// Renumber the branches of all of the user's recorded stacks after commits
+/// were rewritten, so that they point to the commits of their stacks in order. This should be
+/// called after the branches pointing to the rewritten commits have been
+/// moved.
+///
+/// Stacks whose commits have all become public, such as after they were
+/// merged, are left alone.
+#[instrument]
+pub fn sync_stack_branches(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+) -> eyre::Result<()> {
+    let user = match get_stack_branch_user(repo)? {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let conn = repo.get_db_conn()?;
+    let stack_branches_db = StackBranchesDb::new(&conn)?;
+    let stack_names = stack_branches_db.get_stack_names(&user)?;
+    if stack_names.is_empty() {
+        return Ok(());
+    }
+    let references_snapshot = repo.get_references_snapshot()?;
+    let families = get_stack_branch_families(&references_snapshot, &user, &stack_names);
+    for stack_name in stack_names.iter() {
+        if !families.contains_key(stack_name) {
+            // All of the stack's branches were deleted.
+            stack_branches_db.remove_stack(&user, stack_name)?;
+        }
+    }
+    if families.is_empty() {
+        return Ok(());
+    }
+    // `HEAD` may still point to a commit from before the rewrite, which would
+    // keep the old versions of the stack's commits visible.
+    let references_snapshot = RepoReferencesSnapshot {
+        head_oid: None,
+        ..references_snapshot
+    };
+
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let mut updates = Vec::new();
+    for (stack_name, branches) in families {
+        // The stack is the one containing the lowest-numbered branch which is
+        // still a draft commit. Commits which were moved to another stack lose
+        // their branches.
+        let mut stack_oids = Vec::new();
+        for oid in branches.values() {
+            stack_oids = query_stack_commits(repo, &dag, CommitSet::from(*oid))?;
+            if !stack_oids.is_empty() {
+                break;
+            }
+        }
+        if stack_oids.is_empty() {
+            continue;
+        }
+        updates.extend(plan_stack_branch_updates(
+            &references_snapshot,
+            &user,
+            &stack_names,
+            &stack_name,
+            &stack_oids,
+        ));
+    }
+    apply_stack_branch_updates(effects, git_run_info, repo, event_tx_id, &updates)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stack_branch_names() {
+        assert_eq!(
+            make_stack_branch_name("jdoe", "fix-bug", 2),
+            "jdoe/fix-bug/2"
+        );
+        assert_eq!(
+            parse_stack_branch_name("jdoe", "jdoe/fix-bug/2"),
+            Some(("fix-bug".to_owned(), 2))
+        );
+        assert_eq!(
+            parse_stack_branch_name("jdoe", "jdoe/nested/name/10"),
+            Some(("nested/name".to_owned(), 10))
+        );
+        assert_eq!(parse_stack_branch_name("jdoe", "jdoe/fix-bug/0"), None);
+        assert_eq!(parse_stack_branch_name("jdoe", "jdoe/fix-bug/x"), None);
+        assert_eq!(parse_stack_branch_name("jdoe", "jdoe/2"), None);
+        assert_eq!(parse_stack_branch_name("jdoe", "jdoex/fix-bug/2"), None);
+    }
+
+    #[test]
+    fn test_make_stack_name_from_summary() {
+        assert_eq!(
+            make_stack_name_from_summary("Fix the bug in `foo()` (again!)"),
+            "fix-the-bug-in-foo-again"
+        );
+        assert_eq!(make_stack_name_from_summary("  --  "), "");
+    }
+
+    #[test]
+    fn test_stack_branches_db() -> eyre::Result<()> {
+        let conn = rusqlite::Connection::open_in_memory()?;
+        let stack_branches_db = StackBranchesDb::new(&conn)?;
+        stack_branches_db.add_stack("jdoe", "feature")?;
+        stack_branches_db.add_stack("jdoe", "feature")?;
+        stack_branches_db.add_stack("other", "bugfix")?;
+        assert_eq!(
+            stack_branches_db.get_stack_names("jdoe")?,
+            ["feature".to_owned()].into_iter().collect()
+        );
+
+        stack_branches_db.remove_stack("jdoe", "feature")?;
+        assert!(stack_branches_db.get_stack_names("jdoe")?.is_empty());
+        assert_eq!(stack_branches_db.get_stack_names("other")?.len(), 1);
+        Ok(())
+    }
+}
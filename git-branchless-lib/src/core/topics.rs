@@ -0,0 +1,281 @@
+//! Infer human-readable names ("topics") for stacks of draft commits.
+//!
+//! A stack is the set of draft commits descending from a single draft root
+//! commit. Stacks with branches are named after their branches, but many
+//! stacks don't have any branches, so we also try to infer a name from ticket
+//! IDs in the commit messages (such as `PROJ-123`) or from the directory that
+//! most of the stack's changes touch. Users can also name a stack explicitly,
+//! in which case that name takes precedence over any inferred one.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Component;
+
+use bstr::ByteSlice;
+use eden_dag::DagAlgorithm;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tracing::instrument;
+
+use crate::core::dag::{commit_set_to_vec_unsorted, CommitSet, Dag};
+use crate::core::metadata::CommitMetadata;
+use crate::core::repo_ext::RepoReferencesSnapshot;
+use crate::git::{CategorizedReferenceName, NonZeroOid, Repo};
+
+/// The commit metadata key under which the explicitly-assigned name of a stack
+/// is stored. The metadata is set on the root commit of the stack.
+pub const STACK_NAME_METADATA_KEY: &str = "stack-name";
+
+/// Where the name for a stack's topic came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TopicSource {
+    /// A name which the user explicitly assigned to the stack.
+    Name,
+
+    /// The name of a branch pointing to one of the commits in the stack.
+    Branch,
+
+    /// A ticket ID mentioned in the commit messages of the stack.
+    TicketId,
+
+    /// The top-level directory which most of the stack's changes touch.
+    PathPrefix,
+}
+
+/// The inferred topic for a stack of draft commits.
+#[derive(Clone, Debug)]
+pub struct StackTopic {
+    /// The name of the topic. This is unique among the topics returned from
+    /// [`infer_stack_topics`].
+    pub name: String,
+
+    /// Where the name of the topic came from.
+    pub source: TopicSource,
+
+    /// The root commit of the stack.
+    pub root_oid: NonZeroOid,
+
+    /// The most recently-committed head of the stack. This is the commit to
+    /// check out when switching to the topic.
+    pub head_oid: NonZeroOid,
+}
+
+/// Infer a topic for each stack of draft commits in the repository. Stacks
+/// for which no topic could be inferred are omitted.
+///
+/// If the root commit of a stack has a name stored in `commit_metadata` under
+/// [`STACK_NAME_METADATA_KEY`], then that name is used as the topic.
+///
+/// The returned topics are sorted by the time of their root commits. If two
+/// stacks would be given the same name, then a numeric suffix is added to the
+/// name of the later one.
+#[instrument]
+pub fn infer_stack_topics(
+    repo: &Repo,
+    dag: &Dag,
+    references_snapshot: &RepoReferencesSnapshot,
+    commit_metadata: &HashMap<NonZeroOid, CommitMetadata>,
+) -> eyre::Result<Vec<StackTopic>> {
+    let public_commits = dag.query_public_commits()?;
+    let active_heads = dag.query_active_heads(
+        &public_commits,
+        &dag.observed_commits.difference(&dag.obsolete_commits),
+    )?;
+    let draft_commits = dag.query().only(active_heads, public_commits)?;
+    let stack_roots = dag.query().roots(draft_commits.clone())?;
+
+    let mut stack_roots = {
+        let mut result = Vec::new();
+        for root_oid in commit_set_to_vec_unsorted(&stack_roots)? {
+            // The commit may be missing if the repository is corrupt, in which
+            // case we don't want to fail to render the smartlog.
+            if let Some(root_commit) = repo.find_commit(root_oid)? {
+                result.push((root_commit.get_time(), root_oid));
+            }
+        }
+        result
+    };
+    stack_roots.sort_unstable();
+
+    let mut used_names: HashSet<String> = HashSet::new();
+    let mut topics = Vec::new();
+    for (_time, root_oid) in stack_roots {
+        let stack_commits = dag
+            .query()
+            .descendants(CommitSet::from(root_oid))?
+            .intersection(&draft_commits);
+        let stack_oids = commit_set_to_vec_unsorted(&stack_commits)?;
+
+        let stack_name = commit_metadata
+            .get(&root_oid)
+            .and_then(|metadata| metadata.get(STACK_NAME_METADATA_KEY));
+        let (name, source) = match stack_name {
+            Some(stack_name) => (stack_name.clone(), TopicSource::Name),
+            None => match infer_topic_name(repo, references_snapshot, &stack_oids)? {
+                Some(result) => result,
+                None => continue,
+            },
+        };
+        let name = (1..)
+            .map(|i| {
+                if i == 1 {
+                    name.clone()
+                } else {
+                    format!("{}-{}", name, i)
+                }
+            })
+            .find(|name| !used_names.contains(name))
+            .unwrap();
+        used_names.insert(name.clone());
+
+        let head_oid = {
+            let mut heads = Vec::new();
+            for head_oid in commit_set_to_vec_unsorted(&dag.query().heads(stack_commits)?)? {
+                if let Some(head_commit) = repo.find_commit(head_oid)? {
+                    heads.push((head_commit.get_time(), head_oid));
+                }
+            }
+            heads.into_iter().max().map(|(_time, oid)| oid)
+        };
+        topics.push(StackTopic {
+            name,
+            source,
+            root_oid,
+            head_oid: head_oid.unwrap_or(root_oid),
+        });
+    }
+    Ok(topics)
+}
+
+fn infer_topic_name(
+    repo: &Repo,
+    references_snapshot: &RepoReferencesSnapshot,
+    stack_oids: &[NonZeroOid],
+) -> eyre::Result<Option<(String, TopicSource)>> {
+    let branch_name = stack_oids
+        .iter()
+        .filter_map(|oid| references_snapshot.branch_oid_to_names.get(oid))
+        .flatten()
+        .map(|reference_name| CategorizedReferenceName::new(reference_name).render_suffix())
+        .min();
+    if let Some(branch_name) = branch_name {
+        return Ok(Some((branch_name, TopicSource::Branch)));
+    }
+
+    let mut ticket_id_counts: HashMap<String, usize> = HashMap::new();
+    for oid in stack_oids {
+        let commit = match repo.find_commit(*oid)? {
+            Some(commit) => commit,
+            None => continue,
+        };
+        let message = commit.get_message_raw()?;
+        for ticket_id in extract_ticket_ids(&message.to_str_lossy()) {
+            *ticket_id_counts.entry(ticket_id).or_default() += 1;
+        }
+    }
+    if let Some(ticket_id) = most_common(ticket_id_counts) {
+        return Ok(Some((ticket_id, TopicSource::TicketId)));
+    }
+
+    let mut num_paths = 0;
+    let mut path_prefix_counts: HashMap<String, usize> = HashMap::new();
+    for oid in stack_oids {
+        let commit = match repo.find_commit(*oid)? {
+            Some(commit) => commit,
+            None => continue,
+        };
+        let paths = match repo.get_paths_touched_by_commit(&commit)? {
+            Some(paths) => paths,
+            None => continue,
+        };
+        for path in paths {
+            num_paths += 1;
+            let mut components = path.components();
+            if let (Some(Component::Normal(prefix)), Some(_)) =
+                (components.next(), components.next())
+            {
+                *path_prefix_counts
+                    .entry(prefix.to_string_lossy().into_owned())
+                    .or_default() += 1;
+            }
+        }
+    }
+    let path_prefix = path_prefix_counts
+        .iter()
+        .filter(|(_prefix, count)| **count * 2 > num_paths)
+        .map(|(prefix, _count)| prefix.clone())
+        .next();
+    if let Some(path_prefix) = path_prefix {
+        return Ok(Some((path_prefix, TopicSource::PathPrefix)));
+    }
+
+    Ok(None)
+}
+
+/// Get the most common key, breaking ties by choosing the lexicographically
+/// smallest key.
+fn most_common(counts: HashMap<String, usize>) -> Option<String> {
+    counts
+        .into_iter()
+        .max_by(|(lhs_key, lhs_count), (rhs_key, rhs_count)| {
+            lhs_count.cmp(rhs_count).then_with(|| rhs_key.cmp(lhs_key))
+        })
+        .map(|(key, _count)| key)
+}
+
+fn extract_ticket_ids(message: &str) -> Vec<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\b[A-Z][A-Z0-9]+-[0-9]+\b")
+            .expect("Failed to compile `extract_ticket_ids` regex");
+    }
+    RE.find_iter(message)
+        .map(|ticket_id| ticket_id.as_str().to_owned())
+        .collect()
+}
+
+/// Extract the first ticket ID mentioned in a branch name, such as `PROJ-123`
+/// from `feature/proj-123-login`. Since branch names are often lowercase, the
+/// ticket ID is matched case-insensitively and returned in uppercase.
+pub fn extract_ticket_id_from_branch_name(branch_name: &str) -> Option<String> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"\b[A-Za-z][A-Za-z0-9]+-[0-9]+\b")
+            .expect("Failed to compile `extract_ticket_id_from_branch_name` regex");
+    }
+    RE.find(branch_name)
+        .map(|ticket_id| ticket_id.as_str().to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_ticket_ids() {
+        assert_eq!(
+            extract_ticket_ids("PROJ-123: fix the thing\n\nSee also AB2-4."),
+            vec!["PROJ-123".to_owned(), "AB2-4".to_owned()]
+        );
+        assert!(extract_ticket_ids("utf-8 handling for x-1").is_empty());
+    }
+
+    #[test]
+    fn test_extract_ticket_id_from_branch_name() {
+        assert_eq!(
+            extract_ticket_id_from_branch_name("feature/proj-123-login"),
+            Some("PROJ-123".to_owned())
+        );
+        assert_eq!(
+            extract_ticket_id_from_branch_name("AB2-4"),
+            Some("AB2-4".to_owned())
+        );
+        assert_eq!(extract_ticket_id_from_branch_name("feature/login"), None);
+    }
+
+    #[test]
+    fn test_most_common() {
+        let counts: HashMap<String, usize> = [("b".to_owned(), 2), ("a".to_owned(), 2)]
+            .into_iter()
+            .collect();
+        assert_eq!(most_common(counts), Some("a".to_owned()));
+        assert_eq!(most_common(HashMap::new()), None);
+    }
+}
@@ -0,0 +1,353 @@
+//! Run user-configured scripts after branchless operations.
+//!
+//! Users can register commands with `branchless.hooks.postRewrite`, which is
+//! run after any operation which rewrites or hides commits, or with a
+//! command-specific key such as `branchless.hooks.postSync`. This lets them
+//! update external trackers or trigger builds when their commits change.
+//!
+//! Each hook is invoked with the name of the branchless command as its first
+//! argument, and receives one line of the form `<old-oid> <new-oid>` on stdin
+//! for each affected commit, as with Git's own `post-rewrite` hook. Commits
+//! which were hidden or dropped are reported with the all-zeros OID as their
+//! new OID.
+//!
+//! New commit messages can also be validated before they're committed with the
+//! command configured in `branchless.commit.msgHook`, such as a commit message
+//! linter.
+//!
+//! Commits which are created in memory don't go through `git commit`, so Git's
+//! own `pre-commit` and `commit-msg` hooks aren't run for them. Users can set
+//! `branchless.hooks.runCommitHooks` to run those hooks for such commits too.
+
+use std::collections::HashSet;
+use std::fmt::Write as FmtWrite;
+use std::io::Write as IoWrite;
+use std::process::Stdio;
+
+use eyre::{eyre, Context};
+use tracing::instrument;
+
+use crate::core::config::{get_commit_msg_hook, get_run_commit_hooks, get_user_hooks};
+use crate::core::effects::Effects;
+use crate::core::eventlog::{Event, EventTransactionId};
+use crate::git::{GitRunInfo, GitRunOpts, MaybeZeroOid, NonZeroOid, Repo, Tree};
+use crate::util::shell_command;
+
+/// Get the name of the command-specific hook key for the given command, such
+/// as `postSync` for `sync`.
+fn get_command_hook_name(command_name: &str) -> String {
+    let mut chars = command_name.chars();
+    match chars.next() {
+        Some(first) => format!("post{}{}", first.to_ascii_uppercase(), chars.as_str()),
+        None => "post".to_string(),
+    }
+}
+
+/// Determine the commits which were rewritten or hidden by the given events, in
+/// the order that they happened.
+pub fn get_rewritten_oids<'a>(
+    events: impl IntoIterator<Item = &'a Event>,
+) -> Vec<(NonZeroOid, MaybeZeroOid)> {
+    let events: Vec<&Event> = events.into_iter().collect();
+    let rewritten_oids: HashSet<NonZeroOid> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::RewriteEvent {
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                ..
+            } => Some(*old_commit_oid),
+            _ => None,
+        })
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    for event in events {
+        let entry = match event {
+            Event::RewriteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid,
+            } => (*old_commit_oid, *new_commit_oid),
+
+            // Rewritten commits are also marked as obsolete, but they've
+            // already been reported with their new OID.
+            Event::ObsoleteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } if !rewritten_oids.contains(commit_oid) => (*commit_oid, MaybeZeroOid::Zero),
+
+            _ => continue,
+        };
+        if seen.insert(entry) {
+            result.push(entry);
+        }
+    }
+    result
+}
+
+/// Run the hooks configured for the given command, as well as the
+/// `postRewrite` hooks, passing them the rewritten commits on stdin.
+///
+/// Hooks are not run if no commits were rewritten. A hook which fails only
+/// produces a warning, since the operation itself has already completed.
+#[instrument(skip(rewritten_oids))]
+pub fn run_user_hooks(
+    effects: &Effects,
+    repo: &Repo,
+    command_name: &str,
+    rewritten_oids: &[(NonZeroOid, MaybeZeroOid)],
+) -> eyre::Result<()> {
+    if rewritten_oids.is_empty() {
+        return Ok(());
+    }
+
+    let mut hooks = get_user_hooks(repo, &get_command_hook_name(command_name))?;
+    hooks.extend(get_user_hooks(repo, "postRewrite")?);
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let stdin: String = rewritten_oids
+        .iter()
+        .map(|(old_oid, new_oid)| format!("{} {}\n", old_oid, new_oid))
+        .collect();
+    for hook in hooks {
+        let output = run_user_hook(repo, &hook, command_name, &stdin)?;
+        write!(
+            effects.get_output_stream(),
+            "{}",
+            String::from_utf8_lossy(&output.stdout)
+        )?;
+        write!(
+            effects.get_error_stream(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        )?;
+        if !output.status.success() {
+            writeln!(
+                effects.get_output_stream(),
+                "branchless: hook `{}` exited with status {:?}",
+                hook,
+                output.status.code()
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn run_user_hook(
+    repo: &Repo,
+    hook: &str,
+    command_name: &str,
+    stdin: &str,
+) -> eyre::Result<std::process::Output> {
+    let mut child = shell_command(hook)?
+        .current_dir(
+            repo.get_working_copy_path()
+                .unwrap_or_else(|| repo.get_path()),
+        )
+        .arg(command_name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .wrap_err_with(|| format!("Invoking hook: {}", hook))?;
+
+    {
+        let mut child_stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| eyre!("could not get stdin for hook: {}", hook))?;
+        // The hook may exit without reading its input, so ignore broken pipes.
+        match child_stdin.write_all(stdin.as_bytes()) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+            Err(err) => return Err(err).wrap_err("Writing to hook stdin"),
+        }
+    }
+
+    child
+        .wait_with_output()
+        .wrap_err_with(|| format!("Waiting for hook: {}", hook))
+}
+
+/// Validate a new commit message with the command configured in
+/// `branchless.commit.msgHook`, if any.
+///
+/// The message is written to a file in the repository's Git directory, and the
+/// path to that file is passed to the command as its first argument. Returns
+/// the combined output of the command if it rejected the message.
+#[instrument]
+pub fn run_commit_msg_hook(repo: &Repo, message: &str) -> eyre::Result<Option<String>> {
+    let hook = match get_commit_msg_hook(repo)? {
+        Some(hook) => hook,
+        None => return Ok(None),
+    };
+
+    let mut hook_command = shell_command(&hook)?;
+    let message_path = repo.get_path().join("BRANCHLESS_COMMIT_MSG");
+    std::fs::write(&message_path, message)
+        .wrap_err_with(|| format!("Writing commit message to: {:?}", message_path))?;
+    let output = hook_command
+        .current_dir(
+            repo.get_working_copy_path()
+                .unwrap_or_else(|| repo.get_path()),
+        )
+        .arg(&message_path)
+        .stdin(Stdio::null())
+        .output()
+        .wrap_err_with(|| format!("Invoking commit message hook: {}", hook));
+    std::fs::remove_file(&message_path).ok();
+    let output = output?;
+
+    if output.status.success() {
+        Ok(None)
+    } else {
+        let mut result = String::from_utf8_lossy(&output.stdout).into_owned();
+        result.push_str(&String::from_utf8_lossy(&output.stderr));
+        if result.trim().is_empty() {
+            result = format!(
+                "hook `{}` exited with status {:?}",
+                hook,
+                output.status.code()
+            );
+        }
+        Ok(Some(result.trim_end().to_string()))
+    }
+}
+
+/// The result of running Git's commit hooks for a new commit.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CommitHooksResult {
+    /// The hooks succeeded (or weren't configured to run).
+    Succeeded {
+        /// The message to use for the commit, which the `commit-msg` hook may
+        /// have changed.
+        message: String,
+    },
+
+    /// The hook with the given name failed.
+    Failed {
+        /// The name of the hook, such as `pre-commit`.
+        hook_name: &'static str,
+    },
+}
+
+/// Run Git's `pre-commit` and `commit-msg` hooks for a commit with the given
+/// tree and message, which is about to be created in memory, if
+/// `branchless.hooks.runCommitHooks` is set.
+///
+/// The `pre-commit` hook is run with `GIT_INDEX_FILE` pointing to a temporary
+/// index containing the given tree, so that it checks the contents of the new
+/// commit rather than the working copy.
+#[instrument]
+pub fn run_commit_hooks(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    tree: &Tree,
+    message: &str,
+) -> eyre::Result<CommitHooksResult> {
+    if !get_run_commit_hooks(repo)? {
+        return Ok(CommitHooksResult::Succeeded {
+            message: message.to_string(),
+        });
+    }
+
+    let index_path = repo.get_path().join("branchless-commit-hooks.index");
+    let git_run_info = {
+        let mut git_run_info = git_run_info.clone();
+        git_run_info
+            .env
+            .insert("GIT_INDEX_FILE".into(), index_path.clone().into_os_string());
+        git_run_info
+    };
+    let pre_commit_result = git_run_info
+        .run_silent(
+            repo,
+            Some(event_tx_id),
+            &["read-tree", &tree.get_oid().to_string()],
+            GitRunOpts::default(),
+        )
+        .and_then(|_| {
+            let args: &[&str] = &[];
+            git_run_info.run_hook_checked(effects, repo, "pre-commit", event_tx_id, args)
+        });
+    std::fs::remove_file(&index_path).ok();
+    if !pre_commit_result? {
+        return Ok(CommitHooksResult::Failed {
+            hook_name: "pre-commit",
+        });
+    }
+
+    let message_path = repo.get_path().join("COMMIT_EDITMSG");
+    std::fs::write(&message_path, message)
+        .wrap_err_with(|| format!("Writing commit message to: {:?}", message_path))?;
+    if !git_run_info.run_hook_checked(
+        effects,
+        repo,
+        "commit-msg",
+        event_tx_id,
+        &[message_path.to_string_lossy()],
+    )? {
+        return Ok(CommitHooksResult::Failed {
+            hook_name: "commit-msg",
+        });
+    }
+    let message = std::fs::read_to_string(&message_path)
+        .wrap_err_with(|| format!("Reading commit message from: {:?}", message_path))?;
+    Ok(CommitHooksResult::Succeeded { message })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::core::eventlog::testing::make_dummy_transaction_id;
+
+    use super::*;
+
+    #[test]
+    fn test_get_command_hook_name() {
+        assert_eq!(get_command_hook_name("sync"), "postSync");
+        assert_eq!(get_command_hook_name("restack"), "postRestack");
+    }
+
+    #[test]
+    fn test_get_rewritten_oids() -> eyre::Result<()> {
+        let event_tx_id = make_dummy_transaction_id(1);
+        let oid1 = NonZeroOid::from_str("1111111111111111111111111111111111111111")?;
+        let oid2 = NonZeroOid::from_str("2222222222222222222222222222222222222222")?;
+        let oid3 = NonZeroOid::from_str("3333333333333333333333333333333333333333")?;
+        let events = vec![
+            Event::RewriteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(oid1),
+                new_commit_oid: MaybeZeroOid::NonZero(oid2),
+            },
+            Event::ObsoleteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                commit_oid: oid1,
+            },
+            Event::ObsoleteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                commit_oid: oid3,
+            },
+        ];
+        assert_eq!(
+            get_rewritten_oids(&events),
+            vec![
+                (oid1, MaybeZeroOid::NonZero(oid2)),
+                (oid3, MaybeZeroOid::Zero),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Categorized errors, so that callers can distinguish between different kinds
+//! of failures programmatically.
+//!
+//! Most functions in this crate return an [`eyre::Result`]. Failures which fall
+//! into a known category are raised as an [`Error`] inside of the
+//! [`eyre::Report`], and can be recovered with [`Error::find_in_report`].
+//! Failures which don't have a category are assumed to be internal errors,
+//! which probably indicate a bug.
+
+use std::path::Path;
+
+use crate::git::NonZeroOid;
+use crate::util::ExitCode;
+
+/// The broad category of a failure. Each category corresponds to a distinct
+/// exit code for the `git-branchless` process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The user requested something which couldn't be done, such as by passing
+    /// a malformed revset or referring to a commit which doesn't exist.
+    User,
+
+    /// The operation stopped because of a merge conflict, which the user has
+    /// to resolve.
+    Conflict,
+
+    /// The repository or the data stored by `git-branchless` is in an
+    /// inconsistent state. Running `git branchless repair` may fix it.
+    Corruption,
+
+    /// The operation was interrupted by the user, such as by pressing Ctrl-C.
+    Interrupted,
+
+    /// An unexpected error occurred. This probably indicates a bug.
+    Internal,
+}
+
+impl ErrorCategory {
+    /// Get the exit code which the process should exit with for this category
+    /// of failure.
+    ///
+    /// Exit code 2 is not used, since it's used by the command-line parser to
+    /// indicate invalid arguments. Exit code 101 is the same exit code as a
+    /// Rust panic. Exit code 130 is the conventional exit code for a process
+    /// terminated by `SIGINT`.
+    pub fn exit_code(self) -> ExitCode {
+        match self {
+            ErrorCategory::User => ExitCode(1),
+            ErrorCategory::Conflict => ExitCode(3),
+            ErrorCategory::Corruption => ExitCode(4),
+            ErrorCategory::Interrupted => ExitCode(130),
+            ErrorCategory::Internal => ExitCode(101),
+        }
+    }
+
+    /// Determine the category of the given error report.
+    pub fn of_report(report: &eyre::Report) -> Self {
+        match Error::find_in_report(report) {
+            Some(error) => error.category(),
+            None => ErrorCategory::Internal,
+        }
+    }
+}
+
+/// An error which belongs to a known [`ErrorCategory`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The user requested something which couldn't be done.
+    #[error("{message}")]
+    User {
+        /// A description of the problem.
+        message: String,
+    },
+
+    /// A merge conflict occurred while applying a commit.
+    #[error("merge conflict while applying commit {commit_oid}")]
+    Conflict {
+        /// The commit which couldn't be applied.
+        commit_oid: NonZeroOid,
+    },
+
+    /// The repository or the data stored by `git-branchless` is inconsistent.
+    #[error("{message}")]
+    Corruption {
+        /// A description of the problem.
+        message: String,
+
+        /// A suggestion for how the user might fix the problem.
+        hint: Option<String>,
+    },
+
+    /// The operation was interrupted by the user.
+    #[error("operation was interrupted")]
+    Interrupted,
+}
+
+impl Error {
+    /// Get the category of this error.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::User { .. } => ErrorCategory::User,
+            Error::Conflict { .. } => ErrorCategory::Conflict,
+            Error::Corruption { .. } => ErrorCategory::Corruption,
+            Error::Interrupted => ErrorCategory::Interrupted,
+        }
+    }
+
+    /// Get a suggestion for how the user might fix this error, if any.
+    pub fn hint(&self) -> Option<&str> {
+        match self {
+            Error::Corruption { hint, .. } => hint.as_deref(),
+            Error::User { .. } | Error::Conflict { .. } | Error::Interrupted => None,
+        }
+    }
+
+    /// Categorize a failure to open or read the SQLite database at the given
+    /// path. Failures caused by the database file being corrupt are
+    /// categorized as [`ErrorCategory::Corruption`]; other failures (such as
+    /// the database being locked) are returned unchanged.
+    pub fn categorize_database_error(report: eyre::Report, path: &Path) -> eyre::Report {
+        let is_corrupt = report.chain().any(|error| {
+            matches!(
+                error.downcast_ref::<rusqlite::Error>(),
+                Some(rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error {
+                        code: rusqlite::ErrorCode::DatabaseCorrupt
+                            | rusqlite::ErrorCode::NotADatabase,
+                        ..
+                    },
+                    _,
+                ))
+            )
+        });
+        if is_corrupt {
+            report.wrap_err(Error::Corruption {
+                message: format!("The git-branchless database at {:?} is corrupt.", path),
+                hint: Some(
+                    "Move it aside to start over with an empty event log. This discards the history used by `git undo`."
+                        .to_string(),
+                ),
+            })
+        } else {
+            report
+        }
+    }
+
+    /// Find the outermost categorized error in the given error report, if any.
+    pub fn find_in_report(report: &eyre::Report) -> Option<&Error> {
+        // The error may have been attached as context with `wrap_err`, in
+        // which case it doesn't appear in the chain of sources.
+        report.downcast_ref::<Error>().or_else(|| {
+            report
+                .chain()
+                .find_map(|error| error.downcast_ref::<Error>())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::WrapErr;
+
+    use super::*;
+
+    #[test]
+    fn test_error_category_of_report() {
+        let report: eyre::Report = Error::Corruption {
+            message: "bad commit graph".to_string(),
+            hint: None,
+        }
+        .into();
+        let report = Err::<(), _>(report)
+            .wrap_err("Opening commit graph")
+            .unwrap_err();
+        assert_eq!(ErrorCategory::of_report(&report), ErrorCategory::Corruption);
+
+        let report = Err::<(), _>(eyre::eyre!("bad revset"))
+            .wrap_err(Error::User {
+                message: "Could not parse revset".to_string(),
+            })
+            .unwrap_err();
+        assert_eq!(ErrorCategory::of_report(&report), ErrorCategory::User);
+
+        let report = eyre::eyre!("something unexpected");
+        assert_eq!(ErrorCategory::of_report(&report), ErrorCategory::Internal);
+    }
+}
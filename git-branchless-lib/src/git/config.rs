@@ -110,6 +110,23 @@ impl GetConfigValue<bool> for bool {
     }
 }
 
+impl GetConfigValue<i32> for i32 {
+    fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<i32>> {
+        #[instrument]
+        fn inner(config: &Config, key: &str) -> eyre::Result<Option<i32>> {
+            let value = match config.inner.get_i32(key) {
+                Ok(value) => Some(value),
+                Err(err) if err.code() == git2::ErrorCode::NotFound => None,
+                Err(err) => {
+                    return Err(wrap_git_error(err)).wrap_err("Looking up int value for config key")
+                }
+            };
+            Ok(value)
+        }
+        inner(config, key.as_ref())
+    }
+}
+
 impl GetConfigValue<PathBuf> for PathBuf {
     fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<PathBuf>> {
         #[instrument]
@@ -128,6 +145,40 @@ impl GetConfigValue<PathBuf> for PathBuf {
     }
 }
 
+/// Multivariable config entries. All values set for the key are returned in
+/// the order that they were read, or `None` if the key was not set at all.
+impl GetConfigValue<Vec<String>> for Vec<String> {
+    fn get_from_config(config: &Config, key: impl AsRef<str>) -> eyre::Result<Option<Vec<String>>> {
+        #[instrument]
+        fn inner(config: &Config, key: &str) -> eyre::Result<Option<Vec<String>>> {
+            let mut entries = match config.inner.multivar(key, None) {
+                Ok(entries) => entries,
+                Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+                Err(err) => {
+                    return Err(wrap_git_error(err))
+                        .wrap_err("Looking up multivar values for config key")
+                }
+            };
+
+            let mut values = Vec::new();
+            while let Some(entry) = entries.next() {
+                let entry = entry
+                    .map_err(wrap_git_error)
+                    .wrap_err("Iterating multivar values for config key")?;
+                if let Some(value) = entry.value() {
+                    values.push(value.to_owned());
+                }
+            }
+            if values.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(values))
+            }
+        }
+        inner(config, key.as_ref())
+    }
+}
+
 /// Read-only interface to Git's configuration.
 pub trait ConfigRead {
     /// Convert this object into an owned, writable version of the
@@ -212,6 +263,56 @@ impl Config {
         Ok(Config { inner })
     }
 
+    /// Open the user's global configuration file, usually `~/.gitconfig`. The
+    /// file doesn't need to exist yet; it's created when a value is first
+    /// written to it.
+    #[instrument]
+    pub fn open_global() -> eyre::Result<Self> {
+        let path = match git2::Config::find_global() {
+            Ok(path) => path,
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                let home_dir = std::env::var_os("HOME")
+                    .or_else(|| std::env::var_os("USERPROFILE"))
+                    .ok_or_else(|| eyre::eyre!("Could not determine the home directory"))?;
+                PathBuf::from(home_dir).join(".gitconfig")
+            }
+            Err(err) => return Err(wrap_git_error(err)),
+        };
+        Self::open(&path)
+    }
+
+    /// Open the system-wide configuration file, such as `/etc/gitconfig`.
+    #[instrument]
+    pub fn open_system() -> eyre::Result<Self> {
+        let path = git2::Config::find_system()
+            .map_err(wrap_git_error)
+            .wrap_err("Finding system config file")?;
+        Self::open(&path)
+    }
+
+    /// Get all entries whose names match the provided regex, such as
+    /// `^branchless\.`, as pairs of name and value. Names are normalized to
+    /// lowercase by Git. Entries which are set several times appear once per
+    /// value.
+    #[instrument]
+    pub fn get_entries(&self, regex: &str) -> eyre::Result<Vec<(String, String)>> {
+        let mut entries = self
+            .inner
+            .entries(Some(regex))
+            .map_err(wrap_git_error)
+            .wrap_err("Listing config entries")?;
+        let mut result = Vec::new();
+        while let Some(entry) = entries.next() {
+            let entry = entry
+                .map_err(wrap_git_error)
+                .wrap_err("Iterating config entries")?;
+            if let (Some(name), Some(value)) = (entry.name(), entry.value()) {
+                result.push((name.to_owned(), value.to_owned()));
+            }
+        }
+        Ok(result)
+    }
+
     #[instrument]
     fn set_inner(&mut self, key: &str, value: ConfigValue) -> eyre::Result<()> {
         match &value.inner {
@@ -7,13 +7,93 @@ use eyre::Context;
 use git_record::{FileState, Section, SectionChangedLine};
 use itertools::Itertools;
 
-use super::{MaybeZeroOid, Repo};
+use super::{FileMode, MaybeZeroOid, NonZeroOid, Repo};
+
+/// The prefix of the line used to represent the commit that a submodule points
+/// to in the [`FileState`]s from [`process_diff_for_record`]. This is the same
+/// representation as used by `git diff`.
+const SUBMODULE_LINE_PREFIX: &str = "Subproject commit ";
 
 /// A diff between two trees/commits.
 pub struct Diff<'repo> {
     pub(super) inner: git2::Diff<'repo>,
 }
 
+impl Diff<'_> {
+    /// Render the diff as a patch in the unified format used by `git diff`.
+    pub fn get_patch(&self) -> eyre::Result<Vec<u8>> {
+        let mut result = Vec::new();
+        self.inner
+            .print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+                if let origin @ ('+' | '-' | ' ') = line.origin() {
+                    let mut buf = [0; 4];
+                    result.extend(origin.encode_utf8(&mut buf).as_bytes());
+                }
+                result.extend(line.content());
+                true
+            })
+            .wrap_err("Printing diff as patch")?;
+        Ok(result)
+    }
+
+    /// Render a summary of the number of lines changed in each file, in the
+    /// format used by `git diff --stat`.
+    pub fn get_stat(&self, width: usize) -> eyre::Result<String> {
+        let stats = self.inner.stats().wrap_err("Calculating diff stats")?;
+        let buf = stats
+            .to_buf(git2::DiffStatsFormat::FULL, width)
+            .wrap_err("Rendering diff stats")?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    /// Get the number of lines inserted and the number of lines deleted by the
+    /// diff, in that order.
+    pub fn get_line_counts(&self) -> eyre::Result<(usize, usize)> {
+        let stats = self.inner.stats().wrap_err("Calculating diff stats")?;
+        Ok((stats.insertions(), stats.deletions()))
+    }
+
+    /// Get the paths of the files changed in the diff. Renamed files are
+    /// reported under their new paths.
+    pub fn get_changed_paths(&self) -> Vec<PathBuf> {
+        self.inner
+            .deltas()
+            .filter_map(|delta| {
+                delta
+                    .new_file()
+                    .path()
+                    .or_else(|| delta.old_file().path())
+                    .map(PathBuf::from)
+            })
+            .collect()
+    }
+}
+
+/// Whether the given [`FileState`] from [`process_diff_for_record`] represents
+/// a submodule.
+pub fn is_submodule_file_state(file_state: &FileState) -> bool {
+    let submodule_file_mode: usize = u32::from(FileMode::Commit).try_into().unwrap();
+    file_state.file_mode == Some(submodule_file_mode)
+}
+
+/// Get the commit that the submodule represented by the given [`FileState`]
+/// should point to after recording its selected changes. Returns `None` if the
+/// submodule should be removed.
+pub fn get_selected_submodule_commit(file_state: &FileState) -> eyre::Result<Option<NonZeroOid>> {
+    let (selected, _unselected) = file_state.get_selected_contents();
+    // If only the new commit was selected, then the unselected old commit is
+    // also included in the selected contents, so use the last line.
+    match selected.lines().last() {
+        None => Ok(None),
+        Some(line) => {
+            let oid = line.strip_prefix(SUBMODULE_LINE_PREFIX).ok_or_else(|| {
+                eyre::eyre!("Could not parse submodule commit from line: {:?}", line)
+            })?;
+            Ok(Some(oid.parse()?))
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct GitHunk {
     old_start: usize,
@@ -103,7 +183,11 @@ pub fn process_diff_for_record(
 
     let deltas = std::mem::take(&mut *deltas.lock().unwrap());
     let mut result = Vec::new();
-    for (path, delta) in deltas {
+    // Sort by path so that files are always presented in the same order.
+    for (path, delta) in deltas
+        .into_iter()
+        .sorted_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs))
+    {
         let Delta {
             old_oid,
             old_file_mode,
@@ -112,6 +196,34 @@ pub fn process_diff_for_record(
             content,
         } = delta;
 
+        if old_file_mode == git2::FileMode::Commit || new_file_mode == git2::FileMode::Commit {
+            // Submodule entries point to commits which generally aren't in
+            // this repository, so there are no contents to diff. Instead,
+            // present the change to the commit that the submodule points to
+            // as a single changed line, which can be selected like any other.
+            let make_lines = |oid: git2::Oid, file_mode| match MaybeZeroOid::from(oid) {
+                MaybeZeroOid::NonZero(oid) if file_mode == git2::FileMode::Commit => {
+                    vec![SectionChangedLine {
+                        is_selected: false,
+                        line: Cow::Owned(format!("{}{}\n", SUBMODULE_LINE_PREFIX, oid)),
+                    }]
+                }
+                _ => Vec::new(),
+            };
+            let submodule_file_mode: usize = u32::from(FileMode::Commit).try_into().unwrap();
+            result.push((
+                path,
+                FileState {
+                    file_mode: Some(submodule_file_mode),
+                    sections: vec![Section::Changed {
+                        before: make_lines(old_oid, old_file_mode),
+                        after: make_lines(new_oid, new_file_mode),
+                    }],
+                },
+            ));
+            continue;
+        }
+
         if new_oid.is_zero() {
             result.push((path, FileState::absent()));
             continue;
@@ -290,3 +402,75 @@ pub fn process_diff_for_record(
     }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::effects::Effects;
+    use crate::core::formatting::Glyphs;
+    use crate::git::hydrate_tree;
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_process_diff_for_record_submodule() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let repo = git.get_repo()?;
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+        let head_commit = repo.find_commit_or_fail(head_oid)?;
+        let head_tree = head_commit.get_tree()?;
+        let old_oid: NonZeroOid = "1111111111111111111111111111111111111111".parse()?;
+        let new_oid: NonZeroOid = "2222222222222222222222222222222222222222".parse()?;
+        let make_tree = |oid| -> eyre::Result<_> {
+            let tree_oid = hydrate_tree(
+                &repo,
+                Some(&head_tree),
+                HashMap::from([(PathBuf::from("submodule"), Some((oid, FileMode::Commit)))]),
+            )?;
+            repo.find_tree_or_fail(tree_oid)
+        };
+        let old_tree = make_tree(old_oid)?;
+        let new_tree = make_tree(new_oid)?;
+
+        let diff = repo.get_diff_between_trees(&effects, Some(&old_tree), &new_tree, 0)?;
+        let mut file_states = process_diff_for_record(&repo, &diff)?;
+        assert_eq!(file_states.len(), 1);
+        let (path, mut file_state) = file_states.remove(0);
+        assert_eq!(path, PathBuf::from("submodule"));
+        assert!(is_submodule_file_state(&file_state));
+        insta::assert_debug_snapshot!(file_state.sections, @r###"
+        [
+            Changed {
+                before: [
+                    SectionChangedLine {
+                        is_selected: false,
+                        line: "Subproject commit 1111111111111111111111111111111111111111\n",
+                    },
+                ],
+                after: [
+                    SectionChangedLine {
+                        is_selected: false,
+                        line: "Subproject commit 2222222222222222222222222222222222222222\n",
+                    },
+                ],
+            },
+        ]
+        "###);
+
+        assert_eq!(get_selected_submodule_commit(&file_state)?, Some(old_oid));
+        match &mut file_state.sections[0] {
+            Section::Changed { before, after } => {
+                for line in before.iter_mut().chain(after.iter_mut()) {
+                    line.is_selected = true;
+                }
+            }
+            section => panic!("Unexpected section: {:?}", section),
+        }
+        assert_eq!(get_selected_submodule_commit(&file_state)?, Some(new_oid));
+
+        Ok(())
+    }
+}
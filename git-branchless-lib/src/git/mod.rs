@@ -4,22 +4,29 @@ mod config;
 mod diff;
 mod index;
 mod oid;
+mod reference_backend;
 mod repo;
 mod run;
 mod snapshot;
 mod status;
 mod tree;
+mod watchman;
 
 pub use config::{Config, ConfigRead, ConfigValue, ConfigWrite};
-pub use diff::{process_diff_for_record, Diff};
+pub use diff::{
+    get_selected_submodule_commit, is_submodule_file_state, process_diff_for_record, Diff,
+};
 pub use index::{update_index, Index, IndexEntry, Stage, UpdateIndexCommand};
 pub use oid::{MaybeZeroOid, NonZeroOid};
+pub use reference_backend::{
+    FilesReferenceBackend, ReferenceBackend, ReferenceStorageFormat, ReftableReferenceBackend,
+};
 pub use repo::{
     message_prettify, AmendFastOptions, Branch, BranchType, CategorizedReferenceName,
     CherryPickFastError, CherryPickFastOptions, Commit, GitVersion, PatchId, Reference,
-    ReferenceName, ReferenceTarget, Repo, ResolvedReferenceInfo, Time,
+    ReferenceName, ReferenceTarget, Repo, ResolvedReferenceInfo, Signature, Time, Worktree,
 };
-pub use run::{GitRunInfo, GitRunOpts, GitRunResult};
+pub use run::{GitRunInfo, GitRunOpts, GitRunResult, ReferenceTransactionError, ReferenceUpdate};
 pub use snapshot::{WorkingCopyChangesType, WorkingCopySnapshot};
-pub use status::{FileMode, FileStatus, StatusEntry};
-pub use tree::{dehydrate_tree, hydrate_tree, Tree};
+pub use status::{FileMode, FileStatus, StatusEntry, WorkingCopyFileModeSupport};
+pub use tree::{dehydrate_tree, hydrate_tree, make_empty_tree, Tree};
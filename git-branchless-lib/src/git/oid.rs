@@ -4,6 +4,7 @@ use std::fmt::Display;
 use std::str::FromStr;
 
 use eyre::Context;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tracing::instrument;
 
 use crate::git::repo::wrap_git_error;
@@ -21,6 +22,21 @@ impl NonZeroOid {
     }
 }
 
+/// Serialized as the raw 20-byte OID, for compactness.
+impl Serialize for NonZeroOid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.as_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for NonZeroOid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes: Vec<u8> = Deserialize::deserialize(deserializer)?;
+        let oid = MaybeZeroOid::from_bytes(&bytes).map_err(serde::de::Error::custom)?;
+        NonZeroOid::try_from(oid).map_err(serde::de::Error::custom)
+    }
+}
+
 impl std::fmt::Debug for NonZeroOid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "NonZeroOid({:?})", self.inner)
@@ -108,7 +124,7 @@ pub(super) fn make_non_zero_oid(oid: git2::Oid) -> NonZeroOid {
 ///
 /// This type is isomorphic to `Option<NonZeroOid>`. It should be used primarily
 /// when converting to and from string representations of OID values.
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub enum MaybeZeroOid {
     /// The zero OID (i.e. 40 `0`s).
     Zero,
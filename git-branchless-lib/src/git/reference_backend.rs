@@ -0,0 +1,260 @@
+//! Access to the storage backend for a repository's references.
+//!
+//! Git historically stored references as loose files under `refs/`, plus a
+//! `packed-refs` file. Newer versions of Git can instead store them in the
+//! `reftable` format (see `extensions.refStorage` in `git-config(1)`), which
+//! behaves differently in ways which matter to the `reference-transaction`
+//! hook.
+//!
+//! Only the handling of packed references is abstracted here. References are
+//! otherwise still read and updated through libgit2 and `git update-ref`.
+//! libgit2 can't open `reftable` repositories yet, so `Repo::from_dir` rejects
+//! them with an explanatory error, and the `reftable` backend only takes effect
+//! with a version of libgit2 which supports them.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use eyre::Context;
+use lazy_static::lazy_static;
+use tracing::{instrument, warn};
+
+use crate::git::{MaybeZeroOid, ReferenceName};
+
+/// The format used to store a repository's references, as set by the
+/// `extensions.refStorage` configuration option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReferenceStorageFormat {
+    /// Loose reference files, plus the `packed-refs` file. This is the default.
+    Files,
+
+    /// The `reftable` format.
+    Reftable,
+}
+
+impl FromStr for ReferenceStorageFormat {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "files" => Ok(Self::Files),
+            "reftable" => Ok(Self::Reftable),
+            other => eyre::bail!("Unknown reference storage format: {:?}", other),
+        }
+    }
+}
+
+/// Backend-specific operations on a repository's references.
+pub trait ReferenceBackend: Debug {
+    /// The storage format handled by this backend.
+    fn get_format(&self) -> ReferenceStorageFormat;
+
+    /// Get the references whose values were stored in bulk as of the last
+    /// time they were packed or compacted.
+    ///
+    /// When references are packed, Git may report a spurious creation or
+    /// deletion for each reference being packed to the `reference-transaction`
+    /// hook. Callers can compare the reported values against these values to
+    /// detect such updates. Backends which don't report spurious updates return
+    /// an empty map.
+    fn get_packed_references(&self) -> eyre::Result<HashMap<ReferenceName, MaybeZeroOid>>;
+}
+
+/// The backend for loose reference files and the `packed-refs` file.
+#[derive(Debug)]
+pub struct FilesReferenceBackend {
+    packed_refs_path: PathBuf,
+}
+
+impl FilesReferenceBackend {
+    /// Constructor.
+    pub fn new(packed_refs_path: PathBuf) -> Self {
+        Self { packed_refs_path }
+    }
+}
+
+#[instrument]
+fn parse_packed_refs_line(line: &str) -> Option<(ReferenceName, MaybeZeroOid)> {
+    if line.is_empty() {
+        return None;
+    }
+    if line.starts_with('#') {
+        // The leading `# pack-refs with:` pragma.
+        return None;
+    }
+    if !line.starts_with(|c: char| c.is_ascii_hexdigit()) {
+        // The leading `# pack-refs with:` pragma.
+        warn!(?line, "Unrecognized pack-refs line starting character");
+        return None;
+    }
+
+    lazy_static! {
+        static ref RE: regex::Regex = regex::Regex::new(r"^([^ ]+) (.+)$").unwrap();
+    };
+    match RE.captures(line) {
+        None => {
+            warn!(?line, "No regex match for pack-refs line");
+            None
+        }
+
+        Some(captures) => {
+            let oid = &captures[1];
+            let oid = match MaybeZeroOid::from_str(oid) {
+                Ok(oid) => oid,
+                Err(err) => {
+                    warn!(?oid, ?err, "Could not parse OID for pack-refs line");
+                    return None;
+                }
+            };
+
+            let reference_name = &captures[2];
+            let reference_name = ReferenceName::from(reference_name);
+
+            Some((reference_name, oid))
+        }
+    }
+}
+
+impl ReferenceBackend for FilesReferenceBackend {
+    fn get_format(&self) -> ReferenceStorageFormat {
+        ReferenceStorageFormat::Files
+    }
+
+    #[instrument]
+    fn get_packed_references(&self) -> eyre::Result<HashMap<ReferenceName, MaybeZeroOid>> {
+        let file = match File::open(&self.packed_refs_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let reader = BufReader::new(file);
+        let mut result = HashMap::new();
+        for line in reader.lines() {
+            let line = line.wrap_err("Reading line from packed-refs")?;
+            if line.is_empty() {
+                continue;
+            }
+            if let Some((k, v)) = parse_packed_refs_line(&line) {
+                result.insert(k, v);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// The backend for the `reftable` format.
+#[derive(Debug)]
+pub struct ReftableReferenceBackend;
+
+impl ReferenceBackend for ReftableReferenceBackend {
+    fn get_format(&self) -> ReferenceStorageFormat {
+        ReferenceStorageFormat::Reftable
+    }
+
+    fn get_packed_references(&self) -> eyre::Result<HashMap<ReferenceName, MaybeZeroOid>> {
+        // Compacting reftables doesn't go through reference transactions, so
+        // the values reported to the hook can be used as-is.
+        Ok(HashMap::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{GitVersion, Repo};
+    use crate::testing::make_git;
+
+    #[test]
+    fn test_parse_packed_refs_line() {
+        let line = "1234567812345678123456781234567812345678 refs/foo/bar";
+        let name = ReferenceName::from("refs/foo/bar");
+        let oid = MaybeZeroOid::from_str("1234567812345678123456781234567812345678").unwrap();
+        assert_eq!(parse_packed_refs_line(line), Some((name, oid)));
+    }
+
+    #[test]
+    fn test_parse_reference_storage_format() -> eyre::Result<()> {
+        assert_eq!(
+            "files".parse::<ReferenceStorageFormat>()?,
+            ReferenceStorageFormat::Files
+        );
+        assert_eq!(
+            "reftable".parse::<ReferenceStorageFormat>()?,
+            ReferenceStorageFormat::Reftable
+        );
+        assert!("foo".parse::<ReferenceStorageFormat>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_reference_backend() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["pack-refs", "--all"])?;
+
+        let repo = git.get_repo()?;
+        let backend = repo.get_reference_backend()?;
+        assert_eq!(backend.get_format(), ReferenceStorageFormat::Files);
+        assert!(backend
+            .get_packed_references()?
+            .contains_key(&ReferenceName::from("refs/heads/master")));
+
+        git.run(&["config", "extensions.refStorage", "reftable"])?;
+        let repo = git.get_repo()?;
+        let backend = repo.get_reference_backend()?;
+        assert_eq!(backend.get_format(), ReferenceStorageFormat::Reftable);
+        assert!(backend.get_packed_references()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_unsupported_reftable_repository() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.run(&["config", "extensions.refStorage", "reftable"])?;
+        git.run(&["config", "core.repositoryFormatVersion", "1"])?;
+
+        let err = Repo::from_dir(&git.repo_path).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("which is not supported by the version of libgit2"),
+            "{:#}",
+            err
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_reftable_repository() -> eyre::Result<()> {
+        let git = make_git()?;
+        if git.get_version()? < GitVersion(2, 45, 0) {
+            // `--ref-format` isn't supported.
+            return Ok(());
+        }
+        git.run(&["init", "--ref-format=reftable"])?;
+
+        match Repo::from_dir(&git.repo_path) {
+            Ok(repo) => {
+                let backend = repo.get_reference_backend()?;
+                assert_eq!(backend.get_format(), ReferenceStorageFormat::Reftable);
+            }
+            Err(err) => {
+                assert!(
+                    format!("{:#}", err)
+                        .contains("which is not supported by the version of libgit2"),
+                    "{:#}",
+                    err
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
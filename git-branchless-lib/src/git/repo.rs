@@ -11,7 +11,7 @@
 
 use std::borrow::{Borrow, Cow};
 use std::collections::{HashMap, HashSet};
-use std::convert::{TryFrom, TryInto};
+use std::convert::TryInto;
 use std::ffi::OsStr;
 use std::ops::Add;
 use std::path::{Path, PathBuf};
@@ -21,28 +21,36 @@ use std::time::{Duration, SystemTime};
 
 use bstr::{BString, ByteSlice, ByteVec};
 use chrono::NaiveDateTime;
-use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
+use encoding_rs::Encoding;
 use eyre::Context;
 use git2::{message_trailers_bytes, DiffOptions};
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{instrument, warn};
 
+use crate::core::config::get_use_watchman;
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::EventTransactionId;
 use crate::core::formatting::{Glyphs, StyledStringBuilder};
+use crate::core::migrations::run_migrations;
 use crate::core::node_descriptors::{
     render_node_descriptors, CommitMessageDescriptor, CommitOidDescriptor, NodeObject, Redactor,
 };
+use crate::error::Error as LibError;
 use crate::git::config::{Config, ConfigRead};
 use crate::git::oid::{make_non_zero_oid, MaybeZeroOid, NonZeroOid};
-use crate::git::run::GitRunInfo;
+use crate::git::reference_backend::{
+    FilesReferenceBackend, ReferenceBackend, ReferenceStorageFormat, ReftableReferenceBackend,
+};
+use crate::git::run::{GitRunInfo, GitRunOpts, GitRunResult};
 use crate::git::tree::{dehydrate_tree, get_changed_paths_between_trees, hydrate_tree, Tree};
 
 use super::index::{Index, IndexEntry};
 use super::snapshot::WorkingCopySnapshot;
-use super::status::FileMode;
+use super::status::{FileMode, WorkingCopyFileModeSupport};
+use super::watchman::{self, PathsToCheck};
 use super::{Diff, StatusEntry};
 
 /// Convert a `git2::Error` into an `eyre::Error` with an auto-generated message.
@@ -51,18 +59,19 @@ pub(super) fn wrap_git_error(error: git2::Error) -> eyre::Error {
 }
 
 /// Clean up a message, removing extraneous whitespace plus comment lines starting with
-/// `comment_char`, and ensure that the message ends with a newline.
+/// `comment_string`, and ensure that the message ends with a newline.
 #[instrument]
-pub fn message_prettify(message: &str, comment_char: Option<char>) -> eyre::Result<String> {
-    let comment_char = match comment_char {
-        Some(ch) => {
-            let ch = u32::from(ch);
-            let ch = u8::try_from(ch).wrap_err("comment char was not ASCII")?;
-            Some(ch)
-        }
-        None => None,
+pub fn message_prettify(message: &str, comment_string: Option<&str>) -> eyre::Result<String> {
+    // `git2::message_prettify` only supports single-byte comment characters,
+    // so strip comment lines here instead.
+    let message = match comment_string {
+        Some(comment_string) if !comment_string.is_empty() => message
+            .split_inclusive('\n')
+            .filter(|line| !line.starts_with(comment_string))
+            .collect::<String>(),
+        Some(_) | None => message.to_string(),
     };
-    let message = git2::message_prettify(message, comment_char)?;
+    let message = git2::message_prettify(message, None)?;
     Ok(message)
 }
 
@@ -110,6 +119,26 @@ impl ResolvedReferenceInfo {
     }
 }
 
+/// A working copy attached to the repository. Besides the main working copy,
+/// additional ones can be created with `git worktree add`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Worktree {
+    /// The name of the worktree. For linked worktrees, this is the name that
+    /// Git assigned to it (by default, the name of its directory). For the main
+    /// worktree, this is the name of its directory.
+    pub name: String,
+
+    /// The path to the root of the worktree's working copy.
+    pub path: PathBuf,
+
+    /// The OID of the commit checked out in the worktree. If its `HEAD` is
+    /// unborn, then this is `None`.
+    pub head_oid: Option<NonZeroOid>,
+
+    /// Whether or not this is the worktree that the `Repo` was opened from.
+    pub is_current: bool,
+}
+
 /// The parsed version of Git.
 #[derive(Debug, PartialEq, PartialOrd, Eq)]
 pub struct GitVersion(pub isize, pub isize, pub isize);
@@ -183,6 +212,16 @@ impl AmendFastOptions {
     }
 }
 
+/// Determine whether the given error was produced because libgit2 refuses to
+/// open a repository with a format version or extension (such as
+/// `extensions.refStorage=reftable`) which it doesn't support.
+fn is_unsupported_repository_format_error(err: &git2::Error) -> bool {
+    err.class() == git2::ErrorClass::Repository
+        && err.code() == git2::ErrorCode::GenericError
+        && (err.message().starts_with("unsupported extension name")
+            || err.message().starts_with("unsupported repository version"))
+}
+
 /// Wrapper around `git2::Repository`.
 pub struct Repo {
     pub(super) inner: git2::Repository,
@@ -198,7 +237,23 @@ impl Repo {
     /// Get the Git repository associated with the given directory.
     #[instrument]
     pub fn from_dir(path: &Path) -> eyre::Result<Self> {
-        let repo = git2::Repository::discover(path).map_err(wrap_git_error)?;
+        let repo = match git2::Repository::discover(path) {
+            Ok(repo) => repo,
+            Err(err) if is_unsupported_repository_format_error(&err) => {
+                return Err(wrap_git_error(err).wrap_err(LibError::User {
+                    message: "This repository uses a format (such as the reftable reference storage format) which is not supported by the version of libgit2 that git-branchless was built with".to_string(),
+                }));
+            }
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                return Err(wrap_git_error(err).wrap_err(LibError::User {
+                    message: format!(
+                        "Not a Git repository (or any of the parent directories): {:?}",
+                        path
+                    ),
+                }));
+            }
+            Err(err) => return Err(wrap_git_error(err)),
+        };
         Ok(Repo { inner: repo })
     }
 
@@ -221,11 +276,49 @@ impl Repo {
         self.inner.path()
     }
 
+    /// Get the path to the `.git` directory shared between all worktrees of
+    /// the repository. This contains the object database. For the main
+    /// worktree, this is the same as `get_path`.
+    pub fn get_common_path(&self) -> eyre::Result<PathBuf> {
+        if self.inner.is_worktree() {
+            // Linked worktrees record the location of the main repository's
+            // `.git` directory in their `commondir` file.
+            let common_dir_file = self.get_path().join("commondir");
+            let common_dir = std::fs::read_to_string(&common_dir_file)
+                .wrap_err_with(|| format!("Reading {:?}", &common_dir_file))?;
+            Ok(self.get_path().join(common_dir.trim_end()))
+        } else {
+            Ok(self.get_path().to_owned())
+        }
+    }
+
     /// Get the path to the `packed-refs` file for the repository.
     pub fn get_packed_refs_path(&self) -> PathBuf {
         self.inner.path().join("packed-refs")
     }
 
+    /// Get the format used to store the repository's references.
+    #[instrument]
+    pub fn get_reference_storage_format(&self) -> eyre::Result<ReferenceStorageFormat> {
+        let config = self.get_readonly_config()?;
+        match config.get::<String, _>("extensions.refStorage")? {
+            Some(format) => format.parse(),
+            None => Ok(ReferenceStorageFormat::Files),
+        }
+    }
+
+    /// Get the backend for the repository's reference storage format.
+    #[instrument]
+    pub fn get_reference_backend(&self) -> eyre::Result<Box<dyn ReferenceBackend>> {
+        let backend: Box<dyn ReferenceBackend> = match self.get_reference_storage_format()? {
+            ReferenceStorageFormat::Files => {
+                Box::new(FilesReferenceBackend::new(self.get_packed_refs_path()))
+            }
+            ReferenceStorageFormat::Reftable => Box::new(ReftableReferenceBackend),
+        };
+        Ok(backend)
+    }
+
     /// Get the path to the directory inside the `.git` directory which contains
     /// state used for the current rebase (if any).
     pub fn get_rebase_state_dir_path(&self) -> PathBuf {
@@ -238,6 +331,84 @@ impl Repo {
         self.inner.workdir()
     }
 
+    /// Get which file modes the filesystem backing the working copy can
+    /// represent, according to the `core.fileMode` and `core.symlinks`
+    /// settings.
+    #[instrument]
+    pub fn get_working_copy_file_mode_support(&self) -> eyre::Result<WorkingCopyFileModeSupport> {
+        let config = self.get_readonly_config()?;
+        let default = WorkingCopyFileModeSupport::default();
+        Ok(WorkingCopyFileModeSupport {
+            executable_bit: config.get_or("core.fileMode", default.executable_bit)?,
+            symlinks: config.get_or("core.symlinks", default.symlinks)?,
+        })
+    }
+
+    /// Get all the worktrees attached to this repository, including the main
+    /// worktree. Linked worktrees whose directories have been deleted (but not
+    /// yet pruned) are skipped.
+    #[instrument]
+    pub fn get_worktrees(&self) -> eyre::Result<Vec<Worktree>> {
+        fn get_head_oid(repo: &git2::Repository) -> eyre::Result<Option<NonZeroOid>> {
+            match repo.head() {
+                Ok(reference) => {
+                    let commit = reference.peel_to_commit().map_err(wrap_git_error)?;
+                    Ok(Some(make_non_zero_oid(commit.id())))
+                }
+                Err(err)
+                    if err.code() == git2::ErrorCode::UnbornBranch
+                        || err.code() == git2::ErrorCode::NotFound =>
+                {
+                    Ok(None)
+                }
+                Err(err) => Err(wrap_git_error(err)),
+            }
+        }
+
+        let current_path = self.get_working_copy_path().map(|path| path.canonicalize());
+        let is_current = |path: &Path| match &current_path {
+            Some(Ok(current_path)) => path.canonicalize().ok().as_ref() == Some(current_path),
+            Some(Err(_)) | None => false,
+        };
+
+        let common_dir = self.get_common_path()?;
+        let mut result = Vec::new();
+        let main_repo = git2::Repository::open(&common_dir)
+            .map_err(wrap_git_error)
+            .wrap_err("Opening main worktree")?;
+        if let Some(path) = main_repo.workdir() {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            result.push(Worktree {
+                name,
+                path: path.to_owned(),
+                head_oid: get_head_oid(&main_repo)?,
+                is_current: is_current(path),
+            });
+        }
+
+        let worktree_names = self.inner.worktrees().map_err(wrap_git_error)?;
+        for name in worktree_names.iter().flatten() {
+            let worktree = self.inner.find_worktree(name).map_err(wrap_git_error)?;
+            if let Err(err) = worktree.validate() {
+                warn!(?name, ?err, "Skipping invalid worktree");
+                continue;
+            }
+            let worktree_repo =
+                git2::Repository::open_from_worktree(&worktree).map_err(wrap_git_error)?;
+            let path = worktree.path();
+            result.push(Worktree {
+                name: name.to_owned(),
+                path: path.to_owned(),
+                head_oid: get_head_oid(&worktree_repo)?,
+                is_current: is_current(path),
+            });
+        }
+        Ok(result)
+    }
+
     /// Get the index file for this repository.
     pub fn get_index(&self) -> eyre::Result<Index> {
         let mut index = self.inner.index()?;
@@ -273,6 +444,12 @@ impl Repo {
         self.get_path().join("branchless").join("dag")
     }
 
+    /// Get the path to the socket which `git branchless daemon` listens on.
+    #[instrument]
+    pub fn get_daemon_socket_path(&self) -> PathBuf {
+        self.get_path().join("branchless").join("daemon.sock")
+    }
+
     /// Get the directory to store man-pages. Note that this is the `man`
     /// directory, and not a subsection thereof. `git-branchless` man-pages must
     /// go into the `man/man1` directory to be found by `man`.
@@ -281,6 +458,30 @@ impl Repo {
         self.get_path().join("branchless").join("man")
     }
 
+    /// Get the file where the trace of the last command is recorded for use in
+    /// bug reports, if `branchless.bugReport.recordTraces` is set.
+    #[instrument]
+    pub fn get_last_command_trace_path(&self) -> PathBuf {
+        self.get_path().join("branchless").join("last-command.log")
+    }
+
+    /// Get the file which records when commits abandoned by a rewrite were
+    /// last warned about, so that the warning isn't repeated too often.
+    #[instrument]
+    pub fn get_abandoned_warning_timestamp_path(&self) -> PathBuf {
+        self.get_path()
+            .join("branchless")
+            .join("last-abandoned-warning")
+    }
+
+    /// Get the file which records the state of the repository as of the last
+    /// time the smartlog was rendered, so that the changes since then can be
+    /// summarized.
+    #[instrument]
+    pub fn get_last_smartlog_path(&self) -> PathBuf {
+        self.get_path().join("branchless").join("last-smartlog")
+    }
+
     /// Get a directory suitable for storing temporary files.
     ///
     /// In particular, this directory is guaranteed to be on the same filesystem
@@ -292,9 +493,25 @@ impl Repo {
         self.get_path().join("branchless").join("tmp")
     }
 
-    /// Get the connection to the SQLite database for this repository.
+    /// Get the connection to the SQLite database for this repository, applying
+    /// any pending schema migrations.
     #[instrument]
     pub fn get_db_conn(&self) -> eyre::Result<rusqlite::Connection> {
+        let conn = self.get_db_conn_without_migrations()?;
+        run_migrations(&conn).map_err(|err| {
+            LibError::categorize_database_error(
+                err,
+                &self.get_path().join("branchless").join("db.sqlite3"),
+            )
+        })?;
+        Ok(conn)
+    }
+
+    /// Get the connection to the SQLite database for this repository, without
+    /// applying any pending schema migrations. This should only be used to
+    /// inspect the state of the schema.
+    #[instrument]
+    pub fn get_db_conn_without_migrations(&self) -> eyre::Result<rusqlite::Connection> {
         let dir = self.get_path().join("branchless");
         std::fs::create_dir_all(&dir).wrap_err("Creating .git/branchless dir")?;
         let path = dir.join("db.sqlite3");
@@ -345,6 +562,15 @@ impl Repo {
         Ok(())
     }
 
+    /// Set the `HEAD` reference to point symbolically to the provided
+    /// reference, such as a branch. Does not touch the working copy.
+    #[instrument]
+    pub fn set_head_to_reference(&self, reference_name: &ReferenceName) -> eyre::Result<()> {
+        self.inner
+            .set_head(reference_name.as_str())
+            .map_err(wrap_git_error)
+    }
+
     /// Detach `HEAD` by making it point directly to its current OID, rather
     /// than to a branch. If `HEAD` is unborn, logs a warning.
     #[instrument]
@@ -423,6 +649,21 @@ impl Repo {
         }
     }
 
+    /// Count the commits which are ancestors of `head_oid` but not of
+    /// `base_oid`.
+    #[instrument]
+    pub fn count_commits_between(
+        &self,
+        base_oid: NonZeroOid,
+        head_oid: NonZeroOid,
+    ) -> eyre::Result<usize> {
+        let (num_ahead, _num_behind) = self
+            .inner
+            .graph_ahead_behind(head_oid.inner, base_oid.inner)
+            .map_err(wrap_git_error)?;
+        Ok(num_ahead)
+    }
+
     /// Get the patch for a commit, i.e. the diff between that commit and its
     /// parent.
     ///
@@ -437,15 +678,31 @@ impl Repo {
             None => return Ok(None),
             Some(changed_paths) => changed_paths,
         };
-        let dehydrated_commit = self.dehydrate_commit(
+        self.get_patch_for_commit_paths(
+            effects,
             commit,
             changed_paths
                 .iter()
                 .map(|x| -> &Path { x })
                 .collect_vec()
                 .as_slice(),
-            true,
-        )?;
+        )
+    }
+
+    /// Get the part of the patch for a commit which touches the given paths.
+    ///
+    /// If the commit has more than one parent, returns `None`.
+    #[instrument]
+    pub fn get_patch_for_commit_paths(
+        &self,
+        effects: &Effects,
+        commit: &Commit,
+        paths: &[&Path],
+    ) -> eyre::Result<Option<Diff>> {
+        if commit.get_parent_count() > 1 {
+            return Ok(None);
+        }
+        let dehydrated_commit = self.dehydrate_commit(commit, paths, true)?;
 
         let parent = dehydrated_commit.get_only_parent();
         let parent_tree = match &parent {
@@ -534,6 +791,21 @@ impl Repo {
         Ok(Some(changed_paths))
     }
 
+    /// Get the file paths which differ between the two given trees. A tree of
+    /// `None` is treated as the empty tree.
+    #[instrument]
+    pub fn get_changed_paths_between_trees(
+        &self,
+        lhs: Option<&Tree>,
+        rhs: Option<&Tree>,
+    ) -> eyre::Result<HashSet<PathBuf>> {
+        get_changed_paths_between_trees(
+            self,
+            lhs.map(|tree| &tree.inner),
+            rhs.map(|tree| &tree.inner),
+        )
+    }
+
     /// Get the patch ID for this commit.
     #[instrument]
     pub fn get_patch_id(
@@ -637,15 +909,48 @@ impl Repo {
         let (effects, _progress) = effects.start_operation(OperationType::QueryWorkingCopy);
         let _effects = effects;
 
-        let output = git_run_info
-            .run_silent(
-                self,
-                event_tx_id,
-                &["status", "--porcelain=v2", "--untracked-files=no", "-z"],
-                Default::default(),
-            )
-            .wrap_err("Querying status")?
-            .stdout;
+        let watchman_query = if get_use_watchman(self)? {
+            match watchman::query(self, head_info.oid) {
+                Ok(watchman_query) => watchman_query,
+                Err(err) => {
+                    warn!(
+                        ?err,
+                        "Failed to query Watchman, checking entire working copy"
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut args = vec![
+            "--literal-pathspecs",
+            "status",
+            "--porcelain=v2",
+            "--untracked-files=no",
+            "-z",
+        ];
+        let output = match watchman_query
+            .as_ref()
+            .map(|watchman_query| &watchman_query.paths_to_check)
+        {
+            Some(PathsToCheck::Only(paths)) if paths.is_empty() => Vec::new(),
+            Some(PathsToCheck::Only(paths)) if paths.iter().all(|path| path.to_str().is_some()) => {
+                args.push("--");
+                args.extend(paths.iter().filter_map(|path| path.to_str()));
+                git_run_info
+                    .run_silent(self, event_tx_id, &args, Default::default())
+                    .wrap_err("Querying status")?
+                    .stdout
+            }
+            Some(PathsToCheck::Only(_)) | Some(PathsToCheck::All) | None => {
+                git_run_info
+                    .run_silent(self, event_tx_id, &args, Default::default())
+                    .wrap_err("Querying status")?
+                    .stdout
+            }
+        };
 
         let not_null_terminator = |c: &u8| *c != 0_u8;
         let mut statuses = Vec::new();
@@ -682,7 +987,14 @@ impl Repo {
             statuses.push(entry);
         }
 
-        let snapshot = WorkingCopySnapshot::create(self, index, head_info, &statuses)?;
+        if let Some(watchman_query) = watchman_query {
+            if let Err(err) = watchman::save_state(self, watchman_query, head_info.oid, &statuses) {
+                warn!(?err, "Failed to save Watchman state");
+            }
+        }
+
+        let snapshot =
+            WorkingCopySnapshot::create(self, git_run_info, index, head_info, &statuses)?;
         Ok((snapshot, statuses))
     }
 
@@ -728,6 +1040,37 @@ impl Repo {
         Ok(all_branches)
     }
 
+    /// Get all remote-tracking branches in the repository.
+    #[instrument]
+    pub fn get_all_remote_branches(&self) -> eyre::Result<Vec<Branch<'_>>> {
+        let mut all_branches = Vec::new();
+        for branch in self
+            .inner
+            .branches(Some(git2::BranchType::Remote))
+            .map_err(wrap_git_error)
+            .wrap_err("Iterating over all remote branches")?
+        {
+            let (branch, _branch_type) = branch.wrap_err("Accessing individual branch")?;
+            all_branches.push(Branch { inner: branch });
+        }
+        Ok(all_branches)
+    }
+
+    /// Get the names of all remotes in the repository.
+    #[instrument]
+    pub fn get_remote_names(&self) -> eyre::Result<Vec<String>> {
+        let remotes = self
+            .inner
+            .remotes()
+            .map_err(wrap_git_error)
+            .wrap_err("Listing remotes")?;
+        Ok(remotes
+            .iter()
+            .flatten()
+            .map(|name| name.to_owned())
+            .collect())
+    }
+
     /// Look up the branch with the given name. Returns `None` if not found.
     #[instrument]
     pub fn find_branch(&self, name: &str, branch_type: BranchType) -> eyre::Result<Option<Branch>> {
@@ -768,6 +1111,14 @@ impl Repo {
         }
     }
 
+    /// Check whether an object with the given OID exists in the repository,
+    /// without loading it.
+    #[instrument]
+    pub fn contains_object(&self, oid: NonZeroOid) -> eyre::Result<bool> {
+        let odb = self.inner.odb().map_err(wrap_git_error)?;
+        Ok(odb.exists(oid.inner))
+    }
+
     /// Like `find_commit`, but raises a generic error if the commit could not
     /// be found.
     #[instrument]
@@ -801,35 +1152,148 @@ impl Repo {
     }
 
     /// Look up the commit with the given OID and render a friendly description
-    /// of it, or render an error message if not found.
+    /// of it, or render an error message if not found. If the OID is that of
+    /// an annotated tag, then the tagged commit is described.
     pub fn friendly_describe_commit_from_oid(
         &self,
         glyphs: &Glyphs,
         oid: NonZeroOid,
     ) -> eyre::Result<StyledString> {
-        match self.find_commit(oid)? {
+        let commit = match self.find_commit(oid)? {
+            Some(commit) => Some(commit),
+            None => match self.inner.find_tag(oid.inner) {
+                Ok(tag) => self.find_commit(NonZeroOid {
+                    inner: tag.target_id(),
+                })?,
+                Err(_) => None,
+            },
+        };
+        match commit {
             Some(commit) => Ok(commit.friendly_describe(glyphs)?),
             None => {
                 let NonZeroOid { inner: oid } = oid;
                 Ok(StyledString::styled(
                     format!("<commit not available: {}>", oid),
-                    BaseColor::Red.light(),
+                    glyphs.theme.error,
                 ))
             }
         }
     }
 
-    /// Read a file from disk and create a blob corresponding to its contents.
-    /// If the file doesn't exist on disk, returns `None` instead.
+    /// Read a file from the working copy and create a blob corresponding to
+    /// its contents, in the same way as `git add` would. The path is relative
+    /// to the root of the working copy. If the file doesn't exist on disk,
+    /// returns `None` instead.
+    ///
+    /// `libgit2` only knows how to apply Git's built-in filters (such as
+    /// end-of-line conversion). If `.gitattributes` assigns the file a filter
+    /// driver with a clean command (such as Git LFS), the blob is created by
+    /// `git hash-object` instead, so that the clean command is run.
     #[instrument]
-    pub fn create_blob_from_path(&self, path: &Path) -> eyre::Result<Option<NonZeroOid>> {
-        match self.inner.blob_path(path) {
+    pub fn create_blob_from_path(
+        &self,
+        git_run_info: &GitRunInfo,
+        path: &Path,
+    ) -> eyre::Result<Option<NonZeroOid>> {
+        let working_copy_path = self
+            .get_working_copy_path()
+            .ok_or_else(|| eyre::eyre!("unable to get repo working copy path"))?;
+        let file_path = working_copy_path.join(path);
+        let metadata = match std::fs::symlink_metadata(&file_path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| format!("Reading metadata for: {:?}", path))
+            }
+        };
+
+        // Filters are never applied to symbolic links.
+        if !metadata.file_type().is_symlink() && self.has_clean_filter_driver(path)? {
+            let path_str = path.to_str().ok_or_else(|| {
+                eyre::eyre!(
+                    "Path to filtered file could not be converted to UTF-8 string: {:?}",
+                    path
+                )
+            })?;
+            let GitRunResult { stdout, .. } = git_run_info
+                .run_silent(
+                    self,
+                    None,
+                    &["hash-object", "-w", "--", path_str],
+                    GitRunOpts {
+                        treat_git_failure_as_error: true,
+                        stdin: None,
+                    },
+                )
+                .wrap_err_with(|| format!("Creating blob for filtered file: {:?}", path))?;
+            let oid = std::str::from_utf8(&stdout)
+                .wrap_err("Decoding output of `git hash-object`")?
+                .trim()
+                .parse()?;
+            return Ok(Some(oid));
+        }
+
+        match self.inner.blob_path(&file_path) {
             Ok(oid) => Ok(Some(make_non_zero_oid(oid))),
             Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
             Err(err) => Err(wrap_git_error(err)),
         }
     }
 
+    /// Whether `.gitattributes` assigns the given path a filter driver which
+    /// has a clean command configured, either as `filter.<driver>.clean` or
+    /// as `filter.<driver>.process`.
+    #[instrument]
+    fn has_clean_filter_driver(&self, path: &Path) -> eyre::Result<bool> {
+        let value = self
+            .inner
+            .get_attr(path, "filter", git2::AttrCheckFlags::default())
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Looking up filter attribute for: {:?}", path))?;
+        let driver = match git2::AttrValue::from_string(value) {
+            git2::AttrValue::String(driver) => driver,
+            git2::AttrValue::True
+            | git2::AttrValue::False
+            | git2::AttrValue::Bytes(_)
+            | git2::AttrValue::Unspecified => return Ok(false),
+        };
+
+        let config = self.get_readonly_config()?;
+        for command in ["clean", "process"] {
+            let key = format!("filter.{driver}.{command}");
+            if config.get::<String, _>(key)?.is_some() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Get the commit checked out in the submodule at the given path, which is
+    /// relative to the root of the working copy. This is the OID that the
+    /// submodule's gitlink entry should point to when committing the working
+    /// copy. If the submodule doesn't exist on disk, returns `None` instead.
+    #[instrument]
+    pub fn get_submodule_head_oid(&self, path: &Path) -> eyre::Result<Option<NonZeroOid>> {
+        let working_copy_path = self
+            .get_working_copy_path()
+            .ok_or_else(|| eyre::eyre!("unable to get repo working copy path"))?;
+        let submodule_path = working_copy_path.join(path);
+        if !submodule_path.exists() {
+            return Ok(None);
+        }
+        let submodule_repo = git2::Repository::open(&submodule_path)
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Opening submodule at: {:?}", path))?;
+        let head = submodule_repo
+            .head()
+            .map_err(wrap_git_error)
+            .wrap_err_with(|| format!("Resolving HEAD of submodule at: {:?}", path))?;
+        let head_oid = head
+            .target()
+            .ok_or_else(|| eyre::eyre!("HEAD of submodule at {:?} is not a commit", path))?;
+        Ok(Some(make_non_zero_oid(head_oid)))
+    }
+
     /// Create a blob corresponding to the provided byte slice.
     #[instrument]
     pub fn create_blob_from_contents(&self, contents: &[u8]) -> eyre::Result<NonZeroOid> {
@@ -870,6 +1334,124 @@ impl Repo {
         Ok(make_non_zero_oid(oid))
     }
 
+    /// Create a copy of the given commit with a new message, stored in the given
+    /// encoding, such as the value of `i18n.commitEncoding`.
+    ///
+    /// Fails if the message contains characters which can't be represented in
+    /// that encoding.
+    #[instrument]
+    pub fn amend_commit_message_encoded(
+        &self,
+        commit: &Commit,
+        message: &str,
+        encoding: &str,
+    ) -> eyre::Result<NonZeroOid> {
+        if Encoding::for_label(encoding.as_bytes()) == Some(encoding_rs::UTF_8) {
+            return commit.amend_commit(None, None, None, Some(message), None);
+        }
+        let parents = commit.get_parents();
+        self.create_commit_encoded(
+            &commit.get_author(),
+            &commit.get_committer(),
+            message,
+            Some(encoding),
+            &commit.get_tree()?,
+            parents.iter().collect(),
+        )
+        .wrap_err_with(|| format!("Rewording commit {:?}", commit.get_oid()))
+    }
+
+    /// Create a new commit whose message is stored in the given encoding, such
+    /// as the value of `i18n.commitEncoding`, or in UTF-8 if there's none.
+    ///
+    /// Fails if the message contains characters which can't be represented in
+    /// that encoding.
+    #[instrument]
+    pub fn create_commit_encoded(
+        &self,
+        author: &Signature,
+        committer: &Signature,
+        message: &str,
+        encoding: Option<&str>,
+        tree: &Tree,
+        parents: Vec<&Commit>,
+    ) -> eyre::Result<NonZeroOid> {
+        let encoding = match encoding {
+            Some(encoding) => encoding,
+            None => return self.create_commit(None, author, committer, message, tree, parents),
+        };
+        let encoder = match Encoding::for_label(encoding.as_bytes()) {
+            Some(encoder) => encoder,
+            None => eyre::bail!("Unknown commit message encoding: {}", encoding),
+        };
+        if encoder == encoding_rs::UTF_8 {
+            return self.create_commit(None, author, committer, message, tree, parents);
+        }
+        let (encoded_message, _encoding, had_errors) = encoder.encode(message);
+        if had_errors {
+            eyre::bail!(
+                "Commit message cannot be represented in encoding: {}",
+                encoding
+            );
+        }
+        self.create_commit_with_encoding(
+            author,
+            committer,
+            &encoded_message,
+            Some(encoding),
+            tree,
+            parents,
+        )
+    }
+
+    /// Create a new commit whose message is stored as the given bytes, along
+    /// with the given `encoding` header, if any. Unlike `create_commit`, the
+    /// message doesn't need to be valid UTF-8.
+    #[instrument]
+    pub fn create_commit_with_encoding(
+        &self,
+        author: &Signature,
+        committer: &Signature,
+        message: &[u8],
+        encoding: Option<&str>,
+        tree: &Tree,
+        parents: Vec<&Commit>,
+    ) -> eyre::Result<NonZeroOid> {
+        let parents = parents
+            .iter()
+            .map(|commit| &commit.inner)
+            .collect::<Vec<_>>();
+
+        // `git2` can only write UTF-8 messages, so create the commit object
+        // without a message and append the message to it.
+        let buffer = self
+            .inner
+            .commit_create_buffer(
+                &author.inner,
+                &committer.inner,
+                "",
+                &tree.inner,
+                parents.as_slice(),
+            )
+            .map_err(wrap_git_error)?;
+        let headers = match buffer.strip_suffix(b"\n") {
+            Some(headers) => headers,
+            None => eyre::bail!("BUG: commit buffer did not end with a blank line"),
+        };
+        let mut contents = headers.to_vec();
+        if let Some(encoding) = encoding {
+            contents.extend_from_slice(format!("encoding {}\n", encoding).as_bytes());
+        }
+        contents.push(b'\n');
+        contents.extend_from_slice(message);
+
+        let odb = self.inner.odb().map_err(wrap_git_error)?;
+        let oid = odb
+            .write(git2::ObjectType::Commit, &contents)
+            .map_err(wrap_git_error)?;
+        Ok(make_non_zero_oid(oid))
+    }
+
     /// Cherry-pick a commit in memory and return the resulting index.
     #[instrument]
     pub fn cherry_pick_commit(
@@ -1087,6 +1669,7 @@ impl Repo {
     #[instrument]
     pub fn amend_fast(
         &self,
+        git_run_info: &GitRunInfo,
         parent_commit: &Commit,
         opts: &AmendFastOptions,
     ) -> eyre::Result<Tree> {
@@ -1121,26 +1704,37 @@ impl Repo {
             self.dehydrate_commit(parent_commit, changed_paths.as_slice(), true)?;
         let dehydrated_parent_tree = dehydrated_parent.get_tree()?;
 
-        let repo_path = self
-            .get_working_copy_path()
-            .ok_or_else(|| eyre::eyre!("unable to get repo working copy path"))?;
         let new_tree_entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = match opts {
-            AmendFastOptions::FromWorkingCopy { status_entries } => status_entries
-                .iter()
-                .flat_map(|entry| {
-                    entry.paths().into_iter().map(
-                        move |path| -> eyre::Result<(PathBuf, Option<(NonZeroOid, FileMode)>)> {
-                            let file_path = &repo_path.join(&path);
-                            // Try to create a new blob OID based on the current on-disk
-                            // contents of the file in the working copy.
-                            let entry = self
-                                .create_blob_from_path(file_path)?
-                                .map(|oid| (oid, entry.working_copy_file_mode));
-                            Ok((path, entry))
-                        },
-                    )
-                })
-                .collect::<Result<HashMap<_, _>, _>>()?,
+            AmendFastOptions::FromWorkingCopy { status_entries } => {
+                let index = self.get_index()?;
+                let file_mode_support = self.get_working_copy_file_mode_support()?;
+                status_entries
+                    .iter()
+                    .flat_map(|entry| {
+                        let index = &index;
+                        entry.paths().into_iter().map(
+                            move |path| -> eyre::Result<(PathBuf, Option<(NonZeroOid, FileMode)>)> {
+                                let entry = if entry.working_copy_file_mode == FileMode::Commit {
+                                    // Submodules are stored as gitlinks to the commit
+                                    // checked out in them, rather than as blobs.
+                                    self.get_submodule_head_oid(&path)?
+                                        .map(|oid| (oid, FileMode::Commit))
+                                } else {
+                                    // Try to create a new blob OID based on the current on-disk
+                                    // contents of the file in the working copy.
+                                    let file_mode = file_mode_support.reconcile(
+                                        index.get_entry(&path).map(|entry| entry.file_mode),
+                                        entry.working_copy_file_mode,
+                                    );
+                                    self.create_blob_from_path(git_run_info, &path)?
+                                        .map(|oid| (oid, file_mode))
+                                };
+                                Ok((path, entry))
+                            },
+                        )
+                    })
+                    .collect::<Result<HashMap<_, _>, _>>()?
+            }
             AmendFastOptions::FromIndex { paths } => {
                 let index = self.get_index()?;
                 paths
@@ -1204,6 +1798,8 @@ impl std::fmt::Debug for Signature<'_> {
 }
 
 impl<'repo> Signature<'repo> {
+    /// The signature used for commits which `git-branchless` creates for its
+    /// own bookkeeping, rather than on behalf of the user.
     #[instrument]
     pub fn automated() -> eyre::Result<Self> {
         Ok(Signature {
@@ -1248,10 +1844,12 @@ impl<'repo> Signature<'repo> {
         }
     }
 
+    /// Get the name of the signer, if it can be decoded.
     pub fn get_name(&self) -> Option<&str> {
         self.inner.name()
     }
 
+    /// Get the email address of the signer, if it can be decoded.
     pub fn get_email(&self) -> Option<&str> {
         self.inner.email()
     }
@@ -1276,6 +1874,21 @@ pub struct PatchId {
     patch_id: git2::Oid,
 }
 
+impl std::fmt::Display for PatchId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.patch_id)
+    }
+}
+
+impl FromStr for PatchId {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let patch_id = git2::Oid::from_str(s).wrap_err("Parsing patch ID")?;
+        Ok(PatchId { patch_id })
+    }
+}
+
 /// Represents a commit object in the Git object database.
 #[derive(Clone, Debug)]
 pub struct Commit<'repo> {
@@ -1349,9 +1962,19 @@ impl<'repo> Commit<'repo> {
     /// Get the summary (first line) of the commit message.
     #[instrument]
     pub fn get_summary(&self) -> eyre::Result<BString> {
-        match self.inner.summary_bytes() {
-            Some(summary) => Ok(BString::from(summary)),
+        let summary = match self.inner.summary_bytes() {
+            Some(summary) => summary,
             None => eyre::bail!("Could not read summary for commit: {:?}", self.get_oid()),
+        };
+        match self
+            .get_message_encoding()
+            .and_then(|label| Encoding::for_label(label.as_bytes()))
+        {
+            Some(encoding) => {
+                let (summary, _had_errors) = encoding.decode_without_bom_handling(summary);
+                Ok(BString::from(summary.as_bytes()))
+            }
+            None => Ok(BString::from(summary)),
         }
     }
 
@@ -1367,6 +1990,46 @@ impl<'repo> Commit<'repo> {
         Ok(BString::from(self.inner.message_raw_bytes()))
     }
 
+    /// Determine whether the commit has a signature, such as one created with
+    /// `git commit -S`. This doesn't check whether the signature is valid.
+    #[instrument]
+    pub fn is_signed(&self) -> bool {
+        self.inner
+            .raw_header_bytes()
+            .split(|c| *c == b'\n')
+            .any(|line| line.starts_with(b"gpgsig ") || line.starts_with(b"gpgsig-sha256 "))
+    }
+
+    /// Get the encoding of the commit message from the commit's `encoding`
+    /// header, if any. Messages without the header are encoded in UTF-8.
+    #[instrument]
+    pub fn get_message_encoding(&self) -> Option<&str> {
+        self.inner.message_encoding()
+    }
+
+    /// Get the commit message decoded as text, without any whitespace trimmed.
+    ///
+    /// The message is decoded with the encoding from the commit's `encoding`
+    /// header. If there's no header and the message isn't valid UTF-8, it's
+    /// decoded with `fallback_encoding` instead, such as the value of
+    /// `i18n.commitEncoding`. Bytes which can't be decoded are replaced with
+    /// U+FFFD, in which case the returned flag is `true`.
+    #[instrument]
+    pub fn get_message_decoded(&self, fallback_encoding: Option<&str>) -> (String, bool) {
+        let message = self.inner.message_raw_bytes();
+        let encoding = match self.get_message_encoding() {
+            Some(label) => Encoding::for_label(label.as_bytes()),
+            None => match std::str::from_utf8(message) {
+                Ok(message) => return (message.to_string(), false),
+                Err(_) => fallback_encoding.and_then(|label| Encoding::for_label(label.as_bytes())),
+            },
+        };
+        let (message, had_errors) = encoding
+            .unwrap_or(encoding_rs::UTF_8)
+            .decode_without_bom_handling(message);
+        (message.into_owned(), had_errors)
+    }
+
     /// Get the author of this commit.
     #[instrument]
     pub fn get_author(&self) -> Signature {
@@ -1394,12 +2057,9 @@ impl<'repo> Commit<'repo> {
     /// like `Signed-off-by: foo` which appear at the end of the commit message.
     #[instrument]
     pub fn get_trailers(&self) -> eyre::Result<Vec<(String, String)>> {
-        let message = self.get_message_raw()?;
-        let message = message
-            .to_str()
-            .with_context(|| format!("Decoding message for commit {self:?}"))?;
+        let (message, _is_lossy) = self.get_message_decoded(None);
         let mut result = Vec::new();
-        for (k, v) in message_trailers_bytes(message)
+        for (k, v) in message_trailers_bytes(message.as_str())
             .wrap_err("Reading message trailers")?
             .iter()
         {
@@ -1430,13 +2090,13 @@ impl<'repo> Commit<'repo> {
     /// Get a multi-line description of this commit containing information about
     /// its OID, author, commit time, and message.
     #[instrument]
-    pub fn friendly_preview(&self) -> eyre::Result<StyledString> {
+    pub fn friendly_preview(&self, glyphs: &Glyphs) -> eyre::Result<StyledString> {
         let commit_time = self.get_time().to_naive_date_time();
         let preview = StyledStringBuilder::from_lines(vec![
             StyledStringBuilder::new()
                 .append_styled(
                     format!("Commit:\t{}", self.get_oid()),
-                    BaseColor::Yellow.light(),
+                    glyphs.theme.commit_oid,
                 )
                 .build(),
             StyledString::styled(
@@ -1446,9 +2106,9 @@ impl<'repo> Commit<'repo> {
                         .friendly_describe()
                         .unwrap_or_else(|| "".into())
                 ),
-                BaseColor::Magenta.light(),
+                glyphs.theme.annotation,
             ),
-            StyledString::styled(format!("Date:\t{}", commit_time), BaseColor::Green.light()),
+            StyledString::styled(format!("Date:\t{}", commit_time), glyphs.theme.detail),
             StyledString::plain(textwrap::indent(
                 &self.get_message_pretty()?.to_str_lossy(),
                 "    ",
@@ -1497,13 +2157,19 @@ impl<'repo> Commit<'repo> {
         message: Option<&str>,
         tree: Option<&Tree>,
     ) -> eyre::Result<NonZeroOid> {
+        // The original commit's `encoding` header is kept unless another
+        // encoding is given, but the new message is always UTF-8.
+        let message_encoding = match (message, self.get_message_encoding()) {
+            (Some(_), Some(encoding)) if !encoding.eq_ignore_ascii_case("utf-8") => Some("UTF-8"),
+            _ => None,
+        };
         let oid = self
             .inner
             .amend(
                 update_ref,
                 author.map(|author| &author.inner),
                 committer.map(|committer| &committer.inner),
-                None,
+                message_encoding,
                 message,
                 tree.map(|tree| &tree.inner),
             )
@@ -1546,7 +2212,7 @@ pub enum ReferenceNameError {
 }
 
 /// The name of a reference, like `refs/heads/master`.
-#[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct ReferenceName(String);
 
 impl ReferenceName {
@@ -1648,6 +2314,21 @@ impl<'repo> Reference<'repo> {
         }
     }
 
+    /// Get the object ID which this reference points to, following symbolic
+    /// references. Unlike `peel_to_commit`, annotated tags aren't peeled, so
+    /// this is the value which `git update-ref` compares against.
+    #[instrument]
+    pub fn get_target(&self) -> eyre::Result<MaybeZeroOid> {
+        let reference = self
+            .inner
+            .resolve()
+            .wrap_err("Resolving symbolic reference")?;
+        match reference.target() {
+            Some(oid) => Ok(oid.into()),
+            None => Ok(MaybeZeroOid::Zero),
+        }
+    }
+
     /// Delete the reference.
     #[instrument]
     pub fn delete(&mut self) -> eyre::Result<()> {
@@ -1830,6 +2511,37 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_is_unsupported_repository_format_error() {
+        let make_error = |message| {
+            git2::Error::new(
+                git2::ErrorCode::GenericError,
+                git2::ErrorClass::Repository,
+                message,
+            )
+        };
+        assert!(is_unsupported_repository_format_error(&make_error(
+            "unsupported extension name extensions.refstorage"
+        )));
+        assert!(is_unsupported_repository_format_error(&make_error(
+            "unsupported repository version 2; only versions 0 and 1 are supported"
+        )));
+        assert!(!is_unsupported_repository_format_error(&make_error(
+            "failed to resolve path '/foo': No such file or directory"
+        )));
+    }
+
+    #[test]
+    fn test_from_dir_not_a_repository() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let err = Repo::from_dir(temp_dir.path()).unwrap_err();
+        assert_eq!(
+            crate::error::ErrorCategory::of_report(&err),
+            crate::error::ErrorCategory::User
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_parse_git_version_output() {
         assert_eq!(
@@ -1907,6 +2619,7 @@ mod tests {
     fn test_amend_fast_from_index() -> eyre::Result<()> {
         let git = make_git()?;
         git.init_repo()?;
+        let git_run_info = git.get_git_run_info();
 
         git.run(&["checkout", "master"])?;
         let initial_oid = git.commit_file_with_contents("initial", 2, "initial contents")?;
@@ -1933,6 +2646,7 @@ mod tests {
         "###);
 
         let tree = repo.amend_fast(
+            &git_run_info,
             &initial_commit,
             &AmendFastOptions::FromIndex {
                 paths: vec!["initial.txt".into()],
@@ -1957,6 +2671,7 @@ mod tests {
 
         git.run(&["add", "initial.txt"])?;
         let tree = repo.amend_fast(
+            &git_run_info,
             &initial_commit,
             &AmendFastOptions::FromIndex {
                 paths: vec!["initial.txt".into()],
@@ -1986,6 +2701,7 @@ mod tests {
     fn test_amend_fast_from_working_tree() -> eyre::Result<()> {
         let git = make_git()?;
         git.init_repo()?;
+        let git_run_info = git.get_git_run_info();
 
         git.run(&["checkout", "master"])?;
         let initial_oid = git.commit_file_with_contents("initial", 2, "initial contents")?;
@@ -1994,6 +2710,7 @@ mod tests {
         let repo = git.get_repo()?;
         let initial_commit = repo.find_commit_or_fail(initial_oid)?;
         let tree = repo.amend_fast(
+            &git_run_info,
             &initial_commit,
             &AmendFastOptions::FromWorkingCopy {
                 status_entries: vec![StatusEntry {
@@ -2025,6 +2742,7 @@ mod tests {
         git.write_file("file2", "another file")?;
         git.write_file("initial", "updated contents again")?;
         let tree = repo.amend_fast(
+            &git_run_info,
             &initial_commit,
             &AmendFastOptions::FromWorkingCopy {
                 status_entries: vec![StatusEntry {
@@ -2058,6 +2776,7 @@ mod tests {
 
         git.delete_file("initial")?;
         let tree = repo.amend_fast(
+            &git_run_info,
             &initial_commit,
             &AmendFastOptions::FromWorkingCopy {
                 status_entries: vec![StatusEntry {
@@ -9,15 +9,17 @@ use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 
 use bstr::BString;
-use eyre::{eyre, Context};
+use eyre::Context;
 use itertools::Itertools;
+use thiserror::Error;
 use tracing::instrument;
 
 use crate::core::config::get_core_hooks_path;
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::{EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR};
-use crate::git::repo::Repo;
-use crate::util::{get_sh, ExitCode};
+use crate::git::repo::{ReferenceName, Repo};
+use crate::git::MaybeZeroOid;
+use crate::util::{get_sh, is_branchless_native_hook, shell_command, ExitCode};
 
 /// Path to the `git` executable on disk to be executed.
 #[derive(Clone)]
@@ -30,6 +32,10 @@ pub struct GitRunInfo {
 
     /// The environment variables that should be passed to the Git process.
     pub env: HashMap<OsString, OsString>,
+
+    /// Whether the Git process should inherit this process's stdin, so that it
+    /// can prompt the user. If `false`, the Git process's stdin is empty.
+    pub inherit_stdin: bool,
 }
 
 impl std::fmt::Debug for GitRunInfo {
@@ -86,6 +92,31 @@ impl std::fmt::Debug for GitRunResult {
     }
 }
 
+/// An update to a single reference, to be applied as part of a reference
+/// transaction with [`GitRunInfo::update_references`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReferenceUpdate {
+    /// The name of the reference to update.
+    pub ref_name: ReferenceName,
+
+    /// The value which the reference is expected to have before the update.
+    /// If it has any other value, the whole transaction fails. `Zero` means
+    /// that the reference must not exist.
+    pub old_oid: MaybeZeroOid,
+
+    /// The value to set the reference to. `Zero` means that the reference
+    /// should be deleted.
+    pub new_oid: MaybeZeroOid,
+}
+
+/// A reference transaction failed, so none of its references were updated.
+#[derive(Debug, Error)]
+#[error("{message}")]
+pub struct ReferenceTransactionError {
+    /// The error message reported by Git.
+    pub message: String,
+}
+
 impl GitRunInfo {
     fn spawn_writer_thread<
         InputStream: Read + Send + 'static,
@@ -101,9 +132,13 @@ impl GitRunInfo {
                 None => return,
             };
             let reader = BufReader::new(stream);
-            for line in reader.lines() {
+            // The output may not be valid UTF-8, such as when Git prints
+            // commit messages stored in another encoding.
+            for line in reader.split(b'\n') {
                 let line = line.expect("Reading line from subprocess");
-                writeln!(output, "{}", line).expect("Writing line from subprocess");
+                let line = line.strip_suffix(b"\r").unwrap_or(&line);
+                writeln!(output, "{}", String::from_utf8_lossy(line))
+                    .expect("Writing line from subprocess");
             }
         })
     }
@@ -118,6 +153,7 @@ impl GitRunInfo {
             path_to_git,
             working_directory,
             env,
+            inherit_stdin,
         } = self;
 
         let args_string = args
@@ -143,6 +179,9 @@ impl GitRunInfo {
         if let Some(event_tx_id) = event_tx_id {
             command.env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
         }
+        if !inherit_stdin {
+            command.stdin(Stdio::null());
+        }
         command.stdout(Stdio::piped());
         command.stderr(Stdio::piped());
 
@@ -208,6 +247,7 @@ impl GitRunInfo {
             path_to_git,
             working_directory,
             env,
+            inherit_stdin,
         } = self;
 
         let mut command = Command::new(path_to_git);
@@ -218,6 +258,9 @@ impl GitRunInfo {
         if let Some(event_tx_id) = event_tx_id {
             command.env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
         }
+        if !inherit_stdin {
+            command.stdin(Stdio::null());
+        }
 
         let mut child = command.spawn().wrap_err("Spawning Git subprocess")?;
         let exit_status = child
@@ -246,6 +289,8 @@ impl GitRunInfo {
             path_to_git,
             working_directory,
             env,
+            // The stdin is always provided by the caller.
+            inherit_stdin: _,
         } = self;
         let GitRunOpts {
             treat_git_failure_as_error,
@@ -349,10 +394,10 @@ impl GitRunInfo {
         event_tx_id: EventTransactionId,
         args: &[&str],
         stdin: Option<BString>,
-    ) -> eyre::Result<()> {
+    ) -> eyre::Result<bool> {
         let hook_dir = get_core_hooks_path(repo)?;
         if !hook_dir.exists() {
-            return Ok(());
+            return Ok(true);
         }
 
         let GitRunInfo {
@@ -362,6 +407,8 @@ impl GitRunInfo {
             // regardless of where the Git executable was invoked.
             working_directory: _,
             env,
+            // Hooks are always given their stdin by the caller.
+            inherit_stdin: _,
         } = self;
         let path = {
             let mut path_components: Vec<PathBuf> =
@@ -372,49 +419,64 @@ impl GitRunInfo {
             std::env::join_paths(path_components).wrap_err("Joining path components")?
         };
 
-        if hook_dir.join(hook_name).exists() {
-            let mut child = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?)
-                // From `githooks(5)`: Before Git invokes a hook, it changes its
-                // working directory to either $GIT_DIR in a bare repository or the
-                // root of the working tree in a non-bare repository.
-                .current_dir(
-                    repo.get_working_copy_path()
-                        .unwrap_or_else(|| repo.get_path()),
-                )
-                .arg("-c")
-                .arg(format!("{} \"$@\"", hook_name))
-                .arg(hook_name) // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
-                .args(args)
-                .env_clear()
-                .envs(env.iter())
-                .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
-                .env("PATH", &path)
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .wrap_err_with(|| format!("Invoking {} hook with PATH: {:?}", &hook_name, &path))?;
-
-            if let Some(stdin) = stdin {
-                child
-                    .stdin
-                    .as_mut()
-                    .unwrap()
-                    .write_all(&stdin)
-                    .wrap_err("Writing hook process stdin")?;
+        let hook_path = hook_dir.join(hook_name);
+        let mut command = match get_sh() {
+            Some(_) if hook_path.exists() => shell_command(hook_name)?,
+            // Without `sh`, a native hook can still be run by invoking the
+            // hook subcommand it would have invoked.
+            None if is_branchless_native_hook(&hook_path)? => {
+                let mut command = Command::new(
+                    std::env::current_exe().wrap_err("Getting git-branchless executable path")?,
+                );
+                command.arg(format!("hook-{}", hook_name));
+                command
             }
+            None if hook_path.exists() => {
+                eyre::bail!(
+                    "Could not find sh to run the {} hook. Run `git branchless init` to install native hooks.",
+                    hook_name
+                );
+            }
+            _ => return Ok(true),
+        };
 
-            let stdout = child.stdout.take();
-            let stdout_thread = self.spawn_writer_thread(stdout, effects.get_output_stream());
-            let stderr = child.stderr.take();
-            let stderr_thread = self.spawn_writer_thread(stderr, effects.get_error_stream());
+        let mut child = command
+            // From `githooks(5)`: Before Git invokes a hook, it changes its
+            // working directory to either $GIT_DIR in a bare repository or the
+            // root of the working tree in a non-bare repository.
+            .current_dir(
+                repo.get_working_copy_path()
+                    .unwrap_or_else(|| repo.get_path()),
+            )
+            .args(args)
+            .env_clear()
+            .envs(env.iter())
+            .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
+            .env("PATH", &path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err_with(|| format!("Invoking {} hook with PATH: {:?}", &hook_name, &path))?;
 
-            let _ignored: ExitStatus =
-                child.wait().wrap_err("Waiting for child process to exit")?;
-            stdout_thread.join().unwrap();
-            stderr_thread.join().unwrap();
+        if let Some(stdin) = stdin {
+            child
+                .stdin
+                .as_mut()
+                .unwrap()
+                .write_all(&stdin)
+                .wrap_err("Writing hook process stdin")?;
         }
-        Ok(())
+
+        let stdout = child.stdout.take();
+        let stdout_thread = self.spawn_writer_thread(stdout, effects.get_output_stream());
+        let stderr = child.stderr.take();
+        let stderr_thread = self.spawn_writer_thread(stderr, effects.get_error_stream());
+
+        let exit_status: ExitStatus = child.wait().wrap_err("Waiting for child process to exit")?;
+        stdout_thread.join().unwrap();
+        stderr_thread.join().unwrap();
+        Ok(exit_status.success())
     }
 
     /// Run a provided Git hook if it exists for the repository.
@@ -437,13 +499,126 @@ impl GitRunInfo {
             event_tx_id,
             args.iter().map(AsRef::as_ref).collect_vec().as_slice(),
             stdin,
+        )?;
+        Ok(())
+    }
+
+    /// Like `run_hook`, but return whether the hook succeeded. Returns `true`
+    /// if the hook doesn't exist.
+    #[instrument]
+    pub fn run_hook_checked<S: AsRef<str> + std::fmt::Debug>(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        hook_name: &str,
+        event_tx_id: EventTransactionId,
+        args: &[S],
+    ) -> eyre::Result<bool> {
+        self.run_hook_inner(
+            effects,
+            repo,
+            hook_name,
+            event_tx_id,
+            args.iter().map(AsRef::as_ref).collect_vec().as_slice(),
+            None,
         )
     }
+
+    /// Apply all of the given reference updates in a single transaction, so
+    /// that either all of them take effect or none of them do. This is used
+    /// instead of updating the references one at a time with `git2`, which
+    /// can leave the repository in a mixed state if one of the updates fails.
+    ///
+    /// Git's own hooks are not run for the transaction. Instead, the
+    /// `reference-transaction` hook is invoked with the given event
+    /// transaction ID once the transaction has been committed, so that the
+    /// updates are recorded in the event log even on versions of Git which
+    /// don't support that hook.
+    #[instrument]
+    pub fn update_references(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        event_tx_id: EventTransactionId,
+        updates: &[ReferenceUpdate],
+    ) -> eyre::Result<Result<(), ReferenceTransactionError>> {
+        if updates.is_empty() {
+            return Ok(Ok(()));
+        }
+
+        let mut transaction = String::new();
+        let mut hook_stdin = String::new();
+        for ReferenceUpdate {
+            ref_name,
+            old_oid,
+            new_oid,
+        } in updates
+        {
+            match new_oid {
+                MaybeZeroOid::NonZero(_) => writeln!(
+                    transaction,
+                    "update {} {new_oid} {old_oid}",
+                    ref_name.as_str()
+                )?,
+                MaybeZeroOid::Zero => {
+                    writeln!(transaction, "delete {} {old_oid}", ref_name.as_str())?
+                }
+            }
+            writeln!(hook_stdin, "{old_oid} {new_oid} {}", ref_name.as_str())?;
+        }
+
+        // Point `core.hooksPath` at a directory which doesn't exist to keep
+        // Git from running the hooks itself, since they're invoked below.
+        let no_hooks_path = repo.get_path().join("branchless").join("no-hooks");
+        let no_hooks_path = no_hooks_path.to_str().ok_or_else(|| {
+            eyre::eyre!(
+                "Path to Git repo could not be converted to UTF-8 string: {:?}",
+                repo.get_path()
+            )
+        })?;
+        let result = self.run_silent(
+            repo,
+            Some(event_tx_id),
+            &[
+                "-c",
+                &format!("core.hooksPath={no_hooks_path}"),
+                "update-ref",
+                "--stdin",
+            ],
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                stdin: Some(transaction.into_bytes()),
+            },
+        )?;
+        if result.exit_code != 0 {
+            return Ok(Err(ReferenceTransactionError {
+                message: String::from_utf8_lossy(&result.stderr)
+                    .trim_end()
+                    .to_string(),
+            }));
+        }
+
+        self.run_hook(
+            effects,
+            repo,
+            "reference-transaction",
+            event_tx_id,
+            &["committed"],
+            Some(BString::from(hook_stdin)),
+        )?;
+        Ok(Ok(()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{GitRunInfo, GitRunOpts};
+    use std::time::SystemTime;
+
+    use super::{GitRunInfo, GitRunOpts, ReferenceUpdate};
+    use crate::core::effects::Effects;
+    use crate::core::eventlog::EventLogDb;
+    use crate::core::formatting::Glyphs;
+    use crate::git::{MaybeZeroOid, ReferenceName};
     use crate::testing::make_git;
 
     #[test]
@@ -498,6 +673,7 @@ mod tests {
             path_to_git: git.path_to_git.clone(),
             working_directory: git.repo_path.clone(),
             env: Default::default(),
+            inherit_stdin: true,
         };
 
         let result = git_run_info.run_silent(
@@ -524,4 +700,77 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_update_references_atomic() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["branch", "foo"])?;
+        let test2_oid = git.commit_file("test2", 2)?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+        let git_run_info = git.get_git_run_info();
+        let get_branch_oid = |name: &str| -> eyre::Result<MaybeZeroOid> {
+            let reference =
+                repo.find_reference(&ReferenceName::from(format!("refs/heads/{name}")))?;
+            Ok(match reference {
+                Some(reference) => match reference.peel_to_commit()? {
+                    Some(commit) => MaybeZeroOid::NonZero(commit.get_oid()),
+                    None => MaybeZeroOid::Zero,
+                },
+                None => MaybeZeroOid::Zero,
+            })
+        };
+
+        // The update to `bar` expects it to already exist, so the whole
+        // transaction should fail and `foo` should not be moved.
+        let result = git_run_info.update_references(
+            &effects,
+            &repo,
+            event_tx_id,
+            &[
+                ReferenceUpdate {
+                    ref_name: "refs/heads/foo".into(),
+                    old_oid: MaybeZeroOid::NonZero(test1_oid),
+                    new_oid: MaybeZeroOid::NonZero(test2_oid),
+                },
+                ReferenceUpdate {
+                    ref_name: "refs/heads/bar".into(),
+                    old_oid: MaybeZeroOid::NonZero(test1_oid),
+                    new_oid: MaybeZeroOid::NonZero(test2_oid),
+                },
+            ],
+        )?;
+        assert!(result.is_err());
+        assert_eq!(get_branch_oid("foo")?, MaybeZeroOid::NonZero(test1_oid));
+        assert_eq!(get_branch_oid("bar")?, MaybeZeroOid::Zero);
+
+        let result = git_run_info.update_references(
+            &effects,
+            &repo,
+            event_tx_id,
+            &[
+                ReferenceUpdate {
+                    ref_name: "refs/heads/foo".into(),
+                    old_oid: MaybeZeroOid::NonZero(test1_oid),
+                    new_oid: MaybeZeroOid::NonZero(test2_oid),
+                },
+                ReferenceUpdate {
+                    ref_name: "refs/heads/bar".into(),
+                    old_oid: MaybeZeroOid::Zero,
+                    new_oid: MaybeZeroOid::NonZero(test2_oid),
+                },
+            ],
+        )?;
+        assert!(result.is_ok());
+        assert_eq!(get_branch_oid("foo")?, MaybeZeroOid::NonZero(test2_oid));
+        assert_eq!(get_branch_oid("bar")?, MaybeZeroOid::NonZero(test2_oid));
+
+        Ok(())
+    }
 }
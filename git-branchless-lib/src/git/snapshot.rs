@@ -35,7 +35,8 @@ use super::repo::Signature;
 use super::status::FileMode;
 use super::tree::{hydrate_tree, make_empty_tree};
 use super::{
-    Commit, MaybeZeroOid, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo, StatusEntry,
+    Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo,
+    StatusEntry,
 };
 
 const BRANCHLESS_HEAD_TRAILER: &str = "Branchless-head";
@@ -106,6 +107,7 @@ impl<'repo> WorkingCopySnapshot<'repo> {
     #[instrument]
     pub(super) fn create(
         repo: &'repo Repo,
+        git_run_info: &GitRunInfo,
         index: &Index,
         head_info: &ResolvedReferenceInfo,
         status_entries: &[StatusEntry],
@@ -121,7 +123,13 @@ impl<'repo> WorkingCopySnapshot<'repo> {
         let head_reference_name: Option<ReferenceName> = head_info.reference_name.clone();
 
         let commit_unstaged_oid: NonZeroOid = {
-            Self::create_commit_for_unstaged_changes(repo, head_commit.as_ref(), status_entries)?
+            Self::create_commit_for_unstaged_changes(
+                repo,
+                git_run_info,
+                index,
+                head_commit.as_ref(),
+                status_entries,
+            )?
         };
 
         let commit_stage0 = Self::create_commit_for_stage(
@@ -299,6 +307,8 @@ branchless: automated working copy snapshot
     #[instrument]
     fn create_commit_for_unstaged_changes(
         repo: &Repo,
+        git_run_info: &GitRunInfo,
+        index: &Index,
         head_commit: Option<&Commit>,
         status_entries: &[StatusEntry],
     ) -> eyre::Result<NonZeroOid> {
@@ -332,6 +342,7 @@ branchless: automated working copy snapshot
         let num_changes = changed_paths.len();
 
         let head_tree = head_commit.map(|commit| commit.get_tree()).transpose()?;
+        let file_mode_support = repo.get_working_copy_file_mode_support()?;
         let hydrate_entries = {
             let mut result = HashMap::new();
             for (path, file_mode) in changed_paths {
@@ -342,8 +353,15 @@ branchless: automated working copy snapshot
                     // existence on disk because it's no longer being tracked by
                     // the index.
                     None
+                } else if file_mode == FileMode::Commit {
+                    repo.get_submodule_head_oid(&path)?
+                        .map(|commit_oid| (commit_oid, file_mode))
                 } else {
-                    repo.create_blob_from_path(&path)?
+                    let file_mode = file_mode_support.reconcile(
+                        index.get_entry(&path).map(|entry| entry.file_mode),
+                        file_mode,
+                    );
+                    repo.create_blob_from_path(git_run_info, &path)?
                         .map(|blob_oid| (blob_oid, file_mode))
                 };
                 result.insert(path, entry);
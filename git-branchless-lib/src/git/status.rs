@@ -181,6 +181,61 @@ impl StatusEntry {
     }
 }
 
+/// Which file modes the filesystem backing the working copy can represent, as
+/// configured by `core.fileMode` and `core.symlinks`. These are usually both
+/// disabled on Windows. When a kind of file mode can't be represented, the mode
+/// recorded in the index is used instead of the mode observed on disk, in the
+/// same way as Git does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WorkingCopyFileModeSupport {
+    /// Whether the executable bit of files on disk can be trusted.
+    pub executable_bit: bool,
+
+    /// Whether symbolic links on disk can be trusted.
+    pub symlinks: bool,
+}
+
+impl Default for WorkingCopyFileModeSupport {
+    fn default() -> Self {
+        Self {
+            executable_bit: true,
+            symlinks: true,
+        }
+    }
+}
+
+impl WorkingCopyFileModeSupport {
+    /// Determine the file mode that should be committed for a file, given the
+    /// mode observed in the working copy and the mode recorded in the index
+    /// for that path (if any).
+    pub fn reconcile(
+        &self,
+        recorded_file_mode: Option<FileMode>,
+        working_copy_file_mode: FileMode,
+    ) -> FileMode {
+        let is_regular_file = matches!(
+            working_copy_file_mode,
+            FileMode::Blob | FileMode::BlobExecutable
+        );
+        if !is_regular_file {
+            return working_copy_file_mode;
+        }
+
+        match recorded_file_mode {
+            // Without symlink support, symlinks are checked out as regular
+            // files containing the link target.
+            Some(FileMode::Link) if !self.symlinks => FileMode::Link,
+            Some(recorded_file_mode @ (FileMode::Blob | FileMode::BlobExecutable))
+                if !self.executable_bit =>
+            {
+                recorded_file_mode
+            }
+            _ if !self.executable_bit => FileMode::Blob,
+            _ => working_copy_file_mode,
+        }
+    }
+}
+
 impl TryFrom<&[u8]> for StatusEntry {
     type Error = eyre::Error;
 
@@ -285,6 +340,19 @@ mod tests {
             }
         );
 
+        assert_eq!(
+            StatusEntry::try_from(
+                "1 .M SC.. 160000 160000 160000 51fcbe2362663a19d132767b69c2c7829023f3da 51fcbe2362663a19d132767b69c2c7829023f3da submodule".as_bytes(),
+            ).unwrap(),
+            StatusEntry {
+                index_status: FileStatus::Unmodified,
+                working_copy_status: FileStatus::Modified,
+                path: "submodule".into(),
+                orig_path: None,
+                working_copy_file_mode: FileMode::Commit,
+            }
+        );
+
         let entry: StatusEntry = StatusEntry::try_from(
             "2 RD N... 100644 100644 100644 9daeafb9864cf43055ae93beb0afd6c7d144bfa4 9daeafb9864cf43055ae93beb0afd6c7d144bfa4 R100 new_file.rs\x00old_file.rs".as_bytes(),
         ).unwrap();
@@ -317,6 +385,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reconcile_working_copy_file_mode() {
+        let full_support = WorkingCopyFileModeSupport::default();
+        assert_eq!(
+            full_support.reconcile(Some(FileMode::Blob), FileMode::BlobExecutable),
+            FileMode::BlobExecutable
+        );
+        assert_eq!(
+            full_support.reconcile(Some(FileMode::Link), FileMode::Blob),
+            FileMode::Blob
+        );
+
+        // Typical configuration on Windows.
+        let no_support = WorkingCopyFileModeSupport {
+            executable_bit: false,
+            symlinks: false,
+        };
+        assert_eq!(
+            no_support.reconcile(Some(FileMode::BlobExecutable), FileMode::Blob),
+            FileMode::BlobExecutable
+        );
+        assert_eq!(
+            no_support.reconcile(Some(FileMode::Blob), FileMode::BlobExecutable),
+            FileMode::Blob
+        );
+        assert_eq!(
+            no_support.reconcile(None, FileMode::BlobExecutable),
+            FileMode::Blob
+        );
+        assert_eq!(
+            no_support.reconcile(Some(FileMode::Link), FileMode::Blob),
+            FileMode::Link
+        );
+        assert_eq!(
+            no_support.reconcile(Some(FileMode::Blob), FileMode::Commit),
+            FileMode::Commit
+        );
+        assert_eq!(
+            no_support.reconcile(Some(FileMode::Blob), FileMode::Unreadable),
+            FileMode::Unreadable
+        );
+    }
+
     #[test]
     fn test_get_status() -> eyre::Result<()> {
         let git = make_git()?;
@@ -441,7 +552,7 @@ mod tests {
         WorkingCopySnapshot {
             base_commit: Commit {
                 inner: Commit {
-                    id: e378f780ff4d810e12d36e89334d8971d7add0d1,
+                    id: 2f8be3a55bd58854a5871d7260abc37ab5d1199c,
                     summary: "branchless: automated working copy snapshot",
                 },
             },
@@ -460,7 +571,7 @@ mod tests {
             ),
             commit_unstaged: Commit {
                 inner: Commit {
-                    id: 8f4827eba90e1ce5bbd0d76fc29d587c8ad9135e,
+                    id: 329d438eaf36089efa9a49a3942a10985c037dce,
                     summary: "branchless: working copy snapshot data: 4 unstaged changes",
                 },
             },
@@ -380,6 +380,7 @@ pub fn hydrate_tree(
     Ok(make_non_zero_oid(tree_oid))
 }
 
+/// Create a tree with no entries.
 pub fn make_empty_tree(repo: &Repo) -> eyre::Result<Tree> {
     let tree_oid = hydrate_tree(repo, None, Default::default())?;
     repo.find_tree_or_fail(tree_oid)
@@ -538,6 +539,61 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_hydrate_tree_with_submodule() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let repo = git.get_repo()?;
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+        let head_commit = repo.find_commit_or_fail(head_oid)?;
+        let head_tree = head_commit.get_tree()?;
+
+        // The commit that a gitlink points to is generally not in the
+        // repository, so use an OID which doesn't exist.
+        let submodule_oid: NonZeroOid = "1111111111111111111111111111111111111111".parse()?;
+        let hydrated_tree = {
+            let hydrated_tree_oid = hydrate_tree(&repo, Some(&head_tree), {
+                let mut result = HashMap::new();
+                result.insert(
+                    PathBuf::from("vendor/submodule"),
+                    Some((submodule_oid, FileMode::Commit)),
+                );
+                result
+            })?;
+            repo.find_tree_or_fail(hydrated_tree_oid)?
+        };
+        let entry = hydrated_tree
+            .get_path(Path::new("vendor/submodule"))?
+            .unwrap();
+        assert_eq!(entry.get_oid(), submodule_oid);
+        assert_eq!(entry.get_filemode(), FileMode::Commit);
+
+        let changed_paths = get_changed_paths_between_trees(
+            &repo,
+            Some(&head_tree.inner),
+            Some(&hydrated_tree.inner),
+        )?;
+        insta::assert_debug_snapshot!(changed_paths, @r###"
+        {
+            "vendor/submodule",
+        }
+        "###);
+
+        let dehydrated_tree = {
+            let dehydrated_tree_oid =
+                dehydrate_tree(&repo, &hydrated_tree, &[Path::new("vendor/submodule")])?;
+            repo.find_tree_or_fail(dehydrated_tree_oid)?
+        };
+        let entry = dehydrated_tree
+            .get_path(Path::new("vendor/submodule"))?
+            .unwrap();
+        assert_eq!(entry.get_oid(), submodule_oid);
+        assert_eq!(entry.get_filemode(), FileMode::Commit);
+
+        Ok(())
+    }
+
     #[test]
     fn test_detect_path_only_changed_file_mode() -> eyre::Result<()> {
         let git = make_git()?;
@@ -0,0 +1,269 @@
+//! Use [Watchman](https://facebook.github.io/watchman/) to find the files
+//! which changed in the working copy, so that `git status` only has to check
+//! those files rather than crawling the entire working copy.
+//!
+//! We record the Watchman clock along with the set of paths which were dirty
+//! as of the last status query. The next query then only needs to check the
+//! paths which were dirty before, plus the paths which Watchman reports as
+//! changed since that clock. If `HEAD` or the index changed in the meantime,
+//! files could have become dirty without being touched, so we fall back to
+//! checking the entire working copy.
+
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::instrument;
+
+use super::{NonZeroOid, Repo, StatusEntry};
+
+/// If more than this many paths would have to be checked, then check the
+/// entire working copy instead, to avoid exceeding the maximum command-line
+/// length when passing them to `git status`.
+const MAX_PATHS_TO_CHECK: usize = 1000;
+
+/// The state persisted between status queries.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+struct WatchmanState {
+    clock: String,
+    head_oid: Option<NonZeroOid>,
+    index_mtime: Option<SystemTime>,
+    dirty_paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchProjectResponse {
+    watch: PathBuf,
+    relative_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClockResponse {
+    clock: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryResponse {
+    clock: String,
+    #[serde(default)]
+    is_fresh_instance: bool,
+    files: Vec<PathBuf>,
+}
+
+/// The paths which `git status` needs to check.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PathsToCheck {
+    /// Check the entire working copy.
+    All,
+
+    /// Only check the given paths. All other paths are known to be clean.
+    Only(Vec<PathBuf>),
+}
+
+/// The result of querying Watchman. After running `git status`, pass this to
+/// [`save_state`] so that the next query can use it.
+#[derive(Debug)]
+pub(crate) struct WatchmanQuery {
+    clock: String,
+    pub paths_to_check: PathsToCheck,
+}
+
+fn run_watchman(request: serde_json::Value) -> eyre::Result<Option<serde_json::Value>> {
+    let mut child = match Command::new("watchman")
+        .args(["--output-encoding=json", "--no-pretty", "-j"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).wrap_err("Spawning watchman"),
+    };
+
+    {
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| eyre::eyre!("Could not open stdin for watchman"))?;
+        stdin.write_all(&serde_json::to_vec(&request)?)?;
+    }
+    let output = child.wait_with_output().wrap_err("Waiting for watchman")?;
+    if !output.status.success() {
+        eyre::bail!("watchman exited with status: {}", output.status);
+    }
+
+    let response: serde_json::Value =
+        serde_json::from_slice(&output.stdout).wrap_err("Parsing watchman response")?;
+    if let Some(error) = response.get("error") {
+        eyre::bail!("watchman returned an error: {}", error);
+    }
+    Ok(Some(response))
+}
+
+fn get_state_path(repo: &Repo) -> PathBuf {
+    repo.get_path().join("branchless").join("watchman-state")
+}
+
+fn get_index_mtime(repo: &Repo) -> Option<SystemTime> {
+    std::fs::metadata(repo.get_path().join("index"))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn read_state(repo: &Repo) -> Option<WatchmanState> {
+    let contents = std::fs::read(get_state_path(repo)).ok()?;
+    bincode::deserialize(&contents).ok()
+}
+
+fn get_paths_to_check(
+    previous_state: &WatchmanState,
+    query_response: &QueryResponse,
+) -> PathsToCheck {
+    if query_response.is_fresh_instance {
+        return PathsToCheck::All;
+    }
+    let paths: BTreeSet<PathBuf> = previous_state
+        .dirty_paths
+        .iter()
+        .chain(query_response.files.iter())
+        .cloned()
+        .collect();
+    if paths.len() > MAX_PATHS_TO_CHECK {
+        PathsToCheck::All
+    } else {
+        PathsToCheck::Only(paths.into_iter().collect())
+    }
+}
+
+/// Ask Watchman which paths `git status` needs to check. Returns `None` if
+/// Watchman isn't installed.
+#[instrument]
+pub(crate) fn query(
+    repo: &Repo,
+    head_oid: Option<NonZeroOid>,
+) -> eyre::Result<Option<WatchmanQuery>> {
+    let working_copy_path = match repo.get_working_copy_path() {
+        Some(working_copy_path) => working_copy_path,
+        None => return Ok(None),
+    };
+    let response = match run_watchman(json!(["watch-project", working_copy_path]))? {
+        Some(response) => response,
+        None => return Ok(None),
+    };
+    let WatchProjectResponse {
+        watch,
+        relative_path,
+    } = serde_json::from_value(response).wrap_err("Parsing watch-project response")?;
+
+    let previous_state = read_state(repo)
+        .filter(|state| state.head_oid == head_oid && state.index_mtime == get_index_mtime(repo));
+    let query = match previous_state {
+        None => {
+            let response = run_watchman(json!(["clock", watch]))?
+                .ok_or_else(|| eyre::eyre!("watchman disappeared"))?;
+            let ClockResponse { clock } =
+                serde_json::from_value(response).wrap_err("Parsing clock response")?;
+            WatchmanQuery {
+                clock,
+                paths_to_check: PathsToCheck::All,
+            }
+        }
+
+        Some(previous_state) => {
+            let mut query = json!({
+                "since": previous_state.clock,
+                "fields": ["name"],
+                "expression": ["anyof", ["type", "f"], ["type", "l"]],
+            });
+            if let Some(relative_path) = relative_path {
+                query["relative_root"] = json!(relative_path);
+            }
+            let response = run_watchman(json!(["query", watch, query]))?
+                .ok_or_else(|| eyre::eyre!("watchman disappeared"))?;
+            let query_response: QueryResponse =
+                serde_json::from_value(response).wrap_err("Parsing query response")?;
+            WatchmanQuery {
+                paths_to_check: get_paths_to_check(&previous_state, &query_response),
+                clock: query_response.clock,
+            }
+        }
+    };
+    Ok(Some(query))
+}
+
+/// Record the result of a `git status` invocation made with the paths from
+/// the given query.
+#[instrument]
+pub(crate) fn save_state(
+    repo: &Repo,
+    query: WatchmanQuery,
+    head_oid: Option<NonZeroOid>,
+    statuses: &[StatusEntry],
+) -> eyre::Result<()> {
+    let WatchmanQuery {
+        clock,
+        paths_to_check: _,
+    } = query;
+    let dirty_paths: BTreeSet<PathBuf> = statuses.iter().flat_map(|entry| entry.paths()).collect();
+    let state = WatchmanState {
+        clock,
+        head_oid,
+        // `git status` may have refreshed the index, so this must be read after
+        // it has run.
+        index_mtime: get_index_mtime(repo),
+        dirty_paths: dirty_paths.into_iter().collect(),
+    };
+    let state_path = get_state_path(repo);
+    if let Some(parent) = state_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&state_path, bincode::serialize(&state)?)
+        .wrap_err_with(|| format!("Writing watchman state to {:?}", &state_path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_paths_to_check() -> eyre::Result<()> {
+        let previous_state = WatchmanState {
+            clock: "c:0:1".to_owned(),
+            head_oid: None,
+            index_mtime: None,
+            dirty_paths: vec![PathBuf::from("foo"), PathBuf::from("bar/baz")],
+        };
+
+        let query_response: QueryResponse = serde_json::from_value(json!({
+            "clock": "c:0:2",
+            "files": ["qux", "foo"],
+        }))?;
+        assert_eq!(
+            get_paths_to_check(&previous_state, &query_response),
+            PathsToCheck::Only(vec![
+                PathBuf::from("bar/baz"),
+                PathBuf::from("foo"),
+                PathBuf::from("qux"),
+            ])
+        );
+
+        let query_response: QueryResponse = serde_json::from_value(json!({
+            "clock": "c:0:2",
+            "is_fresh_instance": true,
+            "files": ["qux"],
+        }))?;
+        assert_eq!(
+            get_paths_to_check(&previous_state, &query_response),
+            PathsToCheck::All
+        );
+
+        Ok(())
+    }
+}
@@ -5,6 +5,7 @@
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
 pub mod core;
+pub mod error;
 pub mod git;
 pub mod testing;
 pub mod util;
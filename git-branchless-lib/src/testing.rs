@@ -1,18 +1,41 @@
 //! Testing utilities.
 //!
 //! This is inside `src` rather than `tests` since we use this code in some unit
-//! tests.
+//! tests. It's also a supported API for projects which build on git-branchless
+//! and want to write integration tests against real repositories:
+//!
+//! - [`make_git`] creates a temporary repository, which [`Git::init_repo`]
+//!   initializes with git-branchless installed.
+//! - [`Git::run`] and [`Git::run_with_options`] run Git with a deterministic
+//!   environment, so that commit hashes are stable between runs.
+//! - [`Git::build_stack`] creates a stack of commits with the given files.
+//! - [`Git::build_scenario`] creates an arbitrary commit graph, branches and
+//!   working copy from a single ASCII-art literal.
+//! - [`make_synthetic_repo`] quickly generates a large repository for
+//!   benchmarking.
+//!
+//! The Git executable to use is read from the `TEST_GIT` and
+//! `TEST_GIT_EXEC_PATH` environment variables, and the `git-branchless`
+//! executable from `TEST_GIT_BRANCHLESS` if it's set. See
+//! [`crate::core::config::env_vars`].
 
 use std::collections::{BTreeMap, HashMap};
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::Write;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-
-use crate::core::config::env_vars::{get_git_exec_path, get_path_to_git, TEST_GIT};
-use crate::git::{GitRunInfo, GitVersion, NonZeroOid, Repo};
+use std::time::UNIX_EPOCH;
+
+use crate::core::config::env_vars::{
+    get_git_exec_path, get_path_to_git, get_path_to_git_branchless, TEST_GIT,
+};
+use crate::core::eventlog::{Event, EventLogDb};
+use crate::git::{
+    hydrate_tree, Commit, FileMode, GitRunInfo, GitVersion, NonZeroOid, ReferenceName, Repo,
+    Signature,
+};
 use crate::util::get_sh;
 
 use eyre::Context;
@@ -130,7 +153,8 @@ impl Git {
 
     /// Get the `PATH` environment variable to use for testing.
     pub fn get_path_for_env(&self) -> OsString {
-        let cargo_bin_path = assert_cmd::cargo::cargo_bin("git-branchless");
+        let cargo_bin_path = get_path_to_git_branchless()
+            .unwrap_or_else(|| assert_cmd::cargo::cargo_bin("git-branchless"));
         let branchless_path = cargo_bin_path
             .parent()
             .expect("Unable to find git-branchless path parent");
@@ -411,6 +435,204 @@ stderr:
         self.commit_file_with_contents(name, time, &format!("{} contents\n", name))
     }
 
+    /// Commit a stack of commits on top of `HEAD`. Each entry is the message
+    /// for one commit and the files to write for it, as pairs of paths relative
+    /// to the repository root and their contents. Unlike [`Git::commit_file`],
+    /// the paths are used as-is, without appending `.txt`.
+    ///
+    /// The commit timestamps are set to the position of each commit in the
+    /// stack, starting from 1. Returns the OIDs of the new commits, from the
+    /// bottom of the stack to the top.
+    #[instrument]
+    pub fn build_stack(
+        &self,
+        commits: &[(&str, &[(&str, &str)])],
+    ) -> eyre::Result<Vec<NonZeroOid>> {
+        let mut commit_oids = Vec::new();
+        for (i, (message, files)) in commits.iter().enumerate() {
+            for (name, contents) in files.iter() {
+                let file_path = self.repo_path.join(name);
+                if let Some(dir) = file_path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(&file_path, contents)?;
+                self.run(&["add", "--", name])?;
+            }
+            let time = isize::try_from(i + 1)?;
+            self.run_with_options(
+                &["commit", "--allow-empty", "-m", message],
+                &GitRunOptions {
+                    time,
+                    ..Default::default()
+                },
+            )?;
+            let oid = self
+                .get_repo()?
+                .get_head_info()?
+                .oid
+                .expect("Could not find OID for just-created commit");
+            commit_oids.push(oid);
+        }
+        Ok(commit_oids)
+    }
+
+    /// Build the commit graph, branches and working copy described by
+    /// `scenario`, and return the OIDs of the created commits by name.
+    ///
+    /// The lines of the scenario which don't contain a `:` are an ASCII-art
+    /// commit graph, as accepted by the `drawdag` crate. Roots are on the left
+    /// and edges are drawn with `-`, `/` and `\`, or roots are at the bottom
+    /// and edges are drawn with `|`, `/` and `\`:
+    ///
+    /// ```text
+    /// test1-test2-test3
+    ///      \
+    ///       test4
+    /// ```
+    ///
+    /// Each commit `X` is created like `commit_file("X", time)` would, where
+    /// `time` is the position of the commit in the order in which the commits
+    /// are created, starting from 1. Commits with several parents are merge
+    /// commits. The roots of the graph are children of the current `HEAD`
+    /// commit, or root commits if `HEAD` is unborn.
+    ///
+    /// The remaining lines are directives, which are applied after the
+    /// commits are created:
+    ///
+    /// - `branch NAME: X` points the branch `NAME` at `X`.
+    /// - `head: X` checks out `X`. If `X` is a commit in the graph, `HEAD` is
+    ///   detached; otherwise, `X` is passed to `git checkout` as-is, so it can
+    ///   be a branch name. Without this directive, the original `HEAD` is
+    ///   checked out again, if it exists.
+    /// - `file PATH: CONTENTS` writes `CONTENTS` and a newline to `PATH` in the
+    ///   working copy, without staging it.
+    /// - `staged PATH: CONTENTS` is like `file`, but also stages the file.
+    #[instrument]
+    pub fn build_scenario(&self, scenario: &str) -> eyre::Result<BTreeMap<String, NonZeroOid>> {
+        const ORPHAN_BRANCH_NAME: &str = "branchless-scenario-root";
+
+        let (directives, graph): (Vec<&str>, Vec<&str>) =
+            scenario.lines().partition(|line| line.contains(':'));
+        let edges = drawdag::parse(graph.join("\n"));
+
+        let original_head_info = self.get_repo()?.get_head_info()?;
+        let mut commit_oids: BTreeMap<String, NonZeroOid> = BTreeMap::new();
+        while commit_oids.len() < edges.len() {
+            let next = edges.iter().find(|(name, parents)| {
+                !commit_oids.contains_key(*name)
+                    && parents
+                        .iter()
+                        .all(|parent| commit_oids.contains_key(parent))
+            });
+            let (name, parents) = match next {
+                Some(next) => next,
+                None => eyre::bail!("The scenario's commit graph contains a cycle"),
+            };
+            let time = isize::try_from(commit_oids.len() + 1)?;
+            let parent_oids = parents
+                .iter()
+                .map(|parent| commit_oids[parent].to_string())
+                .collect_vec();
+
+            let oid = match (parent_oids.as_slice(), original_head_info.oid) {
+                ([], None) => {
+                    self.run(&["checkout", "--quiet", "--orphan", ORPHAN_BRANCH_NAME])?;
+                    self.run(&["rm", "-r", "--quiet", "--ignore-unmatch", "--", "."])?;
+                    let oid = self.commit_file(name, time)?;
+                    self.run(&["checkout", "--quiet", "--detach"])?;
+                    self.run(&["branch", "--quiet", "-D", ORPHAN_BRANCH_NAME])?;
+                    oid
+                }
+                ([], Some(head_oid)) => {
+                    self.run(&["checkout", "--quiet", "--detach", &head_oid.to_string()])?;
+                    self.commit_file(name, time)?
+                }
+                ([first_parent_oid, other_parent_oids @ ..], _) => {
+                    self.run(&["checkout", "--quiet", "--detach", first_parent_oid])?;
+                    if !other_parent_oids.is_empty() {
+                        let mut args = vec!["merge", "--quiet", "--no-ff", "--no-commit"];
+                        args.extend(other_parent_oids.iter().map(|oid| oid.as_str()));
+                        self.run(&args)?;
+                    }
+                    self.commit_file(name, time)?
+                }
+            };
+            commit_oids.insert(name.clone(), oid);
+        }
+
+        let mut head_target = None;
+        let mut files = Vec::new();
+        for directive in directives {
+            let (key, value) = match directive.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => unreachable!("Directives always contain a colon"),
+            };
+            let resolve = |target: &str| match commit_oids.get(target) {
+                Some(oid) => oid.to_string(),
+                None => target.to_owned(),
+            };
+            match key.split_once(' ') {
+                Some(("branch", branch_name)) => {
+                    self.run(&[
+                        "branch",
+                        "--quiet",
+                        "-f",
+                        branch_name.trim(),
+                        &resolve(value),
+                    ])?;
+                }
+                Some(("file", path)) => files.push((path.trim(), value, false)),
+                Some(("staged", path)) => files.push((path.trim(), value, true)),
+                None if key == "head" => head_target = Some(value),
+                _ => eyre::bail!("Unknown scenario directive: {:?}", directive.trim()),
+            }
+        }
+
+        match head_target {
+            Some(target) => match commit_oids.get(target) {
+                Some(oid) => {
+                    self.run(&["checkout", "--quiet", "--detach", &oid.to_string()])?;
+                }
+                None => {
+                    self.run(&["checkout", "--quiet", target])?;
+                }
+            },
+            None if !commit_oids.is_empty() => {
+                match (
+                    original_head_info.get_branch_name()?,
+                    original_head_info.oid,
+                ) {
+                    (Some(branch_name), _)
+                        if self
+                            .get_repo()?
+                            .get_reference(OsStr::new(&format!("refs/heads/{}", branch_name)))?
+                            .is_some() =>
+                    {
+                        self.run(&["checkout", "--quiet", branch_name])?;
+                    }
+                    (_, Some(head_oid)) => {
+                        self.run(&["checkout", "--quiet", "--detach", &head_oid.to_string()])?;
+                    }
+                    (_, None) => {}
+                }
+            }
+            None => {}
+        }
+
+        for (path, contents, is_staged) in files {
+            let file_path = self.repo_path.join(path);
+            if let Some(dir) = file_path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&file_path, format!("{}\n", contents))?;
+            if is_staged {
+                self.run(&["add", "--", path])?;
+            }
+        }
+
+        Ok(commit_oids)
+    }
+
     /// Detach HEAD. This is useful to call to make sure that no branch is
     /// checked out, and therefore that future commit operations don't move any
     /// branches.
@@ -440,6 +662,7 @@ stderr:
             path_to_git: self.path_to_git.clone(),
             working_directory: self.repo_path.clone(),
             env: self.get_base_env(0).into_iter().collect(),
+            inherit_stdin: true,
         }
     }
 
@@ -484,6 +707,19 @@ stderr:
         Ok(version >= GitVersion(2, 27, 0))
     }
 
+    /// Determine if commits can be signed with SSH keys, which requires Git
+    /// v2.34 and `ssh-keygen`.
+    pub fn supports_ssh_signing(&self) -> eyre::Result<bool> {
+        let version = self.get_version()?;
+        Ok(version >= GitVersion(2, 34, 0)
+            && Command::new("ssh-keygen")
+                .arg("-?")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok())
+    }
+
     /// Resolve a file during a merge or rebase conflict with the provided
     /// contents.
     #[instrument]
@@ -601,3 +837,308 @@ pub fn make_git_with_remote_repo() -> eyre::Result<GitWrapperWithRemoteRepo> {
         cloned_repo,
     })
 }
+
+/// Options for [`make_synthetic_repo`].
+#[derive(Clone, Debug)]
+pub struct SyntheticRepoOptions {
+    /// The number of commits to create on the main branch.
+    pub num_main_commits: usize,
+
+    /// The number of stacks of draft commits to create on top of the main
+    /// branch. The stacks are spread evenly across the main branch.
+    pub num_draft_stacks: usize,
+
+    /// The number of commits in each draft stack.
+    pub draft_stack_size: usize,
+
+    /// The number of directories in the repository.
+    pub num_dirs: usize,
+
+    /// The number of files in each directory.
+    pub files_per_dir: usize,
+
+    /// The number of files modified by each commit.
+    pub files_changed_per_commit: usize,
+}
+
+impl Default for SyntheticRepoOptions {
+    fn default() -> Self {
+        SyntheticRepoOptions {
+            num_main_commits: 1000,
+            num_draft_stacks: 50,
+            draft_stack_size: 5,
+            num_dirs: 100,
+            files_per_dir: 100,
+            files_changed_per_commit: 10,
+        }
+    }
+}
+
+/// A large repository generated by [`make_synthetic_repo`]. The repository is
+/// deleted once this value is dropped.
+pub struct SyntheticRepo {
+    /// Guard to clean up the temporary directory containing the repository.
+    pub temp_dir: TempDir,
+
+    /// The generated repository.
+    pub repo: Repo,
+
+    /// The commits on the main branch, oldest first.
+    pub main_commit_oids: Vec<NonZeroOid>,
+
+    /// The draft commits, in the order they were created.
+    pub draft_commit_oids: Vec<NonZeroOid>,
+}
+
+impl std::fmt::Debug for SyntheticRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<SyntheticRepo path={:?}>", self.temp_dir.path())
+    }
+}
+
+/// Generate a large repository for benchmarking, without invoking Git.
+///
+/// The commits are written directly to the object database, and a commit
+/// event is recorded in the event log for each one, so that the draft commits
+/// are visible as though they had been made with git-branchless installed.
+/// The output is deterministic for a given set of options, so numbers from
+/// different machines can be compared.
+#[instrument]
+pub fn make_synthetic_repo(options: &SyntheticRepoOptions) -> eyre::Result<SyntheticRepo> {
+    let SyntheticRepoOptions {
+        num_main_commits,
+        num_draft_stacks,
+        draft_stack_size,
+        num_dirs,
+        files_per_dir,
+        files_changed_per_commit,
+    } = *options;
+    let num_files = num_dirs * files_per_dir;
+    eyre::ensure!(num_main_commits > 0, "Must create at least one main commit");
+    eyre::ensure!(num_files > 0, "Must create at least one file");
+
+    let temp_dir = tempfile::tempdir()?;
+    git2::Repository::init(temp_dir.path())?;
+    let repo = Repo::from_dir(temp_dir.path())?;
+    let signature = Signature::automated()?;
+    let file_path = |i: usize| -> PathBuf {
+        PathBuf::from(format!(
+            "dir{}/file{}.txt",
+            i / files_per_dir,
+            i % files_per_dir
+        ))
+    };
+
+    let mut commit_counter: usize = 0;
+    let mut make_commit =
+        |parent: Option<&Commit>, message_prefix: &str| -> eyre::Result<NonZeroOid> {
+            let entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = match parent {
+                None => (0..num_files)
+                    .map(|i| -> eyre::Result<_> {
+                        let blob_oid =
+                            repo.create_blob_from_contents(format!("file {i}\n").as_bytes())?;
+                        Ok((file_path(i), Some((blob_oid, FileMode::Blob))))
+                    })
+                    .try_collect()?,
+                Some(_) => (0..files_changed_per_commit)
+                    .map(|j| -> eyre::Result<_> {
+                        // Spread the changes pseudo-randomly over the files.
+                        let i = (commit_counter * 7919 + j * 104729) % num_files;
+                        let blob_oid = repo.create_blob_from_contents(
+                            format!("file {i}\nchanged in commit {commit_counter}\n").as_bytes(),
+                        )?;
+                        Ok((file_path(i), Some((blob_oid, FileMode::Blob))))
+                    })
+                    .try_collect()?,
+            };
+            let parent_tree = match parent {
+                Some(parent) => Some(parent.get_tree()?),
+                None => None,
+            };
+            let tree_oid = hydrate_tree(&repo, parent_tree.as_ref(), entries)?;
+            let tree = repo.find_tree_or_fail(tree_oid)?;
+            // Every tenth commit is a fix, so that message filters have
+            // something to match.
+            let message = if commit_counter % 10 == 0 {
+                format!("{message_prefix} {commit_counter}: fix something")
+            } else {
+                format!("{message_prefix} {commit_counter}")
+            };
+            let commit_oid = repo.create_commit(
+                None,
+                &signature,
+                &signature,
+                &message,
+                &tree,
+                parent.into_iter().collect(),
+            )?;
+            commit_counter += 1;
+            Ok(commit_oid)
+        };
+
+    let mut main_commit_oids = Vec::with_capacity(num_main_commits);
+    let mut parent: Option<Commit> = None;
+    for _ in 0..num_main_commits {
+        let commit_oid = make_commit(parent.as_ref(), "main")?;
+        main_commit_oids.push(commit_oid);
+        parent = Some(repo.find_commit_or_fail(commit_oid)?);
+    }
+    drop(parent);
+
+    let mut draft_commit_oids = Vec::with_capacity(num_draft_stacks * draft_stack_size);
+    for stack_index in 0..num_draft_stacks {
+        let base_index = stack_index * num_main_commits / num_draft_stacks;
+        let mut parent = repo.find_commit_or_fail(main_commit_oids[base_index])?;
+        for _ in 0..draft_stack_size {
+            let commit_oid = make_commit(Some(&parent), "draft")?;
+            draft_commit_oids.push(commit_oid);
+            parent = repo.find_commit_or_fail(commit_oid)?;
+        }
+    }
+
+    let main_oid = *main_commit_oids.last().unwrap();
+    repo.create_reference(
+        &ReferenceName::from("refs/heads/master"),
+        main_oid,
+        true,
+        "synthetic repo",
+    )?;
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(UNIX_EPOCH, "synthetic repo")?;
+    let events = main_commit_oids
+        .iter()
+        .chain(draft_commit_oids.iter())
+        .zip((0_u32..).map(f64::from))
+        .map(|(commit_oid, timestamp)| Event::CommitEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: *commit_oid,
+        })
+        .collect_vec();
+    event_log_db.add_events(events)?;
+
+    Ok(SyntheticRepo {
+        temp_dir,
+        repo,
+        main_commit_oids,
+        draft_commit_oids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_stack() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.detach_head()?;
+
+        let commit_oids = git.build_stack(&[
+            ("add foo", &[("foo.rs", "foo\n"), ("dir/bar.rs", "bar\n")]),
+            ("update foo", &[("foo.rs", "foo2\n")]),
+        ])?;
+        assert_eq!(commit_oids.len(), 2);
+
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "--name-only", "-2"])?;
+        assert_eq!(
+            stdout,
+            "update foo\n\nfoo.rs\nadd foo\n\ndir/bar.rs\nfoo.rs\n"
+        );
+
+        let (stdout, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        assert_eq!(stdout.trim(), commit_oids[1].to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_scenario() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let commit_oids = git.build_scenario(
+            r"
+            test1-test2-test4
+                 \        /
+                  test3----
+            branch feature: test4
+            head: feature
+            file untracked.txt: hello
+            staged initial.txt: goodbye
+            ",
+        )?;
+        assert_eq!(
+            commit_oids.keys().collect_vec(),
+            vec!["test1", "test2", "test3", "test4"]
+        );
+
+        let (stdout, _stderr) = git.run(&["log", "--graph", "--format=%s", "feature"])?;
+        assert_eq!(
+            stdout,
+            "\
+*   create test4.txt
+|\\  
+| * create test3.txt
+* | create test2.txt
+|/  
+* create test1.txt
+* create initial.txt
+"
+        );
+
+        let (stdout, _stderr) = git.run(&["status", "--short", "--branch"])?;
+        assert_eq!(
+            stdout,
+            "\
+## feature
+M  initial.txt
+?? untracked.txt
+"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_make_synthetic_repo() -> eyre::Result<()> {
+        let SyntheticRepo {
+            temp_dir: _temp_dir,
+            repo,
+            main_commit_oids,
+            draft_commit_oids,
+        } = make_synthetic_repo(&SyntheticRepoOptions {
+            num_main_commits: 10,
+            num_draft_stacks: 2,
+            draft_stack_size: 3,
+            num_dirs: 3,
+            files_per_dir: 4,
+            files_changed_per_commit: 2,
+        })?;
+        assert_eq!(main_commit_oids.len(), 10);
+        assert_eq!(draft_commit_oids.len(), 6);
+
+        let main_oid = repo
+            .find_reference(&ReferenceName::from("refs/heads/master"))?
+            .map(|reference| reference.peel_to_commit())
+            .transpose()?
+            .flatten()
+            .map(|commit| commit.get_oid());
+        assert_eq!(main_oid, main_commit_oids.last().copied());
+
+        let root_commit = repo.find_commit_or_fail(main_commit_oids[0])?;
+        let root_tree = root_commit.get_tree()?;
+        assert!(root_tree.get_path(Path::new("dir2/file3.txt"))?.is_some());
+        assert!(root_tree.get_path(Path::new("dir3/file0.txt"))?.is_none());
+
+        let draft_commit = repo.find_commit_or_fail(draft_commit_oids[3])?;
+        assert_eq!(draft_commit.get_parent_oids(), vec![main_commit_oids[5]]);
+        let paths = repo.get_paths_touched_by_commit(&draft_commit)?.unwrap();
+        assert_eq!(paths.len(), 2);
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 //! Utility functions.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 /// Represents the code to exit the process with.
 #[must_use]
@@ -44,12 +45,44 @@ pub fn get_sh() -> Option<PathBuf> {
     if cfg!(target_os = "windows") {
         // Git is typically installed at C:\Program Files\Git\cmd\git.exe with the cmd\ directory
         // in the path, however git-bash is usually not in PATH and is in bin\ directory:
-        let git_path = get_from_path("git.exe").expect("Couldn't find git.exe");
-        let git_dir = git_path.parent().unwrap().parent().unwrap();
-        let git_bash = git_dir.join("bin").join(exe_name);
-        if git_bash.is_file() {
-            return Some(git_bash);
+        let git_bash = get_from_path("git.exe")
+            .as_deref()
+            .and_then(|git_path| git_path.parent())
+            .and_then(|git_dir| git_dir.parent())
+            .map(|git_dir| git_dir.join("bin").join(exe_name));
+        if let Some(git_bash) = git_bash {
+            if git_bash.is_file() {
+                return Some(git_bash);
+            }
         }
     }
     get_from_path(exe_name)
 }
+
+/// Make a command which runs the given shell command line with `sh`, in the
+/// same way that Git runs hooks and aliases. Arguments added to the returned
+/// command are passed to the command line as `"$@"`.
+pub fn shell_command(command: &str) -> eyre::Result<Command> {
+    let sh = get_sh().ok_or_else(|| eyre::eyre!("Could not find sh to run: {}", command))?;
+    let mut result = Command::new(sh);
+    result
+        .arg("-c")
+        .arg(format!("{} \"$@\"", command))
+        .arg(command); // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
+    Ok(result)
+}
+
+/// The marker line identifying a native hook installed by `git-branchless`.
+/// Native hooks invoke the `git-branchless` executable directly, so they can
+/// be run without `sh` by invoking the corresponding hook subcommand.
+pub const NATIVE_HOOK_MARKER: &str = "## BRANCHLESS NATIVE HOOK";
+
+/// Determine whether the hook at the given path is a native hook installed by
+/// `git-branchless`. Returns `false` if the hook doesn't exist.
+pub fn is_branchless_native_hook(path: &Path) -> eyre::Result<bool> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(contents.lines().any(|line| line == NATIVE_HOOK_MARKER)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
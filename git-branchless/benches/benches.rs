@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use git_branchless::revset::{eval, parse};
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Glyphs;
+use lib::core::repo_ext::RepoExt;
+use lib::git::{GitRunInfo, Repo};
+use lib::testing::{make_synthetic_repo, SyntheticRepoOptions};
+
+/// Open the repository at `PATH_TO_REPO`. If it's not set, then the
+/// benchmarks which need a real repository are skipped.
+fn get_repo() -> Option<Repo> {
+    match std::env::var("PATH_TO_REPO") {
+        Ok(repo_dir) => Some(Repo::from_dir(&PathBuf::from(repo_dir)).unwrap()),
+        Err(_) => {
+            eprintln!("`PATH_TO_REPO` environment variable not set, skipping benchmark");
+            None
+        }
+    }
+}
+
+fn bench_revset(c: &mut Criterion, group_name: &str, repo: &Repo) {
+    let mut group = c.benchmark_group(group_name);
+    group.sample_size(10);
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let git_run_info = GitRunInfo {
+        path_to_git: PathBuf::from("git"),
+        working_directory: repo
+            .get_working_copy_path()
+            .unwrap_or_else(|| repo.get_path())
+            .to_path_buf(),
+        env: std::env::vars_os().collect(),
+        inherit_stdin: true,
+    };
+    let conn = repo.get_db_conn().unwrap();
+    let event_log_db = EventLogDb::new(&conn).unwrap();
+    let event_replayer = EventReplayer::from_event_log_db(&effects, repo, &event_log_db).unwrap();
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot().unwrap();
+    let mut dag = Dag::open_and_sync(
+        &effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )
+    .unwrap();
+
+    for revset in [
+        "draft()",
+        "draft() & message(fix)",
+        "message(fix) & draft()",
+        "draft() - paths.changed(README)",
+        "draft() & union(message(fix), message(bug))",
+        "message(fix) & author.name(a) & draft()",
+    ] {
+        let expr = parse(revset).unwrap();
+        group.bench_function(revset, |b| {
+            b.iter(|| {
+                let commits = eval(&effects, &git_run_info, repo, &mut dag, &expr).unwrap();
+                commits.count().unwrap()
+            })
+        });
+    }
+}
+
+/// Compare filters on either side of an intersection. Filters are only tested
+/// against the commits in the other operand, so both orders should take about
+/// as long as evaluating `draft()` alone, rather than as long as testing every
+/// commit in the repository.
+fn bench_revset_filters(c: &mut Criterion) {
+    if let Some(repo) = get_repo() {
+        bench_revset(c, "revset", &repo);
+    }
+}
+
+/// Run the same revsets against a generated repository, so that the numbers
+/// are comparable between machines.
+fn bench_revset_filters_synthetic(c: &mut Criterion) {
+    let synthetic_repo = make_synthetic_repo(&SyntheticRepoOptions::default()).unwrap();
+    bench_revset(c, "synthetic-revset", &synthetic_repo.repo);
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default();
+    targets = bench_revset_filters, bench_revset_filters_synthetic,
+);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+#![no_main]
+
+use git_branchless::revset::parse;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|revset: &str| {
+    let _ = parse(revset);
+});
@@ -0,0 +1,24 @@
+#![no_main]
+
+use std::str::FromStr;
+
+use git_branchless::commands::parse_bulk_edit_message;
+use lib::git::NonZeroOid;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|message: &str| {
+    let commit_oids = [
+        NonZeroOid::from_str("62fc20d2a290daea0d52bdc2ed2ad4be6491010e").unwrap(),
+        NonZeroOid::from_str("96d1c37a3d4363611c49f7e52186e189a04c531f").unwrap(),
+    ];
+
+    // Single commits are parsed differently, since their directive line is
+    // optional.
+    for commit_oids in [&commit_oids[..1], &commit_oids[..]] {
+        if let Ok(result) = parse_bulk_edit_message(message, commit_oids, "#", "++") {
+            for commit_oid in result.messages.keys().chain(result.skipped.iter()) {
+                assert!(commit_oids.contains(commit_oid));
+            }
+        }
+    }
+});
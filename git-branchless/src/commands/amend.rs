@@ -3,16 +3,22 @@
 //! This command amends the HEAD commit with changes to files
 //! that are already tracked in the repo. Following the amend,
 //! the command performs a restack.
+//!
+//! With `--patch`, the changes to amend are selected interactively, and the
+//! rest are left in the working copy.
 
+use std::collections::HashMap;
 use std::fmt::Write;
+use std::path::PathBuf;
 use std::time::SystemTime;
 
 use eyre::Context;
 use itertools::Itertools;
-use lib::core::rewrite::MergeConflictRemediation;
+use lib::core::rewrite::{MergeConflictRemediation, RewriteSummary};
 use lib::util::ExitCode;
 use tracing::instrument;
 
+use crate::commands::record::{get_file_states_for_record, has_selected_changes, select_changes};
 use crate::commands::restack;
 use crate::opts::{MoveOptions, Revset};
 use lib::core::config::get_restack_preserve_timestamps;
@@ -20,13 +26,89 @@ use lib::core::effects::Effects;
 use lib::core::eventlog::{Event, EventLogDb};
 use lib::core::formatting::Pluralize;
 use lib::core::gc::mark_commit_reachable;
-use lib::git::{AmendFastOptions, GitRunInfo, MaybeZeroOid, Repo, ResolvedReferenceInfo};
+use lib::core::user_hooks::{run_commit_hooks, CommitHooksResult};
+use lib::git::{
+    get_selected_submodule_commit, hydrate_tree, is_submodule_file_state, AmendFastOptions,
+    FileMode, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo, ResolvedReferenceInfo, Tree,
+};
+
+/// Where the changes to amend the commit with come from.
+#[derive(Debug)]
+enum AmendSource {
+    /// All of the staged changes, or otherwise all of the uncommitted changes.
+    Fast(AmendFastOptions),
 
-/// Amends the existing HEAD commit.
+    /// The changes to the given paths which were selected interactively.
+    Patch { paths: Vec<PathBuf> },
+}
+
+/// Select changes interactively from the uncommitted changes, and make the
+/// tree for the commit amended with them. Returns `Err` with the exit code if
+/// there's nothing to amend.
+fn select_amended_tree<'repo>(
+    effects: &Effects,
+    repo: &'repo Repo,
+    head_tree: &Tree,
+    working_copy_tree: &Tree,
+) -> eyre::Result<Result<(Tree<'repo>, Vec<PathBuf>), ExitCode>> {
+    let file_states = get_file_states_for_record(effects, repo, head_tree, working_copy_tree)?;
+    if file_states.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "There are no uncommitted or staged changes. Nothing to amend."
+        )?;
+        return Ok(Err(ExitCode(0)));
+    }
+    let file_states = match select_changes(repo, file_states)? {
+        Some(file_states) => file_states,
+        None => {
+            writeln!(effects.get_output_stream(), "Aborted.")?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let mut entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = HashMap::new();
+    for (path, file_state) in file_states {
+        if !has_selected_changes(&file_state) {
+            continue;
+        }
+        if is_submodule_file_state(&file_state) {
+            let entry =
+                get_selected_submodule_commit(&file_state)?.map(|oid| (oid, FileMode::Commit));
+            entries.insert(path, entry);
+            continue;
+        }
+        let (selected, _unselected) = file_state.get_selected_contents();
+        let oid = repo.create_blob_from_contents(selected.as_bytes())?;
+        let file_mode = match head_tree.get_path(&path)? {
+            Some(entry) => entry.get_filemode(),
+            None => match working_copy_tree.get_path(&path)? {
+                Some(entry) => entry.get_filemode(),
+                None => FileMode::Blob,
+            },
+        };
+        entries.insert(path, Some((oid, file_mode)));
+    }
+    if entries.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "No changes were selected. Nothing to amend."
+        )?;
+        return Ok(Err(ExitCode(0)));
+    }
+
+    let paths = entries.keys().cloned().sorted().collect_vec();
+    let amended_tree_oid = hydrate_tree(repo, Some(head_tree), entries)?;
+    Ok(Ok((repo.find_tree_or_fail(amended_tree_oid)?, paths)))
+}
+
+/// Amends the existing HEAD commit. If `patch` is set, the changes to amend it
+/// with are selected interactively.
 #[instrument]
 pub fn amend(
     effects: &Effects,
     git_run_info: &GitRunInfo,
+    patch: bool,
     move_options: &MoveOptions,
 ) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
@@ -86,27 +168,52 @@ pub fn amend(
         .filter(|entry| entry.working_copy_status.is_changed())
         .collect_vec();
 
-    let opts = if !staged_entries.is_empty() {
-        AmendFastOptions::FromIndex {
-            paths: staged_entries
-                .into_iter()
-                .flat_map(|entry| entry.paths())
-                .collect(),
+    let (amended_tree, source) = if patch {
+        if !staged_entries.is_empty() {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot select changes interactively while there are already staged changes."
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "Either commit or unstage your changes and try again. Aborting."
+            )?;
+            return Ok(ExitCode(1));
+        }
+        match select_amended_tree(
+            effects,
+            &repo,
+            &head_commit.get_tree()?,
+            &snapshot.commit_unstaged.get_tree()?,
+        )? {
+            Ok((amended_tree, paths)) => (amended_tree, AmendSource::Patch { paths }),
+            Err(exit_code) => return Ok(exit_code),
         }
     } else {
-        AmendFastOptions::FromWorkingCopy {
-            status_entries: unstaged_entries.clone(),
+        let opts = if !staged_entries.is_empty() {
+            AmendFastOptions::FromIndex {
+                paths: staged_entries
+                    .into_iter()
+                    .flat_map(|entry| entry.paths())
+                    .collect(),
+            }
+        } else {
+            AmendFastOptions::FromWorkingCopy {
+                status_entries: unstaged_entries.clone(),
+            }
+        };
+        if opts.is_empty() {
+            writeln!(
+                effects.get_output_stream(),
+                "There are no uncommitted or staged changes. Nothing to amend."
+            )?;
+            return Ok(ExitCode(0));
         }
+        (
+            repo.amend_fast(git_run_info, &head_commit, &opts)?,
+            AmendSource::Fast(opts),
+        )
     };
-    if opts.is_empty() {
-        writeln!(
-            effects.get_output_stream(),
-            "There are no uncommitted or staged changes. Nothing to amend."
-        )?;
-        return Ok(ExitCode(0));
-    }
-
-    let amended_tree = repo.amend_fast(&head_commit, &opts)?;
 
     let (author, committer) = (head_commit.get_author(), head_commit.get_committer());
     let (author, committer) = if get_restack_preserve_timestamps(&repo)? {
@@ -118,11 +225,38 @@ pub fn amend(
         )
     };
 
+    let (original_message, _is_lossy) = head_commit.get_message_decoded(None);
+    let message = match run_commit_hooks(
+        effects,
+        git_run_info,
+        &repo,
+        event_tx_id,
+        &amended_tree,
+        &original_message,
+    )? {
+        CommitHooksResult::Succeeded { message } => message,
+        CommitHooksResult::Failed { hook_name } => {
+            writeln!(
+                effects.get_output_stream(),
+                "Aborting amend because the {} hook failed.",
+                hook_name
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    // Only replace the message if the `commit-msg` hook changed it, so that
+    // messages which aren't stored as UTF-8 are otherwise kept as-is.
+    let message = if message == original_message {
+        None
+    } else {
+        Some(message.as_str())
+    };
+
     let amended_commit_oid = head_commit.amend_commit(
         Some("HEAD"),
         Some(&author),
         Some(&committer),
-        None,
+        message,
         Some(&amended_tree),
     )?;
     mark_commit_reachable(&repo, amended_commit_oid)
@@ -135,7 +269,9 @@ pub fn amend(
         new_commit_oid: amended_commit_oid.into(),
     }])?;
 
-    if let AmendFastOptions::FromWorkingCopy { .. } = opts {
+    if let AmendSource::Fast(AmendFastOptions::FromWorkingCopy { .. }) | AmendSource::Patch { .. } =
+        source
+    {
         // TODO(#201): Figure out a way to perform "fast amend" on the working copy without needing a reset.
         let exit_code = git_run_info.run(effects, Some(event_tx_id), &["reset"])?;
         if !exit_code.is_success() {
@@ -149,13 +285,14 @@ pub fn amend(
         vec![Revset(head_oid.to_string())],
         move_options,
         MergeConflictRemediation::Restack,
+        &mut RewriteSummary::default(),
     )?;
     if !restack_exit_code.is_success() {
         return Ok(restack_exit_code);
     }
 
-    match opts {
-        AmendFastOptions::FromIndex { paths } => {
+    match source {
+        AmendSource::Fast(AmendFastOptions::FromIndex { paths }) => {
             let staged_changes = Pluralize {
                 determiner: None,
                 amount: paths.len(),
@@ -168,7 +305,7 @@ pub fn amend(
             }
             writeln!(effects.get_output_stream(), "{}", message)?;
         }
-        AmendFastOptions::FromWorkingCopy { status_entries } => {
+        AmendSource::Fast(AmendFastOptions::FromWorkingCopy { status_entries }) => {
             let uncommitted_changes = Pluralize {
                 determiner: None,
                 amount: status_entries.len(),
@@ -180,6 +317,18 @@ pub fn amend(
                 uncommitted_changes,
             )?;
         }
+        AmendSource::Patch { paths } => {
+            let selected_changes = Pluralize {
+                determiner: None,
+                amount: paths.len(),
+                unit: ("selected change", "selected changes"),
+            };
+            writeln!(
+                effects.get_output_stream(),
+                "Amended with {}.",
+                selected_changes,
+            )?;
+        }
     }
     Ok(ExitCode(0))
 }
@@ -0,0 +1,155 @@
+//! Manage branches for the commits in a stack.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::printable_styled_string;
+use lib::core::metadata::CommitMetadataDb;
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::RewriteMap;
+use lib::core::stack_branches::{
+    apply_stack_branch_updates, get_stack_branch_user, get_stack_names_for_commits,
+    make_stack_name_from_summary, plan_stack_branch_updates, query_stack_commits, StackBranchesDb,
+};
+use lib::core::topics::STACK_NAME_METADATA_KEY;
+use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+/// Create or update a branch named `<user>/<stack-name>/<n>` for each commit in
+/// the current stack.
+#[instrument]
+pub fn branch(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    stack: bool,
+    name: Option<String>,
+    dry_run: bool,
+) -> eyre::Result<ExitCode> {
+    if !stack {
+        writeln!(
+            effects.get_error_stream(),
+            "Pass --stack to create a branch for each commit in the current stack."
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let now = SystemTime::now();
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let user = match get_stack_branch_user(&repo)? {
+        Some(user) => user,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Could not determine the user name for stack branches. Set branchless.stackBranches.user or user.email."
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    let stack_branches_db = StackBranchesDb::new(&conn)?;
+    let stack_names = stack_branches_db.get_stack_names(&user)?;
+    let stack_oids = query_stack_commits(&repo, &dag, dag.head_commit.clone())?;
+    let root_oid = match stack_oids.first() {
+        Some(root_oid) => *root_oid,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "HEAD is not in a stack of draft commits, so there are no commits to create branches for."
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    let stack_name = match name {
+        Some(name) => name,
+        None => {
+            let existing_stack_names =
+                get_stack_names_for_commits(&references_snapshot, &user, &stack_names, &stack_oids);
+            let metadata_db = CommitMetadataDb::new(&conn)?;
+            let rewrite_map = RewriteMap::from_event_replayer(&event_replayer, event_cursor);
+            let root_metadata = metadata_db.get(&rewrite_map, root_oid)?;
+            match existing_stack_names.into_iter().next() {
+                Some(stack_name) => stack_name,
+                None => match root_metadata.get(STACK_NAME_METADATA_KEY) {
+                    Some(stack_name) => stack_name.clone(),
+                    None => {
+                        let root_commit = repo.find_commit_or_fail(root_oid)?;
+                        make_stack_name_from_summary(&root_commit.get_summary()?.to_string())
+                    }
+                },
+            }
+        }
+    };
+    if stack_name.is_empty() {
+        writeln!(
+            effects.get_error_stream(),
+            "Could not determine a name for the stack. Pass --name to choose one."
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let updates = plan_stack_branch_updates(
+        &references_snapshot,
+        &user,
+        &stack_names,
+        &stack_name,
+        &stack_oids,
+    );
+    if updates.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Stack branches are already up to date."
+        )?;
+        return Ok(ExitCode(0));
+    }
+    for update in updates.iter() {
+        let branch_name = CategorizedReferenceName::new(&update.ref_name).render_suffix();
+        match (update.old_oid, update.new_oid) {
+            (_, MaybeZeroOid::Zero) => {
+                writeln!(effects.get_output_stream(), "Delete branch {}", branch_name)?;
+            }
+            (old_oid, MaybeZeroOid::NonZero(new_oid)) => {
+                let commit = repo.find_commit_or_fail(new_oid)?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "{} branch {} at {}",
+                    if old_oid == MaybeZeroOid::Zero {
+                        "Create"
+                    } else {
+                        "Move"
+                    },
+                    branch_name,
+                    printable_styled_string(
+                        effects.get_glyphs(),
+                        commit.friendly_describe(effects.get_glyphs())?
+                    )?
+                )?;
+            }
+        }
+    }
+    if dry_run {
+        return Ok(ExitCode(0));
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(now, "branch --stack")?;
+    apply_stack_branch_updates(effects, git_run_info, &repo, event_tx_id, &updates)?;
+    stack_branches_db.add_stack(&user, &stack_name)?;
+    Ok(ExitCode(0))
+}
@@ -0,0 +1,271 @@
+//! Get, set, and list `git-branchless` configuration settings in a given
+//! scope, checking keys and values against the registry of known settings.
+
+use std::fmt::Write;
+
+use lib::core::config::{find_config_key_info, ConfigKeyInfo, ConfigKeyType, CONFIG_KEYS};
+use lib::core::effects::Effects;
+use lib::git::{Config, ConfigRead, ConfigWrite, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use crate::opts::ConfigScope;
+
+/// All keys understood by `git-branchless` start with this prefix.
+const KEY_PREFIX: &str = "branchless.";
+
+/// Matches every `git-branchless` entry when listing a configuration file.
+const KEY_REGEX: &str = "^branchless\\.";
+
+/// Add the `branchless.` prefix to the key if it was omitted.
+fn normalize_key(key: &str) -> String {
+    if key.to_lowercase().starts_with(KEY_PREFIX) {
+        key.to_string()
+    } else {
+        format!("{}{}", KEY_PREFIX, key)
+    }
+}
+
+/// Look up the registry entry for the key, or print an error if the key isn't
+/// known.
+fn lookup_key(effects: &Effects, key: &str) -> eyre::Result<Option<&'static ConfigKeyInfo>> {
+    match find_config_key_info(key) {
+        Some(info) => Ok(Some(info)),
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Unknown configuration key: {}\nRun `git branchless config list --verbose` to see the known keys.",
+                key
+            )?;
+            Ok(None)
+        }
+    }
+}
+
+/// Open the configuration file for the given scope.
+fn open_scope(effects: &Effects, scope: ConfigScope) -> eyre::Result<Option<Config>> {
+    match scope {
+        ConfigScope::Repo => {
+            let repo = Repo::from_current_dir()?;
+            let config_path = repo.get_config_path();
+            if !config_path.exists() {
+                writeln!(
+                    effects.get_error_stream(),
+                    "The repository configuration file {:?} does not exist. Run `git branchless init` first.",
+                    config_path
+                )?;
+                return Ok(None);
+            }
+            Ok(Some(Config::open(&config_path)?))
+        }
+        ConfigScope::User => Ok(Some(Config::open_global()?)),
+        ConfigScope::System => Ok(Some(Config::open_system()?)),
+    }
+}
+
+/// Get the effective values of the key, taking all scopes into account.
+fn get_effective_values(
+    config: &impl ConfigRead,
+    info: &ConfigKeyInfo,
+    key: &str,
+) -> eyre::Result<Vec<String>> {
+    match info.value_type {
+        ConfigKeyType::MultiString => config.get_or(key, Vec::new()),
+        _ => {
+            let value: Option<String> = config.get(key)?;
+            Ok(value
+                .map(|value| info.value_type.validate(&value).unwrap_or(value))
+                .into_iter()
+                .collect())
+        }
+    }
+}
+
+/// List the configuration settings, either for a single scope or the
+/// effective settings across all scopes.
+#[instrument]
+pub fn list(
+    effects: &Effects,
+    scope: Option<ConfigScope>,
+    verbose: bool,
+) -> eyre::Result<ExitCode> {
+    let mut stdout = effects.get_output_stream();
+    match scope {
+        Some(scope) => {
+            let config = match open_scope(effects, scope)? {
+                Some(config) => config,
+                None => return Ok(ExitCode(1)),
+            };
+            for (key, value) in config.get_entries(KEY_REGEX)? {
+                writeln!(stdout, "{} = {}", key, value)?;
+            }
+        }
+
+        None => {
+            let repo = Repo::from_current_dir()?;
+            let config = repo.get_readonly_config()?.into_config();
+            let mut listed_keys = Vec::new();
+            for info in CONFIG_KEYS {
+                if info.key.ends_with('*') {
+                    continue;
+                }
+                listed_keys.push(info.key.to_lowercase());
+                let values = get_effective_values(&config, info, info.key)?;
+                match (values.as_slice(), info.default) {
+                    ([], None) => {
+                        if verbose {
+                            writeln!(stdout, "{} is not set", info.key)?;
+                        }
+                    }
+                    ([], Some(default)) => {
+                        writeln!(stdout, "{} = {} (default)", info.key, default)?;
+                    }
+                    (values, _) => {
+                        for value in values {
+                            writeln!(stdout, "{} = {}", info.key, value)?;
+                        }
+                    }
+                }
+                if verbose {
+                    writeln!(
+                        stdout,
+                        "    {} ({})",
+                        info.description,
+                        info.value_type.describe()
+                    )?;
+                }
+            }
+
+            for (key, value) in config.get_entries(KEY_REGEX)? {
+                if listed_keys.contains(&key) {
+                    continue;
+                }
+                match find_config_key_info(&key) {
+                    Some(_) => writeln!(stdout, "{} = {}", key, value)?,
+                    None => writeln!(stdout, "{} = {} (unknown key)", key, value)?,
+                }
+            }
+        }
+    }
+    Ok(ExitCode(0))
+}
+
+/// Print the effective value of the key, or its default value if unset.
+#[instrument]
+pub fn get(effects: &Effects, key: &str) -> eyre::Result<ExitCode> {
+    let key = normalize_key(key);
+    let info = match lookup_key(effects, &key)? {
+        Some(info) => info,
+        None => return Ok(ExitCode(1)),
+    };
+
+    let repo = Repo::from_current_dir()?;
+    let config = repo.get_readonly_config()?;
+    let values = get_effective_values(&config, info, &key)?;
+    match (values.as_slice(), info.default) {
+        ([], None) => Ok(ExitCode(1)),
+        ([], Some(default)) => {
+            writeln!(effects.get_output_stream(), "{}", default)?;
+            Ok(ExitCode(0))
+        }
+        (values, _) => {
+            for value in values {
+                writeln!(effects.get_output_stream(), "{}", value)?;
+            }
+            Ok(ExitCode(0))
+        }
+    }
+}
+
+/// Validate the values for the key and store them in the given scope.
+#[instrument]
+pub fn set(
+    effects: &Effects,
+    scope: ConfigScope,
+    key: &str,
+    values: &[String],
+) -> eyre::Result<ExitCode> {
+    let key = normalize_key(key);
+    let info = match lookup_key(effects, &key)? {
+        Some(info) => info,
+        None => return Ok(ExitCode(1)),
+    };
+    if values.len() > 1 && info.value_type != ConfigKeyType::MultiString {
+        writeln!(
+            effects.get_error_stream(),
+            "The key {} accepts only a single value, but got {}.",
+            key,
+            values.len()
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let mut validated_values = Vec::new();
+    for value in values {
+        match info.value_type.validate(value) {
+            Ok(value) => validated_values.push(value),
+            Err(message) => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Invalid value for {}: {}",
+                    key,
+                    message
+                )?;
+                return Ok(ExitCode(1));
+            }
+        }
+    }
+
+    let mut config = match open_scope(effects, scope)? {
+        Some(config) => config,
+        None => return Ok(ExitCode(1)),
+    };
+    match info.value_type {
+        ConfigKeyType::MultiString => {
+            config.remove_multivar(&key, ".*")?;
+            for value in validated_values {
+                config.set_multivar(&key, "^$", value)?;
+            }
+        }
+        ConfigKeyType::Bool => {
+            for value in validated_values {
+                config.set(&key, value == "true")?;
+            }
+        }
+        _ => {
+            for value in validated_values {
+                config.set(&key, value)?;
+            }
+        }
+    }
+    Ok(ExitCode(0))
+}
+
+/// Remove the key from the given scope.
+#[instrument]
+pub fn unset(effects: &Effects, scope: ConfigScope, key: &str) -> eyre::Result<ExitCode> {
+    let key = normalize_key(key);
+    if lookup_key(effects, &key)?.is_none() {
+        return Ok(ExitCode(1));
+    }
+
+    let mut config = match open_scope(effects, scope)? {
+        Some(config) => config,
+        None => return Ok(ExitCode(1)),
+    };
+    let lowercase_key = key.to_lowercase();
+    let is_set = config
+        .get_entries(KEY_REGEX)?
+        .into_iter()
+        .any(|(entry_key, _value)| entry_key == lowercase_key);
+    if !is_set {
+        writeln!(
+            effects.get_error_stream(),
+            "The key {} is not set in this scope.",
+            key
+        )?;
+        return Ok(ExitCode(1));
+    }
+    config.remove_multivar(&key, ".*")?;
+    Ok(ExitCode(0))
+}
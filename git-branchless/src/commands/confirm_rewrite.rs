@@ -0,0 +1,151 @@
+//! Ask for confirmation before carrying out very large rewrites, so that a
+//! mistyped revset (such as `git reword 'all()'`) doesn't rewrite far more
+//! commits or move far more branches than intended.
+
+use std::fmt::Write;
+use std::io::{stdin, BufRead};
+
+use itertools::Itertools;
+use lib::core::config::{
+    get_rewrite_confirm_branch_threshold, get_rewrite_confirm_commit_threshold,
+};
+use lib::core::dag::{commit_set_to_vec_unsorted, sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::formatting::{printable_styled_string, Pluralize};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{CategorizedReferenceName, Repo};
+use tracing::instrument;
+
+/// The maximum number of commits or branches to list when asking for
+/// confirmation.
+const MAX_LISTED_ITEMS: usize = 5;
+
+/// If rewriting the given commits would rewrite more commits or move more
+/// branches than configured, describe the rewrite and ask the user to confirm
+/// it.
+///
+/// Returns `true` if the rewrite should proceed. If the user declines, or
+/// there's no terminal to ask on, a message is printed and `false` is
+/// returned.
+#[instrument]
+pub fn confirm_large_rewrite(
+    effects: &Effects,
+    repo: &Repo,
+    dag: &Dag,
+    commits: &CommitSet,
+    skip_confirmation: bool,
+) -> eyre::Result<bool> {
+    if skip_confirmation {
+        return Ok(true);
+    }
+
+    let commit_threshold = get_rewrite_confirm_commit_threshold(repo)?;
+    let branch_threshold = get_rewrite_confirm_branch_threshold(repo)?;
+    let commit_oids = commit_set_to_vec_unsorted(commits)?;
+    let num_commits = commit_oids.len();
+    let mut branch_names = Vec::new();
+    for (oid, names) in repo.get_branch_oid_to_names()? {
+        if commits.contains(&oid.into())? {
+            branch_names.extend(
+                names
+                    .iter()
+                    .map(|name| CategorizedReferenceName::new(name).render_suffix()),
+            );
+        }
+    }
+    branch_names.sort_unstable();
+
+    let exceeded_threshold = if commit_threshold > 0 && num_commits > commit_threshold {
+        Some((
+            "branchless.rewrite.confirmCommitThreshold",
+            commit_threshold,
+        ))
+    } else if branch_threshold > 0 && branch_names.len() > branch_threshold {
+        Some((
+            "branchless.rewrite.confirmBranchThreshold",
+            branch_threshold,
+        ))
+    } else {
+        None
+    };
+    let (config_key, threshold) = match exceeded_threshold {
+        Some(exceeded_threshold) => exceeded_threshold,
+        None => return Ok(true),
+    };
+
+    let glyphs = effects.get_glyphs();
+    writeln!(
+        effects.get_output_stream(),
+        "This operation will rewrite {} and move {}:",
+        Pluralize {
+            determiner: None,
+            amount: num_commits,
+            unit: ("commit", "commits"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: branch_names.len(),
+            unit: ("branch", "branches"),
+        },
+    )?;
+    let listed_commits: CommitSet = commit_oids.into_iter().take(MAX_LISTED_ITEMS).collect();
+    for commit in sorted_commit_set(repo, dag, &listed_commits)? {
+        writeln!(
+            effects.get_output_stream(),
+            "  {}",
+            printable_styled_string(glyphs, commit.friendly_describe(glyphs)?)?
+        )?;
+    }
+    if num_commits > MAX_LISTED_ITEMS {
+        writeln!(
+            effects.get_output_stream(),
+            "  ...and {} more",
+            num_commits - MAX_LISTED_ITEMS
+        )?;
+    }
+    if !branch_names.is_empty() {
+        let mut listed_branches = branch_names.iter().take(MAX_LISTED_ITEMS).join(", ");
+        if branch_names.len() > MAX_LISTED_ITEMS {
+            listed_branches.push_str(&format!(
+                ", ...and {} more",
+                branch_names.len() - MAX_LISTED_ITEMS
+            ));
+        }
+        writeln!(
+            effects.get_output_stream(),
+            "Branches to move: {}",
+            listed_branches
+        )?;
+    }
+    // There's no one to answer the prompt when running from a script, so fail
+    // rather than waiting for input or treating the lack of it as a refusal.
+    if !console::user_attended() {
+        writeln!(
+            effects.get_output_stream(),
+            "This is more than {} ({}), and there's no terminal to confirm it on. Pass --yes to proceed anyway.",
+            config_key,
+            threshold
+        )?;
+        return Ok(false);
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "This is more than {} ({}). Pass --yes to skip this confirmation.",
+        config_key,
+        threshold
+    )?;
+
+    write!(effects.get_output_stream(), "Confirm? [yN] ")?;
+    let mut user_input = String::new();
+    let confirmed = match stdin().lock().read_line(&mut user_input) {
+        Ok(_size) => {
+            let user_input = user_input.trim();
+            user_input == "y" || user_input == "Y"
+        }
+        Err(_) => false,
+    };
+    if !confirmed {
+        writeln!(effects.get_output_stream(), "Aborted.")?;
+    }
+    Ok(confirmed)
+}
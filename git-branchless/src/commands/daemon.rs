@@ -0,0 +1,353 @@
+//! Run a long-lived process which keeps the rendered smartlog for the
+//! repository up-to-date, so that `git smartlog` can be served without
+//! recomputing it.
+//!
+//! The daemon listens on a Unix domain socket in the `.git/branchless`
+//! directory. Before answering a request, it checks whether the references,
+//! event log, or configuration have changed since the output was cached, so
+//! clients never receive stale output. If the daemon isn't running or doesn't
+//! respond, `git smartlog` falls back to rendering the smartlog itself.
+
+use std::time::Duration;
+
+use lib::core::effects::Effects;
+use lib::git::{GitRunInfo, Repo};
+use lib::util::ExitCode;
+
+/// The request sent by the client to get the smartlog rendered with the
+/// "pretty" glyphs, i.e. with Unicode characters and ANSI escape codes.
+///
+/// The client may follow it with the cache key of the glyphs and theme that
+/// it would render the smartlog with. If the daemon would render it with
+/// different ones (for example, because they're configured differently for
+/// the client), then the request fails.
+const REQUEST_SMARTLOG_PRETTY: &str = "smartlog pretty";
+
+/// The request sent by the client to get the smartlog rendered with the
+/// "text" glyphs. See `REQUEST_SMARTLOG_PRETTY`.
+const REQUEST_SMARTLOG_TEXT: &str = "smartlog text";
+
+/// The first line of the response when the request succeeded. The rendered
+/// output follows it.
+const RESPONSE_OK: &str = "ok";
+
+/// The first line of the response when the request failed.
+const RESPONSE_ERROR: &str = "error";
+
+/// How long the client waits for the daemon to respond before rendering the
+/// smartlog itself.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Ask the daemon for the smartlog for the repository, if one is running.
+///
+/// Returns `None` if there is no daemon or it couldn't answer the request, in
+/// which case the caller should render the smartlog itself.
+#[cfg(unix)]
+pub fn query_smartlog(effects: &Effects, repo: &Repo) -> Option<Vec<u8>> {
+    let socket_path = repo.get_daemon_socket_path();
+    if !socket_path.exists() {
+        return None;
+    }
+    match unix::query_smartlog(effects, &socket_path) {
+        Ok(output) => output,
+        Err(err) => {
+            tracing::warn!(?err, "Failed to query daemon for smartlog");
+            None
+        }
+    }
+}
+
+/// Ask the daemon for the smartlog for the repository, if one is running.
+///
+/// The daemon is only supported on Unix platforms, so this always returns
+/// `None`.
+#[cfg(not(unix))]
+pub fn query_smartlog(_effects: &Effects, _repo: &Repo) -> Option<Vec<u8>> {
+    None
+}
+
+/// Run the daemon until it's killed.
+#[cfg(unix)]
+pub fn daemon(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    poll_interval: Duration,
+) -> eyre::Result<ExitCode> {
+    unix::daemon(effects, git_run_info, poll_interval)
+}
+
+/// Run the daemon until it's killed.
+#[cfg(not(unix))]
+pub fn daemon(
+    effects: &Effects,
+    _git_run_info: &GitRunInfo,
+    _poll_interval: Duration,
+) -> eyre::Result<ExitCode> {
+    use std::fmt::Write;
+
+    writeln!(
+        effects.get_error_stream(),
+        "The daemon is only supported on Unix platforms."
+    )?;
+    Ok(ExitCode(1))
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::collections::HashMap;
+    use std::fmt::Write as FmtWrite;
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use eyre::Context;
+    use lib::core::config::{get_color_theme, get_smartlog_glyphs};
+    use lib::core::effects::Effects;
+    use lib::core::formatting::Glyphs;
+    use lib::git::{GitRunInfo, Repo};
+    use lib::util::ExitCode;
+    use tracing::warn;
+
+    use super::{
+        CLIENT_TIMEOUT, REQUEST_SMARTLOG_PRETTY, REQUEST_SMARTLOG_TEXT, RESPONSE_ERROR, RESPONSE_OK,
+    };
+    use crate::commands::smartlog::{
+        get_glyphs_cache_key, render_smartlog, RepoStateFingerprint, SmartlogOptions, MAX_CACHE_AGE,
+    };
+
+    #[derive(Debug)]
+    struct CachedSmartlog {
+        fingerprint: RepoStateFingerprint,
+        rendered_at: Instant,
+        output: Vec<u8>,
+    }
+
+    struct Daemon<'a> {
+        git_run_info: &'a GitRunInfo,
+
+        /// The cached output, keyed by the glyphs and theme which it was
+        /// rendered with (see `get_glyphs_cache_key`).
+        cache: HashMap<String, CachedSmartlog>,
+    }
+
+    impl Daemon<'_> {
+        /// Get the rendered smartlog, re-rendering it if the repository has
+        /// changed since it was last rendered.
+        ///
+        /// The glyphs and theme are read from the configuration for each
+        /// request, since they may have changed since the daemon started. If
+        /// `expected_glyphs_key` is provided and doesn't match them, then
+        /// returns `None`, so that the client renders the smartlog itself.
+        fn get_smartlog(
+            &mut self,
+            pretty: bool,
+            expected_glyphs_key: Option<&str>,
+        ) -> eyre::Result<Option<&[u8]>> {
+            let repo = Repo::from_dir(&self.git_run_info.working_directory)?;
+            let glyphs = get_smartlog_glyphs(
+                &repo,
+                if pretty {
+                    Glyphs::pretty()
+                } else {
+                    Glyphs::text()
+                },
+            )?;
+            let glyphs = if pretty {
+                glyphs.with_theme(get_color_theme(&repo)?)
+            } else {
+                glyphs
+            };
+            let glyphs_key = get_glyphs_cache_key(&glyphs);
+            if let Some(expected_glyphs_key) = expected_glyphs_key {
+                if expected_glyphs_key != glyphs_key {
+                    return Ok(None);
+                }
+            }
+
+            let fingerprint = RepoStateFingerprint::new(&repo)?;
+            let is_fresh = match self.cache.get(&glyphs_key) {
+                Some(cached) => {
+                    cached.fingerprint == fingerprint
+                        && cached.rendered_at.elapsed() < MAX_CACHE_AGE
+                }
+                None => false,
+            };
+            if !is_fresh {
+                self.cache.remove(&glyphs_key);
+                if let Some(output) = self.render(glyphs)? {
+                    self.cache.insert(
+                        glyphs_key.clone(),
+                        CachedSmartlog {
+                            fingerprint,
+                            rendered_at: Instant::now(),
+                            output,
+                        },
+                    );
+                }
+            }
+            Ok(self
+                .cache
+                .get(&glyphs_key)
+                .map(|cached| cached.output.as_slice()))
+        }
+
+        fn render(&self, glyphs: Glyphs) -> eyre::Result<Option<Vec<u8>>> {
+            let stdout: Arc<Mutex<Vec<u8>>> = Default::default();
+            let stderr: Arc<Mutex<Vec<u8>>> = Default::default();
+            let effects = Effects::new_from_buffer(glyphs, &stdout, &stderr);
+            let exit_code =
+                render_smartlog(&effects, self.git_run_info, &SmartlogOptions::default())?;
+            if exit_code.is_success() {
+                let output = stdout.lock().unwrap().clone();
+                Ok(Some(output))
+            } else {
+                Ok(None)
+            }
+        }
+
+        fn handle_connection(&mut self, stream: UnixStream) -> eyre::Result<()> {
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            reader.read_line(&mut request)?;
+
+            let (request_kind, expected_glyphs_key) = match request.trim_end().rsplit_once(' ') {
+                Some((request_kind, glyphs_key))
+                    if request_kind == REQUEST_SMARTLOG_PRETTY
+                        || request_kind == REQUEST_SMARTLOG_TEXT =>
+                {
+                    (request_kind, Some(glyphs_key))
+                }
+                _ => (request.trim_end(), None),
+            };
+            let pretty = match request_kind {
+                REQUEST_SMARTLOG_PRETTY => Some(true),
+                REQUEST_SMARTLOG_TEXT => Some(false),
+                _ => None,
+            };
+            let output = match pretty {
+                Some(pretty) => match self.get_smartlog(pretty, expected_glyphs_key) {
+                    Ok(output) => output,
+                    Err(err) => {
+                        warn!(?err, "Failed to render smartlog");
+                        None
+                    }
+                },
+                None => {
+                    warn!(?request, "Unknown daemon request");
+                    None
+                }
+            };
+
+            let mut stream = &stream;
+            match output {
+                Some(output) => {
+                    writeln!(stream, "{}", RESPONSE_OK)?;
+                    stream.write_all(output)?;
+                }
+                None => {
+                    writeln!(stream, "{}", RESPONSE_ERROR)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn query_smartlog(effects: &Effects, socket_path: &Path) -> eyre::Result<Option<Vec<u8>>> {
+        let mut stream = UnixStream::connect(socket_path)
+            .wrap_err_with(|| format!("Connecting to daemon socket at {:?}", socket_path))?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+        let glyphs = effects.get_glyphs();
+        let request = if glyphs.should_write_ansi_escape_codes {
+            REQUEST_SMARTLOG_PRETTY
+        } else {
+            REQUEST_SMARTLOG_TEXT
+        };
+        let glyphs_key = get_glyphs_cache_key(glyphs);
+        writeln!(stream, "{} {}", request, glyphs_key)?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status)?;
+        if status.trim_end() != RESPONSE_OK {
+            return Ok(None);
+        }
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output)?;
+        Ok(Some(output))
+    }
+
+    pub fn daemon(
+        effects: &Effects,
+        git_run_info: &GitRunInfo,
+        poll_interval: Duration,
+    ) -> eyre::Result<ExitCode> {
+        let repo = Repo::from_dir(&git_run_info.working_directory)?;
+        let socket_path = repo.get_daemon_socket_path();
+        if socket_path.exists() {
+            if UnixStream::connect(&socket_path).is_ok() {
+                writeln!(
+                    effects.get_error_stream(),
+                    "A daemon is already running for this repository."
+                )?;
+                return Ok(ExitCode(1));
+            }
+
+            // The socket was left behind by a daemon which didn't exit
+            // cleanly.
+            std::fs::remove_file(&socket_path)
+                .wrap_err_with(|| format!("Removing stale socket at {:?}", &socket_path))?;
+        }
+        if let Some(parent) = socket_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let listener = UnixListener::bind(&socket_path)
+            .wrap_err_with(|| format!("Binding daemon socket at {:?}", &socket_path))?;
+        listener.set_nonblocking(true)?;
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: daemon listening on {}",
+            socket_path.display()
+        )?;
+
+        let mut daemon = Daemon {
+            git_run_info,
+            cache: HashMap::new(),
+        };
+        let mut last_poll: Option<Instant> = None;
+        loop {
+            match listener.accept() {
+                Ok((stream, _addr)) => {
+                    if let Err(err) = daemon.handle_connection(stream) {
+                        warn!(?err, "Failed to handle daemon request");
+                    }
+                }
+
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    let should_poll = match last_poll {
+                        Some(last_poll) => last_poll.elapsed() >= poll_interval,
+                        None => true,
+                    };
+                    if should_poll {
+                        // Keep the cache warm so that the next request doesn't
+                        // have to wait for the smartlog to be rendered.
+                        for pretty in [false, true] {
+                            if let Err(err) = daemon.get_smartlog(pretty, None) {
+                                warn!(?err, "Failed to render smartlog");
+                            }
+                        }
+                        last_poll = Some(Instant::now());
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+
+                Err(err) => return Err(err).wrap_err("Accepting daemon connection"),
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Show the combined changes made by a set of commits, such as a whole stack.
+
+use std::fmt::Write;
+
+use eden_dag::DagAlgorithm;
+use lib::core::config::get_word_diff;
+use lib::core::dag::{commit_set_to_vec_unsorted, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::{printable_styled_string, render_patch};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{GitRunInfo, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use crate::opts::Revset;
+use crate::revset::resolve_commits;
+
+/// How to render the diff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// Show the full patch.
+    Patch,
+
+    /// Show the number of lines changed in each file.
+    Stat,
+
+    /// Show only the paths of the changed files.
+    NameOnly,
+}
+
+/// Show the combined diff of the commits in the given revset, from the parent
+/// of its root commit to its head commit.
+#[instrument]
+pub fn diff(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revset: Revset,
+    format: DiffFormat,
+    word_diff: bool,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let word_diff = word_diff || get_word_diff(&repo)?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commits =
+        match resolve_commits(effects, git_run_info, &repo, &mut dag, vec![revset.clone()]) {
+            Ok(commit_sets) => commit_sets[0].clone(),
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+
+    let root_oid =
+        match commit_set_to_vec_unsorted(&dag.query().roots(commits.clone())?)?.as_slice() {
+            [] => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Revset did not contain any commits: {}",
+                    revset.0
+                )?;
+                return Ok(ExitCode(1));
+            }
+            [root_oid] => *root_oid,
+            root_oids => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Revset must have exactly 1 root commit to diff against (got {}): {}",
+                    root_oids.len(),
+                    revset.0
+                )?;
+                return Ok(ExitCode(1));
+            }
+        };
+    let head_oid = match commit_set_to_vec_unsorted(&dag.query().heads(commits)?)?.as_slice() {
+        [head_oid] => *head_oid,
+        head_oids => {
+            writeln!(
+                effects.get_error_stream(),
+                "Revset must have exactly 1 head commit to diff (got {}): {}",
+                head_oids.len(),
+                revset.0
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    let root_commit = repo.find_commit_or_fail(root_oid)?;
+    let root_parents = root_commit.get_parents();
+    let old_tree = match root_parents.as_slice() {
+        [] => None,
+        [parent] => Some(parent.get_tree()?),
+        _ => {
+            writeln!(
+                effects.get_error_stream(),
+                "Cannot diff against the root commit {}, since it is a merge commit.",
+                root_oid
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let head_commit = repo.find_commit_or_fail(head_oid)?;
+    let new_tree = head_commit.get_tree()?;
+    let diff = repo.get_diff_between_trees(effects, old_tree.as_ref(), &new_tree, 3)?;
+
+    match format {
+        DiffFormat::Patch => {
+            let patch = diff.get_patch()?;
+            for line in render_patch(&String::from_utf8_lossy(&patch), word_diff) {
+                writeln!(
+                    effects.get_output_stream(),
+                    "{}",
+                    printable_styled_string(effects.get_glyphs(), line)?
+                )?;
+            }
+        }
+        DiffFormat::Stat => {
+            write!(effects.get_output_stream(), "{}", diff.get_stat(80)?)?;
+        }
+        DiffFormat::NameOnly => {
+            for path in diff.get_changed_paths() {
+                writeln!(effects.get_output_stream(), "{}", path.display())?;
+            }
+        }
+    }
+    Ok(ExitCode(0))
+}
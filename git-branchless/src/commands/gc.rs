@@ -1,20 +1,38 @@
 //! Invokes git-branchless's garbage-collection mechanisms.
 
 use std::fmt::Write;
+use std::time::SystemTime;
 
-use lib::core::gc::find_dangling_references;
+use lib::core::gc::{
+    find_dangling_references, find_prunable_references, free_compacted_commits, run_maintenance,
+};
 use tracing::instrument;
 
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
-use lib::core::formatting::Pluralize;
-use lib::git::Repo;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::formatting::{printable_styled_string, Pluralize};
+use lib::core::patch_ids::PatchIdDb;
+use lib::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+
+use crate::opts::PruneWindow;
 
 /// Run branchless's garbage collection.
 ///
-/// Frees any references to commits which are no longer visible in the smartlog.
+/// Frees any references to commits which are no longer visible in the smartlog,
+/// and then performs any configured maintenance on Git's own data structures.
+/// If `prune` is set, only the references to commits which were hidden longer
+/// ago than the window are freed, and the freed commits are listed and
+/// recorded in the event log. Stale entries in the patch ID cache, and those
+/// for commits which are no longer reachable, are also removed, as are the
+/// records of merge conflicts for commits which are no longer in the
+/// repository.
 #[instrument]
-pub fn gc(effects: &Effects) -> eyre::Result<()> {
+pub fn gc(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    prune: Option<PruneWindow>,
+) -> eyre::Result<()> {
+    let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
@@ -25,10 +43,34 @@ pub fn gc(effects: &Effects) -> eyre::Result<()> {
         effects.get_output_stream(),
         "branchless: collecting garbage"
     )?;
-    let dangling_references = find_dangling_references(&repo, &event_replayer, event_cursor)?;
+    let prune_window = prune.map(|PruneWindow(window)| (now, window));
+    let dangling_references = match prune_window {
+        None => find_dangling_references(&repo, &event_replayer, event_cursor)?,
+        Some((now, window)) => {
+            find_prunable_references(&repo, &event_replayer, event_cursor, now, window)?
+        }
+    };
+    let mut freed_commit_oids = Vec::new();
+    for reference in dangling_references.iter() {
+        if let Some(commit) = reference.peel_to_commit()? {
+            freed_commit_oids.push(commit.get_oid());
+        }
+    }
+    // A commit may be kept alive both by its own reference and by the
+    // compacted reference.
+    for commit_oid in free_compacted_commits(&repo, &event_replayer, event_cursor, prune_window)? {
+        if !freed_commit_oids.contains(&commit_oid) {
+            freed_commit_oids.push(commit_oid);
+        }
+    }
+    if prune.is_some() {
+        describe_pruned_commits(effects, &repo, &freed_commit_oids)?;
+        record_pruned_commits(&event_log_db, now, &freed_commit_oids)?;
+    }
+
     let num_dangling_references = Pluralize {
         determiner: None,
-        amount: dangling_references.len(),
+        amount: freed_commit_oids.len(),
         unit: ("dangling reference", "dangling references"),
     }
     .to_string();
@@ -41,5 +83,84 @@ pub fn gc(effects: &Effects) -> eyre::Result<()> {
         "branchless: {} deleted",
         num_dangling_references,
     )?;
+
+    let patch_id_db = PatchIdDb::new(&conn)?;
+    patch_id_db.prune_stale()?;
+    patch_id_db.prune_unreachable(&repo, &freed_commit_oids)?;
+    event_log_db.prune_merge_conflicts(&repo)?;
+    run_maintenance(effects, git_run_info, &repo)?;
+    Ok(())
+}
+
+/// Handle Git's automatic garbage collection.
+///
+/// Git runs this when it decides to collect garbage on its own, so the
+/// references keeping hidden commits alive are left alone; only `git branchless
+/// gc` removes those. Maintenance is still performed.
+#[instrument]
+pub fn hook_pre_auto_gc(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<()> {
+    let repo = Repo::from_current_dir()?;
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: keeping hidden commits; run `git branchless gc --prune=<window>` to free them"
+    )?;
+    run_maintenance(effects, git_run_info, &repo)?;
+    Ok(())
+}
+
+/// List the commits which will no longer be recoverable once they're no
+/// longer kept alive.
+fn describe_pruned_commits(
+    effects: &Effects,
+    repo: &Repo,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<()> {
+    if commit_oids.is_empty() {
+        return Ok(());
+    }
+
+    let glyphs = effects.get_glyphs();
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: these hidden commits can no longer be restored with `git undo`:"
+    )?;
+    for commit_oid in commit_oids {
+        if let Some(commit) = repo.find_commit(*commit_oid)? {
+            writeln!(
+                effects.get_output_stream(),
+                "  {}",
+                printable_styled_string(glyphs, commit.friendly_describe(glyphs)?)?
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Record the removal of the references keeping the given commits alive in the
+/// event log, so that it's possible to audit which commits were pruned and
+/// when.
+fn record_pruned_commits(
+    event_log_db: &EventLogDb,
+    now: SystemTime,
+    commit_oids: &[NonZeroOid],
+) -> eyre::Result<()> {
+    if commit_oids.is_empty() {
+        return Ok(());
+    }
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let event_tx_id = event_log_db.make_transaction_id(now, "gc --prune")?;
+    let events = commit_oids
+        .iter()
+        .map(|commit_oid| Event::RefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name: format!("refs/branchless/{}", commit_oid).into(),
+            old_oid: MaybeZeroOid::NonZero(*commit_oid),
+            new_oid: MaybeZeroOid::Zero,
+            message: None,
+        })
+        .collect();
+    event_log_db.add_events(events)?;
     Ok(())
 }
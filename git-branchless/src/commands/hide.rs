@@ -14,10 +14,11 @@ use lib::core::dag::{sorted_commit_set, union_all, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{CommitActivityStatus, Event};
 use lib::core::eventlog::{EventLogDb, EventReplayer};
-use lib::core::formatting::{printable_styled_string, Glyphs, Pluralize};
+use lib::core::formatting::{printable_styled_string, Glyphs};
 use lib::core::rewrite::move_branches;
 use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 
+use crate::commands::smartlog::render_dry_run_smartlog;
 use crate::opts::Revset;
 use crate::revset::resolve_commits;
 
@@ -29,6 +30,7 @@ pub fn hide(
     revsets: Vec<Revset>,
     delete_branches: bool,
     recursive: bool,
+    dry_run: bool,
 ) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
     let glyphs = Glyphs::detect();
@@ -46,7 +48,7 @@ pub fn hide(
         &references_snapshot,
     )?;
 
-    let commit_sets = match resolve_commits(effects, &repo, &mut dag, revsets) {
+    let commit_sets = match resolve_commits(effects, git_run_info, &repo, &mut dag, revsets) {
         Ok(commit_sets) => commit_sets,
         Err(err) => {
             err.describe(effects)?;
@@ -65,6 +67,18 @@ pub fn hide(
     let commits = dag.query().sort(&commits)?;
     let commits = sorted_commit_set(&repo, &dag, &commits)?;
 
+    if dry_run {
+        let hidden_oids = commits.iter().map(|commit| commit.get_oid()).collect();
+        render_dry_run_smartlog(
+            effects,
+            &repo,
+            &HashMap::new(),
+            &hidden_oids,
+            delete_branches,
+        )?;
+        return Ok(ExitCode(0));
+    }
+
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "hide")?;
     let events = commits
@@ -82,15 +96,22 @@ pub fn hide(
     for commit in commits.iter() {
         writeln!(
             effects.get_output_stream(),
-            "Hid commit: {}",
-            printable_styled_string(&glyphs, commit.friendly_describe(&glyphs)?)?,
+            "{}",
+            effects.tr(
+                "hide-hid-commit",
+                &[(
+                    "commit",
+                    printable_styled_string(&glyphs, commit.friendly_describe(&glyphs)?)?.into()
+                )]
+            ),
         )?;
         if let CommitActivityStatus::Obsolete =
             event_replayer.get_cursor_commit_activity_status(cursor, commit.get_oid())
         {
             writeln!(
                 effects.get_output_stream(),
-                "(It was already hidden, so this operation had no effect.)"
+                "{}",
+                effects.tr("hide-already-hidden", &[])
             )?;
         }
     }
@@ -136,52 +157,47 @@ pub fn hide(
         // Deleted X branches: <branches>
         writeln!(
             effects.get_output_stream(),
-            "{} {}: {}",
-            if delete_branches {
-                "Deleted"
-            } else {
-                "Abandoned"
-            },
-            Pluralize {
-                determiner: None,
-                amount: abandoned_branches.len(),
-                unit: ("branch", "branches"),
-            },
-            abandoned_branches.join(", ")
+            "{}",
+            effects.tr(
+                if delete_branches {
+                    "hide-deleted-branches"
+                } else {
+                    "hide-abandoned-branches"
+                },
+                &[
+                    ("count", abandoned_branches.len().into()),
+                    ("branches", abandoned_branches.join(", ").into()),
+                ]
+            ),
         )?;
     }
 
     // This message will look like either of these:
     // To unhide these X commits, run: git undo
     // To unhide these X commits and restore X branches, run: git undo
-    let delete_branches_message = match delete_branches {
-        true => format!(
-            " and restore {}",
-            Pluralize {
-                determiner: None,
-                amount: abandoned_branches.len(),
-                unit: ("branch", "branches"),
-            }
+    let undo_hint = match delete_branches {
+        true => effects.tr(
+            "hide-undo-hint-with-branches",
+            &[
+                ("count", num_commits.into()),
+                ("branch-count", abandoned_branches.len().into()),
+            ],
         ),
-        false => String::new(),
+        false => effects.tr("hide-undo-hint", &[("count", num_commits.into())]),
     };
-    writeln!(
-        effects.get_output_stream(),
-        "To unhide {}{}, run: git undo",
-        Pluralize {
-            determiner: Some(("this", "these")),
-            amount: num_commits,
-            unit: ("commit", "commits"),
-        },
-        delete_branches_message
-    )?;
+    writeln!(effects.get_output_stream(), "{}", undo_hint)?;
 
     Ok(ExitCode(0))
 }
 
 /// Unhide the hashes provided on the command-line.
 #[instrument]
-pub fn unhide(effects: &Effects, revsets: Vec<Revset>, recursive: bool) -> eyre::Result<ExitCode> {
+pub fn unhide(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revsets: Vec<Revset>,
+    recursive: bool,
+) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
     let glyphs = Glyphs::detect();
     let repo = Repo::from_current_dir()?;
@@ -198,7 +214,7 @@ pub fn unhide(effects: &Effects, revsets: Vec<Revset>, recursive: bool) -> eyre:
         &references_snapshot,
     )?;
 
-    let commit_sets = match resolve_commits(effects, &repo, &mut dag, revsets) {
+    let commit_sets = match resolve_commits(effects, git_run_info, &repo, &mut dag, revsets) {
         Ok(commit_sets) => commit_sets,
         Err(err) => {
             err.describe(effects)?;
@@ -234,27 +250,30 @@ pub fn unhide(effects: &Effects, revsets: Vec<Revset>, recursive: bool) -> eyre:
     for commit in commits {
         writeln!(
             effects.get_output_stream(),
-            "Unhid commit: {}",
-            printable_styled_string(&glyphs, commit.friendly_describe(&glyphs)?)?,
+            "{}",
+            effects.tr(
+                "unhide-unhid-commit",
+                &[(
+                    "commit",
+                    printable_styled_string(&glyphs, commit.friendly_describe(&glyphs)?)?.into()
+                )]
+            ),
         )?;
         if let CommitActivityStatus::Active =
             event_replayer.get_cursor_commit_activity_status(cursor, commit.get_oid())
         {
             writeln!(
                 effects.get_output_stream(),
-                "(It was not hidden, so this operation had no effect.)"
+                "{}",
+                effects.tr("unhide-not-hidden", &[])
             )?;
         }
     }
 
     writeln!(
         effects.get_output_stream(),
-        "To hide {}, run: git undo",
-        Pluralize {
-            determiner: Some(("this", "these")),
-            amount: num_commits,
-            unit: ("commit", "commits"),
-        },
+        "{}",
+        effects.tr("unhide-undo-hint", &[("count", num_commits.into())])
     )?;
 
     Ok(ExitCode(0))
@@ -15,16 +15,76 @@ use eyre::Context;
 use itertools::Itertools;
 use tracing::{error, instrument, warn};
 
-use lib::core::eventlog::{should_ignore_ref_updates, Event, EventLogDb};
+use lib::core::eventlog::{Event, EventLogDb, RefUpdateFilter};
 use lib::core::formatting::{printable_styled_string, Glyphs, Pluralize};
 use lib::core::gc::mark_commit_reachable;
 use lib::git::{CategorizedReferenceName, MaybeZeroOid, ReferenceName, Repo};
 
 use lib::core::effects::Effects;
+use lib::core::rewrite::rewrite_hooks::make_restack_command;
 pub use lib::core::rewrite::rewrite_hooks::{
-    hook_drop_commit_if_empty, hook_post_rewrite, hook_register_extra_post_rewrite_hook,
+    hook_drop_commit_if_empty, hook_register_extra_post_rewrite_hook, hook_skip_folded_commit,
     hook_skip_upstream_applied_commit,
 };
+use lib::core::rewrite::{rewrite_hooks, MergeConflictRemediation, RewriteSummary};
+use lib::git::GitRunInfo;
+
+use crate::commands::restack;
+use crate::opts::{MoveOptions, Revset};
+
+/// Handle Git's `post-rewrite` hook.
+///
+/// If `branchless.restack.auto` is set, then the commits abandoned by the
+/// rewrite are restacked afterwards. This is only done in-memory, so that the
+/// working copy isn't touched while Git is still running. If that's not
+/// possible, such as because of merge conflicts, then a hint with the command
+/// to restack them is printed instead.
+///
+/// See the man-page for `githooks(5)`.
+#[instrument]
+pub fn hook_post_rewrite(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    rewrite_type: &str,
+) -> eyre::Result<()> {
+    let rewritten_commit_oids =
+        rewrite_hooks::hook_post_rewrite(effects, git_run_info, rewrite_type)?;
+    if rewritten_commit_oids.is_empty() {
+        return Ok(());
+    }
+
+    let move_options = MoveOptions {
+        force_rewrite_public_commits: false,
+        force_rewrite_protected_branches: false,
+        force_in_memory: true,
+        force_on_disk: false,
+        detect_duplicate_commits_via_patch_id: true,
+        resolve_merge_conflicts: false,
+        skip_confirmation: true,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+    };
+    let exit_code = restack::restack(
+        effects,
+        git_run_info,
+        rewritten_commit_oids
+            .iter()
+            .map(|oid| Revset(oid.to_string()))
+            .collect(),
+        &move_options,
+        MergeConflictRemediation::Restack,
+        &mut RewriteSummary::default(),
+    )?;
+    if !exit_code.is_success() {
+        let repo = Repo::from_current_dir()?;
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: Could not restack the abandoned commits automatically. To restack, run: {}",
+            make_restack_command(&repo, &rewritten_commit_oids)?
+        )?;
+    }
+    Ok(())
+}
 
 /// Handle Git's `post-checkout` hook.
 ///
@@ -136,95 +196,13 @@ pub fn hook_post_merge(effects: &Effects, _is_squash_merge: isize) -> eyre::Resu
 
 mod reference_transaction {
     use std::collections::HashMap;
-    use std::fs::File;
-    use std::io::{BufRead, BufReader};
     use std::str::FromStr;
 
-    use eyre::Context;
     use itertools::Itertools;
-    use lazy_static::lazy_static;
-    use tracing::{instrument, warn};
+    use tracing::instrument;
 
     use lib::git::{MaybeZeroOid, ReferenceName, Repo};
 
-    #[instrument]
-    fn parse_packed_refs_line(line: &str) -> Option<(ReferenceName, MaybeZeroOid)> {
-        if line.is_empty() {
-            return None;
-        }
-        if line.starts_with('#') {
-            // The leading `# pack-refs with:` pragma.
-            return None;
-        }
-        if !line.starts_with(|c: char| c.is_ascii_hexdigit()) {
-            // The leading `# pack-refs with:` pragma.
-            warn!(?line, "Unrecognized pack-refs line starting character");
-            return None;
-        }
-
-        lazy_static! {
-            static ref RE: regex::Regex = regex::Regex::new(r"^([^ ]+) (.+)$").unwrap();
-        };
-        match RE.captures(line) {
-            None => {
-                warn!(?line, "No regex match for pack-refs line");
-                None
-            }
-
-            Some(captures) => {
-                let oid = &captures[1];
-                let oid = match MaybeZeroOid::from_str(oid) {
-                    Ok(oid) => oid,
-                    Err(err) => {
-                        warn!(?oid, ?err, "Could not parse OID for pack-refs line");
-                        return None;
-                    }
-                };
-
-                let reference_name = &captures[2];
-                let reference_name = ReferenceName::from(reference_name);
-
-                Some((reference_name, oid))
-            }
-        }
-    }
-
-    #[cfg(test)]
-    #[test]
-    fn test_parse_packed_refs_line() {
-        use super::*;
-
-        let line = "1234567812345678123456781234567812345678 refs/foo/bar";
-        let name = ReferenceName::from("refs/foo/bar");
-        let oid = MaybeZeroOid::from_str("1234567812345678123456781234567812345678").unwrap();
-        assert_eq!(parse_packed_refs_line(line), Some((name, oid)));
-    }
-
-    #[instrument]
-    pub fn read_packed_refs_file(
-        repo: &Repo,
-    ) -> eyre::Result<HashMap<ReferenceName, MaybeZeroOid>> {
-        let packed_refs_file_path = repo.get_packed_refs_path();
-        let file = match File::open(&packed_refs_file_path) {
-            Ok(file) => file,
-            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
-            Err(err) => return Err(err.into()),
-        };
-
-        let reader = BufReader::new(file);
-        let mut result = HashMap::new();
-        for line in reader.lines() {
-            let line = line.wrap_err("Reading line from packed-refs")?;
-            if line.is_empty() {
-                continue;
-            }
-            if let Some((k, v)) = parse_packed_refs_line(&line) {
-                result.insert(k, v);
-            }
-        }
-        Ok(result)
-    }
-
     #[derive(Debug, PartialEq, Eq)]
     pub struct ParsedReferenceTransactionLine {
         pub ref_name: ReferenceName,
@@ -362,13 +340,15 @@ mod reference_transaction {
 #[instrument]
 pub fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyre::Result<()> {
     use reference_transaction::{
-        fix_packed_reference_oid, parse_reference_transaction_line, read_packed_refs_file,
-        ParsedReferenceTransactionLine,
+        fix_packed_reference_oid, parse_reference_transaction_line, ParsedReferenceTransactionLine,
     };
 
     if transaction_state != "committed" {
         return Ok(());
     }
+    // The references have already been updated, so the events must be
+    // recorded even if the user presses Ctrl-C.
+    let _interrupt_guard = effects.catch_interrupts()?;
     let now = SystemTime::now();
 
     let repo = Repo::from_current_dir()?;
@@ -376,7 +356,8 @@ pub fn hook_reference_transaction(effects: &Effects, transaction_state: &str) ->
     let event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(now, "reference-transaction")?;
 
-    let packed_references = read_packed_refs_file(&repo)?;
+    let packed_references = repo.get_reference_backend()?.get_packed_references()?;
+    let ref_update_filter = RefUpdateFilter::from_repo(&repo)?;
 
     let parsed_lines: Vec<ParsedReferenceTransactionLine> = stdin()
         .lock()
@@ -406,7 +387,7 @@ pub fn hook_reference_transaction(effects: &Effects, transaction_state: &str) ->
                  ref_name,
                  old_oid: _,
                  new_oid: _,
-             }| !should_ignore_ref_updates(ref_name),
+             }| !ref_update_filter.should_ignore(ref_name),
         )
         .map(|parsed_line| fix_packed_reference_oid(&repo, &packed_references, parsed_line))
         .collect();
@@ -4,15 +4,23 @@ use std::fmt::Write;
 use std::io::{stdin, stdout, BufRead, BufReader, Write as WriteIo};
 use std::path::{Path, PathBuf};
 
-use console::style;
+use cursive::utils::markup::StyledString;
 use eyre::Context;
 use path_slash::PathExt;
 use tracing::{instrument, warn};
 
-use crate::opts::write_man_pages;
-use lib::core::config::{get_core_hooks_path, get_default_branch_name};
+use crate::opts::{write_man_pages, HookType};
+use lib::core::config::{
+    get_ci_status_command, get_core_hooks_path, get_default_branch_name,
+    get_shared_hook_script_path,
+};
 use lib::core::effects::Effects;
-use lib::git::{BranchType, Config, ConfigRead, ConfigWrite, GitRunInfo, GitVersion, Repo};
+use lib::core::formatting::printable_styled_string;
+use lib::git::{
+    BranchType, CategorizedReferenceName, Config, ConfigRead, ConfigWrite, GitRunInfo, GitVersion,
+    Repo,
+};
+use lib::util::NATIVE_HOOK_MARKER;
 
 pub const ALL_HOOKS: &[(&str, &str)] = &[
     (
@@ -164,8 +172,27 @@ fn write_script(path: &Path, contents: &str) -> eyre::Result<()> {
     Ok(())
 }
 
+/// If the hook at the given path is a symlink (such as one installed with
+/// `--hook-type symlink`), remove it, so that writing the hook doesn't
+/// overwrite the shared hook script.
+fn remove_hook_symlink(path: &Path) -> eyre::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            std::fs::remove_file(path)
+                .wrap_err_with(|| format!("Removing hook symlink {:?}", path))?;
+        }
+        Ok(_) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    Ok(())
+}
+
 #[instrument]
 fn update_hook_contents(hook: &Hook, hook_contents: &str) -> eyre::Result<()> {
+    match hook {
+        Hook::RegularHook { path } | Hook::MultiHook { path } => remove_hook_symlink(path)?,
+    }
     let (hook_path, hook_contents) = match hook {
         Hook::RegularHook { path } => match std::fs::read_to_string(path) {
             Ok(lines) => {
@@ -198,15 +225,206 @@ fn install_hook(repo: &Repo, hook_type: &str, hook_script: &str) -> eyre::Result
     Ok(())
 }
 
+/// Whether to install native hooks, which invoke the `git-branchless`
+/// executable directly rather than through `git branchless`. On Windows, the
+/// `git-branchless` executable may not be on the `PATH` seen by hooks, and
+/// `sh` may not be available to run the hooks at all (in which case
+/// `git-branchless` runs its native hooks itself).
+fn should_install_native_hooks() -> bool {
+    cfg!(windows)
+}
+
+/// Quote the given path for use in a POSIX shell script. Backslashes are
+/// converted to forward slashes, which are understood by both the Git for
+/// Windows shell and the Windows APIs.
+fn quote_hook_executable_path(path: &Path) -> String {
+    let path = path.to_slash_lossy();
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+/// Render the contents of a native hook, which is installed under the real
+/// hook name and invokes the `git-branchless` executable at the given path
+/// directly.
+fn render_native_hook(hook_script: &str, executable_path: &Path) -> String {
+    format!(
+        "{}\n{}",
+        NATIVE_HOOK_MARKER,
+        hook_script.replace(
+            "git branchless ",
+            &format!("{} ", quote_hook_executable_path(executable_path))
+        )
+    )
+}
+
+/// The contents of the shared hook script used by `--hook-type symlink`.
+/// Distributions may install this file as
+/// `<prefix>/share/git-branchless/git-branchless-hook`.
+const SHARED_HOOK_SCRIPT: &str = include_str!("../../hooks/git-branchless-hook");
+const SHARED_HOOK_SCRIPT_NAME: &str = "git-branchless-hook";
+
+/// Find the shared hook script which hooks should be symlinked to. If none is
+/// configured or shipped with the binary, write one into the repository.
+#[instrument]
+fn find_shared_hook_script(repo: &Repo) -> eyre::Result<PathBuf> {
+    if let Some(path) = get_shared_hook_script_path(repo)? {
+        if !path.is_file() {
+            eyre::bail!(
+                "The shared hook script configured by branchless.hooks.sharedScript does not exist: {}",
+                path.to_string_lossy()
+            );
+        }
+        return Ok(path);
+    }
+
+    if let Ok(exe_path) = std::env::current_exe() {
+        let shipped_path = exe_path
+            .parent()
+            .and_then(|bin_dir| bin_dir.parent())
+            .map(|prefix| {
+                prefix
+                    .join("share")
+                    .join("git-branchless")
+                    .join(SHARED_HOOK_SCRIPT_NAME)
+            });
+        if let Some(shipped_path) = shipped_path {
+            if shipped_path.is_file() {
+                return Ok(shipped_path);
+            }
+        }
+    }
+
+    let path = repo
+        .get_path()
+        .join("branchless")
+        .join(SHARED_HOOK_SCRIPT_NAME);
+    write_script(&path, SHARED_HOOK_SCRIPT).wrap_err("Writing shared hook script")?;
+    Ok(path)
+}
+
+/// Determine whether the given hook contents contain anything other than the
+/// shebang and the section managed by `git-branchless`.
+fn has_foreign_hook_contents(hook_contents: &str) -> bool {
+    let mut is_ignoring_lines = false;
+    for line in hook_contents.lines() {
+        if line == UPDATE_MARKER_START {
+            is_ignoring_lines = true;
+        } else if line == UPDATE_MARKER_END {
+            is_ignoring_lines = false;
+        } else if !is_ignoring_lines && !line.trim().is_empty() && line != SHEBANG {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(unix)]
+fn symlink_file(target: &Path, path: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn symlink_file(target: &Path, path: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, path)
+}
+
+/// Install the hook as a symlink to the shared hook script. Returns `false`
+/// if the hook couldn't be installed as a symlink, in which case the caller
+/// should install a regular hook instead.
 #[instrument]
-fn install_hooks(effects: &Effects, repo: &Repo) -> eyre::Result<()> {
+fn install_symlink_hook(
+    effects: &Effects,
+    repo: &Repo,
+    hook_type: &str,
+    shared_hook_script: &Path,
+) -> eyre::Result<bool> {
+    let path = match determine_hook_path(repo, hook_type)? {
+        Hook::RegularHook { path } => path,
+        // Multi-hooks are dispatched by directory rather than by file name,
+        // so the shared hook script can't determine which hook to run.
+        Hook::MultiHook { path: _ } => return Ok(false),
+    };
+
+    match std::fs::symlink_metadata(&path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {}
+        Ok(_) => {
+            let hook_contents = std::fs::read_to_string(&path)
+                .wrap_err_with(|| format!("Reading hook {:?}", path))?;
+            if has_foreign_hook_contents(&hook_contents) {
+                writeln!(
+                    effects.get_output_stream(),
+                    "The {} hook has other contents, so it will not be replaced with a symlink.",
+                    hook_type
+                )?;
+                return Ok(false);
+            }
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    let hooks_dir = path
+        .parent()
+        .ok_or_else(|| eyre::eyre!("No parent for hook {:?}", path))?;
+    std::fs::create_dir_all(hooks_dir).wrap_err("Creating hooks dir")?;
+    let temp_path = hooks_dir.join(format!("{}.branchless-symlink", hook_type));
+    let _ignored: std::io::Result<()> = std::fs::remove_file(&temp_path);
+    match symlink_file(shared_hook_script, &temp_path) {
+        Ok(()) => {}
+        Err(err) => {
+            writeln!(
+                effects.get_output_stream(),
+                "Could not create a symlink for the {} hook ({}), so a regular hook will be installed instead.",
+                hook_type,
+                err
+            )?;
+            return Ok(false);
+        }
+    }
+    std::fs::rename(&temp_path, &path)
+        .wrap_err_with(|| format!("Moving hook symlink into place at {:?}", path))?;
+    Ok(true)
+}
+
+#[instrument]
+fn install_hooks(effects: &Effects, repo: &Repo, hook_type: HookType) -> eyre::Result<()> {
+    let shared_hook_script = match hook_type {
+        HookType::Regular => None,
+        HookType::Symlink => {
+            let path = find_shared_hook_script(repo)?;
+            writeln!(
+                effects.get_output_stream(),
+                "Linking hooks to shared hook script: {}",
+                path.to_string_lossy()
+            )?;
+            Some(path)
+        }
+    };
+
     for (hook_type, hook_script) in ALL_HOOKS {
         writeln!(
             effects.get_output_stream(),
             "Installing hook: {}",
             hook_type
         )?;
-        install_hook(repo, hook_type, hook_script)?;
+        let is_symlinked = match &shared_hook_script {
+            Some(shared_hook_script) => {
+                install_symlink_hook(effects, repo, hook_type, shared_hook_script)?
+            }
+            None => false,
+        };
+        if !is_symlinked {
+            if should_install_native_hooks() {
+                let executable_path =
+                    std::env::current_exe().wrap_err("Getting git-branchless executable path")?;
+                install_hook(
+                    repo,
+                    hook_type,
+                    &render_native_hook(hook_script, &executable_path),
+                )?;
+            } else {
+                install_hook(repo, hook_type, hook_script)?;
+            }
+        }
     }
 
     let hooks_path: Option<PathBuf> = repo.get_readonly_config()?.get("core.hooksPath")?;
@@ -214,7 +432,7 @@ fn install_hooks(effects: &Effects, repo: &Repo) -> eyre::Result<()> {
         writeln!(
             effects.get_output_stream(),
             "{}: the configuration value core.hooksPath was set to: {}",
-            style("Warning").yellow().bold(),
+            effects.get_glyphs().render_warning_label()?,
             hooks_path.to_string_lossy()
         )?;
         writeln!(
@@ -330,12 +548,19 @@ fn install_aliases(
     repo: &mut Repo,
     config: &mut Config,
     default_config: &Config,
-    git_run_info: &GitRunInfo,
 ) -> eyre::Result<()> {
     for (from, to) in ALL_ALIASES {
         install_alias(effects, repo, config, default_config, from, to)?;
     }
+    Ok(())
+}
 
+#[instrument]
+fn check_git_version(
+    effects: &Effects,
+    repo: &Repo,
+    git_run_info: &GitRunInfo,
+) -> eyre::Result<()> {
     let version_str = git_run_info
         .run_silent(repo, None, &["version"], Default::default())
         .wrap_err("Determining Git version")?
@@ -362,7 +587,7 @@ do from then on will be correctly undoable.
 This only applies to the `git undo` command. Other commands which are part of
 the branchless workflow will work properly.
 ",
-            warning_str = style("Warning").yellow().bold(),
+            warning_str = effects.get_glyphs().render_warning_label()?,
             version_str = version_str,
         )?;
     }
@@ -411,6 +636,159 @@ fn install_man_pages(effects: &Effects, repo: &Repo, config: &mut Config) -> eyr
     Ok(())
 }
 
+/// Prompt the user for a value, returning `default` if they don't enter
+/// anything.
+fn prompt(
+    r#in: &mut impl BufRead,
+    effects: &Effects,
+    question: &str,
+    default: &str,
+) -> eyre::Result<String> {
+    if default.is_empty() {
+        write!(effects.get_output_stream(), "{}: ", question)?;
+    } else {
+        write!(effects.get_output_stream(), "{} [{}]: ", question, default)?;
+    }
+    stdout().flush()?;
+    let mut input = String::new();
+    r#in.read_line(&mut input)?;
+    match input.trim() {
+        "" => Ok(default.to_string()),
+        input => Ok(input.to_string()),
+    }
+}
+
+/// Prompt the user for a yes/no answer.
+pub fn prompt_yes_no(
+    r#in: &mut impl BufRead,
+    effects: &Effects,
+    question: &str,
+    default: bool,
+) -> eyre::Result<bool> {
+    let choices = if default { "Y/n" } else { "y/N" };
+    loop {
+        let answer = prompt(r#in, effects, &format!("{} ({})", question, choices), "")?;
+        match answer.to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => {
+                writeln!(effects.get_output_stream(), "Please answer yes or no.")?;
+            }
+        }
+    }
+}
+
+/// The choices made in the configuration wizard which affect what `init`
+/// installs, rather than just being written to the config.
+#[derive(Debug)]
+struct WizardChoices {
+    install_aliases: bool,
+}
+
+impl Default for WizardChoices {
+    fn default() -> Self {
+        Self {
+            install_aliases: true,
+        }
+    }
+}
+
+/// Ask which remote the main branch should be synced with, and set it as the
+/// main branch's upstream. Skipped if the main branch already has an upstream
+/// or if there are no remotes.
+#[instrument(skip(r#in))]
+fn configure_sync_remote(
+    r#in: &mut impl BufRead,
+    effects: &Effects,
+    repo: &Repo,
+    config: &mut Config,
+    main_branch_name: &str,
+) -> eyre::Result<()> {
+    if let Some(branch) = repo.find_branch(main_branch_name, BranchType::Local)? {
+        if let Some(upstream_branch) = branch.get_upstream_branch()? {
+            let upstream_name = upstream_branch.into_reference().get_name()?;
+            writeln!(
+                effects.get_output_stream(),
+                "Your main branch is synced with: {}",
+                console::style(CategorizedReferenceName::new(&upstream_name).remove_prefix()?)
+                    .bold()
+            )?;
+            return Ok(());
+        }
+    }
+
+    let remote_names = repo.get_remote_names()?;
+    let default_remote = match remote_names.iter().find(|name| *name == "origin") {
+        Some(name) => name,
+        None => match remote_names.first() {
+            Some(name) => name,
+            None => return Ok(()),
+        },
+    };
+    let remote_name = loop {
+        let remote_name = prompt(
+            r#in,
+            effects,
+            &format!(
+                "Remote to sync your main branch with ({}, or \"none\")",
+                remote_names.join(", ")
+            ),
+            default_remote,
+        )?;
+        if remote_name == "none" {
+            return Ok(());
+        } else if remote_names.contains(&remote_name) {
+            break remote_name;
+        } else {
+            writeln!(
+                effects.get_output_stream(),
+                "No remote named: {}",
+                remote_name
+            )?;
+        }
+    };
+
+    config.set(format!("branch.{}.remote", main_branch_name), remote_name)?;
+    config.set(
+        format!("branch.{}.merge", main_branch_name),
+        format!("refs/heads/{}", main_branch_name),
+    )?;
+    Ok(())
+}
+
+/// Walk through the optional configuration settings. The main branch is
+/// handled separately by `set_configs`.
+#[instrument(skip(r#in))]
+fn run_wizard(
+    r#in: &mut impl BufRead,
+    effects: &Effects,
+    repo: &Repo,
+    config: &mut Config,
+    main_branch_name: &str,
+) -> eyre::Result<WizardChoices> {
+    configure_sync_remote(r#in, effects, repo, config, main_branch_name)?;
+
+    let install_aliases = prompt_yes_no(
+        r#in,
+        effects,
+        "Install aliases for branchless commands, such as `git sl` and `git co`?",
+        true,
+    )?;
+
+    let ci_status_command = prompt(
+        r#in,
+        effects,
+        "Command to query the CI status of commits from your code forge (leave blank to skip)",
+        &get_ci_status_command(repo)?.unwrap_or_default(),
+    )?;
+    if !ci_status_command.is_empty() {
+        config.set("branchless.ci.statusCommand", ci_status_command)?;
+    }
+
+    Ok(WizardChoices { install_aliases })
+}
+
 #[instrument(skip(r#in))]
 fn set_configs(
     r#in: &mut impl BufRead,
@@ -418,11 +796,16 @@ fn set_configs(
     repo: &Repo,
     config: &mut Config,
     main_branch_name: Option<&str>,
-) -> eyre::Result<()> {
+    run_wizard: bool,
+) -> eyre::Result<String> {
     let main_branch_name = match main_branch_name {
         Some(main_branch_name) => main_branch_name.to_string(),
 
         None => match detect_main_branch_name(repo)? {
+            Some(main_branch_name) if run_wizard => {
+                prompt(r#in, effects, "Main branch name", &main_branch_name)?
+            }
+
             Some(main_branch_name) => {
                 writeln!(
                     effects.get_output_stream(),
@@ -440,9 +823,13 @@ fn set_configs(
                 writeln!(
                     effects.get_output_stream(),
                     "{}",
-                    console::style("Your main branch name could not be auto-detected!")
-                        .yellow()
-                        .bold()
+                    printable_styled_string(
+                        effects.get_glyphs(),
+                        StyledString::styled(
+                            "Your main branch name could not be auto-detected!",
+                            effects.get_theme().warning_label,
+                        )
+                    )?
                 )?;
                 writeln!(
                     effects.get_output_stream(),
@@ -467,11 +854,11 @@ fn set_configs(
         },
     };
 
-    config.set("branchless.core.mainBranch", main_branch_name)?;
+    config.set("branchless.core.mainBranch", main_branch_name.as_str())?;
     config.set("advice.detachedHead", false)?;
     config.set("log.excludeDecoration", "refs/branchless/*")?;
 
-    Ok(())
+    Ok(main_branch_name)
 }
 
 const INCLUDE_PATH_REGEX: &str = r"^branchless/";
@@ -553,6 +940,8 @@ pub fn init(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     main_branch_name: Option<&str>,
+    hook_type: HookType,
+    run_wizard: bool,
 ) -> eyre::Result<()> {
     let mut in_ = BufReader::new(stdin());
     let mut repo = Repo::from_current_dir()?;
@@ -560,15 +949,24 @@ pub fn init(
     let readonly_config = repo.get_readonly_config()?;
     let mut config = create_isolated_config(effects, &repo, readonly_config.into_config())?;
 
-    set_configs(&mut in_, effects, &repo, &mut config, main_branch_name)?;
-    install_hooks(effects, &repo)?;
-    install_aliases(
+    let main_branch_name = set_configs(
+        &mut in_,
         effects,
-        &mut repo,
+        &repo,
         &mut config,
-        &default_config,
-        git_run_info,
+        main_branch_name,
+        run_wizard,
     )?;
+    let wizard_choices = if run_wizard {
+        self::run_wizard(&mut in_, effects, &repo, &mut config, &main_branch_name)?
+    } else {
+        WizardChoices::default()
+    };
+    install_hooks(effects, &repo, hook_type)?;
+    if wizard_choices.install_aliases {
+        install_aliases(effects, &mut repo, &mut config, &default_config)?;
+    }
+    check_git_version(effects, &repo, git_run_info)?;
     install_man_pages(effects, &repo, &mut config)?;
     writeln!(
         effects.get_output_stream(),
@@ -597,7 +995,11 @@ pub fn uninstall(effects: &Effects) -> eyre::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use super::{update_between_lines, ALL_ALIASES, UPDATE_MARKER_END, UPDATE_MARKER_START};
+    use super::{
+        render_native_hook, update_between_lines, ALL_ALIASES, ALL_HOOKS, UPDATE_MARKER_END,
+        UPDATE_MARKER_START,
+    };
+    use std::path::Path;
 
     #[test]
     fn test_update_between_lines() {
@@ -635,6 +1037,16 @@ contents 3
         )
     }
 
+    #[test]
+    fn test_render_native_hook() {
+        let executable_path = Path::new("/opt/it's/git-branchless");
+        insta::assert_snapshot!(render_native_hook(ALL_HOOKS[0].1, executable_path), @r###"
+        ## BRANCHLESS NATIVE HOOK
+
+        '/opt/it'\''s/git-branchless' hook-post-commit "$@"
+        "###);
+    }
+
     #[test]
     fn test_all_alias_binaries_exist() {
         let all_alias_binaries_installed = cfg!(feature = "man-pages");
@@ -0,0 +1,114 @@
+//! Resolve historical commit OIDs to their newest versions, so that references
+//! to commits which have since been rewritten (such as in old review comments)
+//! can be followed.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use itertools::Itertools;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::rewrite::RewriteMap;
+use lib::git::{MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+/// Resolve the commit given by the user. The commit may no longer exist in the
+/// repository, in which case it's looked up by prefix among the rewritten
+/// commits.
+fn resolve_commit_oid(
+    effects: &Effects,
+    repo: &Repo,
+    rewrite_map: &RewriteMap,
+    commit: &str,
+) -> eyre::Result<Option<NonZeroOid>> {
+    if let Ok(Some(commit)) = repo.revparse_single_commit(commit) {
+        return Ok(Some(commit.get_oid()));
+    }
+    // Abbreviated OIDs would be parsed as if they were padded with zeros, so
+    // only accept full OIDs here.
+    if commit.len() == 40 {
+        if let Ok(oid) = NonZeroOid::from_str(commit) {
+            return Ok(Some(oid));
+        }
+    }
+
+    match rewrite_map.find_by_prefix(commit).as_slice() {
+        [] => {
+            writeln!(effects.get_error_stream(), "Commit not found: {}", commit)?;
+            Ok(None)
+        }
+        [oid] => Ok(Some(*oid)),
+        oids => {
+            writeln!(
+                effects.get_error_stream(),
+                "Commit OID prefix is ambiguous: {}. It could refer to any of these rewritten commits:",
+                commit
+            )?;
+            for oid in oids {
+                writeln!(effects.get_error_stream(), "{}", oid)?;
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Print the newest versions of the given commit, or of all rewritten commits.
+#[instrument]
+pub fn lookup(effects: &Effects, commit: Option<String>, all: bool) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let rewrite_map = RewriteMap::from_event_replayer(&event_replayer, event_cursor);
+
+    if all {
+        let rewritten_oids = rewrite_map
+            .iter()
+            .map(|(oid, _successors)| oid)
+            .sorted()
+            .collect_vec();
+        for oid in rewritten_oids {
+            for successor in rewrite_map.find_latest_successors(oid) {
+                writeln!(effects.get_output_stream(), "{} {}", oid, successor)?;
+            }
+        }
+        return Ok(ExitCode(0));
+    }
+
+    let commit = match commit {
+        Some(commit) => commit,
+        None => eyre::bail!("No commit provided to look up"),
+    };
+    let oid = match resolve_commit_oid(effects, &repo, &rewrite_map, &commit)? {
+        Some(oid) => oid,
+        None => return Ok(ExitCode(1)),
+    };
+
+    let successors = rewrite_map.find_latest_successors(oid);
+    if successors.is_empty() {
+        writeln!(effects.get_output_stream(), "{}", oid)?;
+        return Ok(ExitCode(0));
+    }
+
+    let successor_oids = successors
+        .into_iter()
+        .filter_map(|successor| match successor {
+            MaybeZeroOid::NonZero(successor_oid) => Some(successor_oid),
+            MaybeZeroOid::Zero => None,
+        })
+        .collect_vec();
+    if successor_oids.is_empty() {
+        writeln!(
+            effects.get_error_stream(),
+            "Commit {} was deleted, and has no newer versions.",
+            oid
+        )?;
+        return Ok(ExitCode(1));
+    }
+    for successor_oid in successor_oids {
+        writeln!(effects.get_output_stream(), "{}", successor_oid)?;
+    }
+    Ok(ExitCode(0))
+}
@@ -0,0 +1,175 @@
+//! Get and set metadata attached to commits. The metadata is carried forward
+//! to the newest versions of commits as they're rewritten.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use eden_dag::DagAlgorithm;
+use lib::core::dag::{commit_set_to_vec_unsorted, union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::metadata::CommitMetadataDb;
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::RewriteMap;
+use lib::core::topics::STACK_NAME_METADATA_KEY;
+use lib::git::{GitRunInfo, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use crate::opts::Revset;
+use crate::revset::resolve_commits;
+
+/// Open the DAG as of the current event.
+fn open_dag(effects: &Effects, repo: &Repo, event_replayer: &EventReplayer) -> eyre::Result<Dag> {
+    let references_snapshot = repo.get_references_snapshot()?;
+    Dag::open_and_sync(
+        effects,
+        repo,
+        event_replayer,
+        event_replayer.make_default_cursor(),
+        &references_snapshot,
+    )
+}
+
+/// Resolve the given revset into a list of commit OIDs, or print an error and
+/// return `None` if it couldn't be resolved.
+fn resolve_commit_oids(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &mut Dag,
+    revset: Revset,
+) -> eyre::Result<Option<Vec<NonZeroOid>>> {
+    match resolve_commits(effects, git_run_info, repo, dag, vec![revset]) {
+        Ok(commit_sets) => Ok(Some(commit_set_to_vec_unsorted(&union_all(&commit_sets))?)),
+        Err(err) => {
+            err.describe(effects)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Print the metadata for the given commit, or the value of a single key.
+#[instrument]
+pub fn get(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    commit: Revset,
+    key: Option<String>,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let mut dag = open_dag(effects, &repo, &event_replayer)?;
+    let commit_oid =
+        match resolve_commit_oids(effects, git_run_info, &repo, &mut dag, commit.clone())? {
+            None => return Ok(ExitCode(1)),
+            Some(commit_oids) => match commit_oids.as_slice() {
+                [commit_oid] => *commit_oid,
+                commit_oids => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "Expected revset to expand to exactly 1 commit (got {}): {}",
+                        commit_oids.len(),
+                        commit.0
+                    )?;
+                    return Ok(ExitCode(1));
+                }
+            },
+        };
+
+    let rewrite_map =
+        RewriteMap::from_event_replayer(&event_replayer, event_replayer.make_default_cursor());
+    let metadata = CommitMetadataDb::new(&conn)?.get(&rewrite_map, commit_oid)?;
+    match key {
+        None => {
+            for (key, value) in metadata {
+                writeln!(effects.get_output_stream(), "{}: {}", key, value)?;
+            }
+        }
+        Some(key) => match metadata.get(&key) {
+            Some(value) => {
+                writeln!(effects.get_output_stream(), "{}", value)?;
+            }
+            None => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Key not set for commit {}: {}",
+                    commit_oid,
+                    key
+                )?;
+                return Ok(ExitCode(1));
+            }
+        },
+    }
+    Ok(ExitCode(0))
+}
+
+/// Set the value for the given key on the given commits. If `value` is `None`,
+/// then the key is unset instead.
+#[instrument]
+pub fn set(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    commits: Revset,
+    key: &str,
+    value: Option<&str>,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let mut dag = open_dag(effects, &repo, &event_replayer)?;
+    let commit_oids = match resolve_commit_oids(effects, git_run_info, &repo, &mut dag, commits)? {
+        Some(commit_oids) => commit_oids,
+        None => return Ok(ExitCode(1)),
+    };
+
+    let metadata_db = CommitMetadataDb::new(&conn)?;
+    for commit_oid in commit_oids {
+        metadata_db.set(now, commit_oid, key, value)?;
+    }
+    Ok(ExitCode(0))
+}
+
+/// Assign a name to the stack containing the given commit. If `name` is `None`,
+/// then the stack's name is removed instead.
+#[instrument]
+pub fn name_stack(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    commit: Revset,
+    name: Option<&str>,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let mut dag = open_dag(effects, &repo, &event_replayer)?;
+    let commit_oids =
+        match resolve_commit_oids(effects, git_run_info, &repo, &mut dag, commit.clone())? {
+            Some(commit_oids) => commit_oids,
+            None => return Ok(ExitCode(1)),
+        };
+
+    let commits: CommitSet = commit_oids.into_iter().collect();
+    let stack_commits = dag.query().only(commits, dag.query_public_commits()?)?;
+    let root_oids = commit_set_to_vec_unsorted(&dag.query().roots(stack_commits)?)?;
+    if root_oids.is_empty() {
+        writeln!(
+            effects.get_error_stream(),
+            "Revset does not contain any commits in a stack: {}",
+            commit.0
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let metadata_db = CommitMetadataDb::new(&conn)?;
+    for root_oid in root_oids {
+        metadata_db.set(now, root_oid, STACK_NAME_METADATA_KEY, name)?;
+    }
+    Ok(ExitCode(0))
+}
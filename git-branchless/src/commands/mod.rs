@@ -1,34 +1,55 @@
 //! Sub-commands of `git-branchless`.
 
 mod amend;
+mod branch;
 mod bug_report;
+mod config;
+mod confirm_rewrite;
+mod daemon;
+mod diff;
 mod gc;
 mod hide;
 mod hooks;
 mod init;
+mod lookup;
+mod meta;
 mod r#move;
 mod navigation;
+mod profile;
+mod prune_remote;
+mod push;
 mod query;
 mod record;
 mod repair;
 mod restack;
+mod resume;
 mod reword;
-mod smartlog;
+mod rewrite_summary;
+mod run;
+mod serve;
+pub mod smartlog;
 mod snapshot;
+mod split;
+mod stats;
 mod sync;
 mod undo;
+mod version;
 mod wrap;
 
+pub use reword::{parse_bulk_edit_message, BulkEditParseError, ParseMessageResult};
+
 use std::any::Any;
 use std::convert::TryInto;
 use std::ffi::OsString;
+use std::fmt::Write;
 use std::path::PathBuf;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use clap::Parser;
+use clap::{ArgMatches, FromArgMatches};
 use eyre::Context;
 use itertools::Itertools;
 use lib::core::rewrite::MergeConflictRemediation;
+use lib::error::Error;
 use lib::util::ExitCode;
 use tracing_chrome::ChromeLayerBuilder;
 use tracing_error::ErrorLayer;
@@ -36,16 +57,27 @@ use tracing_subscriber::fmt as tracing_fmt;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 
+use crate::commands::bug_report::LastCommandTrace;
+use crate::opts::build_command;
 use crate::opts::ColorSetting;
 use crate::opts::Command;
+use crate::opts::ConfigSubcommand;
+use crate::opts::MetaSubcommand;
 use crate::opts::Opts;
+use crate::opts::OutputFormat;
+use crate::opts::PointInTime;
 use crate::opts::SnapshotSubcommand;
 use crate::opts::WrappedCommand;
 use lib::core::config::env_vars::get_path_to_git;
+use lib::core::config::{
+    get_bug_report_record_traces, get_color_theme, get_pager, get_smartlog_glyphs,
+};
 use lib::core::effects::Effects;
+use lib::core::eventlog::BRANCHLESS_PROVENANCE_ENV_VAR;
 use lib::core::formatting::Glyphs;
 use lib::git::GitRunInfo;
 use lib::git::NonZeroOid;
+use lib::git::Repo;
 
 use self::reword::InitialCommitMessages;
 use self::smartlog::SmartlogOptions;
@@ -83,23 +115,130 @@ fn rewrite_args(args: Vec<OsString>) -> Vec<OsString> {
     }
 }
 
+/// Describe the program being run, to be recorded alongside the event log
+/// transactions that it starts.
+///
+/// Hooks run on behalf of whichever Git command invoked them. If that command
+/// sets `GIT_REFLOG_ACTION` (like `git pull` and `git rebase` do), then it's
+/// used as the provenance instead. Otherwise, the invoking command is looked
+/// up among the parent processes, falling back to the name of the hook.
+fn get_provenance(matches: &ArgMatches) -> Option<String> {
+    match matches.subcommand()? {
+        ("wrap", wrap_matches) => {
+            let (git_subcommand, _) = wrap_matches.subcommand()?;
+            Some(format!("git {}", git_subcommand))
+        }
+        (subcommand, _) if subcommand.starts_with("hook-") => {
+            if std::env::var_os("GIT_REFLOG_ACTION").is_some() {
+                return None;
+            }
+            let hook_name = subcommand.trim_start_matches("hook-");
+            Some(get_invoking_git_command().unwrap_or_else(|| format!("{} hook", hook_name)))
+        }
+        (subcommand, _) => Some(format!("git branchless {}", subcommand)),
+    }
+}
+
+/// Find the Git command which (indirectly) started this process, such as
+/// `git commit`, by walking up the parent processes. Invocations of `git
+/// branchless` itself are skipped, since hooks may be run through it.
+#[cfg(target_os = "linux")]
+fn get_invoking_git_command() -> Option<String> {
+    // The options which take a separate value, as in `git -C <path> commit`.
+    const OPTIONS_WITH_VALUES: &[&str] = &[
+        "-C",
+        "-c",
+        "--git-dir",
+        "--work-tree",
+        "--namespace",
+        "--exec-path",
+        "--config-env",
+    ];
+
+    let mut pid = std::os::unix::process::parent_id();
+    // Hooks are usually run through a shell and `git branchless`, so the Git
+    // command should be only a few processes up.
+    for _ in 0..8 {
+        if pid <= 1 {
+            return None;
+        }
+        let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+        let args = cmdline
+            .split(|c| *c == 0)
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect_vec();
+        let is_git = match args.first() {
+            Some(program) => {
+                std::path::Path::new(program).file_name() == Some(std::ffi::OsStr::new("git"))
+            }
+            None => false,
+        };
+        if is_git {
+            let mut args = args.iter().skip(1);
+            let subcommand = loop {
+                match args.next() {
+                    Some(arg) if OPTIONS_WITH_VALUES.contains(&arg.as_str()) => {
+                        args.next();
+                    }
+                    Some(arg) if arg.starts_with('-') => {}
+                    Some(arg) => break Some(arg),
+                    None => break None,
+                }
+            };
+            match subcommand {
+                Some(subcommand) if subcommand != "branchless" => {
+                    // Internal helpers, such as `git bisect--helper`, are
+                    // described as the command which they implement.
+                    let subcommand = subcommand.trim_end_matches("--helper");
+                    return Some(format!("git {}", subcommand));
+                }
+                Some(_) | None => {}
+            }
+        }
+
+        // The parent PID is the second field after the parenthesized command
+        // name, which may itself contain spaces or parentheses.
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        let (_, fields) = stat.rsplit_once(')')?;
+        pid = fields.split_whitespace().nth(1)?.parse().ok()?;
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_invoking_git_command() -> Option<String> {
+    None
+}
+
 /// Wrapper function for `main` to ensure that `Drop` is called for local
 /// variables, since `std::process::exit` will skip them.
 fn do_main_and_drop_locals() -> eyre::Result<i32> {
-    let _tracing_guard = install_tracing();
-
     let args = rewrite_args(std::env::args_os().collect_vec());
+    let matches = build_command().get_matches_from(args);
+    if std::env::var_os(BRANCHLESS_PROVENANCE_ENV_VAR).is_none() {
+        if let Some(provenance) = get_provenance(&matches) {
+            std::env::set_var(BRANCHLESS_PROVENANCE_ENV_VAR, provenance);
+        }
+    }
     let Opts {
         working_directory,
         command,
         color,
-    } = Opts::parse_from(args);
+        paginate,
+        no_pager,
+        profile,
+        profile_trace,
+    } = Opts::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let nesting_level = get_nesting_level();
+    let last_command_trace = LastCommandTrace::default();
+    let _tracing_guard = install_tracing(profile, profile_trace, &last_command_trace);
+
     if let Some(working_directory) = working_directory {
-        std::env::set_current_dir(&working_directory).wrap_err_with(|| {
-            format!(
+        std::env::set_current_dir(&working_directory).wrap_err_with(|| Error::User {
+            message: format!(
                 "Could not set working directory to: {:?}",
                 &working_directory
-            )
+            ),
         })?;
     }
 
@@ -109,6 +248,7 @@ fn do_main_and_drop_locals() -> eyre::Result<i32> {
         path_to_git,
         working_directory: std::env::current_dir()?,
         env: std::env::vars_os().collect(),
+        inherit_stdin: true,
     };
 
     let color = match color {
@@ -116,19 +256,147 @@ fn do_main_and_drop_locals() -> eyre::Result<i32> {
         Some(ColorSetting::Never) => Glyphs::text(),
         Some(ColorSetting::Auto) | None => Glyphs::detect(),
     };
+    // Some output is styled with `console` directly, so make sure that it
+    // agrees with the rest of the output about whether to use color.
+    console::set_colors_enabled(color.should_write_ansi_escape_codes);
+    console::set_colors_enabled_stderr(color.should_write_ansi_escape_codes);
+
+    // The output configuration is applied before running the command, so it
+    // can only be read if the command is run from inside a repository.
+    let repo = Repo::from_current_dir().ok();
+    let should_page = if paginate {
+        true
+    } else if no_pager {
+        false
+    } else {
+        command.uses_pager_by_default() && console::user_attended()
+    };
+    let mut config_errors = Vec::new();
+    // Only the outermost invocation should replace the trace of the last
+    // command, since nested invocations are part of it. The trace is only a
+    // diagnostic aid, so failing to record it shouldn't stop the command.
+    let mut trace_error = None;
+    if let (Some(repo), 0, true) = (&repo, nesting_level, command.records_trace()) {
+        match get_bug_report_record_traces(repo) {
+            Ok(true) => {
+                if let Err(err) = last_command_trace.start(&repo.get_last_command_trace_path()) {
+                    trace_error = Some(err);
+                }
+            }
+            Ok(false) => {}
+            Err(err) => config_errors.push(err),
+        }
+    }
+    let color = match &repo {
+        Some(repo) => match get_smartlog_glyphs(repo, color.clone()) {
+            Ok(glyphs) => glyphs,
+            Err(err) => {
+                config_errors.push(err);
+                color
+            }
+        },
+        None => color,
+    };
+    let color = match (&repo, color.should_write_ansi_escape_codes) {
+        (Some(repo), true) => match get_color_theme(repo) {
+            Ok(theme) => color.with_theme(theme),
+            Err(err) => {
+                config_errors.push(err);
+                color
+            }
+        },
+        (_, _) => color,
+    };
+
     let effects = Effects::new(color);
+    let effects = match (&repo, should_page) {
+        (Some(repo), true) => match get_pager(&git_run_info, repo)? {
+            Some(pager) => effects.enable_pager(&pager)?,
+            None => effects,
+        },
+        (_, _) => effects,
+    };
+    drop(repo);
+    for err in config_errors {
+        writeln!(
+            effects.get_error_stream(),
+            "{}: ignoring invalid configuration: {:#}",
+            effects.get_glyphs().render_warning_label()?,
+            err
+        )?;
+    }
+    if let Some(err) = trace_error {
+        writeln!(
+            effects.get_error_stream(),
+            "{}: could not record a trace of this command: {:#}",
+            effects.get_glyphs().render_warning_label()?,
+            err
+        )?;
+    }
 
     let ExitCode(exit_code) = match command {
-        Command::Amend { move_options } => amend::amend(&effects, &git_run_info, &move_options)?,
+        Command::Abort => resume::abort(&effects, &git_run_info)?,
+
+        Command::Amend {
+            patch,
+            move_options,
+        } => amend::amend(&effects, &git_run_info, patch, &move_options)?,
 
-        Command::BugReport => bug_report::bug_report(&effects, &git_run_info)?,
+        Command::Branch {
+            stack,
+            name,
+            dry_run,
+        } => branch::branch(&effects, &git_run_info, stack, name, dry_run)?,
+
+        Command::BugReport {
+            interactive,
+            output,
+        } => bug_report::bug_report(&effects, &git_run_info, interactive, output)?,
 
         Command::Checkout { checkout_options } => {
             navigation::checkout(&effects, &git_run_info, &checkout_options)?
         }
 
-        Command::Gc | Command::HookPreAutoGc => {
-            gc::gc(&effects)?;
+        Command::Continue => resume::r#continue(&effects, &git_run_info)?,
+
+        Command::Config { subcommand } => match subcommand {
+            ConfigSubcommand::List { scope, verbose } => config::list(&effects, scope, verbose)?,
+            ConfigSubcommand::Get { key } => config::get(&effects, &key)?,
+            ConfigSubcommand::Set { scope, key, values } => {
+                config::set(&effects, scope, &key, &values)?
+            }
+            ConfigSubcommand::Unset { scope, key } => config::unset(&effects, scope, &key)?,
+        },
+
+        Command::Daemon { poll_interval } => daemon::daemon(
+            &effects,
+            &git_run_info,
+            Duration::from_millis(poll_interval),
+        )?,
+
+        Command::Diff {
+            revset,
+            stat,
+            name_only,
+            word_diff,
+        } => {
+            let format = if stat {
+                diff::DiffFormat::Stat
+            } else if name_only {
+                diff::DiffFormat::NameOnly
+            } else {
+                diff::DiffFormat::Patch
+            };
+            diff::diff(&effects, &git_run_info, revset, format, word_diff)?
+        }
+
+        Command::Gc { prune } => {
+            gc::gc(&effects, &git_run_info, prune)?;
+            ExitCode(0)
+        }
+
+        Command::HookPreAutoGc => {
+            gc::hook_pre_auto_gc(&effects, &git_run_info)?;
             ExitCode(0)
         }
 
@@ -136,7 +404,23 @@ fn do_main_and_drop_locals() -> eyre::Result<i32> {
             revsets,
             delete_branches,
             recursive,
-        } => hide::hide(&effects, &git_run_info, revsets, delete_branches, recursive)?,
+            dry_run,
+        } => rewrite_summary::with_rewrite_summary(
+            &effects,
+            &git_run_info,
+            OutputFormat::Text,
+            "hide",
+            |effects, _summary| {
+                hide::hide(
+                    effects,
+                    &git_run_info,
+                    revsets,
+                    delete_branches,
+                    recursive,
+                    dry_run,
+                )
+            },
+        )?,
 
         Command::HookDetectEmptyCommit { old_commit_oid } => {
             let old_commit_oid: NonZeroOid = old_commit_oid.parse()?;
@@ -183,6 +467,12 @@ fn do_main_and_drop_locals() -> eyre::Result<i32> {
             ExitCode(0)
         }
 
+        Command::HookSkipFoldedCommit { commit_oid } => {
+            let commit_oid: NonZeroOid = commit_oid.parse()?;
+            hooks::hook_skip_folded_commit(&effects, commit_oid)?;
+            ExitCode(0)
+        }
+
         Command::HookSkipUpstreamAppliedCommit { commit_oid } => {
             let commit_oid: NonZeroOid = commit_oid.parse()?;
             hooks::hook_skip_upstream_applied_commit(&effects, commit_oid)?;
@@ -192,35 +482,91 @@ fn do_main_and_drop_locals() -> eyre::Result<i32> {
         Command::Init {
             uninstall: false,
             main_branch_name,
+            hook_type,
+            yes,
+            interactive,
         } => {
-            init::init(&effects, &git_run_info, main_branch_name.as_deref())?;
+            let run_wizard = !yes && (interactive || console::user_attended());
+            init::init(
+                &effects,
+                &git_run_info,
+                main_branch_name.as_deref(),
+                hook_type,
+                run_wizard,
+            )?;
             ExitCode(0)
         }
 
         Command::Init {
             uninstall: true,
             main_branch_name: _,
+            hook_type: _,
+            yes: _,
+            interactive: _,
         } => {
             init::uninstall(&effects)?;
             ExitCode(0)
         }
 
+        Command::Lookup { commit, all } => lookup::lookup(&effects, commit, all)?,
+
+        Command::Meta { subcommand } => match subcommand {
+            MetaSubcommand::Get { commit, key } => meta::get(&effects, &git_run_info, commit, key)?,
+            MetaSubcommand::Set {
+                commits,
+                key,
+                value,
+            } => meta::set(&effects, &git_run_info, commits, &key, Some(&value))?,
+            MetaSubcommand::Unset { commits, key } => {
+                meta::set(&effects, &git_run_info, commits, &key, None)?
+            }
+            MetaSubcommand::NameStack {
+                commit,
+                name,
+                clear: _,
+            } => meta::name_stack(&effects, &git_run_info, commit, name.as_deref())?,
+        },
+
+        // The other options are rejected by `clap` when moving to another
+        // repository.
+        Command::Move {
+            source,
+            to_repo: Some(to_repo),
+            ..
+        } => r#move::move_to_repo(&effects, &git_run_info, source, &to_repo)?,
+
         Command::Move {
             source,
             dest,
             base,
             exact,
             insert,
+            fixup,
+            to_repo: None,
             move_options,
-        } => r#move::r#move(
+            output,
+        } => rewrite_summary::with_rewrite_summary(
             &effects,
             &git_run_info,
-            source,
-            dest,
-            base,
-            exact,
-            insert,
-            &move_options,
+            output,
+            "move",
+            |effects, summary| {
+                if fixup {
+                    r#move::move_fixups(effects, &git_run_info, &move_options, summary)
+                } else {
+                    r#move::r#move(
+                        effects,
+                        &git_run_info,
+                        source,
+                        dest,
+                        base,
+                        exact,
+                        insert,
+                        &move_options,
+                        summary,
+                    )
+                }
+            },
         )?,
 
         Command::Next {
@@ -241,62 +587,132 @@ fn do_main_and_drop_locals() -> eyre::Result<i32> {
             &traverse_commits_options,
         )?,
 
+        Command::PruneRemote {
+            fetch,
+            dry_run,
+            yes,
+        } => prune_remote::prune_remote(&effects, &git_run_info, fetch, dry_run, yes)?,
+
+        Command::Push {
+            revsets,
+            remote,
+            dry_run,
+        } => push::push(&effects, &git_run_info, revsets, remote, dry_run)?,
+
         Command::Query {
             revset,
             show_branches,
             raw,
         } => query::query(&effects, &git_run_info, revset, show_branches, raw)?,
 
-        Command::Repair { dry_run } => repair::repair(&effects, dry_run)?,
+        Command::Repair {
+            dry_run,
+            check_schema,
+        } => {
+            if check_schema {
+                repair::check_schema(&effects)?
+            } else {
+                repair::repair(&effects, dry_run)?
+            }
+        }
 
         Command::Restack {
             commits: revsets,
             move_options,
-        } => restack::restack(
+            output,
+        } => rewrite_summary::with_rewrite_summary(
             &effects,
             &git_run_info,
-            revsets,
-            &move_options,
-            MergeConflictRemediation::Retry,
+            output,
+            "restack",
+            |effects, summary| {
+                restack::restack(
+                    effects,
+                    &git_run_info,
+                    revsets,
+                    &move_options,
+                    MergeConflictRemediation::Retry,
+                    summary,
+                )
+            },
         )?,
 
-        Command::Record {
-            message,
-            interactive,
-            detach,
-        } => record::record(&effects, &git_run_info, message, interactive, detach)?,
+        Command::Record { record_options } => {
+            record::record(&effects, &git_run_info, &record_options)?
+        }
 
         Command::Reword {
             revsets,
             messages,
             force_rewrite_public_commits,
+            force_rewrite_protected_branches,
             discard,
+            message_file,
+            resume,
+            reflow,
+            reflow_column,
+            dry_run,
+            skip_confirmation,
+            output,
         } => {
             let messages = if discard {
                 InitialCommitMessages::Discard
+            } else if reflow {
+                InitialCommitMessages::Reflow {
+                    column: reflow_column,
+                }
+            } else if resume {
+                InitialCommitMessages::Resume
+            } else if let Some(message_file) = message_file {
+                InitialCommitMessages::MessageFile(reword::read_message_file(&message_file)?)
             } else {
                 InitialCommitMessages::Messages(messages)
             };
-            reword::reword(
+            rewrite_summary::with_rewrite_summary(
                 &effects,
-                revsets,
-                messages,
                 &git_run_info,
-                force_rewrite_public_commits,
+                output,
+                "reword",
+                |effects, _summary| {
+                    reword::reword(
+                        effects,
+                        revsets,
+                        messages,
+                        &git_run_info,
+                        force_rewrite_public_commits,
+                        force_rewrite_protected_branches,
+                        dry_run,
+                        skip_confirmation,
+                    )
+                },
             )?
         }
 
+        Command::Run { script, args } => run::run(&effects, &git_run_info, &script, args)?,
+
+        Command::Serve { stdio: _ } => serve::serve(&git_run_info)?,
+
         Command::Smartlog {
             show_hidden_commits,
             event_id,
+            at,
             revset,
+            all_worktrees,
+            collapsed,
+            expand,
+            format,
         } => smartlog::smartlog(
             &effects,
             &git_run_info,
             &SmartlogOptions {
                 show_hidden_commits,
                 event_id,
+                at: at.map(|PointInTime(time)| time),
                 revset,
+                all_worktrees,
+                collapsed,
+                expand,
+                format,
             },
         )?,
 
@@ -307,17 +723,69 @@ fn do_main_and_drop_locals() -> eyre::Result<i32> {
             }
         },
 
+        Command::Split {
+            revset,
+            by_directory,
+            group,
+            move_options,
+        } => {
+            let mode = if by_directory {
+                split::SplitMode::ByDirectory
+            } else {
+                split::SplitMode::Globs(group)
+            };
+            split::split(&effects, &git_run_info, revset, mode, &move_options)?
+        }
+
+        Command::Stats { json } => stats::stats(&effects, json)?,
+
         Command::Sync {
             update_refs,
             move_options,
+            dry_run,
             revsets,
-        } => sync::sync(&effects, &git_run_info, update_refs, &move_options, revsets)?,
+            output,
+        } => rewrite_summary::with_rewrite_summary(
+            &effects,
+            &git_run_info,
+            output,
+            "sync",
+            |effects, summary| {
+                sync::sync(
+                    effects,
+                    &git_run_info,
+                    update_refs,
+                    &move_options,
+                    dry_run,
+                    revsets,
+                    summary,
+                )
+            },
+        )?,
 
-        Command::Undo { interactive, yes } => {
-            undo::undo(&effects, &git_run_info, interactive, yes)?
+        Command::Undo {
+            interactive,
+            yes,
+            exclude_remotes,
+            refs,
+            since,
+            until,
+        } => undo::undo(
+            &effects,
+            &git_run_info,
+            interactive,
+            yes,
+            exclude_remotes,
+            refs,
+            since.map(|PointInTime(time)| time),
+            until.map(|PointInTime(time)| time),
+        )?,
+
+        Command::Unhide { revsets, recursive } => {
+            hide::unhide(&effects, &git_run_info, revsets, recursive)?
         }
 
-        Command::Unhide { revsets, recursive } => hide::unhide(&effects, revsets, recursive)?,
+        Command::Version { json } => version::version(&effects, json)?,
 
         Command::Wrap {
             git_executable: explicit_git_executable,
@@ -343,12 +811,45 @@ pub fn main() {
     // Install panic handler.
     color_eyre::install().expect("Could not install panic handler");
 
-    let exit_code = do_main_and_drop_locals().expect("A fatal error occurred");
+    let exit_code = match do_main_and_drop_locals() {
+        Ok(exit_code) => exit_code,
+        Err(err) => match Error::find_in_report(&err) {
+            Some(error) => {
+                eprintln!("branchless: {}", error);
+                if let Some(hint) = error.hint() {
+                    eprintln!("{}", hint);
+                }
+                let ExitCode(exit_code) = error.category().exit_code();
+                exit_code.try_into().unwrap_or(1)
+            }
+
+            // Uncategorized errors probably indicate a bug, so report them
+            // with the full backtrace.
+            None => panic!("A fatal error occurred: {:?}", err),
+        },
+    };
     std::process::exit(exit_code)
 }
 
+// We may invoke a hook that calls back into `git-branchless`. In that case,
+// we have to be careful not to write to the same logging file.
+const NESTING_LEVEL_KEY: &str = "RUST_LOGGING_NESTING_LEVEL";
+
+/// The number of `git-branchless` invocations which this invocation is
+/// nested inside of.
+fn get_nesting_level() -> usize {
+    match std::env::var(NESTING_LEVEL_KEY) {
+        Ok(nesting_level) => nesting_level.parse::<usize>().unwrap_or_default(),
+        Err(_) => 0,
+    }
+}
+
 #[must_use = "This function returns a guard object to flush traces. Dropping it immediately is probably incorrect. Make sure that the returned value lives until tracing has finished."]
-fn install_tracing() -> eyre::Result<impl Drop> {
+fn install_tracing(
+    profile: bool,
+    profile_trace: Option<PathBuf>,
+    last_command_trace: &LastCommandTrace,
+) -> eyre::Result<impl Drop> {
     let (filter_layer, fmt_layer) = match EnvFilter::try_from_default_env() {
         Ok(filter_layer) => {
             let fmt_layer = tracing_fmt::layer()
@@ -371,13 +872,7 @@ fn install_tracing() -> eyre::Result<impl Drop> {
     };
 
     let (profile_layer, flush_guard): (_, Box<dyn Any>) = {
-        // We may invoke a hook that calls back into `git-branchless`. In that case,
-        // we have to be careful not to write to the same logging file.
-        const NESTING_LEVEL_KEY: &str = "RUST_LOGGING_NESTING_LEVEL";
-        let nesting_level = match std::env::var(NESTING_LEVEL_KEY) {
-            Ok(nesting_level) => nesting_level.parse::<usize>().unwrap_or_default(),
-            Err(_) => 0,
-        };
+        let nesting_level = get_nesting_level();
         std::env::set_var(NESTING_LEVEL_KEY, (nesting_level + 1).to_string());
 
         let should_include_function_args = match std::env::var("RUST_PROFILE_INCLUDE_ARGS") {
@@ -386,6 +881,11 @@ fn install_tracing() -> eyre::Result<impl Drop> {
         };
 
         let filename = match std::env::var("RUST_PROFILE") {
+            // Only the outermost invocation should write to the file passed
+            // with `--profile-trace`.
+            _ if nesting_level == 0 && profile_trace.is_some() => {
+                profile_trace.map(|path| path.to_string_lossy().into_owned())
+            }
             Ok(value) if value == "1" || value == "true" => {
                 let filename = format!(
                     "trace-{}.json-{}",
@@ -415,14 +915,23 @@ fn install_tracing() -> eyre::Result<impl Drop> {
         }
     };
 
+    let (timing_layer, timing_guard) = if profile {
+        let (layer, guard) = profile::make_profile_layer();
+        (Some(layer), Some(guard))
+    } else {
+        (None, None)
+    };
+
     tracing_subscriber::registry()
         .with(ErrorLayer::default())
         .with(filter_layer)
         .with(fmt_layer)
+        .with(last_command_trace.make_layer())
         .with(profile_layer)
+        .with(timing_layer)
         .try_init()?;
 
-    Ok(flush_guard)
+    Ok(Box::new((flush_guard, timing_guard)))
 }
 
 /// Re-exports of internals for testing purposes.
@@ -4,30 +4,37 @@
 //! is also used to preserve merge commits using the `--rebase-merges` option.
 
 use std::collections::HashMap;
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
 use std::fmt::Write;
+use std::path::Path;
 use std::time::SystemTime;
 
-use console::style;
+use bstr::{BString, ByteSlice};
 use eden_dag::DagAlgorithm;
+use itertools::Itertools;
 use lib::core::repo_ext::RepoExt;
+use lib::error::ErrorCategory;
 use lib::util::ExitCode;
 use rayon::ThreadPoolBuilder;
 use tracing::instrument;
 
+use crate::commands::confirm_rewrite::confirm_large_rewrite;
 use crate::opts::{MoveOptions, Revset};
 use crate::revset::resolve_commits;
+use lib::core::applied_patches::{AppliedPatch, AppliedPatchesDb};
 use lib::core::config::{
     get_hint_enabled, get_restack_preserve_timestamps, print_hint_suppression_notice, Hint,
 };
 use lib::core::dag::{commit_set_to_vec_unsorted, sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId, SuspendedOperation};
+use lib::core::formatting::{printable_styled_string, Pluralize};
 use lib::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    MergeConflictRemediation, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    execute_rebase_plan, BuildRebasePlanError, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
+    ExecuteRebasePlanResult, FixupKind, MergeConflictRemediation, RebasePlan, RebasePlanBuilder,
+    RebasePlanPermissions, RepoResource, RewriteSummary,
 };
-use lib::git::{GitRunInfo, NonZeroOid, Repo};
+use lib::git::{Commit, GitRunInfo, GitRunOpts, GitRunResult, NonZeroOid, PatchId, Repo};
 
 #[instrument]
 fn resolve_base_commit(
@@ -66,6 +73,7 @@ pub fn r#move(
     exacts: Vec<Revset>,
     insert: bool,
     move_options: &MoveOptions,
+    summary: &mut RewriteSummary,
 ) -> eyre::Result<ExitCode> {
     let sources_provided = !sources.is_empty();
     let bases_provided = !bases.is_empty();
@@ -100,21 +108,23 @@ pub fn r#move(
         &references_snapshot,
     )?;
 
-    let source_oids: CommitSet = match resolve_commits(effects, &repo, &mut dag, sources) {
-        Ok(commit_sets) => union_all(&commit_sets),
-        Err(err) => {
-            err.describe(effects)?;
-            return Ok(ExitCode(1));
-        }
-    };
-    let base_oids: CommitSet = match resolve_commits(effects, &repo, &mut dag, bases) {
+    let source_oids: CommitSet =
+        match resolve_commits(effects, git_run_info, &repo, &mut dag, sources) {
+            Ok(commit_sets) => union_all(&commit_sets),
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+    let base_oids: CommitSet = match resolve_commits(effects, git_run_info, &repo, &mut dag, bases)
+    {
         Ok(commit_sets) => union_all(&commit_sets),
         Err(err) => {
             err.describe(effects)?;
             return Ok(ExitCode(1));
         }
     };
-    let exact_components = match resolve_commits(effects, &repo, &mut dag, exacts) {
+    let exact_components = match resolve_commits(effects, git_run_info, &repo, &mut dag, exacts) {
         Ok(commit_sets) => {
             let exact_oids = union_all(&commit_sets);
             let mut components: HashMap<NonZeroOid, CommitSet> = HashMap::new();
@@ -159,25 +169,26 @@ pub fn r#move(
         }
     };
 
-    let dest_oid: NonZeroOid = match resolve_commits(effects, &repo, &mut dag, vec![dest.clone()]) {
-        Ok(commit_sets) => match commit_set_to_vec_unsorted(&commit_sets[0])?.as_slice() {
-            [only_commit_oid] => *only_commit_oid,
-            other => {
-                let Revset(expr) = dest;
-                writeln!(
-                    effects.get_error_stream(),
-                    "Expected revset to expand to exactly 1 commit (got {}): {}",
-                    other.len(),
-                    expr,
-                )?;
+    let dest_oid: NonZeroOid =
+        match resolve_commits(effects, git_run_info, &repo, &mut dag, vec![dest.clone()]) {
+            Ok(commit_sets) => match commit_set_to_vec_unsorted(&commit_sets[0])?.as_slice() {
+                [only_commit_oid] => *only_commit_oid,
+                other => {
+                    let Revset(expr) = dest;
+                    writeln!(
+                        effects.get_error_stream(),
+                        "Expected revset to expand to exactly 1 commit (got {}): {}",
+                        other.len(),
+                        expr,
+                    )?;
+                    return Ok(ExitCode(1));
+                }
+            },
+            Err(err) => {
+                err.describe(effects)?;
                 return Ok(ExitCode(1));
             }
-        },
-        Err(err) => {
-            err.describe(effects)?;
-            return Ok(ExitCode(1));
-        }
-    };
+        };
 
     let base_oids = if should_sources_default_to_head {
         match head_oid {
@@ -211,7 +222,7 @@ pub fn r#move(
                 writeln!(
                     effects.get_output_stream(),
                     "{}: you can omit the --base flag in this case, as it defaults to HEAD",
-                    style("hint").blue().bold()
+                    effects.get_glyphs().render_hint_label()?
                 )?;
             }
 
@@ -220,7 +231,7 @@ pub fn r#move(
                 writeln!(
                     effects.get_output_stream(),
                     "{}: you can omit the --dest flag in this case, as it defaults to HEAD",
-                    style("hint").blue().bold()
+                    effects.get_glyphs().render_hint_label()?
                 )?;
             }
 
@@ -233,10 +244,12 @@ pub fn r#move(
 
     let MoveOptions {
         force_rewrite_public_commits,
-        force_in_memory,
-        force_on_disk,
+        force_rewrite_protected_branches,
+        force_in_memory: _,
+        force_on_disk: _,
         detect_duplicate_commits_via_patch_id,
-        resolve_merge_conflicts,
+        resolve_merge_conflicts: _,
+        skip_confirmation: _,
         dump_rebase_constraints,
         dump_rebase_plan,
     } = *move_options;
@@ -247,6 +260,7 @@ pub fn r#move(
     let rebase_plan = {
         let build_options = BuildRebasePlanOptions {
             force_rewrite_public_commits,
+            force_rewrite_protected_branches,
             dump_rebase_constraints,
             dump_rebase_plan,
             detect_duplicate_commits_via_patch_id,
@@ -262,8 +276,12 @@ pub fn r#move(
                 commits_to_move
             };
 
-            match RebasePlanPermissions::verify_rewrite_set(&dag, &build_options, &commits_to_move)?
-            {
+            match RebasePlanPermissions::verify_rewrite_set(
+                &dag,
+                &repo,
+                &build_options,
+                &commits_to_move,
+            )? {
                 Ok(permissions) => permissions,
                 Err(err) => {
                     err.describe(effects, &repo)?;
@@ -452,32 +470,542 @@ pub fn r#move(
         }
         builder.build(effects, &pool, &repo_pool)?
     };
+    execute_move_plan(
+        effects,
+        git_run_info,
+        &repo,
+        &dag,
+        &event_log_db,
+        rebase_plan,
+        now,
+        event_tx_id,
+        move_options,
+        summary,
+    )
+}
+
+/// Find the commit that the fixup commit with the given target should be
+/// folded into, among the candidate commits in topological order. Like `git
+/// rebase --autosquash`, this prefers an exact subject match, then a commit hash
+/// prefix, then a subject prefix, and picks the earliest matching commit.
+fn find_fixup_target(candidates: &[(NonZeroOid, &str)], target: &str) -> Option<NonZeroOid> {
+    let is_hash_prefix = target.len() >= 4 && target.chars().all(|c| c.is_ascii_hexdigit());
+    candidates
+        .iter()
+        .find(|(_oid, subject)| *subject == target)
+        .or_else(|| {
+            if is_hash_prefix {
+                candidates
+                    .iter()
+                    .find(|(oid, _subject)| oid.to_string().starts_with(target))
+            } else {
+                None
+            }
+        })
+        .or_else(|| {
+            candidates
+                .iter()
+                .find(|(_oid, subject)| subject.starts_with(target))
+        })
+        .map(|(oid, _subject)| *oid)
+}
+
+/// Fold the `fixup!` and `squash!` commits in the current stack into the
+/// commits that they refer to, as with `git rebase --autosquash`.
+#[instrument]
+pub fn move_fixups(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    move_options: &MoveOptions,
+    summary: &mut RewriteSummary,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let stack = match resolve_commits(
+        effects,
+        git_run_info,
+        &repo,
+        &mut dag,
+        vec![Revset("stack()".to_string())],
+    ) {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let stack_commits = sorted_commit_set(&repo, &dag, &stack)?;
+    let subjects = stack_commits
+        .iter()
+        .map(|commit| -> eyre::Result<(NonZeroOid, String)> {
+            Ok((
+                commit.get_oid(),
+                commit.get_summary()?.to_str_lossy().into_owned(),
+            ))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    let mut fixups: Vec<(NonZeroOid, NonZeroOid, FixupKind)> = Vec::new();
+    for (i, (fixup_oid, subject)) in subjects.iter().enumerate() {
+        let (kind, target) = match FixupKind::parse_subject(subject) {
+            Some(fixup) => fixup,
+            None => continue,
+        };
+
+        // Only fold into ancestors which aren't themselves being folded.
+        let mut candidates = Vec::new();
+        for (candidate_oid, candidate_subject) in subjects[..i].iter() {
+            if FixupKind::parse_subject(candidate_subject).is_none()
+                && dag
+                    .query()
+                    .is_ancestor((*candidate_oid).into(), (*fixup_oid).into())?
+            {
+                candidates.push((*candidate_oid, candidate_subject.as_str()));
+            }
+        }
+        match find_fixup_target(&candidates, target) {
+            Some(target_oid) => fixups.push((*fixup_oid, target_oid, kind)),
+            None => {
+                let commit = repo.find_commit_or_fail(*fixup_oid)?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "Could not find the commit to fold into for: {}",
+                    printable_styled_string(
+                        effects.get_glyphs(),
+                        commit.friendly_describe(effects.get_glyphs())?
+                    )?
+                )?;
+            }
+        }
+    }
+    if fixups.is_empty() {
+        writeln!(effects.get_output_stream(), "Nothing to do.")?;
+        return Ok(ExitCode(0));
+    }
+
+    let MoveOptions {
+        force_rewrite_public_commits,
+        force_rewrite_protected_branches,
+        force_in_memory: _,
+        force_on_disk: _,
+        detect_duplicate_commits_via_patch_id,
+        resolve_merge_conflicts: _,
+        skip_confirmation: _,
+        dump_rebase_constraints,
+        dump_rebase_plan,
+    } = *move_options;
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "move")?;
+    let pool = ThreadPoolBuilder::new().build()?;
+    let repo_pool = RepoResource::new_pool(&repo)?;
+    let rebase_plan = {
+        let build_options = BuildRebasePlanOptions {
+            force_rewrite_public_commits,
+            force_rewrite_protected_branches,
+            dump_rebase_constraints,
+            dump_rebase_plan,
+            detect_duplicate_commits_via_patch_id,
+        };
+        let target_oids: CommitSet = fixups
+            .iter()
+            .map(|(_fixup_oid, target_oid, _kind)| *target_oid)
+            .collect();
+        let permissions = match RebasePlanPermissions::verify_rewrite_set(
+            &dag,
+            &repo,
+            &build_options,
+            &target_oids,
+        )? {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                err.describe(effects, &repo)?;
+                return Ok(ExitCode(1));
+            }
+        };
+        let mut builder = RebasePlanBuilder::new(&dag, permissions);
+        for (fixup_oid, target_oid, kind) in fixups {
+            builder.fixup_commit(fixup_oid, target_oid, kind)?;
+        }
+        builder.build(effects, &pool, &repo_pool)?
+    };
+
+    execute_move_plan(
+        effects,
+        git_run_info,
+        &repo,
+        &dag,
+        &event_log_db,
+        rebase_plan,
+        now,
+        event_tx_id,
+        move_options,
+        summary,
+    )
+}
+
+/// The metadata of a commit which `git am` preserves when applying it as a
+/// patch: the author's name, email and time, and the commit message.
+type PatchIdentity = (Option<String>, Option<String>, SystemTime, BString);
+
+fn get_patch_identity(commit: &Commit) -> eyre::Result<PatchIdentity> {
+    let author = commit.get_author();
+    Ok((
+        author.get_name().map(ToOwned::to_owned),
+        author.get_email().map(ToOwned::to_owned),
+        author.get_time().to_system_time()?,
+        commit.get_message_raw()?,
+    ))
+}
+
+/// Apply the given commits from this repository onto the commit checked out in
+/// the repository at `target_repo_path`, by exporting them as patches and
+/// applying them there with `git am`. Commits which were already applied to
+/// that repository by a previous invocation are skipped, as detected by their
+/// patch IDs. This includes commits applied with `git am --continue` after a
+/// previous invocation stopped partway through.
+#[instrument]
+pub fn move_to_repo(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    sources: Vec<Revset>,
+    target_repo_path: &Path,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let applied_patches_db = AppliedPatchesDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let sources = if sources.is_empty() {
+        vec![Revset("HEAD".to_string())]
+    } else {
+        sources
+    };
+    let source_oids = match resolve_commits(effects, git_run_info, &repo, &mut dag, sources) {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let commits_to_apply = dag
+        .query()
+        .descendants(source_oids)?
+        .difference(&dag.obsolete_commits);
+    let commits_to_apply = sorted_commit_set(&repo, &dag, &commits_to_apply)?;
+    for (parent, child) in commits_to_apply.iter().tuple_windows() {
+        if child.get_parent_oids() != vec![parent.get_oid()] {
+            writeln!(
+                effects.get_error_stream(),
+                "Moving commits to another repository is only supported for a single lineage of commits,\n\
+                 but {} is not the only parent of {}.",
+                parent.get_oid(),
+                child.get_oid(),
+            )?;
+            return Ok(ExitCode(1));
+        }
+    }
+
+    let target_repo = match Repo::from_dir(target_repo_path) {
+        Ok(target_repo) => target_repo,
+        Err(err) => {
+            writeln!(
+                effects.get_error_stream(),
+                "Could not open the repository at {}: {}",
+                target_repo_path.display(),
+                err
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let target_repo_key = target_repo.get_path().to_owned();
+
+    // If applying patches stopped partway through last time, then the user may
+    // have applied the rest with `git am --continue` since then, so find those
+    // commits by their patch IDs. The commit which stopped may have been
+    // changed to resolve a conflict, so also match commits by the metadata
+    // which `git am` preserves.
+    let mut pending_applied_commits: Vec<(NonZeroOid, Option<PatchId>, PatchIdentity)> = Vec::new();
+    if let Some(base_commit_oid) = applied_patches_db.take_pending_base(&target_repo_key)? {
+        if let Some(target_head_oid) = target_repo.get_head_info()?.oid {
+            if target_repo.find_merge_base(base_commit_oid, target_head_oid)?
+                == Some(base_commit_oid)
+            {
+                let mut current_oid = target_head_oid;
+                while current_oid != base_commit_oid {
+                    let current_commit = target_repo.find_commit_or_fail(current_oid)?;
+                    pending_applied_commits.push((
+                        current_oid,
+                        target_repo.get_patch_id(effects, &current_commit)?,
+                        get_patch_identity(&current_commit)?,
+                    ));
+                    match current_commit.get_parent_oids().first() {
+                        Some(parent_oid) => current_oid = *parent_oid,
+                        None => break,
+                    }
+                }
+            }
+        }
+    }
+
+    let mut patch_ids = HashMap::new();
+    let mut pending_applied_patches = Vec::new();
+    let mut mbox = Vec::new();
+    for commit in commits_to_apply.iter() {
+        let commit_description = printable_styled_string(
+            effects.get_glyphs(),
+            commit.friendly_describe(effects.get_glyphs())?,
+        )?;
+        let patch_id = match repo.get_patch_id(effects, commit)? {
+            Some(patch_id) => patch_id,
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Skipped commit (has no changes to apply): {}",
+                    commit_description
+                )?;
+                continue;
+            }
+        };
+        let patch_identity = get_patch_identity(commit)?;
+        if let Some((target_commit_oid, _, _)) = pending_applied_commits.iter().find(
+            |(_target_commit_oid, target_patch_id, target_patch_identity)| {
+                target_patch_id == &Some(patch_id) || target_patch_identity == &patch_identity
+            },
+        ) {
+            let target_commit = target_repo.find_commit_or_fail(*target_commit_oid)?;
+            writeln!(
+                effects.get_output_stream(),
+                "Skipped commit (already applied as {}): {}",
+                target_commit.get_short_oid()?,
+                commit_description
+            )?;
+            pending_applied_patches.push(AppliedPatch {
+                patch_id,
+                source_commit_oid: commit.get_oid(),
+                target_commit_oid: *target_commit_oid,
+            });
+            continue;
+        }
+        if let Some(AppliedPatch {
+            target_commit_oid, ..
+        }) = applied_patches_db.get(&target_repo_key, patch_id)?
+        {
+            if let Some(target_commit) = target_repo.find_commit(target_commit_oid)? {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Skipped commit (already applied as {}): {}",
+                    target_commit.get_short_oid()?,
+                    commit_description
+                )?;
+                continue;
+            }
+        }
+
+        let GitRunResult {
+            exit_code,
+            stdout,
+            stderr,
+        } = git_run_info.run_silent(
+            &repo,
+            None,
+            &[
+                "format-patch",
+                "-1",
+                "--stdout",
+                "--keep-subject",
+                &commit.get_oid().to_string(),
+            ],
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                stdin: None,
+            },
+        )?;
+        if exit_code != 0 {
+            writeln!(
+                effects.get_error_stream(),
+                "Failed to export commit {} as a patch:\n{}",
+                commit.get_oid(),
+                String::from_utf8_lossy(&stderr)
+            )?;
+            return Ok(ExitCode(exit_code.try_into()?));
+        }
+        mbox.extend(stdout);
+        patch_ids.insert(commit.get_oid(), patch_id);
+    }
+    applied_patches_db.add_many(now, &target_repo_key, &pending_applied_patches)?;
+    let commits_to_apply = commits_to_apply
+        .into_iter()
+        .filter(|commit| patch_ids.contains_key(&commit.get_oid()))
+        .collect_vec();
+    if commits_to_apply.is_empty() {
+        writeln!(effects.get_output_stream(), "Nothing to do.")?;
+        return Ok(ExitCode(0));
+    }
+
+    let original_target_head_oid = target_repo.get_head_info()?.oid;
+    let GitRunResult {
+        exit_code,
+        stdout,
+        stderr,
+    } = git_run_info.run_silent(
+        &target_repo,
+        None,
+        &["am", "--3way", "--keep", "--keep-cr"],
+        GitRunOpts {
+            treat_git_failure_as_error: false,
+            stdin: Some(mbox),
+        },
+    )?;
+
+    // Record whichever commits were applied, even if applying a later commit
+    // failed, so that they're skipped when trying again.
+    let applied_commit_oids = {
+        let mut result = Vec::new();
+        let mut current_oid = target_repo.get_head_info()?.oid;
+        while current_oid != original_target_head_oid && result.len() < commits_to_apply.len() {
+            let current_commit = match current_oid {
+                Some(current_oid) => target_repo.find_commit_or_fail(current_oid)?,
+                None => break,
+            };
+            result.push(current_commit.get_oid());
+            current_oid = current_commit.get_only_parent_oid();
+        }
+        result.reverse();
+        result
+    };
+    let applied_patches = commits_to_apply
+        .iter()
+        .zip(applied_commit_oids.iter())
+        .map(|(commit, target_commit_oid)| AppliedPatch {
+            patch_id: patch_ids[&commit.get_oid()],
+            source_commit_oid: commit.get_oid(),
+            target_commit_oid: *target_commit_oid,
+        })
+        .collect_vec();
+    applied_patches_db.add_many(now, &target_repo_key, &applied_patches)?;
+
+    for (i, applied_patch) in applied_patches.iter().enumerate() {
+        let target_commit = target_repo.find_commit_or_fail(applied_patch.target_commit_oid)?;
+        writeln!(
+            effects.get_output_stream(),
+            "[{}/{}] Applied as: {}",
+            i + 1,
+            commits_to_apply.len(),
+            printable_styled_string(
+                effects.get_glyphs(),
+                target_commit.friendly_describe(effects.get_glyphs())?
+            )?
+        )?;
+    }
+
+    if exit_code != 0 {
+        if let Some(target_head_oid) = target_repo.get_head_info()?.oid {
+            applied_patches_db.set_pending_base(&target_repo_key, target_head_oid)?;
+        }
+        write!(
+            effects.get_error_stream(),
+            "{}{}",
+            String::from_utf8_lossy(&stdout),
+            String::from_utf8_lossy(&stderr)
+        )?;
+        writeln!(
+            effects.get_error_stream(),
+            "Failed to apply all commits to the repository at {}.\n\
+             Resolve the problem there and run `git am --continue`, or run `git am --abort` to cancel.",
+            target_repo_path.display()
+        )?;
+        return Ok(ExitCode(exit_code.try_into()?));
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Applied {} to the repository at {}.",
+        Pluralize {
+            determiner: None,
+            amount: applied_patches.len(),
+            unit: ("commit", "commits"),
+        },
+        target_repo_path.display()
+    )?;
+    Ok(ExitCode(0))
+}
+
+/// Execute the rebase plan built for a move, and report its outcome.
+#[allow(clippy::too_many_arguments)]
+fn execute_move_plan(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    rebase_plan: Result<Option<RebasePlan>, BuildRebasePlanError>,
+    now: SystemTime,
+    event_tx_id: EventTransactionId,
+    move_options: &MoveOptions,
+    summary: &mut RewriteSummary,
+) -> eyre::Result<ExitCode> {
     let result = match rebase_plan {
         Ok(None) => {
             writeln!(effects.get_output_stream(), "Nothing to do.")?;
             return Ok(ExitCode(0));
         }
         Ok(Some(rebase_plan)) => {
+            if !confirm_large_rewrite(
+                effects,
+                repo,
+                dag,
+                &rebase_plan
+                    .get_rewritten_commit_oids()
+                    .into_iter()
+                    .collect(),
+                move_options.skip_confirmation,
+            )? {
+                return Ok(ExitCode(1));
+            }
+            // Any previously-suspended operation is superseded by this one.
+            event_log_db.clear_suspended_operation()?;
             let options = ExecuteRebasePlanOptions {
                 now,
                 event_tx_id,
-                preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
-                force_in_memory,
-                force_on_disk,
-                resolve_merge_conflicts,
+                preserve_timestamps: get_restack_preserve_timestamps(repo)?,
+                force_in_memory: move_options.force_in_memory,
+                force_on_disk: move_options.force_on_disk,
+                resolve_merge_conflicts: move_options.resolve_merge_conflicts,
                 check_out_commit_options: Default::default(),
             };
             execute_rebase_plan(
                 effects,
                 git_run_info,
-                &repo,
-                &event_log_db,
+                repo,
+                event_log_db,
                 &rebase_plan,
                 &options,
             )?
         }
         Err(err) => {
-            err.describe(effects, &repo)?;
+            err.describe(effects, repo)?;
             return Ok(ExitCode(1));
         }
     };
@@ -486,10 +1014,21 @@ pub fn r#move(
         ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => Ok(ExitCode(0)),
 
         ExecuteRebasePlanResult::DeclinedToMerge { merge_conflict } => {
-            merge_conflict.describe(effects, &repo, MergeConflictRemediation::Retry)?;
-            Ok(ExitCode(1))
+            merge_conflict.describe(effects, repo, MergeConflictRemediation::Retry)?;
+            summary.add_conflict(&merge_conflict);
+            Ok(ErrorCategory::Conflict.exit_code())
         }
 
-        ExecuteRebasePlanResult::Failed { exit_code } => Ok(exit_code),
+        ExecuteRebasePlanResult::Failed { exit_code } => {
+            if repo.get_current_operation_type() == Some("rebase") {
+                event_log_db.set_suspended_operation(&SuspendedOperation {
+                    command_name: "move".to_string(),
+                    event_tx_id,
+                    resume_args: Vec::new(),
+                    is_rebase_in_progress: true,
+                })?;
+            }
+            Ok(exit_code)
+        }
     }
 }
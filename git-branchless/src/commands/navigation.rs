@@ -6,27 +6,33 @@ use std::ffi::OsString;
 use std::fmt::Write;
 use std::time::SystemTime;
 
-use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use eden_dag::DagAlgorithm;
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
-use lib::core::repo_ext::RepoExt;
+use lib::core::metadata::CommitMetadataDb;
+use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
+use lib::core::rewrite::RewriteMap;
+use lib::core::topics::infer_stack_topics;
 use lib::util::ExitCode;
 use tracing::{instrument, warn};
 
 use crate::commands::smartlog::make_smartlog_graph;
-use crate::opts::{CheckoutOptions, TraverseCommitsOptions};
+use crate::opts::{CheckoutOptions, Revset, TraverseCommitsOptions};
+use crate::revset::{parse, resolve_commits, Expr};
 use crate::tui::prompt_select_commit;
 use lib::core::config::get_next_interactive;
-use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
+use lib::core::dag::{commit_set_to_vec_unsorted, sorted_commit_set, CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
 use lib::core::formatting::{printable_styled_string, Pluralize};
 use lib::core::node_descriptors::{
     BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
     DifferentialRevisionDescriptor, NodeDescriptor, Redactor, RelativeTimeDescriptor,
 };
-use lib::git::{GitRunInfo, NonZeroOid, Repo};
+use lib::git::{
+    CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, ReferenceUpdate,
+    Repo, ResolvedReferenceInfo,
+};
 
 /// The command being invoked, indicating which direction to traverse commits.
 #[derive(Clone, Copy, Debug)]
@@ -84,18 +90,7 @@ fn advance(
     distance: Distance,
     towards: Option<Towards>,
 ) -> eyre::Result<Option<NonZeroOid>> {
-    let towards = match towards {
-        Some(towards) => Some(towards),
-        None => {
-            if get_next_interactive(repo)? {
-                Some(Towards::Interactive)
-            } else {
-                None
-            }
-        }
-    };
-
-    let public_commits = dag.query().ancestors(dag.main_branch_commit.clone())?;
+    let public_commits = dag.query_public_commits()?;
 
     let glyphs = effects.get_glyphs();
     let mut current_oid = current_oid;
@@ -238,7 +233,7 @@ fn advance(
                                 "No more {} commits to go to after traversing {}.",
                                 pluralize.unit.0, pluralize,
                             ),
-                            BaseColor::Yellow.light()
+                            glyphs.theme.warning
                         )
                     )?
                 )?;
@@ -258,6 +253,7 @@ fn advance(
             (Some(Towards::Oldest), [oldest_child, ..]) => oldest_child.get_oid(),
             (Some(Towards::Interactive), [_, _, ..]) => {
                 match prompt_select_commit(
+                    effects,
                     Some(&header),
                     "",
                     candidate_commits,
@@ -313,8 +309,10 @@ pub fn traverse_commits(
         oldest,
         newest,
         interactive,
+        no_interactive,
         merge,
         force,
+        with_branch,
     } = *options;
 
     let distance = match (all_the_way, num_commits) {
@@ -347,7 +345,31 @@ pub fn traverse_commits(
 
     let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
+    let towards = match towards {
+        Some(towards) => Some(towards),
+        None if no_interactive => None,
+        None if get_next_interactive(&repo)? || should_prompt(no_interactive) => {
+            Some(Towards::Interactive)
+        }
+        None => None,
+    };
     let head_info = repo.get_head_info()?;
+    let branch_to_move = if with_branch {
+        match &head_info.reference_name {
+            Some(reference_name) if head_info.get_branch_name()?.is_some() => {
+                Some(reference_name.clone())
+            }
+            _ => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "No branch is checked out, so there is no branch to move with --with-branch."
+                )?;
+                return Ok(ExitCode(1));
+            }
+        }
+    } else {
+        None
+    };
     let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
@@ -401,6 +423,31 @@ pub fn traverse_commits(
         Some(current_oid) => current_oid,
     };
 
+    let additional_args = {
+        let mut args: Vec<OsString> = Vec::new();
+        if merge {
+            args.push("--merge".into());
+        }
+        if force {
+            args.push("--force".into())
+        }
+        args
+    };
+
+    if let Some(branch_to_move) = branch_to_move {
+        return move_branch_and_check_out(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            event_tx_id,
+            branch_to_move,
+            head_oid,
+            current_oid,
+            additional_args,
+        );
+    }
+
     let checkout_target: CheckoutTarget = match distance {
         Distance::AllTheWay {
             move_by_branches: false,
@@ -436,16 +483,6 @@ pub fn traverse_commits(
         }
     };
 
-    let additional_args = {
-        let mut args: Vec<OsString> = Vec::new();
-        if merge {
-            args.push("--merge".into());
-        }
-        if force {
-            args.push("--force".into())
-        }
-        args
-    };
     check_out_commit(
         effects,
         git_run_info,
@@ -460,70 +497,268 @@ pub fn traverse_commits(
     )
 }
 
+/// Move the checked-out branch from `head_oid` to `dest_oid` and check it out
+/// again, for `--with-branch`.
+///
+/// The destination commit is checked out first, so that the branch isn't
+/// moved if the working copy can't be updated (such as if there are
+/// conflicting local changes). If the branch can't be moved afterwards, then
+/// it's checked out again where it was.
+#[instrument]
+fn move_branch_and_check_out(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    branch_name: ReferenceName,
+    head_oid: NonZeroOid,
+    dest_oid: NonZeroOid,
+    additional_args: Vec<OsString>,
+) -> eyre::Result<ExitCode> {
+    if dest_oid != head_oid {
+        let exit_code = check_out_commit(
+            effects,
+            git_run_info,
+            repo,
+            event_log_db,
+            event_tx_id,
+            Some(CheckoutTarget::Oid(dest_oid)),
+            &CheckOutCommitOptions {
+                additional_args,
+                render_smartlog: false,
+            },
+        )?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+
+        let branch_move = ReferenceUpdate {
+            ref_name: branch_name.clone(),
+            old_oid: MaybeZeroOid::NonZero(head_oid),
+            new_oid: MaybeZeroOid::NonZero(dest_oid),
+        };
+        if let Err(err) =
+            git_run_info.update_references(effects, repo, event_tx_id, &[branch_move])?
+        {
+            // The branch wasn't moved, so go back to it rather than leaving
+            // `HEAD` detached at the destination.
+            check_out_commit(
+                effects,
+                git_run_info,
+                repo,
+                event_log_db,
+                event_tx_id,
+                Some(CheckoutTarget::Reference(branch_name.clone())),
+                &CheckOutCommitOptions {
+                    additional_args: Default::default(),
+                    render_smartlog: false,
+                },
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "Failed to move branch {}:\n{}",
+                CategorizedReferenceName::new(&branch_name).render_suffix(),
+                err
+            )?;
+            return Ok(ExitCode(1));
+        }
+    }
+
+    check_out_commit(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        event_tx_id,
+        Some(CheckoutTarget::Reference(branch_name)),
+        &CheckOutCommitOptions::default(),
+    )
+}
+
 fn get_initial_query(checkout_options: &CheckoutOptions) -> Option<&str> {
     match checkout_options {
         CheckoutOptions {
             interactive: true,
+            no_interactive: _,
             target: Some(target),
             branch_name: _,
+            detach: _,
             force: _,
             merge: _,
+            worktree: _,
+            topic: _,
         } => Some(target),
 
         CheckoutOptions {
             interactive: false,
+            no_interactive: _,
             target: Some(_),
             branch_name: None,
+            detach: _,
             force: false,
             merge: false,
+            worktree: _,
+            topic: _,
         } => None,
 
         CheckoutOptions {
             interactive: true,
+            no_interactive: _,
             target: None,
             branch_name: _,
+            detach: _,
             force: _,
             merge: _,
+            worktree: _,
+            topic: _,
         }
         | CheckoutOptions {
             interactive: false,
+            no_interactive: _,
             target: None,
             branch_name: None,
+            detach: _,
             force: false,
             merge: false,
+            worktree: _,
+            topic: _,
         } => Some(""),
 
         CheckoutOptions {
             interactive: false,
+            no_interactive: _,
             target: Some(_),
             branch_name: _,
+            detach: _,
             force: _,
             merge: _,
+            worktree: _,
+            topic: _,
         }
         | CheckoutOptions {
             interactive: false,
+            no_interactive: _,
             target: _,
             branch_name: Some(_),
+            detach: _,
             force: _,
             merge: _,
+            worktree: _,
+            topic: _,
         }
         | CheckoutOptions {
             interactive: false,
+            no_interactive: _,
             target: _,
             branch_name: _,
+            detach: _,
             force: true,
             merge: _,
+            worktree: _,
+            topic: _,
         }
         | CheckoutOptions {
             interactive: false,
+            no_interactive: _,
             target: _,
             branch_name: _,
+            detach: _,
             force: _,
             merge: true,
+            worktree: _,
+            topic: _,
         } => None,
     }
 }
 
+/// Determine what to check out for the target provided by the user.
+///
+/// Unless detaching, targets which Git understands, such as branch names, are
+/// passed through to Git as-is, so that branches are checked out as branches.
+/// Other targets are evaluated as revsets, which must contain exactly one
+/// commit. If the revset contains multiple commits, the user is prompted to
+/// select one instead, when possible. Returns `None` if an error was reported
+/// to the user or they cancelled the prompt.
+#[allow(clippy::too_many_arguments)]
+fn resolve_checkout_target(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &mut Dag,
+    head_info: &ResolvedReferenceInfo,
+    references_snapshot: &RepoReferencesSnapshot,
+    target: &str,
+    detach: bool,
+    no_interactive: bool,
+) -> eyre::Result<Option<CheckoutTarget>> {
+    if !detach {
+        let is_git_target = matches!(repo.revparse_single_commit(target), Ok(Some(_)))
+            || matches!(parse(target), Ok(Expr::Name(_, _)));
+        if is_git_target {
+            return Ok(Some(CheckoutTarget::Unknown(target.to_owned())));
+        }
+    }
+
+    let revset = Revset(target.to_owned());
+    let commits = match resolve_commits(effects, git_run_info, repo, dag, vec![revset]) {
+        Ok(commit_sets) => commit_sets[0].clone(),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(None);
+        }
+    };
+    match commit_set_to_vec_unsorted(&commits)?.as_slice() {
+        [oid] => Ok(Some(CheckoutTarget::Oid(*oid))),
+        [] => {
+            writeln!(
+                effects.get_error_stream(),
+                "Revset did not contain any commits: {}",
+                target
+            )?;
+            Ok(None)
+        }
+        oids if !should_prompt(no_interactive) => {
+            writeln!(
+                effects.get_error_stream(),
+                "Revset must contain exactly 1 commit to check out (got {}): {}",
+                oids.len(),
+                target
+            )?;
+            Ok(None)
+        }
+        _ => {
+            let header = format!("Found multiple commits matching {target}:");
+            let oid = prompt_select_commit(
+                effects,
+                Some(&header),
+                "",
+                sorted_commit_set(repo, dag, &commits)?,
+                &mut [
+                    &mut CommitOidDescriptor::new(true)?,
+                    &mut RelativeTimeDescriptor::new(repo, SystemTime::now())?,
+                    &mut BranchesDescriptor::new(
+                        repo,
+                        head_info,
+                        references_snapshot,
+                        &Redactor::Disabled,
+                    )?,
+                    &mut DifferentialRevisionDescriptor::new(repo, &Redactor::Disabled)?,
+                    &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
+                ],
+            )?;
+            Ok(oid.map(CheckoutTarget::Oid))
+        }
+    }
+}
+
+/// Whether to interactively prompt the user to choose between ambiguous
+/// commits, rather than failing, when they didn't say how to choose. Prompts
+/// are only shown when running in a terminal.
+fn should_prompt(no_interactive: bool) -> bool {
+    !no_interactive && console::user_attended()
+}
+
 /// Interactively checkout a commit from the smartlog.
 pub fn checkout(
     effects: &Effects,
@@ -532,14 +767,44 @@ pub fn checkout(
 ) -> eyre::Result<ExitCode> {
     let CheckoutOptions {
         interactive: _,
+        no_interactive,
         branch_name,
+        detach,
         force,
         merge,
+        worktree,
+        topic,
         target,
     } = checkout_options;
 
     let now = SystemTime::now();
-    let repo = Repo::from_current_dir()?;
+    let (repo, git_run_info) = match worktree {
+        None => (Repo::from_current_dir()?, git_run_info.clone()),
+        Some(worktree_name) => {
+            let repo = Repo::from_current_dir()?;
+            let worktree = repo
+                .get_worktrees()?
+                .into_iter()
+                .find(|worktree| &worktree.name == worktree_name);
+            match worktree {
+                Some(worktree) => (
+                    Repo::from_dir(&worktree.path)?,
+                    GitRunInfo {
+                        working_directory: worktree.path,
+                        ..git_run_info.clone()
+                    },
+                ),
+                None => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "There is no worktree named: {}",
+                        worktree_name
+                    )?;
+                    return Ok(ExitCode(1));
+                }
+            }
+        }
+    };
     let head_info = repo.get_head_info()?;
     let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
@@ -547,7 +812,7 @@ pub fn checkout(
     let event_tx_id = event_log_db.make_transaction_id(now, "checkout")?;
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let event_cursor = event_replayer.make_default_cursor();
-    let dag = Dag::open_and_sync(
+    let mut dag = Dag::open_and_sync(
         effects,
         &repo,
         &event_replayer,
@@ -566,10 +831,47 @@ pub fn checkout(
     )?;
 
     let initial_query = get_initial_query(checkout_options);
-    let target: Option<CheckoutTarget> = match initial_query {
-        None => target.clone().map(CheckoutTarget::Unknown),
-        Some(initial_query) => {
+    let target: Option<CheckoutTarget> = match (topic, initial_query) {
+        (Some(topic_name), _) => {
+            let commit_metadata = {
+                let rewrite_map = RewriteMap::from_event_replayer(&event_replayer, event_cursor);
+                CommitMetadataDb::new(&conn)?.get_all(&rewrite_map)?
+            };
+            let topics = infer_stack_topics(&repo, &dag, &references_snapshot, &commit_metadata)?;
+            match topics.into_iter().find(|topic| &topic.name == topic_name) {
+                Some(topic) => Some(CheckoutTarget::Oid(topic.head_oid)),
+                None => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "There is no stack with the topic: {}",
+                        topic_name
+                    )?;
+                    return Ok(ExitCode(1));
+                }
+            }
+        }
+        (None, None) => match target {
+            None => None,
+            Some(target) => {
+                match resolve_checkout_target(
+                    effects,
+                    &git_run_info,
+                    &repo,
+                    &mut dag,
+                    &head_info,
+                    &references_snapshot,
+                    target,
+                    *detach,
+                    *no_interactive,
+                )? {
+                    Some(target) => Some(target),
+                    None => return Ok(ExitCode(1)),
+                }
+            }
+        },
+        (None, Some(initial_query)) => {
             match prompt_select_commit(
+                effects,
                 None,
                 initial_query,
                 graph.get_commits(),
@@ -609,7 +911,7 @@ pub fn checkout(
 
     let exit_code = check_out_commit(
         effects,
-        git_run_info,
+        &git_run_info,
         &repo,
         &event_log_db,
         event_tx_id,
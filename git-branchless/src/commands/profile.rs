@@ -0,0 +1,176 @@
+//! Collect the time spent in each `tracing` span and print it as a
+//! hierarchical breakdown, for use with `--profile`.
+//!
+//! Spans with the same name and the same ancestors are aggregated into a
+//! single entry, so that a function called in a loop is reported once along
+//! with its number of calls.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::span;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Entries which took less than this fraction of the total time are omitted
+/// from the report, since a full breakdown can include hundreds of spans.
+const MIN_REPORTED_FRACTION: f64 = 0.01;
+
+#[derive(Debug, Default)]
+struct ProfileNode {
+    num_calls: usize,
+    duration: Duration,
+
+    /// The child nodes, in the order in which they were first closed.
+    children: Vec<(&'static str, ProfileNode)>,
+}
+
+impl ProfileNode {
+    fn record(&mut self, path: &[&'static str], duration: Duration) {
+        match path {
+            [] => {
+                self.num_calls += 1;
+                self.duration += duration;
+            }
+            [name, rest @ ..] => {
+                let index = match self
+                    .children
+                    .iter()
+                    .position(|(child_name, _child)| child_name == name)
+                {
+                    Some(index) => index,
+                    None => {
+                        self.children.push((name, ProfileNode::default()));
+                        self.children.len() - 1
+                    }
+                };
+                self.children[index].1.record(rest, duration);
+            }
+        }
+    }
+}
+
+/// The names of a span and all of its ancestors, starting from the root.
+struct SpanPath(Vec<&'static str>);
+
+/// The time at which a span was created.
+struct SpanStart(Instant);
+
+/// A layer which records the duration of each span.
+pub struct ProfileLayer {
+    root: Arc<Mutex<ProfileNode>>,
+}
+
+impl<S> Layer<S> for ProfileLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut path = match span.parent() {
+            Some(parent) => match parent.extensions().get::<SpanPath>() {
+                Some(SpanPath(parent_path)) => parent_path.clone(),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        };
+        path.push(attrs.metadata().name());
+
+        let mut extensions = span.extensions_mut();
+        extensions.insert(SpanPath(path));
+        extensions.insert(SpanStart(Instant::now()));
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let extensions = span.extensions();
+        if let (Some(SpanPath(path)), Some(SpanStart(start))) =
+            (extensions.get::<SpanPath>(), extensions.get::<SpanStart>())
+        {
+            let duration = start.elapsed();
+            if let Ok(mut root) = self.root.lock() {
+                root.record(path, duration);
+            }
+        }
+    }
+}
+
+/// Prints the collected timings to stderr when dropped.
+pub struct ProfileGuard {
+    start: Instant,
+    root: Arc<Mutex<ProfileNode>>,
+}
+
+impl Drop for ProfileGuard {
+    fn drop(&mut self) {
+        let total_duration = self.start.elapsed();
+        let root = match self.root.lock() {
+            Ok(root) => root,
+            Err(_) => return,
+        };
+        let mut lines = Vec::new();
+        render_nodes(&root.children, 1, total_duration, &mut lines);
+
+        let name_width = lines
+            .iter()
+            .map(|(name, _duration, _num_calls)| name.len())
+            .max()
+            .unwrap_or_default();
+        eprintln!("Profile (total {}):", format_duration(total_duration));
+        for (name, duration, num_calls) in lines {
+            eprintln!(
+                "{:name_width$}  {:>10}  {} {}",
+                name,
+                format_duration(duration),
+                num_calls,
+                if num_calls == 1 { "call" } else { "calls" },
+                name_width = name_width,
+            );
+        }
+    }
+}
+
+fn render_nodes(
+    nodes: &[(&'static str, ProfileNode)],
+    depth: usize,
+    total_duration: Duration,
+    lines: &mut Vec<(String, Duration, usize)>,
+) {
+    for (name, node) in nodes {
+        if node.duration.as_secs_f64() < total_duration.as_secs_f64() * MIN_REPORTED_FRACTION {
+            continue;
+        }
+        lines.push((
+            format!("{}{}", "  ".repeat(depth), name),
+            node.duration,
+            node.num_calls,
+        ));
+        render_nodes(&node.children, depth + 1, total_duration, lines);
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Create a layer to collect span timings, and a guard which prints them when
+/// dropped.
+pub fn make_profile_layer() -> (ProfileLayer, ProfileGuard) {
+    let root: Arc<Mutex<ProfileNode>> = Default::default();
+    let layer = ProfileLayer {
+        root: Arc::clone(&root),
+    };
+    let guard = ProfileGuard {
+        start: Instant::now(),
+        root,
+    };
+    (layer, guard)
+}
@@ -0,0 +1,347 @@
+//! Clean up local branches whose work has landed upstream, or whose upstream
+//! branch was deleted, such as after a pull request was merged.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io::{stdin, BufRead};
+use std::time::SystemTime;
+
+use eden_dag::DagAlgorithm;
+use itertools::Itertools;
+use lib::core::config::get_main_branch_name;
+use lib::core::dag::{commit_set_to_vec_unsorted, sorted_commit_set, union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::formatting::{printable_styled_string, Pluralize};
+use lib::core::patch_ids::get_patch_ids;
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{RepoPool, RepoResource};
+use lib::git::{
+    CategorizedReferenceName, ConfigRead, GitRunInfo, MaybeZeroOid, NonZeroOid, PatchId,
+    ReferenceName, ReferenceUpdate, Repo,
+};
+use lib::util::ExitCode;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tracing::instrument;
+
+/// Why a branch is being pruned.
+#[derive(Debug)]
+enum PruneReason {
+    /// The branch tracked a remote branch which no longer exists.
+    UpstreamDeleted { upstream_name: String },
+
+    /// All of the branch's commits are already in the main branch, either
+    /// directly or as commits with the same patch IDs.
+    Merged,
+}
+
+#[derive(Debug)]
+struct BranchToPrune {
+    reference_name: ReferenceName,
+    branch_name: String,
+    oid: NonZeroOid,
+    reason: PruneReason,
+}
+
+/// Get the name of the remote branch which the given local branch is
+/// configured to track, if any, regardless of whether it still exists.
+fn get_configured_upstream_name(repo: &Repo, branch_name: &str) -> eyre::Result<Option<String>> {
+    let config = repo.get_readonly_config()?;
+    let remote: Option<String> = config.get(format!("branch.{}.remote", branch_name))?;
+    let merge: Option<String> = config.get(format!("branch.{}.merge", branch_name))?;
+    let (remote, merge) = match (remote, merge) {
+        (Some(remote), Some(merge)) => (remote, merge),
+        _ => return Ok(None),
+    };
+    if remote == "." {
+        // The branch tracks another local branch.
+        return Ok(None);
+    }
+    let merge = merge.strip_prefix("refs/heads/").unwrap_or(&merge);
+    Ok(Some(format!("{}/{}", remote, merge)))
+}
+
+/// Determine whether all of the commits on the branch (that aren't already in
+/// the main branch) have equivalents in the main branch. A branch with no
+/// commits of its own (i.e. which points at or behind its fork point from the
+/// main branch) is not considered merged, since it may have just been created.
+fn is_merged(
+    effects: &Effects,
+    pool: &ThreadPool,
+    repo_pool: &RepoPool,
+    repo: &Repo,
+    dag: &Dag,
+    branch_oid: NonZeroOid,
+) -> eyre::Result<bool> {
+    let branch_commits = dag
+        .query()
+        .only(CommitSet::from(branch_oid), dag.main_branch_commit.clone())?;
+    let branch_oids = commit_set_to_vec_unsorted(&branch_commits)?;
+    if branch_oids.is_empty() {
+        return Ok(false);
+    }
+
+    let upstream_commits = dag
+        .query()
+        .only(dag.main_branch_commit.clone(), CommitSet::from(branch_oid))?;
+    let upstream_oids = commit_set_to_vec_unsorted(&upstream_commits)?;
+    let patch_ids = get_patch_ids(
+        effects,
+        pool,
+        repo_pool,
+        repo,
+        &branch_oids
+            .iter()
+            .chain(upstream_oids.iter())
+            .copied()
+            .collect_vec(),
+    )?;
+    let upstream_patch_ids: HashSet<PatchId> = upstream_oids
+        .iter()
+        .filter_map(|oid| patch_ids.get(oid).copied())
+        .collect();
+    Ok(branch_oids.iter().all(|oid| match patch_ids.get(oid) {
+        Some(patch_id) => upstream_patch_ids.contains(patch_id),
+        None => false,
+    }))
+}
+
+/// Find local branches which can be pruned.
+fn find_branches_to_prune(
+    effects: &Effects,
+    repo: &Repo,
+    dag: &Dag,
+) -> eyre::Result<Vec<BranchToPrune>> {
+    let main_branch_name = get_main_branch_name(repo)?;
+    let pool = ThreadPoolBuilder::new().build()?;
+    let repo_pool = RepoResource::new_pool(repo)?;
+    let mut result = Vec::new();
+    for branch in repo.get_all_local_branches()? {
+        let upstream_branch = branch.get_upstream_branch()?;
+        let oid = match branch.get_oid()? {
+            Some(oid) => oid,
+            None => continue,
+        };
+        let reference_name = branch.into_reference().get_name()?;
+        let branch_name = CategorizedReferenceName::new(&reference_name).render_suffix();
+        if branch_name == main_branch_name {
+            continue;
+        }
+
+        let reason = match get_configured_upstream_name(repo, &branch_name)? {
+            Some(upstream_name) if upstream_branch.is_none() => {
+                Some(PruneReason::UpstreamDeleted { upstream_name })
+            }
+            _ if is_merged(effects, &pool, &repo_pool, repo, dag, oid)? => {
+                Some(PruneReason::Merged)
+            }
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            result.push(BranchToPrune {
+                reference_name,
+                branch_name,
+                oid,
+                reason,
+            });
+        }
+    }
+    result.sort_by(|lhs, rhs| lhs.branch_name.cmp(&rhs.branch_name));
+    Ok(result)
+}
+
+/// Delete local branches which were merged or deleted upstream, and hide their
+/// commits.
+#[instrument]
+pub fn prune_remote(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    fetch: bool,
+    dry_run: bool,
+    yes: bool,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(now, "prune-remote")?;
+
+    if fetch {
+        let exit_code = git_run_info.run(effects, Some(event_tx_id), &["fetch", "--prune"])?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+    }
+
+    let references_snapshot = repo.get_references_snapshot()?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let branches_to_prune = find_branches_to_prune(effects, &repo, &dag)?;
+    if branches_to_prune.is_empty() {
+        writeln!(effects.get_output_stream(), "No branches to prune.")?;
+        return Ok(ExitCode(0));
+    }
+
+    // Hide the commits which are only reachable from the pruned branches. Any
+    // commits which are still needed by another branch or by `HEAD` are kept.
+    let head_info = repo.get_head_info()?;
+    let pruned_branch_oids: CommitSet = branches_to_prune.iter().map(|branch| branch.oid).collect();
+    let kept_heads: CommitSet = references_snapshot
+        .branch_oid_to_names
+        .iter()
+        .filter(|(_oid, names)| {
+            names.iter().any(|name| {
+                !branches_to_prune
+                    .iter()
+                    .any(|branch| &branch.reference_name == name)
+            })
+        })
+        .map(|(oid, _names)| *oid)
+        .chain(head_info.oid)
+        .collect();
+    let commits_to_hide = dag
+        .query()
+        .only(
+            pruned_branch_oids,
+            union_all(&[dag.main_branch_commit.clone(), kept_heads]),
+        )?
+        .difference(&dag.obsolete_commits);
+    let commits_to_hide = sorted_commit_set(&repo, &dag, &commits_to_hide)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Will prune {}:",
+        Pluralize {
+            determiner: None,
+            amount: branches_to_prune.len(),
+            unit: ("branch", "branches"),
+        }
+    )?;
+    for BranchToPrune {
+        branch_name,
+        reason,
+        ..
+    } in branches_to_prune.iter()
+    {
+        let reason = match reason {
+            PruneReason::UpstreamDeleted { upstream_name } => {
+                format!("{} was deleted", upstream_name)
+            }
+            PruneReason::Merged => "merged into the main branch".to_string(),
+        };
+        writeln!(
+            effects.get_output_stream(),
+            "- {} ({})",
+            branch_name,
+            reason
+        )?;
+    }
+    if !commits_to_hide.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "and hide {}:",
+            Pluralize {
+                determiner: None,
+                amount: commits_to_hide.len(),
+                unit: ("commit", "commits"),
+            }
+        )?;
+        for commit in commits_to_hide.iter() {
+            writeln!(
+                effects.get_output_stream(),
+                "- {}",
+                printable_styled_string(
+                    effects.get_glyphs(),
+                    commit.friendly_describe(effects.get_glyphs())?
+                )?
+            )?;
+        }
+    }
+
+    if dry_run {
+        return Ok(ExitCode(0));
+    }
+    if !yes {
+        write!(effects.get_output_stream(), "Confirm? [yN] ")?;
+        let mut user_input = String::new();
+        let confirmed = match stdin().lock().read_line(&mut user_input) {
+            Ok(_size) => {
+                let user_input = user_input.trim();
+                user_input == "y" || user_input == "Y"
+            }
+            Err(_) => false,
+        };
+        if !confirmed {
+            writeln!(effects.get_output_stream(), "Aborted.")?;
+            return Ok(ExitCode(1));
+        }
+    }
+
+    // `HEAD` must be detached before its branch can be deleted. If the
+    // deletion fails, it's reattached, so that nothing has changed.
+    let head_branch_to_prune = head_info.reference_name.as_ref().filter(|head_branch| {
+        branches_to_prune
+            .iter()
+            .any(|branch| &branch.reference_name == *head_branch)
+    });
+    if head_branch_to_prune.is_some() {
+        repo.detach_head(&head_info)?;
+    }
+    let branch_deletions = branches_to_prune
+        .iter()
+        .map(|branch| ReferenceUpdate {
+            ref_name: branch.reference_name.clone(),
+            old_oid: MaybeZeroOid::NonZero(branch.oid),
+            new_oid: MaybeZeroOid::Zero,
+        })
+        .collect_vec();
+    if let Err(err) =
+        git_run_info.update_references(effects, &repo, event_tx_id, &branch_deletions)?
+    {
+        if let Some(head_branch) = head_branch_to_prune {
+            repo.set_head_to_reference(head_branch)?;
+        }
+        writeln!(
+            effects.get_output_stream(),
+            "Failed to delete branches, so none of them were deleted:\n{}",
+            err
+        )?;
+        return Ok(ExitCode(1));
+    }
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    event_log_db.add_events(
+        commits_to_hide
+            .iter()
+            .map(|commit| Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: commit.get_oid(),
+            })
+            .collect(),
+    )?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Deleted {} and hid {}. To restore them, run: git undo",
+        Pluralize {
+            determiner: None,
+            amount: branches_to_prune.len(),
+            unit: ("branch", "branches"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: commits_to_hide.len(),
+            unit: ("commit", "commits"),
+        },
+    )?;
+    Ok(ExitCode(0))
+}
@@ -0,0 +1,373 @@
+//! Push branches to their remotes, such as after rewriting a stack of commits.
+//!
+//! Branches are force-pushed with `--force-with-lease`, using the value of the
+//! corresponding remote-tracking branch as last recorded in the event log as
+//! the expected value on the remote. If someone else has pushed to the branch
+//! since then, the push is rejected rather than clobbering their commits.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use itertools::Itertools;
+use lib::core::config::get_main_branch_name;
+use lib::core::dag::{union_all, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{
+    CategorizedReferenceName, ConfigRead, GitRunInfo, GitRunOpts, MaybeZeroOid, NonZeroOid,
+    ReferenceName, Repo,
+};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use crate::opts::Revset;
+use crate::revset::resolve_commits;
+
+/// A local branch which should be pushed.
+#[derive(Debug)]
+struct BranchToPush {
+    branch_name: String,
+    oid: NonZeroOid,
+    remote_branch_name: String,
+
+    /// The value which the branch is expected to have on the remote, or `Zero`
+    /// if it's expected not to exist there.
+    expected_oid: MaybeZeroOid,
+}
+
+/// Determine which remote the given branch should be pushed to, following the
+/// same precedence as `git push`.
+fn get_push_remote_name(repo: &Repo, branch_name: &str) -> eyre::Result<Option<String>> {
+    let config = repo.get_readonly_config()?;
+    let push_remote: Option<String> = config.get(format!("branch.{}.pushRemote", branch_name))?;
+    if let Some(push_remote) = push_remote {
+        return Ok(Some(push_remote));
+    }
+    let push_default: Option<String> = config.get("remote.pushDefault")?;
+    if let Some(push_default) = push_default {
+        return Ok(Some(push_default));
+    }
+    let remote: Option<String> = config.get(format!("branch.{}.remote", branch_name))?;
+    match remote {
+        Some(remote) if remote != "." => Ok(Some(remote)),
+        _ => {
+            let remote_names = repo.get_remote_names()?;
+            match remote_names.as_slice() {
+                [remote_name] => Ok(Some(remote_name.clone())),
+                _ => Ok(None),
+            }
+        }
+    }
+}
+
+/// Get the name of the branch on the given remote which the local branch
+/// corresponds to.
+fn get_remote_branch_name(
+    repo: &Repo,
+    branch_name: &str,
+    remote_name: &str,
+) -> eyre::Result<String> {
+    let config = repo.get_readonly_config()?;
+    let remote: Option<String> = config.get(format!("branch.{}.remote", branch_name))?;
+    let merge: Option<String> = config.get(format!("branch.{}.merge", branch_name))?;
+    match (remote, merge) {
+        (Some(remote), Some(merge)) if remote == remote_name => Ok(merge
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&merge)
+            .to_owned()),
+        _ => Ok(branch_name.to_owned()),
+    }
+}
+
+/// Get the last value of the given remote-tracking branch recorded in the event
+/// log. If it was never recorded, such as for a repository cloned before
+/// branchless was installed, then its current value is used.
+fn get_expected_oid(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    remote_tracking_reference_name: &ReferenceName,
+) -> eyre::Result<MaybeZeroOid> {
+    let event_cursor = event_replayer.make_default_cursor();
+    let recorded_oid = event_replayer
+        .get_events_before_cursor(event_cursor)
+        .iter()
+        .rev()
+        .find_map(|event| match event {
+            Event::RefUpdateEvent {
+                timestamp: _,
+                event_tx_id: _,
+                ref_name,
+                old_oid: _,
+                new_oid,
+                message: _,
+            } if ref_name == remote_tracking_reference_name => Some(*new_oid),
+            _ => None,
+        });
+    match recorded_oid {
+        Some(oid) => Ok(oid),
+        None => match repo.find_reference(remote_tracking_reference_name)? {
+            Some(reference) => Ok(match reference.peel_to_commit()? {
+                Some(commit) => MaybeZeroOid::NonZero(commit.get_oid()),
+                None => MaybeZeroOid::Zero,
+            }),
+            None => Ok(MaybeZeroOid::Zero),
+        },
+    }
+}
+
+/// The outcome of pushing a single branch, as reported by `git push
+/// --porcelain`.
+fn describe_push_status(flag: char, summary: &str) -> (String, bool) {
+    let summary = summary.trim();
+    match flag {
+        ' ' => ("updated".to_string(), true),
+        '+' => ("force-updated".to_string(), true),
+        '*' => ("created".to_string(), true),
+        '=' => ("up to date".to_string(), true),
+        '-' => ("deleted".to_string(), true),
+        _ => {
+            let reason = summary
+                .trim_start_matches("[rejected]")
+                .trim_start_matches("[remote rejected]")
+                .trim();
+            if reason.is_empty() {
+                ("rejected".to_string(), false)
+            } else {
+                (format!("rejected {}", reason), false)
+            }
+        }
+    }
+}
+
+/// Push the branches pointing to the given commits. If no commits are
+/// provided, then the branches in the current stack are pushed.
+#[instrument]
+pub fn push(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revsets: Vec<Revset>,
+    remote: Option<String>,
+    dry_run: bool,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let revsets = if revsets.is_empty() {
+        vec![Revset("stack()".to_string())]
+    } else {
+        revsets
+    };
+    let commits = match resolve_commits(effects, git_run_info, &repo, &mut dag, revsets) {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    let main_branch_name = get_main_branch_name(&repo)?;
+    let mut branches_by_remote: BTreeMap<String, Vec<BranchToPush>> = BTreeMap::new();
+    let mut branches_without_remote = Vec::new();
+    let mut branches_up_to_date = Vec::new();
+    for (oid, reference_names) in references_snapshot.branch_oid_to_names.iter() {
+        if !commits.contains(&(*oid).into())? {
+            continue;
+        }
+        for reference_name in reference_names {
+            let branch_name = CategorizedReferenceName::new(reference_name).render_suffix();
+            if branch_name == main_branch_name {
+                continue;
+            }
+            let remote_name = match &remote {
+                Some(remote) => remote.clone(),
+                None => match get_push_remote_name(&repo, &branch_name)? {
+                    Some(remote_name) => remote_name,
+                    None => {
+                        branches_without_remote.push(branch_name);
+                        continue;
+                    }
+                },
+            };
+            let remote_branch_name = get_remote_branch_name(&repo, &branch_name, &remote_name)?;
+            let remote_tracking_reference_name = ReferenceName::from(format!(
+                "refs/remotes/{}/{}",
+                remote_name, remote_branch_name
+            ));
+            let expected_oid =
+                get_expected_oid(&repo, &event_replayer, &remote_tracking_reference_name)?;
+            if expected_oid == MaybeZeroOid::NonZero(*oid) {
+                branches_up_to_date.push(branch_name);
+                continue;
+            }
+            branches_by_remote
+                .entry(remote_name)
+                .or_default()
+                .push(BranchToPush {
+                    branch_name,
+                    oid: *oid,
+                    remote_branch_name,
+                    expected_oid,
+                });
+        }
+    }
+
+    branches_without_remote.sort();
+    for branch_name in branches_without_remote.iter() {
+        writeln!(
+            effects.get_error_stream(),
+            "Not pushing branch {} because it's not clear which remote to push it to. Pass --remote to choose one.",
+            branch_name
+        )?;
+    }
+    if branches_by_remote.is_empty() {
+        if branches_without_remote.is_empty() {
+            writeln!(effects.get_output_stream(), "No branches to push.")?;
+            return Ok(ExitCode(0));
+        } else {
+            return Ok(ExitCode(1));
+        }
+    }
+
+    // A dry run doesn't change anything, so there's nothing to undo and it
+    // shouldn't appear in the event log.
+    let event_tx_id = if dry_run {
+        None
+    } else {
+        Some(event_log_db.make_transaction_id(now, "push")?)
+    };
+    let mut is_success = branches_without_remote.is_empty();
+    for (remote_name, mut branches) in branches_by_remote {
+        branches.sort_by(|lhs, rhs| lhs.branch_name.cmp(&rhs.branch_name));
+        if dry_run {
+            writeln!(
+                effects.get_output_stream(),
+                "Would push to {}:",
+                remote_name
+            )?;
+            for BranchToPush {
+                branch_name,
+                oid,
+                remote_branch_name: _,
+                expected_oid,
+            } in branches.iter()
+            {
+                let commit = repo.find_commit_or_fail(*oid)?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "- {}: {} (expecting {})",
+                    branch_name,
+                    commit.get_short_oid()?,
+                    match expected_oid {
+                        MaybeZeroOid::NonZero(oid) =>
+                            repo.find_commit_or_fail(*oid)?.get_short_oid()?,
+                        MaybeZeroOid::Zero => "no branch on the remote".to_string(),
+                    }
+                )?;
+            }
+            continue;
+        }
+
+        writeln!(effects.get_output_stream(), "Pushing to {}:", remote_name)?;
+        let mut args = vec!["push".to_string(), "--porcelain".to_string()];
+        args.extend(branches.iter().map(|branch| {
+            format!(
+                "--force-with-lease=refs/heads/{}:{}",
+                branch.remote_branch_name,
+                match branch.expected_oid {
+                    MaybeZeroOid::NonZero(oid) => oid.to_string(),
+                    MaybeZeroOid::Zero => String::new(),
+                }
+            )
+        }));
+        args.push(remote_name.clone());
+        args.extend(
+            branches
+                .iter()
+                .map(|branch| format!("{}:refs/heads/{}", branch.oid, branch.remote_branch_name)),
+        );
+        let result = git_run_info.run_silent(
+            &repo,
+            event_tx_id,
+            &args,
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                ..Default::default()
+            },
+        )?;
+
+        // Each line of the form `<flag>\t<from>:<to>\t<summary>` describes the
+        // result for one reference. Other lines, such as `To <url>`, are
+        // ignored.
+        let stdout = String::from_utf8_lossy(&result.stdout);
+        let mut statuses: BTreeMap<String, (String, bool)> = BTreeMap::new();
+        for line in stdout.lines() {
+            let mut chars = line.chars();
+            let flag = match chars.next() {
+                Some(flag) => flag,
+                None => continue,
+            };
+            let fields = match chars.as_str().strip_prefix('\t') {
+                Some(fields) => fields.split('\t').collect_vec(),
+                None => continue,
+            };
+            let (refspec, summary) = match fields.as_slice() {
+                [refspec, summary] => (refspec, summary),
+                _ => continue,
+            };
+            if let Some((_from, to)) = refspec.split_once(':') {
+                statuses.insert(to.to_string(), describe_push_status(flag, summary));
+            }
+        }
+
+        for branch in branches.iter() {
+            let to = format!("refs/heads/{}", branch.remote_branch_name);
+            let (description, branch_success) = match statuses.get(&to) {
+                Some(status) => status.clone(),
+                None => ("not pushed".to_string(), false),
+            };
+            is_success &= branch_success;
+            writeln!(
+                effects.get_output_stream(),
+                "- {}: {}",
+                branch.branch_name,
+                description
+            )?;
+        }
+        if result.exit_code != 0 && statuses.is_empty() {
+            write!(
+                effects.get_error_stream(),
+                "{}",
+                String::from_utf8_lossy(&result.stderr)
+            )?;
+        }
+    }
+
+    if !dry_run && !branches_up_to_date.is_empty() {
+        branches_up_to_date.sort();
+        writeln!(
+            effects.get_output_stream(),
+            "Already up to date: {}",
+            branches_up_to_date.join(", ")
+        )?;
+    }
+
+    if is_success {
+        Ok(ExitCode(0))
+    } else {
+        Ok(ExitCode(1))
+    }
+}
@@ -36,7 +36,7 @@ pub fn query(
         &references_snapshot,
     )?;
 
-    let commit_set = match resolve_commits(effects, &repo, &mut dag, vec![query]) {
+    let commit_set = match resolve_commits(effects, git_run_info, &repo, &mut dag, vec![query]) {
         Ok(commit_sets) => commit_sets[0].clone(),
         Err(err) => {
             err.describe(effects)?;
@@ -78,7 +78,10 @@ pub fn query(
             if raw {
                 writeln!(effects.get_output_stream(), "{}", commit_oid)?;
             } else {
-                let commit = repo.find_commit_or_fail(commit_oid)?;
+                let commit = match repo.find_commit(commit_oid)? {
+                    Some(commit) => commit,
+                    None => continue,
+                };
                 writeln!(
                     effects.get_output_stream(),
                     "{}",
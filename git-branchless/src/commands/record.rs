@@ -1,5 +1,7 @@
+use std::collections::HashSet;
 use std::fmt::Write;
 use std::io;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 use cursive::backends::crossterm;
@@ -7,98 +9,306 @@ use cursive::CursiveRunnable;
 use cursive_buffered_backend::BufferedBackend;
 
 use git_record::Recorder;
-use git_record::{RecordError, RecordState};
+use git_record::{FileState, RecordError, RecordState, Section};
 use itertools::Itertools;
+use lib::core::config::{
+    get_comment_string, get_commit_message_template, get_commit_msg_hook, get_commit_template,
+    get_word_diff,
+};
 use lib::core::effects::{Effects, OperationType};
 use lib::core::eventlog::{EventLogDb, EventTransactionId};
+use lib::core::topics::extract_ticket_id_from_branch_name;
+use lib::core::user_hooks::run_commit_msg_hook;
 use lib::git::{
-    process_diff_for_record, update_index, CategorizedReferenceName, FileMode, GitRunInfo, Repo,
-    ResolvedReferenceInfo, Stage, UpdateIndexCommand, WorkingCopyChangesType, WorkingCopySnapshot,
+    get_selected_submodule_commit, is_submodule_file_state, message_prettify,
+    process_diff_for_record, update_index, CategorizedReferenceName, FileMode, GitRunInfo,
+    GitRunOpts, Repo, ResolvedReferenceInfo, Stage, Tree, UpdateIndexCommand,
+    WorkingCopyChangesType, WorkingCopySnapshot,
 };
 use lib::util::ExitCode;
+use tracing::warn;
 
-pub fn record(
+use crate::commands::reword::{
+    edit_message, edit_message_until_accepted, render_rejection_comment, MessageCheck,
+};
+use crate::opts::RecordOptions;
+
+/// The values substituted for the variables in `branchless.commit.template`.
+#[derive(Debug, Default)]
+struct CommitTemplateVariables {
+    /// The name of the checked-out branch, if any.
+    branch_name: Option<String>,
+
+    /// The paths of the files changed by the new commit.
+    paths: Vec<PathBuf>,
+}
+
+/// Substitute the variables in the given commit message template:
+///
+/// - `{branch}`: the name of the checked-out branch.
+/// - `{ticket}`: the ticket ID in the branch name, such as `PROJ-123`.
+/// - `{files}`: the comma-separated paths of the changed files.
+///
+/// Variables which don't have a value are replaced with the empty string.
+/// Other text in braces is left as-is.
+fn render_commit_template(template: &str, variables: &CommitTemplateVariables) -> String {
+    let CommitTemplateVariables { branch_name, paths } = variables;
+    let ticket_id = branch_name
+        .as_deref()
+        .and_then(extract_ticket_id_from_branch_name);
+    let files = paths
+        .iter()
+        .map(|path| path.to_string_lossy())
+        .sorted()
+        .join(", ");
+    template
+        .replace("{branch}", branch_name.as_deref().unwrap_or_default())
+        .replace("{ticket}", ticket_id.as_deref().unwrap_or_default())
+        .replace("{files}", &files)
+}
+
+/// Calculate the changes between the two trees, in the form used for
+/// selecting changes interactively.
+pub(crate) fn get_file_states_for_record(
     effects: &Effects,
+    repo: &Repo,
+    old_tree: &Tree,
+    new_tree: &Tree,
+) -> eyre::Result<Vec<(PathBuf, FileState<'static>)>> {
+    let (effects, _progress) = effects.start_operation(OperationType::CalculateDiff);
+    let diff = repo.get_diff_between_trees(
+        &effects,
+        Some(old_tree),
+        new_tree,
+        // We manually add context to the git-record output, so suppress the context lines here.
+        0,
+    )?;
+    process_diff_for_record(repo, &diff)
+}
+
+/// Let the user select changes from the given files interactively. Returns
+/// `None` if the user cancelled.
+pub(crate) fn select_changes(
+    repo: &Repo,
+    file_states: Vec<(PathBuf, FileState<'static>)>,
+) -> eyre::Result<Option<Vec<(PathBuf, FileState<'static>)>>> {
+    let record_state = RecordState { file_states };
+
+    let siv = CursiveRunnable::new(|| -> io::Result<_> {
+        // Use crossterm to ensure that we support Windows.
+        let crossterm_backend = crossterm::Backend::init()?;
+        Ok(Box::new(BufferedBackend::new(crossterm_backend)))
+    });
+    let siv = siv.into_runner();
+
+    let recorder = Recorder::new(record_state).with_word_diff(get_word_diff(repo)?);
+    match recorder.run(siv) {
+        Ok(RecordState { file_states }) => Ok(Some(file_states)),
+        Err(RecordError::Cancelled) => Ok(None),
+    }
+}
+
+/// Whether any of the changes in the file were selected to be committed.
+pub(crate) fn has_selected_changes(file_state: &FileState) -> bool {
+    file_state.sections.iter().any(|section| match section {
+        Section::Unchanged { contents: _ } => false,
+        Section::Changed { before, after } => before
+            .iter()
+            .chain(after.iter())
+            .any(|changed_line| changed_line.is_selected),
+        Section::FileMode {
+            is_selected,
+            before: _,
+            after: _,
+        } => *is_selected,
+    })
+}
+
+/// Get the paths of the untracked files in the working copy which aren't
+/// ignored.
+fn get_untracked_paths(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<Vec<PathBuf>> {
+    let result = git_run_info.run_silent(
+        repo,
+        None,
+        &["ls-files", "--others", "--exclude-standard", "-z"],
+        GitRunOpts::default(),
+    )?;
+    Ok(result
+        .stdout
+        .split(|c| *c == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+        .collect())
+}
+
+/// Run a Git command which operates on the given paths, such as `git add`.
+fn run_git_for_paths(
     git_run_info: &GitRunInfo,
-    message: Option<String>,
-    interactive: bool,
-    detach: bool,
-) -> eyre::Result<ExitCode> {
-    let repo = Repo::from_dir(&git_run_info.working_directory)?;
-    let conn = repo.get_db_conn()?;
-    let event_log_db = EventLogDb::new(&conn)?;
-    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "record")?;
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    args: &[&str],
+    paths: &[PathBuf],
+) -> eyre::Result<()> {
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let mut args = args.iter().map(|arg| arg.to_string()).collect_vec();
+    args.push("--".to_string());
+    args.extend(paths.iter().map(|path| path.to_string_lossy().into_owned()));
+    let _result = git_run_info.run_silent(repo, Some(event_tx_id), &args, GitRunOpts::default())?;
+    Ok(())
+}
 
-    let (snapshot, working_copy_changes_type) = {
-        let head_info = repo.get_head_info()?;
-        let index = repo.get_index()?;
-        let (snapshot, _status) =
-            repo.get_status(effects, git_run_info, &index, &head_info, Some(event_tx_id))?;
+/// Untracks the files which were added with `--intent-to-add`, except for the
+/// ones which ended up in the commit. The cleanup also runs when the guard is
+/// dropped, so that the files aren't left in the index if recording fails
+/// partway through.
+struct IntentToAddGuard<'a> {
+    git_run_info: &'a GitRunInfo,
+    repo: &'a Repo,
+    event_tx_id: EventTransactionId,
+    paths: Vec<PathBuf>,
+}
 
-        let working_copy_changes_type = snapshot.get_working_copy_changes_type()?;
-        match working_copy_changes_type {
-            WorkingCopyChangesType::None => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "There are no changes to tracked files in the working copy to commit."
-                )?;
-                return Ok(ExitCode(0));
-            }
-            WorkingCopyChangesType::Unstaged | WorkingCopyChangesType::Staged => {}
-            WorkingCopyChangesType::Conflicts => {
-                writeln!(
-                    effects.get_output_stream(),
-                    "Cannot commit changes while there are unresolved merge conflicts."
-                )?;
-                writeln!(
-                    effects.get_output_stream(),
-                    "Resolve them and try again. Aborting."
-                )?;
-                return Ok(ExitCode(1));
+impl IntentToAddGuard<'_> {
+    /// Add the given paths with `--intent-to-add`, returning a guard which
+    /// untracks them again.
+    fn add<'a>(
+        git_run_info: &'a GitRunInfo,
+        repo: &'a Repo,
+        event_tx_id: EventTransactionId,
+        paths: Vec<PathBuf>,
+    ) -> eyre::Result<IntentToAddGuard<'a>> {
+        let guard = IntentToAddGuard {
+            git_run_info,
+            repo,
+            event_tx_id,
+            paths,
+        };
+        run_git_for_paths(
+            git_run_info,
+            repo,
+            event_tx_id,
+            &["add", "--intent-to-add"],
+            &guard.paths,
+        )?;
+        Ok(guard)
+    }
+
+    /// Untrack the paths which weren't committed.
+    fn clean_up(&mut self) -> eyre::Result<()> {
+        let paths = std::mem::take(&mut self.paths);
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let head_commit = match self.repo.get_head_info()?.oid {
+            Some(head_oid) => Some(self.repo.find_commit_or_fail(head_oid)?),
+            None => None,
+        };
+        let head_tree = match &head_commit {
+            Some(head_commit) => Some(head_commit.get_tree()?),
+            None => None,
+        };
+        let mut uncommitted_paths = Vec::new();
+        for path in paths {
+            let is_committed = match &head_tree {
+                Some(head_tree) => head_tree.get_path(&path)?.is_some(),
+                None => false,
+            };
+            if !is_committed {
+                uncommitted_paths.push(path);
             }
         }
-        (snapshot, working_copy_changes_type)
-    };
+        run_git_for_paths(
+            self.git_run_info,
+            self.repo,
+            self.event_tx_id,
+            &["rm", "--cached", "--quiet", "--ignore-unmatch"],
+            &uncommitted_paths,
+        )
+    }
+}
 
-    let commit_exit_code = if interactive {
-        if working_copy_changes_type == WorkingCopyChangesType::Staged {
-            writeln!(
-                effects.get_output_stream(),
-                "Cannot select changes interactively while there are already staged changes."
-            )?;
+impl Drop for IntentToAddGuard<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.clean_up() {
+            warn!(?err, "Could not untrack files added with --intent-to-add");
+        }
+    }
+}
+
+pub fn record(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    record_options: &RecordOptions,
+) -> eyre::Result<ExitCode> {
+    let RecordOptions {
+        message: _,
+        interactive: _,
+        detach,
+        amend,
+        no_edit: _,
+        all,
+        exclude,
+    } = record_options;
+    let exclude_patterns: Vec<glob::Pattern> = match exclude
+        .iter()
+        .map(|pattern| glob::Pattern::new(pattern))
+        .try_collect()
+    {
+        Ok(exclude_patterns) => exclude_patterns,
+        Err(err) => {
             writeln!(
-                effects.get_output_stream(),
-                "Either commit or unstage your changes and try again. Aborting."
+                effects.get_error_stream(),
+                "Invalid pattern for --exclude: {}",
+                err
             )?;
-            ExitCode(1)
-        } else {
-            record_interactive(
-                effects,
-                git_run_info,
-                &repo,
-                &snapshot,
-                event_tx_id,
-                message.as_deref(),
-            )?
+            return Ok(ExitCode(1));
         }
+    };
+    let is_excluded = |path: &Path| {
+        exclude_patterns
+            .iter()
+            .any(|pattern| pattern.matches_path(path))
+    };
+
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "record")?;
+
+    // Add untracked files to the index with `--intent-to-add`, so that they're
+    // treated like any other unstaged change to a tracked file.
+    let untracked_paths = if *all {
+        get_untracked_paths(git_run_info, &repo)?
+            .into_iter()
+            .filter(|path| !is_excluded(path))
+            .collect_vec()
     } else {
-        let args = {
-            let mut args = vec!["commit"];
-            if let Some(message) = &message {
-                args.extend(["--message", message]);
-            }
-            if working_copy_changes_type == WorkingCopyChangesType::Unstaged {
-                args.push("--all");
-            }
-            args
-        };
-        git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)?
+        Vec::new()
     };
-    if !commit_exit_code.is_success() {
-        return Ok(commit_exit_code);
+    let mut intent_to_add_guard =
+        IntentToAddGuard::add(git_run_info, &repo, event_tx_id, untracked_paths)?;
+
+    let exit_code = record_changes(
+        effects,
+        git_run_info,
+        &repo,
+        event_tx_id,
+        record_options,
+        &intent_to_add_guard.paths,
+        &is_excluded,
+    )?;
+
+    // Any untracked files which didn't end up in the commit should be
+    // untracked again.
+    intent_to_add_guard.clean_up()?;
+    if !exit_code.is_success() {
+        return Ok(exit_code);
     }
 
-    if detach {
+    if *detach && !*amend {
         let head_info = repo.get_head_info()?;
         if let ResolvedReferenceInfo {
             oid: Some(oid),
@@ -142,51 +352,273 @@ pub fn record(
     Ok(ExitCode(0))
 }
 
-fn record_interactive(
+/// Commit the changes in the working copy, according to the given options.
+fn record_changes(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     repo: &Repo,
-    snapshot: &WorkingCopySnapshot,
     event_tx_id: EventTransactionId,
-    message: Option<&str>,
+    record_options: &RecordOptions,
+    untracked_paths: &[PathBuf],
+    is_excluded: &dyn Fn(&Path) -> bool,
 ) -> eyre::Result<ExitCode> {
-    let file_states = {
-        let (effects, _progress) = effects.start_operation(OperationType::CalculateDiff);
-        let old_tree = snapshot.commit_stage0.get_tree()?;
-        let new_tree = snapshot.commit_unstaged.get_tree()?;
-        let diff = repo.get_diff_between_trees(
-            &effects,
-            Some(&old_tree),
-            &new_tree,
-            // We manually add context to the git-record output, so suppress the context lines here.
-            0,
+    let RecordOptions {
+        message,
+        interactive,
+        detach: _,
+        amend,
+        no_edit,
+        all: _,
+        exclude: _,
+    } = record_options;
+
+    let head_info = repo.get_head_info()?;
+    let branch_name = head_info
+        .reference_name
+        .as_ref()
+        .map(|reference_name| CategorizedReferenceName::new(reference_name).render_suffix());
+    let (snapshot, working_copy_changes_type) = {
+        let index = repo.get_index()?;
+        let (snapshot, _status) =
+            repo.get_status(effects, git_run_info, &index, &head_info, Some(event_tx_id))?;
+
+        let working_copy_changes_type = match snapshot.get_working_copy_changes_type()? {
+            // Untracked files added with `--intent-to-add` show up as staged
+            // changes, but they should be committed alongside any unstaged
+            // changes.
+            WorkingCopyChangesType::Staged if !untracked_paths.is_empty() => {
+                let staged_paths = repo
+                    .get_paths_touched_by_commit(&snapshot.commit_stage0)?
+                    .unwrap_or_default();
+                if staged_paths
+                    .iter()
+                    .all(|path| untracked_paths.contains(path))
+                {
+                    WorkingCopyChangesType::Unstaged
+                } else {
+                    WorkingCopyChangesType::Staged
+                }
+            }
+            working_copy_changes_type => working_copy_changes_type,
+        };
+        match working_copy_changes_type {
+            WorkingCopyChangesType::None if !*amend => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "There are no changes to tracked files in the working copy to commit."
+                )?;
+                return Ok(ExitCode(0));
+            }
+            WorkingCopyChangesType::None
+            | WorkingCopyChangesType::Unstaged
+            | WorkingCopyChangesType::Staged => {}
+            WorkingCopyChangesType::Conflicts => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Cannot commit changes while there are unresolved merge conflicts."
+                )?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "Resolve them and try again. Aborting."
+                )?;
+                return Ok(ExitCode(1));
+            }
+        }
+        (snapshot, working_copy_changes_type)
+    };
+
+    let commit_options = {
+        let mut commit_options = Vec::new();
+        if *amend {
+            commit_options.push("--amend".to_string());
+        }
+        if *no_edit {
+            commit_options.push("--no-edit".to_string());
+        }
+        commit_options
+    };
+    // When amending without a new message, Git opens the editor with the
+    // existing message, so the template isn't used.
+    let should_check_message = !*amend || message.is_some();
+
+    if *interactive && working_copy_changes_type != WorkingCopyChangesType::None {
+        if working_copy_changes_type == WorkingCopyChangesType::Staged {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot select changes interactively while there are already staged changes."
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "Either commit or unstage your changes and try again. Aborting."
+            )?;
+            return Ok(ExitCode(1));
+        }
+        return record_interactive(
+            effects,
+            git_run_info,
+            repo,
+            &snapshot,
+            event_tx_id,
+            message.as_deref(),
+            branch_name,
+            &commit_options,
+            should_check_message,
+            is_excluded,
+        );
+    }
+
+    let changes_commit = match working_copy_changes_type {
+        WorkingCopyChangesType::Staged => &snapshot.commit_stage0,
+        _ => &snapshot.commit_unstaged,
+    };
+    let changed_paths: HashSet<PathBuf> = repo
+        .get_paths_touched_by_commit(changes_commit)?
+        .unwrap_or_default()
+        .into_iter()
+        .chain(untracked_paths.iter().cloned())
+        .collect();
+    let (excluded_paths, included_paths): (Vec<PathBuf>, Vec<PathBuf>) = changed_paths
+        .into_iter()
+        .sorted()
+        .partition(|path| is_excluded(path));
+    if included_paths.is_empty() && !*amend {
+        writeln!(
+            effects.get_output_stream(),
+            "There are no changes to tracked files in the working copy to commit."
         )?;
-        process_diff_for_record(repo, &diff)?
+        return Ok(ExitCode(0));
+    }
+
+    let template_variables = CommitTemplateVariables {
+        branch_name,
+        paths: included_paths.clone(),
+    };
+    let message = if should_check_message {
+        match check_message(
+            effects,
+            git_run_info,
+            repo,
+            message.clone(),
+            &template_variables,
+        )? {
+            Ok(message) => message,
+            Err(exit_code) => return Ok(exit_code),
+        }
+    } else {
+        None
     };
-    let record_state = RecordState { file_states };
 
-    let siv = CursiveRunnable::new(|| -> io::Result<_> {
-        // Use crossterm to ensure that we support Windows.
-        let crossterm_backend = crossterm::Backend::init()?;
-        Ok(Box::new(BufferedBackend::new(crossterm_backend)))
-    });
-    let siv = siv.into_runner();
+    let mut args = vec!["commit".to_string()];
+    args.extend(commit_options);
+    if let Some(message) = message {
+        args.extend(["--message".to_string(), message]);
+    }
+    match working_copy_changes_type {
+        WorkingCopyChangesType::Staged => {
+            // Staged changes to excluded paths are unstaged, and untracked
+            // files have to be fully staged to be committed.
+            run_git_for_paths(
+                git_run_info,
+                repo,
+                event_tx_id,
+                &["reset", "--quiet"],
+                &excluded_paths,
+            )?;
+            run_git_for_paths(git_run_info, repo, event_tx_id, &["add"], untracked_paths)?;
+        }
+        WorkingCopyChangesType::Unstaged if excluded_paths.is_empty() => {
+            args.push("--all".to_string());
+        }
+        WorkingCopyChangesType::Unstaged => {
+            args.push("--".to_string());
+            args.extend(
+                included_paths
+                    .iter()
+                    .map(|path| path.to_string_lossy().into_owned()),
+            );
+        }
+        WorkingCopyChangesType::None | WorkingCopyChangesType::Conflicts => {}
+    }
+    git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)
+}
 
-    let recorder = Recorder::new(record_state);
-    let result = recorder.run(siv);
-    let RecordState {
-        file_states: result,
-    } = match result {
-        Ok(result) => result,
-        Err(RecordError::Cancelled) => {
+fn record_interactive(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    snapshot: &WorkingCopySnapshot,
+    event_tx_id: EventTransactionId,
+    message: Option<&str>,
+    branch_name: Option<String>,
+    commit_options: &[String],
+    should_check_message: bool,
+    is_excluded: &dyn Fn(&Path) -> bool,
+) -> eyre::Result<ExitCode> {
+    let file_states = get_file_states_for_record(
+        effects,
+        repo,
+        &snapshot.commit_stage0.get_tree()?,
+        &snapshot.commit_unstaged.get_tree()?,
+    )?
+    .into_iter()
+    .filter(|(path, _file_state)| !is_excluded(path))
+    .collect_vec();
+    if file_states.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "There are no changes to tracked files in the working copy to commit."
+        )?;
+        return Ok(ExitCode(0));
+    }
+    let result = match select_changes(repo, file_states)? {
+        Some(result) => result,
+        None => {
             println!("Aborted.");
             return Ok(ExitCode(1));
         }
     };
 
+    let template_variables = CommitTemplateVariables {
+        branch_name,
+        paths: result
+            .iter()
+            .filter(|(_path, file_state)| has_selected_changes(file_state))
+            .map(|(path, _file_state)| path.clone())
+            .collect(),
+    };
+    let message = if should_check_message {
+        match check_message(
+            effects,
+            git_run_info,
+            repo,
+            message.map(String::from),
+            &template_variables,
+        )? {
+            Ok(message) => message,
+            Err(exit_code) => return Ok(exit_code),
+        }
+    } else {
+        None
+    };
+
+    // Files without any selected changes are left alone, so that untracked
+    // files which weren't selected aren't added as empty files.
     let update_index_script: Vec<UpdateIndexCommand> = result
         .into_iter()
+        .filter(|(_path, file_state)| has_selected_changes(file_state))
         .map(|(path, file_state)| -> eyre::Result<UpdateIndexCommand> {
+            if is_submodule_file_state(&file_state) {
+                let command = match get_selected_submodule_commit(&file_state)? {
+                    Some(oid) => UpdateIndexCommand::Update {
+                        path,
+                        stage: Stage::Stage0,
+                        mode: FileMode::Commit,
+                        oid,
+                    },
+                    None => UpdateIndexCommand::Delete { path },
+                };
+                return Ok(command);
+            }
             let (selected, _unselected) = file_state.get_selected_contents();
             let oid = repo.create_blob_from_contents(selected.as_bytes())?;
             let command = UpdateIndexCommand::Update {
@@ -208,12 +640,127 @@ fn record_interactive(
         &update_index_script,
     )?;
 
-    let args = {
-        let mut args = vec!["commit"];
-        if let Some(message) = message {
-            args.extend(["--message", message]);
-        }
-        args
-    };
+    let mut args = vec!["commit".to_string()];
+    args.extend(commit_options.iter().cloned());
+    if let Some(message) = message {
+        args.extend(["--message".to_string(), message]);
+    }
     git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)
 }
+
+/// The outcome of editing and checking the message for a new commit.
+enum CheckedMessage {
+    /// The message was accepted by the commit message hook, if any.
+    Accepted(String),
+
+    /// The message was empty.
+    Empty,
+
+    /// The commit message hook rejected the message with the given output.
+    Rejected(String),
+}
+
+/// If `branchless.commit.msgHook` is set, validate the message for the new
+/// commit with it before committing.
+///
+/// If no message was provided, and either the hook or
+/// `branchless.commit.template` is set, then the editor is opened to write one,
+/// pre-filled with the rendered template. As with `git reword`, it's re-opened
+/// with the hook's output until the message is accepted or left unchanged.
+/// Returns the message to commit with, or the exit code if the commit should be
+/// aborted.
+fn check_message(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    message: Option<String>,
+    template_variables: &CommitTemplateVariables,
+) -> eyre::Result<Result<Option<String>, ExitCode>> {
+    let message_template = get_commit_message_template(repo)?;
+    if get_commit_msg_hook(repo)?.is_none() && (message.is_some() || message_template.is_none()) {
+        return Ok(Ok(message));
+    }
+
+    let is_interactive = message.is_none();
+    let template = match message_template {
+        Some(message_template) => render_commit_template(&message_template, template_variables),
+        None => get_commit_template(repo)?.unwrap_or_default(),
+    };
+    let comment_char = get_comment_string(repo)?.resolve(&template)?;
+    let editor_message = format!(
+        "{}\n{} Please enter the commit message for your changes. Lines starting\n\
+        {} with '{}' will be ignored, and an empty message aborts the commit.\n",
+        template.trim_end(),
+        comment_char,
+        comment_char,
+        comment_char
+    );
+    let result = edit_message_until_accepted(
+        editor_message,
+        message,
+        None,
+        |message| edit_message(effects, git_run_info, repo, message),
+        |edited_message| {
+            // A message passed with `-m` is committed as-is.
+            let message = if is_interactive {
+                message_prettify(&edited_message, Some(&comment_char))?
+            } else {
+                edited_message
+            };
+            if message.trim().is_empty() {
+                return Ok(MessageCheck::Done(CheckedMessage::Empty));
+            }
+            match run_commit_msg_hook(repo, &message)? {
+                None => Ok(MessageCheck::Done(CheckedMessage::Accepted(message))),
+                Some(output) => Ok(MessageCheck::EditAgain {
+                    comment: format!(
+                        "{}{}\n",
+                        render_rejection_comment(&comment_char, "the new commit", &output),
+                        comment_char
+                    ),
+                    on_unchanged: Some(CheckedMessage::Rejected(output)),
+                }),
+            }
+        },
+    )?;
+
+    match result {
+        CheckedMessage::Accepted(message) => Ok(Ok(Some(message))),
+        CheckedMessage::Empty => {
+            writeln!(
+                effects.get_error_stream(),
+                "Aborting commit due to empty commit message."
+            )?;
+            Ok(Err(ExitCode(1)))
+        }
+        CheckedMessage::Rejected(output) => {
+            writeln!(
+                effects.get_error_stream(),
+                "Aborting commit because the commit message hook rejected the message:\n{}",
+                output
+            )?;
+            Ok(Err(ExitCode(1)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_commit_template() {
+        let variables = CommitTemplateVariables {
+            branch_name: Some("feature/proj-123-login".to_string()),
+            paths: vec![PathBuf::from("src/b.rs"), PathBuf::from("src/a.rs")],
+        };
+        assert_eq!(
+            render_commit_template("[{ticket}] {branch}: {other}\n\nFiles: {files}", &variables),
+            "[PROJ-123] feature/proj-123-login: {other}\n\nFiles: src/a.rs, src/b.rs"
+        );
+        assert_eq!(
+            render_commit_template("[{ticket}] ", &CommitTemplateVariables::default()),
+            "[] "
+        );
+    }
+}
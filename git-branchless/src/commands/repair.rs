@@ -1,8 +1,14 @@
 use std::fmt::Write;
 use std::{collections::HashSet, time::SystemTime};
 
+use eden_dag::DagAlgorithm;
+use eyre::Context;
 use itertools::Itertools;
-use lib::git::{CategorizedReferenceName, MaybeZeroOid};
+use lib::core::dag::{commit_set_to_vec_unsorted, CommitSet, Dag};
+use lib::core::eventlog::EventCursor;
+use lib::core::migrations::{self, SchemaStatus};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid};
 use lib::{
     core::{
         effects::{Effects, OperationType},
@@ -12,6 +18,172 @@ use lib::{
     git::Repo,
     util::ExitCode,
 };
+use tracing::warn;
+
+/// A problem with the on-disk commit graph which requires it to be rebuilt.
+enum DagProblem {
+    /// The commit graph couldn't be opened or updated at all.
+    Unreadable(eyre::Report),
+
+    /// The parents recorded in the commit graph for these commits don't match
+    /// their parents in the Git repository. This can happen if an operation
+    /// was interrupted while the commit graph was being written.
+    InconsistentCommits(Vec<NonZeroOid>),
+}
+
+/// Check the commit graph for problems. The commit graph is only synced with
+/// the repository first if `dry_run` is not set, since syncing writes to it.
+fn find_dag_problem(
+    effects: &Effects,
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    dry_run: bool,
+) -> eyre::Result<Option<DagProblem>> {
+    let (effects, progress) = effects.start_operation(OperationType::RepairDag);
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = if dry_run {
+        Dag::open_without_syncing(
+            &effects,
+            repo,
+            event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )
+    } else {
+        Dag::open_and_sync(
+            &effects,
+            repo,
+            event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )
+    };
+    let dag = match dag {
+        Ok(dag) => dag,
+        Err(err) => return Ok(Some(DagProblem::Unreadable(err))),
+    };
+
+    let draft_commits = match dag
+        .query()
+        .all()
+        .map_err(eyre::Report::from)
+        .and_then(|all_commits| Ok(all_commits.difference(&dag.query_public_commits()?)))
+    {
+        Ok(draft_commits) => draft_commits,
+        Err(err) => return Ok(Some(DagProblem::Unreadable(err))),
+    };
+    let draft_oids = commit_set_to_vec_unsorted(&draft_commits)?;
+    progress.notify_progress(0, draft_oids.len());
+
+    let mut inconsistent_oids = Vec::new();
+    for oid in draft_oids {
+        progress.notify_progress_inc(1);
+        let commit = match repo.find_commit(oid)? {
+            Some(commit) => commit,
+            // Missing commits are handled by obsoleting them in the event log.
+            None => continue,
+        };
+
+        // Parents which have been garbage-collected were never added to the
+        // commit graph, so they can't be restored by rebuilding it.
+        let mut expected_parent_oids = HashSet::new();
+        for parent_oid in commit.get_parent_oids() {
+            if repo.find_commit(parent_oid)?.is_some() {
+                expected_parent_oids.insert(parent_oid);
+            }
+        }
+        let actual_parent_oids: HashSet<NonZeroOid> =
+            commit_set_to_vec_unsorted(&dag.query().parents(CommitSet::from(oid))?)?
+                .into_iter()
+                .collect();
+        if expected_parent_oids != actual_parent_oids {
+            inconsistent_oids.push(oid);
+        }
+    }
+
+    if inconsistent_oids.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(DagProblem::InconsistentCommits(inconsistent_oids)))
+    }
+}
+
+/// Delete the on-disk commit graph and build it again from the event log.
+fn rebuild_dag(effects: &Effects, repo: &Repo, event_log_db: &EventLogDb) -> eyre::Result<()> {
+    let dag_dir = repo.get_dag_dir();
+    if dag_dir.exists() {
+        std::fs::remove_dir_all(&dag_dir)
+            .wrap_err_with(|| format!("Removing DAG directory at: {:?}", &dag_dir))?;
+    }
+
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    Ok(())
+}
+
+/// Report whether the database schema needs to be migrated, without changing
+/// it.
+pub fn check_schema(effects: &Effects) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn_without_migrations()?;
+    match migrations::check_schema(&conn)? {
+        SchemaStatus::UpToDate { version } => {
+            writeln!(
+                effects.get_output_stream(),
+                "The database schema is up to date (version {}).",
+                version
+            )?;
+            Ok(ExitCode(0))
+        }
+
+        SchemaStatus::Outdated {
+            version,
+            pending_migrations,
+        } => {
+            writeln!(
+                effects.get_output_stream(),
+                "The database schema is at version {}, and {} will be applied the next time it's opened:",
+                version,
+                Pluralize {
+                    determiner: None,
+                    amount: pending_migrations.len(),
+                    unit: ("migration", "migrations"),
+                },
+            )?;
+            for migration in pending_migrations {
+                writeln!(
+                    effects.get_output_stream(),
+                    "{}. {}",
+                    migration.version,
+                    migration.description
+                )?;
+            }
+            Ok(ExitCode(0))
+        }
+
+        SchemaStatus::TooNew {
+            version,
+            latest_version,
+        } => {
+            writeln!(
+                effects.get_error_stream(),
+                "The database schema is at version {}, but this version of git-branchless only supports up to version {}. It was probably written by a newer version of git-branchless, which should be used instead.",
+                version,
+                latest_version
+            )?;
+            Ok(ExitCode(1))
+        }
+    }
+}
 
 pub fn repair(effects: &Effects, dry_run: bool) -> eyre::Result<ExitCode> {
     let repo = Repo::from_current_dir()?;
@@ -59,41 +231,50 @@ pub fn repair(effects: &Effects, dry_run: bool) -> eyre::Result<ExitCode> {
         result
     };
 
-    let now = SystemTime::now();
-    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
-    let event_tx_id = event_log_db.make_transaction_id(now, "repair")?;
+    let dag_problem = find_dag_problem(effects, &repo, &event_replayer, event_cursor, dry_run)?;
 
     let num_broken_commits = broken_commits.len();
-    let commit_events = broken_commits
-        .iter()
-        .map(|commit_oid| Event::ObsoleteEvent {
-            timestamp,
-            event_tx_id,
-            commit_oid: *commit_oid,
-        })
-        .collect_vec();
     let num_broken_branches = broken_branches.len();
-    let branch_events =
-        broken_branches
+    if !dry_run {
+        let now = SystemTime::now();
+        let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+        let event_tx_id = event_log_db.make_transaction_id(now, "repair")?;
+        let commit_events = broken_commits
             .iter()
-            .map(|(old_oid, reference_name)| Event::RefUpdateEvent {
+            .map(|commit_oid| Event::ObsoleteEvent {
                 timestamp,
                 event_tx_id,
-                ref_name: reference_name.to_owned(),
-                old_oid: MaybeZeroOid::NonZero(*old_oid),
-                new_oid: MaybeZeroOid::Zero,
-                message: None,
+                commit_oid: *commit_oid,
             });
-
-    if !dry_run {
-        let events = commit_events.into_iter().chain(branch_events).collect_vec();
+        let branch_events =
+            broken_branches
+                .iter()
+                .map(|(old_oid, reference_name)| Event::RefUpdateEvent {
+                    timestamp,
+                    event_tx_id,
+                    ref_name: reference_name.to_owned(),
+                    old_oid: MaybeZeroOid::NonZero(*old_oid),
+                    new_oid: MaybeZeroOid::Zero,
+                    message: None,
+                });
+        let events = commit_events.chain(branch_events).collect_vec();
         event_log_db.add_events(events)?;
+
+        if dag_problem.is_some() {
+            rebuild_dag(effects, &repo, &event_log_db)?;
+        }
     }
 
+    let found = if dry_run {
+        "Found"
+    } else {
+        "Found and repaired"
+    };
     if num_broken_commits > 0 {
         writeln!(
             effects.get_output_stream(),
-            "Found and repaired {}: {}",
+            "{} {}: {}",
+            found,
             Pluralize {
                 determiner: None,
                 amount: num_broken_commits,
@@ -105,7 +286,8 @@ pub fn repair(effects: &Effects, dry_run: bool) -> eyre::Result<ExitCode> {
     if num_broken_branches > 0 {
         writeln!(
             effects.get_output_stream(),
-            "Found and repaired {}: {}",
+            "{} {}: {}",
+            found,
             Pluralize {
                 determiner: None,
                 amount: num_broken_branches,
@@ -122,6 +304,31 @@ pub fn repair(effects: &Effects, dry_run: bool) -> eyre::Result<ExitCode> {
         )?;
     }
 
+    match dag_problem {
+        None => {}
+        Some(DagProblem::Unreadable(err)) => {
+            warn!(?err, "Could not load commit graph");
+            writeln!(
+                effects.get_output_stream(),
+                "{} broken commit graph, which could not be loaded.",
+                found
+            )?;
+        }
+        Some(DagProblem::InconsistentCommits(oids)) => {
+            writeln!(
+                effects.get_output_stream(),
+                "{} {} in the commit graph: {}",
+                found,
+                Pluralize {
+                    determiner: None,
+                    amount: oids.len(),
+                    unit: ("inconsistent commit", "inconsistent commits")
+                },
+                oids.into_iter().sorted().join(", "),
+            )?;
+        }
+    }
+
     if dry_run {
         writeln!(
             effects.get_output_stream(),
@@ -62,14 +62,16 @@ use std::time::SystemTime;
 use eden_dag::DagAlgorithm;
 use lib::core::check_out::CheckOutCommitOptions;
 use lib::core::repo_ext::RepoExt;
+use lib::error::ErrorCategory;
 use lib::util::ExitCode;
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use tracing::{instrument, warn};
 
+use crate::commands::confirm_rewrite::confirm_large_rewrite;
 use crate::commands::smartlog::smartlog;
 use crate::opts::{MoveOptions, Revset};
 use crate::revset::resolve_commits;
-use lib::core::config::get_restack_preserve_timestamps;
+use lib::core::config::{get_restack_current_stack_only, get_restack_preserve_timestamps};
 use lib::core::dag::{commit_set_to_vec_unsorted, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
@@ -77,8 +79,9 @@ use lib::core::rewrite::{
     execute_rebase_plan, find_abandoned_children, find_rewrite_target, move_branches,
     BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
     MergeConflictRemediation, RebasePlanBuilder, RebasePlanPermissions, RepoPool, RepoResource,
+    RewriteSummary,
 };
-use lib::git::{GitRunInfo, NonZeroOid, Repo};
+use lib::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 
 #[instrument(skip(commits))]
 fn restack_commits(
@@ -93,7 +96,9 @@ fn restack_commits(
     commits: Option<impl IntoIterator<Item = NonZeroOid>>,
     build_options: &BuildRebasePlanOptions,
     execute_options: &ExecuteRebasePlanOptions,
+    skip_confirmation: bool,
     merge_conflict_remediation: MergeConflictRemediation,
+    summary: &mut RewriteSummary,
 ) -> eyre::Result<ExitCode> {
     let repo = repo_pool.try_create()?;
     let commit_set: CommitSet = match commits {
@@ -138,6 +143,7 @@ fn restack_commits(
     let rebase_plan = {
         let permissions = match RebasePlanPermissions::verify_rewrite_set(
             dag,
+            &repo,
             build_options,
             &rebases
                 .iter()
@@ -181,6 +187,19 @@ fn restack_commits(
         }
     };
 
+    if !confirm_large_rewrite(
+        effects,
+        &repo,
+        dag,
+        &rebase_plan
+            .get_rewritten_commit_oids()
+            .into_iter()
+            .collect(),
+        skip_confirmation,
+    )? {
+        return Ok(ExitCode(1));
+    }
+
     let execute_rebase_plan_result = execute_rebase_plan(
         effects,
         git_run_info,
@@ -197,7 +216,8 @@ fn restack_commits(
 
         ExecuteRebasePlanResult::DeclinedToMerge { merge_conflict } => {
             merge_conflict.describe(effects, &repo, merge_conflict_remediation)?;
-            Ok(ExitCode(1))
+            summary.add_conflict(&merge_conflict);
+            Ok(ErrorCategory::Conflict.exit_code())
         }
 
         ExecuteRebasePlanResult::Failed { exit_code } => {
@@ -218,6 +238,42 @@ fn restack_commits(
     }
 }
 
+/// Find the abandoned commits whose descendants should be restacked, given
+/// the commits that the user specified. These are the specified commits
+/// themselves, and any visible obsolete commits which were rewritten into one
+/// of them, so that e.g. specifying the current stack restacks the commits
+/// abandoned by rewriting it.
+#[instrument]
+fn find_commits_to_restack(
+    dag: &Dag,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    commits: &CommitSet,
+) -> eyre::Result<HashSet<NonZeroOid>> {
+    let mut result: HashSet<NonZeroOid> =
+        commit_set_to_vec_unsorted(commits)?.into_iter().collect();
+
+    let public_commits = dag.query_public_commits()?;
+    let active_heads = dag.query_active_heads(
+        &public_commits,
+        &dag.observed_commits.difference(&dag.obsolete_commits),
+    )?;
+    let visible_obsolete_commits = dag
+        .query()
+        .ancestors(active_heads)?
+        .intersection(&dag.obsolete_commits);
+    for oid in commit_set_to_vec_unsorted(&visible_obsolete_commits)? {
+        if let Some(MaybeZeroOid::NonZero(rewritten_oid)) =
+            find_rewrite_target(event_replayer, event_cursor, oid)
+        {
+            if commits.contains(&rewritten_oid.into())? {
+                result.insert(oid);
+            }
+        }
+    }
+    Ok(result)
+}
+
 #[instrument]
 fn restack_branches(
     effects: &Effects,
@@ -225,6 +281,7 @@ fn restack_branches(
     conn: &rusqlite::Connection,
     git_run_info: &GitRunInfo,
     event_log_db: &EventLogDb,
+    commits: Option<&HashSet<NonZeroOid>>,
     options: &ExecuteRebasePlanOptions,
 ) -> eyre::Result<ExitCode> {
     let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
@@ -241,6 +298,11 @@ fn restack_branches(
                 continue;
             }
         };
+        if let Some(commits) = commits {
+            if !commits.contains(&branch_target) {
+                continue;
+            }
+        }
 
         if let Some(new_oid) = find_rewrite_target(
             &event_replayer,
@@ -279,6 +341,7 @@ pub fn restack(
     revsets: Vec<Revset>,
     move_options: &MoveOptions,
     merge_conflict_remediation: MergeConflictRemediation,
+    summary: &mut RewriteSummary,
 ) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
@@ -297,7 +360,12 @@ pub fn restack(
         &references_snapshot,
     )?;
 
-    let commit_sets = match resolve_commits(effects, &repo, &mut dag, revsets) {
+    let revsets = if revsets.is_empty() && get_restack_current_stack_only(&repo)? {
+        vec![Revset("stack()".to_string())]
+    } else {
+        revsets
+    };
+    let commit_sets = match resolve_commits(effects, git_run_info, &repo, &mut dag, revsets) {
         Ok(commit_sets) => commit_sets,
         Err(err) => {
             err.describe(effects)?;
@@ -307,24 +375,28 @@ pub fn restack(
     let commits: Option<HashSet<NonZeroOid>> = if commit_sets.is_empty() {
         None
     } else {
-        Some(
-            commit_set_to_vec_unsorted(&union_all(&commit_sets))?
-                .into_iter()
-                .collect(),
-        )
+        Some(find_commits_to_restack(
+            &dag,
+            &event_replayer,
+            event_cursor,
+            &union_all(&commit_sets),
+        )?)
     };
 
     let MoveOptions {
         force_rewrite_public_commits,
+        force_rewrite_protected_branches,
         force_in_memory,
         force_on_disk,
         detect_duplicate_commits_via_patch_id,
         resolve_merge_conflicts,
+        skip_confirmation,
         dump_rebase_constraints,
         dump_rebase_plan,
     } = *move_options;
     let build_options = BuildRebasePlanOptions {
         force_rewrite_public_commits,
+        force_rewrite_protected_branches,
         dump_rebase_constraints,
         dump_rebase_plan,
         detect_duplicate_commits_via_patch_id,
@@ -353,10 +425,12 @@ pub fn restack(
         &event_log_db,
         event_cursor,
         git_run_info,
-        commits,
+        commits.clone(),
         &build_options,
         &execute_options,
+        skip_confirmation,
         merge_conflict_remediation,
+        summary,
     )?;
     if !exit_code.is_success() {
         return Ok(exit_code);
@@ -368,6 +442,7 @@ pub fn restack(
         &conn,
         git_run_info,
         &event_log_db,
+        commits.as_ref(),
         &execute_options,
     )?;
     if !exit_code.is_success() {
@@ -0,0 +1,150 @@
+//! Resume or roll back an operation which stopped partway through.
+//!
+//! Commands like `git sync` may have to stop before they're finished, such as
+//! when an on-disk rebase encounters merge conflicts or when the user presses
+//! Ctrl-C. In that case, they record a [`SuspendedOperation`] with the work
+//! remaining to be done. `git branchless continue` finishes any in-progress
+//! rebase and then carries out the remaining work, while `git branchless abort`
+//! aborts the in-progress rebase and undoes the changes made since the
+//! operation started.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer, SuspendedOperation};
+use lib::git::{GitRunInfo, Repo};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use crate::commands::undo::undo_since_transaction;
+
+/// Get the suspended operation, if any, discarding it if it was suspended
+/// during a rebase which has since been finished or aborted by some other
+/// means.
+fn get_live_suspended_operation(
+    event_log_db: &EventLogDb,
+    is_rebase_in_progress: bool,
+) -> eyre::Result<Option<SuspendedOperation>> {
+    match event_log_db.get_suspended_operation()? {
+        Some(suspended_operation)
+            if suspended_operation.is_rebase_in_progress && !is_rebase_in_progress =>
+        {
+            event_log_db.clear_suspended_operation()?;
+            Ok(None)
+        }
+        suspended_operation => Ok(suspended_operation),
+    }
+}
+
+/// Resume the suspended operation, if any, after continuing the rebase in
+/// progress, if any.
+#[instrument]
+pub fn r#continue(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "continue")?;
+
+    let is_rebase_in_progress = match repo.get_current_operation_type() {
+        None => false,
+        Some("rebase") => true,
+        Some(operation_type) => {
+            writeln!(
+                effects.get_output_stream(),
+                "A {} operation is in progress. Finish it with `git {} --continue` or `git {} --abort` first.",
+                operation_type,
+                operation_type,
+                operation_type,
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let suspended_operation = get_live_suspended_operation(&event_log_db, is_rebase_in_progress)?;
+    if !is_rebase_in_progress && suspended_operation.is_none() {
+        writeln!(effects.get_output_stream(), "No operation to continue.")?;
+        return Ok(ExitCode(1));
+    }
+
+    if is_rebase_in_progress {
+        let exit_code =
+            git_run_info.run_direct_no_wrapping(Some(event_tx_id), &["rebase", "--continue"])?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+    }
+
+    let SuspendedOperation {
+        command_name: _,
+        event_tx_id: _,
+        resume_args,
+        is_rebase_in_progress: _,
+    } = match suspended_operation {
+        Some(suspended_operation) => suspended_operation,
+        None => return Ok(ExitCode(0)),
+    };
+    // The resumed command will record a new suspended operation if it has to
+    // stop again.
+    event_log_db.clear_suspended_operation()?;
+    if resume_args.is_empty() {
+        return Ok(ExitCode(0));
+    }
+    let args = std::iter::once("branchless".to_string())
+        .chain(resume_args)
+        .collect::<Vec<_>>();
+    git_run_info.run(effects, Some(event_tx_id), &args)
+}
+
+/// Abort the rebase in progress, if any, and undo the changes made by the
+/// suspended operation, if any.
+#[instrument]
+pub fn abort(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "abort")?;
+
+    let is_rebase_in_progress = repo.get_current_operation_type() == Some("rebase");
+    let suspended_operation = get_live_suspended_operation(&event_log_db, is_rebase_in_progress)?;
+    if !is_rebase_in_progress && suspended_operation.is_none() {
+        writeln!(effects.get_output_stream(), "No operation to abort.")?;
+        return Ok(ExitCode(1));
+    }
+
+    // Aborting the rebase will itself record events, such as moving `HEAD`,
+    // which shouldn't be undone, so only consider the events which had
+    // happened before this point.
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    if is_rebase_in_progress {
+        let exit_code = git_run_info.run(effects, Some(event_tx_id), &["rebase", "--abort"])?;
+        if !exit_code.is_success() {
+            return Ok(exit_code);
+        }
+    }
+
+    let SuspendedOperation {
+        command_name,
+        event_tx_id: suspended_event_tx_id,
+        resume_args: _,
+        is_rebase_in_progress: _,
+    } = match suspended_operation {
+        Some(suspended_operation) => suspended_operation,
+        None => return Ok(ExitCode(0)),
+    };
+    event_log_db.clear_suspended_operation()?;
+    writeln!(
+        effects.get_output_stream(),
+        "Undoing changes made by `git {}`.",
+        command_name
+    )?;
+    undo_since_transaction(
+        effects,
+        git_run_info,
+        &repo,
+        &mut event_log_db,
+        &event_replayer,
+        suspended_event_tx_id,
+    )
+}
@@ -2,36 +2,49 @@
 
 use lib::core::check_out::CheckOutCommitOptions;
 use lib::core::repo_ext::RepoExt;
-use lib::util::ExitCode;
+use lib::util::{get_sh, shell_command, ExitCode};
 use rayon::ThreadPoolBuilder;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::ffi::OsStr;
 use std::fmt::Write;
 use std::fs::File;
-use std::io::Write as OtherWrite;
-use std::time::SystemTime;
+use std::io::{stdin, stdout, BufRead, Read, Write as OtherWrite};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
 
 use bstr::ByteSlice;
 use chrono::Local;
-use dialoguer::Editor;
 use eden_dag::DagAlgorithm;
 use eyre::Context;
+use itertools::Itertools;
+use thiserror::Error;
 use tracing::{instrument, warn};
 
 use lib::core::config::{
-    get_comment_char, get_commit_template, get_editor, get_restack_preserve_timestamps,
+    get_comment_string, get_commit_encoding, get_commit_template, get_editor,
+    get_restack_preserve_timestamps, get_reword_context, get_reword_directive_prefix,
+    get_reword_lint, get_reword_lint_command, get_reword_max_subject_length,
+    get_reword_reflow_column, get_sequence_editor, is_graphical_editor,
 };
 use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
 use lib::core::formatting::{printable_styled_string, Glyphs, Pluralize};
 use lib::core::node_descriptors::{render_node_descriptors, CommitOidDescriptor, NodeObject};
 use lib::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    execute_rebase_plan, simulate_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
+    ExecuteRebasePlanResult, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    SimulateRebasePlanResult,
 };
+use lib::core::user_hooks::{run_commit_hooks, run_commit_msg_hook, CommitHooksResult};
+use lib::error::Error as LibError;
 use lib::git::{message_prettify, Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 
+use crate::commands::confirm_rewrite::confirm_large_rewrite;
+use crate::commands::smartlog::render_dry_run_smartlog;
 use crate::opts::Revset;
 use crate::revset::resolve_commits;
 
@@ -43,6 +56,324 @@ pub enum InitialCommitMessages {
 
     /// The user provided explicit messages.
     Messages(Vec<String>),
+
+    /// The user provided the contents of a message file, which may be in the
+    /// bulk-edit format used when rewording multiple commits.
+    MessageFile(String),
+
+    /// The user wants to retry the last reword which failed because the edited
+    /// message didn't match the commits being reworded.
+    Resume,
+
+    /// The message saved by the last failed reword, which will be opened in
+    /// the editor again.
+    Saved(SavedMessage),
+
+    /// The user wants to re-wrap the bodies of the existing messages at the
+    /// given column, or at `branchless.reword.reflowColumn` if not provided.
+    Reflow {
+        /// The column to wrap at.
+        column: Option<usize>,
+    },
+}
+
+/// The name of the file in the Git directory where the edited message is saved
+/// if it doesn't match the commits being reworded.
+const SAVED_MESSAGE_FILE_NAME: &str = "REWORD_EDITMSG";
+
+/// An edited message saved by a failed reword.
+#[derive(Debug)]
+pub struct SavedMessage {
+    /// The commits which were being reworded.
+    commit_oids: Vec<NonZeroOid>,
+
+    /// The comment character used in the message.
+    comment_char: String,
+
+    /// The edited message, in the bulk-edit format.
+    message: String,
+}
+
+/// Save the edited message so that the user can recover their edits, or retry
+/// the reword with `--resume`.
+fn save_message(
+    repo: &Repo,
+    comment_char: &str,
+    commits: &[Commit],
+    edited_message: &str,
+) -> eyre::Result<()> {
+    let mut w = File::create(repo.get_path().join(SAVED_MESSAGE_FILE_NAME))
+        .with_context(|| format!("Creating {} file", SAVED_MESSAGE_FILE_NAME))?;
+    write!(
+        &mut w,
+        "{} This file was created by `git branchless reword` at {}\n\
+        {} You can use it to recover any edits you had made to the included commit {}.\n\
+        {} To fix the edits and try again, run `git branchless reword --resume`.\n\
+        {} If you don't need (or don't recognize) these edits, it is safe to delete this file.\n\
+        {} Commits: {}\n\
+        \n\
+        {}",
+        comment_char,
+        Local::now().to_rfc2822(),
+        comment_char,
+        if commits.len() == 1 {
+            "message"
+        } else {
+            "messages"
+        },
+        comment_char,
+        comment_char,
+        comment_char,
+        commits.iter().map(|commit| commit.get_oid()).join(" "),
+        edited_message
+    )?;
+    Ok(())
+}
+
+/// Load the message saved by the last failed reword, if any.
+fn load_saved_message(repo: &Repo) -> eyre::Result<Option<SavedMessage>> {
+    let path = repo.get_path().join(SAVED_MESSAGE_FILE_NAME);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Reading {} file", SAVED_MESSAGE_FILE_NAME))
+        }
+    };
+
+    let (header, message) = contents.split_once("\n\n").unwrap_or((&contents, ""));
+    let comment_char = header
+        .lines()
+        .next()
+        .and_then(|line| line.split_once(" This file was created by `git branchless reword`"))
+        .map(|(comment_char, _rest)| comment_char.to_string());
+    let comment_char = match comment_char {
+        Some(comment_char) => comment_char,
+        None => eyre::bail!(
+            "{} was not created by `git branchless reword`: {:?}",
+            SAVED_MESSAGE_FILE_NAME,
+            path
+        ),
+    };
+
+    let commits_prefix = format!("{} Commits: ", comment_char);
+    let commit_oids = match header
+        .lines()
+        .find_map(|line| line.strip_prefix(commits_prefix.as_str()))
+    {
+        Some(commit_oids) => commit_oids
+            .split_whitespace()
+            .map(NonZeroOid::from_str)
+            .collect::<eyre::Result<Vec<_>>>()?,
+        None => eyre::bail!(
+            "{} does not list the commits to reword: {:?}",
+            SAVED_MESSAGE_FILE_NAME,
+            path
+        ),
+    };
+
+    Ok(Some(SavedMessage {
+        commit_oids,
+        comment_char,
+        message: message.to_string(),
+    }))
+}
+
+/// Read the contents of the message file at the given path, or from stdin if
+/// the path is `-`.
+pub fn read_message_file(path: &Path) -> eyre::Result<String> {
+    if path == Path::new("-") {
+        let mut contents = String::new();
+        stdin()
+            .read_to_string(&mut contents)
+            .context("Reading commit message from stdin")?;
+        Ok(contents)
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Reading commit message from file: {:?}", path))
+    }
+}
+
+/// Re-wrap the body of a commit message so that no line is longer than
+/// `column` characters, where possible. The subject line is left as-is.
+///
+/// Paragraphs are filled, and list items are wrapped with a hanging indent.
+/// Lines which are formatted deliberately are kept verbatim: fenced and
+/// indented code blocks, footnote-style lines such as `[1]: https://...`, and
+/// the trailers at the end of the message, such as `Signed-off-by: ...`.
+/// Words longer than `column`, such as URLs, aren't broken.
+pub fn reflow_message(message: &str, column: usize) -> String {
+    let mut lines = message.lines();
+    let subject = match lines.next() {
+        Some(subject) => subject,
+        None => return message.to_string(),
+    };
+
+    // Split the body into paragraphs, keeping fenced code blocks whole even if
+    // they contain blank lines.
+    let mut paragraphs: Vec<Vec<&str>> = Vec::new();
+    let mut current_paragraph: Vec<&str> = Vec::new();
+    let mut is_in_code_fence = false;
+    for line in lines {
+        if is_code_fence(line) {
+            is_in_code_fence = !is_in_code_fence;
+        }
+        if line.trim().is_empty() && !is_in_code_fence {
+            paragraphs.push(std::mem::take(&mut current_paragraph));
+        } else {
+            current_paragraph.push(line);
+        }
+    }
+    paragraphs.push(current_paragraph);
+
+    let last_paragraph_index = paragraphs.iter().rposition(|lines| !lines.is_empty());
+    let mut output = vec![subject.to_string()];
+    for (i, paragraph) in paragraphs.iter().enumerate() {
+        if Some(i) == last_paragraph_index && is_trailer_paragraph(paragraph) {
+            output.extend(paragraph.iter().map(|line| line.to_string()));
+        } else {
+            output.extend(reflow_paragraph(paragraph, column));
+        }
+        if i + 1 < paragraphs.len() {
+            output.push(String::new());
+        }
+    }
+
+    let mut result = output.join("\n");
+    if message.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+fn is_code_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+fn is_indented(line: &str) -> bool {
+    line.starts_with(' ') || line.starts_with('\t')
+}
+
+/// Whether the line is a Git trailer, such as `Signed-off-by: Name <email>`.
+fn is_trailer(line: &str) -> bool {
+    match line.split_once(": ") {
+        Some((token, _value)) => {
+            !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }
+        None => false,
+    }
+}
+
+/// Whether the paragraph consists of trailers, possibly with indented
+/// continuation lines.
+fn is_trailer_paragraph(paragraph: &[&str]) -> bool {
+    match paragraph.first() {
+        Some(first_line) => {
+            is_trailer(first_line)
+                && paragraph
+                    .iter()
+                    .all(|line| is_trailer(line) || is_indented(line))
+        }
+        None => false,
+    }
+}
+
+/// Whether the line is a footnote or link reference, such as `[1]: https://...`.
+fn is_footnote(line: &str) -> bool {
+    match line.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((label, _rest)) => !label.is_empty() && !label.contains(char::is_whitespace),
+            None => false,
+        },
+        None => false,
+    }
+}
+
+/// If the line starts a list item, such as `- foo` or `2. bar`, return the
+/// marker, including any indentation and the space after it.
+fn get_list_marker(line: &str) -> Option<&str> {
+    let indent_len = line.len() - line.trim_start().len();
+    let rest = &line[indent_len..];
+    let marker_len = if rest.starts_with("- ") || rest.starts_with("* ") || rest.starts_with("+ ") {
+        1
+    } else {
+        let num_digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        match rest[num_digits..].chars().next() {
+            Some('.') | Some(')') if num_digits > 0 => num_digits + 1,
+            _ => return None,
+        }
+    };
+    if rest[marker_len..].starts_with(' ') {
+        Some(&line[..indent_len + marker_len + 1])
+    } else {
+        None
+    }
+}
+
+fn reflow_paragraph(paragraph: &[&str], column: usize) -> Vec<String> {
+    let mut output = Vec::new();
+    let mut is_in_code_fence = false;
+
+    // The text being filled, with the indent for its first line and for the
+    // remaining lines.
+    let mut item: Option<(String, String, String)> = None;
+    let flush = |item: &mut Option<(String, String, String)>, output: &mut Vec<String>| {
+        if let Some((initial_indent, subsequent_indent, text)) = item.take() {
+            let options = textwrap::Options::new(column)
+                .initial_indent(&initial_indent)
+                .subsequent_indent(&subsequent_indent)
+                .break_words(false);
+            output.extend(
+                textwrap::wrap(&text, options)
+                    .into_iter()
+                    .map(|line| line.into_owned()),
+            );
+        }
+    };
+
+    for line in paragraph {
+        if is_in_code_fence || is_code_fence(line) {
+            flush(&mut item, &mut output);
+            if is_code_fence(line) {
+                is_in_code_fence = !is_in_code_fence;
+            }
+            output.push(line.to_string());
+        } else if let Some(marker) = get_list_marker(line) {
+            flush(&mut item, &mut output);
+            item = Some((
+                marker.to_string(),
+                " ".repeat(marker.chars().count()),
+                line[marker.len()..].trim().to_string(),
+            ));
+        } else if is_footnote(line) {
+            flush(&mut item, &mut output);
+            output.push(line.to_string());
+        } else {
+            match &mut item {
+                // An indented line continues the previous list item.
+                Some((_initial_indent, subsequent_indent, text))
+                    if !subsequent_indent.is_empty() && is_indented(line) =>
+                {
+                    text.push(' ');
+                    text.push_str(line.trim());
+                }
+                _ if is_indented(line) => {
+                    flush(&mut item, &mut output);
+                    output.push(line.to_string());
+                }
+                Some((_initial_indent, _subsequent_indent, text)) => {
+                    text.push(' ');
+                    text.push_str(line.trim());
+                }
+                None => {
+                    item = Some((String::new(), String::new(), line.trim().to_string()));
+                }
+            }
+        }
+    }
+    flush(&mut item, &mut output);
+    output
 }
 
 /// Reword a commit and restack its descendants.
@@ -53,6 +384,9 @@ pub fn reword(
     messages: InitialCommitMessages,
     git_run_info: &GitRunInfo,
     force_rewrite_public_commits: bool,
+    force_rewrite_protected_branches: bool,
+    dry_run: bool,
+    skip_confirmation: bool,
 ) -> eyre::Result<ExitCode> {
     let repo = Repo::from_current_dir()?;
     let references_snapshot = repo.get_references_snapshot()?;
@@ -68,18 +402,41 @@ pub fn reword(
         &references_snapshot,
     )?;
 
-    let commits = match resolve_commits_from_hashes(&repo, &mut dag, effects, revsets)? {
-        Some(commits) => commits,
-        None => return Ok(ExitCode(1)),
+    let (revsets, messages) = match messages {
+        InitialCommitMessages::Resume => match load_saved_message(&repo)? {
+            Some(saved_message) => (
+                saved_message
+                    .commit_oids
+                    .iter()
+                    .map(|oid| Revset(oid.to_string()))
+                    .collect(),
+                InitialCommitMessages::Saved(saved_message),
+            ),
+            None => {
+                writeln!(effects.get_output_stream(), "No reword to resume.")?;
+                return Ok(ExitCode(1));
+            }
+        },
+        messages => (revsets, messages),
     };
+    let is_resumed = matches!(messages, InitialCommitMessages::Saved(_));
+    let is_reflow = matches!(messages, InitialCommitMessages::Reflow { .. });
+
+    let commits =
+        match resolve_commits_from_hashes(&repo, &mut dag, effects, git_run_info, revsets)? {
+            Some(commits) => commits,
+            None => return Ok(ExitCode(1)),
+        };
     let build_options = BuildRebasePlanOptions {
         force_rewrite_public_commits,
+        force_rewrite_protected_branches,
         dump_rebase_constraints: false,
         dump_rebase_plan: false,
         detect_duplicate_commits_via_patch_id: false,
     };
     let permissions = match RebasePlanPermissions::verify_rewrite_set(
         &dag,
+        &repo,
         &build_options,
         &commits.iter().map(|commit| commit.get_oid()).collect(),
     )? {
@@ -90,27 +447,45 @@ pub fn reword(
         }
     };
 
-    #[instrument]
-    fn edit_message_fn_inner(
-        git_run_info: &GitRunInfo,
-        repo: &Repo,
-        message: &str,
-    ) -> eyre::Result<String> {
-        let mut editor = Editor::new();
-        let (editor, editor_program) = match get_editor(git_run_info, repo)? {
-            Some(editor_program) => (editor.executable(&editor_program), editor_program),
-            None => (&mut editor, "<default>".into()),
-        };
-        let result = editor
-            .require_save(false)
-            .edit(message)
-            .with_context(|| format!("Invoking editor: '{}'", editor_program.to_string_lossy()))?
-            .expect("`Editor::edit` should not return `None` when `require_save` is `false`");
-        Ok(result)
+    // Ask before opening the editor, so that a mistyped revset doesn't make the
+    // user edit a huge number of messages first.
+    let commits_to_rewrite = dag
+        .query()
+        .descendants(commits.iter().map(|commit| commit.get_oid()).collect())?
+        .difference(&dag.obsolete_commits);
+    if !confirm_large_rewrite(
+        effects,
+        &repo,
+        &dag,
+        &commits_to_rewrite,
+        skip_confirmation || dry_run,
+    )? {
+        return Ok(ExitCode(1));
     }
-    let edit_message_fn = |message: &str| edit_message_fn_inner(git_run_info, &repo, message);
 
-    let messages = match prepare_messages(&repo, messages, &commits, edit_message_fn)? {
+    let edit_message_fn = |message: &str| {
+        if commits.len() > 1 {
+            edit_bulk_message(effects, git_run_info, &repo, message)
+        } else {
+            edit_message(effects, git_run_info, &repo, message)
+        }
+    };
+
+    let messages = match prepare_messages(effects, &repo, messages, &commits, edit_message_fn)? {
+        PrepareMessagesResult::Succeeded { messages } if messages.is_empty() && is_reflow => {
+            writeln!(
+                effects.get_output_stream(),
+                "Aborting. All commit messages are already wrapped; nothing to do."
+            )?;
+            return Ok(ExitCode(1));
+        }
+        PrepareMessagesResult::Succeeded { messages } if messages.is_empty() => {
+            writeln!(
+                effects.get_output_stream(),
+                "Aborting. All commits were skipped; nothing to do."
+            )?;
+            return Ok(ExitCode(1));
+        }
         PrepareMessagesResult::Succeeded { messages } => messages,
         PrepareMessagesResult::IdenticalMessage => {
             writeln!(
@@ -126,6 +501,33 @@ pub fn reword(
             )?;
             return Ok(ExitCode(1));
         }
+        PrepareMessagesResult::Aborted => {
+            writeln!(effects.get_error_stream(), "Aborting reword.")?;
+            writeln!(
+                effects.get_error_stream(),
+                "Your edited message has been saved to .git/REWORD_EDITMSG for review and/or manual recovery."
+            )?;
+            writeln!(
+                effects.get_error_stream(),
+                "To fix it and try again, run: git branchless reword --resume"
+            )?;
+            return Ok(ExitCode(1));
+        }
+        PrepareMessagesResult::RejectedMessages { rejections } => {
+            writeln!(
+                effects.get_error_stream(),
+                "Aborting reword because the commit message hook rejected {}:",
+                Pluralize {
+                    determiner: None,
+                    amount: rejections.len(),
+                    unit: ("message", "messages"),
+                }
+            )?;
+            for (short_oid, output) in rejections {
+                writeln!(effects.get_error_stream(), "{}:\n{}", short_oid, output)?;
+            }
+            return Ok(ExitCode(1));
+        }
         PrepareMessagesResult::MismatchedCommits {
             mut duplicates,
             mut missing,
@@ -182,60 +584,125 @@ pub fn reword(
                 effects.get_error_stream(),
                 "Your edited message has been saved to .git/REWORD_EDITMSG for review and/or manual recovery."
             )?;
+            writeln!(
+                effects.get_error_stream(),
+                "To fix it and try again, run: git branchless reword --resume"
+            )?;
             return Ok(ExitCode(1));
         }
     };
 
-    let subtree_roots = find_subtree_roots(&repo, &dag, &commits)?;
+    // Commits skipped in the bulk-edit message keep their existing message, as
+    // do commits whose messages aren't stored as UTF-8 and weren't edited, so
+    // that their original bytes are preserved.
+    let commit_encoding = get_commit_encoding(&repo)?;
+    let mut messages = messages;
+    for commit in commits.iter() {
+        if let Some(message) = messages.get(&commit.get_oid()) {
+            if is_unedited_non_utf8_message(commit, commit_encoding.as_deref(), message)? {
+                messages.remove(&commit.get_oid());
+            }
+        }
+    }
+    if messages.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Aborting. All commits were skipped; nothing to do."
+        )?;
+        return Ok(ExitCode(1));
+    }
+    let commits: Vec<Commit> = commits
+        .into_iter()
+        .filter(|commit| messages.contains_key(&commit.get_oid()))
+        .collect();
+    for root_commit in find_subtree_roots(&repo, &dag, &commits)? {
+        if root_commit.get_only_parent().is_none() {
+            writeln!(
+                effects.get_error_stream(),
+                "Refusing to reword commit {}, which has {} parents.\n\
+                Rewording is only supported for commits with 1 parent.\n\
+                Aborting.",
+                root_commit.get_oid(),
+                root_commit.get_parents().len(),
+            )?;
+            return Ok(ExitCode(1));
+        }
+    }
 
-    let rebase_plan = {
+    let now = SystemTime::now();
+    let event_tx_id = if dry_run {
+        EventTransactionId::DRY_RUN
+    } else {
+        event_log_db.make_transaction_id(now, "reword")?
+    };
+    let mut replacement_oids: HashMap<NonZeroOid, NonZeroOid> = HashMap::new();
+    for commit in commits.iter() {
+        let message = messages.get(&commit.get_oid()).unwrap();
+        let message = match run_commit_hooks(
+            effects,
+            git_run_info,
+            &repo,
+            event_tx_id,
+            &commit.get_tree()?,
+            message,
+        )? {
+            CommitHooksResult::Succeeded { message } => message,
+            CommitHooksResult::Failed { hook_name } => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Aborting reword because the {} hook failed for commit {}.",
+                    hook_name,
+                    commit.get_short_oid()?
+                )?;
+                return Ok(ExitCode(1));
+            }
+        };
+        // This looks funny, but just means "leave everything but the message as is"
+        let replacement_oid = match &commit_encoding {
+            Some(commit_encoding) => {
+                repo.amend_commit_message_encoded(commit, &message, commit_encoding)?
+            }
+            None => commit.amend_commit(None, None, None, Some(message.as_str()), None)?,
+        };
+        replacement_oids.insert(commit.get_oid(), replacement_oid);
+    }
+
+    // Each independent stack is rebased with its own plan, so that a failure
+    // in one stack doesn't keep the other stacks from being reworded.
+    let stacks = group_commits_by_stack(&dag, &commits)?;
+    let mut stack_plans = Vec::new();
+    {
         let pool = ThreadPoolBuilder::new().build()?;
         let repo_pool = RepoResource::new_pool(&repo)?;
-        let mut builder = RebasePlanBuilder::new(&dag, permissions);
+        for stack_commits in stacks {
+            let mut builder = RebasePlanBuilder::new(&dag, permissions.clone());
+            for root_commit in find_subtree_roots(&repo, &dag, &stack_commits)? {
+                let only_parent_id = root_commit
+                    .get_only_parent()
+                    .map(|parent| parent.get_oid())
+                    .expect("Subtree roots should have been checked to have only one parent");
+                builder.move_subtree(root_commit.get_oid(), only_parent_id)?;
+            }
+            for commit in stack_commits.iter() {
+                builder.replace_commit(commit.get_oid(), replacement_oids[&commit.get_oid()])?;
+            }
 
-        for root_commit in subtree_roots {
-            let only_parent_id = root_commit.get_only_parent().map(|parent| parent.get_oid());
-            let only_parent_id = match only_parent_id {
-                Some(only_parent_id) => only_parent_id,
-                None => {
-                    writeln!(
-                        effects.get_error_stream(),
-                        "Refusing to reword commit {}, which has {} parents.\n\
-                        Rewording is only supported for commits with 1 parent.\n\
-                        Aborting.",
-                        root_commit.get_oid(),
-                        root_commit.get_parents().len(),
-                    )?;
-                    return Ok(ExitCode(1));
+            let rebase_plan = match builder.build(effects, &pool, &repo_pool)? {
+                Ok(Some(rebase_plan)) => Some(rebase_plan),
+                Ok(None) => {
+                    eyre::bail!(
+                        "BUG: rebase plan indicates nothing to do, but rewording should always do something."
+                    );
+                }
+                Err(err) => {
+                    err.describe(effects, &repo)?;
+                    None
                 }
             };
-            builder.move_subtree(root_commit.get_oid(), only_parent_id)?;
+            stack_plans.push((stack_commits, rebase_plan));
         }
+    }
 
-        for commit in commits.iter() {
-            let message = messages.get(&commit.get_oid()).unwrap();
-            // This looks funny, but just means "leave everything but the message as is"
-            let replacement_oid =
-                commit.amend_commit(None, None, None, Some(message.as_str()), None)?;
-            builder.replace_commit(commit.get_oid(), replacement_oid)?;
-        }
-
-        match builder.build(effects, &pool, &repo_pool)? {
-            Ok(Some(rebase_plan)) => rebase_plan,
-            Ok(None) => {
-                eyre::bail!(
-                    "BUG: rebase plan indicates nothing to do, but rewording should always do something."
-                );
-            }
-            Err(err) => {
-                err.describe(effects, &repo)?;
-                return Ok(ExitCode(1));
-            }
-        }
-    };
-
-    let now = SystemTime::now();
-    let event_tx_id = event_log_db.make_transaction_id(now, "reword")?;
     let execute_options = ExecuteRebasePlanOptions {
         now,
         event_tx_id,
@@ -248,42 +715,366 @@ pub fn reword(
             render_smartlog: false,
         },
     };
-    let result = execute_rebase_plan(
-        effects,
-        git_run_info,
-        &repo,
-        &event_log_db,
-        &rebase_plan,
-        &execute_options,
-    )?;
 
-    let exit_code = match result {
-        ExecuteRebasePlanResult::Succeeded {
-            rewritten_oids: Some(rewritten_oids),
-        } => {
-            render_status_report(&repo, effects, &commits, &rewritten_oids)?;
-            ExitCode(0)
+    let mut stack_results: Vec<StackResult> = Vec::new();
+    for (stack_commits, rebase_plan) in stack_plans {
+        let rebase_plan = match rebase_plan {
+            Some(rebase_plan) => rebase_plan,
+            None => {
+                stack_results.push((stack_commits, Err(ExitCode(1))));
+                continue;
+            }
+        };
+
+        let result = if dry_run {
+            match simulate_rebase_plan(effects, &repo, &rebase_plan, &execute_options)? {
+                SimulateRebasePlanResult::Succeeded { rewritten_oids } => Ok(rewritten_oids),
+                SimulateRebasePlanResult::MergeConflict { merge_conflict: _ } => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "BUG: Merge conflict detected, but rewording shouldn't cause any conflicts."
+                    )?;
+                    Err(ExitCode(1))
+                }
+                SimulateRebasePlanResult::CannotSimulate { reason } => {
+                    writeln!(effects.get_error_stream(), "{}", reason)?;
+                    Err(ExitCode(1))
+                }
+            }
+        } else {
+            match execute_rebase_plan(
+                effects,
+                git_run_info,
+                &repo,
+                &event_log_db,
+                &rebase_plan,
+                &execute_options,
+            )? {
+                ExecuteRebasePlanResult::Succeeded {
+                    rewritten_oids: Some(rewritten_oids),
+                } => Ok(rewritten_oids),
+                ExecuteRebasePlanResult::Succeeded {
+                    rewritten_oids: None,
+                } => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "BUG: Succeeded rewording commits via on-disk rebase? But reword should be rebasing in-memory!"
+                    )?;
+                    Err(ExitCode(1))
+                }
+                ExecuteRebasePlanResult::DeclinedToMerge { merge_conflict: _ } => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "BUG: Merge conflict detected, but rewording shouldn't cause any conflicts."
+                    )?;
+                    Err(ExitCode(1))
+                }
+                ExecuteRebasePlanResult::Failed { exit_code } => Err(exit_code),
+            }
+        };
+        stack_results.push((stack_commits, result));
+    }
+
+    let mut rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid> = HashMap::new();
+    let mut exit_code = ExitCode(0);
+    for (_stack_commits, result) in stack_results.iter() {
+        match result {
+            Ok(stack_rewritten_oids) => rewritten_oids.extend(stack_rewritten_oids),
+            Err(stack_exit_code) => {
+                if exit_code.is_success() {
+                    exit_code = *stack_exit_code;
+                }
+            }
         }
-        ExecuteRebasePlanResult::Succeeded {
-            rewritten_oids: None,
-        } => {
-            writeln!(
-                effects.get_error_stream(),
-                "BUG: Succeeded rewording commits via on-disk rebase? But reword should be rebasing in-memory!"
-            )?;
-            ExitCode(1)
+    }
+    let reworded_commits: Vec<Commit> = commits
+        .iter()
+        .filter(|commit| {
+            stack_results.iter().any(|(stack_commits, result)| {
+                result.is_ok()
+                    && stack_commits
+                        .iter()
+                        .any(|stack_commit| stack_commit.get_oid() == commit.get_oid())
+            })
+        })
+        .cloned()
+        .collect();
+
+    if dry_run {
+        if !reworded_commits.is_empty() {
+            render_dry_run_smartlog(effects, &repo, &rewritten_oids, &HashSet::new(), false)?;
+        }
+    } else {
+        if exit_code.is_success() && is_resumed {
+            std::fs::remove_file(repo.get_path().join(SAVED_MESSAGE_FILE_NAME))
+                .with_context(|| format!("Removing {} file", SAVED_MESSAGE_FILE_NAME))?;
+        }
+        if !reworded_commits.is_empty() {
+            render_status_report(&repo, effects, &reworded_commits, &rewritten_oids)?;
         }
-        ExecuteRebasePlanResult::DeclinedToMerge { merge_conflict: _ } => {
+    }
+    if stack_results.len() > 1 {
+        render_stack_report(effects, &stack_results, dry_run)?;
+    }
+
+    Ok(exit_code)
+}
+
+/// Open the user's editor with the given message and return the edited message.
+#[instrument]
+pub(crate) fn edit_message(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    message: &str,
+) -> eyre::Result<String> {
+    let editor = get_editor(git_run_info, repo)?;
+    edit_message_with(effects, git_run_info, repo, editor.as_deref(), message)
+}
+
+/// Open the user's sequence editor with the given bulk-edit message for
+/// several commits and return the edited message. As in Git, this is the
+/// regular editor unless a sequence editor has been configured.
+#[instrument]
+fn edit_bulk_message(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    message: &str,
+) -> eyre::Result<String> {
+    let editor = get_sequence_editor(git_run_info, repo)?;
+    edit_message_with(effects, git_run_info, repo, editor.as_deref(), message)
+}
+
+/// Edit the message with the given editor. If there's no editor, or it can't be
+/// found (as in minimal environments such as containers), then the message is
+/// edited in the terminal instead.
+fn edit_message_with(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    editor: Option<&OsStr>,
+    message: &str,
+) -> eyre::Result<String> {
+    match editor {
+        None => {
             writeln!(
-                effects.get_error_stream(),
-                "BUG: Merge conflict detected, but rewording shouldn't cause any conflicts."
+                effects.get_output_stream(),
+                "No editor is configured, so the message will be edited here. \
+To use an editor instead, set `core.editor` or `$GIT_EDITOR`."
             )?;
-            ExitCode(1)
         }
-        ExecuteRebasePlanResult::Failed { exit_code } => exit_code,
+        Some(editor) => match run_editor(git_run_info, repo, editor, message)? {
+            Some(edited_message) => return Ok(edited_message),
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "The editor '{}' could not be found, so the message will be edited here.",
+                    editor.to_string_lossy()
+                )?;
+            }
+        },
+    }
+    edit_message_in_terminal(effects, &mut stdin().lock(), message)
+}
+
+/// The line which ends the message entered into [`edit_message_in_terminal`].
+const TERMINAL_EDITOR_END_MARKER: &str = ".";
+
+/// Edit the message by showing it and then reading a replacement message from
+/// the given input, line by line.
+///
+/// The message is ended by a line containing only `.`, or by the end of the
+/// input. If `.` is entered on the first line, then the message is kept as-is.
+/// If the input ends before anything has been entered, then the edit is
+/// aborted.
+fn edit_message_in_terminal(
+    effects: &Effects,
+    r#in: &mut impl BufRead,
+    message: &str,
+) -> eyre::Result<String> {
+    writeln!(effects.get_output_stream(), "The current message is:")?;
+    for line in message.lines() {
+        if line.is_empty() {
+            writeln!(effects.get_output_stream())?;
+        } else {
+            writeln!(effects.get_output_stream(), "    {}", line)?;
+        }
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Enter the new message, followed by a line containing only `{marker}`. \
+To keep the current message, enter only `{marker}`.",
+        marker = TERMINAL_EDITOR_END_MARKER
+    )?;
+    stdout().flush()?;
+
+    let mut lines: Vec<String> = Vec::new();
+    loop {
+        let mut line = String::new();
+        if r#in.read_line(&mut line)? == 0 {
+            if lines.is_empty() {
+                return Err(LibError::User {
+                    message: "Aborting because no message was entered.".to_string(),
+                }
+                .into());
+            }
+            break;
+        }
+        let line = line.trim_end_matches(&['\n', '\r'][..]);
+        if line == TERMINAL_EDITOR_END_MARKER {
+            if lines.is_empty() {
+                return Ok(message.to_owned());
+            }
+            break;
+        }
+        lines.push(line.to_owned());
+    }
+    Ok(lines.join("\n"))
+}
+
+/// If a graphical editor exits within this time without saving the message,
+/// then it probably didn't wait for the user to edit the message at all.
+const EDITOR_MIN_DURATION: Duration = Duration::from_secs(1);
+
+/// Run the given editor on the message, in the same way that Git does.
+///
+/// If the editor fails, or is a graphical editor which exits immediately
+/// without saving the message (as those which don't wait for the file to be
+/// closed do), then the edit is aborted. Returns `None` if the editor couldn't
+/// be found.
+#[instrument]
+fn run_editor(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    editor: &OsStr,
+    message: &str,
+) -> eyre::Result<Option<String>> {
+    // Git treats `:` as an editor which leaves the message as-is.
+    if editor == ":" {
+        return Ok(Some(message.to_owned()));
+    }
+
+    let dir = repo.get_tempfile_dir();
+    std::fs::create_dir_all(&dir).wrap_err("Creating temporary directory")?;
+    // Editors recognize commit messages by their file name, so rather than
+    // giving the file a unique name, put it in a directory unique to this
+    // invocation. The directory is removed when it's dropped.
+    let message_dir = tempfile::Builder::new()
+        .prefix("reword-")
+        .tempdir_in(&dir)
+        .wrap_err("Creating temporary directory for message")?;
+    let path = message_dir.path().join("COMMIT_EDITMSG");
+    std::fs::write(&path, message).wrap_err("Writing message to edit")?;
+    let original_modified_time = std::fs::metadata(&path)?.modified()?;
+
+    let editor_str = editor.to_string_lossy();
+    let mut command = match get_sh() {
+        // As in Git, the editor is run by the shell, so that it can include
+        // arguments.
+        Some(_) => shell_command(&editor_str)?,
+        None => {
+            let mut args = editor_str.split_whitespace();
+            let mut command = Command::new(args.next().unwrap_or_default());
+            command.args(args);
+            command
+        }
     };
+    let start_time = Instant::now();
+    let status = match command
+        .arg(&path)
+        .current_dir(&git_run_info.working_directory)
+        .envs(&git_run_info.env)
+        .status()
+    {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(None);
+        }
+        result => result.with_context(|| format!("Invoking editor: '{}'", editor_str))?,
+    };
+    let duration = start_time.elapsed();
+    // The shell exits with this code if it can't find the editor.
+    const SH_COMMAND_NOT_FOUND_EXIT_CODE: i32 = 127;
+    if status.code() == Some(SH_COMMAND_NOT_FOUND_EXIT_CODE) {
+        return Ok(None);
+    }
+    if !status.success() {
+        return Err(LibError::User {
+            message: format!("There was a problem with the editor '{}'.", editor_str),
+        }
+        .into());
+    }
 
-    Ok(exit_code)
+    let edited_message = std::fs::read_to_string(&path).wrap_err("Reading edited message")?;
+    let is_saved = std::fs::metadata(&path)?.modified()? != original_modified_time;
+    if is_graphical_editor(&editor_str)
+        && !is_saved
+        && edited_message == message
+        && duration < EDITOR_MIN_DURATION
+    {
+        return Err(LibError::User {
+            message: format!(
+                "Aborting because the editor '{}' exited without saving the message. \
+If it's a graphical editor, it may need an option to wait until the file is closed, \
+such as `code --wait`.",
+                editor_str
+            ),
+        }
+        .into());
+    }
+
+    Ok(Some(
+        edited_message
+            .trim_end_matches(&['\n', '\r'][..])
+            .to_string(),
+    ))
+}
+
+/// Render the output of a rejected commit message hook as comment lines, so
+/// that it can be shown above the message when re-opening the editor.
+pub(crate) fn render_rejection_comment(comment_char: &str, label: &str, output: &str) -> String {
+    let mut result = format!(
+        "{} The commit message hook rejected the message for {}:\n",
+        comment_char, label
+    );
+    for line in output.lines() {
+        if line.is_empty() {
+            result.push_str(&format!("{}\n", comment_char));
+        } else {
+            result.push_str(&format!("{}   {}\n", comment_char, line));
+        }
+    }
+    result
+}
+
+/// Render the warnings about a commit's edited message as comment lines, so
+/// that they can be shown above the message when re-opening the editor.
+fn render_lint_comment(comment_char: &str, label: &str, warnings: &[String]) -> String {
+    let mut result = format!("{} Warnings for the message for {}:\n", comment_char, label);
+    for warning in warnings {
+        result.push_str(&format!("{}   {}\n", comment_char, warning));
+    }
+    result
+}
+
+/// Whether the commit's message isn't stored as UTF-8, and the new message is
+/// the same as its decoded original message.
+fn is_unedited_non_utf8_message(
+    commit: &Commit,
+    fallback_encoding: Option<&str>,
+    message: &str,
+) -> eyre::Result<bool> {
+    let is_utf8 = match commit.get_message_encoding() {
+        Some(encoding) => encoding.eq_ignore_ascii_case("utf-8"),
+        None => commit.get_message_raw()?.to_str().is_ok(),
+    };
+    if is_utf8 {
+        return Ok(false);
+    }
+
+    let (original_message, _is_lossy) = commit.get_message_decoded(fallback_encoding);
+    let original_message = message_prettify(&original_message, None)?;
+    Ok(original_message.trim() == message.trim())
 }
 
 /// Turn a list of ref-ish strings into a list of Commits.
@@ -291,6 +1082,7 @@ fn resolve_commits_from_hashes<'repo>(
     repo: &'repo Repo,
     dag: &mut Dag,
     effects: &Effects,
+    git_run_info: &GitRunInfo,
     revsets: Vec<Revset>,
 ) -> eyre::Result<Option<Vec<Commit<'repo>>>> {
     let hashes = if revsets.is_empty() {
@@ -299,7 +1091,7 @@ fn resolve_commits_from_hashes<'repo>(
         revsets
     };
 
-    let commit_sets = match resolve_commits(effects, repo, dag, hashes) {
+    let commit_sets = match resolve_commits(effects, git_run_info, repo, dag, hashes) {
         Ok(commit_sets) => commit_sets,
         Err(err) => {
             err.describe(effects)?;
@@ -317,6 +1109,11 @@ fn resolve_commits_from_hashes<'repo>(
 #[must_use]
 #[derive(Debug)]
 enum PrepareMessagesResult {
+    /// The user chose to abort after being warned about problems with the
+    /// edited messages. The edited message has been saved so that the reword
+    /// can be resumed.
+    Aborted,
+
     /// The reworded message was empty.
     EmptyMessage,
 
@@ -329,6 +1126,14 @@ enum PrepareMessagesResult {
         unexpected: Vec<String>,
     },
 
+    /// The commit message hook rejected some of the messages, and the editor
+    /// couldn't be re-opened to fix them.
+    RejectedMessages {
+        /// The short OID of each commit with a rejected message, and the output
+        /// of the hook for it.
+        rejections: Vec<(String, String)>,
+    },
+
     /// The reworded message was built successfully.
     Succeeded {
         /// The reworded messages for each commit.
@@ -340,21 +1145,83 @@ enum PrepareMessagesResult {
 /// NonZeroOid to the relevant message.
 #[instrument(skip(edit_message_fn))]
 fn prepare_messages(
+    effects: &Effects,
     repo: &Repo,
     messages: InitialCommitMessages,
     commits: &[Commit],
     edit_message_fn: impl Fn(&str) -> eyre::Result<String>,
 ) -> eyre::Result<PrepareMessagesResult> {
-    let comment_char = get_comment_char(repo)?;
+    let comment_string = get_comment_string(repo)?;
+    let directive_prefix = get_reword_directive_prefix(repo)?;
+    let commit_context = CommitContext::from_config(repo)?;
+    let commit_encoding = get_commit_encoding(repo)?;
+
+    let (message, load_editor, discard_messages, provided_message) = match messages {
+        InitialCommitMessages::Resume => {
+            eyre::bail!("BUG: the saved message should be loaded before preparing messages.")
+        }
+        InitialCommitMessages::Saved(saved_message) => {
+            return edit_and_parse_messages(
+                effects,
+                repo,
+                commits,
+                &saved_message.comment_char,
+                &directive_prefix,
+                saved_message.message,
+                None,
+                edit_message_fn,
+                true,
+            );
+        }
+        InitialCommitMessages::Reflow { column } => {
+            let column = match column {
+                Some(column) => column,
+                None => get_reword_reflow_column(repo)?,
+            };
+            let mut messages = HashMap::new();
+            for commit in commits {
+                let (original_message, is_lossy) =
+                    commit.get_message_decoded(commit_encoding.as_deref());
+                if is_lossy {
+                    // Re-encoding the message would change more than its
+                    // wrapping, so leave it alone.
+                    continue;
+                }
+                let message = reflow_message(&original_message, column);
+                if message != original_message {
+                    messages.insert(commit.get_oid(), message);
+                }
+            }
 
-    let (message, load_editor, discard_messages) = match messages {
-        InitialCommitMessages::Discard => {
-            (get_commit_template(repo)?.unwrap_or_default(), true, true)
+            let rejections = check_messages(repo, commits, &messages)?;
+            if !rejections.is_empty() {
+                return Ok(PrepareMessagesResult::RejectedMessages { rejections });
+            }
+            return Ok(PrepareMessagesResult::Succeeded { messages });
         }
+        InitialCommitMessages::Discard => (
+            get_commit_template(repo)?.unwrap_or_default(),
+            true,
+            true,
+            None,
+        ),
         InitialCommitMessages::Messages(ref messages) => {
             let message = messages.clone().join("\n\n");
             let message = message.trim();
-            (message.to_string(), message.is_empty(), false)
+            (message.to_string(), message.is_empty(), false, None)
+        }
+        InitialCommitMessages::MessageFile(contents) => {
+            let is_bulk_edit = contents
+                .lines()
+                .any(|line| BulkEditDirective::parse(&directive_prefix, line).is_some());
+            if is_bulk_edit {
+                // Parse the file as if it were the result of editing the
+                // generated message, without invoking the editor.
+                (String::new(), true, false, Some(contents))
+            } else {
+                let message = contents.trim().to_string();
+                (message, false, false, None)
+            }
         }
     };
 
@@ -365,11 +1232,15 @@ fn prepare_messages(
             return Ok(PrepareMessagesResult::EmptyMessage);
         }
 
-        let messages = commits
+        let messages: HashMap<NonZeroOid, String> = commits
             .iter()
             .map(|commit| (commit.get_oid(), message.clone()))
             .collect();
 
+        let rejections = check_messages(repo, commits, &messages)?;
+        if !rejections.is_empty() {
+            return Ok(PrepareMessagesResult::RejectedMessages { rejections });
+        }
         return Ok(PrepareMessagesResult::Succeeded { messages });
     };
 
@@ -380,27 +1251,33 @@ fn prepare_messages(
         format!("{}\n\n", possible_template_message)
     };
     let possible_template_message = possible_template_message.as_str();
+
+    let mut original_messages = Vec::new();
+    for commit in commits.iter() {
+        let (original_message, _is_lossy) = commit.get_message_decoded(commit_encoding.as_deref());
+        let original_message = original_message.trim().to_string();
+        let context = commit_context.render(effects, repo, commit)?;
+        original_messages.push((commit.get_short_oid()?, original_message, context));
+    }
+    let comment_char = comment_string.resolve(
+        &std::iter::once(possible_template_message)
+            .chain(
+                original_messages
+                    .iter()
+                    .map(|(_oid, original_message, _context)| original_message.as_str()),
+            )
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )?;
+    let comment_char = comment_char.as_str();
+
     let discarded_message_header = format!("{} Original message:\n{} ", comment_char, comment_char);
     let discarded_message_header = discarded_message_header.as_str();
     let discarded_message_padding = format!("\n{} ", comment_char);
     let discarded_message_padding = discarded_message_padding.as_str();
 
     let mut message = String::new();
-    for commit in commits.iter() {
-        let oid = commit.get_short_oid()?;
-
-        let original_message = commit
-            .get_message_raw()?
-            .to_str()
-            .with_context(|| {
-                eyre::eyre!(
-                    "Could not decode commit message for commit: {:?}",
-                    commit.get_oid()
-                )
-            })?
-            .trim()
-            .to_string();
-
+    for (oid, original_message, context) in original_messages {
         let msg = if discard_messages {
             [
                 possible_template_message,
@@ -416,10 +1293,29 @@ fn prepare_messages(
             original_message
         };
 
+        let context: String = context
+            .iter()
+            .map(|line| {
+                if line.is_empty() {
+                    format!("{}\n", comment_char)
+                } else {
+                    format!("{} {}\n", comment_char, line)
+                }
+            })
+            .collect();
+        let context = if context.is_empty() {
+            context
+        } else {
+            format!("\n{}", context)
+        };
+
         let msg = if commits.len() == 1 {
-            format!("{}\n\n", msg)
+            format!("{}\n{}\n", msg, context)
         } else {
-            format!("++ reword {}\n{}\n\n", oid, msg)
+            format!(
+                "{} reword {}\n{}\n{}\n",
+                directive_prefix, oid, msg, context
+            )
         };
         message.push_str(msg.as_str());
     }
@@ -444,140 +1340,710 @@ fn prepare_messages(
         )
         .as_str(),
     );
+    if commits.len() > 1 {
+        message.push_str(
+            format!(
+                "\n{} To keep a commit's existing message, change its '{} reword' line to '{} skip'.",
+                comment_char, directive_prefix, directive_prefix
+            )
+            .as_str(),
+        );
+    }
+
+    edit_and_parse_messages(
+        effects,
+        repo,
+        commits,
+        comment_char,
+        &directive_prefix,
+        message,
+        provided_message,
+        edit_message_fn,
+        false,
+    )
+}
+
+/// The result of checking an edited message in [`edit_message_until_accepted`].
+pub(crate) enum MessageCheck<T> {
+    /// Stop editing and return the given result.
+    Done(T),
+
+    /// Re-open the editor with the given comment lines above the edited
+    /// message, so that the user can fix the problems without losing their
+    /// edits. If the user then doesn't change the message, `on_unchanged` is
+    /// returned, or the message is checked again if there is none.
+    EditAgain {
+        comment: String,
+        on_unchanged: Option<T>,
+    },
+}
 
-    let edited_message = edit_message_fn(&message)?;
-    if edited_message == message {
-        return Ok(PrepareMessagesResult::IdenticalMessage);
+/// Open the editor with the given message and check the edited message with
+/// `check_message_fn`, re-opening the editor for as long as the check asks for
+/// it. The comment added above the message when re-opening the editor is
+/// removed again before the edited message is checked.
+///
+/// If the editor is first closed without changing the message, then
+/// `on_unchanged` is returned without checking it, if provided. If the edited
+/// message is provided directly, then the editor is never opened, so a check
+/// which asks to edit the message again returns its `on_unchanged` result
+/// straight away.
+pub(crate) fn edit_message_until_accepted<T>(
+    message: String,
+    provided_message: Option<String>,
+    on_unchanged: Option<T>,
+    mut edit_message_fn: impl FnMut(&str) -> eyre::Result<String>,
+    mut check_message_fn: impl FnMut(String) -> eyre::Result<MessageCheck<T>>,
+) -> eyre::Result<T> {
+    if let Some(provided_message) = provided_message {
+        return match check_message_fn(provided_message)? {
+            MessageCheck::Done(result) => Ok(result),
+            MessageCheck::EditAgain {
+                comment: _,
+                on_unchanged: Some(result),
+            } => Ok(result),
+            MessageCheck::EditAgain {
+                comment: _,
+                on_unchanged: None,
+            } => eyre::bail!("BUG: asked to re-edit a message which wasn't edited interactively"),
+        };
     }
 
-    let message = message_prettify(edited_message.as_str(), Some(comment_char))?;
-    if message.trim().is_empty() {
-        return Ok(PrepareMessagesResult::EmptyMessage);
+    let mut on_unchanged = on_unchanged;
+    let mut editor_message = message;
+    let mut previous_comment: Option<String> = None;
+    loop {
+        let edited_message = edit_message_fn(&editor_message)?;
+        if edited_message == editor_message {
+            if let Some(result) = on_unchanged.take() {
+                return Ok(result);
+            }
+        }
+        let edited_message = match &previous_comment {
+            Some(comment) => edited_message
+                .strip_prefix(comment.as_str())
+                .map(|edited_message| edited_message.to_string())
+                .unwrap_or(edited_message),
+            None => edited_message,
+        };
+
+        match check_message_fn(edited_message.clone())? {
+            MessageCheck::Done(result) => return Ok(result),
+            MessageCheck::EditAgain {
+                comment,
+                on_unchanged: result,
+            } => {
+                editor_message = format!("{}{}", comment, edited_message);
+                previous_comment = Some(comment);
+                on_unchanged = result;
+            }
+        }
     }
+}
 
-    let parsed_messages = parse_bulk_edit_message(message, commits, comment_char)?;
+/// Open the editor with the given bulk-edit message (unless the edited message
+/// was provided directly), and parse the result into the new message for each
+/// commit.
+///
+/// If the commit message hook rejects any of the messages, the editor is
+/// re-opened with the hook's output.
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(edit_message_fn))]
+fn edit_and_parse_messages(
+    effects: &Effects,
+    repo: &Repo,
+    commits: &[Commit],
+    comment_char: &str,
+    directive_prefix: &str,
+    message: String,
+    provided_message: Option<String>,
+    edit_message_fn: impl Fn(&str) -> eyre::Result<String>,
+    is_resumed: bool,
+) -> eyre::Result<PrepareMessagesResult> {
+    let is_interactive = provided_message.is_none();
+    let lint_options = LintOptions::from_config(repo)?;
 
-    let input_oids: HashSet<NonZeroOid> = commits.iter().map(|c| c.get_oid()).collect();
-    let parsed_oids: HashSet<NonZeroOid> = parsed_messages.messages.keys().copied().collect();
+    // A resumed message may have already been fixed outside of the editor, so
+    // it's fine for it to be unchanged.
+    let on_unchanged = if is_resumed {
+        None
+    } else {
+        Some(PrepareMessagesResult::IdenticalMessage)
+    };
+    edit_message_until_accepted(
+        message,
+        provided_message,
+        on_unchanged,
+        edit_message_fn,
+        |edited_message| {
+            let message = message_prettify(edited_message.as_str(), Some(comment_char))?;
+            if message.trim().is_empty() {
+                return Ok(MessageCheck::Done(PrepareMessagesResult::EmptyMessage));
+            }
 
-    if input_oids != parsed_oids
-        || !parsed_messages.duplicates.is_empty()
-        || !parsed_messages.unexpected.is_empty()
-    {
-        let commits: HashMap<NonZeroOid, &Commit> = commits
-            .iter()
-            .map(|commit| (commit.get_oid(), commit))
-            .collect();
+            let commit_oids = commits.iter().map(|c| c.get_oid()).collect_vec();
+            let parsed_messages =
+                parse_bulk_edit_message(&message, &commit_oids, comment_char, directive_prefix)?;
+
+            let input_oids: HashSet<NonZeroOid> = commit_oids.into_iter().collect();
+            let parsed_oids: HashSet<NonZeroOid> = parsed_messages
+                .messages
+                .keys()
+                .chain(parsed_messages.skipped.iter())
+                .copied()
+                .collect();
+
+            if input_oids != parsed_oids
+                || !parsed_messages.duplicates.is_empty()
+                || !parsed_messages.unexpected.is_empty()
+            {
+                save_message(repo, comment_char, commits, &edited_message)?;
+
+                let commits: HashMap<NonZeroOid, &Commit> = commits
+                    .iter()
+                    .map(|commit| (commit.get_oid(), commit))
+                    .collect();
+
+                let mut missing = Vec::new();
+                for oid in input_oids.difference(&parsed_oids) {
+                    let short_oid = match commits.get(oid) {
+                        Some(commit) => commit.get_short_oid()?,
+                        None => eyre::bail!(
+                            "BUG: failed to retrieve known-good parsed OID from list of known-good input OIDs."
+                        ),
+                    };
+                    missing.push(short_oid);
+                }
 
-        let mut missing = Vec::new();
-        for oid in input_oids.difference(&parsed_oids) {
-            let short_oid = match commits.get(oid) {
-                Some(commit) => commit.get_short_oid()?,
-                None => eyre::bail!(
-                    "BUG: failed to retrieve known-good parsed OID from list of known-good input OIDs."
-                ),
+                return Ok(MessageCheck::Done(
+                    PrepareMessagesResult::MismatchedCommits {
+                        duplicates: parsed_messages.duplicates,
+                        missing,
+                        unexpected: parsed_messages.unexpected,
+                    },
+                ));
+            }
+
+            let rejections = check_messages(repo, commits, &parsed_messages.messages)?;
+            if !rejections.is_empty() {
+                // Show the hook's output above the message. If the user
+                // doesn't change the message after all, then give up.
+                let comment: String = rejections
+                    .iter()
+                    .map(|(short_oid, output)| {
+                        render_rejection_comment(comment_char, short_oid, output)
+                    })
+                    .chain(std::iter::once(format!("{}\n", comment_char)))
+                    .collect();
+                return Ok(MessageCheck::EditAgain {
+                    comment,
+                    on_unchanged: Some(PrepareMessagesResult::RejectedMessages { rejections }),
+                });
+            }
+
+            let warnings = if is_interactive {
+                lint_options.lint_messages(
+                    repo,
+                    commits,
+                    &edited_message,
+                    &parsed_messages.messages,
+                    comment_char,
+                    directive_prefix,
+                )?
+            } else {
+                Vec::new()
             };
-            missing.push(short_oid);
+            if warnings.is_empty() {
+                return Ok(MessageCheck::Done(PrepareMessagesResult::Succeeded {
+                    messages: parsed_messages.messages,
+                }));
+            }
+
+            for (short_oid, warnings) in &warnings {
+                writeln!(effects.get_error_stream(), "Warnings for {}:", short_oid)?;
+                for warning in warnings {
+                    writeln!(effects.get_error_stream(), "  {}", warning)?;
+                }
+            }
+            match prompt_lint_choice(effects)? {
+                LintChoice::Proceed => Ok(MessageCheck::Done(PrepareMessagesResult::Succeeded {
+                    messages: parsed_messages.messages,
+                })),
+                LintChoice::Abort => {
+                    save_message(repo, comment_char, commits, &edited_message)?;
+                    Ok(MessageCheck::Done(PrepareMessagesResult::Aborted))
+                }
+                LintChoice::EditAgain => {
+                    // Show the warnings above the message. The user may decide
+                    // not to change it after all, in which case it's checked
+                    // again.
+                    let comment: String = warnings
+                        .iter()
+                        .map(|(short_oid, warnings)| {
+                            render_lint_comment(comment_char, short_oid, warnings)
+                        })
+                        .chain(std::iter::once(format!("{}\n", comment_char)))
+                        .collect();
+                    Ok(MessageCheck::EditAgain {
+                        comment,
+                        on_unchanged: None,
+                    })
+                }
+            }
+        },
+    )
+}
+
+/// Validate the new messages with the commit message hook, returning the short
+/// OID of each commit whose message was rejected, and the hook's output.
+fn check_messages(
+    repo: &Repo,
+    commits: &[Commit],
+    messages: &HashMap<NonZeroOid, String>,
+) -> eyre::Result<Vec<(String, String)>> {
+    let mut results: HashMap<&str, Option<String>> = HashMap::new();
+    let mut rejections = Vec::new();
+    for commit in commits {
+        let message = match messages.get(&commit.get_oid()) {
+            Some(message) => message.as_str(),
+            None => continue,
+        };
+        let result = match results.get(message) {
+            Some(result) => result.clone(),
+            None => {
+                let result = run_commit_msg_hook(repo, message)?;
+                results.insert(message, result.clone());
+                result
+            }
+        };
+        if let Some(output) = result {
+            rejections.push((commit.get_short_oid()?, output));
         }
+    }
+    Ok(rejections)
+}
 
-        let mut w = File::create(repo.get_path().join("REWORD_EDITMSG"))
-            .context("Creating REWORD_EDITMSG file")?;
-        writeln!(
-            &mut w,
-            "{} This file was created by `git branchless reword` at {}\n\
-        {} You can use it to recover any edits you had made to the included commit {}.\n\
-        {} If you don't need (or don't recognize) these edits, it is safe to delete this file.\n\
-        \n\
-        {}
-        ",
-            comment_char,
-            Local::now().to_rfc2822(),
-            comment_char,
-            if commits.len() == 1 {
-                "message"
-            } else {
-                "messages"
-            },
-            comment_char,
-            edited_message
+/// The checks to run on messages edited with `git reword`, configured with
+/// `branchless.reword.lint`, `branchless.reword.maxSubjectLength` and
+/// `branchless.reword.lintCommand`.
+#[derive(Clone, Debug)]
+struct LintOptions {
+    /// Whether to check for style problems with [`lint_message`].
+    check_style: bool,
+
+    /// The maximum length of the subject line.
+    max_subject_length: usize,
+
+    /// The command to run on each message, such as a spell checker.
+    command: Option<String>,
+}
+
+impl LintOptions {
+    fn from_config(repo: &Repo) -> eyre::Result<Self> {
+        Ok(Self {
+            check_style: get_reword_lint(repo)?,
+            max_subject_length: get_reword_max_subject_length(repo)?,
+            command: get_reword_lint_command(repo)?,
+        })
+    }
+
+    /// Check the edited messages, returning the short OID of each commit whose
+    /// message has problems, and a description of each problem. The style
+    /// checks use the sections of the message as the user wrote them, since
+    /// trailing whitespace has already been removed from the parsed messages.
+    fn lint_messages(
+        &self,
+        repo: &Repo,
+        commits: &[Commit],
+        edited_message: &str,
+        messages: &HashMap<NonZeroOid, String>,
+        comment_char: &str,
+        directive_prefix: &str,
+    ) -> eyre::Result<Vec<(String, Vec<String>)>> {
+        if !self.check_style && self.command.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let commit_oids = commits.iter().map(|commit| commit.get_oid()).collect_vec();
+        let mut raw_messages: HashMap<NonZeroOid, String> = HashMap::new();
+        if self.check_style {
+            for (directive, hash, msg) in
+                split_bulk_edit_message(edited_message, &commit_oids, directive_prefix)
+            {
+                if directive == Some(BulkEditDirective::Reword) {
+                    if let Some(oid) = resolve_section_hash(&hash, &commit_oids) {
+                        raw_messages.insert(oid, msg);
+                    }
+                }
+            }
+        }
+
+        let mut result = Vec::new();
+        for commit in commits {
+            let mut warnings = Vec::new();
+            if let Some(raw_message) = raw_messages.get(&commit.get_oid()) {
+                warnings.extend(lint_message(
+                    raw_message,
+                    comment_char,
+                    self.max_subject_length,
+                ));
+            }
+            if let (Some(command), Some(message)) = (&self.command, messages.get(&commit.get_oid()))
+            {
+                warnings.extend(run_lint_command(repo, command, message)?);
+            }
+            if !warnings.is_empty() {
+                result.push((commit.get_short_oid()?, warnings));
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Check a commit message for common style problems, returning a description
+/// of each one: a subject line longer than `max_subject_length`, a missing
+/// body, and trailing whitespace. Lines starting with `comment_char` are
+/// ignored.
+fn lint_message(message: &str, comment_char: &str, max_subject_length: usize) -> Vec<String> {
+    let lines = message
+        .lines()
+        .filter(|line| comment_char.is_empty() || !line.starts_with(comment_char))
+        .skip_while(|line| line.trim().is_empty())
+        .collect_vec();
+
+    let mut warnings = Vec::new();
+    if let Some(subject) = lines.first() {
+        let subject_length = subject.trim_end().chars().count();
+        if subject_length > max_subject_length {
+            warnings.push(format!(
+                "The subject line is {} characters long, which is more than {}.",
+                subject_length, max_subject_length
+            ));
+        }
+        if lines[1..].iter().all(|line| line.trim().is_empty()) {
+            warnings.push("The message has no body.".to_string());
+        }
+    }
+    for (i, line) in lines.iter().enumerate() {
+        if line.ends_with(char::is_whitespace) {
+            warnings.push(format!("Trailing whitespace on line {}: {:?}", i + 1, line));
+        }
+    }
+    warnings
+}
+
+/// Run the `branchless.reword.lintCommand` on the message, returning each line
+/// of its output as a warning. As with the commit message hook, the path to a
+/// file containing the message is passed to the command as its first argument.
+fn run_lint_command(repo: &Repo, command: &str, message: &str) -> eyre::Result<Vec<String>> {
+    let mut lint_command = shell_command(command)?;
+    let message_path = repo.get_path().join("BRANCHLESS_LINT_MSG");
+    std::fs::write(&message_path, message)
+        .wrap_err_with(|| format!("Writing commit message to: {:?}", message_path))?;
+    let output = lint_command
+        .current_dir(
+            repo.get_working_copy_path()
+                .unwrap_or_else(|| repo.get_path()),
+        )
+        .arg(&message_path)
+        .stdin(Stdio::null())
+        .output()
+        .wrap_err_with(|| format!("Invoking lint command: {}", command));
+    std::fs::remove_file(&message_path).ok();
+    let output = output?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut warnings: Vec<String> = stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect();
+    if warnings.is_empty() && !output.status.success() {
+        warnings.push(format!(
+            "`{}` exited with status {:?}",
+            command,
+            output.status.code()
+        ));
+    }
+    Ok(warnings)
+}
+
+/// What to do after being warned about problems with the edited messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LintChoice {
+    /// Re-open the editor to fix the problems.
+    EditAgain,
+
+    /// Reword the commits anyway.
+    Proceed,
+
+    /// Don't reword any commits.
+    Abort,
+}
+
+/// Ask the user what to do about the warnings for the edited messages. If
+/// stdin is closed, the reword is aborted.
+fn prompt_lint_choice(effects: &Effects) -> eyre::Result<LintChoice> {
+    loop {
+        write!(
+            effects.get_output_stream(),
+            "Edit the message again, proceed anyway, or abort? [e/p/a]: "
         )?;
+        stdout().flush()?;
+        let mut input = String::new();
+        if stdin().read_line(&mut input)? == 0 {
+            writeln!(effects.get_output_stream())?;
+            return Ok(LintChoice::Abort);
+        }
+        match input.trim().to_lowercase().as_str() {
+            "e" | "edit" => return Ok(LintChoice::EditAgain),
+            "p" | "proceed" => return Ok(LintChoice::Proceed),
+            "a" | "abort" => return Ok(LintChoice::Abort),
+            _ => writeln!(effects.get_output_stream(), "Please answer e, p or a.")?,
+        }
+    }
+}
+
+/// Additional information about each commit to show as comments when editing
+/// its message, configured with `branchless.reword.context`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CommitContext {
+    /// Don't show any additional information.
+    None,
+
+    /// Show the diff stat of the commit, as with `git show --stat`.
+    Stat,
 
-        return Ok(PrepareMessagesResult::MismatchedCommits {
-            duplicates: parsed_messages.duplicates,
-            missing,
-            unexpected: parsed_messages.unexpected,
-        });
+    /// Show the paths of the files changed by the commit.
+    Files,
+}
+
+impl CommitContext {
+    fn from_config(repo: &Repo) -> eyre::Result<Self> {
+        match get_reword_context(repo)?.as_str() {
+            "none" => Ok(Self::None),
+            "stat" => Ok(Self::Stat),
+            "files" => Ok(Self::Files),
+            other => eyre::bail!(
+                "Invalid value for branchless.reword.context: {:?} (expected one of: none, stat, files)",
+                other
+            ),
+        }
     }
 
-    Ok(PrepareMessagesResult::Succeeded {
-        messages: parsed_messages.messages,
-    })
+    /// Render the context lines for the given commit, without the comment
+    /// character.
+    fn render(self, effects: &Effects, repo: &Repo, commit: &Commit) -> eyre::Result<Vec<String>> {
+        let lines = match self {
+            Self::None => Vec::new(),
+            Self::Stat => match repo.get_patch_for_commit(effects, commit)? {
+                Some(diff) => diff
+                    .get_stat(72)?
+                    .lines()
+                    .map(|line| line.trim_start().to_string())
+                    .collect(),
+                None => Vec::new(),
+            },
+            Self::Files => match repo.get_paths_touched_by_commit(commit)? {
+                Some(paths) => std::iter::once("Changed files:".to_string())
+                    .chain(
+                        paths
+                            .into_iter()
+                            .sorted()
+                            .map(|path| format!("  {}", path.display())),
+                    )
+                    .collect(),
+                None => Vec::new(),
+            },
+        };
+        Ok(lines)
+    }
 }
 
+/// The result of parsing a bulk-edit message with [`parse_bulk_edit_message`].
 #[must_use]
 #[derive(Debug)]
-struct ParseMessageResult {
+pub struct ParseMessageResult {
     /// Commit hashes that were found multiple times while parsing the edited messages.
-    duplicates: Vec<String>,
+    pub duplicates: Vec<String>,
 
     /// The parsed, formatted messages for rewording.
-    messages: HashMap<NonZeroOid, String>,
+    pub messages: HashMap<NonZeroOid, String>,
+
+    /// Commits that the user chose not to reword with a `++ skip` directive.
+    pub skipped: HashSet<NonZeroOid>,
 
     /// Commit hashes that were found while parsing the edited messages, but which were not
-    /// specified on the command line.
-    unexpected: Vec<String>,
+    /// specified on the command line, or which were ambiguous.
+    pub unexpected: Vec<String>,
 }
 
-#[instrument]
-fn parse_bulk_edit_message(
-    message: String,
-    commits: &[Commit],
-    comment_char: char,
-) -> eyre::Result<ParseMessageResult> {
-    let mut commits_oids = HashMap::new();
-    for commit in commits.iter() {
-        commits_oids.insert(commit.get_short_oid()?, commit.get_oid());
-    }
+/// An error encountered while parsing a bulk-edit message.
+#[allow(missing_docs)]
+#[derive(Debug, Error)]
+pub enum BulkEditParseError {
+    #[error("the message for commit {commit_oid} contains a NUL byte")]
+    NulByte { commit_oid: NonZeroOid },
+
+    #[error("could not format the message for commit {commit_oid}: {message}")]
+    Prettify {
+        commit_oid: NonZeroOid,
+        message: String,
+    },
+}
 
-    let message = match commits {
-        // For single commits, add the marker line, but only if the user hasn't already done so.
-        [only_commit] if !message.contains("++ reword") => {
-            format!("++ reword {}\n{}", only_commit.get_short_oid()?, message)
+/// A directive in the bulk-edit message, which starts a section for a commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BulkEditDirective {
+    /// `++ reword <hash>`: the section contains the new message for the commit.
+    Reword,
+
+    /// `++ skip <hash>`: the commit should keep its existing message, and the
+    /// contents of the section are ignored.
+    Skip,
+}
+
+impl BulkEditDirective {
+    fn parse<'a>(directive_prefix: &str, line: &'a str) -> Option<(Self, &'a str)> {
+        let line = line.trim_end().strip_prefix(directive_prefix)?;
+        if let Some(hash) = line.strip_prefix(" reword ") {
+            Some((Self::Reword, hash.trim()))
+        } else {
+            line.strip_prefix(" skip ")
+                .map(|hash| (Self::Skip, hash.trim()))
         }
-        _ => message,
-    };
+    }
+}
 
-    // split the bulk message into (hash, msg) tuples
-    let msgs = message
-        .split("++ reword")
-        .filter_map(|msg| msg.split_once('\n'))
-        .map(|(hash, msg)| (hash.trim(), msg));
+/// Parse the message which the user edited to reword the given commits. Each
+/// commit's section starts with a directive line like `++ reword <hash>`,
+/// where `++` is the `directive_prefix`. If only one commit is being reworded,
+/// then the directive line may be omitted.
+///
+/// Sections which refer to unknown, ambiguous or duplicate commits are
+/// reported in the result rather than as errors, so that the user can be
+/// asked to fix them.
+#[instrument]
+pub fn parse_bulk_edit_message(
+    message: &str,
+    commit_oids: &[NonZeroOid],
+    comment_char: &str,
+    directive_prefix: &str,
+) -> Result<ParseMessageResult, BulkEditParseError> {
+    let sections = split_bulk_edit_message(message, commit_oids, directive_prefix);
 
     let mut duplicates = Vec::new();
     let mut messages = HashMap::new();
+    let mut skipped = HashSet::new();
     let mut unexpected = Vec::new();
-    for (hash, msg) in msgs {
-        let oid = match commits_oids.get(hash) {
-            Some(commit) => *commit,
+    for (directive, hash, msg) in sections {
+        let directive = match directive {
+            Some(directive) => directive,
             None => {
                 unexpected.push(hash.to_string());
                 continue;
             }
         };
-        if messages.contains_key(&oid) {
-            duplicates.push(hash.to_string());
+
+        let oid = match resolve_section_hash(&hash, commit_oids) {
+            Some(oid) => oid,
+            None => {
+                unexpected.push(hash);
+                continue;
+            }
+        };
+        if messages.contains_key(&oid) || skipped.contains(&oid) {
+            duplicates.push(hash);
             continue;
         }
-        messages.insert(oid, message_prettify(msg, Some(comment_char))?);
+        match directive {
+            BulkEditDirective::Reword => {
+                if msg.contains('\0') {
+                    return Err(BulkEditParseError::NulByte { commit_oid: oid });
+                }
+                let msg = message_prettify(&msg, Some(comment_char)).map_err(|err| {
+                    BulkEditParseError::Prettify {
+                        commit_oid: oid,
+                        message: err.to_string(),
+                    }
+                })?;
+                messages.insert(oid, msg);
+            }
+            BulkEditDirective::Skip => {
+                skipped.insert(oid);
+            }
+        }
     }
 
     Ok(ParseMessageResult {
         duplicates,
         messages,
+        skipped,
         unexpected,
     })
 }
 
+/// Split the bulk-edit message into sections, each starting with a directive
+/// line, and return the directive, hash and raw contents of each section. Any
+/// text before the first directive is returned as a section without a
+/// directive. See [`parse_bulk_edit_message`].
+fn split_bulk_edit_message(
+    message: &str,
+    commit_oids: &[NonZeroOid],
+    directive_prefix: &str,
+) -> Vec<(Option<BulkEditDirective>, String, String)> {
+    let has_directive = message
+        .lines()
+        .any(|line| BulkEditDirective::parse(directive_prefix, line).is_some());
+    let message = match commit_oids {
+        // For single commits, add the marker line, but only if the user hasn't already done so.
+        [only_commit_oid] if !has_directive => {
+            format!(
+                "{} reword {}\n{}",
+                directive_prefix, only_commit_oid, message
+            )
+        }
+        _ => message.to_string(),
+    };
+
+    let mut sections: Vec<(Option<BulkEditDirective>, String, String)> = Vec::new();
+    for line in message.split_inclusive('\n') {
+        match BulkEditDirective::parse(directive_prefix, line) {
+            Some((directive, hash)) => {
+                sections.push((Some(directive), hash.to_string(), String::new()))
+            }
+            None => match sections.last_mut() {
+                Some((_directive, _hash, msg)) => msg.push_str(line),
+                None if line.trim().is_empty() => {}
+                None => sections.push((None, line.trim().to_string(), String::new())),
+            },
+        }
+    }
+    sections
+}
+
+/// Find the commit referred to by the hash in a bulk-edit directive. Any
+/// unambiguous prefix of the commit's OID is accepted, including the full OID,
+/// rather than only the abbreviation which we emitted.
+fn resolve_section_hash(hash: &str, commit_oids: &[NonZeroOid]) -> Option<NonZeroOid> {
+    let hash_lower = hash.to_ascii_lowercase();
+    if hash_lower.is_empty() {
+        return None;
+    }
+    let matching_oids: Vec<NonZeroOid> = commit_oids
+        .iter()
+        .copied()
+        .filter(|oid| oid.to_string().starts_with(&hash_lower))
+        .collect();
+    match matching_oids.as_slice() {
+        [oid] => Some(*oid),
+        _ => None,
+    }
+}
+
 /// Return the root commits for given a list of commits. This is the list of commits that have *no*
 /// ancestors also in the list. The idea is to find the minimum number of subtrees that much be
 /// rebased to include all of our rewording.
@@ -605,6 +2071,55 @@ fn find_subtree_roots<'repo>(
     Ok(root_commits)
 }
 
+/// The commits reworded in a stack, along with either the rewritten commits or
+/// the exit code of the failed rebase.
+type StackResult<'repo> = (
+    Vec<Commit<'repo>>,
+    Result<HashMap<NonZeroOid, MaybeZeroOid>, ExitCode>,
+);
+
+/// Group the given commits by the stack that they belong to, i.e. by the
+/// connected component of the commits that would be rewritten. Rewording the
+/// commits in one stack doesn't affect the commits in any other stack. The
+/// stacks are returned in the order that their commits first appear in
+/// `commits`.
+#[instrument]
+fn group_commits_by_stack<'repo>(
+    dag: &Dag,
+    commits: &[Commit<'repo>],
+) -> eyre::Result<Vec<Vec<Commit<'repo>>>> {
+    let commit_set: CommitSet = commits.iter().map(|commit| commit.get_oid()).collect();
+    let commits_to_rewrite = dag
+        .query()
+        .descendants(commit_set.clone())?
+        .difference(&dag.obsolete_commits)
+        .union(&commit_set);
+    let components = dag.get_connected_components(&commits_to_rewrite)?;
+
+    let mut stacks: Vec<(usize, Vec<Commit>)> = Vec::new();
+    for commit in commits {
+        let component_index = components
+            .iter()
+            .position(|component| {
+                component
+                    .contains(&commit.get_oid().into())
+                    .unwrap_or(false)
+            })
+            .expect("Every commit should belong to a connected component");
+        match stacks
+            .iter_mut()
+            .find(|(index, _commits)| *index == component_index)
+        {
+            Some((_index, stack_commits)) => stack_commits.push(commit.clone()),
+            None => stacks.push((component_index, vec![commit.clone()])),
+        }
+    }
+    Ok(stacks
+        .into_iter()
+        .map(|(_index, stack_commits)| stack_commits)
+        .collect())
+}
+
 /// Print a basic status report of what commits were reworded.
 #[instrument]
 fn render_status_report(
@@ -664,23 +2179,95 @@ fn render_status_report(
     Ok(())
 }
 
+/// Print which stacks were reworded, when rewording commits in more than one
+/// stack.
+#[instrument]
+fn render_stack_report(
+    effects: &Effects,
+    stack_results: &[StackResult],
+    dry_run: bool,
+) -> eyre::Result<()> {
+    let glyphs = effects.get_glyphs();
+    let verb = if dry_run { "Would reword" } else { "Reworded" };
+    let num_succeeded = stack_results
+        .iter()
+        .filter(|(_stack_commits, result)| result.is_ok())
+        .count();
+    writeln!(
+        effects.get_output_stream(),
+        "{} {} of {} stacks:",
+        verb,
+        num_succeeded,
+        stack_results.len()
+    )?;
+    for (stack_commits, result) in stack_results {
+        let num_commits = Pluralize {
+            determiner: None,
+            amount: stack_commits.len(),
+            unit: ("commit", "commits"),
+        };
+        let description = match stack_commits.first() {
+            Some(commit) => printable_styled_string(glyphs, commit.friendly_describe(glyphs)?)?,
+            None => continue,
+        };
+        match result {
+            Ok(_rewritten_oids) => writeln!(
+                effects.get_output_stream(),
+                "  {} {} in stack at {}",
+                verb,
+                num_commits,
+                description
+            )?,
+            Err(exit_code) => writeln!(
+                effects.get_output_stream(),
+                "  Failed to reword {} in stack at {} (exit code {})",
+                num_commits,
+                description,
+                exit_code.0
+            )?,
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use lib::testing::make_git;
     use std::collections::BTreeMap;
 
+    #[test]
+    fn test_edit_message_in_terminal() -> eyre::Result<()> {
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let edit = |input: &str| -> eyre::Result<String> {
+            edit_message_in_terminal(&effects, &mut input.as_bytes(), "old subject\n\nold body")
+        };
+
+        assert_eq!(
+            edit("new subject\n\nnew body\n.\n")?,
+            "new subject\n\nnew body"
+        );
+        assert_eq!(edit("new subject\n.\nignored\n")?, "new subject");
+        assert_eq!(edit("new subject\r\n")?, "new subject");
+        assert_eq!(edit(".\n")?, "old subject\n\nold body");
+        assert!(edit("").is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_reword_uses_commit_template() -> eyre::Result<()> {
         let git = make_git()?;
         git.init_repo()?;
         let repo = git.get_repo()?;
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
 
         let head_oid = git.commit_file("test1", 1)?;
         let head_commit = repo.find_commit_or_fail(head_oid)?;
 
         {
             let result = prepare_messages(
+                &effects,
                 &repo,
                 InitialCommitMessages::Discard,
                 &[head_commit.clone()],
@@ -709,6 +2296,7 @@ This is a template!
 
         {
             let result = prepare_messages(
+                &effects,
                 &repo,
                 InitialCommitMessages::Discard,
                 &[head_commit],
@@ -731,11 +2319,163 @@ This is a template!
         Ok(())
     }
 
+    #[test]
+    fn test_reword_comment_string() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let repo = git.get_repo()?;
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+
+        git.write_file("test1", "contents\n")?;
+        git.run(&["add", "."])?;
+        git.run(&["commit", "-m", "create test1.txt\n\n# not a comment"])?;
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+        let head_commit = repo.find_commit_or_fail(head_oid)?;
+
+        git.run(&["config", "core.commentChar", "auto"])?;
+        {
+            let result = prepare_messages(
+                &effects,
+                &repo,
+                InitialCommitMessages::Messages([].to_vec()),
+                std::slice::from_ref(&head_commit),
+                |message| {
+                    insta::assert_snapshot!(message.trim(), @r###"
+                    create test1.txt
+
+                    # not a comment
+
+                    ; Rewording: Please enter the commit message to apply to this 1 commit. Lines
+                    ; starting with ';' will be ignored, and an empty message aborts rewording.
+                    "###);
+                    Ok(format!("{}\n; added comment\n", message))
+                },
+            )?;
+            insta::assert_debug_snapshot!(result, @r###"
+            Succeeded {
+                messages: {
+                    NonZeroOid(e6eae944446acf7e774e4088d618880f285aebd5): "create test1.txt\n\n# not a comment\n",
+                },
+            }
+            "###);
+        }
+
+        git.run(&["config", "core.commentString", "//"])?;
+        {
+            let result = prepare_messages(
+                &effects,
+                &repo,
+                InitialCommitMessages::Messages([].to_vec()),
+                &[head_commit],
+                |message| {
+                    insta::assert_snapshot!(message.trim(), @r###"
+                    create test1.txt
+
+                    # not a comment
+
+                    // Rewording: Please enter the commit message to apply to this 1 commit. Lines
+                    // starting with '//' will be ignored, and an empty message aborts rewording.
+                    "###);
+                    Ok(format!("{}\n// added comment\n", message))
+                },
+            )?;
+            insta::assert_debug_snapshot!(result, @r###"
+            Succeeded {
+                messages: {
+                    NonZeroOid(e6eae944446acf7e774e4088d618880f285aebd5): "create test1.txt\n\n# not a comment\n",
+                },
+            }
+            "###);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reword_directive_prefix_and_context() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let repo = git.get_repo()?;
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test1_commit = repo.find_commit_or_fail(test1_oid)?;
+        let test2_commit = repo.find_commit_or_fail(test2_oid)?;
+
+        git.run(&["config", "branchless.reword.directivePrefix", "%%"])?;
+        git.run(&["config", "branchless.reword.context", "stat"])?;
+        {
+            let result = prepare_messages(
+                &effects,
+                &repo,
+                InitialCommitMessages::Messages([].to_vec()),
+                &[test1_commit.clone(), test2_commit.clone()],
+                |message| {
+                    insta::assert_snapshot!(message.trim(), @r###"
+                    %% reword 62fc20d
+                    create test1.txt
+
+                    # test1.txt | 1 +
+                    # 1 file changed, 1 insertion(+)
+
+                    %% reword 96d1c37
+                    create test2.txt
+
+                    # test2.txt | 1 +
+                    # 1 file changed, 1 insertion(+)
+
+                    # Rewording: Please enter the commit messages to apply to these 2 commits. Lines
+                    # starting with '#' will be ignored, and an empty message aborts rewording.
+                    # To keep a commit's existing message, change its '%% reword' line to '%% skip'.
+                    "###);
+                    Ok(message.replace("create test2.txt", "reword test2.txt"))
+                },
+            )?;
+            let messages: BTreeMap<_, _> = match result {
+                PrepareMessagesResult::Succeeded { messages } => messages.into_iter().collect(),
+                result => panic!("Unexpected result: {:?}", result),
+            };
+            insta::assert_debug_snapshot!(messages, @r###"
+            {
+                NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e): "create test1.txt\n",
+                NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f): "reword test2.txt\n",
+            }
+            "###);
+        }
+
+        git.run(&["config", "branchless.reword.context", "files"])?;
+        {
+            let result = prepare_messages(
+                &effects,
+                &repo,
+                InitialCommitMessages::Messages([].to_vec()),
+                std::slice::from_ref(&test1_commit),
+                |message| {
+                    insta::assert_snapshot!(message.trim(), @r###"
+                    create test1.txt
+
+                    # Changed files:
+                    #   test1.txt
+
+                    # Rewording: Please enter the commit message to apply to this 1 commit. Lines
+                    # starting with '#' will be ignored, and an empty message aborts rewording.
+                    "###);
+                    Ok(message.to_string())
+                },
+            )?;
+            insta::assert_debug_snapshot!(result, @"IdenticalMessage");
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_reword_builds_multi_commit_messages() -> eyre::Result<()> {
         let git = make_git()?;
         git.init_repo()?;
         let repo = git.get_repo()?;
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
 
         let test1_oid = git.commit_file("test1", 1)?;
         let test2_oid = git.commit_file("test2", 2)?;
@@ -744,6 +2484,7 @@ This is a template!
 
         {
             let result = prepare_messages(
+                &effects,
                 &repo,
                 InitialCommitMessages::Messages([].to_vec()),
                 &[test1_commit.clone(), test2_commit.clone()],
@@ -757,6 +2498,7 @@ This is a template!
 
                     # Rewording: Please enter the commit messages to apply to these 2 commits. Lines
                     # starting with '#' will be ignored, and an empty message aborts rewording.
+                    # To keep a commit's existing message, change its '++ reword' line to '++ skip'.
                     "###);
                     Ok(message.to_string())
                 },
@@ -771,24 +2513,22 @@ This is a template!
     fn test_reword_parses_bulk_edit_message() -> eyre::Result<()> {
         let git = make_git()?;
         git.init_repo()?;
-        let repo = git.get_repo()?;
 
         let test1_oid = git.commit_file("test1", 1)?;
         let test2_oid = git.commit_file("test2", 2)?;
-        let test1_commit = repo.find_commit_or_fail(test1_oid)?;
-        let test2_commit = repo.find_commit_or_fail(test2_oid)?;
 
         {
             let mut result = parse_bulk_edit_message(
-                String::from(
+                &String::from(
                     "++ reword 62fc20d\n\
                 create test1.txt\n\
                 \n\
                 ++ reword 96d1c37\n\
                 create test2.txt\n",
                 ),
-                &[test1_commit.clone(), test2_commit.clone()],
-                '#',
+                &[test1_oid, test2_oid],
+                "#",
+                "++",
             )?;
 
             // Convert the messages HashMap into the sorted map for testing
@@ -803,29 +2543,75 @@ This is a template!
             // clear the messages map b/c its contents have already been tested
             result.messages.clear();
             insta::assert_debug_snapshot!(result, @r###"
-                ParseMessageResult {
-                    duplicates: [],
-                    messages: {},
-                    unexpected: [],
-                }"###
+            ParseMessageResult {
+                duplicates: [],
+                messages: {},
+                skipped: {},
+                unexpected: [],
+            }
+            "###
             );
         };
 
         Ok(())
     }
 
+    #[test]
+    fn test_reword_parses_full_oids_and_skip_directives() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+
+        {
+            let result = parse_bulk_edit_message(
+                &format!(
+                    "++ skip 62fc\n\
+                    create test1.txt\n\
+                    \n\
+                    ++ reword {}\n\
+                    reword test3.txt\n\
+                    \n\
+                    ++ reword 96D1C37A\n\
+                    reword test2.txt\n",
+                    test3_oid
+                ),
+                &[test1_oid, test2_oid, test3_oid],
+                "#",
+                "++",
+            )?;
+
+            let messages: BTreeMap<_, _> = result.messages.iter().collect();
+            insta::assert_debug_snapshot!(messages, @r###"
+            {
+                NonZeroOid(70deb1e28791d8e7dd5a1f0c871a51b91282562f): "reword test3.txt\n",
+                NonZeroOid(96d1c37a3d4363611c49f7e52186e189a04c531f): "reword test2.txt\n",
+            }
+            "###);
+            insta::assert_debug_snapshot!(result.skipped, @r###"
+            {
+                NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e),
+            }
+            "###);
+            insta::assert_debug_snapshot!(result.duplicates, @"[]");
+            insta::assert_debug_snapshot!(result.unexpected, @"[]");
+        };
+
+        Ok(())
+    }
+
     #[test]
     fn test_reword_parses_unexpected_and_duplicate_commit_hashs() -> eyre::Result<()> {
         let git = make_git()?;
         git.init_repo()?;
-        let repo = git.get_repo()?;
 
         let test1_oid = git.commit_file("test1", 1)?;
-        let test1_commit = repo.find_commit_or_fail(test1_oid)?;
 
         {
             let result = parse_bulk_edit_message(
-                String::from(
+                &String::from(
                     "++ reword 62fc20d\n\
                 create test1.txt\n\
                 \n\
@@ -836,25 +2622,151 @@ This is a template!
                 this commit has been duplicated\n\
                 \n",
                 ),
-                &[test1_commit.clone()],
-                '#',
+                &[test1_oid],
+                "#",
+                "++",
             )?;
 
             insta::assert_debug_snapshot!(result, @r###"
-                ParseMessageResult {
-                    duplicates: [
-                        "62fc20d",
-                    ],
-                    messages: {
-                        NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e): "create test1.txt\n",
-                    },
-                    unexpected: [
-                        "abc123",
-                    ],
-                }"###
+            ParseMessageResult {
+                duplicates: [
+                    "62fc20d",
+                ],
+                messages: {
+                    NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e): "create test1.txt\n",
+                },
+                skipped: {},
+                unexpected: [
+                    "abc123",
+                ],
+            }
+            "###
             );
         };
 
         Ok(())
     }
+
+    #[test]
+    fn test_reword_rejects_nul_bytes() -> eyre::Result<()> {
+        let commit_oid = NonZeroOid::from_str("62fc20d2a290daea0d52bdc2ed2ad4be6491010e")?;
+        let result =
+            parse_bulk_edit_message("++ reword 62fc20d\nfoo\0bar\n", &[commit_oid], "#", "++");
+        insta::assert_debug_snapshot!(result, @r###"
+        Err(
+            NulByte {
+                commit_oid: NonZeroOid(62fc20d2a290daea0d52bdc2ed2ad4be6491010e),
+            },
+        )
+        "###);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reflow_message() {
+        let message = "\
+Subject line which is longer than the wrap column and is left as-is
+
+This paragraph is written on one long line, so it should be wrapped at the given column.
+Short lines
+are joined.
+
+- A list item which is also long enough that it should be wrapped with a hanging indent.
+  Its continuation line is joined.
+2. A numbered list item.
+
+```
+A code block which is long enough to be wrapped, but which is kept as-is anyway.
+
+Even after a blank line.
+```
+
+    An indented line which is long enough to be wrapped, but which is kept as-is.
+
+See the documentation [1].
+
+[1]: https://example.com/a/very/long/url/which/is/longer/than/the/wrap/column
+
+Signed-off-by: Jane Doe <jane@example.com>
+Co-authored-by: John Doe
+  <john@example.com>
+";
+        insta::assert_snapshot!(reflow_message(message, 40), @r###"
+        Subject line which is longer than the wrap column and is left as-is
+
+        This paragraph is written on one long
+        line, so it should be wrapped at the
+        given column. Short lines are joined.
+
+        - A list item which is also long enough
+          that it should be wrapped with a
+          hanging indent. Its continuation line
+          is joined.
+        2. A numbered list item.
+
+        ```
+        A code block which is long enough to be wrapped, but which is kept as-is anyway.
+
+        Even after a blank line.
+        ```
+
+            An indented line which is long enough to be wrapped, but which is kept as-is.
+
+        See the documentation [1].
+
+        [1]: https://example.com/a/very/long/url/which/is/longer/than/the/wrap/column
+
+        Signed-off-by: Jane Doe <jane@example.com>
+        Co-authored-by: John Doe
+          <john@example.com>
+        "###);
+
+        // Messages which are already wrapped are unchanged.
+        let message = reflow_message(message, 40);
+        assert_eq!(reflow_message(&message, 40), message);
+        assert_eq!(reflow_message("Subject only", 40), "Subject only");
+        assert_eq!(reflow_message("", 40), "");
+    }
+
+    #[test]
+    fn test_lint_message() {
+        insta::assert_debug_snapshot!(lint_message("# comment\n\nsubject\n\nbody\n", "#", 72), @"[]");
+        insta::assert_debug_snapshot!(
+            lint_message("a subject which is too long \n# body\n\n", "#", 20),
+            @r###"
+        [
+            "The subject line is 27 characters long, which is more than 20.",
+            "The message has no body.",
+            "Trailing whitespace on line 1: \"a subject which is too long \"",
+        ]
+        "###
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_reword_parse_arbitrary_bulk_edit_message(
+            lines in proptest::collection::vec(
+                r"(\+\+ (reword|skip) [0-9a-fA-F]{0,12}|#.*|.*)",
+                0..16,
+            ),
+            comment_char in ".{0,2}",
+            directive_prefix in ".{0,3}",
+        ) {
+            let commit_oids = [
+                NonZeroOid::from_str("62fc20d2a290daea0d52bdc2ed2ad4be6491010e").unwrap(),
+                NonZeroOid::from_str("96d1c37a3d4363611c49f7e52186e189a04c531f").unwrap(),
+            ];
+            let message = lines.join("\n");
+            for commit_oids in [&commit_oids[..1], &commit_oids[..]] {
+                if let Ok(result) =
+                    parse_bulk_edit_message(&message, commit_oids, &comment_char, &directive_prefix)
+                {
+                    for commit_oid in result.messages.keys().chain(result.skipped.iter()) {
+                        proptest::prop_assert!(commit_oids.contains(commit_oid));
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,84 @@
+//! Report the results of rewrite operations in a machine-readable format, so
+//! that scripts and editor integrations don't have to parse the
+//! human-readable output, and run user hooks configured for rewrite
+//! operations.
+
+use std::fmt::Write;
+
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::core::rewrite::RewriteSummary;
+use lib::core::user_hooks::{get_rewritten_oids, run_user_hooks};
+use lib::error::ErrorCategory;
+use lib::git::{GitRunInfo, Repo};
+use lib::util::ExitCode;
+use serde_json::json;
+
+use crate::opts::OutputFormat;
+
+/// Run the rewrite operation `f`, report its results in the given output
+/// format, and run any user hooks configured for the command.
+///
+/// `f` is responsible for recording any merge conflicts in the provided
+/// [`RewriteSummary`]. The rewritten commits and moved branches are determined
+/// from the events added to the event log while `f` runs, so that rewrites
+/// carried out by on-disk rebases are included.
+pub fn with_rewrite_summary(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    output: OutputFormat,
+    command_name: &str,
+    f: impl FnOnce(&Effects, &mut RewriteSummary) -> eyre::Result<ExitCode>,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let last_event_rowid = event_log_db.get_last_event_rowid()?;
+
+    let mut summary = RewriteSummary::default();
+    let (exit_code, hook_effects) = match output {
+        OutputFormat::Text => (f(effects, &mut summary)?, effects.clone()),
+        OutputFormat::Json => {
+            let effects = effects.reroute_output_to_stderr();
+            (f(&effects, &mut summary)?, effects)
+        }
+    };
+
+    let events = event_log_db.get_events_after_rowid(last_event_rowid)?;
+    let events: Vec<&Event> = events.iter().map(|(_rowid, event)| event).collect();
+    run_user_hooks(
+        &hook_effects,
+        &repo,
+        command_name,
+        &get_rewritten_oids(events.iter().copied()),
+    )?;
+
+    match output {
+        OutputFormat::Text => Ok(exit_code),
+
+        OutputFormat::Json => {
+            summary.add_events(events);
+
+            let status = if exit_code.is_success() {
+                "succeeded"
+            } else if exit_code.0 == ErrorCategory::Conflict.exit_code().0 {
+                "conflict"
+            } else if exit_code.0 == ErrorCategory::Interrupted.exit_code().0 {
+                "interrupted"
+            } else {
+                "failed"
+            };
+            let ExitCode(exit_code_value) = exit_code;
+            let mut report = serde_json::to_value(&summary)?;
+            report["command"] = json!(command_name);
+            report["status"] = json!(status);
+            report["exit_code"] = json!(exit_code_value);
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                serde_json::to_string(&report)?
+            )?;
+            Ok(exit_code)
+        }
+    }
+}
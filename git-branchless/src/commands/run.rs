@@ -0,0 +1,337 @@
+//! Run a Lua script with access to the commit graph, revset evaluation, and
+//! rewrite operations.
+//!
+//! This lets users automate batch operations, such as renaming the branches of
+//! each stack, without parsing the human-readable output of other commands.
+//! The API is exposed to the script as the global `branchless` table:
+//!
+//! - `branchless.args`: the arguments passed to the script.
+//! - `branchless.query(revset)`: the OIDs of the commits matching the revset,
+//!   topologically sorted with ancestors first.
+//! - `branchless.commit(oid)`: a table describing the given commit, with the
+//!   fields `oid`, `summary`, `message`, `author_name`, `author_email`,
+//!   `parents`, and `branches`.
+//! - `branchless.stacks()`: the OIDs of the draft commits in each stack, with
+//!   each stack topologically sorted. Stacks are ordered by the commit time of
+//!   their root commits.
+//! - `branchless.git(...)`: run a Git command, such as `branchless.git("branch",
+//!   "-m", "old", "new")`, and return its exit code.
+//! - `branchless.move(source, dest)`: move the subtree rooted at the `source`
+//!   revset onto the `dest` revset, as with `git move -x <source> -d <dest>`.
+//! - `branchless.reword(revset, message)`: reword the matching commits, as with
+//!   `git reword <revset> -m <message>`.
+//!
+//! The commit graph is re-read for every query, so the results reflect any
+//! rewrites the script has already made. `print` writes to the command's
+//! output. If the script returns an integer, it's used as the exit code.
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use eden_dag::DagAlgorithm;
+use itertools::Itertools;
+use lib::core::dag::{commit_set_to_vec_unsorted, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{CategorizedReferenceName, GitRunInfo, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use mlua::{Lua, Variadic};
+use tracing::instrument;
+
+use crate::opts::Revset;
+use crate::revset::resolve_commits;
+
+fn to_lua_error(err: eyre::Error) -> mlua::Error {
+    mlua::Error::RuntimeError(err.to_string())
+}
+
+fn query(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revset: &str,
+) -> eyre::Result<Vec<NonZeroOid>> {
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_set = match resolve_commits(
+        effects,
+        git_run_info,
+        &repo,
+        &mut dag,
+        vec![Revset(revset.to_string())],
+    ) {
+        Ok(commit_sets) => commit_sets[0].clone(),
+        Err(err) => eyre::bail!("{}", err),
+    };
+    let commit_set = dag.query().sort(&commit_set)?;
+    let mut commit_oids = commit_set_to_vec_unsorted(&commit_set)?;
+    // The sorted set starts with the descendant commits.
+    commit_oids.reverse();
+    Ok(commit_oids)
+}
+
+/// Get the roots of the draft stacks, ordered by commit time so that the
+/// order of the stacks is stable.
+fn stack_roots(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<Vec<NonZeroOid>> {
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let roots = query(effects, git_run_info, "roots(draft())")?;
+    let mut roots: Vec<_> = roots
+        .into_iter()
+        .map(|oid| {
+            let commit = repo.find_commit_or_fail(oid)?;
+            Ok((commit.get_time(), oid))
+        })
+        .collect::<eyre::Result<_>>()?;
+    roots.sort();
+    Ok(roots.into_iter().map(|(_time, oid)| oid).collect())
+}
+
+fn make_commit_table<'lua>(
+    lua: &'lua Lua,
+    git_run_info: &GitRunInfo,
+    oid: &str,
+) -> eyre::Result<mlua::Table<'lua>> {
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let commit = match repo.revparse_single_commit(oid)? {
+        Some(commit) => commit,
+        None => eyre::bail!("commit not found: {}", oid),
+    };
+    let references_snapshot = repo.get_references_snapshot()?;
+    let branches = match references_snapshot
+        .branch_oid_to_names
+        .get(&commit.get_oid())
+    {
+        Some(branch_names) => branch_names
+            .iter()
+            .map(|name| CategorizedReferenceName::new(name).render_suffix())
+            .sorted()
+            .collect_vec(),
+        None => Vec::new(),
+    };
+
+    let table = lua.create_table().map_err(|err| eyre::eyre!(err))?;
+    let fields: Vec<(&str, mlua::Value)> = vec![
+        (
+            "oid",
+            mlua::Value::String(lua.create_string(commit.get_oid().to_string())?),
+        ),
+        (
+            "summary",
+            mlua::Value::String(lua.create_string(commit.get_summary()?.as_slice())?),
+        ),
+        (
+            "message",
+            mlua::Value::String(lua.create_string(commit.get_message_raw()?.as_slice())?),
+        ),
+        (
+            "author_name",
+            mlua::Value::String(
+                lua.create_string(commit.get_author().get_name().unwrap_or_default())?,
+            ),
+        ),
+        (
+            "author_email",
+            mlua::Value::String(
+                lua.create_string(commit.get_author().get_email().unwrap_or_default())?,
+            ),
+        ),
+        (
+            "parents",
+            mlua::Value::Table(
+                lua.create_sequence_from(
+                    commit
+                        .get_parent_oids()
+                        .into_iter()
+                        .map(|oid| oid.to_string()),
+                )?,
+            ),
+        ),
+        (
+            "branches",
+            mlua::Value::Table(lua.create_sequence_from(branches)?),
+        ),
+    ];
+    for (key, value) in fields {
+        table.set(key, value)?;
+    }
+    Ok(table)
+}
+
+fn run_checked(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    event_tx_id: EventTransactionId,
+    args: &[&str],
+) -> eyre::Result<()> {
+    let exit_code = git_run_info.run(effects, Some(event_tx_id), args)?;
+    if !exit_code.is_success() {
+        let ExitCode(exit_code) = exit_code;
+        eyre::bail!(
+            "command failed with exit code {}: git {}",
+            exit_code,
+            args.join(" ")
+        );
+    }
+    Ok(())
+}
+
+fn make_api(
+    lua: &Lua,
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    event_tx_id: EventTransactionId,
+    args: Vec<String>,
+) -> mlua::Result<()> {
+    let api = lua.create_table()?;
+    api.set("args", lua.create_sequence_from(args)?)?;
+
+    api.set("query", {
+        let effects = effects.clone();
+        let git_run_info = git_run_info.clone();
+        lua.create_function(move |_lua, revset: String| {
+            let oids = query(&effects, &git_run_info, &revset).map_err(to_lua_error)?;
+            Ok(oids.into_iter().map(|oid| oid.to_string()).collect_vec())
+        })?
+    })?;
+
+    api.set("commit", {
+        let git_run_info = git_run_info.clone();
+        lua.create_function(move |lua, oid: String| {
+            make_commit_table(lua, &git_run_info, &oid).map_err(to_lua_error)
+        })?
+    })?;
+
+    api.set("stacks", {
+        let effects = effects.clone();
+        let git_run_info = git_run_info.clone();
+        lua.create_function(move |_lua, ()| {
+            let roots = stack_roots(&effects, &git_run_info).map_err(to_lua_error)?;
+            let stacks: Vec<Vec<String>> = roots
+                .into_iter()
+                .map(|root| {
+                    let revset = format!("draft() & descendants({})", root);
+                    let oids = query(&effects, &git_run_info, &revset)?;
+                    Ok(oids.into_iter().map(|oid| oid.to_string()).collect_vec())
+                })
+                .collect::<eyre::Result<_>>()
+                .map_err(to_lua_error)?;
+            Ok(stacks)
+        })?
+    })?;
+
+    api.set("git", {
+        let effects = effects.clone();
+        let git_run_info = git_run_info.clone();
+        lua.create_function(move |_lua, args: Variadic<String>| {
+            let ExitCode(exit_code) = git_run_info
+                .run(&effects, Some(event_tx_id), &args)
+                .map_err(to_lua_error)?;
+            Ok(exit_code)
+        })?
+    })?;
+
+    api.set("move", {
+        let effects = effects.clone();
+        let git_run_info = git_run_info.clone();
+        lua.create_function(move |_lua, (source, dest): (String, String)| {
+            run_checked(
+                &effects,
+                &git_run_info,
+                event_tx_id,
+                &["branchless", "move", "-x", &source, "-d", &dest],
+            )
+            .map_err(to_lua_error)
+        })?
+    })?;
+
+    api.set("reword", {
+        let effects = effects.clone();
+        let git_run_info = git_run_info.clone();
+        lua.create_function(move |_lua, (revset, message): (String, String)| {
+            run_checked(
+                &effects,
+                &git_run_info,
+                event_tx_id,
+                &["branchless", "reword", &revset, "-m", &message],
+            )
+            .map_err(to_lua_error)
+        })?
+    })?;
+
+    lua.globals().set("branchless", api)?;
+
+    lua.globals().set("print", {
+        let effects = effects.clone();
+        lua.create_function(move |_lua, values: Variadic<mlua::Value>| {
+            let line = values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<mlua::Result<Vec<_>>>()?
+                .join("\t");
+            writeln!(effects.get_output_stream(), "{}", line)
+                .map_err(|err| mlua::Error::RuntimeError(err.to_string()))?;
+            Ok(())
+        })?
+    })?;
+
+    Ok(())
+}
+
+/// Run the Lua script at the given path.
+#[instrument]
+pub fn run(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    script: &Path,
+    args: Vec<String>,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "run")?;
+
+    let source = match std::fs::read_to_string(script) {
+        Ok(source) => source,
+        Err(err) => {
+            writeln!(
+                effects.get_error_stream(),
+                "Could not read script {}: {}",
+                script.display(),
+                err
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    let lua = Lua::new();
+    let result = make_api(&lua, effects, git_run_info, event_tx_id, args).and_then(|()| {
+        lua.load(&source)
+            .set_name(script.to_string_lossy())
+            .eval::<mlua::Value>()
+    });
+    match result {
+        Ok(mlua::Value::Integer(exit_code)) => {
+            Ok(ExitCode(isize::try_from(exit_code).unwrap_or(1)))
+        }
+        Ok(_) => Ok(ExitCode(0)),
+        Err(err) => {
+            writeln!(effects.get_error_stream(), "Script error: {}", err)?;
+            Ok(ExitCode(1))
+        }
+    }
+}
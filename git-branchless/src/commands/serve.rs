@@ -0,0 +1,769 @@
+//! A long-lived server which answers requests from editor extensions, so that
+//! they can query and update the repository without spawning a new process for
+//! each request.
+//!
+//! Requests and responses are JSON-RPC 2.0 messages, sent over stdin and
+//! stdout. As in the Language Server Protocol, each message is preceded by a
+//! `Content-Length` header giving the length of the message in bytes,
+//! followed by a blank line. Messages longer than `MAX_CONTENT_LENGTH` are
+//! rejected.
+//!
+//! The repository, event log, and commit graph are loaded on the first request
+//! which needs them, and are refreshed incrementally before each subsequent
+//! one.
+//!
+//! The supported methods are:
+//!
+//! - `smartlog`: get the commits in the smartlog graph. Takes an optional
+//!   `revset` (defaults to `draft()`) and `hidden` flag.
+//! - `query`: get the OIDs of the commits matching the given `revset`, with
+//!   ancestors appearing first.
+//! - `hide`: hide the commits matching the given `revsets`. Takes optional
+//!   `recursive` and `delete_branches` flags.
+//! - `reword`: replace the message of the commits matching the given
+//!   `revsets` with the given `message`.
+//! - `move`: move the commits matching the given `source`, `base`, or `exact`
+//!   revsets onto the given `dest`.
+//! - `shutdown`: stop the server after responding.
+//!
+//! The methods which update the repository never prompt for input, and always
+//! rebase in-memory. They return the `exit_code` of the operation and the
+//! `output` that it would have printed. `reword` and `move` also return the
+//! same report as `--output json`.
+
+use std::convert::TryInto;
+use std::io::{stdin, stdout, BufRead, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use bstr::ByteSlice;
+use eyre::Context;
+use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Glyphs;
+use lib::core::node_descriptors::NodeObject;
+use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
+use lib::git::{CategorizedReferenceName, GitRunInfo, NonZeroOid, Repo};
+use lib::util::ExitCode;
+use serde_json::{json, Value};
+use tracing::instrument;
+
+use crate::commands::hide;
+use crate::commands::r#move;
+use crate::commands::reword::{self, InitialCommitMessages};
+use crate::commands::rewrite_summary::with_rewrite_summary;
+use crate::commands::smartlog::make_smartlog_graph;
+use crate::opts::{MoveOptions, OutputFormat, Revset};
+use crate::revset::{resolve_commits, ResolveError};
+
+/// The request couldn't be parsed as JSON.
+const PARSE_ERROR: i64 = -32700;
+
+/// The request wasn't a valid JSON-RPC request object.
+const INVALID_REQUEST: i64 = -32600;
+
+/// The requested method doesn't exist.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The parameters of the request were missing or had the wrong type.
+const INVALID_PARAMS: i64 = -32602;
+
+/// The request failed due to an internal error.
+const INTERNAL_ERROR: i64 = -32603;
+
+/// A revset in the request couldn't be parsed or evaluated.
+const REVSET_ERROR: i64 = -32000;
+
+/// An error to report to the client in place of a result.
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<eyre::Error> for RpcError {
+    fn from(err: eyre::Error) -> Self {
+        Self {
+            code: INTERNAL_ERROR,
+            message: format!("{:#}", err),
+        }
+    }
+}
+
+impl From<ResolveError> for RpcError {
+    fn from(err: ResolveError) -> Self {
+        match err {
+            ResolveError::ParseError { .. } | ResolveError::EvalError { .. } => Self {
+                code: REVSET_ERROR,
+                message: err.to_string(),
+            },
+            ResolveError::DagError { source } => eyre::Error::from(source).into(),
+            ResolveError::OtherError { source } => source.into(),
+        }
+    }
+}
+
+/// A message read from the client.
+#[derive(Debug, PartialEq, Eq)]
+enum Message {
+    /// The content of a well-formed message.
+    Content(Vec<u8>),
+
+    /// A message whose header couldn't be parsed. Its content is skipped, and
+    /// reading resumes at the next `Content-Length` header.
+    Malformed(String),
+}
+
+/// The maximum length of a message's content. Longer messages are rejected
+/// without being read into memory.
+const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Read the next message from the client. Returns `None` if the client closed
+/// the connection.
+fn read_message(reader: &mut impl BufRead) -> eyre::Result<Option<Message>> {
+    const CONTENT_LENGTH_HEADER: &str = "content-length:";
+
+    let mut content_length = None;
+    let mut malformed_header = None;
+    loop {
+        let mut line = Vec::new();
+        if reader
+            .read_until(b'\n', &mut line)
+            .wrap_err("Reading message header")?
+            == 0
+        {
+            return Ok(None);
+        }
+        let line = line.to_str_lossy();
+        let line = line.trim_end_matches(&['\r', '\n'][..]);
+        if line.is_empty() {
+            match (content_length, malformed_header.take()) {
+                (Some(_), _) => break,
+                (None, Some(message)) => return Ok(Some(Message::Malformed(message))),
+                (None, None) => continue,
+            }
+        }
+
+        // After a malformed header, the content of that message is skipped
+        // line by line until the next line starting with a header.
+        if line
+            .get(..CONTENT_LENGTH_HEADER.len())
+            .map_or(false, |name| {
+                name.eq_ignore_ascii_case(CONTENT_LENGTH_HEADER)
+            })
+        {
+            let value = &line[CONTENT_LENGTH_HEADER.len()..];
+            match value.trim().parse::<usize>() {
+                Ok(value) => {
+                    content_length = Some(value);
+                    malformed_header = None;
+                }
+                Err(err) => {
+                    content_length = None;
+                    malformed_header =
+                        Some(format!("Invalid Content-Length header {:?}: {}", line, err));
+                }
+            }
+        }
+    }
+
+    let content_length = content_length.unwrap_or_default();
+    if content_length > MAX_CONTENT_LENGTH {
+        let content_length_u64: u64 = content_length.try_into()?;
+        let num_skipped = std::io::copy(
+            &mut reader.by_ref().take(content_length_u64),
+            &mut std::io::sink(),
+        )
+        .wrap_err("Skipping message content")?;
+        if num_skipped < content_length_u64 {
+            return Ok(None);
+        }
+        return Ok(Some(Message::Malformed(format!(
+            "Content-Length {} exceeds the maximum of {}",
+            content_length, MAX_CONTENT_LENGTH
+        ))));
+    }
+    let mut content = vec![0; content_length];
+    reader
+        .read_exact(&mut content)
+        .wrap_err("Reading message content")?;
+    Ok(Some(Message::Content(content)))
+}
+
+/// Write a message to the client.
+fn write_message(writer: &mut impl Write, message: &Value) -> eyre::Result<()> {
+    let content = serde_json::to_string(message)?;
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn get_string_param(params: &Value, name: &str) -> Result<Option<String>, RpcError> {
+    match &params[name] {
+        Value::Null => Ok(None),
+        Value::String(value) => Ok(Some(value.clone())),
+        _ => Err(RpcError::invalid_params(format!(
+            "Parameter `{}` should be a string",
+            name
+        ))),
+    }
+}
+
+fn get_bool_param(params: &Value, name: &str) -> Result<bool, RpcError> {
+    match &params[name] {
+        Value::Null => Ok(false),
+        Value::Bool(value) => Ok(*value),
+        _ => Err(RpcError::invalid_params(format!(
+            "Parameter `{}` should be a boolean",
+            name
+        ))),
+    }
+}
+
+fn get_revsets_param(params: &Value, name: &str) -> Result<Vec<Revset>, RpcError> {
+    match &params[name] {
+        Value::Null => Ok(Vec::new()),
+        Value::String(value) => Ok(vec![Revset(value.clone())]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(value) => Ok(Revset(value.clone())),
+                _ => Err(RpcError::invalid_params(format!(
+                    "Parameter `{}` should be a list of strings",
+                    name
+                ))),
+            })
+            .collect(),
+        _ => Err(RpcError::invalid_params(format!(
+            "Parameter `{}` should be a string or a list of strings",
+            name
+        ))),
+    }
+}
+
+/// The repository state kept across requests, so that it doesn't have to be
+/// reloaded for each one. It's refreshed incrementally before each request,
+/// since the repository may have been changed by the client or by other
+/// processes in the meantime.
+struct ServerState {
+    repo: Repo,
+    conn: rusqlite::Connection,
+    event_replayer: EventReplayer,
+    last_event_rowid: i64,
+
+    /// The DAG as of the last request. If `None`, it's opened from scratch on
+    /// the next request, such as if a previous refresh failed.
+    dag: Option<Dag>,
+}
+
+impl ServerState {
+    fn new(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<Self> {
+        let repo = Repo::from_dir(&git_run_info.working_directory)?;
+        let conn = repo.get_db_conn()?;
+        let (event_replayer, last_event_rowid) = {
+            let event_log_db = EventLogDb::new(&conn)?;
+            let last_event_rowid = event_log_db.get_last_event_rowid()?;
+            let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+            (event_replayer, last_event_rowid)
+        };
+        Ok(Self {
+            repo,
+            conn,
+            event_replayer,
+            last_event_rowid,
+            dag: None,
+        })
+    }
+
+    /// Replay any events added since the last request, and update the DAG
+    /// with the current references. Returns the references snapshot that the
+    /// DAG was updated with.
+    fn refresh(&mut self, effects: &Effects) -> eyre::Result<RepoReferencesSnapshot> {
+        let event_log_db = EventLogDb::new(&self.conn)?;
+        for (rowid, event) in event_log_db.get_events_after_rowid(self.last_event_rowid)? {
+            self.event_replayer.process_event(&event);
+            self.last_event_rowid = rowid;
+        }
+
+        let event_cursor = self.event_replayer.make_default_cursor();
+        let references_snapshot = self.repo.get_references_snapshot()?;
+        let dag = match self.dag.take() {
+            Some(dag) => dag.refresh(
+                effects,
+                &self.repo,
+                &self.event_replayer,
+                event_cursor,
+                &references_snapshot,
+            )?,
+            None => Dag::open_and_sync(
+                effects,
+                &self.repo,
+                &self.event_replayer,
+                event_cursor,
+                &references_snapshot,
+            )?,
+        };
+        self.dag = Some(dag);
+        Ok(references_snapshot)
+    }
+}
+
+/// Run the server until the client sends a `shutdown` request or closes the
+/// connection.
+#[instrument]
+pub fn serve(git_run_info: &GitRunInfo) -> eyre::Result<ExitCode> {
+    let stdin = stdin();
+    let mut reader = stdin.lock();
+    let stdout = stdout();
+    let mut writer = stdout.lock();
+
+    // Git processes run by the server must not read from its stdin, which
+    // carries the requests.
+    let git_run_info = GitRunInfo {
+        inherit_stdin: false,
+        ..git_run_info.clone()
+    };
+    let mut state: Option<ServerState> = None;
+
+    while let Some(message) = read_message(&mut reader)? {
+        let request: Result<Value, String> = match message {
+            Message::Content(content) => {
+                serde_json::from_slice(&content).map_err(|err| err.to_string())
+            }
+            Message::Malformed(message) => Err(message),
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(message) => {
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": Value::Null,
+                    "error": {"code": PARSE_ERROR, "message": message},
+                });
+                write_message(&mut writer, &response)?;
+                continue;
+            }
+        };
+
+        let id = request["id"].clone();
+        let method = request["method"].as_str();
+        let result = match method {
+            Some(method) => handle_request(&git_run_info, &mut state, method, &request["params"]),
+            None => Err(RpcError {
+                code: INVALID_REQUEST,
+                message: "Request should have a `method`".to_string(),
+            }),
+        };
+
+        // Notifications (requests without an ID) don't get a response.
+        if !id.is_null() {
+            let response = match result {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                Err(RpcError { code, message }) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {"code": code, "message": message},
+                }),
+            };
+            write_message(&mut writer, &response)?;
+        }
+
+        if method == Some("shutdown") {
+            break;
+        }
+    }
+
+    Ok(ExitCode(0))
+}
+
+/// Get the server state, loading it if this is the first request (or if
+/// loading it failed previously), and refresh it.
+fn get_state<'a>(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    state: &'a mut Option<ServerState>,
+) -> eyre::Result<(&'a mut ServerState, RepoReferencesSnapshot)> {
+    let state = match state {
+        Some(state) => state,
+        None => state.insert(ServerState::new(effects, git_run_info)?),
+    };
+    let references_snapshot = state.refresh(effects)?;
+    Ok((state, references_snapshot))
+}
+
+fn handle_request(
+    git_run_info: &GitRunInfo,
+    state: &mut Option<ServerState>,
+    method: &str,
+    params: &Value,
+) -> Result<Value, RpcError> {
+    match method {
+        "smartlog" => {
+            let revset =
+                get_string_param(params, "revset")?.unwrap_or_else(|| "draft()".to_string());
+            let hidden = get_bool_param(params, "hidden")?;
+            let (effects, _stdout, _stderr) = make_captured_effects();
+            let (state, references_snapshot) = get_state(&effects, git_run_info, state)?;
+            get_smartlog(
+                &effects,
+                git_run_info,
+                state,
+                &references_snapshot,
+                revset,
+                hidden,
+            )
+        }
+
+        "query" => {
+            let revset = get_string_param(params, "revset")?
+                .ok_or_else(|| RpcError::invalid_params("Parameter `revset` is required"))?;
+            let (effects, _stdout, _stderr) = make_captured_effects();
+            let (state, _references_snapshot) = get_state(&effects, git_run_info, state)?;
+            query(&effects, git_run_info, state, revset)
+        }
+
+        "hide" => {
+            let revsets = get_revsets_param(params, "revsets")?;
+            let recursive = get_bool_param(params, "recursive")?;
+            let delete_branches = get_bool_param(params, "delete_branches")?;
+            run_command(|effects| {
+                hide::hide(
+                    effects,
+                    git_run_info,
+                    revsets,
+                    delete_branches,
+                    recursive,
+                    false,
+                )
+            })
+        }
+
+        "reword" => {
+            let revsets = get_revsets_param(params, "revsets")?;
+            let message = get_string_param(params, "message")?
+                .ok_or_else(|| RpcError::invalid_params("Parameter `message` is required"))?;
+            run_command(|effects| {
+                with_rewrite_summary(
+                    effects,
+                    git_run_info,
+                    OutputFormat::Json,
+                    "reword",
+                    |effects, _summary| {
+                        reword::reword(
+                            effects,
+                            revsets,
+                            InitialCommitMessages::Messages(vec![message]),
+                            git_run_info,
+                            false,
+                            false,
+                            false,
+                            true,
+                        )
+                    },
+                )
+            })
+        }
+
+        "move" => {
+            let sources = get_revsets_param(params, "source")?;
+            let bases = get_revsets_param(params, "base")?;
+            let exacts = get_revsets_param(params, "exact")?;
+            let dest = get_string_param(params, "dest")?.map(Revset);
+            let insert = get_bool_param(params, "insert")?;
+            let move_options = MoveOptions {
+                force_rewrite_public_commits: false,
+                force_rewrite_protected_branches: false,
+                force_in_memory: true,
+                force_on_disk: false,
+                detect_duplicate_commits_via_patch_id: true,
+                resolve_merge_conflicts: false,
+                skip_confirmation: true,
+                dump_rebase_constraints: false,
+                dump_rebase_plan: false,
+            };
+            run_command(|effects| {
+                with_rewrite_summary(
+                    effects,
+                    git_run_info,
+                    OutputFormat::Json,
+                    "move",
+                    |effects, summary| {
+                        r#move::r#move(
+                            effects,
+                            git_run_info,
+                            sources,
+                            dest,
+                            bases,
+                            exacts,
+                            insert,
+                            &move_options,
+                            summary,
+                        )
+                    },
+                )
+            })
+        }
+
+        "shutdown" => Ok(Value::Null),
+
+        method => Err(RpcError {
+            code: METHOD_NOT_FOUND,
+            message: format!("Unknown method: {}", method),
+        }),
+    }
+}
+
+fn resolve_revset(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &mut Dag,
+    revset: String,
+) -> Result<CommitSet, RpcError> {
+    let commit_sets = resolve_commits(effects, git_run_info, repo, dag, vec![Revset(revset)])?;
+    Ok(commit_sets
+        .into_iter()
+        .next()
+        .unwrap_or_else(CommitSet::empty))
+}
+
+fn query(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    state: &mut ServerState,
+    revset: String,
+) -> Result<Value, RpcError> {
+    let ServerState { repo, dag, .. } = state;
+    let dag = dag
+        .as_mut()
+        .expect("The DAG should have been loaded by the refresh");
+    let commit_set = resolve_revset(effects, git_run_info, repo, dag, revset)?;
+    let commits = sorted_commit_set(repo, dag, &commit_set)?;
+    Ok(json!({
+        "commits": commits
+            .iter()
+            .map(|commit| commit.get_oid().to_string())
+            .collect::<Vec<_>>(),
+    }))
+}
+
+fn get_smartlog(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    state: &mut ServerState,
+    references_snapshot: &RepoReferencesSnapshot,
+    revset: String,
+    hidden: bool,
+) -> Result<Value, RpcError> {
+    let ServerState {
+        repo,
+        event_replayer,
+        dag,
+        ..
+    } = state;
+    let dag = dag
+        .as_mut()
+        .expect("The DAG should have been loaded by the refresh");
+    let event_cursor = event_replayer.make_default_cursor();
+
+    let observed_commits = if hidden {
+        let obsolete_commits = std::mem::replace(&mut dag.obsolete_commits, CommitSet::empty());
+        let observed_commits = resolve_revset(effects, git_run_info, repo, dag, revset);
+        dag.obsolete_commits = obsolete_commits;
+        observed_commits?
+    } else {
+        resolve_revset(effects, git_run_info, repo, dag, revset)?
+    };
+    let graph = make_smartlog_graph(
+        effects,
+        repo,
+        dag,
+        event_replayer,
+        event_cursor,
+        &observed_commits,
+        !hidden,
+    )?;
+
+    let node_oids: CommitSet = graph.nodes.keys().copied().collect();
+    let mut sorted_oids: Vec<NonZeroOid> = sorted_commit_set(repo, dag, &node_oids)?
+        .iter()
+        .map(|commit| commit.get_oid())
+        .collect();
+    sorted_oids.extend(
+        graph
+            .nodes
+            .keys()
+            .filter(|oid| !sorted_oids.contains(oid))
+            .copied()
+            .collect::<Vec<_>>(),
+    );
+
+    let mut nodes = Vec::new();
+    for oid in sorted_oids {
+        let node = &graph.nodes[&oid];
+        let summary = match &node.object {
+            NodeObject::Commit { commit } => {
+                Some(commit.get_summary()?.to_str_lossy().into_owned())
+            }
+            NodeObject::GarbageCollected { oid: _ } => None,
+        };
+        let mut branches: Vec<String> = references_snapshot
+            .branch_oid_to_names
+            .get(&oid)
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|name| CategorizedReferenceName::new(name).render_suffix())
+                    .collect()
+            })
+            .unwrap_or_default();
+        branches.sort_unstable();
+        nodes.push(json!({
+            "oid": oid.to_string(),
+            "parent": node.parent.map(|parent| parent.to_string()),
+            "children": node.children.iter().map(|child| child.to_string()).collect::<Vec<_>>(),
+            "summary": summary,
+            "branches": branches,
+            "is_head": references_snapshot.head_oid == Some(oid),
+            "is_main": node.is_main,
+            "is_obsolete": node.is_obsolete,
+        }));
+    }
+
+    Ok(json!({
+        "head_oid": references_snapshot.head_oid.map(|oid| oid.to_string()),
+        "nodes": nodes,
+    }))
+}
+
+/// A buffer which captures the output of a command.
+type OutputBuffer = Arc<Mutex<Vec<u8>>>;
+
+/// Make an `Effects` which captures its output in the returned buffers, since
+/// stdout is reserved for responses.
+fn make_captured_effects() -> (Effects, OutputBuffer, OutputBuffer) {
+    let stdout = Arc::new(Mutex::new(Vec::new()));
+    let stderr = Arc::new(Mutex::new(Vec::new()));
+    let effects = Effects::new_from_buffer(Glyphs::text(), &stdout, &stderr);
+    (effects, stdout, stderr)
+}
+
+/// Run a command which updates the repository, and return its exit code and
+/// captured output.
+fn run_command(f: impl FnOnce(&Effects) -> eyre::Result<ExitCode>) -> Result<Value, RpcError> {
+    let (effects, stdout, stderr) = make_captured_effects();
+    let ExitCode(exit_code) = f(&effects)?;
+
+    let stdout = stdout.lock().unwrap().to_str_lossy().into_owned();
+    let stderr = stderr.lock().unwrap().to_str_lossy().into_owned();
+    // Commands run with `OutputFormat::Json` print their report to stdout and
+    // everything else to stderr.
+    let mut result = match serde_json::from_str::<Value>(&stdout) {
+        Ok(report @ Value::Object(_)) => json!({ "report": report, "output": stderr }),
+        _ => json!({ "output": format!("{}{}", stdout, stderr) }),
+    };
+    result["exit_code"] = json!(exit_code);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write_message() -> eyre::Result<()> {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({"jsonrpc": "2.0", "id": 1}))?;
+        write_message(&mut buffer, &json!({"jsonrpc": "2.0", "id": 2}))?;
+        insta::assert_snapshot!(buffer.to_str_lossy(), @r###"
+        Content-Length: 24
+
+        {"id":1,"jsonrpc":"2.0"}Content-Length: 24
+
+        {"id":2,"jsonrpc":"2.0"}
+        "###);
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(
+            read_message(&mut reader)?,
+            Some(Message::Content(br#"{"id":1,"jsonrpc":"2.0"}"#.to_vec()))
+        );
+        assert_eq!(
+            read_message(&mut reader)?,
+            Some(Message::Content(br#"{"id":2,"jsonrpc":"2.0"}"#.to_vec()))
+        );
+        assert_eq!(read_message(&mut reader)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_malformed_message() -> eyre::Result<()> {
+        let mut buffer = b"Content-Length: twenty\r\n\r\n{\"id\":1}\r\n".to_vec();
+        write_message(&mut buffer, &json!({"jsonrpc": "2.0", "id": 2}))?;
+
+        let mut reader = buffer.as_slice();
+        insta::assert_debug_snapshot!(read_message(&mut reader)?, @r###"
+        Some(
+            Malformed(
+                "Invalid Content-Length header \"Content-Length: twenty\": invalid digit found in string",
+            ),
+        )
+        "###);
+        assert_eq!(
+            read_message(&mut reader)?,
+            Some(Message::Content(br#"{"id":2,"jsonrpc":"2.0"}"#.to_vec()))
+        );
+        assert_eq!(read_message(&mut reader)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_message_header_only_at_line_start() -> eyre::Result<()> {
+        let mut buffer = b"X-Content-Length: 3\r\n\r\n".to_vec();
+        write_message(&mut buffer, &json!({"jsonrpc": "2.0", "id": 1}))?;
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(
+            read_message(&mut reader)?,
+            Some(Message::Content(br#"{"id":1,"jsonrpc":"2.0"}"#.to_vec()))
+        );
+        assert_eq!(read_message(&mut reader)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_message_too_long() -> eyre::Result<()> {
+        let mut buffer = format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1).into_bytes();
+        buffer.extend(std::iter::repeat(b' ').take(MAX_CONTENT_LENGTH + 1));
+        write_message(&mut buffer, &json!({"jsonrpc": "2.0", "id": 2}))?;
+
+        let mut reader = buffer.as_slice();
+        insta::assert_debug_snapshot!(read_message(&mut reader)?, @r###"
+        Some(
+            Malformed(
+                "Content-Length 67108865 exceeds the maximum of 67108864",
+            ),
+        )
+        "###);
+        assert_eq!(
+            read_message(&mut reader)?,
+            Some(Message::Content(br#"{"id":2,"jsonrpc":"2.0"}"#.to_vec()))
+        );
+        assert_eq!(read_message(&mut reader)?, None);
+        Ok(())
+    }
+}
@@ -2,34 +2,74 @@
 //!
 //! The set of commits that are still being worked on is inferred from the event
 //! log; see the `eventlog` module.
+//!
+//! Besides the `git smartlog` command itself, this module can be used to render
+//! the graph elsewhere, such as in documentation or code review descriptions:
+//!
+//! 1. Build the graph of commits to show with [`make_smartlog_graph`] (or
+//!    [`make_hypothetical_smartlog_graph`] for commits which may be discarded).
+//! 2. Lay it out into rows and edges with [`layout_graph`], which describes each
+//!    commit with the given [`NodeDescriptor`](lib::core::node_descriptors::NodeDescriptor)s.
+//! 3. Render the resulting [`SmartlogLayout`] with a [`SmartlogSink`]: [`TextSink`]
+//!    for terminal output, [`HtmlSink`] for an HTML `<pre>` block, or [`DotSink`]
+//!    for a Graphviz graph.
+//!
+//! [`render_graph`] combines the last two steps for text output.
 
 use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::hash::{Hash, Hasher};
 use std::mem::swap;
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
-use console::style;
 use eden_dag::DagAlgorithm;
-use lib::core::config::{get_hint_enabled, print_hint_suppression_notice, Hint};
-use lib::core::repo_ext::RepoExt;
-use lib::core::rewrite::find_rewrite_target;
+use eyre::Context;
+use itertools::Itertools;
+use lib::core::ci_status::{get_ci_statuses, CiStatusDb};
+use lib::core::config::{
+    get_commit_descriptors_ci_status, get_commit_descriptors_signature, get_hint_enabled,
+    get_smartlog_cache, get_smartlog_changes_banner, print_hint_suppression_notice, Hint,
+};
+use lib::core::metadata::CommitMetadataDb;
+use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
+use lib::core::rewrite::{find_rewrite_target, RewriteMap};
+use lib::core::signature_status::{get_signature_statuses, SignatureStatusDb};
+use lib::core::smartlog_cache::{CachedSmartlog, SmartlogCacheDb};
+use lib::core::topics::infer_stack_topics;
 use lib::util::ExitCode;
 use tracing::instrument;
 
-use lib::core::dag::{CommitSet, Dag};
+use lib::core::dag::{union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
-use lib::core::formatting::{printable_styled_string, Pluralize};
+use lib::core::eventlog::{
+    Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId,
+    BRANCHLESS_TRANSACTION_ID_ENV_VAR,
+};
+use lib::core::formatting::{printable_styled_string, Glyphs, Pluralize, Theme};
 use lib::core::node_descriptors::{
-    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
-    DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
-    RelativeTimeDescriptor,
+    BranchesDescriptor, CiStatusDescriptor, CollapsedStackDescriptor, CommitMessageDescriptor,
+    CommitMetadataDescriptor, CommitOidDescriptor, DifferentialRevisionDescriptor,
+    DryRunDescriptor, ObsolescenceExplanationDescriptor, Redactor, RelativeTimeDescriptor,
+    SignatureStatusDescriptor, StackTopicDescriptor, WorktreesDescriptor,
+};
+use lib::git::{
+    ConfigRead, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo,
 };
-use lib::git::{GitRunInfo, Repo};
 
-pub use graph::{make_smartlog_graph, SmartlogGraph};
-pub use render::{render_graph, SmartlogOptions};
+pub use graph::Node;
+pub use graph::{
+    collapse_stacks, make_hypothetical_smartlog_graph, make_smartlog_graph, SmartlogGraph,
+};
+pub use render::{
+    layout_graph, render_graph, DotSink, GraphEdge, GraphRow, GraphRowNode, HtmlSink,
+    SmartlogLayout, SmartlogOptions, SmartlogSink, TextSink,
+};
 
+use crate::commands::daemon;
+use crate::opts::SmartlogFormat;
 use crate::revset::resolve_commits;
 
 mod graph {
@@ -87,6 +127,7 @@ mod graph {
 
     /// Graph of commits that the user is working on.
     pub struct SmartlogGraph<'repo> {
+        /// The nodes in the graph, keyed by their commit OIDs.
         pub nodes: HashMap<NonZeroOid, Node<'repo>>,
     }
 
@@ -216,6 +257,67 @@ mod graph {
         }
     }
 
+    /// Collapse each stack of draft commits in the graph down to its root
+    /// commit, except for stacks which contain any of `expanded_commits`.
+    ///
+    /// Returns a map from the root commit of each collapsed stack to the number
+    /// of commits which were removed from the graph for that stack.
+    #[instrument]
+    pub fn collapse_stacks(
+        graph: &mut SmartlogGraph,
+        expanded_commits: &CommitSet,
+    ) -> eyre::Result<HashMap<NonZeroOid, usize>> {
+        let stack_root_oids: Vec<NonZeroOid> = graph
+            .nodes
+            .iter()
+            .filter(|(_oid, node)| {
+                !node.is_main
+                    && match node.parent {
+                        Some(parent_oid) => graph.nodes[&parent_oid].is_main,
+                        None => true,
+                    }
+            })
+            .map(|(oid, _node)| *oid)
+            .collect();
+
+        let mut result = HashMap::new();
+        for root_oid in stack_root_oids {
+            let mut stack_oids = Vec::new();
+            let mut to_visit = graph.nodes[&root_oid].children.clone();
+            while let Some(oid) = to_visit.pop() {
+                let node = &graph.nodes[&oid];
+                if node.is_main {
+                    continue;
+                }
+                stack_oids.push(oid);
+                to_visit.extend(node.children.iter().copied());
+            }
+            if stack_oids.is_empty() {
+                continue;
+            }
+
+            let mut is_expanded = expanded_commits.contains(&root_oid.into())?;
+            for oid in stack_oids.iter() {
+                is_expanded = is_expanded || expanded_commits.contains(&(*oid).into())?;
+            }
+            if is_expanded {
+                continue;
+            }
+
+            for oid in stack_oids.iter() {
+                graph.nodes.remove(oid);
+            }
+            graph
+                .nodes
+                .get_mut(&root_oid)
+                .unwrap()
+                .children
+                .retain(|child_oid| !stack_oids.contains(child_oid));
+            result.insert(root_oid, stack_oids.len());
+        }
+        Ok(result)
+    }
+
     /// Construct the smartlog graph for the repo.
     #[instrument]
     pub fn make_smartlog_graph<'repo>(
@@ -226,6 +328,31 @@ mod graph {
         event_cursor: EventCursor,
         observed_commits: &CommitSet,
         remove_commits: bool,
+    ) -> eyre::Result<SmartlogGraph<'repo>> {
+        make_smartlog_graph_inner(effects, repo, dag, observed_commits, remove_commits, true)
+    }
+
+    /// Construct the smartlog graph for commits which may only exist
+    /// hypothetically, such as those created by a `--dry-run`. Unlike
+    /// [`make_smartlog_graph`], the commits aren't marked as reachable, so
+    /// they can be garbage-collected later.
+    #[instrument]
+    pub fn make_hypothetical_smartlog_graph<'repo>(
+        effects: &Effects,
+        repo: &'repo Repo,
+        dag: &Dag,
+        observed_commits: &CommitSet,
+    ) -> eyre::Result<SmartlogGraph<'repo>> {
+        make_smartlog_graph_inner(effects, repo, dag, observed_commits, false, false)
+    }
+
+    fn make_smartlog_graph_inner<'repo>(
+        effects: &Effects,
+        repo: &'repo Repo,
+        dag: &Dag,
+        observed_commits: &CommitSet,
+        remove_commits: bool,
+        mark_reachable: bool,
     ) -> eyre::Result<SmartlogGraph<'repo>> {
         let (effects, _progress) = effects.start_operation(OperationType::MakeGraph);
 
@@ -241,8 +368,10 @@ mod graph {
             };
 
             let active_heads = dag.query_active_heads(&public_commits, &observed_commits)?;
-            for oid in commit_set_to_vec_unsorted(&active_heads)? {
-                mark_commit_reachable(repo, oid)?;
+            if mark_reachable {
+                for oid in commit_set_to_vec_unsorted(&active_heads)? {
+                    mark_commit_reachable(repo, oid)?;
+                }
             }
 
             walk_from_active_heads(&effects, repo, dag, &public_commits, &active_heads)?
@@ -254,20 +383,23 @@ mod graph {
 
 mod render {
     use std::cmp::Ordering;
+    use std::fmt::Write;
+    use std::time::SystemTime;
 
-    use cursive::theme::Effect;
+    use cursive::theme::{BaseColor, Color, ColorType, Effect, Style};
     use cursive::utils::markup::StyledString;
+    use cursive::utils::span::Span;
     use eden_dag::DagAlgorithm;
     use tracing::instrument;
 
     use lib::core::dag::{CommitSet, CommitVertex, Dag};
     use lib::core::effects::Effects;
-    use lib::core::formatting::set_effect;
+    use lib::core::formatting::{printable_styled_string, set_style};
     use lib::core::formatting::{Glyphs, StyledStringBuilder};
     use lib::core::node_descriptors::{render_node_descriptors, NodeDescriptor};
     use lib::git::{NonZeroOid, Repo};
 
-    use crate::opts::Revset;
+    use crate::opts::{Revset, SmartlogFormat};
 
     use super::graph::SmartlogGraph;
 
@@ -326,7 +458,105 @@ mod render {
         root_commit_oids
     }
 
-    #[instrument(skip(commit_descriptors, graph))]
+    /// A row of the laid-out smartlog graph.
+    #[derive(Debug)]
+    pub struct GraphRow {
+        /// The graph lines which connect this row to the rows around it, drawn
+        /// to the left of the node.
+        pub prefix: String,
+
+        /// The node drawn on this row, if any. Rows without a node only connect
+        /// the rows above and below them.
+        pub node: Option<GraphRowNode>,
+    }
+
+    /// A node drawn on a row of the laid-out smartlog graph.
+    #[derive(Debug)]
+    pub struct GraphRowNode {
+        /// The OID of the node.
+        pub oid: NonZeroOid,
+
+        /// The glyph used to draw the node itself.
+        pub cursor: String,
+
+        /// Whether the node is the currently checked-out commit.
+        pub is_head: bool,
+
+        /// Whether the node is a commit in the main branch.
+        pub is_main: bool,
+
+        /// Whether the node has been marked as obsolete.
+        pub is_obsolete: bool,
+
+        /// The description of the node, as produced by the node descriptors.
+        pub text: StyledString,
+    }
+
+    /// An edge between two nodes of the laid-out smartlog graph.
+    #[derive(Debug)]
+    pub struct GraphEdge {
+        /// The OID of the node that the edge starts from.
+        pub parent_oid: NonZeroOid,
+
+        /// The OID of the node that the edge leads to.
+        pub child_oid: NonZeroOid,
+
+        /// Whether the parent node is an actual parent commit of the child
+        /// node. If not, then there are commits between them which aren't
+        /// rendered, and the edge is drawn with an ellipsis.
+        pub is_direct: bool,
+    }
+
+    /// The smartlog graph laid out for rendering, independent of where it's
+    /// rendered to.
+    #[derive(Debug)]
+    pub struct SmartlogLayout {
+        /// The rows to draw, from top to bottom.
+        pub rows: Vec<GraphRow>,
+
+        /// The edges between the nodes on the rows.
+        pub edges: Vec<GraphEdge>,
+    }
+
+    impl SmartlogLayout {
+        /// Draw each row of the layout as a line of text, styled according to
+        /// the theme of the given glyphs.
+        pub fn to_styled_lines(&self, glyphs: &Glyphs) -> Vec<StyledString> {
+            let theme = &glyphs.theme;
+            self.rows
+                .iter()
+                .map(|GraphRow { prefix, node }| {
+                    let node = match node {
+                        Some(GraphRowNode {
+                            oid: _,
+                            cursor,
+                            is_head,
+                            is_main: _,
+                            is_obsolete: _,
+                            text,
+                        }) => {
+                            let mut line = StyledString::new();
+                            line.append_styled(cursor.as_str(), theme.graph);
+                            line.append_plain(" ");
+                            line.append(text.clone());
+                            if *is_head {
+                                set_style(line, theme.head)
+                            } else {
+                                line
+                            }
+                        }
+                        None => StyledString::new(),
+                    };
+                    StyledStringBuilder::new()
+                        .append_plain(prefix)
+                        .append(node)
+                        .build()
+                })
+                .collect()
+        }
+    }
+
+    #[instrument(skip(commit_descriptors, graph, edges))]
     fn get_child_output(
         glyphs: &Glyphs,
         graph: &SmartlogGraph,
@@ -335,35 +565,35 @@ mod render {
         head_oid: Option<NonZeroOid>,
         current_oid: NonZeroOid,
         last_child_line_char: Option<&str>,
-    ) -> eyre::Result<Vec<StyledString>> {
+        edges: &mut Vec<GraphEdge>,
+    ) -> eyre::Result<Vec<GraphRow>> {
         let current_node = &graph.nodes[&current_oid];
         let is_head = Some(current_oid) == head_oid;
 
         let text = render_node_descriptors(glyphs, &current_node.object, commit_descriptors)?;
         let cursor = match (current_node.is_main, current_node.is_obsolete, is_head) {
-            (false, false, false) => glyphs.commit_visible,
-            (false, false, true) => glyphs.commit_visible_head,
-            (false, true, false) => glyphs.commit_obsolete,
-            (false, true, true) => glyphs.commit_obsolete_head,
-            (true, false, false) => glyphs.commit_main,
-            (true, false, true) => glyphs.commit_main_head,
-            (true, true, false) => glyphs.commit_main_obsolete,
-            (true, true, true) => glyphs.commit_main_obsolete_head,
-        };
-
-        let first_line = {
-            let mut first_line = StyledString::new();
-            first_line.append_plain(cursor);
-            first_line.append_plain(" ");
-            first_line.append(text);
-            if is_head {
-                set_effect(first_line, Effect::Bold)
-            } else {
-                first_line
-            }
-        };
-
-        let mut lines = vec![first_line];
+            (false, false, false) => &glyphs.commit_visible,
+            (false, false, true) => &glyphs.commit_visible_head,
+            (false, true, false) => &glyphs.commit_obsolete,
+            (false, true, true) => &glyphs.commit_obsolete_head,
+            (true, false, false) => &glyphs.commit_main,
+            (true, false, true) => &glyphs.commit_main_head,
+            (true, true, false) => &glyphs.commit_main_obsolete,
+            (true, true, true) => &glyphs.commit_main_obsolete_head,
+        }
+        .to_string();
+
+        let mut rows = vec![GraphRow {
+            prefix: String::new(),
+            node: Some(GraphRowNode {
+                oid: current_oid,
+                cursor,
+                is_head,
+                is_main: current_node.is_main,
+                is_obsolete: current_node.is_obsolete,
+                text,
+            }),
+        }];
         let children: Vec<_> = current_node
             .children
             .iter()
@@ -375,23 +605,21 @@ mod render {
                 // Will be rendered by the parent.
                 continue;
             }
-
-            if child_idx == children.len() - 1 {
-                let line = match last_child_line_char {
-                    Some(_) => StyledString::plain(format!(
-                        "{}{}",
-                        glyphs.line_with_offshoot, glyphs.slash
-                    )),
-
-                    None => StyledString::plain(glyphs.line.to_string()),
-                };
-                lines.push(line)
+            edges.push(GraphEdge {
+                parent_oid: current_oid,
+                child_oid: *child_oid,
+                is_direct: true,
+            });
+
+            let prefix = if child_idx == children.len() - 1 {
+                match last_child_line_char {
+                    Some(_) => format!("{}{}", glyphs.line_with_offshoot, glyphs.slash),
+                    None => glyphs.line.to_string(),
+                }
             } else {
-                lines.push(StyledString::plain(format!(
-                    "{}{}",
-                    glyphs.line_with_offshoot, glyphs.slash
-                )))
-            }
+                format!("{}{}", glyphs.line_with_offshoot, glyphs.slash)
+            };
+            rows.push(GraphRow { prefix, node: None });
 
             let child_output = get_child_output(
                 glyphs,
@@ -401,29 +629,27 @@ mod render {
                 head_oid,
                 *child_oid,
                 None,
+                edges,
             )?;
-            for child_line in child_output {
-                let line = if child_idx == children.len() - 1 {
+            for GraphRow { prefix, node } in child_output {
+                let prefix = if child_idx == children.len() - 1 {
                     match last_child_line_char {
-                        Some(last_child_line_char) => StyledStringBuilder::new()
-                            .append_plain(format!("{} ", last_child_line_char))
-                            .append(child_line)
-                            .build(),
-                        None => child_line,
+                        Some(last_child_line_char) => {
+                            format!("{} {}", last_child_line_char, prefix)
+                        }
+                        None => prefix,
                     }
                 } else {
-                    StyledStringBuilder::new()
-                        .append_plain(format!("{} ", glyphs.line))
-                        .append(child_line)
-                        .build()
+                    format!("{} {}", glyphs.line, prefix)
                 };
-                lines.push(line)
+                rows.push(GraphRow { prefix, node })
             }
         }
-        Ok(lines)
+        Ok(rows)
     }
 
-    /// Render a pretty graph starting from the given root OIDs in the given graph.
+    /// Lay out a pretty graph starting from the given root OIDs in the given
+    /// graph.
     #[instrument(skip(commit_descriptors, graph))]
     fn get_output(
         glyphs: &Glyphs,
@@ -432,8 +658,9 @@ mod render {
         commit_descriptors: &mut [&mut dyn NodeDescriptor],
         head_oid: Option<NonZeroOid>,
         root_oids: &[NonZeroOid],
-    ) -> eyre::Result<Vec<StyledString>> {
-        let mut lines = Vec::new();
+    ) -> eyre::Result<SmartlogLayout> {
+        let mut rows = Vec::new();
+        let mut edges = Vec::new();
 
         // Determine if the provided OID has the provided parent OID as a parent.
         //
@@ -452,16 +679,33 @@ mod render {
                 .parents(CommitSet::from(*root_oid))?
                 .is_empty()?
             {
-                let line = if root_idx > 0 && has_real_parent(*root_oid, root_oids[root_idx - 1])? {
-                    StyledString::plain(glyphs.line.to_owned())
+                let prefix = if root_idx > 0 {
+                    let parent_oid = root_oids[root_idx - 1];
+                    let is_direct = has_real_parent(*root_oid, parent_oid)?;
+                    edges.push(GraphEdge {
+                        parent_oid,
+                        child_oid: *root_oid,
+                        is_direct,
+                    });
+                    if is_direct {
+                        glyphs.line
+                    } else {
+                        glyphs.vertical_ellipsis
+                    }
                 } else {
-                    StyledString::plain(glyphs.vertical_ellipsis.to_owned())
+                    glyphs.vertical_ellipsis
                 };
-                lines.push(line);
+                rows.push(GraphRow {
+                    prefix: prefix.to_owned(),
+                    node: None,
+                });
             } else if root_idx > 0 {
                 // Pathological case: multiple topologically-unrelated roots.
                 // Separate them with a newline.
-                lines.push(StyledString::new());
+                rows.push(GraphRow {
+                    prefix: String::new(),
+                    node: None,
+                });
             }
 
             let last_child_line_char = {
@@ -485,37 +729,247 @@ mod render {
                 head_oid,
                 *root_oid,
                 last_child_line_char,
+                &mut edges,
             )?;
-            lines.extend(child_output.into_iter());
+            rows.extend(child_output.into_iter());
         }
 
-        Ok(lines)
+        Ok(SmartlogLayout { rows, edges })
     }
 
-    /// Render the smartlog graph and write it to the provided stream.
+    /// Lay out the smartlog graph, so that it can be rendered by any
+    /// [`SmartlogSink`].
     #[instrument(skip(commit_descriptors, graph))]
-    pub fn render_graph(
+    pub fn layout_graph(
         effects: &Effects,
         repo: &Repo,
         dag: &Dag,
         graph: &SmartlogGraph,
         head_oid: Option<NonZeroOid>,
         commit_descriptors: &mut [&mut dyn NodeDescriptor],
-    ) -> eyre::Result<Vec<StyledString>> {
+    ) -> eyre::Result<SmartlogLayout> {
         let root_oids = split_commit_graph_by_roots(effects, repo, dag, graph);
-        let lines = get_output(
+        get_output(
             effects.get_glyphs(),
             dag,
             graph,
             commit_descriptors,
             head_oid,
             &root_oids,
-        )?;
-        Ok(lines)
+        )
+    }
+
+    /// Render the smartlog graph as lines of text.
+    #[instrument(skip(commit_descriptors, graph))]
+    pub fn render_graph(
+        effects: &Effects,
+        repo: &Repo,
+        dag: &Dag,
+        graph: &SmartlogGraph,
+        head_oid: Option<NonZeroOid>,
+        commit_descriptors: &mut [&mut dyn NodeDescriptor],
+    ) -> eyre::Result<Vec<StyledString>> {
+        let layout = layout_graph(effects, repo, dag, graph, head_oid, commit_descriptors)?;
+        Ok(layout.to_styled_lines(effects.get_glyphs()))
+    }
+
+    /// A destination format for a laid-out smartlog graph.
+    pub trait SmartlogSink {
+        /// Render the laid-out graph as a string.
+        fn render(&self, glyphs: &Glyphs, layout: &SmartlogLayout) -> eyre::Result<String>;
+    }
+
+    /// Renders the graph as text, as displayed in the terminal. Styles are
+    /// rendered as ANSI escape codes if the glyphs call for them.
+    pub struct TextSink;
+
+    impl SmartlogSink for TextSink {
+        fn render(&self, glyphs: &Glyphs, layout: &SmartlogLayout) -> eyre::Result<String> {
+            let mut output = String::new();
+            for line in layout.to_styled_lines(glyphs) {
+                writeln!(output, "{}", printable_styled_string(glyphs, line)?)?;
+            }
+            Ok(output)
+        }
+    }
+
+    /// Renders the graph as an HTML `<pre>` block, with styles rendered as
+    /// inline CSS, for embedding into documentation.
+    pub struct HtmlSink;
+
+    impl HtmlSink {
+        fn escape(text: &str) -> String {
+            text.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        }
+
+        fn color_to_css(color: Color) -> Option<String> {
+            let css_color = match color {
+                Color::TerminalDefault => return None,
+                Color::Dark(color) => match color {
+                    BaseColor::Black => "black",
+                    BaseColor::Red => "darkred",
+                    BaseColor::Green => "green",
+                    BaseColor::Yellow => "olive",
+                    BaseColor::Blue => "navy",
+                    BaseColor::Magenta => "purple",
+                    BaseColor::Cyan => "teal",
+                    BaseColor::White => "silver",
+                },
+                Color::Light(color) => match color {
+                    BaseColor::Black => "gray",
+                    BaseColor::Red => "red",
+                    BaseColor::Green => "lime",
+                    BaseColor::Yellow => "yellow",
+                    BaseColor::Blue => "blue",
+                    BaseColor::Magenta => "fuchsia",
+                    BaseColor::Cyan => "aqua",
+                    BaseColor::White => "white",
+                },
+                Color::Rgb(r, g, b) => return Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+                Color::RgbLowRes(..) => return None,
+            };
+            Some(css_color.to_string())
+        }
+
+        fn style_to_css(style: Style) -> String {
+            let Style { effects, color } = style;
+            let mut declarations = Vec::new();
+            if let ColorType::Color(color) = color.front {
+                if let Some(color) = Self::color_to_css(color) {
+                    declarations.push(format!("color: {}", color));
+                }
+            }
+            for effect in effects.iter() {
+                match effect {
+                    Effect::Simple | Effect::Reverse | Effect::Blink => {}
+                    Effect::Bold => declarations.push("font-weight: bold".to_string()),
+                    Effect::Dim => declarations.push("opacity: 0.6".to_string()),
+                    Effect::Italic => declarations.push("font-style: italic".to_string()),
+                    Effect::Strikethrough => {
+                        declarations.push("text-decoration: line-through".to_string())
+                    }
+                    Effect::Underline => {
+                        declarations.push("text-decoration: underline".to_string())
+                    }
+                }
+            }
+            declarations.join("; ")
+        }
+    }
+
+    impl SmartlogSink for HtmlSink {
+        fn render(&self, glyphs: &Glyphs, layout: &SmartlogLayout) -> eyre::Result<String> {
+            let mut output = String::new();
+            writeln!(output, r#"<pre class="git-branchless-smartlog">"#)?;
+            for line in layout.to_styled_lines(glyphs) {
+                // Adjacent spans often have the same style, such as in the
+                // highlighted `HEAD` line, so merge them to keep the output
+                // readable.
+                let mut styled_runs: Vec<(String, String)> = Vec::new();
+                for span in line.spans() {
+                    let Span {
+                        content,
+                        attr,
+                        width: _,
+                    } = span;
+                    let css = Self::style_to_css(*attr);
+                    match styled_runs.last_mut() {
+                        Some((last_css, last_content)) if *last_css == css => {
+                            last_content.push_str(content)
+                        }
+                        _ => styled_runs.push((css, content.to_string())),
+                    }
+                }
+                for (css, content) in styled_runs {
+                    if css.is_empty() {
+                        write!(output, "{}", Self::escape(&content))?;
+                    } else {
+                        write!(
+                            output,
+                            r#"<span style="{}">{}</span>"#,
+                            css,
+                            Self::escape(&content)
+                        )?;
+                    }
+                }
+                writeln!(output)?;
+            }
+            writeln!(output, "</pre>")?;
+            Ok(output)
+        }
+    }
+
+    /// Renders the graph in the DOT language, to be drawn with Graphviz.
+    pub struct DotSink;
+
+    impl DotSink {
+        fn quote(text: &str) -> String {
+            format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+    }
+
+    impl SmartlogSink for DotSink {
+        fn render(&self, _glyphs: &Glyphs, layout: &SmartlogLayout) -> eyre::Result<String> {
+            let mut output = String::new();
+            writeln!(output, "digraph smartlog {{")?;
+            writeln!(output, r#"  node [shape=box, fontname="monospace"];"#)?;
+            for GraphRow { prefix: _, node } in &layout.rows {
+                let GraphRowNode {
+                    oid,
+                    cursor: _,
+                    is_head,
+                    is_main,
+                    is_obsolete,
+                    text,
+                } = match node {
+                    Some(node) => node,
+                    None => continue,
+                };
+                let label: String = text.spans().map(|span| span.content).collect();
+                let mut attributes = vec![format!("label={}", Self::quote(&label))];
+                if *is_main {
+                    attributes.push("shape=ellipse".to_string());
+                }
+                if *is_obsolete {
+                    attributes.push("style=dashed".to_string());
+                }
+                if *is_head {
+                    attributes.push("penwidth=2".to_string());
+                }
+                writeln!(
+                    output,
+                    "  {} [{}];",
+                    Self::quote(&oid.to_string()),
+                    attributes.join(", ")
+                )?;
+            }
+            for GraphEdge {
+                parent_oid,
+                child_oid,
+                is_direct,
+            } in &layout.edges
+            {
+                write!(
+                    output,
+                    "  {} -> {}",
+                    Self::quote(&parent_oid.to_string()),
+                    Self::quote(&child_oid.to_string())
+                )?;
+                if !is_direct {
+                    write!(output, " [style=dotted]")?;
+                }
+                writeln!(output, ";")?;
+            }
+            writeln!(output, "}}")?;
+            Ok(output)
+        }
     }
 
     /// Options for rendering the smartlog.
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Eq)]
     pub struct SmartlogOptions {
         /// Whether to also show commits in the smartlog which would normally not be
         /// visible.
@@ -526,9 +980,27 @@ mod render {
         /// as an offset from the current event.
         pub event_id: Option<isize>,
 
+        /// The time at which to show the smartlog. If provided, renders the
+        /// smartlog as of the last event which happened at or before this time.
+        pub at: Option<SystemTime>,
+
         /// The commits to render. These commits and their ancestors up to the
         /// main branch will be rendered.
         pub revset: Revset,
+
+        /// Whether to also render the commits checked out in other worktrees,
+        /// annotated with the names of those worktrees.
+        pub all_worktrees: bool,
+
+        /// Whether to collapse each stack of draft commits into a single line.
+        pub collapsed: bool,
+
+        /// When collapsing stacks, the commits whose stacks should still be
+        /// rendered in full.
+        pub expand: Vec<Revset>,
+
+        /// The format to render the smartlog in.
+        pub format: SmartlogFormat,
     }
 
     impl Default for SmartlogOptions {
@@ -536,23 +1008,613 @@ mod render {
             Self {
                 show_hidden_commits: Default::default(),
                 event_id: Default::default(),
+                at: Default::default(),
                 revset: Revset("draft()".to_string()),
+                all_worktrees: Default::default(),
+                collapsed: Default::default(),
+                expand: Default::default(),
+                format: SmartlogFormat::Text,
             }
         }
     }
 }
 
+/// Describe the point in the event log that a time-travelling smartlog is
+/// being rendered at, so that it can't be mistaken for the current state of
+/// the repository.
+fn print_time_travel_header(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+) -> eyre::Result<()> {
+    let (event_id, event) = match event_replayer.get_event_before_cursor(event_cursor) {
+        Some(event) => event,
+        None => {
+            writeln!(
+                effects.get_output_stream(),
+                "Showing the smartlog as it was before any events were recorded."
+            )?;
+            return Ok(());
+        }
+    };
+
+    let event_tx_id = event.get_event_tx_id();
+    let now = SystemTime::now();
+    let relative_time = if RelativeTimeDescriptor::new(repo, now)?.is_enabled() {
+        format!(
+            ", {} ago",
+            RelativeTimeDescriptor::describe_time_delta(now, event.get_timestamp())?
+        )
+    } else {
+        String::new()
+    };
+    writeln!(
+        effects.get_output_stream(),
+        "Showing the smartlog as it was after event {} (transaction {}: {}{}).",
+        event_id,
+        event_tx_id.to_string(),
+        event_log_db.get_transaction_message(event_tx_id)?,
+        relative_time,
+    )?;
+    Ok(())
+}
+
 /// Display a nice graph of commits you've recently worked on.
 #[instrument]
 pub fn smartlog(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     options: &SmartlogOptions,
+) -> eyre::Result<ExitCode> {
+    if options.event_id.is_none() && options.at.is_none() {
+        let repo = Repo::from_dir(&git_run_info.working_directory)?;
+        if let Err(err) = print_changes_banner(effects, &repo) {
+            tracing::warn!(?err, "Failed to summarize changes since the last smartlog");
+        }
+    }
+    if *options == SmartlogOptions::default() {
+        let repo = Repo::from_dir(&git_run_info.working_directory)?;
+        if let Some(output) = daemon::query_smartlog(effects, &repo) {
+            write!(
+                effects.get_output_stream(),
+                "{}",
+                String::from_utf8_lossy(&output)
+            )?;
+            return Ok(ExitCode(0));
+        }
+        if get_smartlog_cache(&repo)? {
+            return render_smartlog_cached(effects, git_run_info, &repo);
+        }
+    }
+    render_smartlog(effects, git_run_info, options)
+}
+
+/// The smartlog includes relative commit times, so cached output has to be
+/// re-rendered periodically even if the repository hasn't changed.
+pub(crate) const MAX_CACHE_AGE: Duration = Duration::from_secs(60);
+
+/// The parts of the repository state which affect the rendered smartlog.
+/// If any of them change, then the cached output has to be re-rendered.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct RepoStateFingerprint {
+    references_snapshot: RepoReferencesSnapshot,
+    head_reference_name: Option<ReferenceName>,
+    num_events: usize,
+    metadata_modified: Option<SystemTime>,
+    config_entries: Vec<(String, String)>,
+    dag_modified: Option<SystemTime>,
+}
+
+impl RepoStateFingerprint {
+    pub(crate) fn new(repo: &Repo) -> eyre::Result<Self> {
+        let references_snapshot = repo.get_references_snapshot()?;
+        let head_reference_name = repo.get_head_info()?.reference_name;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let num_events = event_log_db.count_events()?;
+        let metadata_modified = CommitMetadataDb::new(&conn)?.get_last_modified()?;
+        // The resolved entries are used rather than the repository's config
+        // file, so that changes to the global or system config (such as the
+        // theme or glyphs) are noticed too.
+        let config_entries = repo
+            .get_readonly_config()?
+            .into_config()
+            .get_entries(".*")?;
+        // The DAG's metadata file is rewritten whenever the DAG is updated, so
+        // checking it ensures that a repaired (or broken) DAG is noticed.
+        let dag_modified = std::fs::metadata(repo.get_dag_dir().join("multimeta"))
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        Ok(Self {
+            references_snapshot,
+            head_reference_name,
+            num_events,
+            metadata_modified,
+            config_entries,
+            dag_modified,
+        })
+    }
+
+    /// Summarize the fingerprint as a string, so that it can be stored on
+    /// disk. The version of git-branchless is included, since the rendered
+    /// output may differ between versions.
+    pub(crate) fn digest(&self) -> String {
+        let Self {
+            references_snapshot:
+                RepoReferencesSnapshot {
+                    head_oid,
+                    main_branch_oid,
+                    additional_main_branch_oids,
+                    public_remote_branch_oids,
+                    branch_oid_to_names,
+                },
+            head_reference_name,
+            num_events,
+            metadata_modified,
+            config_entries,
+            dag_modified,
+        } = self;
+
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        head_oid.hash(&mut hasher);
+        main_branch_oid.hash(&mut hasher);
+        additional_main_branch_oids
+            .iter()
+            .sorted()
+            .collect_vec()
+            .hash(&mut hasher);
+        public_remote_branch_oids
+            .iter()
+            .sorted()
+            .collect_vec()
+            .hash(&mut hasher);
+        branch_oid_to_names
+            .iter()
+            .map(|(oid, names)| (oid, names.iter().sorted().collect_vec()))
+            .sorted()
+            .collect_vec()
+            .hash(&mut hasher);
+        head_reference_name.hash(&mut hasher);
+        num_events.hash(&mut hasher);
+        metadata_modified.hash(&mut hasher);
+        config_entries.hash(&mut hasher);
+        dag_modified.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+/// Get the key under which the smartlog rendered with the given glyphs is
+/// cached.
+///
+/// The glyphs and their theme (for colored output) may be configured outside of
+/// the repository, so they're part of the key rather than the fingerprint.
+pub(crate) fn get_glyphs_cache_key(glyphs: &Glyphs) -> String {
+    let mut hasher = DefaultHasher::new();
+    if glyphs.should_write_ansi_escape_codes {
+        glyphs.hash(&mut hasher);
+    } else {
+        // The theme isn't used for uncolored output.
+        glyphs
+            .clone()
+            .with_theme(Theme::default())
+            .hash(&mut hasher);
+    }
+    let kind = if glyphs.should_write_ansi_escape_codes {
+        "pretty"
+    } else {
+        "text"
+    };
+    format!("{}-{:016x}", kind, hasher.finish())
+}
+
+/// Print the cached smartlog if the repository hasn't changed since it was
+/// rendered. Otherwise, render it and cache the output for next time.
+///
+/// On a cache hit, neither the event log is replayed nor the commit graph
+/// synced, so repeated invocations are nearly instant.
+#[instrument]
+fn render_smartlog_cached(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+) -> eyre::Result<ExitCode> {
+    let glyphs = effects.get_glyphs();
+    let glyphs_key = get_glyphs_cache_key(glyphs);
+    let fingerprint = RepoStateFingerprint::new(repo)?;
+    let conn = repo.get_db_conn()?;
+    let smartlog_cache_db = SmartlogCacheDb::new(&conn)?;
+
+    let now = SystemTime::now();
+    if let Some(cached) = smartlog_cache_db.get(&glyphs_key)? {
+        let is_fresh = match now.duration_since(cached.rendered_at) {
+            Ok(age) => age < MAX_CACHE_AGE,
+            Err(_) => false,
+        };
+        if is_fresh && cached.fingerprint == fingerprint.digest() {
+            write!(
+                effects.get_output_stream(),
+                "{}",
+                String::from_utf8_lossy(&cached.stdout)
+            )?;
+            write!(
+                effects.get_error_stream(),
+                "{}",
+                String::from_utf8_lossy(&cached.stderr)
+            )?;
+            return Ok(ExitCode(0));
+        }
+    }
+
+    let stdout: Arc<Mutex<Vec<u8>>> = Default::default();
+    let stderr: Arc<Mutex<Vec<u8>>> = Default::default();
+    let exit_code = {
+        let buffer_effects = Effects::new_from_buffer(glyphs.clone(), &stdout, &stderr);
+        render_smartlog(&buffer_effects, git_run_info, &SmartlogOptions::default())?
+    };
+    let stdout = stdout.lock().unwrap().clone();
+    let stderr = stderr.lock().unwrap().clone();
+    write!(
+        effects.get_output_stream(),
+        "{}",
+        String::from_utf8_lossy(&stdout)
+    )?;
+    write!(
+        effects.get_error_stream(),
+        "{}",
+        String::from_utf8_lossy(&stderr)
+    )?;
+
+    // Rendering syncs the commit graph, which is expected to change its
+    // modification time. If anything else changed, then the output may not
+    // correspond to either fingerprint, so it's not cached.
+    let rendered_fingerprint = RepoStateFingerprint::new(repo)?;
+    let is_unchanged = RepoStateFingerprint {
+        dag_modified: rendered_fingerprint.dag_modified,
+        ..fingerprint
+    } == rendered_fingerprint;
+    if exit_code.is_success() && is_unchanged {
+        smartlog_cache_db.set(
+            &glyphs_key,
+            &CachedSmartlog {
+                fingerprint: rendered_fingerprint.digest(),
+                rendered_at: now,
+                stdout,
+                stderr,
+            },
+        )?;
+    }
+    Ok(exit_code)
+}
+
+/// Read the `rowid` of the last event and the OID of the main branch as of the
+/// last time the smartlog was rendered, if it has been rendered before.
+fn read_last_smartlog(repo: &Repo) -> eyre::Result<Option<(i64, NonZeroOid)>> {
+    let path = repo.get_last_smartlog_path();
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {:?}", path)),
+    };
+    let mut fields = contents.split_whitespace();
+    match (fields.next(), fields.next()) {
+        (Some(last_event_rowid), Some(main_branch_oid)) => {
+            Ok(Some((last_event_rowid.parse()?, main_branch_oid.parse()?)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Print a one-line summary of what happened to the repository since the last
+/// time the smartlog was rendered, and record the current state for next time.
+///
+/// The state is kept in a file rather than in the database, and is only
+/// written if it changed, so that rendering the smartlog doesn't write to the
+/// database.
+fn print_changes_banner(effects: &Effects, repo: &Repo) -> eyre::Result<()> {
+    if !get_smartlog_changes_banner(repo)? {
+        return Ok(());
+    }
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let last_event_rowid = event_log_db.get_last_event_rowid()?;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let last_smartlog = read_last_smartlog(repo)?;
+    if last_smartlog != Some((last_event_rowid, main_branch_oid)) {
+        let path = repo.get_last_smartlog_path();
+        std::fs::write(&path, format!("{} {}\n", last_event_rowid, main_branch_oid))
+            .wrap_err_with(|| format!("Writing {:?}", path))?;
+    }
+
+    let (previous_event_rowid, previous_main_branch_oid) = match last_smartlog {
+        Some(last_smartlog) => last_smartlog,
+        None => return Ok(()),
+    };
+    let events = event_log_db.get_events_after_rowid(previous_event_rowid)?;
+
+    let mut summary = Vec::new();
+    if previous_main_branch_oid != main_branch_oid {
+        // The old main branch commit may have been garbage-collected, in which
+        // case we can't say how many commits landed.
+        if let Ok(num_landed) =
+            repo.count_commits_between(previous_main_branch_oid, main_branch_oid)
+        {
+            if num_landed > 0 {
+                summary.push(format!(
+                    "{} landed",
+                    Pluralize {
+                        determiner: None,
+                        amount: num_landed,
+                        unit: ("commit", "commits"),
+                    }
+                ));
+            }
+        }
+    }
+
+    // Amending a commit produces both a commit event and a rewrite event for
+    // the new commit, but it shouldn't be counted as a new commit.
+    let rewritten_oids: HashSet<NonZeroOid> = events
+        .iter()
+        .filter_map(|(_rowid, event)| match event {
+            Event::RewriteEvent {
+                new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                ..
+            } => Some(*new_commit_oid),
+            _ => None,
+        })
+        .collect();
+
+    let mut num_rewritten_by_tx: Vec<(EventTransactionId, usize)> = Vec::new();
+    let mut num_new_commits = 0;
+    let mut num_hidden_commits = 0;
+    for (_rowid, event) in events {
+        match event {
+            Event::RewriteEvent {
+                event_tx_id,
+                new_commit_oid: MaybeZeroOid::NonZero(_),
+                ..
+            } => match num_rewritten_by_tx
+                .iter_mut()
+                .find(|(tx_id, _)| *tx_id == event_tx_id)
+            {
+                Some((_, num_rewritten)) => *num_rewritten += 1,
+                None => num_rewritten_by_tx.push((event_tx_id, 1)),
+            },
+            Event::CommitEvent { commit_oid, .. } => {
+                // Commits which were made on the main branch have already been
+                // counted as landed.
+                let is_on_main_branch = matches!(
+                    repo.find_merge_base(commit_oid, main_branch_oid),
+                    Ok(Some(merge_base_oid)) if merge_base_oid == commit_oid
+                );
+                if !rewritten_oids.contains(&commit_oid) && !is_on_main_branch {
+                    num_new_commits += 1;
+                }
+            }
+            Event::ObsoleteEvent { .. } => num_hidden_commits += 1,
+            Event::RewriteEvent { .. }
+            | Event::RefUpdateEvent { .. }
+            | Event::UnobsoleteEvent { .. }
+            | Event::WorkingCopySnapshot { .. } => {}
+        }
+    }
+
+    // Group rewrites by the command which performed them, in the order in
+    // which the commands were first run.
+    let mut num_rewritten_by_description: Vec<(String, usize)> = Vec::new();
+    for (event_tx_id, num_rewritten) in num_rewritten_by_tx {
+        let description = event_log_db.get_transaction_description(event_tx_id)?;
+        match num_rewritten_by_description
+            .iter_mut()
+            .find(|(other_description, _)| *other_description == description)
+        {
+            Some((_, total)) => *total += num_rewritten,
+            None => num_rewritten_by_description.push((description, num_rewritten)),
+        }
+    }
+    for (description, num_rewritten) in num_rewritten_by_description {
+        summary.push(format!(
+            "{} rewritten by {}",
+            Pluralize {
+                determiner: None,
+                amount: num_rewritten,
+                unit: ("commit", "commits"),
+            },
+            description
+        ));
+    }
+
+    if num_new_commits > 0 {
+        summary.push(format!(
+            "{}",
+            Pluralize {
+                determiner: None,
+                amount: num_new_commits,
+                unit: ("new commit", "new commits"),
+            }
+        ));
+    }
+    if num_hidden_commits > 0 {
+        summary.push(format!(
+            "{} hidden",
+            Pluralize {
+                determiner: None,
+                amount: num_hidden_commits,
+                unit: ("commit", "commits"),
+            }
+        ));
+    }
+
+    // When the smartlog is rendered at the end of another command, such as
+    // after `git move` checks out the rewritten commits, the summary would
+    // just repeat what that command did.
+    let is_nested_command = std::env::var_os(BRANCHLESS_TRANSACTION_ID_ENV_VAR).is_some();
+    if !summary.is_empty() && !is_nested_command {
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: since your last smartlog: {}",
+            summary.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+/// Render the smartlog as it would look if the given commits were rewritten
+/// and hidden, without changing the repository, for use with `--dry-run`.
+///
+/// Branches which point to rewritten commits are rendered on the rewritten
+/// commits. Branches which point to hidden commits are removed if
+/// `delete_branches` is set.
+#[instrument]
+pub fn render_dry_run_smartlog(
+    effects: &Effects,
+    repo: &Repo,
+    rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
+    hidden_oids: &HashSet<NonZeroOid>,
+    delete_branches: bool,
+) -> eyre::Result<()> {
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let mut event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+
+    // Replay the events which the operation would have recorded, but don't
+    // add them to the event log. They belong to no transaction, since making
+    // one would write to the event log.
+    let now = SystemTime::now();
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let event_tx_id = EventTransactionId::DRY_RUN;
+    for (old_oid, new_oid) in rewritten_oids {
+        event_replayer.process_event(&Event::RewriteEvent {
+            timestamp,
+            event_tx_id,
+            old_commit_oid: MaybeZeroOid::NonZero(*old_oid),
+            new_commit_oid: *new_oid,
+        });
+    }
+    for hidden_oid in hidden_oids {
+        event_replayer.process_event(&Event::ObsoleteEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: *hidden_oid,
+        });
+    }
+    let event_cursor = event_replayer.make_default_cursor();
+
+    let rewrite_oid = |oid: NonZeroOid| match rewritten_oids.get(&oid) {
+        Some(MaybeZeroOid::NonZero(new_oid)) => Some(*new_oid),
+        Some(MaybeZeroOid::Zero) => None,
+        None => Some(oid),
+    };
+    let references_snapshot = {
+        let RepoReferencesSnapshot {
+            head_oid,
+            main_branch_oid,
+            additional_main_branch_oids,
+            public_remote_branch_oids,
+            branch_oid_to_names,
+        } = repo.get_references_snapshot()?;
+        let mut new_branch_oid_to_names: HashMap<NonZeroOid, HashSet<ReferenceName>> =
+            HashMap::new();
+        for (oid, names) in branch_oid_to_names {
+            if delete_branches && hidden_oids.contains(&oid) {
+                continue;
+            }
+            if let Some(new_oid) = rewrite_oid(oid) {
+                new_branch_oid_to_names
+                    .entry(new_oid)
+                    .or_default()
+                    .extend(names);
+            }
+        }
+        RepoReferencesSnapshot {
+            head_oid: head_oid.map(|head_oid| rewrite_oid(head_oid).unwrap_or(head_oid)),
+            main_branch_oid: rewrite_oid(main_branch_oid).unwrap_or(main_branch_oid),
+            additional_main_branch_oids: additional_main_branch_oids
+                .into_iter()
+                .map(|oid| rewrite_oid(oid).unwrap_or(oid))
+                .collect(),
+            public_remote_branch_oids,
+            branch_oid_to_names: new_branch_oid_to_names,
+        }
+    };
+    let head_info = {
+        let ResolvedReferenceInfo {
+            oid: _,
+            reference_name,
+        } = repo.get_head_info()?;
+        ResolvedReferenceInfo {
+            oid: references_snapshot.head_oid,
+            reference_name,
+        }
+    };
+
+    // The rewritten commits only exist in the object database, so add them to
+    // the DAG without writing it to disk.
+    let mut dag = Dag::open_without_syncing(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    dag.sync_without_flushing(repo)?;
+    let hidden_commits: CommitSet = hidden_oids.iter().copied().collect();
+    let observed_commits = dag
+        .observed_commits
+        .difference(&dag.obsolete_commits)
+        .union(&hidden_commits);
+    let graph = make_hypothetical_smartlog_graph(effects, repo, &dag, &observed_commits)?;
+    let lines = render_graph(
+        effects,
+        repo,
+        &dag,
+        &graph,
+        references_snapshot.head_oid,
+        &mut [
+            &mut CommitOidDescriptor::new(true)?,
+            &mut RelativeTimeDescriptor::new(repo, SystemTime::now())?,
+            &mut DryRunDescriptor::new(rewritten_oids, hidden_oids.clone())?,
+            &mut BranchesDescriptor::new(
+                repo,
+                &head_info,
+                &references_snapshot,
+                &Redactor::Disabled,
+            )?,
+            &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
+        ],
+    )?;
+    for line in lines {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            printable_styled_string(effects.get_glyphs(), line)?
+        )?;
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "(This was a dry-run; run without --dry-run to apply changes.)"
+    )?;
+    Ok(())
+}
+
+/// Render the smartlog without consulting `git branchless daemon`.
+pub fn render_smartlog(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    options: &SmartlogOptions,
 ) -> eyre::Result<ExitCode> {
     let SmartlogOptions {
         show_hidden_commits,
         event_id,
+        at,
         revset,
+        all_worktrees,
+        collapsed,
+        expand,
+        format,
     } = options;
 
     let repo = Repo::from_dir(&git_run_info.working_directory)?;
@@ -562,13 +1624,26 @@ pub fn smartlog(
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let (references_snapshot, event_cursor) = {
         let default_cursor = event_replayer.make_default_cursor();
-        match event_id {
+        let event_cursor = match (event_id, at) {
+            (None, None) => None,
+            (Some(event_id), _) => Some(match event_id.cmp(&0) {
+                Ordering::Less => event_replayer.advance_cursor(default_cursor, *event_id),
+                Ordering::Equal | Ordering::Greater => event_replayer.make_cursor(*event_id),
+            }),
+            (None, Some(at)) => Some(event_replayer.make_cursor_at_time(*at)),
+        };
+        match event_cursor {
             None => (repo.get_references_snapshot()?, default_cursor),
-            Some(event_id) => {
-                let event_cursor = match event_id.cmp(&0) {
-                    Ordering::Less => event_replayer.advance_cursor(default_cursor, *event_id),
-                    Ordering::Equal | Ordering::Greater => event_replayer.make_cursor(*event_id),
-                };
+            Some(event_cursor) => {
+                if *format == SmartlogFormat::Text {
+                    print_time_travel_header(
+                        effects,
+                        &repo,
+                        &event_log_db,
+                        &event_replayer,
+                        event_cursor,
+                    )?;
+                }
                 let references_snapshot =
                     event_replayer.get_references_snapshot(&repo, event_cursor)?;
                 (references_snapshot, event_cursor)
@@ -583,6 +1658,23 @@ pub fn smartlog(
         &references_snapshot,
     )?;
 
+    let worktrees = if *all_worktrees {
+        repo.get_worktrees()?
+    } else {
+        Vec::new()
+    };
+    let worktree_commits: CommitSet = worktrees
+        .iter()
+        .filter_map(|worktree| worktree.head_oid)
+        .collect();
+    dag.sync_from_oids(effects, &repo, CommitSet::empty(), worktree_commits.clone())?;
+    dag.worktree_commits = worktree_commits;
+    let commit_metadata = {
+        let rewrite_map = RewriteMap::from_event_replayer(&event_replayer, event_cursor);
+        CommitMetadataDb::new(&conn)?.get_all(&rewrite_map)?
+    };
+    let topics = infer_stack_topics(&repo, &dag, &references_snapshot, &commit_metadata)?;
+
     let observed_commits = {
         // For the purpose of resolving the revset expression, we may
         // temporarily clear the DAG's obsolete commit set. However, when we
@@ -593,27 +1685,27 @@ pub fn smartlog(
         if *show_hidden_commits {
             swap(&mut dag.obsolete_commits, &mut old_obsolete_commits);
         }
-        let observed_commits = match resolve_commits(effects, &repo, &mut dag, vec![revset.clone()])
-        {
-            Ok(result) => match result.as_slice() {
-                [commit_set] => commit_set.clone(),
-                other => panic!(
-                    "Expected exactly 1 result from resolve commits, got: {:?}",
-                    other
-                ),
-            },
-            Err(err) => {
-                err.describe(effects)?;
-                return Ok(ExitCode(1));
-            }
-        };
+        let observed_commits =
+            match resolve_commits(effects, git_run_info, &repo, &mut dag, vec![revset.clone()]) {
+                Ok(result) => match result.as_slice() {
+                    [commit_set] => commit_set.clone(),
+                    other => panic!(
+                        "Expected exactly 1 result from resolve commits, got: {:?}",
+                        other
+                    ),
+                },
+                Err(err) => {
+                    err.describe(effects)?;
+                    return Ok(ExitCode(1));
+                }
+            };
         if *show_hidden_commits {
             swap(&mut dag.obsolete_commits, &mut old_obsolete_commits);
         }
         observed_commits
     };
 
-    let graph = make_smartlog_graph(
+    let mut graph = make_smartlog_graph(
         effects,
         &repo,
         &dag,
@@ -623,7 +1715,49 @@ pub fn smartlog(
         !show_hidden_commits,
     )?;
 
-    let lines = render_graph(
+    let num_collapsed_commits = if *collapsed {
+        let expanded_commits =
+            match resolve_commits(effects, git_run_info, &repo, &mut dag, expand.clone()) {
+                Ok(commit_sets) => union_all(&commit_sets),
+                Err(err) => {
+                    err.describe(effects)?;
+                    return Ok(ExitCode(1));
+                }
+            };
+        let expanded_commits = match references_snapshot.head_oid {
+            Some(head_oid) => expanded_commits.union(&CommitSet::from(head_oid)),
+            None => expanded_commits,
+        };
+        collapse_stacks(&mut graph, &expanded_commits)?
+    } else {
+        HashMap::new()
+    };
+
+    let ci_statuses = if get_commit_descriptors_ci_status(&repo)? {
+        let draft_oids: Vec<NonZeroOid> = graph
+            .nodes
+            .iter()
+            .filter(|(_oid, node)| !node.is_main)
+            .map(|(oid, _node)| *oid)
+            .collect();
+        get_ci_statuses(
+            &repo,
+            &CiStatusDb::new(&conn)?,
+            SystemTime::now(),
+            &draft_oids,
+        )?
+    } else {
+        HashMap::new()
+    };
+
+    let signature_statuses = if get_commit_descriptors_signature(&repo)? {
+        let oids: Vec<NonZeroOid> = graph.nodes.keys().copied().collect();
+        get_signature_statuses(git_run_info, &repo, &SignatureStatusDb::new(&conn)?, &oids)?
+    } else {
+        HashMap::new()
+    };
+
+    let layout = layout_graph(
         effects,
         &repo,
         &dag,
@@ -632,29 +1766,39 @@ pub fn smartlog(
         &mut [
             &mut CommitOidDescriptor::new(true)?,
             &mut RelativeTimeDescriptor::new(&repo, SystemTime::now())?,
-            &mut ObsolescenceExplanationDescriptor::new(
-                &event_replayer,
-                event_replayer.make_default_cursor(),
-            )?,
+            &mut ObsolescenceExplanationDescriptor::new(&event_replayer, event_cursor)?,
             &mut BranchesDescriptor::new(
                 &repo,
                 &head_info,
                 &references_snapshot,
                 &Redactor::Disabled,
             )?,
+            &mut WorktreesDescriptor::new(&worktrees)?,
+            &mut StackTopicDescriptor::new(&topics)?,
+            &mut CommitMetadataDescriptor::new(&repo, commit_metadata)?,
+            &mut CollapsedStackDescriptor::new(num_collapsed_commits)?,
+            &mut CiStatusDescriptor::new(&repo, ci_statuses)?,
+            &mut SignatureStatusDescriptor::new(&repo, signature_statuses)?,
             &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
             &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
         ],
     )?;
-    for line in lines {
-        writeln!(
-            effects.get_output_stream(),
-            "{}",
-            printable_styled_string(effects.get_glyphs(), line)?
-        )?;
-    }
+    let sink: &dyn SmartlogSink = match format {
+        SmartlogFormat::Text => &TextSink,
+        SmartlogFormat::Html => &HtmlSink,
+        SmartlogFormat::Dot => &DotSink,
+    };
+    write!(
+        effects.get_output_stream(),
+        "{}",
+        sink.render(effects.get_glyphs(), &layout)?
+    )?;
 
-    if !show_hidden_commits && get_hint_enabled(&repo, Hint::SmartlogFixAbandoned)? {
+    // The hints would make the output invalid in the other formats.
+    if *format == SmartlogFormat::Text
+        && !show_hidden_commits
+        && get_hint_enabled(&repo, Hint::SmartlogFixAbandoned)?
+    {
         let commits_with_abandoned_children: CommitSet = graph
             .nodes
             .iter()
@@ -674,7 +1818,7 @@ pub fn smartlog(
             writeln!(
                 effects.get_output_stream(),
                 "{}: there {} in your commit graph",
-                style("hint").blue().bold(),
+                effects.get_glyphs().render_hint_label()?,
                 Pluralize {
                     determiner: Some(("is", "are")),
                     amount: num_abandoned_children,
@@ -684,7 +1828,7 @@ pub fn smartlog(
             writeln!(
                 effects.get_output_stream(),
                 "{}: to fix this, run: git restack",
-                style("hint").blue().bold(),
+                effects.get_glyphs().render_hint_label()?,
             )?;
             print_hint_suppression_notice(effects, Hint::SmartlogFixAbandoned)?;
         }
@@ -5,7 +5,6 @@ use std::convert::TryInto;
 use std::fmt::Write;
 use std::time::SystemTime;
 
-use cursive::theme::BaseColor;
 use cursive::utils::markup::StyledString;
 use eyre::Context;
 use lib::core::check_out::{create_snapshot, restore_snapshot};
@@ -49,7 +48,7 @@ pub fn create(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<Exit
                 effects.get_glyphs(),
                 StyledString::styled(
                     "Failed to clean up working copy state".to_string(),
-                    BaseColor::Red.light()
+                    effects.get_theme().error
                 )
             )?
         )?;
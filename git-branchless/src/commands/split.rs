@@ -0,0 +1,408 @@
+//! Split a commit into several commits.
+//!
+//! The changes in the commit are divided into groups of paths, either by
+//! top-level directory or by the provided glob patterns, and one commit is made
+//! for each group in turn. Following the split, the command performs a restack.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use eyre::Context;
+use itertools::Itertools;
+use lib::core::rewrite::{
+    BuildRebasePlanOptions, MergeConflictRemediation, RebasePlanPermissions, RewriteSummary,
+};
+use lib::util::ExitCode;
+use tracing::instrument;
+
+use crate::commands::restack;
+use crate::opts::{MoveOptions, Revset};
+use crate::revset::resolve_commits;
+use lib::core::config::{get_commit_encoding, get_restack_preserve_timestamps};
+use lib::core::dag::{commit_set_to_vec_unsorted, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::formatting::{printable_styled_string, Pluralize};
+use lib::core::gc::mark_commit_reachable;
+use lib::core::repo_ext::RepoExt;
+use lib::git::{hydrate_tree, Commit, FileMode, GitRunInfo, NonZeroOid, Repo};
+
+/// How to divide the changes in a commit into groups.
+#[derive(Debug)]
+pub enum SplitMode {
+    /// One group per top-level directory, plus one for the files at the top
+    /// level of the repository.
+    ByDirectory,
+
+    /// One group per entry, where each entry is a comma-separated list of glob
+    /// patterns. A path belongs to the first group with a matching pattern.
+    /// Any paths not matched by any group go into a final group.
+    Globs(Vec<String>),
+}
+
+/// A group of paths to make a commit for, and the label to add to its
+/// message.
+struct PathGroup {
+    label: String,
+    paths: Vec<PathBuf>,
+}
+
+fn group_paths(mode: &SplitMode, paths: Vec<PathBuf>) -> eyre::Result<Vec<PathGroup>> {
+    match mode {
+        SplitMode::ByDirectory => {
+            let mut groups: BTreeMap<Option<String>, Vec<PathBuf>> = BTreeMap::new();
+            for path in paths {
+                let mut components = path.components();
+                let directory = match (components.next(), components.next()) {
+                    (Some(directory), Some(_)) => {
+                        Some(directory.as_os_str().to_string_lossy().into_owned())
+                    }
+                    _ => None,
+                };
+                groups.entry(directory).or_default().push(path);
+            }
+            Ok(groups
+                .into_iter()
+                .map(|(directory, paths)| PathGroup {
+                    label: match directory {
+                        Some(directory) => format!("{}/", directory),
+                        None => "top-level files".to_string(),
+                    },
+                    paths,
+                })
+                .collect())
+        }
+
+        SplitMode::Globs(groups) => {
+            let patterns = groups
+                .iter()
+                .map(|group| -> eyre::Result<Vec<glob::Pattern>> {
+                    group
+                        .split(',')
+                        .map(|pattern| pattern.trim())
+                        .filter(|pattern| !pattern.is_empty())
+                        .map(|pattern| {
+                            glob::Pattern::new(pattern)
+                                .wrap_err_with(|| format!("Compiling glob pattern: {}", pattern))
+                        })
+                        .collect()
+                })
+                .collect::<eyre::Result<Vec<_>>>()?;
+
+            let mut grouped_paths: Vec<Vec<PathBuf>> = vec![Vec::new(); groups.len()];
+            let mut remaining_paths = Vec::new();
+            for path in paths {
+                match patterns.iter().position(|patterns| {
+                    patterns
+                        .iter()
+                        .any(|pattern| pattern.matches_path(path.as_path()))
+                }) {
+                    Some(i) => grouped_paths[i].push(path),
+                    None => remaining_paths.push(path),
+                }
+            }
+
+            Ok(groups
+                .iter()
+                .cloned()
+                .zip(grouped_paths)
+                .chain(std::iter::once((
+                    "remaining files".to_string(),
+                    remaining_paths,
+                )))
+                .filter(|(_label, paths)| !paths.is_empty())
+                .map(|(label, paths)| PathGroup { label, paths })
+                .collect())
+        }
+    }
+}
+
+/// Add the label of the group of paths to the subject line of the message.
+fn make_split_message(message: &str, label: &str) -> String {
+    let (subject, rest) = message.split_once('\n').unwrap_or((message, ""));
+    format!("{} ({})\n{}", subject.trim_end(), label, rest)
+}
+
+/// Create one commit per group of paths on top of the parent of `commit`, such
+/// that the last commit has the same tree as `commit`. Returns the OIDs of the
+/// new commits.
+fn create_split_commits(
+    repo: &Repo,
+    commit: &Commit,
+    parent: &Commit,
+    groups: &[PathGroup],
+    now: SystemTime,
+) -> eyre::Result<Vec<NonZeroOid>> {
+    let commit_tree = commit.get_tree()?;
+    // Keep the message in the encoding of the original commit, or else in the
+    // configured encoding for new commits.
+    let configured_encoding = get_commit_encoding(repo)?;
+    let encoding = commit
+        .get_message_encoding()
+        .map(|encoding| encoding.to_string())
+        .or_else(|| configured_encoding.clone());
+    let (message, _is_lossy) = commit.get_message_decoded(configured_encoding.as_deref());
+    let author = commit.get_author();
+    let committer = if get_restack_preserve_timestamps(repo)? {
+        commit.get_committer()
+    } else {
+        commit.get_committer().update_timestamp(now)?
+    };
+
+    let mut split_commit_oids = Vec::new();
+    let mut current_commit = parent.clone();
+    for (i, PathGroup { label, paths }) in groups.iter().enumerate() {
+        let tree = if i + 1 == groups.len() {
+            // All remaining changes belong to the last group.
+            commit.get_tree()?
+        } else {
+            let mut entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = HashMap::new();
+            for path in paths {
+                let entry = commit_tree
+                    .get_path(path)?
+                    .map(|entry| (entry.get_oid(), entry.get_filemode()));
+                entries.insert(path.clone(), entry);
+            }
+            let tree_oid = hydrate_tree(repo, Some(&current_commit.get_tree()?), entries)?;
+            repo.find_tree_or_fail(tree_oid)?
+        };
+
+        let split_commit_oid = repo.create_commit_encoded(
+            &author,
+            &committer,
+            &make_split_message(&message, label),
+            encoding.as_deref(),
+            &tree,
+            vec![&current_commit],
+        )?;
+        split_commit_oids.push(split_commit_oid);
+        current_commit = repo.find_commit_or_fail(split_commit_oid)?;
+    }
+    Ok(split_commit_oids)
+}
+
+/// Split the commit at `revset` into one commit per group of changed paths,
+/// and restack its descendants onto the last of the new commits.
+#[instrument]
+pub fn split(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revset: Revset,
+    mode: SplitMode,
+    move_options: &MoveOptions,
+) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_oid =
+        match resolve_commits(effects, git_run_info, &repo, &mut dag, vec![revset.clone()]) {
+            Ok(commit_sets) => match commit_set_to_vec_unsorted(&commit_sets[0])?.as_slice() {
+                [only_commit_oid] => *only_commit_oid,
+                other => {
+                    let Revset(expr) = revset;
+                    writeln!(
+                        effects.get_error_stream(),
+                        "Expected revset to expand to exactly 1 commit (got {}): {}",
+                        other.len(),
+                        expr,
+                    )?;
+                    return Ok(ExitCode(1));
+                }
+            },
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(ExitCode(1));
+            }
+        };
+    let commit = repo.find_commit_or_fail(commit_oid)?;
+    let commit_description = printable_styled_string(
+        effects.get_glyphs(),
+        commit.friendly_describe(effects.get_glyphs())?,
+    )?;
+    let parent = match commit.get_only_parent() {
+        Some(parent) => parent,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Refusing to split commit {}, which has {} parents.\n\
+                Splitting is only supported for commits with 1 parent.",
+                commit_oid,
+                commit.get_parent_count(),
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+
+    let paths = match repo.get_paths_touched_by_commit(&commit)? {
+        Some(paths) => paths.into_iter().sorted().collect_vec(),
+        None => eyre::bail!("BUG: Could not get paths touched by commit {}", commit_oid),
+    };
+    let groups = group_paths(&mode, paths)?;
+    if groups.len() < 2 {
+        writeln!(
+            effects.get_output_stream(),
+            "Not splitting commit {}, since its changes can't be divided into more than 1 group.",
+            commit_description
+        )?;
+        return Ok(ExitCode(0));
+    }
+
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits: move_options.force_rewrite_public_commits,
+        force_rewrite_protected_branches: move_options.force_rewrite_protected_branches,
+        dump_rebase_constraints: move_options.dump_rebase_constraints,
+        dump_rebase_plan: move_options.dump_rebase_plan,
+        detect_duplicate_commits_via_patch_id: move_options.detect_duplicate_commits_via_patch_id,
+    };
+    if let Err(err) = RebasePlanPermissions::verify_rewrite_set(
+        &dag,
+        &repo,
+        &build_options,
+        &CommitSet::from(commit_oid),
+    )? {
+        err.describe(effects, &repo)?;
+        return Ok(ExitCode(1));
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(now, "split")?;
+    let split_commit_oids = create_split_commits(&repo, &commit, &parent, &groups, now)?;
+    let last_split_commit_oid = match split_commit_oids.last() {
+        Some(oid) => *oid,
+        None => eyre::bail!("BUG: No commits were created when splitting {}", commit_oid),
+    };
+    mark_commit_reachable(&repo, last_split_commit_oid)
+        .wrap_err("Marking commit as reachable for GC purposes.")?;
+    let mut events = split_commit_oids
+        .iter()
+        .map(|split_commit_oid| Event::CommitEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: *split_commit_oid,
+        })
+        .collect_vec();
+    events.push(Event::RewriteEvent {
+        timestamp,
+        event_tx_id,
+        old_commit_oid: commit_oid.into(),
+        new_commit_oid: last_split_commit_oid.into(),
+    });
+    event_log_db.add_events(events)?;
+
+    // The working copy doesn't change, since the last commit has the same tree
+    // as the original commit. If `HEAD` is on a branch, the branch will be
+    // moved as part of the restack.
+    let head_info = repo.get_head_info()?;
+    if head_info.oid == Some(commit_oid) && head_info.reference_name.is_none() {
+        repo.set_head(last_split_commit_oid)?;
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Split commit {} into {}:",
+        commit_description,
+        Pluralize {
+            determiner: None,
+            amount: split_commit_oids.len(),
+            unit: ("commit", "commits"),
+        },
+    )?;
+    for split_commit_oid in split_commit_oids.iter() {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            printable_styled_string(
+                effects.get_glyphs(),
+                repo.friendly_describe_commit_from_oid(effects.get_glyphs(), *split_commit_oid)?,
+            )?
+        )?;
+    }
+
+    restack::restack(
+        effects,
+        git_run_info,
+        vec![Revset(commit_oid.to_string())],
+        move_options,
+        MergeConflictRemediation::Restack,
+        &mut RewriteSummary::default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe_groups(mode: &SplitMode, paths: &[&str]) -> eyre::Result<Vec<String>> {
+        let groups = group_paths(mode, paths.iter().map(PathBuf::from).collect())?;
+        Ok(groups
+            .into_iter()
+            .map(|PathGroup { label, paths }| {
+                format!(
+                    "{}: {}",
+                    label,
+                    paths
+                        .iter()
+                        .map(|path| path.to_string_lossy().into_owned())
+                        .join(", ")
+                )
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_group_paths() -> eyre::Result<()> {
+        let paths = ["README.md", "docs/a.md", "src/lib.rs", "src/foo/mod.rs"];
+        insta::assert_debug_snapshot!(describe_groups(&SplitMode::ByDirectory, &paths)?, @r###"
+        [
+            "top-level files: README.md",
+            "docs/: docs/a.md",
+            "src/: src/lib.rs, src/foo/mod.rs",
+        ]
+        "###);
+        insta::assert_debug_snapshot!(
+            describe_groups(
+                &SplitMode::Globs(vec!["src/*".to_string(), "*.md, docs/*".to_string()]),
+                &paths,
+            )?,
+            @r###"
+        [
+            "src/*: src/lib.rs, src/foo/mod.rs",
+            "*.md, docs/*: README.md, docs/a.md",
+        ]
+        "###
+        );
+        insta::assert_debug_snapshot!(
+            describe_groups(&SplitMode::Globs(vec!["tests/*".to_string()]), &paths)?,
+            @r###"
+        [
+            "remaining files: README.md, docs/a.md, src/lib.rs, src/foo/mod.rs",
+        ]
+        "###
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_make_split_message() {
+        assert_eq!(
+            make_split_message("Add foo\n\nMore details.\n", "src/"),
+            "Add foo (src/)\n\nMore details.\n"
+        );
+        assert_eq!(make_split_message("Add foo\n", "src/"), "Add foo (src/)\n");
+        assert_eq!(make_split_message("Add foo", "src/"), "Add foo (src/)\n");
+    }
+}
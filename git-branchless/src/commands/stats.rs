@@ -0,0 +1,240 @@
+//! Summarize the draft commits in the repository, such as how many stacks
+//! there are, how old their commits are, and how many lines they change.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+
+use eden_dag::DagAlgorithm;
+use lib::core::dag::{commit_set_to_vec_unsorted, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::{printable_styled_string, Pluralize};
+use lib::core::metadata::CommitMetadataDb;
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::RewriteMap;
+use lib::core::topics::infer_stack_topics;
+use lib::git::{NonZeroOid, Repo};
+use lib::util::ExitCode;
+use serde_json::json;
+use tracing::instrument;
+
+/// The buckets into which commits are sorted by age, as tuples of the key used
+/// in JSON output, the human-readable description, and the bucket's
+/// (exclusive) upper bound.
+const AGE_BUCKETS: &[(&str, &str, Option<Duration>)] = &[
+    (
+        "less_than_1_day",
+        "less than 1 day",
+        Some(Duration::from_secs(24 * 60 * 60)),
+    ),
+    (
+        "1_to_7_days",
+        "1 to 7 days",
+        Some(Duration::from_secs(7 * 24 * 60 * 60)),
+    ),
+    (
+        "1_to_4_weeks",
+        "1 to 4 weeks",
+        Some(Duration::from_secs(28 * 24 * 60 * 60)),
+    ),
+    ("more_than_4_weeks", "more than 4 weeks", None),
+];
+
+#[derive(Debug)]
+struct StackStats {
+    root_oid: NonZeroOid,
+    name: Option<String>,
+    num_commits: usize,
+    lines_added: usize,
+    lines_removed: usize,
+}
+
+/// Get the index of the bucket in [`AGE_BUCKETS`] for a commit of the given
+/// age.
+fn get_age_bucket(age: Duration) -> usize {
+    AGE_BUCKETS
+        .iter()
+        .position(|(_key, _description, upper_bound)| match upper_bound {
+            Some(upper_bound) => age < *upper_bound,
+            None => true,
+        })
+        .unwrap_or(AGE_BUCKETS.len() - 1)
+}
+
+/// Print statistics about the stacks of draft commits in the repository.
+///
+/// Merge conflicts are counted from the conflicts recorded in the database
+/// when an in-memory rebase fails, so conflicts which occurred during on-disk
+/// rebases aren't included. `git branchless gc` discards the records for
+/// commits which are no longer in the repository.
+#[instrument]
+pub fn stats(effects: &Effects, json: bool) -> eyre::Result<ExitCode> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_metadata = {
+        let rewrite_map = RewriteMap::from_event_replayer(&event_replayer, event_cursor);
+        CommitMetadataDb::new(&conn)?.get_all(&rewrite_map)?
+    };
+    let topic_names: HashMap<NonZeroOid, String> =
+        infer_stack_topics(&repo, &dag, &references_snapshot, &commit_metadata)?
+            .into_iter()
+            .map(|topic| (topic.root_oid, topic.name))
+            .collect();
+
+    let public_commits = dag.query_public_commits()?;
+    let active_heads = dag.query_active_heads(
+        &public_commits,
+        &dag.observed_commits.difference(&dag.obsolete_commits),
+    )?;
+    let draft_commits = dag.query().only(active_heads, public_commits)?;
+    let root_oids = {
+        let mut result = Vec::new();
+        for root_oid in commit_set_to_vec_unsorted(&dag.query().roots(draft_commits.clone())?)? {
+            if let Some(root_commit) = repo.find_commit(root_oid)? {
+                result.push((root_commit.get_time().to_system_time()?, root_oid));
+            }
+        }
+        result.sort_unstable();
+        result
+    };
+
+    let mut age_counts = vec![0_usize; AGE_BUCKETS.len()];
+    let mut stacks = Vec::new();
+    for (_time, root_oid) in root_oids {
+        let stack_commits = dag
+            .query()
+            .descendants(CommitSet::from(root_oid))?
+            .intersection(&draft_commits);
+        let mut stack_stats = StackStats {
+            root_oid,
+            name: topic_names.get(&root_oid).cloned(),
+            num_commits: 0,
+            lines_added: 0,
+            lines_removed: 0,
+        };
+        for commit_oid in commit_set_to_vec_unsorted(&stack_commits)? {
+            let commit = match repo.find_commit(commit_oid)? {
+                Some(commit) => commit,
+                None => continue,
+            };
+            stack_stats.num_commits += 1;
+
+            let authored_time = commit.get_author().get_time().to_system_time()?;
+            // Commits authored in the future are treated as brand-new.
+            let age = now.duration_since(authored_time).unwrap_or_default();
+            age_counts[get_age_bucket(age)] += 1;
+
+            // Merge commits don't have a single patch, so they're not included
+            // in the line counts.
+            if let Some(diff) = repo.get_patch_for_commit(effects, &commit)? {
+                let (lines_added, lines_removed) = diff.get_line_counts()?;
+                stack_stats.lines_added += lines_added;
+                stack_stats.lines_removed += lines_removed;
+            }
+        }
+        stacks.push(stack_stats);
+    }
+
+    let num_commits: usize = stacks.iter().map(|stack| stack.num_commits).sum();
+    let lines_added: usize = stacks.iter().map(|stack| stack.lines_added).sum();
+    let lines_removed: usize = stacks.iter().map(|stack| stack.lines_removed).sum();
+    let num_merge_conflicts = event_log_db.get_merge_conflicts()?.len();
+
+    if json {
+        let report = json!({
+            "num_stacks": stacks.len(),
+            "num_commits": num_commits,
+            "stacks": stacks.iter().map(|stack| json!({
+                "root_oid": stack.root_oid.to_string(),
+                "name": stack.name,
+                "num_commits": stack.num_commits,
+                "lines_added": stack.lines_added,
+                "lines_removed": stack.lines_removed,
+            })).collect::<Vec<_>>(),
+            "age_distribution": AGE_BUCKETS
+                .iter()
+                .zip(age_counts.iter())
+                .map(|((key, _description, _upper_bound), count)| (key.to_string(), json!(count)))
+                .collect::<serde_json::Map<_, _>>(),
+            "lines_added": lines_added,
+            "lines_removed": lines_removed,
+            "num_merge_conflicts": num_merge_conflicts,
+        });
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            serde_json::to_string(&report)?
+        )?;
+        return Ok(ExitCode(0));
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "{} in {}",
+        Pluralize {
+            determiner: None,
+            amount: num_commits,
+            unit: ("draft commit", "draft commits"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: stacks.len(),
+            unit: ("stack", "stacks"),
+        },
+    )?;
+    if !stacks.is_empty() {
+        writeln!(effects.get_output_stream(), "Stacks:")?;
+    }
+    for stack in &stacks {
+        let description = printable_styled_string(
+            effects.get_glyphs(),
+            repo.friendly_describe_commit_from_oid(effects.get_glyphs(), stack.root_oid)?,
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "  {}{}: {}, +{}/-{} lines",
+            description,
+            match &stack.name {
+                Some(name) => format!(" ({})", name),
+                None => String::new(),
+            },
+            Pluralize {
+                determiner: None,
+                amount: stack.num_commits,
+                unit: ("commit", "commits"),
+            },
+            stack.lines_added,
+            stack.lines_removed,
+        )?;
+    }
+    writeln!(effects.get_output_stream(), "Commit age:")?;
+    for ((_key, description, _upper_bound), count) in AGE_BUCKETS.iter().zip(age_counts.iter()) {
+        writeln!(effects.get_output_stream(), "  {}: {}", description, count)?;
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Lines changed: +{}/-{}",
+        lines_added,
+        lines_removed
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Merge conflicts encountered while rewriting: {}",
+        num_merge_conflicts
+    )?;
+    Ok(ExitCode(0))
+}
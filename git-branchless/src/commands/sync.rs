@@ -1,5 +1,6 @@
 //! Implements the `git sync` command.
 
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::time::SystemTime;
 
@@ -7,19 +8,23 @@ use eden_dag::DagAlgorithm;
 use itertools::Itertools;
 use lib::core::check_out::CheckOutCommitOptions;
 use lib::core::repo_ext::RepoExt;
+use lib::error::ErrorCategory;
 use lib::util::ExitCode;
 use rayon::ThreadPoolBuilder;
 
+use crate::commands::confirm_rewrite::confirm_large_rewrite;
+use crate::commands::smartlog::render_dry_run_smartlog;
 use crate::opts::{MoveOptions, Revset};
 use crate::revset::resolve_commits;
 use lib::core::config::get_restack_preserve_timestamps;
 use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::{Effects, OperationType};
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId, SuspendedOperation};
 use lib::core::formatting::{printable_styled_string, Glyphs, StyledStringBuilder};
 use lib::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanError, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
-    ExecuteRebasePlanResult, RebasePlan, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    execute_rebase_plan, simulate_rebase_plan, BuildRebasePlanError, BuildRebasePlanOptions,
+    ExecuteRebasePlanOptions, ExecuteRebasePlanResult, RebasePlan, RebasePlanBuilder,
+    RebasePlanPermissions, RepoResource, RewriteSummary, SimulateRebasePlanResult,
 };
 use lib::git::{Commit, GitRunInfo, NonZeroOid, Repo};
 
@@ -41,22 +46,81 @@ fn get_stack_roots(dag: &Dag) -> eyre::Result<CommitSet> {
     Ok(draft_roots)
 }
 
+/// Record that syncing stopped partway through, so that the given stacks can be
+/// synced by `git branchless continue`.
+fn suspend_sync(
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    move_options: &MoveOptions,
+    remaining_commit_oids: &[NonZeroOid],
+) -> eyre::Result<()> {
+    let MoveOptions {
+        force_rewrite_public_commits,
+        force_rewrite_protected_branches,
+        force_in_memory,
+        force_on_disk,
+        detect_duplicate_commits_via_patch_id,
+        resolve_merge_conflicts,
+        skip_confirmation: _,
+        dump_rebase_constraints: _,
+        dump_rebase_plan: _,
+    } = *move_options;
+
+    let resume_args = if remaining_commit_oids.is_empty() {
+        Vec::new()
+    } else {
+        let mut args = vec!["sync".to_string()];
+        for (is_set, flag) in [
+            (force_rewrite_public_commits, "--force-rewrite"),
+            (
+                force_rewrite_protected_branches,
+                "--force-rewrite-protected-branches",
+            ),
+            (force_in_memory, "--in-memory"),
+            (force_on_disk, "--on-disk"),
+            (
+                !detect_duplicate_commits_via_patch_id,
+                "--no-deduplicate-commits",
+            ),
+            (resolve_merge_conflicts, "--merge"),
+            // The user already confirmed the sync, if it was large enough to
+            // need confirmation.
+            (true, "--yes"),
+        ] {
+            if is_set {
+                args.push(flag.to_string());
+            }
+        }
+        args.push(remaining_commit_oids.iter().join(" + "));
+        args
+    };
+
+    event_log_db.set_suspended_operation(&SuspendedOperation {
+        command_name: "sync".to_string(),
+        event_tx_id,
+        resume_args,
+        is_rebase_in_progress: repo.get_current_operation_type() == Some("rebase"),
+    })
+}
+
 /// Move all commit stacks on top of the main branch.
 pub fn sync(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     update_refs: bool,
     move_options: &MoveOptions,
+    dry_run: bool,
     revsets: Vec<Revset>,
+    summary: &mut RewriteSummary,
 ) -> eyre::Result<ExitCode> {
     let glyphs = Glyphs::detect();
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
-    let now = SystemTime::now();
-    let event_tx_id = event_log_db.make_transaction_id(now, "sync fetch")?;
-
     if update_refs {
+        let now = SystemTime::now();
+        let event_tx_id = event_log_db.make_transaction_id(now, "sync fetch")?;
         let exit_code = git_run_info.run(effects, Some(event_tx_id), &["fetch", "--all"])?;
         if !exit_code.is_success() {
             return Ok(exit_code);
@@ -74,7 +138,7 @@ pub fn sync(
         &references_snapshot,
     )?;
 
-    let commit_sets = match resolve_commits(effects, &repo, &mut dag, revsets) {
+    let commit_sets = match resolve_commits(effects, git_run_info, &repo, &mut dag, revsets) {
         Ok(commit_sets) => commit_sets,
         Err(err) => {
             err.describe(effects)?;
@@ -90,10 +154,12 @@ pub fn sync(
 
     let MoveOptions {
         force_rewrite_public_commits,
+        force_rewrite_protected_branches,
         force_in_memory,
         force_on_disk,
         detect_duplicate_commits_via_patch_id,
         resolve_merge_conflicts,
+        skip_confirmation,
         dump_rebase_constraints,
         dump_rebase_plan,
     } = *move_options;
@@ -102,12 +168,14 @@ pub fn sync(
     let root_commit_and_plans: Vec<(NonZeroOid, Option<RebasePlan>)> = {
         let build_options = BuildRebasePlanOptions {
             force_rewrite_public_commits,
+            force_rewrite_protected_branches,
             detect_duplicate_commits_via_patch_id,
             dump_rebase_constraints,
             dump_rebase_plan,
         };
         let permissions = match RebasePlanPermissions::verify_rewrite_set(
             &dag,
+            &repo,
             &build_options,
             &root_commit_oids,
         )? {
@@ -167,7 +235,11 @@ pub fn sync(
     };
 
     let now = SystemTime::now();
-    let event_tx_id = event_log_db.make_transaction_id(now, "sync")?;
+    let event_tx_id = if dry_run {
+        EventTransactionId::DRY_RUN
+    } else {
+        event_log_db.make_transaction_id(now, "sync")?
+    };
     let execute_options = ExecuteRebasePlanOptions {
         now,
         event_tx_id,
@@ -181,16 +253,77 @@ pub fn sync(
         },
     };
 
-    let (success_commits, merge_conflict_commits, skipped_commits) = {
+    if dry_run {
+        let mut rewritten_oids = HashMap::new();
+        for (root_commit_oid, rebase_plan) in root_commit_and_plans {
+            let rebase_plan = match rebase_plan {
+                Some(rebase_plan) => rebase_plan,
+                None => continue,
+            };
+            let root_commit = repo.find_commit_or_fail(root_commit_oid)?;
+            match simulate_rebase_plan(effects, &repo, &rebase_plan, &execute_options)? {
+                SimulateRebasePlanResult::Succeeded {
+                    rewritten_oids: stack_rewritten_oids,
+                } => {
+                    rewritten_oids.extend(stack_rewritten_oids);
+                }
+                SimulateRebasePlanResult::MergeConflict { merge_conflict: _ } => {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "{}",
+                        printable_styled_string(
+                            &glyphs,
+                            StyledStringBuilder::new()
+                                .append_plain("Merge conflict for ")
+                                .append(root_commit.friendly_describe(&glyphs)?)
+                                .build()
+                        )?
+                    )?;
+                }
+                SimulateRebasePlanResult::CannotSimulate { reason } => {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Not simulating sync for stack at {}: {}",
+                        printable_styled_string(&glyphs, root_commit.friendly_describe(&glyphs)?)?,
+                        reason
+                    )?;
+                }
+            }
+        }
+        render_dry_run_smartlog(effects, &repo, &rewritten_oids, &HashSet::new(), false)?;
+        return Ok(ExitCode(0));
+    }
+
+    let rewritten_commits: CommitSet = root_commit_and_plans
+        .iter()
+        .filter_map(|(_root_commit_oid, rebase_plan)| rebase_plan.as_ref())
+        .flat_map(|rebase_plan| rebase_plan.get_rewritten_commit_oids())
+        .collect();
+    if !confirm_large_rewrite(effects, &repo, &dag, &rewritten_commits, skip_confirmation)? {
+        return Ok(ExitCode(1));
+    }
+    // Any previously-suspended operation is superseded by this one.
+    event_log_db.clear_suspended_operation()?;
+
+    let (success_commits, merge_conflict_commits, skipped_commits, interrupted_commits) = {
         let mut success_commits: Vec<Commit> = Vec::new();
         let mut merge_conflict_commits: Vec<Commit> = Vec::new();
         let mut skipped_commits: Vec<Commit> = Vec::new();
+        let mut interrupted_commits: Vec<Commit> = Vec::new();
 
         let (effects, progress) = effects.start_operation(OperationType::SyncCommits);
         progress.notify_progress(0, root_commit_and_plans.len());
 
-        for (root_commit_oid, rebase_plan) in root_commit_and_plans {
+        // If the user presses Ctrl-C, finish syncing the current stack, but
+        // don't start syncing any more stacks.
+        let _interrupt_guard = effects.catch_interrupts()?;
+        let mut root_commit_and_plans = root_commit_and_plans.into_iter();
+        while let Some((root_commit_oid, rebase_plan)) = root_commit_and_plans.next() {
             let root_commit = repo.find_commit_or_fail(root_commit_oid)?;
+            if effects.is_interrupted() {
+                interrupted_commits.push(root_commit);
+                continue;
+            }
             let rebase_plan = match rebase_plan {
                 Some(rebase_plan) => rebase_plan,
                 None => {
@@ -212,16 +345,40 @@ pub fn sync(
                 ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => {
                     success_commits.push(root_commit);
                 }
-                ExecuteRebasePlanResult::DeclinedToMerge { merge_conflict: _ } => {
+                ExecuteRebasePlanResult::DeclinedToMerge { merge_conflict } => {
+                    summary.add_conflict(&merge_conflict);
                     merge_conflict_commits.push(root_commit);
                 }
+                ExecuteRebasePlanResult::Failed { exit_code: _ }
+                    if effects.is_interrupted() && repo.get_current_operation_type().is_none() =>
+                {
+                    interrupted_commits.push(root_commit);
+                }
                 ExecuteRebasePlanResult::Failed { exit_code } => {
+                    // The current stack is in the middle of an on-disk rebase,
+                    // so only the stacks after it need to be synced once the
+                    // rebase is continued.
+                    let remaining_commit_oids = root_commit_and_plans
+                        .map(|(root_commit_oid, _rebase_plan)| root_commit_oid)
+                        .collect_vec();
+                    suspend_sync(
+                        &repo,
+                        &event_log_db,
+                        event_tx_id,
+                        move_options,
+                        &remaining_commit_oids,
+                    )?;
                     return Ok(exit_code);
                 }
             }
         }
 
-        (success_commits, merge_conflict_commits, skipped_commits)
+        (
+            success_commits,
+            merge_conflict_commits,
+            skipped_commits,
+            interrupted_commits,
+        )
     };
 
     for success_commit in success_commits {
@@ -260,5 +417,26 @@ pub fn sync(
         )?;
     }
 
+    if !interrupted_commits.is_empty() {
+        suspend_sync(
+            &repo,
+            &event_log_db,
+            event_tx_id,
+            move_options,
+            &interrupted_commits
+                .iter()
+                .map(|commit| commit.get_oid())
+                .collect_vec(),
+        )?;
+        for interrupted_commit in interrupted_commits {
+            writeln!(
+                effects.get_output_stream(),
+                "Interrupted before syncing stack at {}",
+                printable_styled_string(&glyphs, interrupted_commit.friendly_describe(&glyphs)?)?
+            )?;
+        }
+        return Ok(ErrorCategory::Interrupted.exit_code());
+    }
+
     Ok(ExitCode(0))
 }
@@ -3,6 +3,7 @@
 //! This is accomplished by finding the events that have happened since a certain
 //! time and inverting them.
 
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::Write;
 use std::io::{stdin, BufRead, BufReader, Read};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
@@ -14,6 +15,7 @@ use cursive::utils::markup::StyledString;
 use cursive::views::{Dialog, EditView, LinearLayout, OnEventView, Panel, ScrollView, TextView};
 use cursive::{Cursive, CursiveRunnable, CursiveRunner};
 use eyre::Context;
+use itertools::Itertools;
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
 use lib::core::repo_ext::RepoExt;
 use lib::util::ExitCode;
@@ -31,7 +33,10 @@ use lib::core::node_descriptors::{
     DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
     RelativeTimeDescriptor,
 };
-use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, Repo, ResolvedReferenceInfo};
+use lib::git::{
+    CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, ReferenceUpdate,
+    Repo, ResolvedReferenceInfo,
+};
 
 fn render_cursor_smartlog(
     effects: &Effects,
@@ -451,6 +456,8 @@ fn select_past_event(
 
     let mut cursor = event_replayer.make_default_cursor();
     let now = SystemTime::now();
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
     main_tx.send(Message::Init)?;
     while siv.is_running() {
         let message = main_rx.try_recv();
@@ -507,6 +514,11 @@ fn select_past_event(
                         .append_plain(relative_time)
                         .append_plain(". Press 'h' for help, 'q' to quit.")
                         .build()];
+                    if let Some(provenance) =
+                        event_log_db.get_transaction_provenance(events[0].get_event_tx_id())?
+                    {
+                        lines.push(StyledString::plain(format!("Caused by: {}", provenance)));
+                    }
                     lines.extend(event_description_lines);
                     lines
                 }
@@ -693,6 +705,61 @@ fn inverse_event(
     Ok(inverse_event)
 }
 
+/// Which events `undo` should revert.
+#[derive(Clone, Debug, Default)]
+struct UndoReferenceFilter {
+    /// Don't revert updates to remote-tracking branches, such as those made by
+    /// `git fetch`.
+    exclude_remotes: bool,
+
+    /// If non-empty, only revert updates to references matching one of these
+    /// patterns, and leave commits and the working copy alone. A pattern may
+    /// match either the full reference name (`refs/remotes/origin/main`) or
+    /// its short name (`origin/main`).
+    patterns: Vec<glob::Pattern>,
+}
+
+impl UndoReferenceFilter {
+    fn should_restore_reference(&self, ref_name: &ReferenceName) -> bool {
+        let categorized_ref_name = CategorizedReferenceName::new(ref_name);
+        if self.exclude_remotes
+            && matches!(
+                categorized_ref_name,
+                CategorizedReferenceName::RemoteBranch { .. }
+            )
+        {
+            return false;
+        }
+        if self.patterns.is_empty() {
+            return true;
+        }
+        let short_name = categorized_ref_name.render_suffix();
+        self.patterns.iter().any(|pattern| {
+            pattern.matches(ref_name.as_str()) || pattern.matches(short_name.as_str())
+        })
+    }
+
+    fn should_undo_event(&self, event: &Event) -> bool {
+        match event {
+            Event::RefUpdateEvent {
+                timestamp: _,
+                event_tx_id: _,
+                ref_name,
+                old_oid: _,
+                new_oid: _,
+                message: _,
+            } if ref_name.as_str() != "HEAD" => self.should_restore_reference(ref_name),
+
+            Event::RefUpdateEvent { .. }
+            | Event::CommitEvent { .. }
+            | Event::ObsoleteEvent { .. }
+            | Event::UnobsoleteEvent { .. }
+            | Event::RewriteEvent { .. }
+            | Event::WorkingCopySnapshot { .. } => self.patterns.is_empty(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct UndoCheckoutTarget {
     target: CheckoutTarget,
@@ -752,6 +819,79 @@ fn extract_checkout_target(
     Ok((checkout_target, new_events))
 }
 
+/// Determine where each reference updated since `event_cursor` should be
+/// restored to, and compare that against where it actually is now.
+///
+/// The target is the reference's last recorded location before the cursor.
+/// If there is none, it falls back to the old value of the first update since
+/// the cursor. The old value reported by the `reference-transaction` hook is
+/// not always accurate (for example, `git branch -f` reports it as zero), so
+/// it's only used when the event log has no better information.
+///
+/// The event log also doesn't necessarily contain every intermediate update to
+/// a reference (for example, when it was moved by a tool which doesn't trigger
+/// the `reference-transaction` hook), so applying the inverse of each event in
+/// turn can leave a branch at an intermediate commit. Instead, each reference
+/// is moved directly to its original location, and references which are
+/// already there are skipped.
+fn plan_reference_restores<'a>(
+    repo: &Repo,
+    event_replayer: &'a EventReplayer,
+    event_cursor: EventCursor,
+    ref_filter: &UndoReferenceFilter,
+) -> eyre::Result<Vec<ReferenceUpdate>> {
+    let mut target_oids: BTreeMap<&'a ReferenceName, MaybeZeroOid> = BTreeMap::new();
+    for event in event_replayer.get_events_since_cursor(event_cursor) {
+        match event {
+            Event::RefUpdateEvent {
+                timestamp: _,
+                event_tx_id: _,
+                ref_name,
+                old_oid,
+                new_oid: _,
+                message: _,
+            } if ref_name.as_str() != "HEAD" && ref_filter.should_restore_reference(ref_name) => {
+                target_oids.entry(ref_name).or_insert(*old_oid);
+            }
+            _ => {}
+        }
+    }
+    for event in event_replayer.get_events_before_cursor(event_cursor) {
+        if let Event::RefUpdateEvent {
+            timestamp: _,
+            event_tx_id: _,
+            ref_name,
+            old_oid: _,
+            new_oid,
+            message: _,
+        } = event
+        {
+            if let Some(target_oid) = target_oids.get_mut(ref_name) {
+                *target_oid = *new_oid;
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    for (ref_name, target_oid) in target_oids {
+        // The event log records the raw targets of references, and `git
+        // update-ref` checks the expected old value against the raw target,
+        // so annotated tags mustn't be peeled here.
+        let current_oid = match repo.find_reference(ref_name)? {
+            Some(reference) => reference.get_target()?,
+            None => MaybeZeroOid::Zero,
+        };
+        if current_oid != target_oid {
+            result.push(ReferenceUpdate {
+                ref_name: ref_name.clone(),
+                old_oid: current_oid,
+                new_oid: target_oid,
+            });
+        }
+    }
+    Ok(result)
+}
+
 #[instrument(skip(in_))]
 fn undo_events(
     in_: &mut impl Read,
@@ -761,6 +901,7 @@ fn undo_events(
     event_log_db: &mut EventLogDb,
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
+    ref_filter: &UndoReferenceFilter,
     skip_confirmation: bool,
 ) -> eyre::Result<ExitCode> {
     let now = SystemTime::now();
@@ -783,6 +924,7 @@ fn undo_events(
                 } if ref_name.as_str() == "HEAD"
             )
         })
+        .filter(|event| ref_filter.should_undo_event(event))
         .map(|event| inverse_event(event.clone(), now, event_tx_id))
         .collect::<eyre::Result<Vec<Event>>>()?;
 
@@ -793,6 +935,27 @@ fn undo_events(
         )?;
         return Ok(ExitCode(0));
     }
+
+    // With `--refs`, the working copy is left alone, so moving the checked-out
+    // branch would leave the working copy out of sync with it.
+    let planned_reference_updates =
+        plan_reference_restores(repo, event_replayer, event_cursor, ref_filter)?;
+    if !ref_filter.patterns.is_empty() {
+        if let Some(head_reference_name) = &head_info.reference_name {
+            if planned_reference_updates
+                .iter()
+                .any(|reference_update| &reference_update.ref_name == head_reference_name)
+            {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Not restoring {} with --refs, because it's checked out. Check out another commit first, or run git undo without --refs.",
+                    CategorizedReferenceName::new(head_reference_name).friendly_describe()
+                )?;
+                return Ok(ExitCode(1));
+            }
+        }
+    }
+
     writeln!(effects.get_output_stream(), "Will apply these actions:")?;
     let events = describe_events_numbered(effects.get_glyphs(), repo, &inverse_events)?;
     for line in events {
@@ -830,58 +993,60 @@ fn undo_events(
     .to_string();
 
     let (checkout_target, filtered_events) = extract_checkout_target(&inverse_events)?;
+
+    // Commits referenced by the event log may have since been garbage
+    // collected. Refuse only the steps which need those commits, and apply the
+    // rest.
+    let mut num_skipped_events = 0;
+    let checkout_target = match checkout_target {
+        Some(UndoCheckoutTarget {
+            target: CheckoutTarget::Oid(oid),
+            options: _,
+        }) if !repo.contains_object(oid)? => {
+            writeln!(
+                effects.get_output_stream(),
+                "Not checking out commit {}, because it is no longer available.",
+                oid
+            )?;
+            num_skipped_events += 1;
+            None
+        }
+        checkout_target => checkout_target,
+    };
     if checkout_target.is_some() {
         repo.detach_head(&head_info)?;
     }
+
+    let mut reference_updates = Vec::new();
+    for reference_update in planned_reference_updates {
+        match reference_update.new_oid {
+            MaybeZeroOid::NonZero(target_oid) if !repo.contains_object(target_oid)? => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "Not moving reference {} to commit {}, because it is no longer available.",
+                    reference_update.ref_name.as_str(),
+                    target_oid
+                )?;
+                num_skipped_events += 1;
+            }
+            _ => reference_updates.push(reference_update),
+        }
+    }
+    if let Err(err) =
+        git_run_info.update_references(effects, repo, event_tx_id, &reference_updates)?
+    {
+        writeln!(
+            effects.get_output_stream(),
+            "Failed to restore branches, so none of them were moved:\n{}",
+            err
+        )?;
+        return Ok(ExitCode(1));
+    }
+
     for event in filtered_events.into_iter() {
         match event {
-            Event::RefUpdateEvent {
-                timestamp: _,
-                event_tx_id: _,
-                ref_name: _,
-                old_oid: MaybeZeroOid::Zero,
-                new_oid: MaybeZeroOid::Zero,
-                message: _,
-            } => {
-                // Do nothing.
-            }
-            Event::RefUpdateEvent {
-                timestamp: _,
-                event_tx_id: _,
-                ref_name,
-                old_oid: MaybeZeroOid::NonZero(_),
-                new_oid: MaybeZeroOid::Zero,
-                message: _,
-            } => match repo.find_reference(ref_name)? {
-                Some(mut reference) => {
-                    reference.delete().wrap_err("Applying `RefUpdateEvent`")?;
-                }
-                None => {
-                    writeln!(
-                        effects.get_output_stream(),
-                        "Reference {} did not exist, not deleting it.",
-                        ref_name.as_str()
-                    )?;
-                }
-            },
-            Event::RefUpdateEvent {
-                timestamp: _,
-                event_tx_id: _,
-                ref_name,
-                old_oid: MaybeZeroOid::Zero,
-                new_oid: MaybeZeroOid::NonZero(new_oid),
-                message: _,
-            }
-            | Event::RefUpdateEvent {
-                timestamp: _,
-                event_tx_id: _,
-                ref_name,
-                old_oid: MaybeZeroOid::NonZero(_),
-                new_oid: MaybeZeroOid::NonZero(new_oid),
-                message: _,
-            } => {
-                // Create or update the given reference.
-                repo.create_reference(ref_name, *new_oid, true, "branchless undo")?;
+            Event::RefUpdateEvent { .. } => {
+                // Handled by restoring the references above.
             }
 
             Event::WorkingCopySnapshot { .. } => {
@@ -913,6 +1078,24 @@ fn undo_events(
         }
     }
 
+    if num_skipped_events > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "Applied {}, but skipped {} which referred to commits that may have been garbage collected.",
+            Pluralize {
+                determiner: None,
+                amount: inverse_events.len() - num_skipped_events,
+                unit: ("inverse event", "inverse events"),
+            },
+            Pluralize {
+                determiner: None,
+                amount: num_skipped_events,
+                unit: ("inverse event", "inverse events"),
+            },
+        )?;
+        return Ok(ExitCode(1));
+    }
+
     writeln!(
         effects.get_output_stream(),
         "Applied {}.",
@@ -928,7 +1111,35 @@ pub fn undo(
     git_run_info: &GitRunInfo,
     interactive: bool,
     skip_confirmation: bool,
+    exclude_remotes: bool,
+    refs: Vec<String>,
+    since: Option<SystemTime>,
+    until: Option<SystemTime>,
 ) -> eyre::Result<ExitCode> {
+    if let Some(since) = since {
+        return print_changes_summary(effects, since, until);
+    }
+
+    let patterns: Vec<glob::Pattern> = match refs
+        .iter()
+        .map(|pattern| glob::Pattern::new(pattern))
+        .try_collect()
+    {
+        Ok(patterns) => patterns,
+        Err(err) => {
+            writeln!(
+                effects.get_error_stream(),
+                "Invalid pattern for --refs: {}",
+                err
+            )?;
+            return Ok(ExitCode(1));
+        }
+    };
+    let ref_filter = UndoReferenceFilter {
+        exclude_remotes,
+        patterns,
+    };
+
     let repo = Repo::from_current_dir()?;
     let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
@@ -970,11 +1181,325 @@ pub fn undo(
         &mut event_log_db,
         &event_replayer,
         event_cursor,
+        &ref_filter,
         skip_confirmation,
     )?;
     Ok(result)
 }
 
+/// The net changes made to the repository between two event cursors.
+#[derive(Debug, Default)]
+struct ChangesSummary {
+    created_commits: Vec<NonZeroOid>,
+    rewritten_commits: Vec<(NonZeroOid, NonZeroOid)>,
+    hidden_commits: Vec<NonZeroOid>,
+    unhidden_commits: Vec<NonZeroOid>,
+    moved_references: Vec<(ReferenceName, MaybeZeroOid, MaybeZeroOid)>,
+    num_head_moves: usize,
+    last_head_oid: Option<NonZeroOid>,
+}
+
+impl ChangesSummary {
+    fn from_events(events: &[Event]) -> Self {
+        // Amending a commit produces both a commit event and a rewrite event
+        // for the new commit, but it shouldn't be counted as a new commit.
+        let rewritten_oids: HashSet<NonZeroOid> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::RewriteEvent {
+                    new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                    ..
+                } => Some(*new_commit_oid),
+                _ => None,
+            })
+            .collect();
+
+        let mut summary = Self::default();
+        // Only the last change in visibility for each commit is reported, so
+        // that a commit which was hidden and then unhidden again isn't listed.
+        let mut visibility_changes: Vec<(NonZeroOid, bool)> = Vec::new();
+        let mut set_visibility = |oid: NonZeroOid, is_visible: bool| {
+            visibility_changes.retain(|(other_oid, _)| *other_oid != oid);
+            visibility_changes.push((oid, is_visible));
+        };
+        for event in events {
+            match event {
+                Event::CommitEvent { commit_oid, .. } => {
+                    if !rewritten_oids.contains(commit_oid) {
+                        summary.created_commits.push(*commit_oid);
+                    }
+                }
+
+                Event::RewriteEvent {
+                    old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                    new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                    ..
+                } => summary
+                    .rewritten_commits
+                    .push((*old_commit_oid, *new_commit_oid)),
+
+                Event::ObsoleteEvent { commit_oid, .. }
+                | Event::RewriteEvent {
+                    old_commit_oid: MaybeZeroOid::NonZero(commit_oid),
+                    new_commit_oid: MaybeZeroOid::Zero,
+                    ..
+                } => set_visibility(*commit_oid, false),
+
+                Event::UnobsoleteEvent { commit_oid, .. }
+                | Event::RewriteEvent {
+                    old_commit_oid: MaybeZeroOid::Zero,
+                    new_commit_oid: MaybeZeroOid::NonZero(commit_oid),
+                    ..
+                } => set_visibility(*commit_oid, true),
+
+                Event::RefUpdateEvent {
+                    ref_name,
+                    old_oid,
+                    new_oid,
+                    ..
+                } if ref_name.as_str() == "HEAD" => {
+                    if let MaybeZeroOid::NonZero(new_oid) = new_oid {
+                        if old_oid != &MaybeZeroOid::NonZero(*new_oid) {
+                            summary.num_head_moves += 1;
+                            summary.last_head_oid = Some(*new_oid);
+                        }
+                    }
+                }
+
+                Event::RefUpdateEvent {
+                    ref_name,
+                    old_oid,
+                    new_oid,
+                    ..
+                } => match summary
+                    .moved_references
+                    .iter_mut()
+                    .find(|(other_ref_name, _, _)| other_ref_name == ref_name)
+                {
+                    Some((_, _, last_oid)) => *last_oid = *new_oid,
+                    None => summary
+                        .moved_references
+                        .push((ref_name.clone(), *old_oid, *new_oid)),
+                },
+
+                Event::RewriteEvent {
+                    old_commit_oid: MaybeZeroOid::Zero,
+                    new_commit_oid: MaybeZeroOid::Zero,
+                    ..
+                }
+                | Event::WorkingCopySnapshot { .. } => {}
+            }
+        }
+
+        for (oid, is_visible) in visibility_changes {
+            if is_visible {
+                summary.unhidden_commits.push(oid);
+            } else {
+                summary.hidden_commits.push(oid);
+            }
+        }
+        summary
+            .moved_references
+            .retain(|(_, first_oid, last_oid)| first_oid != last_oid);
+        summary
+    }
+
+    fn describe(&self, glyphs: &Glyphs, repo: &Repo) -> eyre::Result<Vec<StyledString>> {
+        let Self {
+            created_commits,
+            rewritten_commits,
+            hidden_commits,
+            unhidden_commits,
+            moved_references,
+            num_head_moves,
+            last_head_oid,
+        } = self;
+
+        let heading = |verb: &str, amount: usize, unit: (&str, &str)| {
+            StyledString::plain(format!(
+                "{} {}:",
+                verb,
+                Pluralize {
+                    determiner: None,
+                    amount,
+                    unit,
+                }
+            ))
+        };
+        let describe_commit = |oid: NonZeroOid| repo.friendly_describe_commit_from_oid(glyphs, oid);
+
+        let mut lines = Vec::new();
+        let push_commits =
+            |lines: &mut Vec<StyledString>, verb: &str, oids: &[NonZeroOid]| -> eyre::Result<()> {
+                if !oids.is_empty() {
+                    lines.push(heading(verb, oids.len(), ("commit", "commits")));
+                    for oid in oids {
+                        lines.push(
+                            StyledStringBuilder::new()
+                                .append_plain("  ")
+                                .append(describe_commit(*oid)?)
+                                .build(),
+                        );
+                    }
+                }
+                Ok(())
+            };
+        push_commits(&mut lines, "Created", created_commits)?;
+
+        if !rewritten_commits.is_empty() {
+            lines.push(heading(
+                "Rewrote",
+                rewritten_commits.len(),
+                ("commit", "commits"),
+            ));
+            for (old_oid, new_oid) in rewritten_commits {
+                lines.push(
+                    StyledStringBuilder::new()
+                        .append_plain("  ")
+                        .append(describe_commit(*old_oid)?)
+                        .build(),
+                );
+                lines.push(
+                    StyledStringBuilder::new()
+                        .append_plain("    as ")
+                        .append(describe_commit(*new_oid)?)
+                        .build(),
+                );
+            }
+        }
+        push_commits(&mut lines, "Hid", hidden_commits)?;
+        push_commits(&mut lines, "Unhid", unhidden_commits)?;
+
+        if !moved_references.is_empty() {
+            lines.push(heading(
+                "Updated",
+                moved_references.len(),
+                ("reference", "references"),
+            ));
+            for (ref_name, old_oid, new_oid) in moved_references {
+                let ref_name = CategorizedReferenceName::new(ref_name).friendly_describe();
+                let line = match (old_oid, new_oid) {
+                    (MaybeZeroOid::Zero, MaybeZeroOid::NonZero(new_oid)) => {
+                        StyledStringBuilder::new()
+                            .append_plain(format!("  Create {} at ", ref_name))
+                            .append(describe_commit(*new_oid)?)
+                            .build()
+                    }
+                    (MaybeZeroOid::NonZero(old_oid), MaybeZeroOid::Zero) => {
+                        StyledStringBuilder::new()
+                            .append_plain(format!("  Delete {} at ", ref_name))
+                            .append(describe_commit(*old_oid)?)
+                            .build()
+                    }
+                    (MaybeZeroOid::NonZero(_), MaybeZeroOid::NonZero(new_oid)) => {
+                        StyledStringBuilder::new()
+                            .append_plain(format!("  Move {} to ", ref_name))
+                            .append(describe_commit(*new_oid)?)
+                            .build()
+                    }
+                    (MaybeZeroOid::Zero, MaybeZeroOid::Zero) => continue,
+                };
+                lines.push(line);
+            }
+        }
+
+        if let Some(last_head_oid) = last_head_oid {
+            lines.push(
+                StyledStringBuilder::new()
+                    .append_plain(format!(
+                        "Moved HEAD {}, ending at ",
+                        Pluralize {
+                            determiner: None,
+                            amount: *num_head_moves,
+                            unit: ("time", "times"),
+                        }
+                    ))
+                    .append(describe_commit(*last_head_oid)?)
+                    .build(),
+            );
+        }
+
+        Ok(lines)
+    }
+}
+
+/// Print a summary of everything which changed in the repository between
+/// `since` and `until` (or now), without undoing any of it.
+fn print_changes_summary(
+    effects: &Effects,
+    since: SystemTime,
+    until: Option<SystemTime>,
+) -> eyre::Result<ExitCode> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let start_cursor = event_replayer.make_cursor_at_time(since);
+    let end_cursor = match until {
+        Some(until) => event_replayer.make_cursor_at_time(until),
+        None => event_replayer.make_default_cursor(),
+    };
+    let events = event_replayer.get_events_between_cursors(start_cursor, end_cursor);
+
+    let num_transactions = events
+        .iter()
+        .map(|event| event.get_event_tx_id())
+        .dedup()
+        .count();
+    if num_transactions == 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "No changes were recorded in that time."
+        )?;
+        return Ok(ExitCode(0));
+    }
+
+    let glyphs = effects.get_glyphs();
+    writeln!(
+        effects.get_output_stream(),
+        "Changes made in {}:",
+        Pluralize {
+            determiner: None,
+            amount: num_transactions,
+            unit: ("transaction", "transactions"),
+        }
+    )?;
+    let summary = ChangesSummary::from_events(events);
+    for line in summary.describe(glyphs, &repo)? {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            printable_styled_string(glyphs, line)?
+        )?;
+    }
+    Ok(ExitCode(0))
+}
+
+/// Undo all events in `event_replayer` since the beginning of the given
+/// transaction, after asking the user for confirmation.
+#[instrument]
+pub fn undo_since_transaction(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &mut EventLogDb,
+    event_replayer: &EventReplayer,
+    event_tx_id: EventTransactionId,
+) -> eyre::Result<ExitCode> {
+    let event_cursor = event_replayer.make_cursor_before_transaction(event_tx_id);
+    undo_events(
+        &mut stdin(),
+        effects,
+        repo,
+        git_run_info,
+        event_log_db,
+        event_replayer,
+        event_cursor,
+        &UndoReferenceFilter::default(),
+        false,
+    )
+}
+
 #[allow(missing_docs)]
 pub mod testing {
     use std::io::Read;
@@ -1014,6 +1539,7 @@ pub mod testing {
             event_log_db,
             event_replayer,
             event_cursor,
+            &Default::default(),
             false,
         )
     }
@@ -0,0 +1,44 @@
+//! Print the version of `git-branchless`, along with the capabilities of this
+//! build, so that wrappers such as editor plugins can detect which features are
+//! available.
+
+use std::fmt::Write;
+
+use lib::core::effects::Effects;
+use lib::core::migrations::get_latest_schema_version;
+use lib::util::ExitCode;
+use serde_json::json;
+use tracing::instrument;
+
+use crate::revset::get_function_names;
+
+/// The optional Cargo features of `git-branchless`, along with whether each
+/// was enabled in this build.
+const CARGO_FEATURES: &[(&str, bool)] = &[("man-pages", cfg!(feature = "man-pages"))];
+
+/// Print the version, either as text or as a single JSON object.
+#[instrument]
+pub fn version(effects: &Effects, json: bool) -> eyre::Result<ExitCode> {
+    let version = env!("CARGO_PKG_VERSION");
+    if !json {
+        writeln!(effects.get_output_stream(), "git-branchless {}", version)?;
+        return Ok(ExitCode(0));
+    }
+
+    let report = json!({
+        "version": version,
+        "event_log_schema_version": get_latest_schema_version(),
+        "revset_functions": get_function_names(),
+        "features": CARGO_FEATURES
+            .iter()
+            .filter(|(_name, is_enabled)| *is_enabled)
+            .map(|(name, _is_enabled)| name)
+            .collect::<Vec<_>>(),
+    });
+    writeln!(
+        effects.get_output_stream(),
+        "{}",
+        serde_json::to_string(&report)?
+    )?;
+    Ok(ExitCode(0))
+}
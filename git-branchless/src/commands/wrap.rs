@@ -2,7 +2,7 @@
 //! processing.
 
 use std::convert::TryInto;
-use std::process::Command;
+use std::process::{Command, Stdio};
 use std::time::SystemTime;
 
 use eyre::Context;
@@ -21,6 +21,7 @@ fn pass_through_git_command_inner(
         path_to_git,
         working_directory,
         env,
+        inherit_stdin,
     } = git_run_info;
     let mut command = Command::new(path_to_git);
     command.current_dir(working_directory);
@@ -30,6 +31,9 @@ fn pass_through_git_command_inner(
     if let Some(event_tx_id) = event_tx_id {
         command.env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
     }
+    if !inherit_stdin {
+        command.stdin(Stdio::null());
+    }
     let exit_status = command.status().wrap_err("Running Git command")?;
     let exit_code: isize = exit_status.code().unwrap_or(1).try_into()?;
     let exit_code = ExitCode(exit_code);
@@ -1,14 +1,19 @@
 //! The command-line options for `git-branchless`.
 
+use chrono::Local;
+use chrono_english::{parse_date_string, Dialect};
 use clap::{ArgEnum, Args, Command as ClapCommand, IntoApp, Parser};
+use itertools::Itertools;
 use lib::git::NonZeroOid;
 use man::Arg;
+use once_cell::sync::Lazy;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 /// A revset expression. Can be a commit hash, branch name, or one of the
 /// various revset functions.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Revset(pub String);
 
 impl FromStr for Revset {
@@ -19,6 +24,68 @@ impl FromStr for Revset {
     }
 }
 
+/// How long a commit must have been hidden before `git branchless gc --prune`
+/// removes the reference keeping it alive. Written like `30 days`,
+/// `2.weeks.ago`, or `now`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PruneWindow(pub Duration);
+
+impl FromStr for PruneWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = s
+            .split(|c: char| c.is_whitespace() || c == '.')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        let words = match words.as_slice() {
+            [words @ .., "ago"] => words,
+            words => words,
+        };
+        let (amount, unit) = match words {
+            ["now"] => return Ok(Self(Duration::ZERO)),
+            [amount, unit] => (amount, unit),
+            _ => {
+                return Err(format!(
+                    "expected a duration like `30 days`, but got: {}",
+                    s
+                ))
+            }
+        };
+        let amount: u64 = amount
+            .parse()
+            .map_err(|_| format!("invalid number in duration: {}", amount))?;
+        let unit_seconds = match unit.trim_end_matches('s') {
+            "second" | "sec" => 1,
+            "minute" | "min" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            _ => return Err(format!("unknown unit in duration: {}", unit)),
+        };
+        Ok(Self(Duration::from_secs(amount * unit_seconds)))
+    }
+}
+
+/// A point in time to inspect the repository at. Written like `2 days ago`,
+/// `yesterday`, `2022-06-01 12:00`, or `now`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PointInTime(pub SystemTime);
+
+impl FromStr for PointInTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "now" {
+            return Ok(Self(SystemTime::now()));
+        }
+        match parse_date_string(s, Local::now(), Dialect::Us) {
+            Ok(date) => Ok(Self(SystemTime::from(date))),
+            Err(_) => Err(format!("expected a time like `2 days ago`, but got: {}", s)),
+        }
+    }
+}
+
 /// A command wrapped by `git-branchless wrap`. The arguments are forwarded to
 /// `git`.
 #[derive(Parser)]
@@ -36,6 +103,11 @@ pub struct MoveOptions {
     #[clap(action, short = 'f', long = "force-rewrite", visible_alias = "fr")]
     pub force_rewrite_public_commits: bool,
 
+    /// Force moving or deleting branches which match
+    /// `branchless.core.protectedBranches`.
+    #[clap(action, long = "force-rewrite-protected-branches")]
+    pub force_rewrite_protected_branches: bool,
+
     /// Only attempt to perform an in-memory rebase. If it fails, do not
     /// attempt an on-disk rebase.
     #[clap(action, long = "in-memory", conflicts_with_all(&["force-on-disk", "merge"]))]
@@ -57,6 +129,12 @@ pub struct MoveOptions {
     #[clap(action, name = "merge", short = 'm', long = "merge")]
     pub resolve_merge_conflicts: bool,
 
+    /// Don't ask for confirmation before rewriting more commits or moving
+    /// more branches than `branchless.rewrite.confirmCommitThreshold` or
+    /// `branchless.rewrite.confirmBranchThreshold`.
+    #[clap(action, short = 'y', long = "yes")]
+    pub skip_confirmation: bool,
+
     /// Debugging option. Print the constraints used to create the rebase
     /// plan before executing it.
     #[clap(action, long = "debug-dump-rebase-constraints")]
@@ -104,6 +182,11 @@ pub struct TraverseCommitsOptions {
     )]
     pub interactive: bool,
 
+    /// When encountering multiple next commits, fail rather than prompting
+    /// which to advance to, even if running in a terminal. Useful in scripts.
+    #[clap(action, long = "no-interactive", conflicts_with("interactive"))]
+    pub no_interactive: bool,
+
     /// If the local changes conflict with the destination commit, attempt to
     /// merge them.
     #[clap(action, short = 'm', long = "merge")]
@@ -113,6 +196,11 @@ pub struct TraverseCommitsOptions {
     /// (Use with caution!)
     #[clap(action, short = 'f', long = "force", conflicts_with("merge"))]
     pub force: bool,
+
+    /// Move the currently-checked-out branch to the destination commit, and
+    /// keep it checked out, rather than checking out the destination commit.
+    #[clap(action, long = "with-branch", conflicts_with("move-by-branches"))]
+    pub with_branch: bool,
 }
 
 /// Options for checking out a commit.
@@ -122,13 +210,31 @@ pub struct CheckoutOptions {
     #[clap(action, short = 'i', long = "interactive")]
     pub interactive: bool,
 
+    /// If the target matches multiple commits, fail rather than prompting
+    /// which one to check out, even if running in a terminal. Useful in
+    /// scripts.
+    #[clap(action, long = "no-interactive", conflicts_with("interactive"))]
+    pub no_interactive: bool,
+
     /// When checking out the target commit, also create a branch with the
     /// provided name pointing to that commit.
-    #[clap(value_parser, short = 'b', long = "branch")]
+    #[clap(
+        value_parser,
+        short = 'b',
+        visible_short_alias = 'c',
+        long = "branch",
+        visible_alias = "create"
+    )]
     pub branch_name: Option<String>,
 
+    /// Check out the target commit without checking out a branch, even if the
+    /// target is a branch name.
+    #[clap(action, short = 'd', long = "detach", conflicts_with("branch-name"))]
+    pub detach: bool,
+
     /// Forcibly switch commits, discarding any working copy changes if
-    /// necessary.
+    /// necessary. If `branchless.undo.createSnapshots` is enabled (the
+    /// default), the discarded changes can be recovered with `git undo`.
     #[clap(action, short = 'f', long = "force")]
     pub force: bool,
 
@@ -137,7 +243,24 @@ pub struct CheckoutOptions {
     #[clap(action, short = 'm', long = "merge", conflicts_with("force"))]
     pub merge: bool,
 
-    /// The commit or branch to check out.
+    /// Check out the target commit in the worktree with the given name,
+    /// rather than in the current worktree. Run `git worktree list` to see the
+    /// available worktrees.
+    #[clap(value_parser, long = "worktree")]
+    pub worktree: Option<String>,
+
+    /// Check out the head of the stack with the given topic, rather than a
+    /// commit or branch. Topics are inferred from the branch names, ticket IDs,
+    /// or paths touched by each stack, and are shown in the smartlog.
+    #[clap(
+        value_parser,
+        long = "topic",
+        conflicts_with_all(&["target", "interactive"])
+    )]
+    pub topic: Option<String>,
+
+    /// The commit or branch to check out. This can be any revset expression
+    /// which evaluates to exactly one commit, such as `stack() & message(foo)`.
     ///
     /// If this is not provided, then interactive commit selection starts as
     /// if `--interactive` were passed.
@@ -148,29 +271,184 @@ pub struct CheckoutOptions {
     pub target: Option<String>,
 }
 
+/// Options for recording changes.
+#[derive(Args, Debug)]
+pub struct RecordOptions {
+    /// The commit message to use. If not provided, will be prompted to provide a commit message
+    /// interactively.
+    #[clap(value_parser, short = 'm', long = "message")]
+    pub message: Option<String>,
+
+    /// Select changes to include interactively, rather than using the
+    /// current staged/unstaged changes.
+    #[clap(action, short = 'i', long = "interactive", visible_alias = "patch")]
+    pub interactive: bool,
+
+    /// Detach the current branch before committing.
+    #[clap(action, short = 'd', long = "detach", conflicts_with("amend"))]
+    pub detach: bool,
+
+    /// Amend the current commit with the changes instead of creating a new
+    /// commit. The existing message is opened in the editor unless `--message`
+    /// or `--no-edit` is passed.
+    #[clap(action, long = "amend")]
+    pub amend: bool,
+
+    /// When amending, keep the existing commit message without opening the
+    /// editor.
+    #[clap(action, long = "no-edit", requires("amend"), conflicts_with("message"))]
+    pub no_edit: bool,
+
+    /// Also include untracked files which aren't ignored. With
+    /// `--interactive`, they can be selected like any other change.
+    #[clap(action, short = 'a', long = "all")]
+    pub all: bool,
+
+    /// Leave changes to paths matching the given glob out of the commit. Can be
+    /// passed multiple times. Staged changes to excluded paths are unstaged
+    /// rather than committed.
+    #[clap(value_parser, long = "exclude")]
+    pub exclude: Vec<String>,
+}
+
 /// FIXME: write man-page text
 #[derive(Parser)]
 pub enum Command {
+    /// Abort an operation which stopped partway through due to a merge
+    /// conflict or interruption, such as `git sync` or `git move --on-disk`,
+    /// and undo the changes it already made.
+    Abort,
+
     /// Amend the current HEAD commit.
     Amend {
+        /// Select the changes to amend the commit with interactively. The
+        /// changes which aren't selected are left in the working copy.
+        #[clap(action, short = 'p', long = "patch")]
+        patch: bool,
+
         /// Options for moving commits.
         #[clap(flatten)]
         move_options: MoveOptions,
     },
 
+    /// Manage branches for the commits in the current stack.
+    ///
+    /// With `--stack`, create or update a branch named
+    /// `<user>/<stack-name>/<n>` pointing to the `n`th commit in the current
+    /// stack, for code forges which review one branch per commit. The user name
+    /// is `branchless.stackBranches.user`, or the local part of `user.email`.
+    /// These branches are renumbered automatically whenever the commits in the
+    /// stack are rewritten.
+    Branch {
+        /// Create or update a branch for each commit in the current stack.
+        #[clap(action, long = "stack")]
+        stack: bool,
+
+        /// The name of the stack to use in the branch names. Defaults to the
+        /// name of the existing stack branches, the name set with `git
+        /// branchless meta name-stack`, or the summary of the stack's first
+        /// commit, in that order.
+        #[clap(value_parser, long = "name")]
+        name: Option<String>,
+
+        /// Only print the branches which would be created, moved or deleted.
+        #[clap(action, long = "dry-run")]
+        dry_run: bool,
+    },
+
     /// Gather information about recent operations to upload as part of a bug
     /// report.
-    BugReport,
+    ///
+    /// The report includes the versions of `git-branchless` and Git, the Git
+    /// configuration (with credentials redacted), the installed hooks, the
+    /// most recent events, and the trace of the last command, if
+    /// `branchless.bugReport.recordTraces` is set. By default, branch names,
+    /// commit messages, and file paths are redacted.
+    BugReport {
+        /// Ask whether to include commit messages, file paths, and the trace
+        /// of the last command in the report, rather than redacting them.
+        #[clap(action, short = 'i', long = "interactive")]
+        interactive: bool,
+
+        /// Write the report to the given file instead of stdout.
+        #[clap(value_parser, short = 'o', long = "output", value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
 
     /// Check out a given commit.
+    #[clap(visible_alias = "switch")]
     Checkout {
         /// Options for checking out a commit.
         #[clap(flatten)]
         checkout_options: CheckoutOptions,
     },
 
+    /// Resume an operation which stopped partway through due to a merge
+    /// conflict or interruption, such as `git sync` or `git move --on-disk`.
+    /// Any rebase in progress is continued first.
+    Continue,
+
+    /// Get, set, or list `git-branchless` configuration settings.
+    ///
+    /// Settings can be stored for the current repository (in
+    /// `.git/branchless/config`), for the current user (in `~/.gitconfig`), or
+    /// for the whole system. Only known keys can be set, and their values are
+    /// checked against the expected type.
+    Config {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: ConfigSubcommand,
+    },
+
+    /// Run a background process which keeps the smartlog for this repository
+    /// up-to-date, so that `git smartlog` can be served from its cache.
+    Daemon {
+        /// How often to check the repository for changes, in milliseconds.
+        #[clap(value_parser, long = "poll-interval", default_value = "1000")]
+        poll_interval: u64,
+    },
+
+    /// Show the combined changes made by a set of commits.
+    ///
+    /// The diff is computed from the parent of the revset's root commit to its
+    /// head commit, so the revset must have exactly one root and one head. Any
+    /// commits between the root and the head are included in the diff, even
+    /// if they're not in the revset.
+    Diff {
+        /// The commits whose changes to show. Defaults to the current stack.
+        #[clap(value_parser, default_value = "stack()")]
+        revset: Revset,
+
+        /// Show the number of lines changed in each file instead of the full
+        /// patch.
+        #[clap(action, long = "stat", conflicts_with = "name-only")]
+        stat: bool,
+
+        /// Show only the paths of the changed files.
+        #[clap(action, long = "name-only")]
+        name_only: bool,
+
+        /// Highlight the words which changed within modified lines. Can also
+        /// be enabled with the `branchless.diff.wordDiff` config option.
+        #[clap(action, long = "word-diff")]
+        word_diff: bool,
+    },
+
     /// Run internal garbage collection.
-    Gc,
+    ///
+    /// Hidden commits are kept alive by references under `refs/branchless/`,
+    /// so that they can be restored with `git undo`. These references are
+    /// only removed by this command, after which Git's own garbage collection
+    /// may delete the commits.
+    Gc {
+        /// Only remove the references for commits which have been hidden for
+        /// longer than the given window, such as `30 days` or `2.weeks.ago`,
+        /// and list the commits which will no longer be recoverable. The
+        /// pruning is recorded in the event log. Without this option, the
+        /// references for all hidden commits are removed.
+        #[clap(value_parser, long = "prune", value_name = "WINDOW")]
+        prune: Option<PruneWindow>,
+    },
 
     /// Hide the provided commits from the smartlog.
     Hide {
@@ -186,6 +464,11 @@ pub enum Command {
         /// commits.
         #[clap(action, short = 'r', long = "recursive")]
         recursive: bool,
+
+        /// Show the smartlog as it would look after this operation, without
+        /// changing any commits or branches.
+        #[clap(action, long = "dry-run")]
+        dry_run: bool,
     },
 
     /// Internal use.
@@ -248,6 +531,14 @@ pub enum Command {
     #[clap(hide = true)]
     HookRegisterExtraPostRewriteHook,
 
+    /// Internal use.
+    #[clap(hide = true)]
+    HookSkipFoldedCommit {
+        /// The OID of the commit that was folded into another commit.
+        #[clap(value_parser)]
+        commit_oid: String,
+    },
+
     /// Internal use.
     #[clap(hide = true)]
     HookSkipUpstreamAppliedCommit {
@@ -268,6 +559,60 @@ pub enum Command {
         /// then you will be prompted to enter a value for the main branch name.
         #[clap(value_parser, long = "main-branch", conflicts_with = "uninstall")]
         main_branch_name: Option<String>,
+
+        /// How to install the Git hooks.
+        #[clap(
+            value_parser,
+            long = "hook-type",
+            arg_enum,
+            default_value = "regular",
+            conflicts_with = "uninstall"
+        )]
+        hook_type: HookType,
+
+        /// Don't prompt for any configuration, and accept the defaults
+        /// instead. The configuration wizard is only shown by default when
+        /// running in a terminal.
+        #[clap(action, short = 'y', long = "yes", conflicts_with = "uninstall")]
+        yes: bool,
+
+        /// Walk through the configuration wizard even when not running in a
+        /// terminal, reading the answers from stdin.
+        #[clap(
+            action,
+            long = "interactive",
+            conflicts_with_all(&["uninstall", "yes"])
+        )]
+        interactive: bool,
+    },
+
+    /// Find the newest versions of a commit which has since been rewritten.
+    ///
+    /// Prints the OIDs of the commits which the given commit was most recently
+    /// rewritten into, one per line. If the commit was never rewritten, prints
+    /// the commit's own OID. The commit may be given as an abbreviated OID,
+    /// even if it has since been garbage-collected.
+    Lookup {
+        /// The OID of the commit to look up.
+        #[clap(value_parser, required_unless_present = "all")]
+        commit: Option<String>,
+
+        /// Instead of looking up a single commit, print each rewritten commit
+        /// and its newest versions as pairs of OIDs, one pair per line. A
+        /// commit which was deleted is paired with the zero OID.
+        #[clap(action, long = "all", conflicts_with = "commit")]
+        all: bool,
+    },
+
+    /// Get or set metadata attached to commits, such as the associated code
+    /// review or the status of a test run.
+    ///
+    /// Metadata follows a commit when it's rewritten, such as by amending or
+    /// rebasing it, and is displayed in the smartlog.
+    Meta {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: MetaSubcommand,
     },
 
     /// Move a subtree of commits from one location to another.
@@ -317,9 +662,37 @@ pub enum Command {
         #[clap(action, short = 'I', long = "insert")]
         insert: bool,
 
+        /// Instead of moving commits, fold the `fixup!` and `squash!` commits
+        /// in the current stack into the commits that they refer to, as with
+        /// `git rebase --autosquash`.
+        #[clap(
+            action,
+            long = "fixup",
+            conflicts_with_all(&["source", "base", "exact", "dest", "insert"])
+        )]
+        fixup: bool,
+
+        /// Instead of moving commits within this repository, apply the source
+        /// commits and their descendants as patches on top of the commit
+        /// checked out in the repository at the given path, such as a mirror
+        /// of this repository. Commits which were already applied to that
+        /// repository are skipped, as detected by their patch IDs.
+        #[clap(
+            value_parser,
+            long = "to-repo",
+            value_name = "PATH",
+            conflicts_with_all(&["base", "exact", "dest", "insert", "fixup"])
+        )]
+        to_repo: Option<PathBuf>,
+
         /// Options for moving commits.
         #[clap(flatten)]
         move_options: MoveOptions,
+
+        /// The format to report the rewritten commits, moved branches, and
+        /// merge conflicts in.
+        #[clap(value_parser, long = "output", arg_enum, default_value = "text")]
+        output: OutputFormat,
     },
 
     /// Move to a later commit in the current stack.
@@ -336,6 +709,54 @@ pub enum Command {
         traverse_commits_options: TraverseCommitsOptions,
     },
 
+    /// Delete local branches which were merged into the main branch or whose
+    /// upstream branch was deleted, and hide their commits.
+    ///
+    /// A branch was merged if all of its commits are in the main branch, or
+    /// have equivalent commits (with the same patch IDs) in the main branch,
+    /// such as after its pull request was rebased or squashed onto it. The
+    /// remote-tracking branches are used as of the last fetch. The pruning can
+    /// be reverted with `git undo`.
+    PruneRemote {
+        /// Run `git fetch --prune` first, so that branches deleted on the
+        /// remote are detected.
+        #[clap(action, short = 'f', long = "fetch")]
+        fetch: bool,
+
+        /// Only list the branches and commits which would be pruned.
+        #[clap(action, long = "dry-run")]
+        dry_run: bool,
+
+        /// Skip confirmation and prune the branches immediately.
+        #[clap(action, short = 'y', long = "yes")]
+        yes: bool,
+    },
+
+    /// Push branches to their remotes, force-pushing rewritten branches with
+    /// `--force-with-lease`.
+    ///
+    /// Each branch is expected to have the value on the remote which its
+    /// remote-tracking branch had as of the last fetch or push, as recorded in
+    /// the event log. If someone else has pushed to the branch since then, then
+    /// the push for that branch is rejected instead of overwriting their
+    /// commits. Branches which are already up to date aren't pushed.
+    Push {
+        /// The commits whose branches should be pushed. If no commits are
+        /// provided, the branches in the current stack are pushed.
+        #[clap(value_parser)]
+        revsets: Vec<Revset>,
+
+        /// The remote to push to. By default, each branch is pushed to its
+        /// push remote or upstream remote, as `git push` would.
+        #[clap(value_parser, short = 'r', long = "remote")]
+        remote: Option<String>,
+
+        /// Only list the branches which would be pushed, and their expected
+        /// values on the remote.
+        #[clap(action, long = "dry-run")]
+        dry_run: bool,
+    },
+
     /// Query the commit graph using the "revset" language and print matching
     /// commits.
     ///
@@ -360,40 +781,46 @@ pub enum Command {
     },
 
     /// Restore internal invariants by reconciling the internal operation log
-    /// with the state of the Git repository.
+    /// and commit graph with the state of the Git repository.
+    #[clap(visible_alias = "doctor")]
     Repair {
         /// Apply changes.
         #[clap(action(clap::ArgAction::SetFalse), long = "no-dry-run")]
         dry_run: bool,
+
+        /// Only check whether the schema of the git-branchless database is
+        /// up to date with this version of git-branchless, without applying
+        /// any migrations or checking anything else.
+        #[clap(action, long = "check-schema", conflicts_with("dry-run"))]
+        check_schema: bool,
     },
 
     /// Fix up commits abandoned by a previous rewrite operation.
     Restack {
-        /// The IDs of the abandoned commits whose descendants should be
-        /// restacked. If not provided, all abandoned commits are restacked.
+        /// The abandoned commits whose descendants should be restacked. A
+        /// commit which an abandoned commit was rewritten into also selects
+        /// it, so `git restack 'stack()'` restacks the commits abandoned by
+        /// rewriting the current stack. If not provided, all abandoned commits
+        /// are restacked, or only those for the current stack if
+        /// `branchless.restack.currentStackOnly` is set.
         #[clap(value_parser)]
         commits: Vec<Revset>,
 
         /// Options for moving commits.
         #[clap(flatten)]
         move_options: MoveOptions,
+
+        /// The format to report the rewritten commits, moved branches, and
+        /// merge conflicts in.
+        #[clap(value_parser, long = "output", arg_enum, default_value = "text")]
+        output: OutputFormat,
     },
 
     /// Create a commit by interactively selecting which changes to include.
     Record {
-        /// The commit message to use. If not provided, will be prompted to provide a commit message
-        /// interactively.
-        #[clap(value_parser, short = 'm', long = "message")]
-        message: Option<String>,
-
-        /// Select changes to include interactively, rather than using the
-        /// current staged/unstaged changes.
-        #[clap(action, short = 'i', long = "interactive")]
-        interactive: bool,
-
-        /// Detach the current branch before committing.
-        #[clap(action, short = 'd', long = "detach")]
-        detach: bool,
+        /// Options for recording changes.
+        #[clap(flatten)]
+        record_options: RecordOptions,
     },
 
     /// Reword commits.
@@ -409,6 +836,10 @@ pub enum Command {
         #[clap(action, short = 'f', long = "force-rewrite", visible_alias = "fr")]
         force_rewrite_public_commits: bool,
 
+        /// Force moving branches which match `branchless.core.protectedBranches`.
+        #[clap(action, long = "force-rewrite-protected-branches")]
+        force_rewrite_protected_branches: bool,
+
         /// Message to apply to commits. Multiple messages will be combined as separate paragraphs,
         /// similar to `git commit`.
         #[clap(value_parser, short = 'm', long = "message")]
@@ -420,6 +851,98 @@ pub enum Command {
         /// that; otherwise, the editor starts empty.
         #[clap(action, short = 'd', long = "discard", conflicts_with("messages"))]
         discard: bool,
+
+        /// Read the new commit message(s) from the given file, or from stdin if
+        /// the file is `-`, instead of invoking an editor.
+        ///
+        /// If the file contains `++ reword <hash>` lines, it's parsed in the
+        /// same format as the message shown in the editor when rewording
+        /// multiple commits. Otherwise, its contents are used as the message
+        /// for every commit, as with `--message`.
+        #[clap(
+            value_parser,
+            short = 'F',
+            long = "message-file",
+            conflicts_with_all(&["messages", "discard"])
+        )]
+        message_file: Option<PathBuf>,
+
+        /// Retry the last reword which failed because the edited message didn't
+        /// match the commits being reworded.
+        ///
+        /// The message saved in `.git/REWORD_EDITMSG` is opened in the editor
+        /// again, and then applied to the same commits as the original reword.
+        #[clap(
+            action,
+            long = "resume",
+            conflicts_with_all(&["revsets", "messages", "discard", "message-file"])
+        )]
+        resume: bool,
+
+        /// Re-wrap the bodies of the commit messages instead of invoking an
+        /// editor.
+        ///
+        /// Paragraphs and list items are wrapped to the column given by
+        /// `--reflow-column`, or by `branchless.reword.reflowColumn` (72 by
+        /// default). The subject line, code blocks, indented lines,
+        /// footnote-style lines such as `[1]: <url>`, and trailers such as
+        /// `Signed-off-by:` are left as-is.
+        #[clap(
+            action,
+            long = "reflow",
+            conflicts_with_all(&["messages", "discard", "message-file", "resume"])
+        )]
+        reflow: bool,
+
+        /// The column to wrap commit message bodies at with `--reflow`.
+        #[clap(value_parser, long = "reflow-column", requires("reflow"))]
+        reflow_column: Option<usize>,
+
+        /// Show the smartlog as it would look after this operation, without
+        /// changing any commits or branches.
+        #[clap(action, long = "dry-run")]
+        dry_run: bool,
+
+        /// Don't ask for confirmation before rewording more commits or moving
+        /// more branches than `branchless.rewrite.confirmCommitThreshold` or
+        /// `branchless.rewrite.confirmBranchThreshold`.
+        #[clap(action, short = 'y', long = "yes")]
+        skip_confirmation: bool,
+
+        /// The format to report the rewritten commits, moved branches, and
+        /// merge conflicts in.
+        #[clap(value_parser, long = "output", arg_enum, default_value = "text")]
+        output: OutputFormat,
+    },
+
+    /// Run a Lua script which can query the commit graph with revsets and
+    /// rewrite commits.
+    ///
+    /// The script can access the API through the global `branchless` table,
+    /// such as `branchless.query(revset)`, `branchless.commit(oid)`,
+    /// `branchless.stacks()`, `branchless.move(source, dest)`,
+    /// `branchless.reword(revset, message)`, and `branchless.git(...)`.
+    Run {
+        /// The path to the Lua script to run.
+        #[clap(value_parser)]
+        script: PathBuf,
+
+        /// Arguments to pass to the script, available as `branchless.args`.
+        #[clap(value_parser)]
+        args: Vec<String>,
+    },
+
+    /// Run a server which answers JSON-RPC requests over stdin and stdout, so
+    /// that editor extensions can query the smartlog, evaluate revsets, and
+    /// hide, reword, or move commits without spawning a process per request.
+    ///
+    /// Messages are framed with a `Content-Length` header, as in the Language
+    /// Server Protocol.
+    Serve {
+        /// Communicate over stdin and stdout. This is currently the only
+        /// supported transport.
+        #[clap(action, long = "stdio", required = true)]
+        stdio: bool,
     },
 
     /// Display a nice graph of the commits you've recently worked on.
@@ -434,10 +957,46 @@ pub enum Command {
         #[clap(value_parser, long = "event-id")]
         event_id: Option<isize>,
 
+        /// Show the smartlog as it was at the given time, such as `2 days ago`
+        /// or `yesterday`, including the branch positions and hidden commits
+        /// at that time.
+        #[clap(
+            value_parser,
+            long = "at",
+            value_name = "TIME",
+            conflicts_with("event-id")
+        )]
+        at: Option<PointInTime>,
+
         /// The commits to render. These commits and their ancestors up to the
         /// main branch will be rendered.
         #[clap(value_parser, default_value = "draft()")]
         revset: Revset,
+
+        /// Also show the commits checked out in other worktrees of this
+        /// repository, annotated with the names of those worktrees.
+        #[clap(action, long = "all-worktrees")]
+        all_worktrees: bool,
+
+        /// Collapse each stack of draft commits into a single line, showing its
+        /// root commit and the number of hidden commits. The stack containing
+        /// `HEAD` is always shown in full.
+        #[clap(action, long = "collapsed")]
+        collapsed: bool,
+
+        /// When collapsing stacks, also show in full the stacks containing
+        /// these commits.
+        #[clap(
+            action(clap::ArgAction::Append),
+            long = "expand",
+            requires = "collapsed"
+        )]
+        expand: Vec<Revset>,
+
+        /// The format to render the smartlog in. Use `html` or `dot` to embed
+        /// the commit graph in documentation or pull request descriptions.
+        #[clap(value_parser, long = "format", arg_enum, default_value = "text")]
+        format: SmartlogFormat,
     },
 
     #[clap(hide = true)]
@@ -448,6 +1007,47 @@ pub enum Command {
         subcommand: SnapshotSubcommand,
     },
 
+    /// Split a commit into one commit per group of the paths that it changes,
+    /// and restack its descendants on top of the new commits.
+    ///
+    /// Each new commit keeps the original message, with the group added to
+    /// the end of its subject line.
+    Split {
+        /// The commit to split.
+        #[clap(value_parser, default_value = "HEAD")]
+        revset: Revset,
+
+        /// Make one commit per top-level directory, and one for the changed
+        /// files at the top level of the repository.
+        #[clap(
+            action,
+            long = "by-directory",
+            conflicts_with = "group",
+            required_unless_present = "group"
+        )]
+        by_directory: bool,
+
+        /// Make one commit for the paths matching any of the given
+        /// comma-separated glob patterns, such as `src/*,tests/*`. May be
+        /// passed multiple times; a path belongs to the first group which
+        /// matches it, and any unmatched paths are committed last.
+        #[clap(action(clap::ArgAction::Append), short = 'g', long = "group")]
+        group: Vec<String>,
+
+        /// Options for moving commits.
+        #[clap(flatten)]
+        move_options: MoveOptions,
+    },
+
+    /// Summarize the stacks of draft commits in the repository, such as the
+    /// number of commits in each stack, how old the commits are, and how many
+    /// lines they change.
+    Stats {
+        /// Print the statistics as a single JSON object.
+        #[clap(action, long = "json")]
+        json: bool,
+    },
+
     /// Move any local commit stacks on top of the main branch.
     Sync {
         /// Run `git fetch` to update remote references before carrying out the
@@ -465,10 +1065,20 @@ pub enum Command {
         #[clap(flatten)]
         move_options: MoveOptions,
 
+        /// Show the smartlog as it would look after syncing, without changing
+        /// any commits or branches.
+        #[clap(action, long = "dry-run", conflicts_with = "update-refs")]
+        dry_run: bool,
+
         /// The commits whose stacks will be moved on top of the main branch. If
         /// no commits are provided, all draft commits will be synced.
         #[clap(value_parser)]
         revsets: Vec<Revset>,
+
+        /// The format to report the rewritten commits, moved branches, and
+        /// merge conflicts in.
+        #[clap(value_parser, long = "output", arg_enum, default_value = "text")]
+        output: OutputFormat,
     },
 
     /// Browse or return to a previous state of the repository.
@@ -481,6 +1091,34 @@ pub enum Command {
         /// Skip confirmation and apply changes immediately.
         #[clap(action, short = 'y', long = "yes")]
         yes: bool,
+
+        /// Don't revert updates to remote-tracking branches, such as those
+        /// made by `git fetch`.
+        #[clap(action, long = "exclude-remotes")]
+        exclude_remotes: bool,
+
+        /// Only revert updates to references matching the given glob, such as
+        /// `origin/*` or `refs/remotes/origin/main`, and leave commits and the
+        /// working copy alone. The checked-out branch can't be restored this
+        /// way. Can be passed multiple times.
+        #[clap(action(clap::ArgAction::Append), long = "refs")]
+        refs: Vec<String>,
+
+        /// Instead of undoing anything, print a summary of the commits,
+        /// references, and checkouts which changed since the given time, such
+        /// as `yesterday` or `8 hours ago`.
+        #[clap(
+            value_parser,
+            long = "since",
+            value_name = "TIME",
+            conflicts_with_all(&["interactive", "yes", "exclude-remotes", "refs"])
+        )]
+        since: Option<PointInTime>,
+
+        /// When summarizing changes with `--since`, only include changes up to
+        /// the given time, rather than up to now.
+        #[clap(value_parser, long = "until", value_name = "TIME", requires = "since")]
+        until: Option<PointInTime>,
     },
 
     /// Unhide previously-hidden commits from the smartlog.
@@ -494,6 +1132,18 @@ pub enum Command {
         recursive: bool,
     },
 
+    /// Print the version of `git-branchless`.
+    ///
+    /// With `--json`, also print the capabilities of this build, such as the
+    /// supported event log schema version, revset functions, and enabled Cargo
+    /// features, so that tools which wrap `git-branchless` can detect which
+    /// features are available.
+    Version {
+        /// Print the version and capabilities as a single JSON object.
+        #[clap(action, long = "json")]
+        json: bool,
+    },
+
     /// Wrap a Git command inside a branchless transaction.
     Wrap {
         /// The `git` executable to invoke.
@@ -506,6 +1156,77 @@ pub enum Command {
     },
 }
 
+impl Command {
+    /// Whether the command's output is piped into the pager by default when
+    /// writing to a terminal, because it can be long.
+    pub fn uses_pager_by_default(&self) -> bool {
+        matches!(self, Command::Smartlog { .. } | Command::Query { .. })
+    }
+
+    /// Whether the trace of the command should be recorded for use in bug
+    /// reports. Hooks run on behalf of other commands, and `bug-report` would
+    /// overwrite the trace that it's reporting.
+    pub fn records_trace(&self) -> bool {
+        !matches!(
+            self,
+            Command::BugReport { .. }
+                | Command::HookDetectEmptyCommit { .. }
+                | Command::HookPreAutoGc
+                | Command::HookPostCheckout { .. }
+                | Command::HookPostCommit
+                | Command::HookPostMerge { .. }
+                | Command::HookPostRewrite { .. }
+                | Command::HookReferenceTransaction { .. }
+                | Command::HookRegisterExtraPostRewriteHook
+                | Command::HookSkipFoldedCommit { .. }
+                | Command::HookSkipUpstreamAppliedCommit { .. }
+        )
+    }
+}
+
+/// The format to report the results of a rewrite operation in.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text. This is the default behavior.
+    Text,
+
+    /// A single JSON object printed to stdout, describing the rewritten
+    /// commits, moved branches, and merge conflicts. Human-readable output is
+    /// printed to stderr instead.
+    Json,
+}
+
+/// The format to render the smartlog in.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmartlogFormat {
+    /// The commit graph as displayed in the terminal. This is the default
+    /// behavior.
+    Text,
+
+    /// The commit graph as an HTML `<pre>` block, with colors rendered as
+    /// inline styles.
+    Html,
+
+    /// The commit graph in the DOT language, to be drawn with Graphviz.
+    Dot,
+}
+
+/// How `git branchless init` installs the Git hooks.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookType {
+    /// Write the hook script text into each hook file. This is the default
+    /// behavior.
+    Regular,
+
+    /// Make each hook a symlink to a shared hook script, so that upgrading
+    /// `git-branchless` upgrades the hooks without re-running `init`. The
+    /// shared script is taken from `branchless.hooks.sharedScript` if set, or
+    /// from `share/git-branchless/git-branchless-hook` next to the
+    /// installation prefix, or else it's written into the repository. Falls
+    /// back to regular hooks if symlinks can't be created.
+    Symlink,
+}
+
 /// Whether to display terminal colors.
 #[derive(ArgEnum, Clone)]
 pub enum ColorSetting {
@@ -533,6 +1254,26 @@ pub struct Opts {
     #[clap(value_parser, long = "color", arg_enum, global = true)]
     pub color: Option<ColorSetting>,
 
+    /// Pipe output into the pager (see `core.pager`), even if the command
+    /// doesn't use the pager by default or output isn't to a terminal.
+    #[clap(action, long = "paginate", global = true, conflicts_with = "no-pager")]
+    pub paginate: bool,
+
+    /// Don't pipe output into the pager. By default, long output, such as from
+    /// `git smartlog` and `git query`, is paged when writing to a terminal.
+    #[clap(action, long = "no-pager", global = true)]
+    pub no_pager: bool,
+
+    /// Print a breakdown of the time spent in each part of the operation to
+    /// stderr once it finishes.
+    #[clap(action, long = "profile", global = true)]
+    pub profile: bool,
+
+    /// Write a trace of the operation to the given file, which can be opened
+    /// in the Chrome trace viewer (`chrome://tracing`).
+    #[clap(value_parser, long = "profile-trace", global = true)]
+    pub profile_trace: Option<PathBuf>,
+
     /// The `git-branchless` subcommand to run.
     #[clap(subcommand)]
     pub command: Command,
@@ -555,6 +1296,370 @@ pub enum SnapshotSubcommand {
     },
 }
 
+/// `meta` subcommands.
+#[derive(Parser)]
+pub enum MetaSubcommand {
+    /// Print the metadata for a commit.
+    ///
+    /// Prints each key and its value, one per line. If a key is provided, then
+    /// prints only the value for that key, and exits with a non-zero exit code
+    /// if the key is not set.
+    Get {
+        /// The commit to get the metadata for.
+        #[clap(value_parser, short = 'c', long = "commit", default_value = "HEAD")]
+        commit: Revset,
+
+        /// The key to get the value for.
+        #[clap(value_parser)]
+        key: Option<String>,
+    },
+
+    /// Set the value for a key on the given commits.
+    Set {
+        /// The commits to set the metadata for.
+        #[clap(value_parser, short = 'c', long = "commit", default_value = "HEAD")]
+        commits: Revset,
+
+        /// The key to set.
+        #[clap(value_parser)]
+        key: String,
+
+        /// The value to set.
+        #[clap(value_parser)]
+        value: String,
+    },
+
+    /// Unset a key on the given commits.
+    Unset {
+        /// The commits to unset the metadata for.
+        #[clap(value_parser, short = 'c', long = "commit", default_value = "HEAD")]
+        commits: Revset,
+
+        /// The key to unset.
+        #[clap(value_parser)]
+        key: String,
+    },
+
+    /// Assign a name to the stack containing the given commit.
+    ///
+    /// The name is stored on the root commit of the stack, and is displayed in
+    /// the smartlog in place of the stack's inferred topic. The stack can then
+    /// be checked out with `git switch --topic <name>`.
+    NameStack {
+        /// A commit in the stack to name.
+        #[clap(value_parser, short = 'c', long = "commit", default_value = "HEAD")]
+        commit: Revset,
+
+        /// The name to assign to the stack.
+        #[clap(value_parser, required_unless_present = "clear")]
+        name: Option<String>,
+
+        /// Remove the name from the stack instead.
+        #[clap(action, long = "clear", conflicts_with = "name")]
+        clear: bool,
+    },
+}
+
+/// `config` subcommands.
+#[derive(Parser)]
+pub enum ConfigSubcommand {
+    /// List configuration settings.
+    ///
+    /// Without `--scope`, lists every known key with its effective value,
+    /// marking keys which are using their default value. With `--scope`,
+    /// lists only the settings stored in that scope.
+    List {
+        /// Only list the settings stored in the given scope.
+        #[clap(value_parser, arg_enum, long = "scope")]
+        scope: Option<ConfigScope>,
+
+        /// Include a description of each key.
+        #[clap(action, short = 'v', long = "verbose")]
+        verbose: bool,
+    },
+
+    /// Print the effective value of a setting.
+    ///
+    /// Prints the default value if the key is not set. Exits with a non-zero
+    /// exit code if the key is not set and has no default.
+    Get {
+        /// The key to get, such as `branchless.core.mainBranch`. The
+        /// `branchless.` prefix may be omitted.
+        #[clap(value_parser)]
+        key: String,
+    },
+
+    /// Set a setting in the given scope.
+    Set {
+        /// The scope to store the setting in.
+        #[clap(value_parser, arg_enum, long = "scope", default_value = "repo")]
+        scope: ConfigScope,
+
+        /// The key to set, such as `branchless.core.mainBranch`. The
+        /// `branchless.` prefix may be omitted.
+        #[clap(value_parser)]
+        key: String,
+
+        /// The value to set. Keys which accept several values may be given
+        /// more than one, which replace any existing values.
+        #[clap(value_parser, required = true)]
+        values: Vec<String>,
+    },
+
+    /// Remove a setting from the given scope.
+    Unset {
+        /// The scope to remove the setting from.
+        #[clap(value_parser, arg_enum, long = "scope", default_value = "repo")]
+        scope: ConfigScope,
+
+        /// The key to unset.
+        #[clap(value_parser)]
+        key: String,
+    },
+}
+
+/// Where a configuration setting is stored.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigScope {
+    /// The `git-branchless` configuration for the current repository, at
+    /// `.git/branchless/config`.
+    Repo,
+
+    /// The current user's global Git configuration, usually `~/.gitconfig`.
+    User,
+
+    /// The system-wide Git configuration.
+    System,
+}
+
+/// A worked example of how to use a subcommand. The examples are shown at the
+/// end of the subcommand's `--help` output and in its man-page.
+#[derive(Clone, Copy, Debug)]
+pub struct Example {
+    /// What the example does.
+    pub description: &'static str,
+
+    /// The arguments to pass to `git branchless`, starting with the name of
+    /// the subcommand.
+    pub args: &'static [&'static str],
+}
+
+/// The worked examples for each subcommand.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        description: "Amend the current commit with the changes in the working copy, and restack its descendants",
+        args: &["amend"],
+    },
+    Example {
+        description: "Select which changes to amend the current commit with",
+        args: &["amend", "--patch"],
+    },
+    Example {
+        description: "Check out a commit by interactively searching for it",
+        args: &["checkout", "--interactive"],
+    },
+    Example {
+        description: "Check out the commit in the current stack whose message mentions \"parser\"",
+        args: &["checkout", "stack() & message(parser)"],
+    },
+    Example {
+        description: "Show the changes made by the current stack",
+        args: &["diff"],
+    },
+    Example {
+        description: "Hide a commit and all of its descendants, and delete their branches",
+        args: &["hide", "--recursive", "--delete-branches", "abc123"],
+    },
+    Example {
+        description: "Hide all draft commits whose messages start with \"WIP\"",
+        args: &["hide", "draft() & message(\"re:^WIP\")"],
+    },
+    Example {
+        description: "Move the current commit and its descendants onto the main branch",
+        args: &["move", "--dest", "main"],
+    },
+    Example {
+        description: "Move the whole stack containing the current commit onto the main branch",
+        args: &["move", "--base", "HEAD", "--dest", "main"],
+    },
+    Example {
+        description: "Move only the given commits, leaving their descendants in place",
+        args: &["move", "--exact", "abc123+def456", "--dest", "main"],
+    },
+    Example {
+        description: "Insert a commit between another commit and its children",
+        args: &["move", "--exact", "abc123", "--dest", "def456", "--insert"],
+    },
+    Example {
+        description: "Fold the `fixup!` commits in the current stack into the commits that they refer to",
+        args: &["move", "--fixup"],
+    },
+    Example {
+        description: "Check out the next commit in the current stack",
+        args: &["next"],
+    },
+    Example {
+        description: "Check out the last commit in the current stack",
+        args: &["next", "--all"],
+    },
+    Example {
+        description: "Check out the commit three commits before the current commit",
+        args: &["prev", "3"],
+    },
+    Example {
+        description: "List the draft commits in the current stack",
+        args: &["query", "stack()"],
+    },
+    Example {
+        description: "List the branches for the draft commits authored by Alice",
+        args: &["query", "--branches", "draft() & author.name(Alice)"],
+    },
+    Example {
+        description: "Print the hashes of the commits which changed a file since the main branch",
+        args: &["query", "--raw", "main.. & paths.changed(src/main.rs)"],
+    },
+    Example {
+        description: "Restack the descendants of all rewritten commits",
+        args: &["restack"],
+    },
+    Example {
+        description: "Replace the message of the current commit",
+        args: &["reword", "--message", "Fix the build"],
+    },
+    Example {
+        description: "Edit the messages of all the commits in the current stack at once",
+        args: &["reword", "stack()"],
+    },
+    Example {
+        description: "Re-wrap the messages of the draft commits at 72 columns",
+        args: &["reword", "--reflow", "--reflow-column", "72", "draft()"],
+    },
+    Example {
+        description: "Answer JSON-RPC requests from an editor extension over stdin and stdout",
+        args: &["serve", "--stdio"],
+    },
+    Example {
+        description: "Show the commit graph",
+        args: &["smartlog"],
+    },
+    Example {
+        description: "Show the commit graph as it was yesterday",
+        args: &["smartlog", "--at", "yesterday"],
+    },
+    Example {
+        description: "Draw the current stack with Graphviz",
+        args: &["smartlog", "--format", "dot", "stack()"],
+    },
+    Example {
+        description: "Fetch the main branch and move all draft stacks onto it",
+        args: &["sync", "--pull"],
+    },
+    Example {
+        description: "Show what syncing the current stack would do, without changing anything",
+        args: &["sync", "--dry-run", "stack()"],
+    },
+    Example {
+        description: "Undo the last operation",
+        args: &["undo"],
+    },
+    Example {
+        description: "Browse through previous states of the repository to pick one to return to",
+        args: &["undo", "--interactive"],
+    },
+    Example {
+        description: "Undo only the changes made to the `origin` remote-tracking branches",
+        args: &["undo", "--refs", "origin/*"],
+    },
+    Example {
+        description: "Summarize the changes made to the repository in the last hour",
+        args: &["undo", "--since", "1 hour ago"],
+    },
+    Example {
+        description: "Unhide a commit which was hidden by mistake",
+        args: &["unhide", "abc123"],
+    },
+    Example {
+        description: "Print the version and capabilities of this build for use by another program",
+        args: &["version", "--json"],
+    },
+];
+
+/// Render the given arguments as a `git branchless` command line, quoting
+/// any arguments which the shell would otherwise interpret.
+fn render_example_command(args: &[&str]) -> String {
+    let mut command = String::from("git branchless");
+    for arg in args {
+        command.push(' ');
+        if !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./=:@^+%,".contains(c))
+        {
+            command.push_str(arg);
+        } else {
+            command.push('\'');
+            command.push_str(arg);
+            command.push('\'');
+        }
+    }
+    command
+}
+
+/// An example with its command line rendered for display.
+struct RenderedExample {
+    description: &'static str,
+    command: String,
+}
+
+/// The examples for each subcommand, along with their rendered command lines,
+/// in the order given in `EXAMPLES`.
+static RENDERED_EXAMPLES: Lazy<Vec<(&'static str, Vec<RenderedExample>)>> = Lazy::new(|| {
+    let mut rendered_examples: Vec<(&'static str, Vec<RenderedExample>)> = Vec::new();
+    for example in EXAMPLES {
+        let subcommand_name = example.args[0];
+        let rendered_example = RenderedExample {
+            description: example.description,
+            command: render_example_command(example.args),
+        };
+        match rendered_examples
+            .iter_mut()
+            .find(|(name, _examples)| *name == subcommand_name)
+        {
+            Some((_name, examples)) => examples.push(rendered_example),
+            None => rendered_examples.push((subcommand_name, vec![rendered_example])),
+        }
+    }
+    rendered_examples
+});
+
+/// The `EXAMPLES` section appended to the `--help` output of each subcommand
+/// which has examples.
+static EXAMPLES_HELP: Lazy<Vec<(&'static str, String)>> = Lazy::new(|| {
+    RENDERED_EXAMPLES
+        .iter()
+        .map(|(subcommand_name, examples)| {
+            let help = examples
+                .iter()
+                .map(|example| format!("    # {}\n    {}", example.description, example.command))
+                .join("\n\n");
+            (*subcommand_name, format!("EXAMPLES:\n{}", help))
+        })
+        .collect()
+});
+
+/// Build the `clap` command for `git-branchless`, with the worked examples
+/// attached to the help of each subcommand. This should be used instead of
+/// `Opts::command` so that the `--help` output and man-pages include them.
+pub fn build_command() -> ClapCommand<'static> {
+    let mut command = Opts::command();
+    for (subcommand_name, help) in EXAMPLES_HELP.iter() {
+        command = command.mut_subcommand(*subcommand_name, |subcommand| {
+            subcommand.after_long_help(help.as_str())
+        });
+    }
+    command
+}
+
 /// Generate and write man-pages into the specified directory.
 ///
 /// The generated files are named things like `man1/git-branchless-smartlog.1`,
@@ -564,7 +1669,7 @@ pub fn write_man_pages(man_dir: &Path) -> std::io::Result<()> {
     let man1_dir = man_dir.join("man1");
     std::fs::create_dir_all(&man1_dir)?;
 
-    let app = Opts::command();
+    let app = build_command();
     generate_man_page(&man1_dir, "git-branchless", &app)?;
     for subcommand in app.get_subcommands() {
         let subcommand_exe_name = format!("git-branchless-{}", subcommand.get_name());
@@ -621,9 +1726,74 @@ fn generate_man_page(man1_dir: &Path, name: &str, command: &ClapCommand) -> std:
         manual = manual.option(opt);
     }
 
+    let examples = RENDERED_EXAMPLES
+        .iter()
+        .find(|(subcommand_name, _examples)| *subcommand_name == command.get_name());
+    if let Some((_subcommand_name, examples)) = examples {
+        for example in examples {
+            manual = manual.example(
+                man::Example::new()
+                    .text(example.description)
+                    .command(example.command.as_str()),
+            );
+        }
+    }
+
     // FIXME: implement rest of man-page rendering.
 
     let output_path = man1_dir.join(format!("{}.1", name));
     std::fs::write(output_path, manual.render())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::FromArgMatches;
+
+    use crate::revset::parse;
+
+    #[test]
+    fn test_examples_are_valid() {
+        for example in EXAMPLES {
+            let args = std::iter::once("git-branchless").chain(example.args.iter().copied());
+            let matches = build_command()
+                .try_get_matches_from(args)
+                .unwrap_or_else(|err| panic!("Invalid example {:?}: {}", example.args, err));
+            Opts::from_arg_matches(&matches)
+                .unwrap_or_else(|err| panic!("Invalid example {:?}: {}", example.args, err));
+
+            for arg in example.args.iter().filter(|arg| arg.contains('(')) {
+                parse(arg).unwrap_or_else(|err| {
+                    panic!("Invalid revset in example {:?}: {}", example.args, err)
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_example_command() {
+        insta::assert_snapshot!(
+            render_example_command(&["query", "--branches", "draft() & author.name(Alice)"]),
+            @"git branchless query --branches 'draft() & author.name(Alice)'"
+        );
+        insta::assert_snapshot!(
+            render_example_command(&["move", "--exact", "abc123+def456", "--dest", "main"]),
+            @"git branchless move --exact abc123+def456 --dest main"
+        );
+    }
+
+    #[test]
+    fn test_examples_in_help() {
+        let command = build_command();
+        let help = command
+            .find_subcommand("prev")
+            .and_then(|subcommand| subcommand.get_after_long_help());
+        insta::assert_snapshot!(help.unwrap(), @r###"
+        EXAMPLES:
+            # Check out the commit three commits before the current commit
+            git branchless prev 3
+        "###);
+    }
+}
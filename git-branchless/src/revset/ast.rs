@@ -2,19 +2,58 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Display;
 
+/// The location of a node in the source text of an expression, as a range of
+/// byte offsets.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Constructor.
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+impl std::fmt::Debug for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
 /// A node in the parsed AST.
 #[allow(missing_docs)]
 #[derive(Clone, Debug)]
 pub enum Expr<'input> {
-    Name(Cow<'input, str>),
-    FunctionCall(Cow<'input, str>, Vec<Expr<'input>>),
+    Name(Cow<'input, str>, Span),
+    FunctionCall(Cow<'input, str>, Vec<Expr<'input>>, Span),
+}
+
+impl Drop for Expr<'_> {
+    fn drop(&mut self) {
+        // Dropping a deeply-nested expression recursively could overflow the
+        // stack, so move all of the descendants into a flat list first. Each
+        // one is dropped once its own arguments have been moved out, so the
+        // recursion never goes more than one level deep.
+        if let Expr::FunctionCall(_name, args, _span) = self {
+            let mut stack = std::mem::take(args);
+            while let Some(mut expr) = stack.pop() {
+                if let Expr::FunctionCall(_name, args, _span) = &mut expr {
+                    stack.append(args);
+                }
+            }
+        }
+    }
 }
 
 impl Display for Expr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Expr::Name(name) => write!(f, "{}", name),
-            Expr::FunctionCall(name, args) => {
+            Expr::Name(name, _span) => write!(f, "{}", name),
+            Expr::FunctionCall(name, args, _span) => {
                 write!(f, "{}(", name)?;
                 for (i, arg) in args.iter().enumerate() {
                     if i > 0 {
@@ -29,6 +68,13 @@ impl Display for Expr<'_> {
 }
 
 impl<'input> Expr<'input> {
+    /// Get the location of this expression in the source text.
+    pub fn get_span(&self) -> Span {
+        match self {
+            Expr::Name(_, span) | Expr::FunctionCall(_, _, span) => *span,
+        }
+    }
+
     /// Replace names in this expression with arbitrary expressions.
     ///
     /// Given a HashMap of names to Expr's, build a new Expr by crawling this
@@ -36,13 +82,27 @@ impl<'input> Expr<'input> {
     /// Expr.
     pub fn replace_names(&self, map: &HashMap<String, Expr<'input>>) -> Expr<'input> {
         match self {
-            Expr::Name(name) => match map.get(&name.to_string()) {
+            Expr::Name(name, _span) => match map.get(&name.to_string()) {
                 Some(expr) => expr.clone(),
                 None => self.clone(),
             },
-            Expr::FunctionCall(name, args) => {
+            Expr::FunctionCall(name, args, span) => {
                 let args = args.iter().map(|arg| arg.replace_names(map)).collect();
-                Expr::FunctionCall(name.clone(), args)
+                Expr::FunctionCall(name.clone(), args, *span)
+            }
+        }
+    }
+
+    /// Determine whether this expression contains a call to the function with
+    /// the given name.
+    pub fn contains_function_call(&self, function_name: &str) -> bool {
+        match self {
+            Expr::Name(_name, _span) => false,
+            Expr::FunctionCall(name, args, _span) => {
+                name == function_name
+                    || args
+                        .iter()
+                        .any(|arg| arg.contains_function_call(function_name))
             }
         }
     }
@@ -1,19 +1,23 @@
 use bstr::ByteSlice;
 use eden_dag::DagAlgorithm;
-use lib::core::dag::CommitSet;
-use lib::git::{Commit, Repo};
-use std::borrow::Cow;
+use lib::core::dag::{commit_set_to_vec_unsorted, CommitSet};
+use lib::core::eventlog::{Event, EventLogDb, EventTransactionId};
+use lib::core::signature_status::{get_signature_statuses, SignatureStatus, SignatureStatusDb};
+use lib::git::{Commit, MaybeZeroOid, NonZeroOid, Repo};
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use tracing::warn;
 
 use eyre::Context as EyreContext;
+use itertools::Itertools;
 use lazy_static::lazy_static;
 
-use crate::revset::pattern::{PatternError, PatternMatcher};
+use crate::revset::pattern::{Pattern, PatternError, PatternMatcher};
 
 use super::eval::{
-    eval0, eval0_or_1, eval1, eval1_pattern, eval2, eval_number_rhs, Context, EvalError, EvalResult,
+    eval0, eval0_or_1, eval1, eval1_number, eval1_pattern, eval2, eval_inner, eval_number_rhs,
+    eval_optional_number_rhs, eval_scoped, Context, EvalError, EvalResult,
 };
 use super::pattern::make_pattern_matcher_set;
 use super::Expr;
@@ -39,6 +43,7 @@ lazy_static! {
             ("roots", &fn_roots),
             ("heads", &fn_heads),
             ("branches", &fn_branches),
+            ("remotes", &fn_remotes),
             ("draft", &fn_draft),
             ("stack", &fn_stack),
             ("message", &fn_message),
@@ -50,11 +55,51 @@ lazy_static! {
             ("committer.email", &fn_committer_email),
             ("committer.date", &fn_committer_date),
             ("exactly", &fn_exactly),
+            ("obsolete", &fn_obsolete),
+            ("rewritten_by", &fn_rewritten_by),
+            ("created_after", &fn_created_after),
+            ("signed", &fn_signed),
+            ("verified", &fn_verified),
         ];
         functions.iter().cloned().collect()
     };
 }
 
+/// Functions which select commits by testing each commit individually, such
+/// as by matching its message. Evaluating them over all commits is expensive,
+/// so when they're intersected with another set, only the commits in that set
+/// are tested.
+const FILTER_FUNCTIONS: &[&str] = &[
+    "message",
+    "paths.changed",
+    "author.name",
+    "author.email",
+    "author.date",
+    "committer.name",
+    "committer.email",
+    "committer.date",
+    "signed",
+    "verified",
+];
+
+/// Determine whether the expression calls one of the `FILTER_FUNCTIONS`,
+/// either directly or as an operand of a set operation (such as
+/// `message(foo) | message(bar)`). Such expressions are cheaper to evaluate
+/// with `eval_scoped`, since their filters only need to test the commits in
+/// the scope.
+pub(super) fn is_filter_expr(expr: &Expr) -> bool {
+    match expr {
+        Expr::FunctionCall(name, args, _span) => match (name.as_ref(), args.as_slice()) {
+            ("union" | "intersection" | "difference", [lhs, rhs]) => {
+                is_filter_expr(lhs) || is_filter_expr(rhs)
+            }
+            ("not", [arg]) => is_filter_expr(arg),
+            (name, _args) => FILTER_FUNCTIONS.contains(&name),
+        },
+        Expr::Name(_name, _span) => false,
+    }
+}
+
 fn fn_all(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
     eval0(ctx, name, args)?;
     Ok(ctx.query_active_commits()?.clone())
@@ -71,13 +116,39 @@ fn fn_union(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
 }
 
 fn fn_intersection(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
-    let (lhs, rhs) = eval2(ctx, name, args)?;
-    Ok(lhs.intersection(&rhs))
+    // Evaluate the operand which isn't a filter first, and then only test its
+    // commits against the filter. The scoped filter result is already a subset
+    // of the other operand.
+    match args {
+        [lhs, rhs] if is_filter_expr(lhs) && !is_filter_expr(rhs) => {
+            let rhs = eval_inner(ctx, rhs)?;
+            eval_scoped(ctx, lhs, &rhs)
+        }
+        [lhs, rhs] if is_filter_expr(rhs) => {
+            let lhs = eval_inner(ctx, lhs)?;
+            eval_scoped(ctx, rhs, &lhs)
+        }
+        args => {
+            let (lhs, rhs) = eval2(ctx, name, args)?;
+            Ok(lhs.intersection(&rhs))
+        }
+    }
 }
 
 fn fn_difference(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
-    let (lhs, rhs) = eval2(ctx, name, args)?;
-    Ok(lhs.difference(&rhs))
+    // Only the commits in the left-hand side need to be tested against a
+    // filter on the right-hand side.
+    match args {
+        [lhs, rhs] if is_filter_expr(rhs) => {
+            let lhs = eval_inner(ctx, lhs)?;
+            let rhs = eval_scoped(ctx, rhs, &lhs)?;
+            Ok(lhs.difference(&rhs))
+        }
+        args => {
+            let (lhs, rhs) = eval2(ctx, name, args)?;
+            Ok(lhs.difference(&rhs))
+        }
+    }
 }
 
 fn fn_only(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
@@ -96,14 +167,54 @@ fn fn_not(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
     Ok(active_commits.difference(&expr))
 }
 
+/// Get the ancestors of a set, or with a depth argument, only those at most
+/// that many generations away from the set (so `ancestors(@, 0)` is just
+/// `@`).
 fn fn_ancestors(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
-    let expr = eval1(ctx, name, args)?;
-    Ok(ctx.dag.query().ancestors(expr)?)
+    let (expr, depth) = eval_optional_number_rhs(ctx, name, args)?;
+    match depth {
+        None => Ok(ctx.dag.query().ancestors(expr)?),
+        Some(depth) => traverse_to_depth(expr, depth, |commits| {
+            Ok(ctx.dag.query().parents(commits)?)
+        }),
+    }
 }
 
+/// Get the descendants of a set, or with a depth argument, only those at most
+/// that many generations away from the set.
 fn fn_descendants(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
-    let expr = eval1(ctx, name, args)?;
-    Ok(ctx.dag.query().descendants(expr)?)
+    let (expr, depth) = eval_optional_number_rhs(ctx, name, args)?;
+    match depth {
+        None => Ok(ctx.dag.query().descendants(expr)?),
+        Some(depth) => traverse_to_depth(expr, depth, |commits| {
+            Ok(ctx.dag.query().children(commits)?)
+        }),
+    }
+}
+
+/// Repeatedly apply `step` (such as getting the parents of a set) starting
+/// from `commits`, at most `depth` times, and return all the commits visited.
+/// Stops early if there are no new commits to visit, so that a large depth
+/// doesn't cost more than a full traversal.
+fn traverse_to_depth(
+    commits: CommitSet,
+    depth: usize,
+    mut step: impl FnMut(CommitSet) -> EvalResult,
+) -> EvalResult {
+    let mut result = commits.clone();
+    let mut frontier = commits;
+    for _ in 0..depth {
+        frontier = step(frontier)?.difference(&result);
+        if frontier
+            .is_empty()
+            .wrap_err("Checking for unvisited commits")
+            .map_err(EvalError::OtherError)?
+        {
+            break;
+        }
+        result = result.union(&frontier);
+    }
+    Ok(result)
 }
 
 fn fn_parents(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
@@ -126,9 +237,102 @@ fn fn_heads(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
     Ok(ctx.dag.query().heads(expr)?)
 }
 
+/// Evaluate the optional reference-name pattern argument to `branches` or
+/// `remotes`. Unprefixed patterns are globs.
+fn eval0_or_1_reference_pattern(
+    _ctx: &mut Context,
+    name: &str,
+    args: &[Expr],
+) -> Result<Option<Pattern>, EvalError> {
+    match args {
+        [] => Ok(None),
+        [Expr::Name(pattern, span)] => Ok(Some(
+            Pattern::new_glob_by_default(pattern).map_err(|err| EvalError::from(err).at(*span))?,
+        )),
+        [Expr::FunctionCall(name, _args, span)] => Err(EvalError::ExpectedPatternNotFunction {
+            function_name: name.clone().into_owned(),
+        }
+        .at(*span)),
+        args => Err(EvalError::ArityMismatch {
+            function_name: name.to_string(),
+            expected_arities: vec![0, 1],
+            actual_arity: args.len(),
+        }),
+    }
+}
+
+/// Get the commits pointed to by local branches, optionally only those whose
+/// names match the given pattern (such as `branches("experiment/*")`).
 fn fn_branches(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
-    eval0(ctx, name, args)?;
-    Ok(ctx.dag.branch_commits.clone())
+    let pattern = match eval0_or_1_reference_pattern(ctx, name, args)? {
+        None => return Ok(ctx.dag.branch_commits.clone()),
+        Some(pattern) => pattern,
+    };
+
+    let references_snapshot = ctx.query_references_snapshot()?;
+    let commit_oids: Vec<NonZeroOid> = references_snapshot
+        .branch_oid_to_names
+        .iter()
+        .filter(|(_oid, reference_names)| {
+            reference_names.iter().any(|reference_name| {
+                match reference_name.as_str().strip_prefix("refs/heads/") {
+                    Some(branch_name) => pattern.matches_text(branch_name),
+                    None => false,
+                }
+            })
+        })
+        .map(|(oid, _reference_names)| *oid)
+        .collect();
+    make_reference_commit_set(ctx, commit_oids)
+}
+
+/// Get the commits pointed to by remote-tracking branches, optionally only
+/// those whose names (such as `origin/main`) match the given pattern.
+fn fn_remotes(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    let pattern = eval0_or_1_reference_pattern(ctx, name, args)?;
+
+    let mut commit_oids = Vec::new();
+    for branch in ctx
+        .repo
+        .get_all_remote_branches()
+        .map_err(EvalError::OtherError)?
+    {
+        let branch_oid = match branch.get_oid().map_err(EvalError::OtherError)? {
+            Some(branch_oid) => branch_oid,
+            None => continue,
+        };
+        let reference_name = branch
+            .into_reference()
+            .get_name()
+            .map_err(EvalError::OtherError)?;
+        let branch_name = reference_name
+            .as_str()
+            .strip_prefix("refs/remotes/")
+            .unwrap_or_else(|| reference_name.as_str());
+        let is_match = match &pattern {
+            None => true,
+            Some(pattern) => pattern.matches_text(branch_name),
+        };
+        if is_match {
+            commit_oids.push(branch_oid);
+        }
+    }
+    make_reference_commit_set(ctx, commit_oids)
+}
+
+/// Construct a commit set from commits pointed to by references, making sure
+/// that they're present in the DAG.
+fn make_reference_commit_set(ctx: &mut Context, commit_oids: Vec<NonZeroOid>) -> EvalResult {
+    let commit_set: CommitSet = commit_oids.into_iter().collect();
+    ctx.dag
+        .sync_from_oids(
+            ctx.effects,
+            ctx.repo,
+            CommitSet::empty(),
+            commit_set.clone(),
+        )
+        .map_err(EvalError::OtherError)?;
+    Ok(commit_set)
 }
 
 fn fn_parents_nth(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
@@ -226,7 +430,7 @@ fn make_pattern_matcher(
     }
 
     let matcher = Matcher {
-        expr: Expr::FunctionCall(Cow::Borrowed(name), args.to_vec()).to_string(),
+        expr: format!("{}({})", name, args.iter().join(", ")),
         f,
     };
     let matcher = make_pattern_matcher_set(ctx, ctx.repo, Box::new(matcher))?;
@@ -398,3 +602,125 @@ fn fn_exactly(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
         })
     }
 }
+
+fn fn_obsolete(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    eval0(ctx, name, args)?;
+    Ok(ctx.dag.obsolete_commits.clone())
+}
+
+/// Get the set of commits produced by rewrites in event transactions whose
+/// message matches the given pattern. The transaction message is the name of
+/// the command that started the transaction, such as `sync` or `reword`.
+fn fn_rewritten_by(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    let pattern = eval1_pattern(ctx, name, args)?;
+    let conn = ctx.repo.get_db_conn().map_err(EvalError::OtherError)?;
+    let event_log_db = EventLogDb::new(&conn).map_err(EvalError::OtherError)?;
+
+    let mut transaction_matches: HashMap<EventTransactionId, bool> = HashMap::new();
+    let mut commit_oids = Vec::new();
+    for event in ctx.query_events()? {
+        if let Event::RewriteEvent {
+            timestamp: _,
+            event_tx_id,
+            old_commit_oid: _,
+            new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+        } = event
+        {
+            let is_match = match transaction_matches.entry(*event_tx_id) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    let message = event_log_db
+                        .get_transaction_message(*event_tx_id)
+                        .map_err(EvalError::OtherError)?;
+                    *entry.insert(pattern.matches_text(&message))
+                }
+            };
+            if is_match {
+                commit_oids.push(*new_commit_oid);
+            }
+        }
+    }
+    make_event_commit_set(ctx, commit_oids)
+}
+
+/// Get the set of commits which were created (committed or produced by a
+/// rewrite) by events after the event with the given ID.
+fn fn_created_after(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    let event_id = eval1_number(ctx, name, args)?;
+    let events = ctx.query_events()?;
+    let commit_oids: Vec<NonZeroOid> = events
+        .iter()
+        .skip(event_id)
+        .filter_map(|event| match event {
+            Event::CommitEvent { commit_oid, .. } => Some(*commit_oid),
+            Event::RewriteEvent {
+                new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                ..
+            } => Some(*new_commit_oid),
+            Event::RewriteEvent { .. }
+            | Event::RefUpdateEvent { .. }
+            | Event::ObsoleteEvent { .. }
+            | Event::UnobsoleteEvent { .. }
+            | Event::WorkingCopySnapshot { .. } => None,
+        })
+        .collect();
+    make_event_commit_set(ctx, commit_oids)
+}
+
+/// Get the set of commits which have a signature, whether or not it's valid.
+fn fn_signed(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    eval0(ctx, name, args)?;
+    make_pattern_matcher(
+        ctx,
+        name,
+        args,
+        Box::new(|_repo, commit| Ok(commit.is_signed())),
+    )
+}
+
+/// Get the set of commits which have a valid signature from a trusted key.
+fn fn_verified(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    let signed_commits = fn_signed(ctx, name, args)?;
+    let signed_oids = commit_set_to_vec_unsorted(&signed_commits).map_err(EvalError::OtherError)?;
+    let conn = ctx.repo.get_db_conn().map_err(EvalError::OtherError)?;
+    let signature_status_db = SignatureStatusDb::new(&conn).map_err(EvalError::OtherError)?;
+    let statuses = get_signature_statuses(
+        ctx.git_run_info,
+        ctx.repo,
+        &signature_status_db,
+        &signed_oids,
+    )
+    .map_err(EvalError::OtherError)?;
+    Ok(statuses
+        .into_iter()
+        .filter(|(_oid, status)| *status == SignatureStatus::Good)
+        .map(|(oid, _status)| oid)
+        .collect())
+}
+
+/// Construct a commit set from commits referred to by the event log. Commits
+/// which no longer exist in the repository (for example, because they were
+/// garbage-collected) are skipped.
+fn make_event_commit_set(ctx: &mut Context, commit_oids: Vec<NonZeroOid>) -> EvalResult {
+    let mut existing_commit_oids = Vec::new();
+    for commit_oid in commit_oids {
+        if ctx
+            .repo
+            .find_commit(commit_oid)
+            .map_err(EvalError::OtherError)?
+            .is_some()
+        {
+            existing_commit_oids.push(commit_oid);
+        }
+    }
+    let commit_set: CommitSet = existing_commit_oids.into_iter().collect();
+    ctx.dag
+        .sync_from_oids(
+            ctx.effects,
+            ctx.repo,
+            CommitSet::empty(),
+            commit_set.clone(),
+        )
+        .map_err(EvalError::OtherError)?;
+    Ok(commit_set)
+}
@@ -11,24 +11,34 @@ use once_cell::unsync::OnceCell;
 use thiserror::Error;
 
 use lib::core::dag::{CommitSet, Dag};
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
 use lib::core::formatting::Pluralize;
-use lib::git::{ConfigRead, Repo, ResolvedReferenceInfo};
+use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
+use lib::git::{ConfigRead, GitRunInfo, Repo, ResolvedReferenceInfo};
 use tracing::instrument;
 
-use super::builtins::FUNCTIONS;
+use super::builtins::{is_filter_expr, FUNCTIONS};
 use super::parser::{parse, ParseError};
 use super::pattern::{Pattern, PatternError};
-use super::Expr;
+use super::plugins::{eval_plugin_function, get_registered_function_names};
+use super::{Expr, Span};
 
 #[derive(Debug)]
 pub(super) struct Context<'a> {
     pub effects: &'a Effects,
+    pub git_run_info: &'a GitRunInfo,
     pub repo: &'a Repo,
     pub dag: &'a mut Dag,
     pub public_commits: OnceCell<CommitSet>,
     pub active_heads: OnceCell<CommitSet>,
     pub active_commits: OnceCell<CommitSet>,
     pub draft_commits: OnceCell<CommitSet>,
+    pub events: OnceCell<Vec<Event>>,
+    pub references_snapshot: OnceCell<RepoReferencesSnapshot>,
+
+    /// If set, the filter function currently being evaluated only needs to
+    /// test the commits in this set. See `eval_scoped`.
+    pub filter_scope: Option<CommitSet>,
 }
 
 impl Context<'_> {
@@ -70,6 +80,34 @@ impl Context<'_> {
         })
     }
 
+    /// Get the current locations of the branches in the repository.
+    #[instrument]
+    pub fn query_references_snapshot(&self) -> Result<&RepoReferencesSnapshot, EvalError> {
+        self.references_snapshot.get_or_try_init(|| {
+            self.repo
+                .get_references_snapshot()
+                .map_err(EvalError::OtherError)
+        })
+    }
+
+    /// Get the events from the event log, in order. The index of an event
+    /// in this list is one less than its event ID.
+    #[instrument]
+    pub fn query_events(&self) -> Result<&[Event], EvalError> {
+        let events = self.events.get_or_try_init(|| -> Result<_, EvalError> {
+            let conn = self.repo.get_db_conn().map_err(EvalError::OtherError)?;
+            let event_log_db = EventLogDb::new(&conn).map_err(EvalError::OtherError)?;
+            let event_replayer =
+                EventReplayer::from_event_log_db(self.effects, self.repo, &event_log_db)
+                    .map_err(EvalError::OtherError)?;
+            let events = event_replayer
+                .get_events_since_cursor(event_replayer.make_cursor(0))
+                .to_vec();
+            Ok(events)
+        })?;
+        Ok(events.as_slice())
+    }
+
     #[instrument]
     pub fn query_draft_commits(&self) -> Result<&CommitSet, EvalError> {
         self.draft_commits.get_or_try_init(|| {
@@ -94,7 +132,7 @@ pub enum EvalError {
     )]
     UnboundFunction {
         name: String,
-        available_names: Vec<&'static str>,
+        available_names: Vec<String>,
     },
 
     #[error(
@@ -147,6 +185,46 @@ pub enum EvalError {
 
     #[error(transparent)]
     OtherError(eyre::Error),
+
+    /// An error which occurred while evaluating the part of the expression at
+    /// `span`.
+    #[error("{source}")]
+    Spanned { span: Span, source: Box<EvalError> },
+}
+
+impl EvalError {
+    /// Record that this error occurred while evaluating the part of the
+    /// expression at `span`, unless it already has a more specific location.
+    /// Errors which aren't caused by the expression itself aren't located.
+    pub(super) fn at(self, span: Span) -> Self {
+        match self {
+            EvalError::Spanned { .. } | EvalError::DagError { .. } | EvalError::OtherError(_) => {
+                self
+            }
+            err => EvalError::Spanned {
+                span,
+                source: Box::new(err),
+            },
+        }
+    }
+
+    /// Discard the location of this error, such as when the location refers
+    /// to an expression other than the one that the user wrote.
+    fn without_span(self) -> Self {
+        match self {
+            EvalError::Spanned { span: _, source } => *source,
+            err => err,
+        }
+    }
+
+    /// Get the location in the expression at which this error occurred, if
+    /// known.
+    pub fn get_span(&self) -> Option<Span> {
+        match self {
+            EvalError::Spanned { span, source: _ } => Some(*span),
+            _ => None,
+        }
+    }
 }
 
 pub(super) fn make_dag_backend_error(error: impl Display) -> eden_dag::Error {
@@ -159,29 +237,87 @@ pub type EvalResult = Result<CommitSet, EvalError>;
 
 /// Evaluate the provided revset expression.
 #[instrument]
-pub fn eval(effects: &Effects, repo: &Repo, dag: &mut Dag, expr: &Expr) -> EvalResult {
+pub fn eval(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &mut Dag,
+    expr: &Expr,
+) -> EvalResult {
     let (effects, _progress) =
         effects.start_operation(OperationType::EvaluateRevset(Arc::new(expr.to_string())));
 
     let mut ctx = Context {
         effects: &effects,
+        git_run_info,
         repo,
         dag,
         public_commits: Default::default(),
         active_heads: Default::default(),
         active_commits: Default::default(),
         draft_commits: Default::default(),
+        events: Default::default(),
+        references_snapshot: Default::default(),
+        filter_scope: None,
     };
     let commits = eval_inner(&mut ctx, expr)?;
     Ok(commits)
 }
 
 #[instrument]
-fn eval_inner(ctx: &mut Context, expr: &Expr) -> EvalResult {
-    match expr {
-        Expr::Name(name) => eval_name(ctx, name),
-        Expr::FunctionCall(name, args) => eval_fn(ctx, name, args),
+pub(super) fn eval_inner(ctx: &mut Context, expr: &Expr) -> EvalResult {
+    let result = match expr {
+        Expr::Name(name, _span) => eval_name(ctx, name),
+        Expr::FunctionCall(name, args, _span) => eval_fn(ctx, name, args),
+    };
+    result.map_err(|err| err.at(expr.get_span()))
+}
+
+/// Evaluate the intersection of `expr` and `scope`. If `expr` uses filter
+/// functions (see `is_filter_expr`), then the scope is pushed down through
+/// any set operations, so that each filter only tests the commits which could
+/// end up in the result.
+pub(super) fn eval_scoped(ctx: &mut Context, expr: &Expr, scope: &CommitSet) -> EvalResult {
+    if !is_filter_expr(expr) {
+        return Ok(eval_inner(ctx, expr)?.intersection(scope));
     }
+
+    let result = match expr {
+        Expr::FunctionCall(name, args, _span) => match (name.as_ref(), args.as_slice()) {
+            ("union", [lhs, rhs]) => {
+                let lhs = eval_scoped(ctx, lhs, scope)?;
+                let rhs = eval_scoped(ctx, rhs, scope)?;
+                Ok(lhs.union(&rhs))
+            }
+            ("intersection", [lhs, rhs]) => {
+                let (lhs, rhs) = if is_filter_expr(lhs) && !is_filter_expr(rhs) {
+                    (rhs, lhs)
+                } else {
+                    (lhs, rhs)
+                };
+                let lhs = eval_scoped(ctx, lhs, scope)?;
+                eval_scoped(ctx, rhs, &lhs)
+            }
+            ("difference", [lhs, rhs]) => {
+                let lhs = eval_scoped(ctx, lhs, scope)?;
+                let rhs = eval_scoped(ctx, rhs, &lhs)?;
+                Ok(lhs.difference(&rhs))
+            }
+            ("not", [arg]) => {
+                let scope = ctx.query_active_commits()?.intersection(scope);
+                let arg = eval_scoped(ctx, arg, &scope)?;
+                Ok(scope.difference(&arg))
+            }
+            _ => {
+                let previous_scope = ctx.filter_scope.replace(scope.clone());
+                let result = eval_inner(ctx, expr);
+                ctx.filter_scope = previous_scope;
+                result
+            }
+        },
+        Expr::Name(_name, _span) => eval_inner(ctx, expr),
+    };
+    result.map_err(|err| err.at(expr.get_span()))
 }
 
 pub(super) fn eval_name(ctx: &mut Context, name: &str) -> EvalResult {
@@ -243,16 +379,54 @@ pub(super) fn eval_fn(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResul
             .map(|(i, arg)| (format!("${}", i + 1), arg.clone()))
             .collect();
         let alias_expr = alias_expr.replace_names(&arg_map);
-        let commits = eval_inner(ctx, &alias_expr);
+        // The locations in the alias expression refer to the alias template
+        // rather than to the user's expression, so report errors at the call
+        // to the alias instead.
+        let commits = eval_inner(ctx, &alias_expr).map_err(EvalError::without_span);
         return commits;
     }
 
+    let plugin_args: Vec<String> = args
+        .iter()
+        .map(|arg| match arg {
+            Expr::Name(name, _span) => name.to_string(),
+            Expr::FunctionCall(_, _, _) => arg.to_string(),
+        })
+        .collect();
+    let plugin_result =
+        eval_plugin_function(ctx.repo, name, &plugin_args).map_err(EvalError::OtherError)?;
+    if let Some(oids) = plugin_result {
+        let commit_set: CommitSet = oids.into_iter().collect();
+        ctx.dag
+            .sync_from_oids(
+                ctx.effects,
+                ctx.repo,
+                CommitSet::empty(),
+                commit_set.clone(),
+            )
+            .map_err(EvalError::OtherError)?;
+        return Ok(commit_set);
+    }
+
     Err(EvalError::UnboundFunction {
         name: name.to_owned(),
-        available_names: FUNCTIONS.keys().sorted().copied().collect(),
+        available_names: get_function_names(),
     })
 }
 
+/// Get the names of all the revset functions which can be called, including
+/// the functions registered with [`super::register_revset_function`], in sorted
+/// order. Functions configured with `branchless.revsets.function.<name>` aren't
+/// included, since they depend on the repository.
+pub fn get_function_names() -> Vec<String> {
+    FUNCTIONS
+        .keys()
+        .map(|name| name.to_string())
+        .chain(get_registered_function_names())
+        .sorted()
+        .collect()
+}
+
 #[instrument]
 pub(super) fn eval0(
     ctx: &mut Context,
@@ -312,11 +486,14 @@ pub(super) fn eval1_pattern(
     args: &[Expr],
 ) -> Result<Pattern, EvalError> {
     match args {
-        [Expr::Name(pattern)] => Ok(Pattern::new(pattern)?),
+        [Expr::Name(pattern, span)] => {
+            Ok(Pattern::new(pattern).map_err(|err| EvalError::from(err).at(*span))?)
+        }
 
-        [Expr::FunctionCall(name, _args)] => Err(EvalError::ExpectedNumberNotFunction {
+        [Expr::FunctionCall(name, _args, span)] => Err(EvalError::ExpectedPatternNotFunction {
             function_name: name.clone().into_owned(),
-        }),
+        }
+        .at(*span)),
 
         args => Err(EvalError::ArityMismatch {
             function_name: function_name.to_string(),
@@ -347,21 +524,48 @@ pub(super) fn eval2(
     }
 }
 
+pub(super) fn eval1_number(
+    _ctx: &mut Context,
+    function_name: &str,
+    args: &[Expr],
+) -> Result<usize, EvalError> {
+    match args {
+        [Expr::Name(name, span)] => {
+            let number: usize = name.parse().map_err(|err| EvalError::from(err).at(*span))?;
+            Ok(number)
+        }
+
+        [Expr::FunctionCall(name, _args, span)] => Err(EvalError::ExpectedNumberNotFunction {
+            function_name: name.clone().into_owned(),
+        }
+        .at(*span)),
+
+        args => Err(EvalError::ArityMismatch {
+            function_name: function_name.to_string(),
+            expected_arities: vec![1],
+            actual_arity: args.len(),
+        }),
+    }
+}
+
 pub(super) fn eval_number_rhs(
     ctx: &mut Context,
     function_name: &str,
     args: &[Expr],
 ) -> Result<(CommitSet, usize), EvalError> {
     match args {
-        [lhs, Expr::Name(name)] => {
+        [lhs, Expr::Name(name, span)] => {
             let lhs = eval_inner(ctx, lhs)?;
-            let number: usize = { name.parse()? };
+            let number: usize = name.parse().map_err(|err| EvalError::from(err).at(*span))?;
             Ok((lhs, number))
         }
 
-        [_lhs, Expr::FunctionCall(name, _args)] => Err(EvalError::ExpectedNumberNotFunction {
-            function_name: name.clone().into_owned(),
-        }),
+        [_lhs, Expr::FunctionCall(name, _args, span)] => {
+            Err(EvalError::ExpectedNumberNotFunction {
+                function_name: name.clone().into_owned(),
+            }
+            .at(*span))
+        }
 
         args => Err(EvalError::ArityMismatch {
             function_name: function_name.to_string(),
@@ -371,6 +575,32 @@ pub(super) fn eval_number_rhs(
     }
 }
 
+/// Evaluate a set and an optional number, as in `ancestors(x)` or
+/// `ancestors(x, 5)`.
+pub(super) fn eval_optional_number_rhs(
+    ctx: &mut Context,
+    function_name: &str,
+    args: &[Expr],
+) -> Result<(CommitSet, Option<usize>), EvalError> {
+    match args {
+        [lhs] => {
+            let lhs = eval_inner(ctx, lhs)?;
+            Ok((lhs, None))
+        }
+
+        [_lhs, _rhs] => {
+            let (lhs, number) = eval_number_rhs(ctx, function_name, args)?;
+            Ok((lhs, Some(number)))
+        }
+
+        args => Err(EvalError::ArityMismatch {
+            function_name: function_name.to_string(),
+            expected_arities: vec![1, 2],
+            actual_arity: args.len(),
+        }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
@@ -380,7 +610,7 @@ mod tests {
     use lib::core::eventlog::{EventLogDb, EventReplayer};
     use lib::core::formatting::Glyphs;
     use lib::core::repo_ext::RepoExt;
-    use lib::git::Commit;
+    use lib::git::{Commit, NonZeroOid};
     use lib::testing::{make_git, GitRunOptions};
 
     use super::*;
@@ -388,11 +618,12 @@ mod tests {
 
     fn eval_and_sort<'a>(
         effects: &Effects,
+        git_run_info: &GitRunInfo,
         repo: &'a Repo,
         dag: &mut Dag,
         expr: &Expr,
     ) -> eyre::Result<Vec<Commit<'a>>> {
-        let result = eval(effects, repo, dag, expr)?;
+        let result = eval(effects, git_run_info, repo, dag, expr)?;
         let mut commits: Vec<Commit> = commit_set_to_vec_unsorted(&result)?
             .into_iter()
             .map(|oid| repo.find_commit_or_fail(oid))
@@ -435,8 +666,8 @@ mod tests {
         )?;
 
         {
-            let expr = Expr::FunctionCall(Cow::Borrowed("all"), vec![]);
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            let expr = Expr::FunctionCall(Cow::Borrowed("all"), vec![], Span::default());
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -493,8 +724,8 @@ mod tests {
         }
 
         {
-            let expr = Expr::FunctionCall(Cow::Borrowed("none"), vec![]);
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            let expr = Expr::FunctionCall(Cow::Borrowed("none"), vec![], Span::default());
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [],
             )
@@ -505,11 +736,12 @@ mod tests {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("union"),
                 vec![
-                    Expr::Name(Cow::Owned(test1_oid.to_string())),
-                    Expr::Name(Cow::Owned(test2_oid.to_string())),
+                    Expr::Name(Cow::Owned(test1_oid.to_string()), Span::default()),
+                    Expr::Name(Cow::Owned(test2_oid.to_string()), Span::default()),
                 ],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -530,8 +762,8 @@ mod tests {
         }
 
         {
-            let expr = Expr::FunctionCall(Cow::Borrowed("stack"), vec![]);
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            let expr = Expr::FunctionCall(Cow::Borrowed("stack"), vec![], Span::default());
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -560,9 +792,13 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("stack"),
-                vec![Expr::Name(Cow::Owned(test2_oid.to_string()))],
+                vec![Expr::Name(
+                    Cow::Owned(test2_oid.to_string()),
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -583,8 +819,8 @@ mod tests {
         }
 
         {
-            let expr = Expr::FunctionCall(Cow::Borrowed("draft"), vec![]);
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            let expr = Expr::FunctionCall(Cow::Borrowed("draft"), vec![], Span::default());
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -625,9 +861,14 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("not"),
-                vec![Expr::FunctionCall(Cow::Borrowed("draft"), vec![])],
+                vec![Expr::FunctionCall(
+                    Cow::Borrowed("draft"),
+                    vec![],
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -657,11 +898,12 @@ mod tests {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("parents.nth"),
                 vec![
-                    Expr::Name(Cow::Owned(test7_oid.to_string())),
-                    Expr::Name(Cow::Borrowed("1")),
+                    Expr::Name(Cow::Owned(test7_oid.to_string()), Span::default()),
+                    Expr::Name(Cow::Borrowed("1"), Span::default()),
                 ],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -679,11 +921,12 @@ mod tests {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("ancestors.nth"),
                 vec![
-                    Expr::Name(Cow::Owned(test7_oid.to_string())),
-                    Expr::Name(Cow::Borrowed("2")),
+                    Expr::Name(Cow::Owned(test7_oid.to_string()), Span::default()),
+                    Expr::Name(Cow::Borrowed("2"), Span::default()),
                 ],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -700,9 +943,10 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("message"),
-                vec![Expr::Name(Cow::Borrowed("test4"))],
+                vec![Expr::Name(Cow::Borrowed("test4"), Span::default())],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -719,9 +963,13 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("message"),
-                vec![Expr::Name(Cow::Borrowed("exact:create test4.txt"))],
+                vec![Expr::Name(
+                    Cow::Borrowed("exact:create test4.txt"),
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -738,9 +986,13 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("message"),
-                vec![Expr::Name(Cow::Borrowed("regex:^create test4.txt$"))],
+                vec![Expr::Name(
+                    Cow::Borrowed("regex:^create test4.txt$"),
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -757,9 +1009,13 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("paths.changed"),
-                vec![Expr::Name(Cow::Borrowed("glob:test[1-3].txt"))],
+                vec![Expr::Name(
+                    Cow::Borrowed("glob:test[1-3].txt"),
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -789,11 +1045,12 @@ mod tests {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("exactly"),
                 vec![
-                    Expr::FunctionCall(Cow::Borrowed("stack"), vec![]),
-                    Expr::Name(Cow::Borrowed("3")),
+                    Expr::FunctionCall(Cow::Borrowed("stack"), vec![], Span::default()),
+                    Expr::Name(Cow::Borrowed("3"), Span::default()),
                 ],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -823,16 +1080,20 @@ mod tests {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("exactly"),
                 vec![
-                    Expr::FunctionCall(Cow::Borrowed("stack"), vec![]),
-                    Expr::Name(Cow::Borrowed("2")),
+                    Expr::FunctionCall(Cow::Borrowed("stack"), vec![], Span::default()),
+                    Expr::Name(Cow::Borrowed("2"), Span::default()),
                 ],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Err(
-                UnexpectedSetLength {
-                    expr: "stack()",
-                    expected_len: 2,
-                    actual_len: 3,
+                Spanned {
+                    span: 0..0,
+                    source: UnexpectedSetLength {
+                        expr: "stack()",
+                        expected_len: 2,
+                        actual_len: 3,
+                    },
                 },
             )
             "###);
@@ -904,9 +1165,10 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("author.name"),
-                vec![Expr::Name(Cow::Borrowed("Foo"))],
+                vec![Expr::Name(Cow::Borrowed("Foo"), Span::default())],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -923,9 +1185,10 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("author.email"),
-                vec![Expr::Name(Cow::Borrowed("foo"))],
+                vec![Expr::Name(Cow::Borrowed("foo"), Span::default())],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -942,9 +1205,10 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("author.date"),
-                vec![Expr::Name(Cow::Borrowed("before:today"))],
+                vec![Expr::Name(Cow::Borrowed("before:today"), Span::default())],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -973,9 +1237,13 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("author.date"),
-                vec![Expr::Name(Cow::Borrowed("after:yesterday"))],
+                vec![Expr::Name(
+                    Cow::Borrowed("after:yesterday"),
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [],
             )
@@ -985,9 +1253,10 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("committer.name"),
-                vec![Expr::Name(Cow::Borrowed("Foo"))],
+                vec![Expr::Name(Cow::Borrowed("Foo"), Span::default())],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -1004,9 +1273,10 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("committer.email"),
-                vec![Expr::Name(Cow::Borrowed("foo"))],
+                vec![Expr::Name(Cow::Borrowed("foo"), Span::default())],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -1023,9 +1293,10 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("committer.date"),
-                vec![Expr::Name(Cow::Borrowed("before:today"))],
+                vec![Expr::Name(Cow::Borrowed("before:today"), Span::default())],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -1054,9 +1325,13 @@ mod tests {
         {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("committer.date"),
-                vec![Expr::Name(Cow::Borrowed("after:yesterday"))],
+                vec![Expr::Name(
+                    Cow::Borrowed("after:yesterday"),
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [],
             )
@@ -1066,6 +1341,79 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_eval_scoped_filters() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let references_snapshot = repo.get_references_snapshot()?;
+        let mut dag = Dag::open_and_sync(
+            &effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+
+        let mut eval_oids = |expr: &str| -> eyre::Result<Vec<NonZeroOid>> {
+            let expr = parse(expr)?;
+            let commits = eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr)?;
+            Ok(commits.iter().map(|commit| commit.get_oid()).collect())
+        };
+
+        assert_eq!(
+            eval_oids("draft() & message(create)")?,
+            vec![test2_oid, test3_oid]
+        );
+        assert_eq!(
+            eval_oids("message(create) & draft()")?,
+            vec![test2_oid, test3_oid]
+        );
+        assert_eq!(
+            eval_oids("message(create) & author.name(Testy)")?,
+            eval_oids("message(create)")?,
+        );
+        assert_eq!(eval_oids("draft() - message(test2)")?, vec![test3_oid]);
+
+        // Scopes are pushed down through nested set operations.
+        assert_eq!(
+            eval_oids("draft() & union(message(test2), message(test3))")?,
+            vec![test2_oid, test3_oid]
+        );
+        assert_eq!(
+            eval_oids("message(create) & message(test3) & draft()")?,
+            vec![test3_oid]
+        );
+        assert_eq!(eval_oids("draft() & not(message(test2))")?, vec![test3_oid]);
+        assert_eq!(
+            eval_oids("draft() - difference(message(test2), author.name(nobody))")?,
+            vec![test3_oid]
+        );
+        assert_eq!(
+            eval_oids("draft() & union(message(test2), .)")?,
+            vec![test2_oid, test3_oid]
+        );
+        assert_eq!(eval_oids("none() & message(create)")?, vec![]);
+        assert_eq!(eval_oids("message(create) & none()")?, vec![]);
+
+        // Invalid patterns are still reported, even if there are no commits
+        // to test.
+        assert!(eval_oids("none() & message('regex:[')").is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_eval_aliases() -> eyre::Result<()> {
         let git = make_git()?;
@@ -1100,9 +1448,14 @@ mod tests {
 
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("simpleAlias"),
-                vec![Expr::FunctionCall(Cow::Borrowed("stack"), vec![])],
+                vec![Expr::FunctionCall(
+                    Cow::Borrowed("stack"),
+                    vec![],
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -1125,9 +1478,14 @@ mod tests {
 
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("complexAlias"),
-                vec![Expr::FunctionCall(Cow::Borrowed("stack"), vec![])],
+                vec![Expr::FunctionCall(
+                    Cow::Borrowed("stack"),
+                    vec![],
+                    Span::default(),
+                )],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Ok(
                 [
                     Commit {
@@ -1184,14 +1542,18 @@ mod tests {
             let expr = Expr::FunctionCall(
                 Cow::Borrowed("outerAlias"),
                 vec![
-                    Expr::FunctionCall(Cow::Borrowed("stack"), vec![]),
-                    Expr::FunctionCall(Cow::Borrowed("nonsense"), vec![]),
+                    Expr::FunctionCall(Cow::Borrowed("stack"), vec![], Span::default()),
+                    Expr::FunctionCall(Cow::Borrowed("nonsense"), vec![], Span::default()),
                 ],
+                Span::default(),
             );
-            insta::assert_debug_snapshot!(eval_and_sort(&effects, &repo, &mut dag, &expr), @r###"
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
             Err(
-                UnboundName {
-                    name: "$2",
+                Spanned {
+                    span: 0..0,
+                    source: UnboundName {
+                        name: "$2",
+                    },
                 },
             )
             "###);
@@ -1199,4 +1561,108 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_eval_plugin_functions() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let _test2_oid = git.commit_file("test2", 2)?;
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let references_snapshot = repo.get_references_snapshot()?;
+        let mut dag = Dag::open_and_sync(
+            &effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+
+        {
+            crate::revset::register_revset_function(
+                "testRegisteredFunction",
+                move |_repo: &Repo, args: &[String]| {
+                    assert_eq!(args, ["foo", "stack()"]);
+                    Ok(vec![test1_oid])
+                },
+            )?;
+
+            let expr = Expr::FunctionCall(
+                Cow::Borrowed("testRegisteredFunction"),
+                vec![
+                    Expr::Name(Cow::Borrowed("foo"), Span::default()),
+                    Expr::FunctionCall(Cow::Borrowed("stack"), vec![], Span::default()),
+                ],
+                Span::default(),
+            );
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
+            Ok(
+                [
+                    Commit {
+                        inner: Commit {
+                            id: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e,
+                            summary: "create test1.txt",
+                        },
+                    },
+                ],
+            )
+            "###);
+        }
+
+        {
+            let result = crate::revset::register_revset_function(
+                "stack",
+                |_repo: &Repo, _args: &[String]| Ok(Vec::new()),
+            );
+            assert!(result.is_err());
+        }
+
+        {
+            git.run(&[
+                "config",
+                "branchless.revsets.function.ticket",
+                "git log --format=%H --grep",
+            ])?;
+            let expr = Expr::FunctionCall(
+                Cow::Borrowed("ticket"),
+                vec![Expr::Name(Cow::Borrowed("test2"), Span::default())],
+                Span::default(),
+            );
+            insta::assert_debug_snapshot!(eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr), @r###"
+            Ok(
+                [
+                    Commit {
+                        inner: Commit {
+                            id: 96d1c37a3d4363611c49f7e52186e189a04c531f,
+                            summary: "create test2.txt",
+                        },
+                    },
+                ],
+            )
+            "###);
+        }
+
+        {
+            git.run(&["config", "branchless.revsets.function.failing", "false"])?;
+            let expr = Expr::FunctionCall(Cow::Borrowed("failing"), vec![], Span::default());
+            insta::assert_debug_snapshot!(
+                eval_and_sort(&effects, &git.get_git_run_info(), &repo, &mut dag, &expr).map_err(|err| err.to_string()),
+                @r###"
+            Err(
+                "command for revset function failing exited with status Some(1)",
+            )
+            "###
+            );
+        }
+
+        Ok(())
+    }
 }
@@ -6,12 +6,14 @@ mod builtins;
 mod eval;
 mod parser;
 mod pattern;
+mod plugins;
 mod resolve;
 
-pub use ast::Expr;
-pub use eval::eval;
-pub use parser::parse;
-pub use resolve::resolve_commits;
+pub use ast::{Expr, Span};
+pub use eval::{eval, get_function_names};
+pub use parser::{parse, ParseError, MAX_EXPR_DEPTH};
+pub use plugins::{register_revset_function, RevsetFunction};
+pub use resolve::{resolve_commits, ResolveError};
 
 use lalrpop_util::lalrpop_mod;
 lalrpop_mod!(
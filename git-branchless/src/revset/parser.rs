@@ -1,15 +1,162 @@
+use lalrpop_util::lexer::Token;
 use lazy_static::lazy_static;
 use regex::Regex;
 use thiserror::Error;
 use tracing::instrument;
 
 use super::grammar::ExprParser;
-use super::Expr;
+use super::{Expr, Span};
 
+/// The maximum nesting depth of a parsed expression. The evaluator is
+/// recursive, so deeper expressions could overflow the stack.
+pub const MAX_EXPR_DEPTH: usize = 1000;
+
+/// An error encountered while parsing a revset expression.
+#[allow(missing_docs)]
 #[derive(Debug, Error)]
 pub enum ParseError {
-    #[error("parse error: {0}")]
-    ParseError(String),
+    #[error("parse error: Invalid token at {location}")]
+    InvalidToken { location: usize },
+
+    #[error(
+        "parse error: Unrecognized EOF found at {location}{}",
+        fmt_expected(expected)
+    )]
+    UnrecognizedEof {
+        location: usize,
+        expected: Vec<String>,
+    },
+
+    #[error(
+        "parse error: Unrecognized token `{token}` found at {}:{}{}",
+        span.start,
+        span.end,
+        fmt_expected(expected)
+    )]
+    UnrecognizedToken {
+        token: String,
+        span: Span,
+        expected: Vec<String>,
+    },
+
+    #[error("parse error: Extra token {token} found at {}:{}", span.start, span.end)]
+    ExtraToken { token: String, span: Span },
+
+    #[error("parse error: expression is nested more than {max_depth} levels deep")]
+    TooDeeplyNested { max_depth: usize },
+
+    #[error("parse error: {message}")]
+    Other { message: String },
+}
+
+impl ParseError {
+    /// Get the location in the source text that this error refers to, if any.
+    pub fn get_span(&self) -> Option<Span> {
+        match self {
+            ParseError::InvalidToken { location }
+            | ParseError::UnrecognizedEof { location, .. } => Some(Span::new(*location, *location)),
+            ParseError::UnrecognizedToken { span, .. } | ParseError::ExtraToken { span, .. } => {
+                Some(*span)
+            }
+            ParseError::TooDeeplyNested { .. } | ParseError::Other { .. } => None,
+        }
+    }
+}
+
+/// Format the list of expected tokens in the same way as `lalrpop`.
+fn fmt_expected(expected: &[String]) -> String {
+    let mut result = String::new();
+    for (i, token) in expected.iter().enumerate() {
+        let sep = match i {
+            0 => "\nExpected one of",
+            _ if i < expected.len() - 1 => ",",
+            _ => " or",
+        };
+        result.push_str(sep);
+        result.push(' ');
+        result.push_str(token);
+    }
+    result
+}
+
+/// Describe an expected token from the grammar in a user-friendly way.
+fn describe_expected_token(token: String) -> String {
+    // HACK: `lalrpop` doesn't let us customize the text of the string
+    // literal token, so replace it after the fact.
+    lazy_static! {
+        // NOTE: the `lalrpop` output contains Rust raw string literals, so
+        // we need to match those as well. However, the `#` character is
+        // interpreted by insignificant-whitespace mode as a comment, so we
+        // use `\x23` instead.
+        static ref OBJECT_RE: Regex = Regex::new(
+            r###"(?x)
+                r\x23"
+                \(
+                \[
+                [^"]+
+                "\x23
+            "###
+        )
+        .unwrap();
+        static ref STRING_LITERAL_RE: Regex = Regex::new(
+            r###"(?x)
+                r\x23"
+                \\
+                [^"]+
+                "\x23
+            "###
+        )
+        .unwrap();
+    }
+    let token = OBJECT_RE.replace(&token, "a commit/branch/tag");
+    let token = STRING_LITERAL_RE.replace(&token, "a string literal");
+    token.into_owned()
+}
+
+impl From<lalrpop_util::ParseError<usize, Token<'_>, &str>> for ParseError {
+    fn from(err: lalrpop_util::ParseError<usize, Token<'_>, &str>) -> Self {
+        match err {
+            lalrpop_util::ParseError::InvalidToken { location } => {
+                ParseError::InvalidToken { location }
+            }
+            lalrpop_util::ParseError::UnrecognizedEOF { location, expected } => {
+                ParseError::UnrecognizedEof {
+                    location,
+                    expected: expected.into_iter().map(describe_expected_token).collect(),
+                }
+            }
+            lalrpop_util::ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => ParseError::UnrecognizedToken {
+                token: token.to_string(),
+                span: Span::new(start, end),
+                expected: expected.into_iter().map(describe_expected_token).collect(),
+            },
+            lalrpop_util::ParseError::ExtraToken {
+                token: (start, token, end),
+            } => ParseError::ExtraToken {
+                token: token.to_string(),
+                span: Span::new(start, end),
+            },
+            lalrpop_util::ParseError::User { error } => ParseError::Other {
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+/// Get the nesting depth of the expression, without recursing.
+fn get_depth(expr: &Expr) -> usize {
+    let mut max_depth = 0;
+    let mut stack = vec![(expr, 1)];
+    while let Some((expr, depth)) = stack.pop() {
+        max_depth = max_depth.max(depth);
+        if let Expr::FunctionCall(_name, args, _span) = expr {
+            stack.extend(args.iter().map(|arg| (arg, depth + 1)));
+        }
+    }
+    max_depth
 }
 
 /// Parse a string representing a revset expression into an [Expr].
@@ -17,41 +164,13 @@ pub enum ParseError {
 /// To update the grammar, modify `grammar.lalrpop`.
 #[instrument]
 pub fn parse(s: &str) -> Result<Expr, ParseError> {
-    ExprParser::new().parse(s).map_err(|err| {
-        let message = err.to_string();
-
-        // HACK: `lalrpop` doesn't let us customize the text of the string
-        // literal token, so replace it after the fact.
-        lazy_static! {
-            // NOTE: the `lalrpop` output contains Rust raw string literals, so
-            // we need to match those as well. However, the `#` character is
-            // interpreted by insignificant-whitespace mode as a comment, so we
-            // use `\x23` instead.
-            static ref OBJECT_RE: Regex = Regex::new(
-                r###"(?x)
-                    r\x23"
-                    \(
-                    \[
-                    [^"]+
-                    "\x23
-                "###
-            )
-            .unwrap();
-            static ref STRING_LITERAL_RE: Regex = Regex::new(
-                r###"(?x)
-                    r\x23"
-                    \\
-                    [^"]+
-                    "\x23
-                "###
-            )
-            .unwrap();
-        }
-        let message = OBJECT_RE.replace(&message, "a commit/branch/tag");
-        let message = STRING_LITERAL_RE.replace(&message, "a string literal");
-
-        ParseError::ParseError(message.into_owned())
-    })
+    let expr = ExprParser::new().parse(s)?;
+    if get_depth(&expr) > MAX_EXPR_DEPTH {
+        return Err(ParseError::TooDeeplyNested {
+            max_depth: MAX_EXPR_DEPTH,
+        });
+    }
+    Ok(expr)
 }
 
 #[cfg(test)]
@@ -64,6 +183,7 @@ mod tests {
         Ok(
             Name(
                 "hello",
+                0..5,
             ),
         )
         "###);
@@ -77,6 +197,7 @@ mod tests {
             FunctionCall(
                 "foo",
                 [],
+                0..5,
             ),
         )
         "###);
@@ -87,8 +208,10 @@ mod tests {
                 [
                     Name(
                         "bar",
+                        4..7,
                     ),
                 ],
+                0..8,
             ),
         )
         "###);
@@ -99,11 +222,14 @@ mod tests {
                 [
                     Name(
                         "bar",
+                        4..7,
                     ),
                     Name(
                         "baz",
+                        9..12,
                     ),
                 ],
+                0..13,
             ),
         )
         "###);
@@ -114,33 +240,66 @@ mod tests {
                 [
                     Name(
                         "bar",
+                        4..7,
                     ),
                     Name(
                         "baz",
+                        9..12,
                     ),
                 ],
+                0..14,
             ),
         )
         "###);
         insta::assert_debug_snapshot!(parse("foo(,)"), @r###"
         Err(
-            ParseError(
-                "Unrecognized token `,` found at 4:5\nExpected one of \"(\", \")\", \"..\", \":\", \"::\", a commit/branch/tag or a string literal",
-            ),
+            UnrecognizedToken {
+                token: ",",
+                span: 4..5,
+                expected: [
+                    "\"(\"",
+                    "\")\"",
+                    "\"..\"",
+                    "\":\"",
+                    "\"::\"",
+                    "a commit/branch/tag",
+                    "a string literal",
+                ],
+            },
         )
         "###);
         insta::assert_debug_snapshot!(parse("foo(,bar)"), @r###"
         Err(
-            ParseError(
-                "Unrecognized token `,` found at 4:5\nExpected one of \"(\", \")\", \"..\", \":\", \"::\", a commit/branch/tag or a string literal",
-            ),
+            UnrecognizedToken {
+                token: ",",
+                span: 4..5,
+                expected: [
+                    "\"(\"",
+                    "\")\"",
+                    "\"..\"",
+                    "\":\"",
+                    "\"::\"",
+                    "a commit/branch/tag",
+                    "a string literal",
+                ],
+            },
         )
         "###);
         insta::assert_debug_snapshot!(parse("foo(bar,,)"), @r###"
         Err(
-            ParseError(
-                "Unrecognized token `,` found at 8:9\nExpected one of \"(\", \")\", \"..\", \":\", \"::\", a commit/branch/tag or a string literal",
-            ),
+            UnrecognizedToken {
+                token: ",",
+                span: 8..9,
+                expected: [
+                    "\"(\"",
+                    "\")\"",
+                    "\"..\"",
+                    "\":\"",
+                    "\"::\"",
+                    "a commit/branch/tag",
+                    "a string literal",
+                ],
+            },
         )
         "###);
         Ok(())
@@ -155,19 +314,24 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                     FunctionCall(
                         "intersection",
                         [
                             Name(
                                 "bar",
+                                6..9,
                             ),
                             Name(
                                 "bar",
+                                12..15,
                             ),
                         ],
+                        6..15,
                     ),
                 ],
+                0..15,
             ),
         )
         "###);
@@ -180,16 +344,21 @@ mod tests {
                     [
                         Name(
                             "foo",
+                            0..3,
                         ),
                         Name(
                             "bar",
+                            6..9,
                         ),
                     ],
+                    0..9,
                 ),
                 Name(
                     "bar",
+                    12..15,
                 ),
             ],
+            0..15,
         )
         "###);
         insta::assert_debug_snapshot!(parse("foo | bar")?, @r###"
@@ -198,11 +367,14 @@ mod tests {
             [
                 Name(
                     "foo",
+                    0..3,
                 ),
                 Name(
                     "bar",
+                    6..9,
                 ),
             ],
+            0..9,
         )
         "###);
         insta::assert_debug_snapshot!(parse("foo | bar - baz")?, @r###"
@@ -211,26 +383,38 @@ mod tests {
             [
                 Name(
                     "foo",
+                    0..3,
                 ),
                 FunctionCall(
                     "difference",
                     [
                         Name(
                             "bar",
+                            6..9,
                         ),
                         Name(
                             "baz",
+                            12..15,
                         ),
                     ],
+                    6..15,
                 ),
             ],
+            0..15,
         )
         "###);
         insta::assert_debug_snapshot!(parse("foo |"), @r###"
         Err(
-            ParseError(
-                "Unrecognized EOF found at 5\nExpected one of \"..\", \":\", \"::\", a commit/branch/tag or a string literal",
-            ),
+            UnrecognizedEof {
+                location: 5,
+                expected: [
+                    "\"..\"",
+                    "\":\"",
+                    "\"::\"",
+                    "a commit/branch/tag",
+                    "a string literal",
+                ],
+            },
         )
         "###);
         Ok(())
@@ -245,11 +429,14 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                     Name(
                         "bar",
+                        4..7,
                     ),
                 ],
+                0..7,
             ),
         )
         "###);
@@ -260,8 +447,10 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                 ],
+                0..4,
             ),
         )
         "###);
@@ -272,8 +461,10 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        1..4,
                     ),
                 ],
+                0..4,
             ),
         )
         "###);
@@ -285,11 +476,14 @@ mod tests {
                 [
                     Name(
                         "foo-bar/baz",
+                        0..11,
                     ),
                     Name(
                         "qux-grault",
+                        12..22,
                     ),
                 ],
+                0..22,
             ),
         )
         "###);
@@ -301,11 +495,14 @@ mod tests {
                 [
                     Name(
                         "bar",
+                        5..8,
                     ),
                     Name(
                         "foo",
+                        0..3,
                     ),
                 ],
+                0..8,
             ),
         )
         "###);
@@ -316,11 +513,14 @@ mod tests {
                 [
                     Name(
                         ".",
+                        3..5,
                     ),
                     Name(
                         "foo",
+                        0..3,
                     ),
                 ],
+                0..5,
             ),
         )
         "###);
@@ -331,11 +531,14 @@ mod tests {
                 [
                     Name(
                         "bar",
+                        2..5,
                     ),
                     Name(
                         ".",
+                        0..2,
                     ),
                 ],
+                0..5,
             ),
         )
         "###);
@@ -349,6 +552,7 @@ mod tests {
         Ok(
             Name(
                 "",
+                1..3,
             ),
         )
         "###);
@@ -356,6 +560,7 @@ mod tests {
         Ok(
             Name(
                 "foo",
+                1..6,
             ),
         )
         "###);
@@ -363,6 +568,7 @@ mod tests {
         Ok(
             Name(
                 "foo bar",
+                1..10,
             ),
         )
         "###);
@@ -370,6 +576,7 @@ mod tests {
         Ok(
             Name(
                 "foo\nba\r\\\\baz",
+                1..16,
             ),
         )
         "###);
@@ -377,6 +584,7 @@ mod tests {
         Ok(
             Name(
                 "foo\nba\r\\\\baz",
+                1..16,
             ),
         )
         "###);
@@ -391,6 +599,7 @@ mod tests {
             FunctionCall(
                 "foo",
                 [],
+                2..7,
             ),
         )
         "###);
@@ -407,19 +616,24 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                     FunctionCall(
                         "parents.nth",
                         [
                             Name(
                                 "bar",
+                                4..7,
                             ),
                             Name(
                                 "1",
+                                7..8,
                             ),
                         ],
+                        4..8,
                     ),
                 ],
+                0..8,
             ),
         )
         "###);
@@ -430,19 +644,24 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                     FunctionCall(
                         "parents.nth",
                         [
                             Name(
                                 "bar",
+                                4..7,
                             ),
                             Name(
                                 "1",
+                                7..8,
                             ),
                         ],
+                        4..8,
                     ),
                 ],
+                0..8,
             ),
         )
         "###);
@@ -453,19 +672,24 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                     FunctionCall(
                         "parents.nth",
                         [
                             Name(
                                 "bar",
+                                4..7,
                             ),
                             Name(
                                 "3",
+                                8..9,
                             ),
                         ],
+                        4..9,
                     ),
                 ],
+                0..9,
             ),
         )
         "###);
@@ -477,19 +701,24 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                     FunctionCall(
                         "ancestors.nth",
                         [
                             Name(
                                 "bar",
+                                4..7,
                             ),
                             Name(
                                 "1",
+                                7..8,
                             ),
                         ],
+                        4..8,
                     ),
                 ],
+                0..8,
             ),
         )
         "###);
@@ -500,19 +729,24 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                     FunctionCall(
                         "ancestors.nth",
                         [
                             Name(
                                 "bar",
+                                4..7,
                             ),
                             Name(
                                 "1",
+                                7..8,
                             ),
                         ],
+                        4..8,
                     ),
                 ],
+                0..8,
             ),
         )
         "###);
@@ -523,23 +757,60 @@ mod tests {
                 [
                     Name(
                         "foo",
+                        0..3,
                     ),
                     FunctionCall(
                         "ancestors.nth",
                         [
                             Name(
                                 "bar",
+                                4..7,
                             ),
                             Name(
                                 "3",
+                                8..9,
                             ),
                         ],
+                        4..9,
                     ),
                 ],
+                0..9,
             ),
         )
         "###);
 
         Ok(())
     }
+
+    #[test]
+    fn test_revset_parse_deeply_nested() -> eyre::Result<()> {
+        let revset = format!("foo{}", "^".repeat(100_000));
+        insta::assert_debug_snapshot!(parse(&revset), @r###"
+        Err(
+            TooDeeplyNested {
+                max_depth: 1000,
+            },
+        )
+        "###);
+
+        let revset = format!("{}foo{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert!(parse(&revset).is_ok());
+
+        let revset = format!("foo{}", "^".repeat(MAX_EXPR_DEPTH - 1));
+        assert!(parse(&revset).is_ok());
+
+        Ok(())
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_revset_parse_arbitrary_input(s in ".*") {
+            let _ = parse(&s);
+        }
+
+        #[test]
+        fn test_revset_parse_arbitrary_tokens(s in r#"([a-z0-9.]+|[()|+&%:^~,'"\\ -]|and|or|\.\.|::){0,64}"#) {
+            let _ = parse(&s);
+        }
+    }
 }
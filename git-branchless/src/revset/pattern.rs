@@ -53,6 +53,19 @@ pub enum PatternError {
 }
 
 impl Pattern {
+    /// Like `Pattern::new`, but a pattern without a prefix is treated as a
+    /// glob rather than a substring. This suits reference names, such as
+    /// `experiment/*`.
+    pub fn new_glob_by_default(pattern: &str) -> Result<Self, PatternError> {
+        const PREFIXES: &[&str] = &["exact:", "substring:", "substr:", "glob:", "regex:"];
+        if PREFIXES.iter().any(|prefix| pattern.starts_with(prefix)) {
+            Self::new(pattern)
+        } else {
+            let pattern = glob::Pattern::new(pattern)?;
+            Ok(Pattern::Glob(pattern))
+        }
+    }
+
     pub fn matches_text(&self, subject: &str) -> bool {
         let subject = subject.strip_suffix('\n').unwrap_or(subject);
         match self {
@@ -140,10 +153,28 @@ pub(super) fn make_pattern_matcher_set(
         active_commits: CommitSet,
         matcher: Box<dyn PatternMatcher>,
     }
+
+    // If the caller only needs a subset of the commits, then only test those,
+    // iterating over the (presumably smaller) scope rather than all active
+    // commits.
+    let active_commits = ctx.query_active_commits().map_err(Box::new)?.clone();
+    let active_commits = match ctx.filter_scope.take() {
+        None => active_commits,
+        Some(scope) => {
+            if scope
+                .is_empty()
+                .map_err(|err| PatternError::Repo(err.into()))?
+            {
+                return Ok(CommitSet::empty());
+            }
+            scope.intersection(&active_commits)
+        }
+    };
+
     let wrapped = Arc::new(Mutex::new(Wrapped {
         effects: ctx.effects.clone(),
         repo: repo.try_clone().map_err(PatternError::Repo)?,
-        active_commits: ctx.query_active_commits().map_err(Box::new)?.clone(),
+        active_commits,
         matcher,
     }));
 
@@ -179,9 +210,18 @@ pub(super) fn make_pattern_matcher_set(
                             let commit_oid =
                                 NonZeroOid::try_from(commit_oid).map_err(make_dag_backend_error)?;
                             let repo = repo_pool.try_create().map_err(make_dag_backend_error)?;
-                            let commit = repo
-                                .find_commit_or_fail(commit_oid)
-                                .map_err(make_dag_backend_error)?;
+                            let commit = match repo
+                                .find_commit(commit_oid)
+                                .map_err(make_dag_backend_error)?
+                            {
+                                Some(commit) => commit,
+                                None => {
+                                    // The commit may have been garbage
+                                    // collected, in which case it can't match.
+                                    progress.notify_progress_inc(1);
+                                    return Ok(acc);
+                                }
+                            };
                             if matcher
                                 .matches_commit(&*repo, &commit)
                                 .map_err(make_dag_backend_error)?
@@ -216,9 +256,10 @@ pub(super) fn make_pattern_matcher_set(
             }
 
             let oid = NonZeroOid::try_from(vertex.clone()).map_err(make_dag_backend_error)?;
-            let commit = repo
-                .find_commit_or_fail(oid)
-                .map_err(make_dag_backend_error)?;
+            let commit = match repo.find_commit(oid).map_err(make_dag_backend_error)? {
+                Some(commit) => commit,
+                None => return Ok(false),
+            };
             let result = matcher
                 .matches_commit(repo, &commit)
                 .map_err(make_dag_backend_error)?;
@@ -0,0 +1,145 @@
+//! Revset functions which are defined outside of this crate.
+//!
+//! There are two ways to add a revset function without modifying the built-in
+//! functions:
+//!
+//! - Programs which embed `git-branchless` can call
+//!   [`register_revset_function`] with any type implementing
+//!   [`RevsetFunction`] before evaluating revsets.
+//! - Users can set `branchless.revsets.function.<name>` to a shell command. The
+//!   command is invoked with the function's arguments, and should print the
+//!   commits which match, one per line.
+//!
+//! Unknown function names are only resolved against these sources at
+//! evaluation time, so the parser accepts calls to any function, such as
+//! `jira("ABC-123")`.
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+
+use eyre::{eyre, Context};
+use lazy_static::lazy_static;
+use lib::git::{ConfigRead, NonZeroOid, Repo};
+use lib::util::shell_command;
+
+use super::builtins::FUNCTIONS;
+
+/// A revset function provided by an extension.
+pub trait RevsetFunction: Send + Sync {
+    /// Evaluate the function with the given arguments and return the OIDs of
+    /// the matching commits.
+    ///
+    /// Arguments which are names or string literals are passed as-is. Arguments
+    /// which are themselves function calls are passed as the text of the
+    /// expression, such as `stack()`.
+    fn eval(&self, repo: &Repo, args: &[String]) -> eyre::Result<Vec<NonZeroOid>>;
+}
+
+impl<F> RevsetFunction for F
+where
+    F: Fn(&Repo, &[String]) -> eyre::Result<Vec<NonZeroOid>> + Send + Sync,
+{
+    fn eval(&self, repo: &Repo, args: &[String]) -> eyre::Result<Vec<NonZeroOid>> {
+        self(repo, args)
+    }
+}
+
+lazy_static! {
+    static ref REGISTERED_FUNCTIONS: RwLock<HashMap<String, Arc<dyn RevsetFunction>>> =
+        Default::default();
+}
+
+/// Register a revset function under the given name, replacing any function
+/// previously registered with that name.
+///
+/// Built-in functions can't be replaced.
+pub fn register_revset_function(
+    name: impl Into<String>,
+    function: impl RevsetFunction + 'static,
+) -> eyre::Result<()> {
+    let name = name.into();
+    if FUNCTIONS.contains_key(name.as_str()) {
+        eyre::bail!("cannot replace built-in revset function: {}", name);
+    }
+    REGISTERED_FUNCTIONS
+        .write()
+        .map_err(|_| eyre!("revset function registry lock poisoned"))?
+        .insert(name, Arc::new(function));
+    Ok(())
+}
+
+/// Get the names of all registered revset functions.
+pub(super) fn get_registered_function_names() -> Vec<String> {
+    match REGISTERED_FUNCTIONS.read() {
+        Ok(functions) => functions.keys().cloned().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Evaluate the registered function or configured command with the given name,
+/// if any.
+pub(super) fn eval_plugin_function(
+    repo: &Repo,
+    name: &str,
+    args: &[String],
+) -> eyre::Result<Option<Vec<NonZeroOid>>> {
+    let function = REGISTERED_FUNCTIONS
+        .read()
+        .map_err(|_| eyre!("revset function registry lock poisoned"))?
+        .get(name)
+        .cloned();
+    if let Some(function) = function {
+        return function.eval(repo, args).map(Some);
+    }
+
+    let command: Option<String> = repo
+        .get_readonly_config()?
+        .get(format!("branchless.revsets.function.{}", name))?;
+    match command {
+        Some(command) => run_function_command(repo, name, &command, args).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn run_function_command(
+    repo: &Repo,
+    name: &str,
+    command: &str,
+    args: &[String],
+) -> eyre::Result<Vec<NonZeroOid>> {
+    let output = shell_command(command)?
+        .current_dir(
+            repo.get_working_copy_path()
+                .unwrap_or_else(|| repo.get_path()),
+        )
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .wrap_err_with(|| format!("Invoking command for revset function: {}", name))?;
+    if !output.status.success() {
+        eyre::bail!(
+            "command for revset function {} exited with status {:?}",
+            name,
+            output.status.code()
+        );
+    }
+
+    let mut result = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match repo.revparse_single_commit(line)? {
+            Some(commit) => result.push(commit.get_oid()),
+            None => eyre::bail!(
+                "command for revset function {} printed an unknown commit: {}",
+                name,
+                line
+            ),
+        }
+    }
+    Ok(result)
+}
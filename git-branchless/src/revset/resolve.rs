@@ -1,45 +1,61 @@
 use std::fmt::Write;
 
-use lib::core::dag::{CommitSet, Dag};
+use itertools::Itertools;
+use lib::core::dag::{commit_set_to_vec_unsorted, CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::git::Repo;
+use lib::core::formatting::Pluralize;
+use lib::git::{GitRunInfo, Repo};
 use tracing::instrument;
 
 use crate::opts::Revset;
 
 use super::eval::EvalError;
 use super::parser::ParseError;
-use super::{eval, parse};
+use super::{eval, parse, Span};
 
 /// The result of attempting to resolve commits.
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum ResolveError {
-    ParseError { expr: String, source: ParseError },
-    EvalError { expr: String, source: EvalError },
-    DagError { source: eden_dag::Error },
-    OtherError { source: eyre::Error },
+    /// The revset expression couldn't be parsed.
+    ParseError {
+        /// The revset expression.
+        expr: String,
+
+        /// The underlying parse error.
+        source: ParseError,
+    },
+
+    /// The revset expression was parsed, but couldn't be evaluated, such as
+    /// if it called an unknown function.
+    EvalError {
+        /// The revset expression.
+        expr: String,
+
+        /// The underlying evaluation error.
+        source: EvalError,
+    },
+
+    /// Querying the commit graph failed.
+    DagError {
+        /// The underlying error.
+        source: eden_dag::Error,
+    },
+
+    /// Some other error occurred, such as failing to read the repository.
+    OtherError {
+        /// The underlying error.
+        source: eyre::Error,
+    },
 }
 
 impl ResolveError {
+    /// Describe a problem with the user's revset expression to the user.
+    /// Errors which aren't caused by the expression are returned instead.
     pub fn describe(self, effects: &Effects) -> eyre::Result<()> {
         match self {
-            ResolveError::ParseError { expr, source } => {
-                writeln!(
-                    effects.get_error_stream(),
-                    "Parse error for expression '{}': {}",
-                    expr,
-                    source
-                )?;
-                Ok(())
-            }
-            ResolveError::EvalError { expr, source } => {
-                writeln!(
-                    effects.get_error_stream(),
-                    "Evaluation error for expression '{}': {}",
-                    expr,
-                    source
-                )?;
+            ResolveError::ParseError { .. } | ResolveError::EvalError { .. } => {
+                writeln!(effects.get_error_stream(), "{}", self)?;
                 Ok(())
             }
             ResolveError::DagError { source } => Err(source.into()),
@@ -48,6 +64,50 @@ impl ResolveError {
     }
 }
 
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveError::ParseError { expr, source } => {
+                write!(f, "Parse error for expression '{}': {}", expr, source)
+            }
+            ResolveError::EvalError { expr, source } => {
+                write!(f, "Evaluation error for expression '{}': {}", expr, source)?;
+                match source.get_span() {
+                    Some(span) => write_span_marker(f, expr, span),
+                    None => Ok(()),
+                }
+            }
+            ResolveError::DagError { source } => write!(f, "{}", source),
+            ResolveError::OtherError { source } => write!(f, "{}", source),
+        }
+    }
+}
+
+/// Underline the part of `expr` at `span` with carets on a new line, so that
+/// the user can tell which part of the expression an error refers to. Nothing
+/// is written if the span covers the entire expression.
+fn write_span_marker(f: &mut std::fmt::Formatter<'_>, expr: &str, span: Span) -> std::fmt::Result {
+    let (before, marked, after) = match (
+        expr.get(..span.start),
+        expr.get(span.start..span.end),
+        expr.get(span.end..),
+    ) {
+        (Some(before), Some(marked), Some(after)) => (before, marked, after),
+        _ => return Ok(()),
+    };
+    if before.trim().is_empty() && after.trim().is_empty() {
+        return Ok(());
+    }
+
+    write!(
+        f,
+        "\n    {}\n    {}{}",
+        expr,
+        " ".repeat(before.chars().count()),
+        "^".repeat(marked.chars().count().max(1)),
+    )
+}
+
 /// Parse strings which refer to commits, such as:
 ///
 /// - Full OIDs.
@@ -56,6 +116,7 @@ impl ResolveError {
 #[instrument]
 pub fn resolve_commits(
     effects: &Effects,
+    git_run_info: &GitRunInfo,
     repo: &Repo,
     dag: &mut Dag,
     revsets: Vec<Revset>,
@@ -76,9 +137,11 @@ pub fn resolve_commits(
             expr: revset.clone(),
             source: err,
         })?;
-        let commits = eval(effects, repo, dag, &expr).map_err(|err| ResolveError::EvalError {
-            expr: revset.clone(),
-            source: err,
+        let commits = eval(effects, git_run_info, repo, dag, &expr).map_err(|err| {
+            ResolveError::EvalError {
+                expr: revset.clone(),
+                source: err,
+            }
         })?;
 
         // Note that commits which are identified by `revparse_single_commit`
@@ -87,9 +150,44 @@ pub fn resolve_commits(
         // simple revset expression, such as `HEAD^`) will result in it being
         // returned, but not when using a revset expression like
         // `descendants(@)`.
-        let commits = commits.difference(&dag.obsolete_commits);
+        //
+        // Likewise, obsolete commits are returned if they were explicitly
+        // requested with `obsolete()`.
+        let commits = if expr.contains_function_call("obsolete") {
+            commits
+        } else {
+            commits.difference(&dag.obsolete_commits)
+        };
 
         commit_sets.push(commits);
     }
+
+    warn_evaporated_commits(effects, dag)
+        .map_err(|err| ResolveError::OtherError { source: err })?;
     Ok(commit_sets)
 }
+
+/// Let the user know that some commits are missing from the results because
+/// they no longer exist in the repository.
+fn warn_evaporated_commits(effects: &Effects, dag: &Dag) -> eyre::Result<()> {
+    let evaporated_oids = commit_set_to_vec_unsorted(&dag.evaporated_commits)?;
+    if evaporated_oids.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        effects.get_error_stream(),
+        "{}: {} could not be found in the repository and will be ignored: {}",
+        effects.get_glyphs().render_warning_label()?,
+        Pluralize {
+            determiner: None,
+            amount: evaporated_oids.len(),
+            unit: ("commit", "commits"),
+        },
+        evaporated_oids.into_iter().sorted().join(", "),
+    )?;
+    writeln!(
+        effects.get_error_stream(),
+        "They may have been garbage collected. Run `git branchless repair --no-dry-run` to clean up the event log."
+    )?;
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use lib::core::effects::Effects;
 use lib::core::node_descriptors::NodeDescriptor;
 use lib::git::{Commit, NonZeroOid};
 
@@ -5,16 +6,18 @@ use lib::git::{Commit, NonZeroOid};
 /// of commits, and returns the OID of the selected commit.
 #[cfg(unix)]
 pub fn prompt_select_commit(
+    effects: &Effects,
     header: Option<&str>,
     initial_query: &str,
     commits: Vec<Commit>,
     commit_descriptors: &mut [&mut dyn NodeDescriptor],
 ) -> eyre::Result<Option<NonZeroOid>> {
-    skim::prompt_skim(header, initial_query, commits, commit_descriptors)
+    skim::prompt_skim(effects, header, initial_query, commits, commit_descriptors)
 }
 
 #[cfg(not(unix))]
 pub fn prompt_select_commit(
+    effects: &Effects,
     header: Option<&str>,
     initial_query: &str,
     commits: Vec<Commit>,
@@ -32,6 +35,7 @@ mod skim {
 
     use itertools::Itertools;
 
+    use lib::core::effects::Effects;
     use lib::core::formatting::{printable_styled_string, Glyphs};
     use lib::core::node_descriptors::{render_node_descriptors, NodeDescriptor, NodeObject};
     use lib::git::{Commit, NonZeroOid};
@@ -95,10 +99,11 @@ mod skim {
 
     impl CommitSkimItem {
         fn from_descriptors(
+            effects: &Effects,
             commit: &Commit,
             commit_descriptors: &mut [&mut dyn NodeDescriptor],
         ) -> eyre::Result<Self> {
-            let glyphs = Glyphs::pretty();
+            let glyphs = Glyphs::pretty().with_theme(effects.get_theme().clone());
             let styled_summary = render_node_descriptors(
                 &glyphs,
                 &NodeObject::Commit {
@@ -111,8 +116,8 @@ mod skim {
                 oid: commit.get_oid(),
                 styled_summary: printable_styled_string(&glyphs, styled_summary)?,
                 styled_preview: printable_styled_string(
-                    &Glyphs::pretty(),
-                    commit.friendly_preview()?,
+                    &glyphs,
+                    commit.friendly_preview(&glyphs)?,
                 )?,
             })
         }
@@ -120,6 +125,7 @@ mod skim {
 
     #[cfg(unix)]
     pub fn prompt_skim(
+        effects: &Effects,
         header: Option<&str>,
         initial_query: &str,
         commits: Vec<Commit>,
@@ -138,7 +144,7 @@ mod skim {
 
         let items: Vec<CommitSkimItem> = commits
             .iter()
-            .map(|commit| CommitSkimItem::from_descriptors(commit, commit_descriptors))
+            .map(|commit| CommitSkimItem::from_descriptors(effects, commit, commit_descriptors))
             .try_collect()?;
 
         let rx_item = {
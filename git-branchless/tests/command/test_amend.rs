@@ -1,3 +1,4 @@
+use crate::util::{run_in_pty, PtyAction};
 use lib::testing::{make_git, GitRunOptions};
 
 #[test]
@@ -44,7 +45,7 @@ fn test_amend_with_children() -> eyre::Result<()> {
         let (stdout, _stderr) = git.run_with_options(
             &["branchless", "amend"],
             &GitRunOptions {
-                expected_exit_code: 1,
+                expected_exit_code: 3,
                 ..Default::default()
             },
         )?;
@@ -310,6 +311,150 @@ fn test_amend_executable() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg(unix)]
+fn test_amend_ignores_executable_bit_without_core_filemode() -> eyre::Result<()> {
+    use std::{fs, os::unix::prelude::PermissionsExt};
+
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["config", "core.fileMode", "false"])?;
+    git.commit_file("test1", 1)?;
+
+    git.write_file("test1", "updated contents\n")?;
+    git.set_file_permissions("test1", fs::Permissions::from_mode(0o777))?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "amend"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> reset
+        No abandoned commits to restack.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |
+        @ 885cc20 create test1.txt
+        Amended with 1 uncommitted change.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "--raw", "--oneline", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        885cc20 create test1.txt
+        :000000 100644 0000000 27e2fc9 A	test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_clean_filter() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["config", "filter.redact.clean", "sed s/secret/REDACTED/"])?;
+    git.run(&["config", "filter.redact.smudge", "cat"])?;
+    std::fs::write(
+        git.repo_path.join(".gitattributes"),
+        "test1.txt filter=redact\n",
+    )?;
+    git.run(&["add", ".gitattributes"])?;
+    git.commit_file("test1", 1)?;
+
+    git.write_file("test1", "a secret value\n")?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "amend"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> reset
+        No abandoned commits to restack.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |
+        @ 99f430a create test1.txt
+        Amended with 1 uncommitted change.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "HEAD:test1.txt"])?;
+        insta::assert_snapshot!(stdout, @"a REDACTED value");
+    }
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        assert_eq!(stdout, "");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_submodule() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["init", "submodule"])?;
+    git.run(&["-C", "submodule", "config", "user.name", "Submodule User"])?;
+    git.run(&[
+        "-C",
+        "submodule",
+        "config",
+        "user.email",
+        "submodule@example.com",
+    ])?;
+    git.run(&[
+        "-C",
+        "submodule",
+        "commit",
+        "--allow-empty",
+        "-m",
+        "submodule 1",
+    ])?;
+    git.run(&["add", "submodule"])?;
+    git.run(&["commit", "-m", "add submodule"])?;
+
+    git.run(&[
+        "-C",
+        "submodule",
+        "commit",
+        "--allow-empty",
+        "-m",
+        "submodule 2",
+    ])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "amend"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> reset
+        No abandoned commits to restack.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |
+        @ db166e7 add submodule
+        Amended with 1 uncommitted change.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "--raw", "--oneline", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        db166e7 add submodule
+        :000000 160000 0000000 369d97b A	submodule
+        "###);
+    }
+    {
+        let (submodule_head, _stderr) = git.run(&["-C", "submodule", "rev-parse", "HEAD"])?;
+        let (gitlink, _stderr) = git.run(&["rev-parse", "HEAD:submodule"])?;
+        assert_eq!(gitlink, submodule_head);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        assert_eq!(stdout, "");
+    }
+
+    Ok(())
+}
+
 #[test]
 #[cfg(unix)]
 fn test_amend_unresolved_merge_conflict() -> eyre::Result<()> {
@@ -413,6 +558,7 @@ fn test_amend_undo() -> eyre::Result<()> {
         4. Restore snapshot for branch master
                     pointing to c0bdfb5 create file1.txt
                 backed up using 4416dc0 branchless: automated working copy snapshot
+        branchless: processing 1 update: branch master
         branchless: running command: <git-executable> checkout 4416dc07ff5adcc1601f93362ab9924d78d3b05a -B master
         branchless: running command: <git-executable> reset --hard HEAD
         HEAD is now at 4416dc0 branchless: automated working copy snapshot
@@ -513,3 +659,155 @@ fn test_amend_undo_detached_head() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg(unix)]
+fn test_amend_runs_commit_hooks() -> eyre::Result<()> {
+    use std::{fs, os::unix::prelude::PermissionsExt};
+
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let hook_path = git.repo_path.join(".git").join("hooks").join("pre-commit");
+    fs::write(
+        &hook_path,
+        "#!/bin/sh\nif git diff --cached | grep -q TODO; then echo 'Found a TODO'; exit 1; fi\n",
+    )?;
+    fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755))?;
+    git.run(&["config", "branchless.hooks.runCommitHooks", "true"])?;
+
+    git.write_file("test1", "TODO\n")?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["amend"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found a TODO
+        Aborting amend because the pre-commit hook failed.
+        "###);
+    }
+
+    git.write_file("test1", "done\n")?;
+    {
+        let (stdout, _stderr) = git.run(&["amend"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> reset
+        No abandoned commits to restack.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |
+        @ 07e3323 create test1.txt
+        Amended with 1 uncommitted change.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_patch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    git.write_file("test1", "contents1\n")?;
+    git.write_file("test2", "contents2\n")?;
+    {
+        run_in_pty(
+            &git,
+            &["branchless", "amend", "--patch"],
+            &[
+                PtyAction::WaitUntilContains("contents1"),
+                PtyAction::Write("q"),
+            ],
+        )?;
+    }
+
+    {
+        // The above should not have amended anything.
+        let (stdout, _stderr) = git.run(&["diff", "--name-only"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        test1.txt
+        test2.txt
+        "###);
+    }
+
+    {
+        run_in_pty(
+            &git,
+            &["branchless", "amend", "--patch"],
+            &[
+                PtyAction::WaitUntilContains("contents1"),
+                // Select the changes to `test1.txt`.
+                PtyAction::Write(" "),
+                PtyAction::WaitUntilContains("[X]"),
+                PtyAction::Write("c"),
+            ],
+        )?;
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "--format=%s"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+
+        diff --git a/test1.txt b/test1.txt
+        index 7432a8f..a024003 100644
+        --- a/test1.txt
+        +++ b/test1.txt
+        @@ -1 +1 @@
+        -test1 contents
+        +contents1
+        diff --git a/test2.txt b/test2.txt
+        new file mode 100644
+        index 0000000..4e512d2
+        --- /dev/null
+        +++ b/test2.txt
+        @@ -0,0 +1 @@
+        +test2 contents
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["diff"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        diff --git a/test2.txt b/test2.txt
+        index 4e512d2..6b46faa 100644
+        --- a/test2.txt
+        +++ b/test2.txt
+        @@ -1 +1 @@
+        -test2 contents
+        +contents2
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        @ 058ec4b create test2.txt
+        |
+        o 5216fdb create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_branch_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "branch", "--stack", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Create branch test/create-test1.txt/1 at 62fc20d create test1.txt
+        Create branch test/create-test1.txt/2 at 96d1c37 create test2.txt
+        Create branch test/create-test1.txt/3 at 70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "branch", "--stack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Create branch test/create-test1.txt/1 at 62fc20d create test1.txt
+        Create branch test/create-test1.txt/2 at 96d1c37 create test2.txt
+        Create branch test/create-test1.txt/3 at 70deb1e create test3.txt
+        branchless: processing 3 updates: branch test/create-test1.txt/1, branch test/create-test1.txt/2, branch test/create-test1.txt/3
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "branch", "--stack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Stack branches are already up to date.
+        "###);
+    }
+
+    // Removing a commit from the stack renumbers the stack branches.
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "-x",
+            &test2_oid.to_string(),
+            "-d",
+            "master",
+            "--in-memory",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 4838e49 create test3.txt
+        [2/2] Committed as: fe65c1f create test2.txt
+        branchless: processing 2 updates: branch test/create-test1.txt/2, branch test/create-test1.txt/3
+        branchless: processing 2 rewritten commits
+        branchless: processing 2 updates: branch test/create-test1.txt/2, branch test/create-test1.txt/3
+        branchless: running command: <git-executable> checkout 4838e49b08954becdd17c0900c1179c2c654c627
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d (test/create-test1.txt/1) create test1.txt
+        | |
+        | @ 4838e49 (test/create-test1.txt/2) create test3.txt
+        |
+        o fe65c1f create test2.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d (test/create-test1.txt/1) create test1.txt
+        | |
+        | @ 4838e49 (test/create-test1.txt/2) create test3.txt
+        |
+        o fe65c1f create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_branch_stack_name() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.run(&["config", "branchless.stackBranches.user", "jdoe"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "branch", "--stack", "--name", "feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Create branch jdoe/feature/1 at 62fc20d create test1.txt
+        branchless: processing 1 update: branch jdoe/feature/1
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["reword", "-m", "amended test1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 5b7c694 amended test1
+        branchless: processing 1 update: branch jdoe/feature/1
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 5b7c69463612ab9104c7630dd439231b9b846053
+        In-memory rebase succeeded.
+        Reworded commit 62fc20d as 5b7c694 amended test1
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 5b7c694 (jdoe/feature/1) amended test1
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_branch_stack_ignores_other_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.run(&["config", "branchless.stackBranches.user", "jdoe"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    // These branches look like stack branches, but weren't created by `git
+    // branchless branch --stack`.
+    git.run(&["branch", "jdoe/mine/1", "HEAD^"])?;
+    git.run(&["branch", "jdoe/mine/2", "HEAD^"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["reword", "HEAD^", "-m", "amended test1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 5b7c694 amended test1
+        [2/2] Committed as: d8fb700 create test2.txt
+        branchless: processing 2 updates: branch jdoe/mine/1, branch jdoe/mine/2
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout d8fb7002d5e666a5baddb20ec67173e58c5d1e0d
+        In-memory rebase succeeded.
+        Reworded commit 62fc20d as 5b7c694 amended test1
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 5b7c694 (jdoe/mine/1, jdoe/mine/2) amended test1
+        |
+        @ d8fb700 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use lib::testing::{make_git, make_git_with_remote_repo, GitRunOptions, GitWrapperWithRemoteRepo};
+
+#[test]
+fn test_config_set_get_unset() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "config", "get", "undo.createSnapshots"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        true
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "config", "get", "bugReport.recordTraces"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        false
+        "###);
+    }
+
+    git.run(&["branchless", "config", "set", "undo.createSnapshots", "no"])?;
+    git.run(&[
+        "branchless",
+        "config",
+        "set",
+        "core.protectedBranches",
+        "release/*",
+        "prod",
+    ])?;
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "config", "get", "undo.createSnapshots"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        false
+        "###);
+    }
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "config", "get", "core.protectedBranches"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        release/*
+        prod
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "config", "list", "--scope", "repo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless.core.mainbranch = master
+        branchless.undo.createsnapshots = false
+        branchless.core.protectedbranches = release/*
+        branchless.core.protectedbranches = prod
+        "###);
+    }
+
+    git.run(&[
+        "branchless",
+        "config",
+        "unset",
+        "branchless.core.protectedBranches",
+    ])?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "config", "get", "core.protectedBranches"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_config_validation() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "config", "set", "core.noSuchKey", "foo"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Unknown configuration key: branchless.core.noSuchKey
+        Run `git branchless config list --verbose` to see the known keys.
+        "###);
+    }
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "config", "set", "ci.cacheTtl", "soon"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Invalid value for branchless.ci.cacheTtl: expected a non-negative integer, but got: soon
+        "###);
+    }
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &[
+                "branchless",
+                "config",
+                "set",
+                "reword.context",
+                "everything",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Invalid value for branchless.reword.context: expected one of none, stat, files, but got: everything
+        "###);
+    }
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &[
+                "branchless",
+                "config",
+                "set",
+                "core.mainBranch",
+                "main",
+                "trunk",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        The key branchless.core.mainBranch accepts only a single value, but got 2.
+        "###);
+    }
+
+    git.run(&[
+        "branchless",
+        "config",
+        "set",
+        "revsets.alias.mine",
+        "draft()",
+    ])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "config", "get", "revsets.alias.mine"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        draft()
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_config_user_scope() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir,
+        original_repo: git,
+        cloned_repo: _,
+    } = make_git_with_remote_repo()?;
+    git.init_repo()?;
+
+    let home_dir = temp_dir.path().join("home");
+    std::fs::create_dir_all(&home_dir)?;
+    let options = GitRunOptions {
+        env: {
+            let mut env = HashMap::new();
+            env.insert("HOME".to_string(), home_dir.to_str().unwrap().to_string());
+            env
+        },
+        ..Default::default()
+    };
+
+    git.run_with_options(
+        &[
+            "branchless",
+            "config",
+            "set",
+            "--scope",
+            "user",
+            "smartlog.changesBanner",
+            "false",
+        ],
+        &options,
+    )?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "config", "list", "--scope", "user"],
+            &options,
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless.smartlog.changesbanner = false
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "config", "get", "smartlog.changesBanner"],
+            &options,
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        false
+        "###);
+    }
+
+    // The repository setting takes precedence over the user setting.
+    git.run_with_options(
+        &[
+            "branchless",
+            "config",
+            "set",
+            "smartlog.changesBanner",
+            "true",
+        ],
+        &options,
+    )?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "config", "get", "smartlog.changesBanner"],
+            &options,
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        true
+        "###);
+    }
+
+    Ok(())
+}
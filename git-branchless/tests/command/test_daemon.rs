@@ -0,0 +1,249 @@
+#[cfg(unix)]
+mod unix {
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::path::Path;
+    use std::process::{Child, Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    use lib::testing::{make_git, Git, GitRunOptions};
+
+    struct DaemonProcess(Child);
+
+    impl Drop for DaemonProcess {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn spawn_daemon(git: &Git) -> eyre::Result<DaemonProcess> {
+        spawn_daemon_with_env(git, HashMap::new())
+    }
+
+    fn spawn_daemon_with_env(
+        git: &Git,
+        env: HashMap<String, String>,
+    ) -> eyre::Result<DaemonProcess> {
+        let child = Command::new(assert_cmd::cargo::cargo_bin("git-branchless"))
+            .args(["daemon", "--poll-interval", "100"])
+            .current_dir(&git.repo_path)
+            .env_clear()
+            .envs(git.get_base_env(0))
+            .envs(env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let daemon = DaemonProcess(child);
+
+        let socket_path = git
+            .repo_path
+            .join(".git")
+            .join("branchless")
+            .join("daemon.sock");
+        let start = Instant::now();
+        while UnixStream::connect(&socket_path).is_err() {
+            if start.elapsed() > Duration::from_secs(30) {
+                eyre::bail!("Timed out waiting for daemon socket at {:?}", socket_path);
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        Ok(daemon)
+    }
+
+    fn query_daemon(socket_path: &Path) -> eyre::Result<String> {
+        let mut stream = UnixStream::connect(socket_path)?;
+        writeln!(stream, "smartlog text")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    #[test]
+    fn test_daemon_smartlog() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "-b", "foo"])?;
+        git.commit_file("test2", 2)?;
+
+        let _daemon = spawn_daemon(&git)?;
+        let socket_path = git
+            .repo_path
+            .join(".git")
+            .join("branchless")
+            .join("daemon.sock");
+
+        {
+            let response = query_daemon(&socket_path)?;
+            insta::assert_snapshot!(response, @r###"
+            ok
+            :
+            O 62fc20d (master) create test1.txt
+            |
+            @ 96d1c37 (> foo) create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d (master) create test1.txt
+            |
+            @ 96d1c37 (> foo) create test2.txt
+            "###);
+        }
+
+        // The daemon must not serve stale output after the repository changes.
+        git.commit_file("test3", 3)?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d (master) create test1.txt
+            |
+            o 96d1c37 create test2.txt
+            |
+            @ 70deb1e (> foo) create test3.txt
+            "###);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_daemon_smartlog_glyphs() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        let _daemon = spawn_daemon(&git)?;
+        let socket_path = git
+            .repo_path
+            .join(".git")
+            .join("branchless")
+            .join("daemon.sock");
+        git.run(&["config", "branchless.smartlog.glyphs", "unicode"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            ◇ f777ecc (master) create initial.txt
+            ┃
+            ● 62fc20d create test1.txt
+            "###);
+        }
+        {
+            let response = query_daemon(&socket_path)?;
+            insta::assert_snapshot!(response, @r###"
+            ok
+            ◇ f777ecc (master) create initial.txt
+            ┃
+            ● 62fc20d create test1.txt
+            "###);
+        }
+
+        // The client's configuration may differ from the daemon's, in which
+        // case the client has to render the smartlog itself.
+        {
+            let home_dir = git.repo_path.join(".git").join("home");
+            std::fs::create_dir_all(&home_dir)?;
+            std::fs::write(
+                home_dir.join(".gitconfig"),
+                "[branchless \"smartlog\"]\n\theadGlyph = H\n",
+            )?;
+            let (stdout, _stderr) = git.run_with_options(
+                &["smartlog"],
+                &GitRunOptions {
+                    env: {
+                        let mut env = HashMap::new();
+                        env.insert("HOME".to_string(), home_dir.to_str().unwrap().to_string());
+                        env
+                    },
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            ◇ f777ecc (master) create initial.txt
+            ┃
+            H 62fc20d create test1.txt
+            "###);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_daemon_global_config_change() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        let home_dir = git.repo_path.join(".git").join("home");
+        std::fs::create_dir_all(&home_dir)?;
+        let _daemon = spawn_daemon_with_env(&git, {
+            let mut env = HashMap::new();
+            env.insert("HOME".to_string(), home_dir.to_str().unwrap().to_string());
+            env
+        })?;
+        let socket_path = git
+            .repo_path
+            .join(".git")
+            .join("branchless")
+            .join("daemon.sock");
+
+        {
+            let response = query_daemon(&socket_path)?;
+            insta::assert_snapshot!(response, @r###"
+            ok
+            O f777ecc (master) create initial.txt
+            |
+            @ 62fc20d create test1.txt
+            "###);
+        }
+
+        // Changing the global config must invalidate the daemon's cached
+        // output, even though the repository's own config is unchanged.
+        std::fs::write(
+            home_dir.join(".gitconfig"),
+            "[branchless \"smartlog\"]\n\theadGlyph = H\n",
+        )?;
+        {
+            let response = query_daemon(&socket_path)?;
+            insta::assert_snapshot!(response, @r###"
+            ok
+            O f777ecc (master) create initial.txt
+            |
+            H 62fc20d create test1.txt
+            "###);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_daemon_already_running() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+
+        let _daemon = spawn_daemon(&git)?;
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "daemon"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        A daemon is already running for this repository.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+
+        Ok(())
+    }
+}
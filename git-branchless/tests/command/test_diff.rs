@@ -0,0 +1,126 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_diff_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.write_file("test1", "updated contents\n")?;
+    git.run(&["commit", "-am", "update test1.txt"])?;
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "diff"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        diff --git a/test1.txt b/test1.txt
+        index 7432a8f..27e2fc9 100644
+        --- a/test1.txt
+        +++ b/test1.txt
+        @@ -1 +1 @@
+        -test1 contents
+        +updated contents
+        diff --git a/test2.txt b/test2.txt
+        new file mode 100644
+        index 0000000..4e512d2
+        --- /dev/null
+        +++ b/test2.txt
+        @@ -0,0 +1 @@
+        +test2 contents
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "diff", "--stat"])?;
+        insta::assert_snapshot!(stderr, @"");
+        // Compared directly, since inline snapshots strip leading whitespace.
+        assert_eq!(
+            stdout,
+            " test1.txt | 2 +-\n test2.txt | 1 +\n 2 files changed, 2 insertions(+), 1 deletion(-)\n"
+        );
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "diff", "--name-only", "HEAD"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_multiple_heads() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "diff", "draft()"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Revset must have exactly 1 head commit to diff (got 2): draft()
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_word_diff() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.write_file("test1", "let foo = bar(1);\nunchanged\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "create test1.txt"])?;
+    git.write_file("test1", "let foo = qux(1, 2);\nunchanged\n")?;
+    git.run(&["commit", "-am", "update test1.txt"])?;
+
+    {
+        let (stdout, stderr) = git.run(&[
+            "branchless",
+            "--color",
+            "always",
+            "diff",
+            "--word-diff",
+            "HEAD",
+        ])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(console::strip_ansi_codes(&stdout), @r###"
+        diff --git a/test1.txt b/test1.txt
+        index dc193fb..08c5881 100644
+        --- a/test1.txt
+        +++ b/test1.txt
+        @@ -1,2 +1,2 @@
+        -let foo = bar(1);
+        +let foo = qux(1, 2);
+         unchanged
+        "###);
+        // The changed words should be highlighted in reverse video.
+        assert!(stdout.contains("\u{1b}[7m"), "{:?}", stdout);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--color", "always", "diff", "HEAD"])?;
+        assert!(!stdout.contains("\u{1b}[7m"), "{:?}", stdout);
+    }
+
+    Ok(())
+}
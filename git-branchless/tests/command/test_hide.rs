@@ -446,3 +446,84 @@ fn test_hide_recursive() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_hide_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "test"])?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["hide", "--dry-run", "--delete-branches", "test", "test^"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        x 62fc20d (would be hidden) create test1.txt
+        |
+        x 96d1c37 (would be hidden) create test2.txt
+        (This was a dry-run; run without --dry-run to apply changes.)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 (test) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hide_runs_user_hooks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    std::fs::write(
+        git.repo_path.join(".git").join("hide-hook.sh"),
+        "echo \"postHide hook for $1:\"; cat\n",
+    )?;
+    std::fs::write(
+        git.repo_path.join(".git").join("rewrite-hook.sh"),
+        "echo \"postRewrite hook for $1:\"; cat\n",
+    )?;
+    git.run(&[
+        "config",
+        "branchless.hooks.postHide",
+        "sh .git/hide-hook.sh",
+    ])?;
+    git.run(&[
+        "config",
+        "branchless.hooks.postRewrite",
+        "sh .git/rewrite-hook.sh",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["hide", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 96d1c37 create test2.txt
+        To unhide this 1 commit, run: git undo
+        postHide hook for hide:
+        96d1c37a3d4363611c49f7e52186e189a04c531f 0000000000000000000000000000000000000000
+        postRewrite hook for hide:
+        96d1c37a3d4363611c49f7e52186e189a04c531f 0000000000000000000000000000000000000000
+        "###);
+    }
+
+    Ok(())
+}
@@ -325,7 +325,7 @@ fn test_main_branch_not_found_error_message() -> eyre::Result<()> {
 
       ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━ SPANTRACE ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━
 
-       0: git_branchless::commands::smartlog::smartlog with effects=<Output fancy=false> git_run_info=<GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> options=SmartlogOptions { show_hidden_commits: false, event_id: None, revset: Revset("draft()") }
+       0: git_branchless::commands::smartlog::smartlog with effects=<Output fancy=false> git_run_info=<GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> options=SmartlogOptions { show_hidden_commits: false, event_id: None, at: None, revset: Revset("draft()"), all_worktrees: false, collapsed: false, expand: [], format: Text }
           at some/file/path.rs:123
 
     Suggestion:
@@ -567,3 +567,169 @@ fn test_init_core_hooks_path_warning() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[cfg(unix)]
+#[test]
+fn test_init_symlink_hooks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    std::fs::write(
+        hooks_dir.join("post-merge"),
+        "#!/bin/sh
+echo Hello, world
+",
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "init", "--hook-type", "symlink"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/.git/branchless/config
+        Auto-detected your main branch as: master
+        If this is incorrect, run: git config branchless.core.mainBranch <branch>
+        Linking hooks to shared hook script: <repo-path>/.git/branchless/git-branchless-hook
+        Installing hook: post-commit
+        Installing hook: post-merge
+        The post-merge hook has other contents, so it will not be replaced with a symlink.
+        Installing hook: post-rewrite
+        Installing hook: post-checkout
+        Installing hook: pre-auto-gc
+        Installing hook: reference-transaction
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+
+    let shared_hook_script = git
+        .repo_path
+        .join(".git")
+        .join("branchless")
+        .join("git-branchless-hook");
+    assert_eq!(
+        std::fs::read_link(hooks_dir.join("post-commit"))?,
+        shared_hook_script
+    );
+    assert!(!std::fs::symlink_metadata(hooks_dir.join("post-merge"))?
+        .file_type()
+        .is_symlink());
+
+    {
+        let (stdout, stderr) = git.run(&["commit", "--allow-empty", "-m", "test"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [master 4cd1a9b] test
+        "###);
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 2 updates: branch master, ref HEAD
+        branchless: processed commit: 4cd1a9b test
+        "###);
+    }
+
+    {
+        let (_stdout, _stderr) = git.run(&["branchless", "init", "--uninstall"])?;
+        let post_commit_path = hooks_dir.join("post-commit");
+        assert!(!std::fs::symlink_metadata(&post_commit_path)?
+            .file_type()
+            .is_symlink());
+        insta::assert_snapshot!(std::fs::read_to_string(&post_commit_path)?, @r###"
+        #!/bin/sh
+        ## START BRANCHLESS CONFIG
+
+        # This hook has been uninstalled.
+        # Run `git branchless init` to reinstall.
+
+        ## END BRANCHLESS CONFIG
+        "###);
+        assert!(shared_hook_script.exists());
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_init_wizard() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+    git.run(&[
+        "remote",
+        "add",
+        "upstream",
+        "https://example.com/upstream.git",
+    ])?;
+    git.run(&["remote", "add", "origin", "https://example.com/origin.git"])?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "init", "--interactive"],
+            &GitRunOptions {
+                input: Some("\nfoo\n\nmaybe\nn\nmy-ci-status\n".to_string()),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/.git/branchless/config
+        Main branch name [master]: Remote to sync your main branch with (origin, upstream, or "none") [origin]: No remote named: foo
+        Remote to sync your main branch with (origin, upstream, or "none") [origin]: Install aliases for branchless commands, such as `git sl` and `git co`? (Y/n): Please answer yes or no.
+        Install aliases for branchless commands, such as `git sl` and `git co`? (Y/n): Command to query the CI status of commits from your code forge (leave blank to skip): Installing hook: post-commit
+        Installing hook: post-merge
+        Installing hook: post-rewrite
+        Installing hook: post-checkout
+        Installing hook: pre-auto-gc
+        Installing hook: reference-transaction
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["config", "branch.master.remote"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        origin
+        "###);
+        let (stdout, _stderr) = git.run(&["config", "branchless.ci.statusCommand"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        my-ci-status
+        "###);
+        let (stdout, _stderr) = git.run_with_options(
+            &["config", "alias.sl"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "init", "--yes"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Created config file at <repo-path>/.git/branchless/config
+        Auto-detected your main branch as: master
+        If this is incorrect, run: git config branchless.core.mainBranch <branch>
+        Installing hook: post-commit
+        Installing hook: post-merge
+        Installing hook: post-rewrite
+        Installing hook: post-checkout
+        Installing hook: pre-auto-gc
+        Installing hook: reference-transaction
+        Successfully installed git-branchless.
+        To uninstall, run: git branchless init --uninstall
+        "###);
+    }
+
+    Ok(())
+}
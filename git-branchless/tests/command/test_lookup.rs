@@ -0,0 +1,105 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_lookup() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+    git.run(&["commit", "--amend", "-m", "amended test2 again"])?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    git.run(&["restack"])?;
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "lookup", "96d1c37"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        c39ce22124565335cd74d5647ef61911925b07ec
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "lookup", &test1_oid.to_string()])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        ae94dc2a748bc0965c88fcf3edac2e30074ff7e2
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "lookup", "HEAD"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        ae94dc2a748bc0965c88fcf3edac2e30074ff7e2
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "lookup", "abcdef"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Commit not found: abcdef
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "lookup", "--all"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        62fc20d2a290daea0d52bdc2ed2ad4be6491010e ae94dc2a748bc0965c88fcf3edac2e30074ff7e2
+        96d1c37a3d4363611c49f7e52186e189a04c531f c39ce22124565335cd74d5647ef61911925b07ec
+        cb8137adb1d2a166d27eeaf6bfc39a374748852c c39ce22124565335cd74d5647ef61911925b07ec
+        f6b1b74c8a736b1ac450b089c3ceb71a409db37f c39ce22124565335cd74d5647ef61911925b07ec
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_lookup_deleted_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["cherry-pick", &test2_oid.to_string()])?;
+    git.run(&[
+        "move",
+        "--source",
+        &test2_oid.to_string(),
+        "--dest",
+        "master",
+    ])?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "lookup", &test2_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Commit fe65c1fe15584744e649b2c79d4cf9b0d878f92e was deleted, and has no newer versions.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
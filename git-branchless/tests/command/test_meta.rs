@@ -0,0 +1,181 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_meta_follows_rewrites() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branchless", "meta", "set", "review", "PR #123"])?;
+    git.run(&["branchless", "meta", "set", "tests", "passed"])?;
+    git.run(&[
+        "branchless",
+        "meta",
+        "set",
+        "-c",
+        "HEAD^",
+        "tests",
+        "failed",
+    ])?;
+
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    git.run(&["restack"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ ae94dc2 [tests: failed] amended test1
+        |
+        o 51452b5 [review: PR #123, tests: passed] create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "meta", "get", "-c", "children(HEAD)"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        review: PR #123
+        tests: passed
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run(&[
+            "branchless",
+            "meta",
+            "get",
+            "-c",
+            "children(HEAD)",
+            "review",
+        ])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        PR #123
+        "###);
+    }
+
+    git.run(&[
+        "branchless",
+        "meta",
+        "unset",
+        "-c",
+        "children(HEAD)",
+        "review",
+    ])?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &[
+                "branchless",
+                "meta",
+                "get",
+                "-c",
+                "children(HEAD)",
+                "review",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Key not set for commit 51452b55e09488387e59770a9f44d999eba27864: review
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "meta", "get", "-c", "draft()"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Expected revset to expand to exactly 1 commit (got 2): draft()
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_meta_name_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branchless", "meta", "name-stack", "my-feature"])?;
+
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    git.run(&["restack"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ ae94dc2 [topic: my-feature] amended test1
+        |
+        o 51452b5 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "switch", "--topic", "my-feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 51452b55e09488387e59770a9f44d999eba27864
+        O f777ecc (master) create initial.txt
+        |
+        o ae94dc2 [topic: my-feature] amended test1
+        |
+        @ 51452b5 create test2.txt
+        "###);
+    }
+
+    git.run(&["branchless", "meta", "name-stack", "--clear"])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o ae94dc2 amended test1
+        |
+        @ 51452b5 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "meta", "name-stack", "-c", "master", "foo"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Revset does not contain any commits in a stack: master
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
@@ -2292,7 +2292,7 @@ fn test_move_merge_conflict() -> eyre::Result<()> {
         let (stdout, _stderr) = git.run_with_options(
             &["move", "--source", &other_oid.to_string()],
             &GitRunOptions {
-                expected_exit_code: 1,
+                expected_exit_code: 3,
                 ..Default::default()
             },
         )?;
@@ -2376,6 +2376,48 @@ fn test_move_merge_conflict() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_merge_conflict_word_diff() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "branchless.diff.wordDiff", "true"])?;
+
+    let base_oid = git.commit_file_with_contents("conflict", 1, "hello world\n")?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "hello there\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 3, "hello again\n")?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["move", "--source", &other_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 3,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        This operation would cause a merge conflict:
+        - (1 conflicting file) ed92d5c create conflict.txt
+        diff --git a/conflict.txt b/conflict.txt
+        index 3b18e51..c7c7da3 100644
+        --- a/conflict.txt
+        +++ b/conflict.txt
+        @@ -1 +1 @@
+        -hello world
+        +hello there
+        To resolve merge conflicts, retry this operation with the --merge option.
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_move_base() -> eyre::Result<()> {
     let git = make_git()?;
@@ -3837,7 +3879,7 @@ fn test_move_merge_commit_both_parents() -> eyre::Result<()> {
         branchless: processing 3 rewritten commits
         branchless: This operation abandoned 1 commit!
         branchless: Consider running one of the following:
-        branchless:   - git restack: re-apply the abandoned commits/branches
+        branchless:   - git restack 355e173 9ea1b36: re-apply the abandoned commits/branches
         branchless:     (this is most likely what you want to do)
         branchless:   - git smartlog: assess the situation
         branchless:   - git hide [<commit>...]: hide the commits from the smartlog
@@ -4476,6 +4518,9 @@ fn test_move_public_commit() -> eyre::Result<()> {
         You are trying to rewrite 2 public commits, such as: 96d1c37 create test2.txt
         It is generally not advised to rewrite public commits, because your
         collaborators will have difficulty merging your changes.
+        These commits are public:
+          62fc20d create test1.txt (reachable from master)
+          96d1c37 create test2.txt (reachable from master)
         Retry with -f/--force-rewrite to proceed anyways.
         "###);
     }
@@ -4499,3 +4544,567 @@ fn test_move_public_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_move_public_commit_explanation() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["tag", "v1.0"])?;
+    git.commit_file("test2", 2)?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["move", "-x", "master^", "-d", "master^^"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        You are trying to rewrite 2 public commits, such as: 96d1c37 create test2.txt
+        It is generally not advised to rewrite public commits, because your
+        collaborators will have difficulty merging your changes.
+        These commits are public:
+          62fc20d create test1.txt (reachable from master; contained in tag v1.0)
+          96d1c37 create test2.txt (reachable from master)
+        To rewrite only the draft commits, use the revset: 'draft() & descendants(62fc20d)'
+        Retry with -f/--force-rewrite to proceed anyways.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_protected_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "release-1.0"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "feature"])?;
+    git.run(&["config", "branchless.core.protectedBranches", "release-*"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["move", "-x", "release-1.0", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        You are trying to move or delete 1 protected branch: release-1.0
+        These branches match the patterns in branchless.core.protectedBranches.
+        Retry with --force-rewrite-protected-branches to proceed anyways.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["move", "-x", "feature", "-d", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: fe65c1f create test2.txt
+        branchless: processing 1 update: branch feature
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout fe65c1fe15584744e649b2c79d4cf9b0d878f92e
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d (release-1.0) create test1.txt
+        |
+        @ fe65c1f (feature) create test2.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "move",
+            "-x",
+            "release-1.0",
+            "-d",
+            "feature",
+            "--force-rewrite-protected-branches",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        hint: you can omit the --dest flag in this case, as it defaults to HEAD
+        hint: disable this hint by running: git config --global branchless.hint.moveImplicitHeadArgument false
+        Attempting rebase in-memory...
+        [1/1] Committed as: 0770943 create test1.txt
+        branchless: processing 1 update: branch release-1.0
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_output_json() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, stderr) = git.run(&[
+            "move", "--source", "foo", "--dest", "master", "--output", "json",
+        ])?;
+        insta::assert_snapshot!(stderr, @r###"
+        hint: you can omit the --dest flag in this case, as it defaults to HEAD
+        hint: disable this hint by running: git config --global branchless.hint.moveImplicitHeadArgument false
+        Attempting rebase in-memory...
+        [1/1] Committed as: 0770943 create test1.txt
+        branchless: processing 1 update: branch foo
+        branchless: processing 1 rewritten commit
+        branchless: creating working copy snapshot
+        branchless: running command: <git-executable> checkout master
+        Switched to branch 'master'
+        branchless: processing checkout
+        branchless: running command: <git-executable> branchless smartlog
+        :
+        @ fe65c1f (> master) create test2.txt
+        |
+        o 0770943 (foo) create test1.txt
+        In-memory rebase succeeded.
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        {"command":"move","conflicts":[],"exit_code":0,"moved_branches":[{"name":"foo","new_oid":"07709435a8f6d1566e0091896d130c78acd429dd","old_oid":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e"}],"rewritten_commits":[{"new_oid":"07709435a8f6d1566e0091896d130c78acd429dd","old_oid":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e"}],"status":"succeeded"}
+        "###);
+    }
+
+    git.commit_file_with_contents("test1", 3, "conflict\n")?;
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &[
+                "move", "--source", "foo", "--dest", "master", "--output", "json",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 3,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        hint: you can omit the --dest flag in this case, as it defaults to HEAD
+        hint: disable this hint by running: git config --global branchless.hint.moveImplicitHeadArgument false
+        Attempting rebase in-memory...
+        This operation would cause a merge conflict:
+        - (1 conflicting file) 0770943 create test1.txt
+        To resolve merge conflicts, retry this operation with the --merge option.
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        {"command":"move","conflicts":[{"commit_oid":"07709435a8f6d1566e0091896d130c78acd429dd","paths":["test1.txt"]}],"exit_code":3,"moved_branches":[],"rewritten_commits":[],"status":"conflict"}
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_fixup() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.write_file("test1", "fixed test1 contents\n")?;
+    git.run(&["add", "."])?;
+    git.run_with_options(
+        &["commit", "-m", "fixup! create test1.txt"],
+        &GitRunOptions {
+            time: 3,
+            ..Default::default()
+        },
+    )?;
+    git.write_file("test2", "squashed test2 contents\n")?;
+    git.run(&["add", "."])?;
+    git.run_with_options(
+        &[
+            "commit",
+            "-m",
+            "squash! create test2.txt\n\nAlso update test2.",
+        ],
+        &GitRunOptions {
+            time: 4,
+            ..Default::default()
+        },
+    )?;
+    git.run(&["branch", "foo"])?;
+
+    // --on-disk
+    {
+        let git = git.duplicate_repo()?;
+        git.run(&["move", "--fixup", "--on-disk"])?;
+
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 1e6e5ce create test1.txt
+        |
+        @ 594059c (foo) create test2.txt
+        "###);
+    }
+
+    // --in-memory
+    {
+        let (stdout, _stderr) = git.run(&["move", "--fixup", "--in-memory"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/4] Committed as: 62fc20d create test1.txt
+        [2/4] Fixed up 74e78c9 fixup! create test1.txt into: 1e6e5ce create test1.txt
+        [3/4] Committed as: 0127c92 create test2.txt
+        [4/4] Squashed 2924c74 squash! create test2.txt into: 06092e5 create test2.txt
+        branchless: processing 1 update: branch foo
+        branchless: processing 4 rewritten commits
+        branchless: running command: <git-executable> checkout 06092e5c5d47b257302a40c4bee895b325782ae6
+        O f777ecc (master) create initial.txt
+        |
+        o 1e6e5ce create test1.txt
+        |
+        @ 06092e5 (foo) create test2.txt
+        In-memory rebase succeeded.
+        "###);
+
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%b", "HEAD~2..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+        Also update test2.
+
+        create test1.txt
+
+        "###);
+        let (stdout, _stderr) = git.run(&["show", "HEAD~:test1.txt"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        fixed test1 contents
+        "###);
+
+        let (stdout, _stderr) = git.run(&["move", "--fixup"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Nothing to do.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_to_repo() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    // Refer to the other repository relative to the working directory, so
+    // that its path is stable in the output.
+    let target_repo_path = "../cloned";
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    std::fs::create_dir_all(&cloned_repo.repo_path)?;
+    cloned_repo.init_repo()?;
+    cloned_repo.commit_file("test1", 1)?;
+
+    original_repo.detach_head()?;
+    let test2_oid = original_repo.commit_file("test2", 2)?;
+    original_repo.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = original_repo.run(&[
+            "move",
+            "--to-repo",
+            target_repo_path,
+            "-s",
+            &test2_oid.to_string(),
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [1/2] Applied as: f8d9985 create test2.txt
+        [2/2] Applied as: 2cbf184 create test3.txt
+        Applied 2 commits to the repository at ../cloned.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 2cbf184 (> master) create test3.txt
+        "###);
+    }
+
+    // Commits which were already applied are skipped.
+    original_repo.commit_file("test4", 4)?;
+    {
+        let (stdout, _stderr) = original_repo.run(&[
+            "move",
+            "--to-repo",
+            target_repo_path,
+            "-s",
+            &test2_oid.to_string(),
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Skipped commit (already applied as f8d9985): 96d1c37 create test2.txt
+        Skipped commit (already applied as 2cbf184): 70deb1e create test3.txt
+        [1/1] Applied as: 5ed59ba create test4.txt
+        Applied 1 commit to the repository at ../cloned.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = original_repo.run(&["move", "--to-repo", target_repo_path])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Skipped commit (already applied as 5ed59ba): 355e173 create test4.txt
+        Nothing to do.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 5ed59ba (> master) create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_to_repo_after_am_continue() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    let target_repo_path = "../cloned";
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    std::fs::create_dir_all(&cloned_repo.repo_path)?;
+    cloned_repo.init_repo()?;
+    cloned_repo.commit_file("test1", 1)?;
+    cloned_repo.commit_file_with_contents("test3", 3, "conflicting contents\n")?;
+
+    original_repo.detach_head()?;
+    let test2_oid = original_repo.commit_file("test2", 2)?;
+    original_repo.commit_file("test3", 3)?;
+    original_repo.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = original_repo.run_with_options(
+            &[
+                "move",
+                "--to-repo",
+                target_repo_path,
+                "-s",
+                &test2_oid.to_string(),
+            ],
+            &GitRunOptions {
+                expected_exit_code: 128,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"[1/3] Applied as: 7bdae54 create test2.txt");
+    }
+
+    cloned_repo.resolve_file("test3", "resolved contents\n")?;
+    cloned_repo.run(&["am", "--continue"])?;
+
+    // The commits applied by `git am --continue` are skipped, including the
+    // one whose conflict was resolved.
+    {
+        let (stdout, _stderr) = original_repo.run(&[
+            "move",
+            "--to-repo",
+            target_repo_path,
+            "-s",
+            &test2_oid.to_string(),
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Skipped commit (already applied as 7bdae54): 96d1c37 create test2.txt
+        Skipped commit (already applied as 28d273f): 70deb1e create test3.txt
+        Skipped commit (already applied as 06dc1b3): 355e173 create test4.txt
+        Nothing to do.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 06dc1b3 (> master) create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_confirm_large_rewrite() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test4", 4)?;
+    git.run(&["config", "branchless.rewrite.confirmCommitThreshold", "2"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["move", "-b", "foo", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        hint: you can omit the --dest flag in this case, as it defaults to HEAD
+        hint: disable this hint by running: git config --global branchless.hint.moveImplicitHeadArgument false
+        This operation will rewrite 3 commits and move 2 branches:
+          62fc20d create test1.txt
+          96d1c37 create test2.txt
+          70deb1e create test3.txt
+        Branches to move: bar, foo
+        This is more than branchless.rewrite.confirmCommitThreshold (2), and there's no terminal to confirm it on. Pass --yes to proceed anyway.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["move", "-b", "foo", "-d", "master", "--yes"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        hint: you can omit the --dest flag in this case, as it defaults to HEAD
+        hint: disable this hint by running: git config --global branchless.hint.moveImplicitHeadArgument false
+        Attempting rebase in-memory...
+        [1/3] Committed as: 6c398da create test1.txt
+        [2/3] Committed as: d166405 create test2.txt
+        [3/3] Committed as: e2e80c7 create test3.txt
+        branchless: processing 2 updates: branch bar, branch foo
+        branchless: processing 3 rewritten commits
+        branchless: running command: <git-executable> checkout master
+        :
+        @ 8f7aef5 (> master) create test4.txt
+        |
+        o 6c398da (foo) create test1.txt
+        |
+        o d166405 create test2.txt
+        |
+        o e2e80c7 (bar) create test3.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    git.run(&["config", "branchless.rewrite.confirmBranchThreshold", "1"])?;
+    {
+        let (stdout, _stderr) = git.run(&["move", "-x", "foo", "-d", "master^", "--yes"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/3] Committed as: fa82175 create test2.txt
+        [2/3] Committed as: 127dd23 create test3.txt
+        [3/3] Committed as: 62fc20d create test1.txt
+        branchless: processing 2 updates: branch bar, branch foo
+        branchless: processing 3 rewritten commits
+        branchless: running command: <git-executable> checkout master
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d (foo) create test1.txt
+        |
+        @ 8f7aef5 (> master) create test4.txt
+        |
+        o fa82175 create test2.txt
+        |
+        o 127dd23 (bar) create test3.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_roll_back_after_failed_checkout() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "foo"])?;
+    git.write_file("test3", "untracked contents\n")?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["move", "-x", "foo", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: Untracked working tree file 'test3.txt' would be overwritten by merge.
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: d742fb9 create test2.txt
+        branchless: processing 1 update: branch foo
+        branchless: processing 1 update: branch foo
+        The rebase could not be completed, so it was rolled back. Moved back 1 branch: foo
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d create test1.txt
+        |\
+        | @ 96d1c37 (> foo) create test2.txt
+        |
+        O 4838e49 (master) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
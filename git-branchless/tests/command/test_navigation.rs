@@ -200,6 +200,61 @@ fn test_next_ambiguous_interactive() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg(unix)]
+fn test_next_ambiguous_prompts_in_terminal() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    run_in_pty(
+        &git,
+        &["next"],
+        &[
+            PtyAction::WaitUntilContains("> "),
+            PtyAction::Write("test2"),
+            PtyAction::WaitUntilContains("> fe65c1f"),
+            PtyAction::Write(CARRIAGE_RETURN),
+        ],
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |
+        @ fe65c1f create test2.txt
+        "###);
+    }
+
+    {
+        git.run(&["checkout", "master"])?;
+        let (stdout, _stderr) = git.run_with_options(
+            &["next", "--no-interactive"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found multiple possible child commits to go to after traversing 0 children:
+          - 62fc20d create test1.txt (oldest)
+          - fe65c1f create test2.txt (newest)
+        (Pass --oldest (-o), --newest (-n), or --interactive (-i) to select between ambiguous commits)
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_next_on_master() -> eyre::Result<()> {
     let git = make_git()?;
@@ -839,3 +894,427 @@ fn test_navigation_checkout_target_only() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_navigation_checkout_worktree() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["worktree", "add", "--detach", "wt", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "branchless",
+            "switch",
+            "--worktree",
+            "wt",
+            &test1_oid.to_string(),
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        :
+        @ 62fc20d create test1.txt
+        |
+        O 96d1c37 (master) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["-C", "wt", "rev-parse", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--all-worktrees"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (worktree: wt) create test1.txt
+        |
+        @ 96d1c37 (> master) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &[
+                "branchless",
+                "switch",
+                "--worktree",
+                "nonexistent",
+                "master",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        There is no worktree named: nonexistent
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_navigation_switch_topic() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.write_file("test1", "test1 contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "PROJ-12: create test1.txt"])?;
+    git.commit_file("test2", 2)?;
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.write_file("docs/intro", "intro contents\n")?;
+    git.write_file("docs/usage", "usage contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "write docs"])?;
+
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o e6e2063 [topic: PROJ-12] PROJ-12: create test1.txt
+        | |
+        | o d613efe create test2.txt
+        |\
+        | o fffd064 [topic: docs] write docs
+        |
+        @ 98b9119 (> master) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "switch", "--topic", "PROJ-12"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout d613efe8b69d774fda23ec5d1f8939dc80b9a25d
+        O f777ecc create initial.txt
+        |\
+        | o e6e2063 [topic: PROJ-12] PROJ-12: create test1.txt
+        | |
+        | @ d613efe create test2.txt
+        |\
+        | o fffd064 [topic: docs] write docs
+        |
+        O 98b9119 (master) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "switch", "--topic", "docs"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout fffd0648ce4b35b47eaf31ff33a72434a14bb23a
+        O f777ecc create initial.txt
+        |\
+        | o e6e2063 [topic: PROJ-12] PROJ-12: create test1.txt
+        | |
+        | o d613efe create test2.txt
+        |\
+        | @ fffd064 [topic: docs] write docs
+        |
+        O 98b9119 (master) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "switch", "--topic", "nonexistent"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        There is no stack with the topic: nonexistent
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_navigation_switch_revset() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "switch", "draft() & message(test2)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "branchless",
+            "switch",
+            "-c",
+            "foo",
+            "draft() & message(test3)",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 70deb1e28791d8e7dd5a1f0c871a51b91282562f -b foo
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e (> foo) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "switch", "--detach", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        :
+        @ 62fc20d (master) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e (foo) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "switch", "draft()"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Revset must contain exactly 1 commit to check out (got 2): draft()
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "switch", "message(nonexistent)"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Revset did not contain any commits: message(nonexistent)
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "switch", "--detach", "-c", "bar", "master"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: The argument '--detach' cannot be used with '--branch <BRANCH_NAME>'
+
+        USAGE:
+            git-branchless checkout --detach <TARGET>
+
+        For more information try --help
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_navigation_switch_revset_ambiguous() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    run_in_pty(
+        &git,
+        &["branchless", "switch", "draft()"],
+        &[
+            PtyAction::WaitUntilContains("> "),
+            PtyAction::Write("test2"),
+            PtyAction::WaitUntilContains("> 96d1c37"),
+            PtyAction::Write(CARRIAGE_RETURN),
+        ],
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_navigation_with_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "-b", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["prev", "--with-branch", "2"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        branchless: processing 1 update: branch foo
+        branchless: running command: <git-executable> checkout foo
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d (> foo) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["next", "--with-branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+        branchless: processing 1 update: branch foo
+        branchless: running command: <git-executable> checkout foo
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        @ 96d1c37 (> foo) create test2.txt
+        |
+        o 70deb1e create test3.txt
+        "###);
+    }
+
+    // The branch move is recorded in the event log, so it can be undone.
+    {
+        let (stdout, _stderr) = git.run(&["undo", "-y"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Check out from 96d1c37 create test2.txt
+                       to 96d1c37 create test2.txt
+        2. Restore snapshot for 96d1c37 create test2.txt
+                backed up using 97aa866 branchless: automated working copy snapshot
+        3. Move branch foo from 96d1c37 create test2.txt
+                             to 62fc20d create test1.txt
+        4. Check out from 96d1c37 create test2.txt
+                       to 62fc20d create test1.txt
+        5. Restore snapshot for branch foo
+                    pointing to 62fc20d create test1.txt
+                backed up using 268ea2b branchless: automated working copy snapshot
+        branchless: processing 1 update: branch foo
+        branchless: running command: <git-executable> checkout 268ea2b4186f4e12e16f4fcbf8875a8ec16935e8 -B foo
+        branchless: running command: <git-executable> reset --hard HEAD
+        HEAD is now at 268ea2b branchless: automated working copy snapshot
+        branchless: running command: <git-executable> checkout cd8605eef8b78e22427fa3846f1a23f95e88aa7e
+        branchless: running command: <git-executable> reset 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        branchless: running command: <git-executable> update-ref refs/heads/foo 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        branchless: running command: <git-executable> symbolic-ref HEAD refs/heads/foo
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d (> foo) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        Applied 5 inverse events.
+        "###);
+    }
+
+    git.run(&["checkout", "--detach"])?;
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["next", "--with-branch"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"No branch is checked out, so there is no branch to move with --with-branch.");
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_navigation_with_branch_restores_head_on_failure() -> eyre::Result<()> {
+    let git = make_git()?;
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "-b", "foo"])?;
+
+    // Hold the lock on the branch, so that it can't be moved.
+    let lock_path = git.repo_path.join(".git").join("refs/heads/foo.lock");
+    std::fs::write(&lock_path, "")?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["prev", "--with-branch"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        let failure_line = stdout.lines().find(|line| line.starts_with("Failed"));
+        insta::assert_snapshot!(failure_line.unwrap_or_default(), @"Failed to move branch foo:");
+    }
+    std::fs::remove_file(&lock_path)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 96d1c37 (> foo, master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
+
+#[test]
+fn test_prune_remote() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "-b", "foo"])?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.run(&["checkout", "-b", "bar", "master"])?;
+    original_repo.commit_file("test3", 3)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "foo"])?;
+    cloned_repo.run(&["checkout", "bar"])?;
+    cloned_repo.run(&["checkout", "-b", "baz", "master"])?;
+    cloned_repo.commit_file("test4", 4)?;
+    cloned_repo.run(&["checkout", "master"])?;
+
+    // `foo` is deleted on the remote, and `bar` is merged by cherry-picking it,
+    // but not deleted.
+    original_repo.run(&["branch", "-D", "foo"])?;
+    original_repo.run(&["cherry-pick", "bar"])?;
+    cloned_repo.run(&["fetch", "--prune"])?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        |\
+        | o 96d1c37 (foo) create test2.txt
+        |\
+        | o 4838e49 (bar) create test3.txt
+        |\
+        | o bf0d52a (baz) create test4.txt
+        |
+        O cade1d3 (remote origin/master) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["branchless", "prune-remote", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will prune 2 branches:
+        - bar (merged into the main branch)
+        - foo (origin/foo was deleted)
+        and hide 2 commits:
+        - 96d1c37 create test2.txt
+        - 4838e49 create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run_with_options(
+            &["branchless", "prune-remote"],
+            &GitRunOptions {
+                input: Some("y".to_string()),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will prune 2 branches:
+        - bar (merged into the main branch)
+        - foo (origin/foo was deleted)
+        and hide 2 commits:
+        - 96d1c37 create test2.txt
+        - 4838e49 create test3.txt
+        Confirm? [yN] branchless: processing 2 updates: branch bar, branch foo
+        Deleted 2 branches and hid 2 commits. To restore them, run: git undo
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        |\
+        | o bf0d52a (baz) create test4.txt
+        |
+        O cade1d3 (remote origin/master) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["undo", "-y"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Unhide commit 4838e49 create test3.txt
+           
+        2. Unhide commit 96d1c37 create test2.txt
+           
+        3. Create branch foo at 96d1c37 create test2.txt
+           
+        4. Create branch bar at 4838e49 create test3.txt
+           
+        branchless: processing 2 updates: branch bar, branch foo
+        Applied 4 inverse events.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 62fc20d (> master) create test1.txt
+        |\
+        | o 96d1c37 (foo) create test2.txt
+        |\
+        | o 4838e49 (bar) create test3.txt
+        |\
+        | o bf0d52a (baz) create test4.txt
+        |
+        O cade1d3 (remote origin/master) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_remote_abort() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "-b", "foo"])?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "foo"])?;
+
+    original_repo.run(&["branch", "-D", "foo"])?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run_with_options(
+            &["branchless", "prune-remote", "--fetch"],
+            &GitRunOptions {
+                input: Some("n".to_string()),
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch --prune
+        Will prune 1 branch:
+        - foo (origin/foo was deleted)
+        Confirm? [yN] Aborted.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master, remote origin/master) create test1.txt
+        |
+        @ 96d1c37 (> foo) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_remote_ignores_fresh_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "fresh"])?;
+    git.run(&["branch", "behind", "HEAD^"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "prune-remote", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @"No branches to prune.");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use lib::testing::{
+    make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
+
+#[test]
+fn test_push_rewritten_stack() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "-b", "foo"])?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "foo"])?;
+    cloned_repo.run(&["commit", "--amend", "-m", "amended test2"])?;
+    cloned_repo.run(&["checkout", "-b", "bar"])?;
+    cloned_repo.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["branchless", "push", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would push to origin:
+        - bar: 62c4b43 (expecting no branch on the remote)
+        - foo: cb8137a (expecting 96d1c37)
+        "###);
+    }
+    {
+        // A dry run shouldn't record a transaction which `git undo` would show.
+        let repo = cloned_repo.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let num_push_transactions: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM event_transactions WHERE message = 'push'",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        assert_eq!(num_push_transactions, 0);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["branchless", "push"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Pushing to origin:
+        - bar: created
+        - foo: force-updated
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = original_repo.run(&["log", "--oneline", "foo", "bar"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        62c4b43 create test3.txt
+        cb8137a amended test2
+        62fc20d create test1.txt
+        f777ecc create initial.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["branchless", "push"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No branches to push.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_push_does_not_clobber_remote_changes() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "-b", "foo"])?;
+    original_repo.commit_file("test2", 2)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "foo"])?;
+    cloned_repo.run(&["commit", "--amend", "-m", "amended test2"])?;
+
+    // Someone else pushes to `foo` in the meantime.
+    original_repo.commit_file("test3", 3)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run_with_options(
+            &["branchless", "push"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Pushing to origin:
+        - foo: rejected (stale info)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = original_repo.run(&["log", "--oneline", "foo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        70deb1e create test3.txt
+        96d1c37 create test2.txt
+        62fc20d create test1.txt
+        f777ecc create initial.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -1,4 +1,6 @@
-use lib::testing::{make_git, GitRunOptions};
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
 
 #[test]
 fn test_query() -> eyre::Result<()> {
@@ -84,7 +86,146 @@ fn test_query_eval_error() -> eyre::Result<()> {
             },
         )?;
         insta::assert_snapshot!(stderr, @r###"
-        Evaluation error for expression 'foo()': no function with the name 'foo' could be found; these functions are available: all, ancestors, ancestors.nth, author.date, author.email, author.name, branches, children, committer.date, committer.email, committer.name, descendants, difference, draft, exactly, heads, intersection, message, none, not, only, parents, parents.nth, paths.changed, range, roots, stack, union
+        Evaluation error for expression 'foo()': no function with the name 'foo' could be found; these functions are available: all, ancestors, ancestors.nth, author.date, author.email, author.name, branches, children, committer.date, committer.email, committer.name, created_after, descendants, difference, draft, exactly, heads, intersection, message, none, not, obsolete, only, parents, parents.nth, paths.changed, range, remotes, rewritten_by, roots, signed, stack, union, verified
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_query_eval_error_location() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["query", "stack() | draft(foo)"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Evaluation error for expression 'stack() | draft(foo)': invalid number of arguments to draft: expected 0 but got 1
+            stack() | draft(foo)
+                      ^^^^^^^^^^
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["query", "draft() & ancestors.nth(@, foo)"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Evaluation error for expression 'draft() & ancestors.nth(@, foo)': not an integer: invalid digit found in string
+            draft() & ancestors.nth(@, foo)
+                                       ^^^
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["query", "draft() - message(stack())"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Evaluation error for expression 'draft() - message(stack())': expected a text-matching pattern, but got a call to function: stack
+            draft() - message(stack())
+                              ^^^^^^^
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["query", "draft() & exactly(stack(), 2)"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Evaluation error for expression 'draft() & exactly(stack(), 2)': expected 'stack()' to evaluate to 2 elements, but got 1
+            draft() & exactly(stack(), 2)
+                      ^^^^^^^^^^^^^^^^^^^
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_query_ancestors_descendants_depth() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "ancestors(@, 0)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        355e173 create test4.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "ancestors(@, 2)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        355e173 create test4.txt
+        70deb1e create test3.txt
+        96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "ancestors(@, 100)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        355e173 create test4.txt
+        70deb1e create test3.txt
+        96d1c37 create test2.txt
+        62fc20d create test1.txt
+        f777ecc create initial.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "descendants(roots(draft()), 1)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        70deb1e create test3.txt
+        96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["query", "ancestors(@, 1, 2)"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Evaluation error for expression 'ancestors(@, 1, 2)': invalid number of arguments to ancestors: expected 1/2 but got 3
         "###);
         insta::assert_snapshot!(stdout, @"");
     }
@@ -171,6 +312,84 @@ fn test_query_branches() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_query_branches_pattern() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "experiment/foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "experiment/bar"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "feature"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "branches(\"experiment/*\")"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37 create test2.txt
+        62fc20d create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "branches('regex:^f')"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "branches(nonexistent)"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_query_remotes() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["branch", "release/1.0"])?;
+    original_repo.commit_file("test2", 2)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["query", "remotes()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37 create test2.txt
+        62fc20d create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["query", "remotes(\"origin/release/*\")"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        62fc20d create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["query", "branches(\"origin/*\")"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_query_hidden_commits() -> eyre::Result<()> {
     let git = make_git()?;
@@ -193,3 +412,134 @@ fn test_query_hidden_commits() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_query_event_log() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, stderr) = git.run(&["query", "created_after(0)"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37 create test2.txt
+        62fc20d create test1.txt
+        "###);
+    }
+
+    git.run(&[
+        "branchless",
+        "reword",
+        "-m",
+        "reworded test1",
+        &test1_oid.to_string(),
+    ])?;
+
+    {
+        let (stdout, stderr) = git.run(&["query", "rewritten_by(reword)"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        01e347e create test2.txt
+        d93f3da reworded test1
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["query", "rewritten_by(sync)"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["query", "obsolete()"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37 create test2.txt
+        62fc20d create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["query", "created_after(4)"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        01e347e create test2.txt
+        d93f3da reworded test1
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_query_public_remote_branches() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "-b", "feature"])?;
+    original_repo.commit_file("test2", 2)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "origin/feature"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["update-ref", "refs/remotes/fork/wip", "HEAD"])?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["query", "draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        355e173 create test4.txt
+        70deb1e create test3.txt
+        96d1c37 create test2.txt
+        "###);
+    }
+
+    cloned_repo.run(&["config", "branchless.core.publicRemoteBranches", "origin/*"])?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["query", "draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        355e173 create test4.txt
+        70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run_with_options(
+            &["move", "-x", "origin/feature", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        You are trying to rewrite 1 public commit, such as: 96d1c37 create test2.txt
+        It is generally not advised to rewrite public commits, because your
+        collaborators will have difficulty merging your changes.
+        These commits are public:
+          96d1c37 create test2.txt (reachable from origin/feature)
+        To rewrite only the draft commits, use the revset: 'draft() & descendants(96d1c37)'
+        Retry with -f/--force-rewrite to proceed anyways.
+        "###);
+    }
+
+    Ok(())
+}
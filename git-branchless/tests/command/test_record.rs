@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use crate::util::{run_in_pty, PtyAction};
 use lib::testing::{make_git, GitInitOptions, GitRunOptions};
 
@@ -271,3 +273,375 @@ fn test_record_detach() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[cfg(unix)]
+#[test]
+fn test_record_watchman() -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["config", "branchless.core.useWatchman", "true"])?;
+
+    // Fake `watchman` executable which reports the files listed in
+    // `watchman-files.json` as changed.
+    let fake_bin_dir = git.repo_path.join(".git").join("fake-bin");
+    std::fs::create_dir_all(&fake_bin_dir)?;
+    let files_path = git.repo_path.join(".git").join("watchman-files.json");
+    std::fs::write(&files_path, "[]")?;
+    let watchman_path = fake_bin_dir.join("watchman");
+    std::fs::write(
+        &watchman_path,
+        format!(
+            r#"#!/bin/sh
+request=$(cat)
+case "$request" in
+    *watch-project*) printf '{{"watch": "%s"}}' "{repo_path}" ;;
+    *'"query"'*) printf '{{"clock": "c:2", "files": %s}}' "$(cat "{files_path}")" ;;
+    *) printf '{{"clock": "c:1"}}' ;;
+esac
+"#,
+            repo_path = git.repo_path.display(),
+            files_path = files_path.display(),
+        ),
+    )?;
+    std::fs::set_permissions(&watchman_path, std::fs::Permissions::from_mode(0o755))?;
+
+    let options = GitRunOptions {
+        env: {
+            let path = std::env::join_paths(
+                std::iter::once(fake_bin_dir.clone().into_os_string())
+                    .chain(std::env::split_paths(&git.get_path_for_env()).map(|path| path.into())),
+            )?;
+            vec![("PATH".to_string(), path.to_str().unwrap().to_string())]
+                .into_iter()
+                .collect()
+        },
+        ..Default::default()
+    };
+
+    {
+        let (stdout, _stderr) = git.run_with_options(&["record", "-m", "foo"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        There are no changes to tracked files in the working copy to commit.
+        "###);
+    }
+
+    // Watchman doesn't report the change, so it's not seen.
+    git.write_file("test1", "new contents\n")?;
+    {
+        let (stdout, _stderr) = git.run_with_options(&["record", "-m", "foo"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        There are no changes to tracked files in the working copy to commit.
+        "###);
+    }
+
+    std::fs::write(&files_path, r#"["test1.txt"]"#)?;
+    {
+        let (stdout, _stderr) = git.run_with_options(&["record", "-m", "foo"], &options)?;
+        insta::assert_snapshot!(stdout, @r###"
+        [master 30ab92f] foo
+         1 file changed, 1 insertion(+), 1 deletion(-)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_record_commit_msg_hook() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.write_file("test1", "contents1\n")?;
+    std::fs::write(
+        git.repo_path.join("lint.sh"),
+        "grep -q '^good:' \"$1\" || { echo 'subject must start with \"good:\"'; exit 1; }\n",
+    )?;
+    git.run(&["config", "branchless.commit.msgHook", "sh lint.sh"])?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["record", "-m", "bad"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Aborting commit because the commit message hook rejected the message:
+        subject must start with "good:"
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["record", "-m", "good: foo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [master 77bf4d4] good: foo
+         1 file changed, 1 insertion(+), 1 deletion(-)
+        "###);
+    }
+
+    // As with `git reword`, the first edit is rejected, so the editor is
+    // re-opened with the hook's output.
+    git.write_file("test1", "contents2\n")?;
+    let edited_path = git.repo_path.join(".git").join("edited");
+    let reopened_path = git.repo_path.join(".git").join("reopened");
+    std::fs::write(
+        git.repo_path.join(".git").join("editor.sh"),
+        format!(
+            "\
+if [ -e {edited} ]; then
+    cp \"$1\" {reopened}
+    echo 'good: fixed' >\"$1\"
+else
+    touch {edited}
+    echo 'still bad' >\"$1\"
+fi
+",
+            edited = edited_path.display(),
+            reopened = reopened_path.display(),
+        ),
+    )?;
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["record"],
+            &GitRunOptions {
+                env: {
+                    let mut env: HashMap<String, String> = HashMap::new();
+                    env.insert(
+                        "GIT_EDITOR".to_string(),
+                        format!(
+                            "sh {}",
+                            git.repo_path.join(".git").join("editor.sh").display()
+                        ),
+                    );
+                    env
+                },
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        [master 02d1224] good: fixed
+         1 file changed, 1 insertion(+), 1 deletion(-)
+        "###);
+        let reopened = std::fs::read_to_string(&reopened_path)?;
+        insta::assert_snapshot!(reopened, @r###"
+        # The commit message hook rejected the message for the new commit:
+        #   subject must start with "good:"
+        #
+        still bad
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_record_commit_message_template() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "feature/proj-42-login"])?;
+    git.write_file("test1", "contents1\n")?;
+    git.write_file("test2", "contents2\n")?;
+    git.run(&["add", "test2.txt"])?;
+    git.run(&[
+        "config",
+        "branchless.commit.template",
+        "{ticket}: update {files}\n\nOn branch {branch}.",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["record"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [feature/proj-42-login 8384548] PROJ-42: update test2.txt
+         1 file changed, 1 insertion(+)
+         create mode 100644 test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        PROJ-42: update test2.txt
+
+        On branch feature/proj-42-login.
+
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_record_amend_no_edit() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    git.write_file("test1", "new test1 contents\n")?;
+    {
+        let (stdout, _stderr) = git.run(&["record", "--amend", "--no-edit"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [master 1b1171e] create test1.txt
+         Date: Thu Oct 29 12:34:56 2020 -0100
+         1 file changed, 1 insertion(+)
+         create mode 100644 test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "--stat", "--format=%s"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test1.txt
+
+         test1.txt | 1 +
+         1 file changed, 1 insertion(+)
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["record", "--no-edit"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: The following required arguments were not provided:
+            --amend
+
+        USAGE:
+            git-branchless record --amend --no-edit
+
+        For more information try --help
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_record_all_untracked_files() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    git.write_file("test1", "new test1 contents\n")?;
+    git.write_file("new", "new contents\n")?;
+    git.write_file("ignored", "ignored contents\n")?;
+    git.write_file("excluded", "excluded contents\n")?;
+    std::fs::write(git.repo_path.join(".gitignore"), "ignored.txt\n")?;
+    git.run(&["add", ".gitignore"])?;
+    git.run(&["commit", "-m", "add .gitignore"])?;
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["record", "--all", "--exclude", "excluded*", "-m", "foo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [master bed917f] foo
+         2 files changed, 2 insertions(+), 1 deletion(-)
+         create mode 100644 new.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        ?? excluded.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_record_all_untracked_files_on_error() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    git.write_file("new", "new contents\n")?;
+    // Make the commit message hook fail partway through recording, since the
+    // message can't be written to the path it expects.
+    git.run(&["config", "branchless.commit.msgHook", "true"])?;
+    std::fs::create_dir(git.repo_path.join(".git").join("BRANCHLESS_COMMIT_MSG"))?;
+    git.run_with_options(
+        &["record", "--all", "-m", "foo"],
+        &GitRunOptions {
+            expected_exit_code: 101,
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        insta::assert_snapshot!(stdout, @"?? new.txt");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_record_exclude() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.write_file("test1", "new test1 contents\n")?;
+    git.write_file("test2", "new test2 contents\n")?;
+    {
+        let (stdout, _stderr) = git.run(&["record", "--exclude", "test2.txt", "-m", "foo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [master b437fb4] foo
+         1 file changed, 1 insertion(+), 1 deletion(-)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["diff", "--name-only"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        test2.txt
+        "###);
+    }
+
+    git.run(&["add", "test2.txt"])?;
+    {
+        let (stdout, _stderr) = git.run(&["record", "--exclude", "test2.txt", "-m", "bar"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        There are no changes to tracked files in the working copy to commit.
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["record", "--exclude", "[", "-m", "baz"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Invalid pattern for --exclude: Pattern syntax error near position 0: invalid range pattern
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
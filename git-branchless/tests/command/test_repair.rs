@@ -1,6 +1,7 @@
 use std::ffi::OsStr;
 
-use lib::{git::BranchType, testing::make_git};
+use lib::git::BranchType;
+use lib::testing::{make_git, GitRunOptions};
 
 #[test]
 fn test_repair_broken_commit() -> eyre::Result<()> {
@@ -22,8 +23,6 @@ fn test_repair_broken_commit() -> eyre::Result<()> {
     {
         let (stdout, _stderr) = git.run(&["smartlog"])?;
         insta::assert_snapshot!(stdout, @r###"
-        o 70deb1e <garbage collected>
-
         O f777ecc (master) create initial.txt
         |
         o 62fc20d create test1.txt
@@ -32,6 +31,14 @@ fn test_repair_broken_commit() -> eyre::Result<()> {
         "###);
     }
 
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "repair"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found 1 broken commit: 70deb1e28791d8e7dd5a1f0c871a51b91282562f
+        (This was a dry-run; run with --no-dry-run to apply changes.)
+        "###);
+    }
+
     {
         let (stdout, _stderr) = git.run(&["branchless", "repair", "--no-dry-run"])?;
         insta::assert_snapshot!(stdout, @"Found and repaired 1 broken commit: 70deb1e28791d8e7dd5a1f0c871a51b91282562f
@@ -82,6 +89,7 @@ fn test_repair_broken_branch() -> eyre::Result<()> {
 
         let (stdout, _stderr) = git.run(&["smartlog", "--event-id=-1"])?;
         insta::assert_snapshot!(stdout, @r###"
+        Showing the smartlog as it was after event 7 (transaction 5: reference-transaction).
         :
         @ 96d1c37 (> master) create test2.txt
         "###);
@@ -89,3 +97,164 @@ fn test_repair_broken_branch() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_repair_broken_dag() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["smartlog"])?;
+
+    let repo = git.get_repo()?;
+    std::fs::write(repo.get_dag_dir().join("multimeta"), "garbage")?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["smartlog"],
+            &GitRunOptions {
+                expected_exit_code: 4,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: The commit graph could not be loaded.
+        Run `git branchless repair --no-dry-run` to try to fix this.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "doctor"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found broken commit graph, which could not be loaded.
+        (This was a dry-run; run with --no-dry-run to apply changes.)
+        "###);
+    }
+
+    // The dry-run shouldn't have repaired the commit graph.
+    git.run_with_options(
+        &["smartlog"],
+        &GitRunOptions {
+            expected_exit_code: 4,
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "repair", "--no-dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found and repaired broken commit graph, which could not be loaded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "repair"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        (This was a dry-run; run with --no-dry-run to apply changes.)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_corrupt_database() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    let repo = git.get_repo()?;
+    std::fs::write(
+        repo.get_path().join("branchless").join("db.sqlite3"),
+        "garbage",
+    )?;
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["smartlog"],
+            &GitRunOptions {
+                expected_exit_code: 4,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: The git-branchless database at "<repo-path>/.git/branchless/db.sqlite3" is corrupt.
+        Move it aside to start over with an empty event log. This discards the history used by `git undo`.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_check_schema() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "doctor", "--check-schema"])?;
+        insta::assert_snapshot!(stdout, @"The database schema is up to date (version 2).");
+    }
+
+    let repo = git.get_repo()?;
+    {
+        let conn = repo.get_db_conn_without_migrations()?;
+        conn.execute("DELETE FROM schema_migrations", rusqlite::params![])?;
+    }
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "doctor", "--check-schema"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        The database schema is at version 0, and 2 migrations will be applied the next time it's opened:
+        1. Add `provenance` column to `event_transactions`
+        2. Add `verification_key` column to `signature_status`
+        "###);
+    }
+
+    // Opening the database applies the migrations again.
+    git.run(&["smartlog"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "doctor", "--check-schema"])?;
+        insta::assert_snapshot!(stdout, @"The database schema is up to date (version 2).");
+    }
+
+    {
+        let conn = repo.get_db_conn_without_migrations()?;
+        conn.execute(
+            "INSERT INTO schema_migrations (version, description, applied_at) VALUES (1000, 'from the future', 0.0)",
+            rusqlite::params![],
+        )?;
+    }
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "doctor", "--check-schema"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"The database schema is at version 1000, but this version of git-branchless only supports up to version 2. It was probably written by a newer version of git-branchless, which should be used instead.");
+    }
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["smartlog"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"branchless: The git-branchless database has schema version 1000, but this version of git-branchless only supports up to version 2. It was probably written by a newer version of git-branchless, which should be used instead.");
+    }
+    Ok(())
+}
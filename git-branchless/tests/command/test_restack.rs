@@ -245,7 +245,7 @@ fn test_restack_aborts_during_rebase_conflict() -> eyre::Result<()> {
         let (stdout, _stderr) = git.run_with_options(
             &["restack", "-f"],
             &GitRunOptions {
-                expected_exit_code: 1,
+                expected_exit_code: 3,
                 ..Default::default()
             },
         )?;
@@ -586,3 +586,150 @@ fn test_restack_non_observed_branch_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_restack_runs_user_hooks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+
+    std::fs::write(
+        git.repo_path.join(".git").join("restack-hook.sh"),
+        "echo \"postRestack hook for $1:\"; cat; exit 1\n",
+    )?;
+    git.run(&[
+        "config",
+        "branchless.hooks.postRestack",
+        "sh .git/restack-hook.sh",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["restack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 8cd7de6 create test2.txt
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        Finished restacking commits.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |
+        @ 024c35c amend test1.txt
+        |
+        o 8cd7de6 create test2.txt
+        postRestack hook for restack:
+        96d1c37a3d4363611c49f7e52186e189a04c531f 8cd7de680cafaba911d09f430d2bafb1169d6e65
+        branchless: hook `sh .git/restack-hook.sh` exited with status Some(1)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restack_current_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+    git.run(&["checkout", &test3_oid.to_string()])?;
+    git.run(&["commit", "--amend", "-m", "amend test3.txt"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["restack", "stack()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: d106e21 create test4.txt
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        Finished restacking commits.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |\
+        | o 024c35c amend test1.txt
+        |\
+        | @ 51ea4f6 amend test3.txt
+        | |
+        | o d106e21 create test4.txt
+        |
+        x 62fc20d (rewritten as 024c35ce) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        hint: there is 1 abandoned commit in your commit graph
+        hint: to fix this, run: git restack
+        hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
+        "###);
+    }
+
+    git.run(&["config", "branchless.restack.currentStackOnly", "true"])?;
+    {
+        let (stdout, _stderr) = git.run(&["restack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No abandoned commits to restack.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |\
+        | o 024c35c amend test1.txt
+        |\
+        | @ 51ea4f6 amend test3.txt
+        | |
+        | o d106e21 create test4.txt
+        |
+        x 62fc20d (rewritten as 024c35ce) create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        hint: there is 1 abandoned commit in your commit graph
+        hint: to fix this, run: git restack
+        hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
+        "###);
+    }
+
+    // Only the stack of the checked-out commit is restacked.
+    git.run(&["checkout", "024c35c"])?;
+    {
+        let (stdout, _stderr) = git.run(&["restack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 8cd7de6 create test2.txt
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        Finished restacking commits.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |\
+        | @ 024c35c amend test1.txt
+        | |
+        | o 8cd7de6 create test2.txt
+        |
+        o 51ea4f6 amend test3.txt
+        |
+        o d106e21 create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use lib::testing::{make_git, GitRunOptions, GitWrapper};
+
+/// Create three stacks, where the second one conflicts with the main branch.
+fn make_conflicting_stacks(git: &GitWrapper) -> eyre::Result<()> {
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file_with_contents("conflict", 2, "stack contents\n")?;
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+
+    git.run(&["checkout", "master"])?;
+    git.commit_file_with_contents("conflict", 4, "master contents\n")?;
+
+    Ok(())
+}
+
+#[test]
+fn test_continue_sync_after_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    make_conflicting_stacks(&git)?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["sync", "--merge"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 4c09ac3 create test1.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        Attempting rebase in-memory...
+        There was a merge conflict, which currently can't be resolved when rebasing in-memory.
+        The conflicting commit was: f75f09b create conflict.txt
+        Trying again on-disk...
+        branchless: running command: <git-executable> diff --quiet
+        Calling Git for on-disk rebase...
+        branchless: running command: <git-executable> rebase --continue
+        Auto-merging conflict.txt
+        CONFLICT (add/add): Merge conflict in conflict.txt
+        "###);
+    }
+
+    git.resolve_file("conflict", "resolved contents\n")?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "continue"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [detached HEAD 595c085] create conflict.txt
+         1 file changed, 1 insertion(+), 1 deletion(-)
+        branchless: running command: <git-executable> branchless sync --merge --yes 98b9119d16974f372e76cb64a3b77c528fc0b18b
+        Attempting rebase in-memory...
+        [1/1] Committed as: 1219dca create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        In-memory rebase succeeded.
+        Synced 98b9119 create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 88a65f0 (> master) create conflict.txt
+        |\
+        | o 4c09ac3 create test1.txt
+        |\
+        | o 595c085 create conflict.txt
+        |
+        o 1219dca create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "continue"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No operation to continue.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_abort_sync_after_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    make_conflicting_stacks(&git)?;
+
+    git.run_with_options(
+        &["sync", "--merge"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "abort"],
+            &GitRunOptions {
+                input: Some("y".to_string()),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> rebase --abort
+        Undoing changes made by `git sync`.
+        Will apply these actions:
+        1. Check out from 88a65f0 create conflict.txt
+                       to 88a65f0 create conflict.txt
+        2. Restore snapshot for 88a65f0 create conflict.txt
+                backed up using 1b0ce54 branchless: automated working copy snapshot
+        3. Rewrite commit 4c09ac3 create test1.txt
+                      as 62fc20d create test1.txt
+        Confirm? [yN] branchless: running command: <git-executable> checkout 1b0ce54ba7ebf29529d555b54d688de33c419d7b
+        branchless: running command: <git-executable> reset --hard HEAD
+        HEAD is now at 1b0ce54 branchless: automated working copy snapshot
+        branchless: running command: <git-executable> checkout fc5760d2814b09a649403bddd69beb70f552052c
+        branchless: running command: <git-executable> reset 88a65f07f25de889c39472994d25fe26b4307523
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |\
+        | o f75f09b create conflict.txt
+        |\
+        | o 98b9119 create test3.txt
+        |
+        @ 88a65f0 (master) create conflict.txt
+        Applied 3 inverse events.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |\
+        | o f75f09b create conflict.txt
+        |\
+        | o 98b9119 create test3.txt
+        |
+        @ 88a65f0 (master) create conflict.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_abort_after_rebase_aborted_directly() -> eyre::Result<()> {
+    let git = make_git()?;
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    make_conflicting_stacks(&git)?;
+
+    git.run_with_options(
+        &["sync", "--merge"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    git.run(&["rebase", "--abort"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "abort"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"No operation to abort.");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o f75f09b create conflict.txt
+        |\
+        | o 98b9119 create test3.txt
+        |
+        @ 88a65f0 (> master) create conflict.txt
+        |
+        o 4c09ac3 create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_abort_no_operation() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let (stdout, _stderr) = git.run_with_options(
+        &["branchless", "abort"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    insta::assert_snapshot!(stdout, @"No operation to abort.
+");
+
+    Ok(())
+}
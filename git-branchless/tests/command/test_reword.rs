@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use lib::testing::{make_git, GitRunOptions};
 
 #[test]
@@ -328,9 +330,958 @@ fn test_reword_exit_early_public_commit() -> eyre::Result<()> {
         You are trying to rewrite 1 public commit, such as: 62fc20d create test1.txt
         It is generally not advised to rewrite public commits, because your
         collaborators will have difficulty merging your changes.
+        These commits are public:
+          62fc20d create test1.txt (reachable from master)
         Retry with -f/--force-rewrite to proceed anyways.
         "###);
     }
 
     Ok(())
 }
+
+#[test]
+fn test_reword_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "test1"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["reword", "--dry-run", "HEAD^", "--message", "bar"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [1/2] Committed as: 8d4a670 bar
+        [2/2] Committed as: 8f7f70e create test2.txt
+        O f777ecc (master) create initial.txt
+        |
+        o 8d4a670 (rewritten from 62fc20d) (test1) bar
+        |
+        @ 8f7f70e (rewritten from 96d1c37) create test2.txt
+        (This was a dry-run; run without --dry-run to apply changes.)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d (test1) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_message_file() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    std::fs::write(git.repo_path.join("message"), "foo\n\nbar\n")?;
+    git.run(&["reword", "HEAD", "-F", "message"])?;
+
+    {
+        let input = format!(
+            "\
+++ reword {}
+first from stdin
+
+++ skip {}
+# This section is ignored.
+ignored
+",
+            test1_oid, test2_oid
+        );
+        git.run_with_options(
+            &[
+                "reword",
+                &test1_oid.to_string(),
+                &test2_oid.to_string(),
+                "--message-file",
+                "-",
+            ],
+            &GitRunOptions {
+                input: Some(input),
+                ..Default::default()
+            },
+        )?;
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%b", "master..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        foo
+        bar
+
+        create test2.txt
+
+        first from stdin
+
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_commit_msg_hook() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    std::fs::write(
+        git.repo_path.join("lint.sh"),
+        "grep -q '^good:' \"$1\" || { echo 'subject must start with \"good:\"'; exit 1; }\n",
+    )?;
+    git.run(&["config", "branchless.commit.msgHook", "sh lint.sh"])?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["reword", "-m", "bad"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Aborting reword because the commit message hook rejected 1 message:
+        62fc20d:
+        subject must start with "good:"
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    // The first edit is rejected, so the editor is re-opened with the hook's
+    // output.
+    std::fs::write(
+        git.repo_path.join("editor.sh"),
+        "\
+if [ -e edited ]; then
+    cp \"$1\" reopened
+    echo 'good: fixed' >\"$1\"
+else
+    touch edited
+    echo 'still bad' >\"$1\"
+fi
+",
+    )?;
+    {
+        let (_stdout, _stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env: {
+                    let mut env: HashMap<String, String> = HashMap::new();
+                    env.insert(
+                        "GIT_EDITOR".to_string(),
+                        format!("sh {}", git.repo_path.join("editor.sh").display()),
+                    );
+                    env
+                },
+                ..Default::default()
+            },
+        )?;
+        let reopened = std::fs::read_to_string(git.repo_path.join("reopened"))?;
+        insta::assert_snapshot!(reopened, @r###"
+        # The commit message hook rejected the message for 62fc20d:
+        #   subject must start with "good:"
+        #
+        still bad
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%s"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        good: fixed
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_resume() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["reword", "HEAD^", "HEAD", "-F", "-"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                input: Some(format!("++ reword {}\nfirst\n", test1_oid)),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Aborting reword due to mismatched inputs.
+        This 1 commit was specified on the command line, but not found in the edited message:
+        96d1c37
+        Your edited message has been saved to .git/REWORD_EDITMSG for review and/or manual recovery.
+        To fix it and try again, run: git branchless reword --resume
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    std::fs::write(
+        git.repo_path.join("editor.sh"),
+        format!("printf '++ reword {}\\nsecond\\n' >>\"$1\"\n", test2_oid),
+    )?;
+    let env = {
+        let mut env: HashMap<String, String> = HashMap::new();
+        env.insert(
+            "GIT_EDITOR".to_string(),
+            format!("sh {}", git.repo_path.join("editor.sh").display()),
+        );
+        env
+    };
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["reword", "--resume"],
+            &GitRunOptions {
+                env: env.clone(),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 576d01d first
+        [2/2] Committed as: 8a17c10 second
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout 8a17c100e8191d23c02160fdd3b825431f1401c5
+        In-memory rebase succeeded.
+        Reworded commit 62fc20d as 576d01d first
+        Reworded commit 96d1c37 as 8a17c10 second
+        Reworded 2 commits. If this was unintentional, run: git undo
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["reword", "--resume"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                env,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No reword to resume.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_non_utf8_messages() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["config", "i18n.commitEncoding", "ISO-8859-1"])?;
+    git.write_file("test1", "test1 contents\n")?;
+    git.run(&["add", "."])?;
+    std::fs::write(git.repo_path.join("message"), b"caf\xe9 latin-1\n")?;
+    git.run(&["commit", "-q", "-F", "message"])?;
+    let (test1_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    // The Latin-1 message isn't edited, so its commit is left untouched.
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["reword", "HEAD^", "HEAD", "-F", "-"],
+            &GitRunOptions {
+                input: Some(format!(
+                    "++ reword {}\ncafé latin-1\n\n++ reword {}\nnaïve\n",
+                    test1_oid.trim(),
+                    test2_oid
+                )),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 233bc38 naïve
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 233bc38086c3550eda5e22147d338123241f0efe
+        In-memory rebase succeeded.
+        Reworded commit 17a5afd as 233bc38 naïve
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["reword", "HEAD^", "-m", "café edited"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: f7d3aca café edited
+        [2/2] Committed as: a72d310 naïve
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout a72d310ea95fe4e9dbf948dc11e88eea7ab8895e
+        In-memory rebase succeeded.
+        Reworded commit 44d07f3 as f7d3aca café edited
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["log", "--encoding=UTF-8", "--format=%e %s", "master..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        ISO-8859-1 naïve
+        ISO-8859-1 café edited
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_reword_runs_commit_hooks() -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let hook_path = git.repo_path.join(".git").join("hooks").join("commit-msg");
+    std::fs::write(
+        &hook_path,
+        "#!/bin/sh\n\
+        grep -q WIP \"$1\" && { echo 'WIP commits are not allowed'; exit 1; }\n\
+        printf '\\nReviewed-by: hook\\n' >>\"$1\"\n",
+    )?;
+    std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))?;
+    git.run(&["config", "branchless.hooks.runCommitHooks", "true"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["reword", "-m", "WIP"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        WIP commits are not allowed
+        Aborting reword because the commit-msg hook failed for commit 62fc20d.
+        "###);
+    }
+
+    git.run(&["reword", "-m", "foo"])?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        foo
+
+        Reviewed-by: hook
+
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_reflow() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.run(&[
+        "reword",
+        "HEAD",
+        "-m",
+        "create test2.txt",
+        "-m",
+        "This body was written on a single line which is longer than the configured column.",
+        "-m",
+        "Signed-off-by: Jane Doe <jane@example.com>",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "reword",
+            "--reflow",
+            "--reflow-column",
+            "30",
+            "HEAD~",
+            "HEAD",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/1] Committed as: 33c68fa create test2.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 33c68fa35a52f3b295817bc25719261afc1ca5ed
+        In-memory rebase succeeded.
+        Reworded commit d824b25 as 33c68fa create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%B", "-1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+
+        This body was written on a
+        single line which is longer
+        than the configured column.
+
+        Signed-off-by: Jane Doe <jane@example.com>
+
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["reword", "--reflow", "--reflow-column", "30", "HEAD"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"Aborting. All commit messages are already wrapped; nothing to do.
+");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_lint() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    git.run(&["config", "branchless.reword.lint", "true"])?;
+    git.run(&["config", "branchless.reword.maxSubjectLength", "20"])?;
+    std::fs::write(
+        git.repo_path.join("spell.sh"),
+        "grep -o -w teh \"$1\" | sed 's/^/misspelled: /'\n",
+    )?;
+    git.run(&["config", "branchless.reword.lintCommand", "sh spell.sh"])?;
+
+    // The first edit has problems, so the user chooses to edit it again, and
+    // the editor is re-opened with the warnings.
+    std::fs::write(
+        git.repo_path.join("editor.sh"),
+        "\
+if [ -e edited ]; then
+    cp \"$1\" reopened
+    printf 'fix the subject\\n\\nExplain the fix.\\n' >\"$1\"
+else
+    touch edited
+    printf 'fix teh subject of this commit\\n' >\"$1\"
+fi
+",
+    )?;
+    let env: HashMap<String, String> = [(
+        "GIT_EDITOR".to_string(),
+        format!("sh {}", git.repo_path.join("editor.sh").display()),
+    )]
+    .iter()
+    .cloned()
+    .collect();
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env: env.clone(),
+                input: Some("e\n".to_string()),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Warnings for 62fc20d:
+          The subject line is 30 characters long, which is more than 20.
+          The message has no body.
+          misspelled: teh
+        branchless: creating working copy snapshot
+        Previous HEAD position was 62fc20d create test1.txt
+        branchless: processing 1 update: ref HEAD
+        HEAD is now at 2d498f6 fix the subject
+        branchless: processing checkout
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        Edit the message again, proceed anyway, or abort? [e/p/a]: Attempting rebase in-memory...
+        [1/1] Committed as: 2d498f6 fix the subject
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 2d498f6c2bbb8c4185f2f1072188e6eca6134660
+        In-memory rebase succeeded.
+        Reworded commit 62fc20d as 2d498f6 fix the subject
+        "###);
+        let reopened = std::fs::read_to_string(git.repo_path.join("reopened"))?;
+        insta::assert_snapshot!(reopened, @r###"
+        # Warnings for the message for 62fc20d:
+        #   The subject line is 30 characters long, which is more than 20.
+        #   The message has no body.
+        #   misspelled: teh
+        #
+        fix teh subject of this commit
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        fix the subject
+
+        Explain the fix.
+
+        "###);
+    }
+
+    // Aborting leaves the commit as-is, and saves the message so that the
+    // reword can be resumed.
+    std::fs::remove_file(git.repo_path.join("edited"))?;
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env,
+                input: Some("a\n".to_string()),
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Warnings for 2d498f6:
+          The subject line is 30 characters long, which is more than 20.
+          The message has no body.
+          misspelled: teh
+        Aborting reword.
+        Your edited message has been saved to .git/REWORD_EDITMSG for review and/or manual recovery.
+        To fix it and try again, run: git branchless reword --resume
+        "###);
+        insta::assert_snapshot!(stdout, @"Edit the message again, proceed anyway, or abort? [e/p/a]: ");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%s"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        fix the subject
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_editor_resolution() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    std::fs::write(
+        git.repo_path.join("editor.sh"),
+        "sed -i.bak 's/^create/edited by editor:/' \"$1\"\n",
+    )?;
+    std::fs::write(
+        git.repo_path.join("sequence-editor.sh"),
+        "sed -i.bak 's/^create/edited by sequence editor:/' \"$1\"\n",
+    )?;
+    let env: HashMap<String, String> = [
+        (
+            "GIT_EDITOR".to_string(),
+            format!("sh {}", git.repo_path.join("editor.sh").display()),
+        ),
+        (
+            "GIT_SEQUENCE_EDITOR".to_string(),
+            format!("sh {}", git.repo_path.join("sequence-editor.sh").display()),
+        ),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+
+    // Rewording a single commit uses the regular editor, and rewording
+    // several commits uses the sequence editor.
+    git.run_with_options(
+        &["reword", "HEAD"],
+        &GitRunOptions {
+            env: env.clone(),
+            ..Default::default()
+        },
+    )?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%s"])?;
+        insta::assert_snapshot!(stdout, @"edited by editor: test2.txt");
+    }
+
+    git.run_with_options(
+        &["reword", "HEAD^", "HEAD"],
+        &GitRunOptions {
+            env,
+            ..Default::default()
+        },
+    )?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "-2", "--format=%s"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        edited by editor: test2.txt
+        edited by sequence editor: test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_editor_failure() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env: [("GIT_EDITOR".to_string(), "false".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"branchless: There was a problem with the editor 'false'.");
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    // A graphical editor which exits without saving, such as because it
+    // doesn't wait for the file to be closed, aborts the reword.
+    {
+        let editor_path = git.repo_path.join("code");
+        std::fs::write(&editor_path, "#!/bin/sh\nexit 0\n")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&editor_path, std::fs::Permissions::from_mode(0o755))?;
+        }
+        let (stdout, stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env: [(
+                    "GIT_EDITOR".to_string(),
+                    editor_path.to_str().unwrap().to_string(),
+                )]
+                .iter()
+                .cloned()
+                .collect(),
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"branchless: Aborting because the editor '<repo-path>/code --wait' exited without saving the message. If it's a graphical editor, it may need an option to wait until the file is closed, such as `code --wait`.");
+        insta::assert_snapshot!(stdout, @"");
+        std::fs::remove_file(&editor_path)?;
+    }
+
+    // Other editors which exit without saving, such as `true`, leave the
+    // message as-is, as in Git.
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env: [("GIT_EDITOR".to_string(), "true".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @"Aborting. The message was not edited; nothing to do.");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%s"])?;
+        insta::assert_snapshot!(stdout, @"create test1.txt");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_terminal_editor() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    // With no editor configured, the message is read from the terminal.
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env: [("GIT_EDITOR".to_string(), "".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                input: Some("edited in terminal\n\nwith a body\n.\n".to_string()),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        No editor is configured, so the message will be edited here. To use an editor instead, set `core.editor` or `$GIT_EDITOR`.
+        The current message is:
+            create test1.txt
+
+            # Rewording: Please enter the commit message to apply to this 1 commit. Lines
+            # starting with '#' will be ignored, and an empty message aborts rewording.
+        Enter the new message, followed by a line containing only `.`. To keep the current message, enter only `.`.
+        Attempting rebase in-memory...
+        [1/1] Committed as: 9c17ded edited in terminal
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 9c17ded57511c4d628042ae5a23e089149af7479
+        In-memory rebase succeeded.
+        Reworded commit 62fc20d as 9c17ded edited in terminal
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        edited in terminal
+
+        with a body
+
+        "###);
+    }
+
+    // If the editor can't be found, then the message is also read from the
+    // terminal.
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env: [("GIT_EDITOR".to_string(), "nonexistent-editor".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                input: Some("edited again".to_string()),
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        The editor 'nonexistent-editor' could not be found, so the message will be edited here.
+        The current message is:
+            edited in terminal
+
+            with a body
+
+            # Rewording: Please enter the commit message to apply to this 1 commit. Lines
+            # starting with '#' will be ignored, and an empty message aborts rewording.
+        Enter the new message, followed by a line containing only `.`. To keep the current message, enter only `.`.
+        Attempting rebase in-memory...
+        [1/1] Committed as: 7b5a557 edited again
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout 7b5a55728c0ef24851c1774976b8d6069c94beb9
+        In-memory rebase succeeded.
+        Reworded commit 9c17ded as 7b5a557 edited again
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%s"])?;
+        insta::assert_snapshot!(stdout, @"edited again");
+    }
+
+    // Closing the input without entering anything aborts the reword.
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["reword"],
+            &GitRunOptions {
+                env: [("GIT_EDITOR".to_string(), "".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                input: Some("".to_string()),
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"branchless: Aborting because no message was entered.");
+        insta::assert_snapshot!(stdout, @r###"
+        No editor is configured, so the message will be edited here. To use an editor instead, set `core.editor` or `$GIT_EDITOR`.
+        The current message is:
+            edited again
+
+            # Rewording: Please enter the commit message to apply to this 1 commit. Lines
+            # starting with '#' will be ignored, and an empty message aborts rewording.
+        Enter the new message, followed by a line containing only `.`. To keep the current message, enter only `.`.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_confirm_large_rewrite() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["config", "branchless.rewrite.confirmCommitThreshold", "2"])?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["reword", "draft()"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        This operation will rewrite 3 commits and move 0 branches:
+          62fc20d create test1.txt
+          96d1c37 create test2.txt
+          70deb1e create test3.txt
+        This is more than branchless.rewrite.confirmCommitThreshold (2), and there's no terminal to confirm it on. Pass --yes to proceed anyway.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_stacks_independently() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&["merge", &test3_oid.to_string()])?;
+    git.commit_file("test5", 5)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    let test6_oid = git.commit_file("test6", 6)?;
+
+    // The stack containing the merge commit can't be reworded in-memory, but
+    // the unrelated stack is still reworded.
+    let (stdout, _stderr) = git.run_with_options(
+        &[
+            "reword",
+            &test4_oid.to_string(),
+            &test6_oid.to_string(),
+            "--message",
+            "foo",
+        ],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    insta::assert_snapshot!(stdout, @r###"
+    Attempting rebase in-memory...
+    Merge commits currently can't be rebased in-memory.
+    The merge commit was: 1c33816 Merge commit '70deb1e28791d8e7dd5a1f0c871a51b91282562f' into HEAD
+    Aborting since an in-memory rebase was requested.
+    Attempting rebase in-memory...
+    [1/1] Committed as: 72c3420 foo
+    branchless: processing 1 rewritten commit
+    branchless: running command: <git-executable> checkout 72c342033b8c65a361dd4d7c36ae7c60354865d7
+    In-memory rebase succeeded.
+    Reworded commit d25afe6 as 72c3420 foo
+    Reworded 1 of 2 stacks:
+      Failed to reword 1 commit in stack at bf0d52a create test4.txt (exit code 1)
+      Reworded 1 commit in stack at d25afe6 create test6.txt
+    "###);
+
+    let (stdout, _stderr) = git.run(&["smartlog"])?;
+    insta::assert_snapshot!(stdout, @r###"
+    :
+    O 62fc20d (master) create test1.txt
+    |\
+    | o 96d1c37 create test2.txt
+    | |
+    | o 70deb1e create test3.txt
+    | |
+    | o 1c33816 Merge commit '70deb1e28791d8e7dd5a1f0c871a51b91282562f' into HEAD
+    | |
+    | o 1d929cf create test5.txt
+    |\
+    | o bf0d52a create test4.txt
+    | |
+    | o 1c33816 Merge commit '70deb1e28791d8e7dd5a1f0c871a51b91282562f' into HEAD
+    | |
+    | o 1d929cf create test5.txt
+    |
+    @ 72c3420 foo
+    "###);
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_run_script() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+
+    std::fs::write(
+        git.repo_path.join("script.lua"),
+        r#"
+print("args:", table.concat(branchless.args, ","))
+for i, stack in ipairs(branchless.stacks()) do
+    for _, oid in ipairs(stack) do
+        local commit = branchless.commit(oid)
+        print(i, commit.summary, "[" .. table.concat(commit.branches, ",") .. "]")
+    end
+end
+for _, oid in ipairs(branchless.query("branches() & draft()")) do
+    for _, branch in ipairs(branchless.commit(oid).branches) do
+        branchless.git("branch", "-m", branch, "ticket-" .. branch)
+    end
+end
+branchless.move('message("create test3.txt")', "ticket-foo")
+"#,
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "run", "script.lua", "a", "b"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        args:	a,b
+        1	create test1.txt	[foo]
+        1	create test2.txt	[]
+        2	create test3.txt	[]
+        branchless: running command: <git-executable> branch -m foo ticket-foo
+        branchless: running command: <git-executable> branchless move -x message("create test3.txt") -d ticket-foo
+        Attempting rebase in-memory...
+        [1/1] Committed as: 4838e49 create test3.txt
+        branchless: processing 1 rewritten commit
+        branchless: running command: <git-executable> checkout master
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d (ticket-foo) create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        |
+        o 4838e49 create test3.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d (ticket-foo) create test1.txt
+        |\
+        | o 96d1c37 create test2.txt
+        |
+        o 4838e49 create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_run_script_error() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    std::fs::write(
+        git.repo_path.join("script.lua"),
+        r#"
+branchless.query("foo(")
+"#,
+    )?;
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["branchless", "run", "script.lua"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"");
+        insta::assert_snapshot!(stderr, @r###"
+        Script error: runtime error: Parse error for expression 'foo(': parse error: Unrecognized EOF found at 4
+        Expected one of "(", ")", "..", ":", "::", a commit/branch/tag or a string literal
+        stack traceback:
+        	[C]: in field 'query'
+        	[string "script.lua"]:2: in main chunk
+
+        "###);
+    }
+
+    std::fs::write(git.repo_path.join("script.lua"), "return 3\n")?;
+    git.run_with_options(
+        &["branchless", "run", "script.lua"],
+        &GitRunOptions {
+            expected_exit_code: 3,
+            ..Default::default()
+        },
+    )?;
+
+    Ok(())
+}
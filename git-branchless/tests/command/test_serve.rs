@@ -0,0 +1,338 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use lib::testing::{make_git, Git};
+use serde_json::{json, Value};
+
+/// Frame the given request with a `Content-Length` header.
+fn frame_request(request: &Value) -> String {
+    let content = request.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+}
+
+/// Send the given requests to `git branchless serve --stdio`, and return the
+/// responses.
+fn run_requests(git: &Git, requests: &[Value]) -> eyre::Result<Vec<Value>> {
+    let input: String = requests.iter().map(frame_request).collect();
+    run_input(git, &input)
+}
+
+/// Send the given raw input to `git branchless serve --stdio`, and return the
+/// responses. The server's output isn't passed through `Git::run`, since that
+/// would strip the `\r\n` line endings of the message headers.
+fn run_input(git: &Git, input: &str) -> eyre::Result<Vec<Value>> {
+    let mut child = Command::new(&git.path_to_git)
+        .current_dir(&git.repo_path)
+        .args(["branchless", "serve", "--stdio"])
+        .env_clear()
+        .envs(git.get_base_env(0))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    write!(child.stdin.take().unwrap(), "{}", input)?;
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut responses = Vec::new();
+    let mut rest = stdout.as_str();
+    while let Some((header, content)) = rest.split_once("\r\n\r\n") {
+        let content_length: usize = header
+            .strip_prefix("Content-Length: ")
+            .expect("response should have a Content-Length header")
+            .parse()?;
+        let (content, next) = content.split_at(content_length);
+        responses.push(serde_json::from_str(content)?);
+        rest = next;
+    }
+    assert_eq!(rest, "");
+    Ok(responses)
+}
+
+fn render_responses(git: &Git, responses: &[Value]) -> eyre::Result<String> {
+    git.preprocess_output(serde_json::to_string_pretty(responses)?)
+}
+
+#[test]
+fn test_serve_query_and_smartlog() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    let responses = run_requests(
+        &git,
+        &[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "query", "params": {"revset": "draft()"}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "query", "params": {"revset": "foo("}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "smartlog"}),
+            json!({"jsonrpc": "2.0", "method": "smartlog"}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "frobnicate"}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "query", "params": {"revset": "."}}),
+        ],
+    )?;
+    insta::assert_snapshot!(render_responses(&git, &responses)?, @r###"
+    [
+      {
+        "id": 1,
+        "jsonrpc": "2.0",
+        "result": {
+          "commits": [
+            "96d1c37a3d4363611c49f7e52186e189a04c531f",
+            "70deb1e28791d8e7dd5a1f0c871a51b91282562f"
+          ]
+        }
+      },
+      {
+        "error": {
+          "code": -32000,
+          "message": "Parse error for expression 'foo(': parse error: Unrecognized EOF found at 4\nExpected one of \"(\", \")\", \"..\", \":\", \"::\", a commit/branch/tag or a string literal"
+        },
+        "id": 2,
+        "jsonrpc": "2.0"
+      },
+      {
+        "id": 3,
+        "jsonrpc": "2.0",
+        "result": {
+          "head_oid": "70deb1e28791d8e7dd5a1f0c871a51b91282562f",
+          "nodes": [
+            {
+              "branches": [
+                "master"
+              ],
+              "children": [
+                "96d1c37a3d4363611c49f7e52186e189a04c531f"
+              ],
+              "is_head": false,
+              "is_main": true,
+              "is_obsolete": false,
+              "oid": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "parent": null,
+              "summary": "create test1.txt"
+            },
+            {
+              "branches": [],
+              "children": [
+                "70deb1e28791d8e7dd5a1f0c871a51b91282562f"
+              ],
+              "is_head": false,
+              "is_main": false,
+              "is_obsolete": false,
+              "oid": "96d1c37a3d4363611c49f7e52186e189a04c531f",
+              "parent": "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+              "summary": "create test2.txt"
+            },
+            {
+              "branches": [],
+              "children": [],
+              "is_head": true,
+              "is_main": false,
+              "is_obsolete": false,
+              "oid": "70deb1e28791d8e7dd5a1f0c871a51b91282562f",
+              "parent": "96d1c37a3d4363611c49f7e52186e189a04c531f",
+              "summary": "create test3.txt"
+            }
+          ]
+        }
+      },
+      {
+        "error": {
+          "code": -32601,
+          "message": "Unknown method: frobnicate"
+        },
+        "id": 4,
+        "jsonrpc": "2.0"
+      },
+      {
+        "id": 5,
+        "jsonrpc": "2.0",
+        "result": null
+      }
+    ]
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_serve_rewrite() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test4", 4)?;
+
+    let responses = run_requests(
+        &git,
+        &[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "reword", "params": {"revsets": test2_oid.to_string(), "message": "reworded test2"}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "move", "params": {"source": "draft() - master", "dest": "master"}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "hide", "params": {"revsets": ["draft() - master"], "recursive": true}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "hide", "params": {"revsets": 1}}),
+        ],
+    )?;
+    insta::assert_snapshot!(render_responses(&git, &responses)?, @r###"
+    [
+      {
+        "id": 1,
+        "jsonrpc": "2.0",
+        "result": {
+          "exit_code": 0,
+          "output": "Attempting rebase in-memory...\n[1/2] Committed as: bf4ffa8 reworded test2\n[2/2] Committed as: 76de37e create test3.txt\nbranchless: processing 2 rewritten commits\nbranchless: creating working copy snapshot\nbranchless: running command: <git-executable> checkout master\nSwitched to branch 'master'\nbranchless: processing checkout\nIn-memory rebase succeeded.\nReworded commit 96d1c37 as bf4ffa8 reworded test2\n",
+          "report": {
+            "command": "reword",
+            "conflicts": [],
+            "exit_code": 0,
+            "moved_branches": [],
+            "rewritten_commits": [
+              {
+                "new_oid": "bf4ffa82f2dca324ea9e8f3494b2bff1e8dbb25e",
+                "old_oid": "96d1c37a3d4363611c49f7e52186e189a04c531f"
+              },
+              {
+                "new_oid": "76de37e0f346b395a86b65951631966fadc4cfc7",
+                "old_oid": "70deb1e28791d8e7dd5a1f0c871a51b91282562f"
+              }
+            ],
+            "status": "succeeded"
+          }
+        }
+      },
+      {
+        "id": 2,
+        "jsonrpc": "2.0",
+        "result": {
+          "exit_code": 0,
+          "output": "hint: you can omit the --dest flag in this case, as it defaults to HEAD\nhint: disable this hint by running: git config --global branchless.hint.moveImplicitHeadArgument false\nAttempting rebase in-memory...\n[1/2] Committed as: 46a456e reworded test2\n[2/2] Committed as: d8c082c create test3.txt\nbranchless: processing 2 rewritten commits\nbranchless: creating working copy snapshot\nbranchless: running command: <git-executable> checkout master\nSwitched to branch 'master'\nbranchless: processing checkout\nbranchless: running command: <git-executable> branchless smartlog\n:\n@ bf0d52a (> master) create test4.txt\n|\no 46a456e reworded test2\n|\no d8c082c create test3.txt\nIn-memory rebase succeeded.\n",
+          "report": {
+            "command": "move",
+            "conflicts": [],
+            "exit_code": 0,
+            "moved_branches": [],
+            "rewritten_commits": [
+              {
+                "new_oid": "46a456ea8ac7c5071ee75c5dc8092b9aac37071c",
+                "old_oid": "bf4ffa82f2dca324ea9e8f3494b2bff1e8dbb25e"
+              },
+              {
+                "new_oid": "d8c082cabf57434d6b8b707791c63851f3b910a1",
+                "old_oid": "76de37e0f346b395a86b65951631966fadc4cfc7"
+              }
+            ],
+            "status": "succeeded"
+          }
+        }
+      },
+      {
+        "id": 3,
+        "jsonrpc": "2.0",
+        "result": {
+          "exit_code": 0,
+          "output": "Hid commit: 46a456e reworded test2\nHid commit: d8c082c create test3.txt\nTo unhide these 2 commits, run: git undo\n"
+        }
+      },
+      {
+        "error": {
+          "code": -32602,
+          "message": "Parameter `revsets` should be a string or a list of strings"
+        },
+        "id": 4,
+        "jsonrpc": "2.0"
+      }
+    ]
+    "###);
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ bf0d52a (> master) create test4.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_serve_malformed_header() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let input = format!(
+        "Content-Length: lots\r\n\r\n{{\"id\": 1}}\r\n{}{}",
+        frame_request(
+            &json!({"jsonrpc": "2.0", "id": 2, "method": "query", "params": {"revset": "."}})
+        ),
+        frame_request(&json!({"jsonrpc": "2.0", "id": 3, "method": "shutdown"})),
+    );
+    let responses = run_input(&git, &input)?;
+    insta::assert_snapshot!(render_responses(&git, &responses)?, @r###"
+    [
+      {
+        "error": {
+          "code": -32700,
+          "message": "Invalid Content-Length header \"Content-Length: lots\": invalid digit found in string"
+        },
+        "id": null,
+        "jsonrpc": "2.0"
+      },
+      {
+        "id": 2,
+        "jsonrpc": "2.0",
+        "result": {
+          "commits": [
+            "f777ecc9b0db5ed372b2615695191a8a17f79f24"
+          ]
+        }
+      },
+      {
+        "id": 3,
+        "jsonrpc": "2.0",
+        "result": null
+      }
+    ]
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_serve_refresh_after_changes() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    let responses = run_requests(
+        &git,
+        &[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "query", "params": {"revset": "draft()"}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "hide", "params": {"revsets": ["draft()"]}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "query", "params": {"revset": "draft()"}}),
+        ],
+    )?;
+    let commits: Vec<&Value> = responses
+        .iter()
+        .map(|response| &response["result"]["commits"])
+        .collect();
+    insta::assert_snapshot!(git.preprocess_output(serde_json::to_string_pretty(&commits)?)?, @r###"
+    [
+      [
+        "96d1c37a3d4363611c49f7e52186e189a04c531f"
+      ],
+      null,
+      []
+    ]
+    "###);
+
+    Ok(())
+}
@@ -1,4 +1,13 @@
 use crate::util::extract_hint_command;
+use git_branchless::commands::smartlog::{
+    layout_graph, make_smartlog_graph, DotSink, SmartlogSink, TextSink,
+};
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Glyphs;
+use lib::core::node_descriptors::{CommitMessageDescriptor, CommitOidDescriptor, Redactor};
+use lib::core::repo_ext::RepoExt;
 use lib::testing::{
     make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
 };
@@ -188,13 +197,14 @@ fn test_non_adjacent_commits() -> eyre::Result<()> {
     let git = make_git()?;
 
     git.init_repo()?;
-    git.detach_head()?;
-    git.commit_file("test1", 1)?;
-    git.run(&["checkout", "master"])?;
-    git.commit_file("test2", 2)?;
-    git.commit_file("test3", 3)?;
-    git.detach_head()?;
-    git.commit_file("test4", 4)?;
+    git.build_scenario(
+        "
+        test1
+        test2-test3-test4
+        branch master: test3
+        head: test4
+        ",
+    )?;
 
     {
         let (stdout, _stderr) = git.run(&["smartlog"])?;
@@ -217,14 +227,14 @@ fn test_non_adjacent_commits2() -> eyre::Result<()> {
     let git = make_git()?;
 
     git.init_repo()?;
-    git.detach_head()?;
-    git.commit_file("test1", 1)?;
-    git.commit_file("test2", 2)?;
-    git.run(&["checkout", "master"])?;
-    git.commit_file("test3", 3)?;
-    git.commit_file("test4", 4)?;
-    git.detach_head()?;
-    git.commit_file("test5", 5)?;
+    git.build_scenario(
+        "
+        test1-test2
+        test3-test4-test5
+        branch master: test4
+        head: test5
+        ",
+    )?;
 
     {
         let (stdout, _stderr) = git.run(&["smartlog"])?;
@@ -249,16 +259,15 @@ fn test_non_adjacent_commits3() -> eyre::Result<()> {
     let git = make_git()?;
 
     git.init_repo()?;
-    git.commit_file("test1", 1)?;
-    git.detach_head()?;
-    git.commit_file("test2", 2)?;
-    git.run(&["checkout", "master"])?;
-    git.commit_file("test3", 3)?;
-    git.detach_head()?;
-    git.commit_file("test4", 4)?;
-    git.run(&["checkout", "master"])?;
-    git.commit_file("test5", 5)?;
-    git.commit_file("test6", 6)?;
+    git.build_scenario(
+        r"
+        test1-test3-test5-test6
+             \     \
+              test2 test4
+        branch master: test6
+        head: master
+        ",
+    )?;
 
     {
         let (stdout, _stderr) = git.run(&["smartlog"])?;
@@ -633,3 +642,983 @@ fn test_smartlog_hint_abandoned_except_current_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_smartlog_all_worktrees() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["worktree", "add", "--detach", "wt", &test1_oid.to_string()])?;
+    git.run(&["hide", &test1_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ fe65c1f (> master) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--all-worktrees"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | x 62fc20d (manually hidden) (worktree: wt) create test1.txt
+        |
+        @ fe65c1f (> master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_changes_banner() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    {
+        let (_stdout, stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["commit", "--amend", "--message", "amended test3"])?;
+    git.run(&["hide", &test2_oid.to_string()])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: since your last smartlog: 1 commit landed, 1 commit rewritten by git commit, 2 new commits, 1 commit hidden
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    git.run(&["config", "branchless.smartlog.changesBanner", "false"])?;
+    git.commit_file("test4", 4)?;
+    {
+        let (_stdout, stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_collapsed() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test4", 4)?;
+    let test5_oid = git.commit_file("test5", 5)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test6", 6)?;
+    git.run(&[
+        "branchless",
+        "meta",
+        "name-stack",
+        "-c",
+        "62fc20d",
+        "my-feature",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--collapsed"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d [topic: my-feature] (+2 commits) create test1.txt
+        |\
+        | o 8f7aef5 (+1 commit) create test4.txt
+        |
+        @ fd6c44b create test6.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "smartlog",
+            "--collapsed",
+            "--expand",
+            &test5_oid.to_string(),
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d [topic: my-feature] (+2 commits) create test1.txt
+        |\
+        | o 8f7aef5 create test4.txt
+        | |
+        | o 47d30fa create test5.txt
+        |
+        @ fd6c44b create test6.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_ci_status() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    std::fs::write(
+        git.repo_path.join(".git").join("ci-status.sh"),
+        format!(
+            r#"echo called >>.git/ci-calls
+for oid in "$@"; do
+    case "$oid" in
+        {test1_oid}) echo "$oid success";;
+        {test2_oid}) echo "$oid failure";;
+    esac
+done
+"#,
+            test1_oid = test1_oid,
+            test2_oid = test2_oid,
+        ),
+    )?;
+    git.run(&[
+        "config",
+        "branchless.ci.statusCommand",
+        "sh .git/ci-status.sh",
+    ])?;
+
+    // Wait for the statuses being queried in the background to be ready.
+    let wait_for_statuses = || -> eyre::Result<()> {
+        let statuses_path = git
+            .repo_path
+            .join(".git")
+            .join("branchless")
+            .join("ci-status")
+            .join("statuses");
+        for _ in 0..100 {
+            if statuses_path.exists() {
+                return Ok(());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        eyre::bail!("Timed out waiting for CI statuses")
+    };
+
+    // The statuses are queried in the background, so they aren't shown until
+    // the next smartlog.
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    wait_for_statuses()?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d [ci: passed] create test1.txt
+        |
+        o 96d1c37 [ci: failed] create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    // The statuses should have been cached.
+    git.run(&["smartlog"])?;
+    let ci_calls = std::fs::read_to_string(git.repo_path.join(".git").join("ci-calls"))?;
+    assert_eq!(ci_calls.lines().count(), 1);
+
+    git.run(&["config", "branchless.ci.cacheTtl", "0"])?;
+    git.run(&["smartlog"])?;
+    wait_for_statuses()?;
+    let ci_calls = std::fs::read_to_string(git.repo_path.join(".git").join("ci-calls"))?;
+    assert_eq!(ci_calls.lines().count(), 2);
+
+    git.run(&["config", "branchless.commitDescriptors.ciStatus", "false"])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        @ 70deb1e create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_signature_status() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_ssh_signing()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let dot_git = git.repo_path.join(".git");
+    for key_name in ["trusted-key", "untrusted-key"] {
+        let status = std::process::Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", key_name, "-f"])
+            .arg(dot_git.join(key_name))
+            .status()?;
+        assert!(status.success());
+    }
+    let trusted_public_key = std::fs::read_to_string(dot_git.join("trusted-key.pub"))?;
+    std::fs::write(
+        dot_git.join("allowed-signers"),
+        format!("test@example.com {}", trusted_public_key),
+    )?;
+    git.run(&["config", "gpg.format", "ssh"])?;
+    git.run(&[
+        "config",
+        "gpg.ssh.allowedSignersFile",
+        dot_git.join("allowed-signers").to_str().unwrap(),
+    ])?;
+    for (key_name, message) in [("trusted-key", "good"), ("untrusted-key", "unknown")] {
+        git.run(&[
+            "-c",
+            &format!(
+                "user.signingKey={}",
+                dot_git.join(format!("{}.pub", key_name)).to_str().unwrap()
+            ),
+            "commit",
+            "--allow-empty",
+            "-S",
+            "-m",
+            message,
+        ])?;
+    }
+
+    // Signature statuses aren't shown by default.
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        assert!(!stdout.contains("[sig:"), "{}", stdout);
+    }
+
+    git.run(&["config", "branchless.commitDescriptors.signature", "true"])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert!(lines[2].ends_with(" create test1.txt"), "{}", stdout);
+        assert!(lines[4].ends_with(" [sig: good] good"), "{}", stdout);
+        assert!(lines[6].ends_with(" [sig: unknown] unknown"), "{}", stdout);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "signed()"])?;
+        let stdout: Vec<&str> = stdout.lines().map(|line| &line[8..]).collect();
+        insta::assert_debug_snapshot!(stdout, @r###"
+        [
+            "unknown",
+            "good",
+        ]
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["query", "verified()"])?;
+        let stdout: Vec<&str> = stdout.lines().map(|line| &line[8..]).collect();
+        insta::assert_debug_snapshot!(stdout, @r###"
+        [
+            "good",
+        ]
+        "###);
+    }
+
+    // Cached statuses are verified again when the allowed signers change.
+    let untrusted_public_key = std::fs::read_to_string(dot_git.join("untrusted-key.pub"))?;
+    std::fs::write(
+        dot_git.join("allowed-signers"),
+        format!("test@example.com {}", untrusted_public_key),
+    )?;
+    {
+        let (stdout, _stderr) = git.run(&["query", "verified()"])?;
+        let stdout: Vec<&str> = stdout.lines().map(|line| &line[8..]).collect();
+        insta::assert_debug_snapshot!(stdout, @r###"
+        [
+            "unknown",
+        ]
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_profile() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    let trace_path = git.repo_path.join(".git").join("trace.json");
+    let (stdout, stderr) = git.run(&[
+        "branchless",
+        "smartlog",
+        "--profile",
+        "--profile-trace",
+        trace_path.to_str().unwrap(),
+    ])?;
+    insta::assert_snapshot!(stdout, @r###"
+    :
+    @ 62fc20d (> master) create test1.txt
+    "###);
+
+    // The timings vary between runs, so only check that the expected spans
+    // are reported.
+    let mut stderr_lines = stderr.lines();
+    assert!(stderr_lines.next().unwrap().starts_with("Profile (total "));
+    assert!(stderr_lines.any(|line| line.starts_with("  smartlog ")));
+
+    let trace: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(trace_path)?)?;
+    assert!(trace
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|event| event["name"] == "smartlog"));
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_additional_main_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "release-1.0", "master"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "-b", "feature"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&[
+        "config",
+        "branchless.core.additionalMainBranches",
+        "release-1.0",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        O 62fc20d create test1.txt
+        |
+        O 96d1c37 (release-1.0) create test2.txt
+        |
+        @ 70deb1e (> feature) create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["query", "draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        70deb1e create test3.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["move", "-x", "release-1.0", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        You are trying to rewrite 1 public commit, such as: 96d1c37 create test2.txt
+        It is generally not advised to rewrite public commits, because your
+        collaborators will have difficulty merging your changes.
+        These commits are public:
+          96d1c37 create test2.txt (reachable from release-1.0)
+        To rewrite only the draft commits, use the revset: 'draft() & descendants(96d1c37)'
+        Retry with -f/--force-rewrite to proceed anyways.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_dag_sync_in_chunks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+    for i in 1..=5 {
+        git.commit_file(&format!("test{i}"), i)?;
+    }
+    git.run(&["config", "branchless.core.dagSyncChunkSize", "2"])?;
+    git.run(&["branchless", "init"])?;
+    git.detach_head()?;
+    git.commit_file("test6", 6)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O f81d55c (master) create test5.txt
+        |
+        @ 2831fb5 create test6.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "query", "ancestors(master)", "--raw"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        f81d55c0d520ff8d02ef9294d95156dcb78a5255
+        355e173bf9c5d2efac2e451da0cdad3fb82b869a
+        70deb1e28791d8e7dd5a1f0c871a51b91282562f
+        96d1c37a3d4363611c49f7e52186e189a04c531f
+        62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        f777ecc9b0db5ed372b2615695191a8a17f79f24
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_cache() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    // The cache is disabled by default.
+    git.run(&["smartlog"])?;
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let num_tables: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE name = 'smartlog_cache'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(num_tables, 0);
+    }
+
+    git.run(&["config", "branchless.smartlog.cache", "true"])?;
+    git.run(&["smartlog"])?;
+
+    // Tamper with the cached output to check that it's what gets printed.
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        conn.execute(
+            "UPDATE smartlog_cache SET stdout = CAST('cached smartlog\n' AS BLOB)",
+            [],
+        )?;
+    }
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @"cached smartlog");
+    }
+
+    git.commit_file("test2", 2)?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_color_theme() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    const YELLOW: &str = "\u{1b}[33m";
+    const BLUE: &str = "\u{1b}[34m";
+    const RED: &str = "\u{1b}[31m";
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--color", "always", "smartlog"])?;
+        assert!(stdout.contains(YELLOW), "{:?}", stdout);
+        assert!(!stdout.contains(BLUE), "{:?}", stdout);
+    }
+
+    git.run(&["config", "branchless.color.theme", "high-contrast"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--color", "always", "smartlog"])?;
+        assert!(!stdout.contains(YELLOW), "{:?}", stdout);
+        assert!(stdout.contains(BLUE), "{:?}", stdout);
+    }
+
+    git.run(&["config", "branchless.color.commitOid", "red"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--color", "always", "smartlog"])?;
+        assert!(stdout.contains(RED), "{:?}", stdout);
+    }
+
+    git.run(&["config", "branchless.color.commitOid", "octarine"])?;
+    {
+        let (stdout, stderr) = git.run(&["branchless", "--color", "always", "smartlog"])?;
+        insta::assert_snapshot!(console::strip_ansi_codes(&stderr), @r###"
+        Warning: ignoring invalid configuration: Parsing branchless.color.commitOid: Unsupported color or attribute "octarine" in: "octarine"
+        "###);
+        assert!(stdout.contains(YELLOW), "{:?}", stdout);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_color_env_vars() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    let run_with_env = |args: &[&str], env: &[(&str, &str)]| -> eyre::Result<String> {
+        let (stdout, _stderr) = git.run_with_options(
+            args,
+            &GitRunOptions {
+                env: env
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+                ..Default::default()
+            },
+        )?;
+        Ok(stdout)
+    };
+
+    let stdout = run_with_env(&["smartlog"], &[("NO_COLOR", ""), ("CLICOLOR_FORCE", "1")])?;
+    assert!(stdout.contains('\u{1b}'), "{:?}", stdout);
+
+    let stdout = run_with_env(&["smartlog"], &[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")])?;
+    insta::assert_snapshot!(stdout, @"@ f777ecc (> master) create initial.txt
+");
+
+    // An explicit `--color` option takes precedence over the environment.
+    let stdout = run_with_env(
+        &["branchless", "--color", "always", "smartlog"],
+        &[("NO_COLOR", "1")],
+    )?;
+    assert!(stdout.contains('\u{1b}'), "{:?}", stdout);
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_glyphs() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["hide", "HEAD^"])?;
+
+    git.run(&["config", "branchless.smartlog.glyphs", "unicode"])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        ◇ f777ecc (master) create initial.txt
+        ┃
+        ✕ 62fc20d (manually hidden) create test1.txt
+        ┃
+        ● 96d1c37 create test2.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.smartlog.glyphs", "ascii"])?;
+    git.run(&["config", "branchless.smartlog.headGlyph", "*"])?;
+    git.run(&["config", "branchless.smartlog.hiddenGlyph", "-"])?;
+    git.run(&["config", "branchless.smartlog.mainGlyph", "#"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--color", "always", "smartlog"])?;
+        insta::assert_snapshot!(console::strip_ansi_codes(&stdout), @r###"
+        # f777ecc (master) create initial.txt
+        |
+        - 62fc20d (manually hidden) create test1.txt
+        |
+        * 96d1c37 create test2.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.smartlog.glyphs", "wingdings"])?;
+    {
+        let (stdout, stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Warning: ignoring invalid configuration: Unknown glyph set "wingdings" for branchless.smartlog.glyphs (expected one of: ascii, unicode, nerdfont)
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        x 62fc20d (manually hidden) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_pager() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+
+    let run_with_pager = |args: &[&str], pager: &str| -> eyre::Result<String> {
+        let (stdout, _stderr) = git.run_with_options(
+            args,
+            &GitRunOptions {
+                env: std::iter::once(("GIT_PAGER".to_string(), pager.to_string())).collect(),
+                ..Default::default()
+            },
+        )?;
+        Ok(stdout)
+    };
+
+    // Output isn't to a terminal, so it's only paged when requested.
+    let stdout = run_with_pager(&["smartlog"], "sed s/^/paged:/")?;
+    insta::assert_snapshot!(stdout, @"@ f777ecc (> master) create initial.txt
+");
+
+    let stdout = run_with_pager(&["branchless", "--paginate", "smartlog"], "sed s/^/paged:/")?;
+    insta::assert_snapshot!(stdout, @"paged:@ f777ecc (> master) create initial.txt
+");
+
+    let stdout = run_with_pager(
+        &["branchless", "--paginate", "query", "."],
+        "sed s/^/paged:/",
+    )?;
+    insta::assert_snapshot!(stdout, @"paged:f777ecc create initial.txt
+");
+
+    let stdout = run_with_pager(&["branchless", "--paginate", "smartlog"], "cat")?;
+    insta::assert_snapshot!(stdout, @"@ f777ecc (> master) create initial.txt
+");
+
+    git.run(&["config", "core.pager", "sed s/^/core.pager:/"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "--paginate", "smartlog"])?;
+        insta::assert_snapshot!(stdout, @"core.pager:@ f777ecc (> master) create initial.txt
+");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_time_travel() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "-f", "foo", "HEAD"])?;
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--event-id=3"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Showing the smartlog as it was after event 3 (transaction 2: post-commit).
+        :
+        @ 62fc20d (> master) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--hidden", "--at", "now"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Showing the smartlog as it was after event 12 (transaction 9: hook-post-rewrite).
+        :
+        O 62fc20d create test1.txt
+        |\
+        | x 96d1c37 (rewritten as cb8137ad) (foo) create test2.txt
+        |
+        @ cb8137a (> master) amended test2
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--at", "10 years ago"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Showing the smartlog as it was before any events were recorded.
+        :
+        O cb8137a (> master) amended test2
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["smartlog", "--at", "the day after tomorrow-ish"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: Invalid value "the day after tomorrow-ish" for '--at <TIME>': expected a time like `2 days ago`, but got: the day after tomorrow-ish
+
+        For more information try --help
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["smartlog", "--at", "now", "--event-id", "1"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: The argument '--at <TIME>' cannot be used with '--event-id <EVENT_ID>'
+
+        USAGE:
+            git-branchless smartlog --at <TIME>
+
+        For more information try --help
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_formats() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test4", 4)?;
+    git.commit_file("test5", 5)?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--format", "text"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d create test1.txt
+        |\
+        : o 96d1c37 (foo) create test2.txt
+        : |
+        : o 70deb1e create test3.txt
+        :
+        @ 848121c (> master) create test5.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--format", "dot"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        digraph smartlog {
+          node [shape=box, fontname="monospace"];
+          "62fc20d2a290daea0d52bdc2ed2ad4be6491010e" [label="62fc20d create test1.txt", shape=ellipse];
+          "96d1c37a3d4363611c49f7e52186e189a04c531f" [label="96d1c37 (foo) create test2.txt"];
+          "70deb1e28791d8e7dd5a1f0c871a51b91282562f" [label="70deb1e create test3.txt"];
+          "848121cb21bf9af8b064c91bc8930bd16d624a22" [label="848121c (> master) create test5.txt", shape=ellipse, penwidth=2];
+          "62fc20d2a290daea0d52bdc2ed2ad4be6491010e" -> "96d1c37a3d4363611c49f7e52186e189a04c531f";
+          "96d1c37a3d4363611c49f7e52186e189a04c531f" -> "70deb1e28791d8e7dd5a1f0c871a51b91282562f";
+          "62fc20d2a290daea0d52bdc2ed2ad4be6491010e" -> "848121cb21bf9af8b064c91bc8930bd16d624a22" [style=dotted];
+        }
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog", "--format", "html"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        <pre class="git-branchless-smartlog">
+        :
+        O <span style="color: olive">62fc20d</span> create test1.txt
+        |\
+        : o <span style="color: olive">96d1c37</span> <span style="color: lime">(foo)</span> create test2.txt
+        : |
+        : o <span style="color: olive">70deb1e</span> create test3.txt
+        :
+        <span style="font-weight: bold">@ </span><span style="color: olive; font-weight: bold">848121c</span><span style="font-weight: bold"> </span><span style="color: lime; font-weight: bold">(&gt; master)</span><span style="font-weight: bold"> create test5.txt</span>
+        </pre>
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_library_sinks() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let repo = git.get_repo()?;
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = Dag::open_and_sync(
+        &effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    let graph = make_smartlog_graph(
+        &effects,
+        &repo,
+        &dag,
+        &event_replayer,
+        event_cursor,
+        &dag.observed_commits,
+        true,
+    )?;
+    let layout = layout_graph(
+        &effects,
+        &repo,
+        &dag,
+        &graph,
+        references_snapshot.head_oid,
+        &mut [
+            &mut CommitOidDescriptor::new(true)?,
+            &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
+        ],
+    )?;
+
+    insta::assert_snapshot!(TextSink.render(effects.get_glyphs(), &layout)?, @r###"
+    O f777ecc create initial.txt
+    |
+    @ 62fc20d create test1.txt
+    "###);
+    insta::assert_snapshot!(DotSink.render(effects.get_glyphs(), &layout)?, @r###"
+    digraph smartlog {
+      node [shape=box, fontname="monospace"];
+      "f777ecc9b0db5ed372b2615695191a8a17f79f24" [label="f777ecc create initial.txt", shape=ellipse];
+      "62fc20d2a290daea0d52bdc2ed2ad4be6491010e" [label="62fc20d create test1.txt", penwidth=2];
+      "f777ecc9b0db5ed372b2615695191a8a17f79f24" -> "62fc20d2a290daea0d52bdc2ed2ad4be6491010e";
+    }
+    "###);
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_event_id_public_remote_branches() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "-b", "feature"])?;
+    let test2_oid = original_repo.commit_file("test2", 2)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["config", "branchless.core.publicRemoteBranches", "origin/*"])?;
+    cloned_repo.run(&["checkout", "origin/feature"])?;
+    let test3_oid = cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["checkout", "master"])?;
+
+    let num_events = {
+        let repo = cloned_repo.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = lib::core::eventlog::EventLogDb::new(&conn)?;
+        event_log_db.count_events()?
+    };
+
+    // Simulate the commit being pushed, which makes it public. Like `git push`,
+    // pass the old value, so that it's recorded in the event log.
+    cloned_repo.run(&[
+        "update-ref",
+        "refs/remotes/origin/feature",
+        &test3_oid.to_string(),
+        &test2_oid.to_string(),
+    ])?;
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["query", "draft()"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    // The commit was still a draft commit at the time.
+    {
+        let (stdout, _stderr) =
+            cloned_repo.run(&["smartlog", &format!("--event-id={}", num_events), "draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Showing the smartlog as it was after event 5 (transaction 5: hook-post-checkout).
+        :
+        @ 62fc20d (remote origin/master) create test1.txt
+        |
+        O 96d1c37 create test2.txt
+        |
+        o 70deb1e create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_split_by_directory() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.write_file("README", "readme\n")?;
+    git.write_file("src/lib", "lib\n")?;
+    git.write_file("src/foo/mod", "mod\n")?;
+    git.write_file("docs/guide", "guide\n")?;
+    git.run(&["add", "."])?;
+    git.run_with_options(
+        &["commit", "-m", "Add everything\n\nThis is the body."],
+        &GitRunOptions {
+            time: 1,
+            ..Default::default()
+        },
+    )?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "split", "--by-directory"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Split commit 148cf28 Add everything into 3 commits:
+        e7bc739 Add everything (top-level files)
+        146f061 Add everything (docs/)
+        5bb09f6 Add everything (src/)
+        Attempting rebase in-memory...
+        [1/1] Committed as: 7c6ad1d create test2.txt
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        Finished restacking commits.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |
+        o e7bc739 Add everything (top-level files)
+        |
+        o 146f061 Add everything (docs/)
+        |
+        @ 5bb09f6 Add everything (src/)
+        |
+        o 7c6ad1d create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s%n%b", "--name-only", "master.."])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Add everything (src/)
+        This is the body.
+
+
+        src/foo/mod.txt
+        src/lib.txt
+        Add everything (docs/)
+        This is the body.
+
+
+        docs/guide.txt
+        Add everything (top-level files)
+        This is the body.
+
+
+        README.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o e7bc739 Add everything (top-level files)
+        |
+        o 146f061 Add everything (docs/)
+        |
+        @ 5bb09f6 Add everything (src/)
+        |
+        o 7c6ad1d create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_split_by_group() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.write_file("README", "readme\n")?;
+    git.write_file("src/lib", "lib\n")?;
+    git.write_file("docs/guide", "guide\n")?;
+    git.run(&["add", "."])?;
+    git.run_with_options(
+        &["commit", "-m", "Add everything"],
+        &GitRunOptions {
+            time: 1,
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["branchless", "split", "--group", "src/*"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        You are trying to rewrite 1 public commit, such as: 961c1e5 Add everything
+        It is generally not advised to rewrite public commits, because your
+        collaborators will have difficulty merging your changes.
+        These commits are public:
+          961c1e5 Add everything (reachable from master)
+        Retry with -f/--force-rewrite to proceed anyways.
+        "###);
+    }
+
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "foo"])?;
+    git.run(&["branch", "-f", "master", "HEAD^"])?;
+    {
+        let (stdout, _stderr) = git.run(&[
+            "branchless",
+            "split",
+            "--group",
+            "docs/*,README*",
+            "--group",
+            "tests/*",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Split commit 961c1e5 Add everything into 2 commits:
+        82bc6e4 Add everything (docs/*,README*)
+        5012970 Add everything (remaining files)
+        No abandoned commits to restack.
+        branchless: processing 1 update: branch foo
+        Finished restacking branches.
+        O f777ecc (master) create initial.txt
+        |
+        o 82bc6e4 Add everything (docs/*,README*)
+        |
+        @ 5012970 (> foo) Add everything (remaining files)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 82bc6e4 Add everything (docs/*,README*)
+        |
+        @ 5012970 (> foo) Add everything (remaining files)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "split", "--group", "tests/*"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Not splitting commit 5012970 Add everything (remaining files), since its changes can't be divided into more than 1 group.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_split_preserves_message_encoding() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["config", "i18n.commitEncoding", "ISO-8859-1"])?;
+
+    git.write_file("src/lib", "lib\n")?;
+    git.write_file("docs/guide", "guide\n")?;
+    git.run(&["add", "."])?;
+    std::fs::write(git.repo_path.join("message"), b"caf\xe9 latin-1\n")?;
+    git.run(&["commit", "-q", "-F", "message"])?;
+    std::fs::remove_file(git.repo_path.join("message"))?;
+
+    git.run(&["branchless", "split", "--group", "docs/*"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "-c",
+            "i18n.logOutputEncoding=UTF-8",
+            "log",
+            "-2",
+            "--format=%e %s",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        ISO-8859-1 café latin-1 (remaining files)
+        ISO-8859-1 café latin-1 (docs/*)
+        "###);
+    }
+
+    Ok(())
+}
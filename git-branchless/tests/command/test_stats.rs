@@ -0,0 +1,80 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_stats() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let other_oid = git.commit_file_with_contents("conflict", 4, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 5, "conflict 2\nline 2\n")?;
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "stats"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        4 draft commits in 3 stacks
+        Stacks:
+          96d1c37 create test2.txt: 2 commits, +2/-0 lines
+          8bbdcaa create conflict.txt: 1 commit, +1/-0 lines
+          aeb8cf9 create conflict.txt: 1 commit, +2/-0 lines
+        Commit age:
+          less than 1 day: 0
+          1 to 7 days: 0
+          1 to 4 weeks: 0
+          more than 4 weeks: 4
+        Lines changed: +5/-0
+        Merge conflicts encountered while rewriting: 0
+        "###);
+    }
+
+    git.run_with_options(
+        &["move", "--source", &other_oid.to_string()],
+        &GitRunOptions {
+            expected_exit_code: 3,
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "stats", "--json"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        {"age_distribution":{"1_to_4_weeks":0,"1_to_7_days":0,"less_than_1_day":0,"more_than_4_weeks":4},"lines_added":5,"lines_removed":0,"num_commits":4,"num_merge_conflicts":1,"num_stacks":3,"stacks":[{"lines_added":2,"lines_removed":0,"name":null,"num_commits":2,"root_oid":"96d1c37a3d4363611c49f7e52186e189a04c531f"},{"lines_added":1,"lines_removed":0,"name":null,"num_commits":1,"root_oid":"8bbdcaaaba2f48f03fba4e37a6ee2dea98fc9418"},{"lines_added":2,"lines_removed":0,"name":null,"num_commits":1,"root_oid":"aeb8cf99e4e224f16cb3d85e0a30c69292e0623d"}]}
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_stats_no_draft_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "stats"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        0 draft commits in 0 stacks
+        Commit age:
+          less than 1 day: 0
+          1 to 7 days: 0
+          1 to 4 weeks: 0
+          more than 4 weeks: 0
+        Lines changed: +0/-0
+        Merge conflicts encountered while rewriting: 0
+        "###);
+    }
+
+    Ok(())
+}
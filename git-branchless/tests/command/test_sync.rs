@@ -233,3 +233,73 @@ fn test_sync_specific_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_sync_dry_run() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file_with_contents("test3", 3, "conflicting contents\n")?;
+
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 4)?;
+
+    let count_transactions = || -> eyre::Result<i64> {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let num_transactions = conn.query_row(
+            "SELECT COUNT(*) FROM event_transactions",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        Ok(num_transactions)
+    };
+    let num_transactions = count_transactions()?;
+    {
+        let (stdout, _stderr) = git.run(&["sync", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        [1/2] Committed as: 58f245c create test1.txt
+        [2/2] Committed as: cbe212c create test2.txt
+        Merge conflict for 7fc72e9 create test3.txt
+        O f777ecc create initial.txt
+        |\
+        | o 7fc72e9 create test3.txt
+        |
+        @ 34c119c (> master) create test3.txt
+        |
+        o 58f245c (rewritten from 62fc20d) create test1.txt
+        |
+        o cbe212c (rewritten from 96d1c37) create test2.txt
+        (This was a dry-run; run without --dry-run to apply changes.)
+        "###);
+    }
+    // A dry run shouldn't record a transaction which `git undo` would show.
+    assert_eq!(count_transactions()?, num_transactions);
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        | |
+        | o 96d1c37 create test2.txt
+        |\
+        | o 7fc72e9 create test3.txt
+        |
+        @ 34c119c (> master) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
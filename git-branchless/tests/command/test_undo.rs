@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::mem::swap;
 use std::rc::Rc;
@@ -13,7 +14,10 @@ use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
 use lib::core::repo_ext::RepoExt;
 use lib::git::{GitRunInfo, GitVersion, Repo};
-use lib::testing::{make_git, Git};
+use lib::testing::{
+    make_git, make_git_with_remote_repo, Git, GitInitOptions, GitRunOptions,
+    GitWrapperWithRemoteRepo,
+};
 
 use cursive::event::Key;
 use cursive::CursiveRunnable;
@@ -59,6 +63,7 @@ fn run_undo_events(git: &Git, event_cursor: EventCursor) -> eyre::Result<(isize,
         path_to_git: git.path_to_git.clone(),
         working_directory: repo.get_working_copy_path().unwrap().to_path_buf(),
         env: git.get_base_env(0).into_iter().collect(),
+        inherit_stdin: true,
     };
 
     let ExitCode(exit_code) = undo_events(
@@ -178,10 +183,10 @@ fn test_undo_navigate() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         │                                                                                                                      │
-        │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.                                                  │
+        │Caused by: git commit                                                                                                 │
         │1. Check out from 62fc20d create test1.txt                                                                            │
         │               to 96d1c37 create test2.txt                                                                            │
         │2. Move branch master from 62fc20d create test1.txt                                                                   │
@@ -206,10 +211,10 @@ fn test_undo_navigate() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         │                                                                                                                      │
-        │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 4 (event 6). Press 'h' for help, 'q' to quit.                                                  │
+        │Caused by: git commit                                                                                                 │
         │1. Commit 96d1c37 create test2.txt                                                                                    │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -263,10 +268,10 @@ fn test_go_to_event() -> eyre::Result<()> {
     │                                                                                                                      │
     │                                                                                                                      │
     │                                                                                                                      │
-    │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
     │Repo after transaction 4 (event 6). Press 'h' for help, 'q' to quit.                                                  │
+    │Caused by: git commit                                                                                                 │
     │1. Commit 96d1c37 create test2.txt                                                                                    │
     │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -289,10 +294,10 @@ fn test_go_to_event() -> eyre::Result<()> {
     │                                                                                                                      │
     │                                                                                                                      │
     │                                                                                                                      │
-    │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
     │Repo after transaction 1 (event 1). Press 'h' for help, 'q' to quit.                                                  │
+    │Caused by: git commit                                                                                                 │
     │1. Check out from f777ecc create initial.txt                                                                          │
     │               to 62fc20d create test1.txt                                                                            │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -353,7 +358,8 @@ fn test_undo_hide() -> eyre::Result<()> {
 
         2. Unhide commit 62fc20d create test1.txt
 
-        Confirm? [yN] Applied 2 inverse events.
+        Confirm? [yN] branchless: processing 1 update: branch test1
+        Applied 2 inverse events.
         "###);
         assert_eq!(exit_code, 0);
     }
@@ -372,6 +378,105 @@ fn test_undo_hide() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_undo_restores_branches_atomically() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["branch", "bar"])?;
+    let event_cursor = {
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        event_replayer.make_default_cursor()
+    };
+
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "-f", "foo", "HEAD"])?;
+    git.run(&["branch", "-f", "bar", "HEAD"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 (bar, foo) create test2.txt
+        "###);
+    }
+
+    // With one of the branches locked, neither branch should be moved.
+    let lock_path = git
+        .repo_path
+        .join(".git")
+        .join("refs")
+        .join("heads")
+        .join("foo.lock");
+    std::fs::write(&lock_path, "")?;
+    {
+        let (exit_code, stdout) = run_undo_events(&git, event_cursor)?;
+        assert_eq!(exit_code, 1);
+        assert!(
+            stdout.contains("Failed to restore branches, so none of them were moved:"),
+            "{}",
+            stdout
+        );
+    }
+    {
+        let (stdout, _stderr) =
+            git.run(&["branch", "--format=%(refname:short) %(objectname:short)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        (HEAD detached from 62fc20d) 96d1c37
+        bar 96d1c37
+        foo 96d1c37
+        master 62fc20d
+        "###);
+    }
+
+    std::fs::remove_file(&lock_path)?;
+    {
+        let (exit_code, stdout) = run_undo_events(&git, event_cursor)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Delete branch bar at 96d1c37 create test2.txt
+
+        2. Delete branch foo at 96d1c37 create test2.txt
+
+        3. Hide commit 96d1c37 create test2.txt
+
+        4. Check out from 96d1c37 create test2.txt
+                       to 62fc20d create test1.txt
+        5. Check out from 62fc20d create test1.txt
+                       to 62fc20d create test1.txt
+        Confirm? [yN] branchless: processing 2 updates: branch bar, branch foo
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e --detach
+        Applied 5 inverse events.
+        "###);
+        assert_eq!(exit_code, 0);
+    }
+    {
+        let (stdout, _stderr) =
+            git.run(&["branch", "--format=%(refname:short) %(objectname:short)"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        (HEAD detached at 62fc20d) 62fc20d
+        bar 62fc20d
+        foo 62fc20d
+        master 62fc20d
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_undo_move_refs() -> eyre::Result<()> {
     let git = make_git()?;
@@ -412,7 +517,8 @@ fn test_undo_move_refs() -> eyre::Result<()> {
                                 to 62fc20d create test1.txt
         3. Check out from 96d1c37 create test2.txt
                        to 62fc20d create test1.txt
-        Confirm? [yN] branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e --detach
+        Confirm? [yN] branchless: processing 1 update: branch master
+        branchless: running command: <git-executable> checkout 62fc20d2a290daea0d52bdc2ed2ad4be6491010e --detach
         Applied 3 inverse events.
         "###);
         assert_eq!(exit_code, 0);
@@ -468,10 +574,10 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         │                                                                                                                      │
-        │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.                                                  │
+        │Caused by: git branchless hide                                                                                        │
         │1. Hide commit 62fc20d create test1.txt                                                                               │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -494,10 +600,10 @@ fn test_historical_smartlog_visibility() -> eyre::Result<()> {
         │                                                                                                                      │
         │                                                                                                                      │
         │                                                                                                                      │
-        │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
         ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
         │Repo after transaction 2 (event 3). Press 'h' for help, 'q' to quit.                                                  │
+        │Caused by: git commit                                                                                                 │
         │1. Commit 62fc20d create test1.txt                                                                                    │
         │                                                                                                                      │
         └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -637,7 +743,8 @@ fn test_undo_doesnt_make_working_dir_dirty() -> eyre::Result<()> {
                        to f777ecc create initial.txt
         5. Delete branch foo at f777ecc create initial.txt
 
-        Confirm? [yN] branchless: running command: <git-executable> checkout f777ecc9b0db5ed372b2615695191a8a17f79f24 --detach
+        Confirm? [yN] branchless: processing 3 updates: branch bar, branch foo, branch master
+        branchless: running command: <git-executable> checkout f777ecc9b0db5ed372b2615695191a8a17f79f24 --detach
         Applied 5 inverse events.
         "###);
         assert_eq!(exit_code, 0);
@@ -694,10 +801,10 @@ fn test_git_bisect_produces_empty_event() -> eyre::Result<()> {
     │                                                                                                                      │
     │                                                                                                                      │
     │                                                                                                                      │
-    │                                                                                                                      │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
     ┌──────────────────────────────────────────────────────┤ Events ├──────────────────────────────────────────────────────┐
     │Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.                                                  │
+    │Caused by: git bisect                                                                                                 │
     │1. Empty event for BISECT_HEAD                                                                                        │
     │   This may be an unsupported use-case; see https://github.com/arxanas/git-branchless/issues/57                       │
     └──────────────────────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -876,7 +983,8 @@ fn test_undo_noninteractive() -> eyre::Result<()> {
                                 to 96d1c37 create test2.txt
         4. Check out from 9ed8f9a bad message
                        to 96d1c37 create test2.txt
-        Confirm? [yN] branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f --detach
+        Confirm? [yN] branchless: processing 1 update: branch master
+        branchless: running command: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f --detach
         :
         @ 96d1c37 (master) create test2.txt
         Applied 4 inverse events.
@@ -918,3 +1026,406 @@ fn test_undo_no_confirm() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_undo_skip_garbage_collected_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["update-ref", "-d", &format!("refs/branchless/{test1_oid}")])?;
+    git.run(&["reflog", "expire", "--expire=now", "--all"])?;
+    git.run(&["prune", "--expire=now"])?;
+
+    {
+        let (stdout, stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Warning: 1 commit could not be found in the repository and will be ignored: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        They may have been garbage collected. Run `git branchless repair --no-dry-run` to clean up the event log.
+        "###);
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run_with_options(
+            &["undo", "--yes"],
+            &lib::testing::GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        let stdout = trim_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Check out from f777ecc create initial.txt
+                       to <commit not available: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e>
+        Not checking out commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e, because it is no longer available.
+        Applied 0 inverse events, but skipped 1 inverse event which referred to commits that may have been garbage collected.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_fetch() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    original_repo.commit_file("test1", 1)?;
+    cloned_repo.run(&["fetch"])?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["undo", "--yes"])?;
+        let stdout = trim_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Move remote branch origin/master from 62fc20d create test1.txt
+                                              to f777ecc create initial.txt
+        branchless: processing 1 update: remote branch origin/master
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["rev-parse", "--short", "origin/master"])?;
+        insta::assert_snapshot!(stdout, @"f777ecc");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_exclude_remotes() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    let initial_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+    git.run(&["branch", "foo"])?;
+    git.run(&[
+        "update-ref",
+        "refs/remotes/origin/master",
+        &initial_oid.to_string(),
+    ])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run_with_options(
+        &["update-ref", "--stdin"],
+        &lib::testing::GitRunOptions {
+            input: Some(format!(
+                "update refs/heads/foo {test1_oid} {initial_oid}\nupdate refs/remotes/origin/master {test1_oid} {initial_oid}\n"
+            )),
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["undo", "--yes", "--exclude-remotes"])?;
+        let stdout = trim_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Move branch foo from 62fc20d create test1.txt
+                             to f777ecc create initial.txt
+        branchless: processing 1 update: branch foo
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branch", "-a", "-v"])?;
+        insta::assert_snapshot!(stdout, @r###"
+          foo                   f777ecc create initial.txt
+        * master                62fc20d create test1.txt
+          remotes/origin/master 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_refs() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    let initial_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+    git.run(&["branch", "foo"])?;
+    git.run(&["branch", "bar"])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run_with_options(
+        &["update-ref", "--stdin"],
+        &lib::testing::GitRunOptions {
+            input: Some(format!(
+                "update refs/heads/foo {test1_oid} {initial_oid}\nupdate refs/heads/bar {test1_oid} {initial_oid}\n"
+            )),
+            ..Default::default()
+        },
+    )?;
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["undo", "--yes", "--refs", "["],
+            &lib::testing::GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"Invalid pattern for --refs: Pattern syntax error near position 0: invalid range pattern");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["undo", "--yes", "--refs", "fo*"])?;
+        let stdout = trim_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Move branch foo from 62fc20d create test1.txt
+                             to f777ecc create initial.txt
+        branchless: processing 1 update: branch foo
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branch", "-v"])?;
+        insta::assert_snapshot!(stdout, @r###"
+          bar    62fc20d create test1.txt
+          foo    f777ecc create initial.txt
+        * master 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_refs_checked_out_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["reset", "--hard", "HEAD^"])?;
+
+    {
+        let (stdout, stderr) = git.run_with_options(
+            &["undo", "--yes", "--refs", "master"],
+            &lib::testing::GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"Not restoring branch master with --refs, because it's checked out. Check out another commit first, or run git undo without --refs.");
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short", "--branch"])?;
+        insta::assert_snapshot!(stdout, @"## master");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_refs_annotated_tag() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["tag", "-a", "v1", "-m", "first release"])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&[
+        "tag",
+        "-f",
+        "-a",
+        "v1",
+        "-m",
+        "second release",
+        &test1_oid.to_string(),
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["undo", "--yes", "--refs", "refs/tags/*"])?;
+        let stdout = trim_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Move ref refs/tags/v1 from 62fc20d create test1.txt
+                                   to f777ecc create initial.txt
+        branchless: processing 1 update: ref refs/tags/v1
+        Applied 1 inverse event.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["tag", "-n"])?;
+        insta::assert_snapshot!(stdout, @"v1              first release");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_since_summary() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["branch", "foo"])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+    git.run(&["branch", "-f", "foo", "HEAD"])?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["branch", "bar"])?;
+    git.run(&["branch", "-D", "bar"])?;
+    git.run(&["branch", "baz"])?;
+    git.run(&["hide", &test1_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["undo", "--since", "10 years ago"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Changes made in 16 transactions:
+        Created 2 commits:
+          62fc20d create test1.txt
+          96d1c37 create test2.txt
+        Rewrote 1 commit:
+          96d1c37 create test2.txt
+            as cb8137a amended test2
+        Hid 1 commit:
+          62fc20d create test1.txt
+        Updated 2 references:
+          Create branch foo at cb8137a amended test2
+          Create branch baz at f777ecc create initial.txt
+        Moved HEAD 5 times, ending at f777ecc create initial.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.run(&["undo", "--since", "10 years ago", "--until", "9 years ago"])?;
+        insta::assert_snapshot!(stdout, @"No changes were recorded in that time.");
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["undo", "--since", "yesterday", "--yes"],
+            &lib::testing::GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: The argument '--since <TIME>' cannot be used with '--yes'
+
+        USAGE:
+            git-branchless undo --since <TIME>
+
+        For more information try --help
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branch", "-v"])?;
+        insta::assert_snapshot!(stdout, @r###"
+          baz    f777ecc create initial.txt
+          foo    cb8137a amended test2
+        * master f777ecc create initial.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_transaction_provenance() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run_with_options(
+        &["commit", "--allow-empty", "-m", "pulled"],
+        &GitRunOptions {
+            env: {
+                let mut env = HashMap::new();
+                env.insert("GIT_REFLOG_ACTION".to_string(), "pull origin".to_string());
+                env
+            },
+            ..Default::default()
+        },
+    )?;
+    git.run(&["branchless", "hide", "HEAD"])?;
+    git.run(&["branchless", "wrap", "--", "checkout", "HEAD^"])?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let mut transactions: Vec<String> = Vec::new();
+    for event in event_log_db.get_events()? {
+        let event_tx_id = event.get_event_tx_id();
+        let transaction = format!(
+            "{}: {}",
+            event_log_db.get_transaction_message(event_tx_id)?,
+            event_log_db
+                .get_transaction_provenance(event_tx_id)?
+                .unwrap_or_else(|| "<unknown>".to_string()),
+        );
+        if transactions.last() != Some(&transaction) {
+            transactions.push(transaction);
+        }
+    }
+    insta::assert_snapshot!(transactions.join("\n"), @r###"
+    reference-transaction: git checkout
+    hook-post-checkout: git checkout
+    reference-transaction: git commit
+    post-commit: git commit
+    reference-transaction: git pull
+    post-commit: git pull
+    hide: git branchless hide
+    checkout: git checkout
+    "###);
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use lib::core::migrations::get_latest_schema_version;
+use lib::testing::make_git;
+
+#[test]
+fn test_version() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "version"])?;
+        assert_eq!(
+            stdout,
+            format!("git-branchless {}\n", env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "version", "--json"])?;
+        insta::assert_snapshot!(stderr, @"");
+        let report: serde_json::Value = serde_json::from_str(&stdout)?;
+        assert_eq!(report["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            report["event_log_schema_version"],
+            get_latest_schema_version()
+        );
+        let expected_features: &[&str] = if cfg!(feature = "man-pages") {
+            &["man-pages"]
+        } else {
+            &[]
+        };
+        assert_eq!(report["features"], serde_json::json!(expected_features));
+
+        let revset_functions = report["revset_functions"].as_array().unwrap();
+        assert!(revset_functions.contains(&serde_json::json!("stack")));
+        assert!(revset_functions.contains(&serde_json::json!("paths.changed")));
+    }
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ use itertools::Itertools;
 use lib::core::eventlog::testing::redact_event_timestamp;
 use lib::core::eventlog::EventLogDb;
 use lib::git::GitVersion;
-use lib::testing::{make_git, GitInitOptions};
+use lib::testing::{make_git, GitInitOptions, GitRunOptions};
 
 #[test]
 fn test_gc() -> eyre::Result<()> {
@@ -289,3 +289,278 @@ fn test_gc_no_init() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_gc_write_commit_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["config", "branchless.maintenance.writeCommitGraph", "true"])?;
+
+    let commit_graph_chain_path = git
+        .repo_path
+        .join(".git")
+        .join("objects")
+        .join("info")
+        .join("commit-graphs")
+        .join("commit-graph-chain");
+    assert!(!commit_graph_chain_path.exists());
+
+    {
+        let (stdout, stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        branchless: writing commit-graph
+        "###);
+    }
+    assert!(commit_graph_chain_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_stale_bitmaps() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["repack", "-a", "-d", "-b"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        "###);
+    }
+
+    git.commit_file("test2", 2)?;
+    git.run(&["repack", "-d"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        branchless: reachability bitmaps are out of date; run `git repack -a -d -b` to regenerate them
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_write_commit_graph_after_rewrite() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["config", "branchless.maintenance.writeCommitGraph", "true"])?;
+
+    let commit_graph_chain_path = git
+        .repo_path
+        .join(".git")
+        .join("objects")
+        .join("info")
+        .join("commit-graphs")
+        .join("commit-graph-chain");
+
+    git.run(&["config", "branchless.maintenance.rewriteThreshold", "3"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "move", "-b", "HEAD", "-d", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 0770943 create test1.txt
+        [2/2] Committed as: b85f7eb create test3.txt
+        branchless: processing 2 rewritten commits
+        branchless: running command: <git-executable> checkout b85f7eb5fe44aa8fc3194cd36779d78d600c4944
+        :
+        O fe65c1f (master) create test2.txt
+        |
+        o 0770943 create test1.txt
+        |
+        @ b85f7eb create test3.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+    assert!(!commit_graph_chain_path.exists());
+
+    git.run(&["config", "branchless.maintenance.rewriteThreshold", "2"])?;
+    {
+        let (stdout, _stderr) =
+            git.run(&["branchless", "move", "-s", "0770943", "-d", "f777ecc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Attempting rebase in-memory...
+        [1/2] Committed as: 62fc20d create test1.txt
+        [2/2] Committed as: 4838e49 create test3.txt
+        branchless: processing 2 rewritten commits
+        branchless: writing commit-graph
+        branchless: running command: <git-executable> checkout 4838e49b08954becdd17c0900c1179c2c654c627
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        | |
+        | @ 4838e49 create test3.txt
+        |
+        O fe65c1f (master) create test2.txt
+        In-memory rebase succeeded.
+        "###);
+    }
+    assert!(commit_graph_chain_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_prune_window() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.run(&["hide", "62fc20d2"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc", "--prune", "30 days"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        "###);
+    }
+
+    git.run(&["gc", "--prune=now"])?;
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.revparse_single_commit("62fc20d2")?.is_some());
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc", "--prune", "now"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: these hidden commits can no longer be restored with `git undo`:
+          62fc20d create test1.txt
+        branchless: 1 dangling reference deleted
+        "###);
+    }
+
+    {
+        let conn = git.get_repo()?.get_db_conn()?;
+        let event_log = EventLogDb::new(&conn)?;
+        let events = event_log
+            .get_events()?
+            .into_iter()
+            .map(redact_event_timestamp)
+            .collect_vec();
+        insta::assert_debug_snapshot!(events.last(), @r###"
+        Some(
+            RefUpdateEvent {
+                timestamp: 0.0,
+                event_tx_id: EventTransactionId(
+                    11,
+                ),
+                ref_name: ReferenceName(
+                    "refs/branchless/62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+                ),
+                old_oid: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e,
+                new_oid: 0000000000000000000000000000000000000000,
+                message: None,
+            },
+        )
+        "###);
+    }
+
+    git.run(&["gc", "--prune=now"])?;
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.revparse_single_commit("62fc20d2")?.is_none());
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["branchless", "gc", "--prune", "soon"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        error: Invalid value "soon" for '--prune <WINDOW>': expected a duration like `30 days`, but got: soon
+
+        For more information try --help
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gc_compact_references() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&[
+        "config",
+        "branchless.maintenance.keepRefCompactionThreshold",
+        "2",
+    ])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 0 dangling references deleted
+        branchless: compacted 2 references into refs/branchless/keep
+        "###);
+    }
+    {
+        let (stdout, _stderr) =
+            git.run(&["for-each-ref", "--format=%(refname)", "refs/branchless/"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        refs/branchless/keep
+        "###);
+    }
+
+    git.run(&["gc", "--prune=now"])?;
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        "###);
+    }
+
+    git.run(&["hide", "96d1c37a"])?;
+    {
+        let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: collecting garbage
+        branchless: 1 dangling reference deleted
+        "###);
+    }
+
+    git.run(&["gc", "--prune=now"])?;
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.revparse_single_commit("62fc20d2")?.is_some());
+        assert!(repo.revparse_single_commit("96d1c37a")?.is_none());
+    }
+
+    Ok(())
+}
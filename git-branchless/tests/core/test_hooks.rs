@@ -40,7 +40,7 @@ fn test_abandoned_commit_message() -> eyre::Result<()> {
         branchless: processing 1 rewritten commit
         branchless: This operation abandoned 1 commit and 1 branch (master)!
         branchless: Consider running one of the following:
-        branchless:   - git restack: re-apply the abandoned commits/branches
+        branchless:   - git restack 9e8dbe9: re-apply the abandoned commits/branches
         branchless:     (this is most likely what you want to do)
         branchless:   - git smartlog: assess the situation
         branchless:   - git hide [<commit>...]: hide the commits from the smartlog
@@ -73,7 +73,7 @@ fn test_abandoned_branch_message() -> eyre::Result<()> {
         branchless: processing 1 rewritten commit
         branchless: This operation abandoned 2 branches (abc, master)!
         branchless: Consider running one of the following:
-        branchless:   - git restack: re-apply the abandoned commits/branches
+        branchless:   - git restack 62fc20d: re-apply the abandoned commits/branches
         branchless:     (this is most likely what you want to do)
         branchless:   - git smartlog: assess the situation
         branchless:   - git hide [<commit>...]: hide the commits from the smartlog
@@ -85,6 +85,120 @@ fn test_abandoned_branch_message() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_abandoned_commit_restack_hint() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.auto", "hint"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 9e8dbe9 amend test1
+        branchless: processing 1 rewritten commit
+        branchless: This operation abandoned 1 commit. To restack, run: git restack 62fc20d
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_abandoned_commit_auto_restack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.auto", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 9e8dbe9 amend test1
+        branchless: processing 1 rewritten commit
+        Attempting rebase in-memory...
+        [1/1] Committed as: 7ffa962 create test2.txt
+        branchless: processing 1 update: branch foo
+        branchless: processing 1 rewritten commit
+        In-memory rebase succeeded.
+        Finished restacking commits.
+        No abandoned branches to restack.
+        O f777ecc (master) create initial.txt
+        |
+        @ 9e8dbe9 amend test1
+        |
+        o 7ffa962 (foo) create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 9e8dbe9 amend test1
+        |
+        o 7ffa962 (foo) create test2.txt
+        "###);
+    }
+
+    // Restacking would conflict, so only a hint is printed.
+    git.write_file("test2", "conflicting contents\n")?;
+    git.run(&["add", "test2.txt"])?;
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1 again"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 0d18075 amend test1 again
+        branchless: processing 1 rewritten commit
+        Attempting rebase in-memory...
+        There was a merge conflict, which currently can't be resolved when rebasing in-memory.
+        The conflicting commit was: 7ffa962 create test2.txt
+        Aborting since an in-memory rebase was requested.
+        Error: Could not restack commits (exit code 1).
+        You can resolve the error and try running `git restack` again.
+        branchless: Could not restack the abandoned commits automatically. To restack, run: git restack 9e8dbe9
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | @ 0d18075 amend test1 again
+        |
+        x 9e8dbe9 (rewritten as 0d18075a) amend test1
+        |
+        o 7ffa962 (foo) create test2.txt
+        hint: there is 1 abandoned commit in your commit graph
+        hint: to fix this, run: git restack
+        hint: disable this hint by running: git config --global branchless.hint.smartlogFixAbandoned false
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_fixup_no_abandoned_commit_message() -> eyre::Result<()> {
     let git = make_git()?;
@@ -143,7 +257,7 @@ fn test_rebase_individual_commit() -> eyre::Result<()> {
             branchless: processing 1 rewritten commit
             branchless: This operation abandoned 1 commit!
             branchless: Consider running one of the following:
-            branchless:   - git restack: re-apply the abandoned commits/branches
+            branchless:   - git restack fe65c1f: re-apply the abandoned commits/branches
             branchless:     (this is most likely what you want to do)
             branchless:   - git smartlog: assess the situation
             branchless:   - git hide [<commit>...]: hide the commits from the smartlog
@@ -214,8 +328,7 @@ fn test_pre_auto_gc() -> eyre::Result<()> {
         stderr
     );
     insta::assert_snapshot!(stdout, @r###"
-    branchless: collecting garbage
-    branchless: 0 dangling references deleted
+    branchless: keeping hidden commits; run `git branchless gc --prune=<window>` to free them
     "###);
 
     Ok(())
@@ -345,3 +458,122 @@ fn test_merge_commit_recorded() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_ref_patterns_to_ignore() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (_stdout, stderr) = git.run(&["update-ref", "refs/pull/1/head", "HEAD"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref refs/pull/1/head
+        "###);
+    }
+
+    git.run(&[
+        "config",
+        "--add",
+        "branchless.core.refPatternsToIgnore",
+        "refs/notes/*",
+    ])?;
+    git.run(&[
+        "config",
+        "--add",
+        "branchless.core.refPatternsToIgnore",
+        "refs/pull/*",
+    ])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["notes", "add", "-m", "some note"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let (_stdout, stderr) = git.run(&["update-ref", "refs/pull/2/head", "HEAD"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let (_stdout, stderr) = git.run(&["branch", "foo"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: branch foo
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_abandoned_commit_warning_rate_limited() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+    let test5_oid = git.commit_file("test5", 5)?;
+    git.commit_file("test6", 6)?;
+
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 9e8dbe9 amend test1
+        branchless: processing 1 rewritten commit
+        branchless: This operation abandoned 1 commit!
+        branchless: Consider running one of the following:
+        branchless:   - git restack 62fc20d: re-apply the abandoned commits/branches
+        branchless:     (this is most likely what you want to do)
+        branchless:   - git smartlog: assess the situation
+        branchless:   - git hide [<commit>...]: hide the commits from the smartlog
+        branchless:   - git undo: undo the operation
+        hint: disable this hint by running: git config --global branchless.hint.restackWarnAbandoned false
+        "###);
+    }
+
+    // The full warning was just shown, so only a one-line hint is shown.
+    git.run(&["checkout", &test3_oid.to_string()])?;
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test3"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 3d78f47 amend test3
+        branchless: processing 1 rewritten commit
+        branchless: This operation abandoned 1 commit. To restack, run: git restack 98b9119
+        "###);
+    }
+
+    git.run(&["config", "branchless.restack.warnAbandonedInterval", "0"])?;
+    git.run(&["checkout", &test5_oid.to_string()])?;
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test5"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: ref HEAD
+        branchless: processed commit: 88abe9a amend test5
+        branchless: processing 1 rewritten commit
+        branchless: This operation abandoned 1 commit!
+        branchless: Consider running one of the following:
+        branchless:   - git restack 1393298: re-apply the abandoned commits/branches
+        branchless:     (this is most likely what you want to do)
+        branchless:   - git smartlog: assess the situation
+        branchless:   - git hide [<commit>...]: hide the commits from the smartlog
+        branchless:   - git undo: undo the operation
+        hint: disable this hint by running: git config --global branchless.hint.restackWarnAbandoned false
+        "###);
+    }
+
+    Ok(())
+}
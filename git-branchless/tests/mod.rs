@@ -8,19 +8,33 @@ mod core {
 
 mod command {
     mod test_amend;
+    mod test_branch;
     mod test_bug_report;
+    mod test_config;
+    mod test_daemon;
+    mod test_diff;
     mod test_hide;
     mod test_init;
+    mod test_lookup;
+    mod test_meta;
     mod test_move;
     mod test_navigation;
+    mod test_prune_remote;
+    mod test_push;
     mod test_query;
     mod test_record;
     mod test_repair;
     mod test_restack;
+    mod test_resume;
     mod test_reword;
+    mod test_run;
+    mod test_serve;
     mod test_smartlog;
     mod test_snapshot;
+    mod test_split;
+    mod test_stats;
     mod test_sync;
     mod test_undo;
+    mod test_version;
     mod test_wrap;
 }
@@ -17,7 +17,9 @@ mod cursive_utils;
 mod tristate;
 mod types;
 mod ui;
+mod word_diff;
 
 pub use cursive_utils::testing;
 pub use types::{FileState, RecordError, RecordState, Section, SectionChangedLine};
 pub use ui::Recorder;
+pub use word_diff::{diff_words, highlight_word_diff, WordDiffSpan};
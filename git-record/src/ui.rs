@@ -12,6 +12,7 @@ use tracing::error;
 
 use crate::cursive_utils::{EventDrivenCursiveApp, EventDrivenCursiveAppExt};
 use crate::tristate::{Tristate, TristateBox};
+use crate::word_diff::highlight_word_diff;
 use crate::{FileState, RecordError, RecordState, Section, SectionChangedLine};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -81,6 +82,7 @@ impl SectionLineKey {
 pub struct Recorder<'a> {
     did_user_confirm_exit: bool,
     state: RecordState<'a>,
+    word_diff: bool,
 }
 
 impl<'a> Recorder<'a> {
@@ -89,9 +91,17 @@ impl<'a> Recorder<'a> {
         Self {
             did_user_confirm_exit: false,
             state,
+            word_diff: false,
         }
     }
 
+    /// Whether to highlight the words which changed between removed lines and
+    /// the added lines which replaced them. Lines are only paired up if a
+    /// section has the same number of removed and added lines.
+    pub fn with_word_diff(self, word_diff: bool) -> Self {
+        Self { word_diff, ..self }
+    }
+
     /// Run the terminal user interface and have the user interactively select
     /// changes.
     pub fn run(self, siv: CursiveRunner<CursiveRunnable>) -> Result<RecordState<'a>, RecordError> {
@@ -265,7 +275,35 @@ impl<'a> Recorder<'a> {
             section_num,
         };
 
-        for (section_line_num, section_changed_line) in before.iter().enumerate() {
+        let (before_contents, after_contents): (Vec<StyledString>, Vec<StyledString>) =
+            if self.word_diff && before.len() == after.len() {
+                before
+                    .iter()
+                    .zip(after.iter())
+                    .map(|(before_line, after_line)| {
+                        highlight_word_diff(" -", &before_line.line, " +", &after_line.line)
+                    })
+                    .unzip()
+            } else {
+                (
+                    before
+                        .iter()
+                        .map(|SectionChangedLine { line, .. }| {
+                            StyledString::styled(format!(" -{}", line), BaseColor::Red.dark())
+                        })
+                        .collect(),
+                    after
+                        .iter()
+                        .map(|SectionChangedLine { line, .. }| {
+                            StyledString::styled(format!(" +{}", line), BaseColor::Green.dark())
+                        })
+                        .collect(),
+                )
+            };
+
+        for (section_line_num, (section_changed_line, line_contents)) in
+            before.iter().zip(before_contents).enumerate()
+        {
             let section_line_key = SectionLineKey {
                 file_num,
                 section_num,
@@ -276,10 +314,13 @@ impl<'a> Recorder<'a> {
                 main_tx.clone(),
                 section_line_key,
                 section_changed_line,
+                line_contents,
             ));
         }
 
-        for (section_line_num, section_changed_line) in after.iter().enumerate() {
+        for (section_line_num, (section_changed_line, line_contents)) in
+            after.iter().zip(after_contents).enumerate()
+        {
             let section_line_key = SectionLineKey {
                 file_num,
                 section_num,
@@ -290,6 +331,7 @@ impl<'a> Recorder<'a> {
                 main_tx.clone(),
                 section_line_key,
                 section_changed_line,
+                line_contents,
             ));
         }
 
@@ -339,18 +381,12 @@ impl<'a> Recorder<'a> {
         main_tx: Sender<Message>,
         section_line_key: SectionLineKey,
         section_changed_line: &SectionChangedLine,
+        line_contents: StyledString,
     ) -> impl View {
-        let SectionChangedLine { is_selected, line } = section_changed_line;
-
-        let line_contents = {
-            let (line, style) = match section_line_key.section_type {
-                SectionChangedLineType::Before => (format!(" -{}", line), BaseColor::Red.dark()),
-                SectionChangedLineType::After => (format!(" +{}", line), BaseColor::Green.dark()),
-            };
-            let mut s = StyledString::new();
-            s.append_styled(line, style);
-            s
-        };
+        let SectionChangedLine {
+            is_selected,
+            line: _,
+        } = section_changed_line;
 
         LinearLayout::horizontal()
             .child(TextView::new("    "))
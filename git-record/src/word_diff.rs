@@ -0,0 +1,219 @@
+//! Intra-line ("word-level") diffs, used to highlight which parts of a changed
+//! line actually differ from the line it replaced.
+
+use cursive::theme::{BaseColor, Effect, Style};
+use cursive::utils::markup::StyledString;
+
+/// Lines whose token counts multiply to more than this are not diffed, since
+/// the diff takes quadratic time.
+const MAX_DIFF_CELLS: usize = 100_000;
+
+/// A span of text from one side of a word-level diff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WordDiffSpan<'a> {
+    /// The text of the span.
+    pub text: &'a str,
+
+    /// Whether or not the text differs from the other side of the diff.
+    pub is_changed: bool,
+}
+
+/// Split a line into words, runs of whitespace, and individual punctuation
+/// characters.
+fn tokenize(line: &str) -> Vec<&str> {
+    #[derive(PartialEq, Eq)]
+    enum Class {
+        Word,
+        Whitespace,
+        Other,
+    }
+    let classify = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            Class::Word
+        } else if c.is_whitespace() {
+            Class::Whitespace
+        } else {
+            Class::Other
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut prev_class = None;
+    for (i, c) in line.char_indices() {
+        let class = classify(c);
+        let continues_token = match &prev_class {
+            Some(prev_class) => *prev_class == class && class != Class::Other,
+            None => true,
+        };
+        if !continues_token {
+            tokens.push(&line[start..i]);
+            start = i;
+        }
+        prev_class = Some(class);
+    }
+    if start < line.len() {
+        tokens.push(&line[start..]);
+    }
+    tokens
+}
+
+/// Calculate a word-level diff between a removed line and the added line which
+/// replaced it.
+///
+/// Returns the spans of the old line and the spans of the new line. Spans are
+/// marked as changed if they don't appear in the longest common subsequence of
+/// words between the two lines.
+pub fn diff_words<'a>(
+    old: &'a str,
+    new: &'a str,
+) -> (Vec<WordDiffSpan<'a>>, Vec<WordDiffSpan<'a>>) {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+    let (n, m) = (old_tokens.len(), new_tokens.len());
+
+    let mut old_is_common = vec![false; n];
+    let mut new_is_common = vec![false; m];
+    if n.saturating_mul(m) <= MAX_DIFF_CELLS {
+        // `lcs[i][j]` is the length of the longest common subsequence of
+        // `old_tokens[i..]` and `new_tokens[j..]`.
+        let mut lcs = vec![vec![0_usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if old_tokens[i] == new_tokens[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if old_tokens[i] == new_tokens[j] {
+                old_is_common[i] = true;
+                new_is_common[j] = true;
+                i += 1;
+                j += 1;
+            } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+
+    let make_spans = |line: &'a str, tokens: &[&'a str], is_common: &[bool]| {
+        // Merge adjacent tokens with the same status into a single span.
+        let mut ranges: Vec<(usize, usize, bool)> = Vec::new();
+        let mut offset = 0;
+        for (token, is_common) in tokens.iter().zip(is_common) {
+            let is_changed = !is_common;
+            let end = offset + token.len();
+            match ranges.last_mut() {
+                Some((_start, last_end, last_is_changed)) if *last_is_changed == is_changed => {
+                    *last_end = end;
+                }
+                _ => ranges.push((offset, end, is_changed)),
+            }
+            offset = end;
+        }
+        ranges
+            .into_iter()
+            .map(|(start, end, is_changed)| WordDiffSpan {
+                text: &line[start..end],
+                is_changed,
+            })
+            .collect::<Vec<_>>()
+    };
+    (
+        make_spans(old, &old_tokens, &old_is_common),
+        make_spans(new, &new_tokens, &new_is_common),
+    )
+}
+
+fn render_spans(prefix: &str, spans: &[WordDiffSpan], color: BaseColor) -> StyledString {
+    let mut result = StyledString::new();
+    result.append_styled(prefix, color.dark());
+    for WordDiffSpan { text, is_changed } in spans {
+        if *is_changed {
+            result.append_styled(*text, Style::from(color.dark()).combine(Effect::Reverse));
+        } else {
+            result.append_styled(*text, color.dark());
+        }
+    }
+    result
+}
+
+/// Render a removed line and the added line which replaced it, highlighting
+/// the words which differ between them.
+///
+/// `old_prefix` and `new_prefix` are prepended to the respective lines without
+/// highlighting, such as to render the `-` and `+` markers of a patch.
+pub fn highlight_word_diff(
+    old_prefix: &str,
+    old: &str,
+    new_prefix: &str,
+    new: &str,
+) -> (StyledString, StyledString) {
+    let (old_spans, new_spans) = diff_words(old, new);
+
+    // If nothing is in common, then highlighting every word would just be
+    // noise.
+    let has_common_words = old_spans
+        .iter()
+        .any(|span| !span.is_changed && !span.text.trim().is_empty());
+    if !has_common_words {
+        let mut old_line = StyledString::new();
+        old_line.append_styled(format!("{}{}", old_prefix, old), BaseColor::Red.dark());
+        let mut new_line = StyledString::new();
+        new_line.append_styled(format!("{}{}", new_prefix, new), BaseColor::Green.dark());
+        return (old_line, new_line);
+    }
+
+    (
+        render_spans(old_prefix, &old_spans, BaseColor::Red),
+        render_spans(new_prefix, &new_spans, BaseColor::Green),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(spans: &[WordDiffSpan]) -> String {
+        spans
+            .iter()
+            .map(|WordDiffSpan { text, is_changed }| {
+                if *is_changed {
+                    format!("[{}]", text)
+                } else {
+                    text.to_string()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("let foo_bar = baz(1);"),
+            vec!["let", " ", "foo_bar", " ", "=", " ", "baz", "(", "1", ")", ";"]
+        );
+    }
+
+    #[test]
+    fn test_diff_words() {
+        let (old, new) = diff_words("let foo = bar(1);", "let foo = qux(1, 2);");
+        assert_eq!(render(&old), "let foo = [bar](1);");
+        assert_eq!(render(&new), "let foo = [qux](1[, 2]);");
+
+        let (old, new) = diff_words("same", "same");
+        assert_eq!(render(&old), "same");
+        assert_eq!(render(&new), "same");
+
+        let (old, new) = diff_words("", "added");
+        assert_eq!(render(&old), "");
+        assert_eq!(render(&new), "[added]");
+    }
+}